@@ -0,0 +1,68 @@
+//! Edge pass on a large dataset: the frozen FxHashMap shared between the checker threads
+//! against the previous single-threaded pass on the std HashMap.
+//!
+//! `cargo bench --bench lookup_maps` prints the best time of each pass over a few rounds.
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use rusthound::json::checker::add_type_for_ace;
+use rusthound::json::checker::lookup::{par_for_each, LookupMaps};
+
+const ROUNDS: usize = 10;
+
+/// 10 000 principals and 20 000 objects with 10 ACEs each.
+fn dataset() -> (HashMap<String, String>, Vec<serde_json::value::Value>) {
+    let sids: Vec<String> = (0..10_000).map(|i| format!("S-1-5-21-1-2-3-{}", 1000 + i)).collect();
+    let sid_type: HashMap<String, String> = sids.iter().enumerate()
+        .map(|(i, sid)| (sid.to_owned(), ["User", "Group", "Computer"][i % 3].to_string()))
+        .collect();
+    let objects: Vec<serde_json::value::Value> = (0..20_000)
+        .map(|i| serde_json::json!({"Aces": (0..10).map(|j| serde_json::json!({"PrincipalSID": sids[(i * 7 + j * 13) % sids.len()]})).collect::<Vec<_>>()}))
+        .collect();
+    (sid_type, objects)
+}
+
+/// Type of every ACE principal, the lookup of add_type_for_ace without its progress bar.
+fn add_types<M: Fn(&str) -> Option<String> + Sync>(object: &mut serde_json::value::Value, lookup: &M) {
+    for ace in object["Aces"].as_array_mut().unwrap() {
+        let object_type = lookup(ace["PrincipalSID"].as_str().unwrap()).unwrap_or("Group".to_string());
+        ace["PrincipalType"] = object_type.into();
+    }
+}
+
+/// Best time of the rounds, the copy of the input is not timed.
+fn best_of<F: FnMut(&mut Vec<serde_json::value::Value>)>(objects: &[serde_json::value::Value], mut f: F) -> (Duration, Vec<serde_json::value::Value>) {
+    let mut best = Duration::MAX;
+    let mut output = Vec::new();
+    for _ in 0..ROUNDS {
+        let mut round = objects.to_vec();
+        let started = Instant::now();
+        f(&mut round);
+        best = best.min(started.elapsed());
+        output = round;
+    }
+    (best, output)
+}
+
+fn main() {
+    let (sid_type, objects) = dataset();
+    let lookups = LookupMaps::freeze(HashMap::new(), sid_type.to_owned(), HashMap::new());
+    let threads = std::thread::available_parallelism().map(|n| n.get()).unwrap_or(1);
+    let std_lookup = |sid: &str| sid_type.get(sid).cloned();
+    let fx_lookup = |sid: &str| lookups.sid_type.get(sid).cloned();
+
+    // Previous pass: one thread reading the std HashMap
+    let (std_time, std_output) = best_of(&objects, |objects| objects.iter_mut().for_each(|object| add_types(object, &std_lookup)));
+    // Frozen map, one thread then every thread
+    let (fx_time, fx_output) = best_of(&objects, |objects| objects.iter_mut().for_each(|object| add_types(object, &fx_lookup)));
+    let (par_time, par_output) = best_of(&objects, |objects| par_for_each(objects, |object| add_types(object, &fx_lookup)));
+    // Whole checker step, progress bar included
+    let (checker_time, checker_output) = best_of(&objects, |objects| add_type_for_ace(objects, &lookups.sid_type));
+    assert!(fx_output == std_output && par_output == std_output && checker_output == std_output);
+
+    println!("200000 ACEs, {} threads, best of {} rounds", threads, ROUNDS);
+    println!("  std HashMap, 1 thread           {:>10.2?}", std_time);
+    println!("  frozen FxHashMap, 1 thread      {:>10.2?}", fx_time);
+    println!("  frozen FxHashMap, {:>2} threads    {:>10.2?}", threads, par_time);
+    println!("  add_type_for_ace                {:>10.2?}", checker_time);
+}
@@ -0,0 +1,341 @@
+//! .NET Message Framing and NegotiateStream of the ADWS connection
+//!
+//! ADWS listens on TCP 9389 with the NetTcpBinding of WCF. The records of .NET Message Framing [MC-NMF] open a
+//! duplex session on one endpoint, the connection is upgraded to NegotiateStream [MS-NNS] authenticated with NTLMv2,
+//! then every byte is signed and sealed with the NTLM session keys [MS-NLMP]. The SOAP envelopes go in the sized
+//! envelope records, in the binary XML of the `nbfx` module.
+use md5::{Digest, Md5};
+use std::future::Future;
+use std::io::{Error, ErrorKind, Result};
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+
+use crate::modules::remote::{hmac_md5, ntlm_authenticate_flags, ntlm_negotiate_flags, parse_ntlm_challenge, Rc4, NTLM_SEAL_FLAGS};
+use crate::secret::SecretString;
+
+/// Timeout of the connection and of every read, a Pull of a big page can take a while on a loaded DC.
+pub const ADWS_TIMEOUT: Duration = Duration::from_secs(120);
+/// Biggest envelope accepted from the server.
+const MAX_ENVELOPE_SIZE: usize = 64 * 1024 * 1024;
+
+// Record types of .NET Message Framing
+const VERSION_RECORD: u8 = 0x00;
+const MODE_RECORD: u8 = 0x01;
+const VIA_RECORD: u8 = 0x02;
+const KNOWN_ENCODING_RECORD: u8 = 0x03;
+const SIZED_ENVELOPE_RECORD: u8 = 0x06;
+const END_RECORD: u8 = 0x07;
+const FAULT_RECORD: u8 = 0x08;
+const UPGRADE_REQUEST_RECORD: u8 = 0x09;
+const UPGRADE_RESPONSE_RECORD: u8 = 0x0A;
+const PREAMBLE_ACK_RECORD: u8 = 0x0B;
+const PREAMBLE_END_RECORD: u8 = 0x0C;
+const DUPLEX_MODE: u8 = 0x02;
+/// SOAP 1.2 in binary XML with the in-band dictionary of the session.
+const BINARY_SESSION_ENCODING: u8 = 0x08;
+const NEGOTIATE_UPGRADE: &str = "application/negotiate";
+
+// Handshake messages of NegotiateStream
+const HANDSHAKE_DONE: u8 = 0x14;
+const HANDSHAKE_ERROR: u8 = 0x15;
+const HANDSHAKE_IN_PROGRESS: u8 = 0x16;
+/// Biggest payload of one NegotiateStream data message.
+const NNS_MAX_PAYLOAD: usize = 0xFC00;
+
+const CLIENT_SIGNING_MAGIC: &[u8] = b"session key to client-to-server signing key magic constant\0";
+const CLIENT_SEALING_MAGIC: &[u8] = b"session key to client-to-server sealing key magic constant\0";
+const SERVER_SIGNING_MAGIC: &[u8] = b"session key to server-to-client signing key magic constant\0";
+const SERVER_SEALING_MAGIC: &[u8] = b"session key to server-to-client sealing key magic constant\0";
+
+/// NTLM session security of one direction, with extended session security and key exchange:
+/// signing key, RC4 key stream going on from one message to the next and sequence number.
+pub struct NtlmSealing {
+   signing_key: Vec<u8>,
+   rc4: Rc4,
+   sequence: u32,
+}
+
+impl NtlmSealing {
+   /// Keys of the messages sent by the client (`client_to_server`) or by the server.
+   pub fn new(session_key: &[u8], client_to_server: bool) -> NtlmSealing {
+      let (signing, sealing) = if client_to_server {
+         (CLIENT_SIGNING_MAGIC, CLIENT_SEALING_MAGIC)
+      } else {
+         (SERVER_SIGNING_MAGIC, SERVER_SEALING_MAGIC)
+      };
+      let key = |magic: &[u8]| Md5::new().chain_update(session_key).chain_update(magic).finalize().to_vec();
+      NtlmSealing { signing_key: key(signing), rc4: Rc4::new(&key(sealing)), sequence: 0 }
+   }
+
+   /// Function to seal one message, return the signature (16 bytes) followed by the encrypted message.
+   pub fn seal(&mut self, message: &[u8]) -> Vec<u8> {
+      let sealed = self.rc4.apply(message);
+      let mut token = self.signature(message);
+      token.extend(sealed);
+      token
+   }
+
+   /// Function to decrypt one sealed message and check its signature.
+   pub fn unseal(&mut self, token: &[u8]) -> Result<Vec<u8>> {
+      if token.len() < 16 {
+         return Err(invalid("NegotiateStream message shorter than its signature"))
+      }
+      let message = self.rc4.apply(&token[16..]);
+      if self.signature(&message)[..] != token[..16] {
+         return Err(invalid("bad signature of a NegotiateStream message"))
+      }
+      Ok(message)
+   }
+
+   /// Function to compute the signature of a message: version, checksum encrypted with the key stream, sequence number.
+   fn signature(&mut self, message: &[u8]) -> Vec<u8> {
+      let sequence = self.sequence.to_le_bytes();
+      self.sequence = self.sequence.wrapping_add(1);
+      let checksum = hmac_md5(&self.signing_key, &[&sequence, message]);
+      let mut signature = 1u32.to_le_bytes().to_vec();
+      signature.extend(self.rc4.apply(&checksum[..8]));
+      signature.extend_from_slice(&sequence);
+      signature
+   }
+}
+
+/// Function to encode a size of the framing records, 7 bits per byte from the lowest ones.
+/// It is also the MultiByteInt31 of the binary XML.
+pub fn encode_size(mut size: usize, buffer: &mut Vec<u8>) {
+   while size >= 0x80 {
+      buffer.push(size as u8 & 0x7F | 0x80);
+      size >>= 7;
+   }
+   buffer.push(size as u8);
+}
+
+/// Function to decode a size at the start of a buffer, return it with the number of bytes read.
+pub fn decode_size(buffer: &[u8]) -> Option<(usize, usize)> {
+   let mut size = 0usize;
+   for (index, byte) in buffer.iter().take(5).enumerate() {
+      size |= ((byte & 0x7F) as usize) << (7 * index);
+      if byte & 0x80 == 0 {
+         return Some((size, index + 1))
+      }
+   }
+   None
+}
+
+/// Session with one ADWS endpoint: framing upgraded to NegotiateStream and sealed with NTLM.
+pub struct NmfConnection {
+   stream: TcpStream,
+   client: NtlmSealing,
+   server: NtlmSealing,
+   /// Bytes of the server unsealed and not read yet
+   received: Vec<u8>,
+}
+
+impl NmfConnection {
+   /// Function to open the session of an endpoint like net.tcp://dc01.essos.local:9389/ActiveDirectoryWebServices/Windows/Enumeration
+   /// with the NTLM credentials.
+   pub async fn connect(host: &str, port: u16, via: &str, user: &str, domain: &str, password: &SecretString) -> Result<NmfConnection> {
+      let mut stream = timeout(TcpStream::connect((host, port))).await?;
+      let mut preamble = vec![VERSION_RECORD, 1, 0, MODE_RECORD, DUPLEX_MODE, VIA_RECORD];
+      encode_size(via.len(), &mut preamble);
+      preamble.extend_from_slice(via.as_bytes());
+      preamble.extend_from_slice(&[KNOWN_ENCODING_RECORD, BINARY_SESSION_ENCODING, UPGRADE_REQUEST_RECORD]);
+      encode_size(NEGOTIATE_UPGRADE.len(), &mut preamble);
+      preamble.extend_from_slice(NEGOTIATE_UPGRADE.as_bytes());
+      timeout(stream.write_all(&preamble)).await?;
+      match read_raw(&mut stream, 1).await?[0] {
+         UPGRADE_RESPONSE_RECORD => {}
+         FAULT_RECORD => {
+            let size = read_raw_size(&mut stream).await?;
+            return Err(fault(&read_raw(&mut stream, size).await?))
+         }
+         record => return Err(invalid(format!("record 0x{:02x} instead of the upgrade response", record))),
+      }
+
+      // NTLM in the NegotiateStream handshake: NEGOTIATE, CHALLENGE, AUTHENTICATE and the done of the server
+      write_handshake(&mut stream, HANDSHAKE_IN_PROGRESS, &ntlm_negotiate_flags(NTLM_SEAL_FLAGS)).await?;
+      let challenge = read_handshake(&mut stream).await?;
+      let challenge = parse_ntlm_challenge(&challenge).ok_or_else(|| invalid("no NTLM challenge in the NegotiateStream handshake"))?;
+      let (authenticate, session_key) = ntlm_authenticate_flags(&challenge, user, domain, password.expose(), NTLM_SEAL_FLAGS);
+      write_handshake(&mut stream, HANDSHAKE_DONE, &authenticate).await?;
+      read_handshake(&mut stream).await?;
+
+      let mut connection = NmfConnection {
+         stream,
+         client: NtlmSealing::new(&session_key, true),
+         server: NtlmSealing::new(&session_key, false),
+         received: Vec::new(),
+      };
+      connection.write(&[PREAMBLE_END_RECORD]).await?;
+      match connection.read(1).await?[0] {
+         PREAMBLE_ACK_RECORD => Ok(connection),
+         FAULT_RECORD => Err(connection.read_fault().await),
+         record => Err(invalid(format!("record 0x{:02x} instead of the preamble ack", record))),
+      }
+   }
+
+   /// Function to send one SOAP envelope.
+   pub async fn send_envelope(&mut self, envelope: &[u8]) -> Result<()> {
+      let mut record = vec![SIZED_ENVELOPE_RECORD];
+      encode_size(envelope.len(), &mut record);
+      record.extend_from_slice(envelope);
+      self.write(&record).await
+   }
+
+   /// Function to read the next SOAP envelope of the server.
+   pub async fn read_envelope(&mut self) -> Result<Vec<u8>> {
+      match self.read(1).await?[0] {
+         SIZED_ENVELOPE_RECORD => {
+            let size = self.read_size().await?;
+            if size > MAX_ENVELOPE_SIZE {
+               return Err(invalid(format!("envelope of {} bytes", size)))
+            }
+            self.read(size).await
+         }
+         END_RECORD => Err(Error::new(ErrorKind::ConnectionAborted, "ADWS session ended by the server")),
+         FAULT_RECORD => Err(self.read_fault().await),
+         record => Err(invalid(format!("record 0x{:02x} instead of an envelope", record))),
+      }
+   }
+
+   /// Function to end the session.
+   pub async fn close(mut self) -> Result<()> {
+      self.write(&[END_RECORD]).await?;
+      timeout(self.stream.shutdown()).await
+   }
+
+   /// Function to send bytes of the session in sealed NegotiateStream data messages.
+   async fn write(&mut self, data: &[u8]) -> Result<()> {
+      let mut messages = Vec::new();
+      for chunk in data.chunks(NNS_MAX_PAYLOAD) {
+         let token = self.client.seal(chunk);
+         messages.extend_from_slice(&(token.len() as u32).to_le_bytes());
+         messages.extend(token);
+      }
+      timeout(self.stream.write_all(&messages)).await
+   }
+
+   /// Function to read the next bytes of the session, the data messages are unsealed as needed.
+   async fn read(&mut self, len: usize) -> Result<Vec<u8>> {
+      while self.received.len() < len {
+         let size = read_raw(&mut self.stream, 4).await?;
+         let size = u32::from_le_bytes([size[0], size[1], size[2], size[3]]) as usize;
+         if size > NNS_MAX_PAYLOAD + 16 {
+            return Err(invalid(format!("NegotiateStream message of {} bytes", size)))
+         }
+         let token = read_raw(&mut self.stream, size).await?;
+         let data = self.server.unseal(&token)?;
+         self.received.extend(data);
+      }
+      Ok(self.received.drain(..len).collect())
+   }
+
+   async fn read_size(&mut self) -> Result<usize> {
+      let mut encoded = Vec::new();
+      loop {
+         let byte = self.read(1).await?[0];
+         encoded.push(byte);
+         if byte & 0x80 == 0 || encoded.len() == 5 {
+            return decode_size(&encoded).map(|(size, _)| size).ok_or_else(|| invalid("bad record size"))
+         }
+      }
+   }
+
+   /// Function to read the reason of a fault record, like http://schemas.microsoft.com/ws/2006/05/framing/faults/EndpointNotFound.
+   async fn read_fault(&mut self) -> Error {
+      let size = match self.read_size().await {
+         Ok(size) => size,
+         Err(err) => return err,
+      };
+      match self.read(size).await {
+         Ok(reason) => fault(&reason),
+         Err(err) => err,
+      }
+   }
+}
+
+/// Function to run one network operation with the ADWS timeout.
+async fn timeout<T>(operation: impl Future<Output = std::io::Result<T>>) -> Result<T> {
+   tokio::time::timeout(ADWS_TIMEOUT, operation).await
+      .map_err(|_| Error::new(ErrorKind::TimedOut, "ADWS connection timed out"))?
+}
+
+async fn read_raw(stream: &mut TcpStream, len: usize) -> Result<Vec<u8>> {
+   let mut buffer = vec![0u8; len];
+   timeout(stream.read_exact(&mut buffer)).await?;
+   Ok(buffer)
+}
+
+async fn read_raw_size(stream: &mut TcpStream) -> Result<usize> {
+   let mut encoded = Vec::new();
+   loop {
+      let byte = read_raw(stream, 1).await?[0];
+      encoded.push(byte);
+      if byte & 0x80 == 0 || encoded.len() == 5 {
+         return decode_size(&encoded).map(|(size, _)| size).ok_or_else(|| invalid("bad record size"))
+      }
+   }
+}
+
+/// Function to send one handshake message of NegotiateStream: id, version 1.0, big endian size and payload.
+async fn write_handshake(stream: &mut TcpStream, id: u8, payload: &[u8]) -> Result<()> {
+   let mut message = vec![id, 1, 0];
+   message.extend_from_slice(&(payload.len() as u16).to_be_bytes());
+   message.extend_from_slice(payload);
+   timeout(stream.write_all(&message)).await
+}
+
+/// Function to read one handshake message of NegotiateStream, return its payload. The error message of the server fails.
+async fn read_handshake(stream: &mut TcpStream) -> Result<Vec<u8>> {
+   let header = read_raw(stream, 5).await?;
+   let payload = read_raw(stream, u16::from_be_bytes([header[3], header[4]]) as usize).await?;
+   match header[0] {
+      HANDSHAKE_DONE | HANDSHAKE_IN_PROGRESS => Ok(payload),
+      HANDSHAKE_ERROR => {
+         let code = payload.iter().rev().map(|byte| format!("{:02x}", byte)).collect::<String>();
+         Err(Error::new(ErrorKind::PermissionDenied, format!("NTLM authentication refused (error 0x{})", code.trim_start_matches('0'))))
+      }
+      id => Err(invalid(format!("NegotiateStream handshake message 0x{:02x}", id))),
+   }
+}
+
+fn fault(reason: &[u8]) -> Error {
+   Error::other(format!("ADWS fault {}", String::from_utf8_lossy(reason)))
+}
+
+fn invalid<E: Into<Box<dyn std::error::Error + Send + Sync>>>(reason: E) -> Error {
+   Error::new(ErrorKind::InvalidData, reason)
+}
+
+/// Test functions
+#[test]
+pub fn test_framing() {
+   let hex = |bytes: &[u8]| bytes.iter().map(|byte| format!("{:02x}", byte)).collect::<String>();
+   // Record sizes
+   for (size, encoded) in [(0x7Fusize, "7f"), (0x80, "8001"), (0x3FFF, "ff7f"), (0x4000, "808001"), (0x1FFFFF, "ffff7f")] {
+      let mut buffer = Vec::new();
+      encode_size(size, &mut buffer);
+      assert_eq!(hex(&buffer), encoded);
+      assert_eq!(decode_size(&buffer), Some((size, buffer.len())));
+   }
+   assert_eq!(decode_size(&[0x80, 0x80]), None);
+
+   // Sealing example of MS-NLMP 4.2.4.4 (NTLMv2 with extended session security and key exchange)
+   let session_key = [0x55u8; 16];
+   let plaintext: Vec<u8> = "Plaintext".encode_utf16().flat_map(|c| c.to_le_bytes()).collect();
+   let mut client = NtlmSealing::new(&session_key, true);
+   assert_eq!(hex(&client.signing_key), "4788dc861b4782f35d43fd98fe1a2d39");
+   let token = client.seal(&plaintext);
+   assert_eq!(hex(&token[..16]), "010000007fb38ec5c55d497600000000");
+   assert_eq!(hex(&token[16..]), "54e50165bf1936dc996020c1811b0f06fb5f");
+
+   // The peer unseals the messages in order, a modified message fails
+   let mut peer = NtlmSealing::new(&session_key, true);
+   assert_eq!(peer.unseal(&token).unwrap(), plaintext);
+   assert_eq!(peer.unseal(&client.seal(b"second message")).unwrap(), b"second message");
+   let mut modified = client.seal(b"third message");
+   modified[20] ^= 1;
+   assert!(peer.unseal(&modified).is_err());
+   // Other keys in the other direction
+   assert_ne!(NtlmSealing::new(&session_key, false).seal(&plaintext), NtlmSealing::new(&session_key, true).seal(&plaintext));
+}
@@ -0,0 +1,488 @@
+//! Collection over the Active Directory Web Services (--protocol adws)
+//!
+//! ADWS answers on TCP 9389 of the DCs since 2008 R2, the PowerShell AD module uses it. The same searches as
+//! `ldap_search` go in SOAP requests of the enumeration endpoint, for the networks where 389/636 are filtered and
+//! the detections watching the LDAP queries. The session is authenticated with NTLM and sealed, see `framing`;
+//! the envelopes are binary XML, see `nbfx`; the requests and the objects, see `soap`.
+//! The DC queries its own LDAP service: no referral, no fail-over and no deleted objects over ADWS.
+use ldap3::{Scope, SearchEntry};
+use log::{debug, error, info, warn};
+use std::collections::{HashMap, HashSet};
+use std::io::{self, ErrorKind};
+
+use crate::args::Options;
+use crate::banner::{progress_bar, Colorize, ProgressBar};
+use crate::enums::extrights::add_live_extended_rights;
+use crate::enums::schema::{add_live_schema_guids, load_schema_cache, save_schema_cache, schema_cache_path};
+use crate::enums::sid::decode_guid;
+use crate::errors::{Error, Kind, Result};
+use crate::json::parser::ParsePipeline;
+use crate::ldap::{jitter, prepare_ldap_dc, prepare_ldap_filter, requested_attributes, Referral, STEALTH_JITTER_MS};
+use crate::modules::control::{set_objects, stop_requested};
+use crate::modules::remote::split_username;
+use crate::modules::sampling::keep_sampled_entry;
+use crate::modules::targets::{related_filters, targets_filters, RELATED_DN_ATTRIBUTES};
+use crate::secret::SecretString;
+
+pub mod framing;
+pub mod nbfx;
+pub mod soap;
+
+use framing::NmfConnection;
+use nbfx::{decode_envelope, encode_envelope, SessionDictionary, XmlElement};
+use soap::{enumerate_request, parse_response, pull_request, EnumerationPage};
+
+/// TCP port of ADWS.
+pub const ADWS_PORT: u16 = 9389;
+/// Endpoint of the WS-Enumeration searches.
+const ENUMERATION_ENDPOINT: &str = "ActiveDirectoryWebServices/Windows/Enumeration";
+/// Objects per Pull accepted by ADWS (MaxPullElements of its configuration).
+pub const ADWS_MAX_ELEMENTS: usize = 256;
+
+/// ADWS session on the enumeration endpoint of a DC.
+pub struct AdwsClient {
+   connection: NmfConnection,
+   to: String,
+   dictionary: SessionDictionary,
+   /// Objects already sent to the parsing pipeline
+   sent: u64,
+}
+
+impl AdwsClient {
+   /// Function to open the session with the NTLM credentials, `fqdn` names the DC in the endpoint address.
+   pub async fn connect(host: &str, port: u16, fqdn: &str, user: &str, domain: &str, password: &SecretString) -> io::Result<AdwsClient> {
+      let to = format!("net.tcp://{}:{}/{}", fqdn, port, ENUMERATION_ENDPOINT);
+      let connection = NmfConnection::connect(host, port, &to, user, domain, password).await?;
+      Ok(AdwsClient { connection, to, dictionary: SessionDictionary::default(), sent: 0 })
+   }
+
+   /// Function to send one request and decode the envelope of its response.
+   async fn request(&mut self, request: &XmlElement) -> io::Result<XmlElement> {
+      self.connection.send_envelope(&encode_envelope(request)).await?;
+      let envelope = self.connection.read_envelope().await?;
+      decode_envelope(&envelope, &mut self.dictionary)
+   }
+
+   /// Function to start an enumeration, return its context for the pulls.
+   pub async fn enumerate(&mut self, base: &str, scope: Scope, filter: &str, attributes: &[&str]) -> io::Result<Option<String>> {
+      let response = self.request(&enumerate_request(&self.to, base, scope, filter, attributes)).await?;
+      Ok(parse_response(&response)?.context)
+   }
+
+   /// Function to get the next page of an enumeration.
+   pub async fn pull(&mut self, context: &str, max_elements: usize) -> io::Result<EnumerationPage> {
+      let response = self.request(&pull_request(&self.to, context, max_elements)).await?;
+      parse_response(&response)
+   }
+
+   /// Function to get all the objects of a search.
+   pub async fn search(&mut self, base: &str, scope: Scope, filter: &str, attributes: &[&str]) -> io::Result<Vec<SearchEntry>> {
+      let mut entries = Vec::new();
+      let mut context = self.enumerate(base, scope, filter, attributes).await?;
+      while let Some(current) = context {
+         let page = self.pull(&current, ADWS_MAX_ELEMENTS).await?;
+         entries.extend(page.entries);
+         context = if page.end { None } else { page.context.or(Some(current)) };
+      }
+      Ok(entries)
+   }
+
+   /// Function to get one value of the base object, empty when the object or the value is missing.
+   async fn base_attribute(&mut self, base: &str, attribute: &str) -> io::Result<String> {
+      let entries = self.search(base, Scope::Base, "(objectClass=*)", &[attribute]).await?;
+      let value = entries.first()
+         .and_then(|entry| entry.attrs.get(attribute))
+         .and_then(|values| values.first().cloned())
+         .unwrap_or_default();
+      debug!("{}: {}", attribute, &value);
+      Ok(value)
+   }
+}
+
+/// Function to request all AD values over ADWS with the connection and search options of the collection,
+/// like `ldap_search`. No referral is returned.
+pub async fn adws_search(
+   common_args: &Options,
+   targets: &Vec<String>,
+   pipeline: Option<&ParsePipeline>,
+) -> Result<(Vec<SearchEntry>, Vec<Referral>)> {
+   let (ip, domain, ldapfqdn, username) = (&common_args.ip, &common_args.domain, &common_args.ldapfqdn, &common_args.username);
+   let (extended_rights, schema_guids, sample, stealth) = (common_args.extended_rights, common_args.schema_guids, common_args.sample, common_args.stealth);
+   let (ldap_filter, search_base, acl_only) = (&common_args.ldap_filter, &common_args.search_base, common_args.acl_only);
+
+   // 1- ADWS session, NTLM with the username and password only
+   let password = match &common_args.password {
+      Some(password) if !common_args.sspi && username != "not set" && common_args.socks.contains("not set") => password,
+      _ => {
+         error!("ADWS needs a username and a password (NTLM), no kerberos, SSPI or SOCKS proxy. Please use '{}'\n", "-u USER -p PASSWORD".bold());
+         return Err(Error::new(Kind::Other).desc("username and password needed for ADWS"));
+      }
+   };
+   if common_args.ldaps || common_args.port != "not set" {
+      warn!("--ldaps and --ldapport ignored, ADWS is on TCP {} and sealed with NTLM", ADWS_PORT);
+   }
+   if common_args.include_deleted {
+      warn!("Deleted objects not available over ADWS, only the live objects are collected");
+   }
+   let host = if !ip.contains("not set") { ip } else if !ldapfqdn.contains("not set") { ldapfqdn } else { domain };
+   let fqdn = if ldapfqdn.contains("not set") { host } else { ldapfqdn };
+   let (user, user_domain) = split_username(username, domain);
+   let mut client = match AdwsClient::connect(host, ADWS_PORT, fqdn, &user, &user_domain, password).await {
+      Ok(client) => {
+         info!("Connected to {} Active Directory Web Services!", domain.to_uppercase().bold().green());
+         info!("Starting data collection...");
+         client
+      }
+      Err(err) => {
+         error!("Failed to authenticate to {} Active Directory Web Services. Reason: {err}\n", domain.to_uppercase().bold().red());
+         let error = if err.kind() == ErrorKind::PermissionDenied { Error::new_login() } else { Error::new_host() };
+         return Err(error.with(err));
+      }
+   };
+
+   // 2- Naming contexts, schema version, schema GUIDs and extended rights like the LDAP collection
+   let s_dc = prepare_ldap_dc(domain);
+   let (schema_nc, config_nc) = match adws_naming_contexts(&mut client).await {
+      Ok(contexts) => contexts,
+      Err(err) => {
+         debug!("Failed to read the rootDSE over ADWS, naming contexts of the domain. Reason: {err}");
+         (format!("CN=Schema,CN=Configuration,{}", s_dc), format!("CN=Configuration,{}", s_dc))
+      }
+   };
+   let version = match client.base_attribute(&schema_nc, "objectVersion").await {
+      Ok(version) => version,
+      Err(err) => {
+         error!("Failed to read the schema version, requesting all attributes. Reason: {err}");
+         String::new()
+      }
+   };
+   if schema_guids {
+      // Stealth mode only uses the cache, CN=Schema search is too noisy
+      match adws_schema_guids(&mut client, domain, &schema_nc, &version, stealth).await {
+         Ok(count) => info!("{} schema GUIDs loaded", count.to_string().bold()),
+         Err(err) => error!("Failed to collect schema GUIDs from CN=Schema, using the builtin table. Reason: {err}"),
+      }
+   }
+   if extended_rights && !stealth {
+      match client.search(&format!("CN=Extended-Rights,{}", config_nc), Scope::OneLevel, "(objectClass=controlAccessRight)", &["cn", "displayName", "rightsGuid"]).await {
+         Ok(rights) => {
+            let custom = add_live_extended_rights(&rights);
+            info!("{} extended rights collected ({} custom)", rights.len().to_string().bold(), custom.to_string().bold());
+         }
+         Err(err) => error!("Failed to collect extended rights from CN=Extended-Rights. Reason: {err}"),
+      }
+   }
+   let attributes = requested_attributes(acl_only, false, &version);
+
+   // 3- Prepare filter and base DN
+   let s_filter = prepare_ldap_filter(ldap_filter);
+   let s_base = if search_base.contains("not set") { s_dc.to_owned() } else { search_base.to_owned() };
+   debug!("Filter: {}", &s_filter);
+   debug!("Search base: {}", &s_base);
+
+   // 4- Request ADWS
+   let mut rs: Vec<SearchEntry> = Vec::new();
+   let page_size = (common_args.page_size.max(1) as usize).min(ADWS_MAX_ELEMENTS);
+   if stealth {
+      info!("Stealth mode: pages of {} objects with {}-{}ms jitter", page_size, STEALTH_JITTER_MS.0, STEALTH_JITTER_MS.1);
+      jitter().await;
+   }
+   if targets.is_empty() {
+      adws_collect(&mut client, &s_base, &s_filter, &attributes, page_size, sample, stealth, pipeline, &mut rs).await?;
+   } else {
+      // Only the targets, then their direct relationships anywhere in the domain
+      let mut attributes = attributes.to_owned();
+      for attribute in RELATED_DN_ATTRIBUTES {
+         if !attributes.contains(&attribute) {
+            attributes.push(attribute);
+         }
+      }
+      for filter in targets_filters(targets) {
+         if stop_requested() {
+            break;
+         }
+         adws_collect(&mut client, &s_base, &format!("(&{}{})", &s_filter, filter), &attributes, page_size, sample, stealth, None, &mut rs).await?;
+      }
+      let count = rs.len();
+      for filter in related_filters(&rs) {
+         if stop_requested() {
+            break;
+         }
+         adws_collect(&mut client, &s_dc, &filter, &attributes, page_size, 100, stealth, None, &mut rs).await?;
+      }
+      let mut collected: HashSet<String> = HashSet::new();
+      rs.retain(|entry| collected.insert(entry.dn.to_uppercase()));
+      info!("{} targets and {} related objects collected", count.to_string().bold(), (rs.len() - count).to_string().bold());
+   }
+   if common_args.follow_referrals {
+      warn!("Referrals not followed over ADWS, run rusthound on the child domains");
+   }
+
+   // 5- The FSMO role objects and the Exchange organizations of the configuration NC, parsed with the domain objects
+   if !acl_only && !stealth {
+      match adws_configuration_entries(&mut client, &schema_nc, &config_nc).await {
+         Ok(entries) => rs.extend(entries),
+         Err(err) => debug!("Failed to collect the forest FSMO roles. Reason: {err}"),
+      }
+   }
+
+   // 6- End of the session
+   if let Err(err) = client.connection.close().await {
+      debug!("Failed to close the ADWS session. Reason: {err}");
+   }
+   Ok((rs, Vec::new()))
+}
+
+/// Function to run one search of the collection page by page: progress, sampling, graceful stop and
+/// pages sent to the parsing pipeline like `ldap_collect`.
+async fn adws_collect(
+   client: &mut AdwsClient,
+   base: &str,
+   filter: &str,
+   attributes: &[&str],
+   page_size: usize,
+   sample: u8,
+   stealth: bool,
+   pipeline: Option<&ParsePipeline>,
+   rs: &mut Vec<SearchEntry>,
+) -> Result<()> {
+   let pb = ProgressBar::new(1);
+   let mut count = 0;
+   let mut skipped = 0;
+   let mut seen: HashSet<String> = HashSet::new();
+   let mut context = client.enumerate(base, Scope::Subtree, filter, attributes).await.map_err(|err| {
+      error!("No data collected! Reason: {err}");
+      Error::new(Kind::Other).desc(format!("no data collected: {err}"))
+   })?;
+   while let Some(current) = context {
+      let page = client.pull(&current, page_size).await.map_err(|err| {
+         error!("No data collected! Reason: {err}");
+         Error::new(Kind::Other).desc(format!("no data collected: {err}"))
+      })?;
+      let page_start = rs.len();
+      for entry in page.entries {
+         if !seen.insert(entry.dn.to_uppercase()) {
+            continue;
+         }
+         count += 1;
+         progress_bar(pb.to_owned(), "ADWS objects retreived".to_string(), count, "#".to_string());
+         set_objects(client.sent + rs.len() as u64 + 1);
+         // Graceful stop from the control socket, keep what is collected
+         if stop_requested() {
+            break;
+         }
+         // Drop regular users and computers out of the sample
+         if !keep_sampled_entry(&entry, sample) {
+            skipped += 1;
+            continue;
+         }
+         rs.push(entry);
+      }
+      if let Some(pipeline) = pipeline {
+         client.sent += (rs.len() - page_start) as u64;
+         pipeline.send(rs.split_off(page_start)).await;
+      }
+      if page.end || stop_requested() {
+         break;
+      }
+      context = page.context.or(Some(current));
+      // The next page is requested when this one is consumed, wait before
+      if stealth {
+         jitter().await;
+      }
+   }
+   pb.finish_and_clear();
+   if sample < 100 {
+      info!("Sampling {}% of regular users and computers: {} objects skipped", sample.to_string().bold(), skipped.to_string().bold());
+   }
+   if stop_requested() {
+      info!("Collection stopped, {} objects kept", (client.sent + rs.len() as u64).to_string().bold());
+   } else {
+      info!("All data collected!");
+   }
+   Ok(())
+}
+
+/// Function to get the schema and configuration naming contexts of the rootDSE.
+async fn adws_naming_contexts(client: &mut AdwsClient) -> io::Result<(String, String)> {
+   let entries = client.search("", Scope::Base, "(objectClass=*)", &["schemaNamingContext", "configurationNamingContext"]).await?;
+   let value = |name: &str| entries.first().and_then(|entry| entry.attrs.get(name)).and_then(|values| values.first().cloned());
+   match (value("schemaNamingContext"), value("configurationNamingContext")) {
+      (Some(schema_nc), Some(config_nc)) => Ok((schema_nc, config_nc)),
+      _ => Err(io::Error::new(ErrorKind::NotFound, "no naming context in the rootDSE")),
+   }
+}
+
+/// Function to build the schema map (cn => schemaIDGUID) from CN=Schema,CN=Configuration with the disk cache
+/// of `ldap_schema_guids`. Return the number of GUIDs loaded.
+async fn adws_schema_guids(client: &mut AdwsClient, domain: &String, schema_nc: &str, version: &String, cache_only: bool) -> io::Result<usize> {
+   let cache = schema_cache_path(domain, version);
+   if let Some(guids) = load_schema_cache(&cache) {
+      debug!("Schema GUIDs loaded from cache {}", &cache);
+      let count = guids.len();
+      add_live_schema_guids(guids);
+      return Ok(count)
+   }
+   if cache_only {
+      debug!("No schema cache {}, using the builtin table", &cache);
+      return Ok(0)
+   }
+   let entries = client.search(schema_nc, Scope::OneLevel, "(|(objectClass=attributeSchema)(objectClass=classSchema))", &["cn", "schemaIDGUID"]).await?;
+   let mut guids: HashMap<String, String> = HashMap::new();
+   for entry in entries {
+      if let (Some(name), Some(guid)) = (entry.attrs.get("cn"), entry.bin_attrs.get("schemaIDGUID")) {
+         guids.insert(name[0].to_lowercase(), decode_guid(&guid[0]).to_lowercase());
+      }
+   }
+   save_schema_cache(&cache, &guids);
+   let count = guids.len();
+   add_live_schema_guids(guids);
+   Ok(count)
+}
+
+/// Function to request the objects of the configuration NC parsed with the domain, like `ldap_configuration_entries`:
+/// FSMO role owners, Exchange organizations and servers, device registration service. A missing container is
+/// a fault of ADWS, skipped.
+async fn adws_configuration_entries(client: &mut AdwsClient, schema_nc: &str, config_nc: &str) -> io::Result<Vec<SearchEntry>> {
+   let mut entries: Vec<SearchEntry> = Vec::new();
+   for base in [schema_nc.to_owned(), format!("CN=Partitions,{}", config_nc)] {
+      entries.extend(client.search(&base, Scope::Base, "(objectClass=*)", &["objectClass", "distinguishedName", "fSMORoleOwner", "msDS-Behavior-Version"]).await?);
+   }
+   let exchange = format!("CN=Microsoft Exchange,CN=Services,{}", config_nc);
+   let organizations = client.search(&exchange, Scope::OneLevel, "(objectClass=msExchOrganizationContainer)", &["objectClass", "distinguishedName", "name"]).await.unwrap_or_default();
+   if !organizations.is_empty() {
+      entries.extend(organizations);
+      entries.extend(client.search(&exchange, Scope::Subtree, "(objectClass=msExchExchangeServer)", &["objectClass", "distinguishedName", "name", "networkAddress", "serialNumber", "msExchCurrentServerRoles"]).await?);
+   }
+   let devices = format!("CN=Device Registration Configuration,CN=Services,{}", config_nc);
+   entries.extend(client.search(&devices, Scope::OneLevel, "(objectClass=serviceConnectionPoint)", &["objectClass", "distinguishedName", "keywords"]).await.unwrap_or_default());
+   Ok(entries)
+}
+
+/// Test functions
+#[test]
+pub fn test_adws_client() {
+   use crate::modules::remote::{hmac_md5, ntowfv2, Rc4, NTLM_SEAL_FLAGS};
+   use framing::NtlmSealing;
+   use std::io::{Read, Write};
+
+   // Fake ADWS: framing preamble, NTLM handshake checking the proof of the password, then sealed envelopes
+   let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+   let port = listener.local_addr().unwrap().port();
+   let server = std::thread::spawn(move || {
+      let mut filters: Vec<String> = Vec::new();
+      for stream in listener.incoming().take(2) {
+         let mut stream = stream.unwrap();
+         let mut read = |len: usize| -> Vec<u8> {
+            let mut buffer = vec![0u8; len];
+            stream.read_exact(&mut buffer).unwrap();
+            buffer
+         };
+         assert_eq!(read(6), [0x00, 1, 0, 0x01, 0x02, 0x02]);
+         let via = read(1)[0] as usize;
+         assert_eq!(String::from_utf8(read(via)).unwrap(), format!("net.tcp://dc01.essos.local:{}/ActiveDirectoryWebServices/Windows/Enumeration", port));
+         assert_eq!(read(3), [0x03, 0x08, 0x09]);
+         let upgrade = read(1)[0] as usize;
+         assert_eq!(read(upgrade), b"application/negotiate");
+         stream.write_all(&[0x0A]).unwrap();
+
+         let read_handshake = |stream: &mut std::net::TcpStream| -> Vec<u8> {
+            let mut header = [0u8; 5];
+            stream.read_exact(&mut header).unwrap();
+            let mut payload = vec![0u8; u16::from_be_bytes([header[3], header[4]]) as usize];
+            stream.read_exact(&mut payload).unwrap();
+            payload
+         };
+         read_handshake(&mut stream);
+         let server_challenge = [7u8; 8];
+         let mut challenge = b"NTLMSSP\0".to_vec();
+         challenge.extend_from_slice(&2u32.to_le_bytes());
+         challenge.extend_from_slice(&[0, 0, 0, 0, 48, 0, 0, 0]);
+         challenge.extend_from_slice(&NTLM_SEAL_FLAGS.to_le_bytes());
+         challenge.extend_from_slice(&server_challenge);
+         challenge.extend_from_slice(&[0; 8]);
+         challenge.extend_from_slice(&[4, 0, 4, 0, 48, 0, 0, 0, 0, 0, 0, 0]);
+         let mut message = vec![0x16, 1, 0];
+         message.extend_from_slice(&(challenge.len() as u16).to_be_bytes());
+         message.extend(challenge);
+         stream.write_all(&message).unwrap();
+
+         let authenticate = read_handshake(&mut stream);
+         let field = |index: usize| {
+            let at = 12 + 8 * index;
+            let (len, offset) = (u16::from_le_bytes([authenticate[at], authenticate[at + 1]]) as usize, u32::from_le_bytes([authenticate[at + 4], authenticate[at + 5], authenticate[at + 6], authenticate[at + 7]]) as usize);
+            authenticate[offset..offset + len].to_vec()
+         };
+         let nt_response = field(1);
+         let response_key = ntowfv2("Password1", "khal.drogo", "ESSOS");
+         if hmac_md5(&response_key, &[&server_challenge, &nt_response[16..]]) != nt_response[..16] {
+            // SEC_E_LOGON_DENIED
+            stream.write_all(&[0x15, 1, 0, 0, 8, 0x0c, 0x03, 0x09, 0x80, 0, 0, 0, 0]).unwrap();
+            continue
+         }
+         let session_key = Rc4::new(&hmac_md5(&response_key, &[&nt_response[..16]])).apply(&field(5));
+         stream.write_all(&[0x14, 1, 0, 0, 0]).unwrap();
+
+         let (mut client, mut server) = (NtlmSealing::new(&session_key, true), NtlmSealing::new(&session_key, false));
+         let mut read_sealed = |stream: &mut std::net::TcpStream| -> Vec<u8> {
+            let mut size = [0u8; 4];
+            stream.read_exact(&mut size).unwrap();
+            let mut token = vec![0u8; u32::from_le_bytes(size) as usize];
+            stream.read_exact(&mut token).unwrap();
+            client.unseal(&token).unwrap()
+         };
+         let mut write_sealed = |stream: &mut std::net::TcpStream, data: &[u8]| {
+            let token = server.seal(data);
+            stream.write_all(&(token.len() as u32).to_le_bytes()).unwrap();
+            stream.write_all(&token).unwrap();
+         };
+         assert_eq!(read_sealed(&mut stream), [0x0C]);
+         write_sealed(&mut stream, &[0x0B]);
+
+         let mut dictionary = SessionDictionary::default();
+         let mut envelope = |data: Vec<u8>| {
+            assert_eq!(data[0], 0x06);
+            let (size, len) = framing::decode_size(&data[1..]).unwrap();
+            decode_envelope(&data[1 + len..1 + len + size], &mut dictionary).unwrap()
+         };
+         let respond = |stream: &mut std::net::TcpStream, write_sealed: &mut dyn FnMut(&mut std::net::TcpStream, &[u8]), body: XmlElement| {
+            let data = encode_envelope(&XmlElement::new("s:Envelope").child(XmlElement::new("s:Body").child(body)));
+            let mut record = vec![0x06];
+            framing::encode_size(data.len(), &mut record);
+            record.extend(data);
+            write_sealed(stream, &record);
+         };
+         let enumerate = envelope(read_sealed(&mut stream));
+         filters.push(enumerate.find("LdapQuery").unwrap().find("Filter").unwrap().text_content());
+         respond(&mut stream, &mut write_sealed, XmlElement::new("wsen:EnumerateResponse")
+            .child(XmlElement::new("wsen:EnumerationContext").text("context-1")));
+         let pull = envelope(read_sealed(&mut stream));
+         assert_eq!(pull.find("EnumerationContext").unwrap().text_content(), "context-1");
+         let value = |text: &str, value_type: &str| XmlElement::new("ad:value").attribute("xsi:type", value_type).text(text);
+         respond(&mut stream, &mut write_sealed, XmlElement::new("wsen:PullResponse")
+            .child(XmlElement::new("wsen:Items").child(XmlElement::new("addata:user")
+               .child(XmlElement::new("ad:distinguishedName").child(value("CN=khal.drogo,CN=Users,DC=essos,DC=local", "xsd:string")))
+               .child(XmlElement::new("addata:objectSid").child(value("AQEAAAAAAAUgAAAA", "xsd:base64Binary")))))
+            .child(XmlElement::new("wsen:EndOfSequence")));
+         assert_eq!(read_sealed(&mut stream), [0x07]);
+      }
+      filters
+   });
+
+   let runtime = tokio::runtime::Builder::new_current_thread().enable_all().build().unwrap();
+   runtime.block_on(async {
+      let password = SecretString::new("Password1");
+      let mut client = AdwsClient::connect("127.0.0.1", port, "dc01.essos.local", "khal.drogo", "ESSOS", &password).await.unwrap();
+      let entries = client.search("DC=essos,DC=local", Scope::Subtree, "(objectClass=user)", &["objectSid"]).await.unwrap();
+      assert_eq!(entries.len(), 1);
+      assert_eq!(entries[0].dn, "CN=khal.drogo,CN=Users,DC=essos,DC=local");
+      assert_eq!(entries[0].bin_attrs["objectSid"], vec![vec![1, 1, 0, 0, 0, 0, 0, 5, 32, 0, 0, 0]]);
+      client.connection.close().await.unwrap();
+
+      let wrong = SecretString::new("Password2");
+      let err = AdwsClient::connect("127.0.0.1", port, "dc01.essos.local", "khal.drogo", "ESSOS", &wrong).await.err().unwrap();
+      assert_eq!(err.kind(), ErrorKind::PermissionDenied);
+      assert!(err.to_string().contains("8009030c"));
+   });
+   assert_eq!(server.join().unwrap(), vec!["(objectClass=user)"]);
+}
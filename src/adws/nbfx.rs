@@ -0,0 +1,569 @@
+//! Binary XML of the ADWS SOAP envelopes
+//!
+//! The NetTcpBinding encodes the envelopes in the .NET binary XML format [MC-NBFX]: one record per element,
+//! attribute or text, the strings inline or as ids of a dictionary. The even ids are the static dictionary of
+//! [MC-NBFS], the odd ids the strings sent by the peer at the start of its envelopes for the rest of the session
+//! [MC-NBFSE]. The envelopes of rusthound have no dictionary string, all the names and texts are inline.
+use chrono::{Duration, NaiveDate};
+use std::io::{Error, ErrorKind, Result};
+
+use crate::adws::framing::{decode_size, encode_size};
+use crate::modules::offline::base64_encode;
+
+const END_ELEMENT: u8 = 0x01;
+const COMMENT: u8 = 0x02;
+const SHORT_ATTRIBUTE: u8 = 0x04;
+const ATTRIBUTE: u8 = 0x05;
+const SHORT_DICTIONARY_ATTRIBUTE: u8 = 0x06;
+const DICTIONARY_ATTRIBUTE: u8 = 0x07;
+const SHORT_XMLNS_ATTRIBUTE: u8 = 0x08;
+const XMLNS_ATTRIBUTE: u8 = 0x09;
+const SHORT_DICTIONARY_XMLNS_ATTRIBUTE: u8 = 0x0A;
+const DICTIONARY_XMLNS_ATTRIBUTE: u8 = 0x0B;
+const PREFIX_DICTIONARY_ATTRIBUTE_A: u8 = 0x0C;
+const PREFIX_ATTRIBUTE_A: u8 = 0x26;
+const SHORT_ELEMENT: u8 = 0x40;
+const ELEMENT: u8 = 0x41;
+const SHORT_DICTIONARY_ELEMENT: u8 = 0x42;
+const DICTIONARY_ELEMENT: u8 = 0x43;
+const PREFIX_DICTIONARY_ELEMENT_A: u8 = 0x44;
+const PREFIX_ELEMENT_A: u8 = 0x5E;
+const PREFIX_ELEMENT_Z: u8 = 0x77;
+
+// Text records, the next odd record type is the same text followed by the end of the element
+const ZERO_TEXT: u8 = 0x80;
+const ONE_TEXT: u8 = 0x82;
+const FALSE_TEXT: u8 = 0x84;
+const TRUE_TEXT: u8 = 0x86;
+const INT8_TEXT: u8 = 0x88;
+const INT16_TEXT: u8 = 0x8A;
+const INT32_TEXT: u8 = 0x8C;
+const INT64_TEXT: u8 = 0x8E;
+const FLOAT_TEXT: u8 = 0x90;
+const DOUBLE_TEXT: u8 = 0x92;
+const DECIMAL_TEXT: u8 = 0x94;
+const DATETIME_TEXT: u8 = 0x96;
+const CHARS8_TEXT: u8 = 0x98;
+const CHARS16_TEXT: u8 = 0x9A;
+const CHARS32_TEXT: u8 = 0x9C;
+const BYTES8_TEXT: u8 = 0x9E;
+const BYTES16_TEXT: u8 = 0xA0;
+const BYTES32_TEXT: u8 = 0xA2;
+const START_LIST_TEXT: u8 = 0xA4;
+const END_LIST_TEXT: u8 = 0xA6;
+const EMPTY_TEXT: u8 = 0xA8;
+const DICTIONARY_TEXT: u8 = 0xAA;
+const UNIQUE_ID_TEXT: u8 = 0xAC;
+const TIMESPAN_TEXT: u8 = 0xAE;
+const UUID_TEXT: u8 = 0xB0;
+const UINT64_TEXT: u8 = 0xB2;
+const BOOL_TEXT: u8 = 0xB4;
+const UNICODE_CHARS8_TEXT: u8 = 0xB6;
+const UNICODE_CHARS16_TEXT: u8 = 0xB8;
+const UNICODE_CHARS32_TEXT: u8 = 0xBA;
+const QNAME_DICTIONARY_TEXT: u8 = 0xBC;
+
+/// Static dictionary of the SOAP, addressing and security strings, id / 2.
+/// The envelopes of ADWS only use the first ones, the others are decoded as placeholders.
+const STATIC_DICTIONARY: [&str; 128] = [
+   "mustUnderstand", "Envelope", "http://www.w3.org/2003/05/soap-envelope", "http://www.w3.org/2005/08/addressing",
+   "Header", "Action", "To", "Body", "Algorithm", "RelatesTo", "http://www.w3.org/2005/08/addressing/anonymous", "URI",
+   "Reference", "MessageID", "Id", "Identifier", "http://schemas.xmlsoap.org/ws/2005/02/rm", "Transforms", "Transform",
+   "DigestMethod", "DigestValue", "Address", "ReplyTo", "SequenceAcknowledgement", "AcknowledgementRange", "Upper",
+   "Lower", "BufferRemaining", "http://schemas.microsoft.com/ws/2006/05/rm",
+   "http://schemas.xmlsoap.org/ws/2005/02/rm/SequenceAcknowledgement", "SecurityTokenReference", "Sequence",
+   "MessageNumber", "http://www.w3.org/2000/09/xmldsig#", "http://www.w3.org/2000/09/xmldsig#enveloped-signature",
+   "KeyInfo", "http://docs.oasis-open.org/wss/2004/01/oasis-200401-wss-wssecurity-secext-1.0.xsd",
+   "http://www.w3.org/2001/04/xmlenc#", "http://schemas.xmlsoap.org/ws/2005/02/sc", "DerivedKeyToken", "Nonce",
+   "Signature", "SignedInfo", "CanonicalizationMethod", "SignatureMethod", "SignatureValue", "DataReference",
+   "EncryptedData", "EncryptionMethod", "CipherData", "CipherValue",
+   "http://docs.oasis-open.org/wss/2004/01/oasis-200401-wss-wssecurity-utility-1.0.xsd", "Security", "Timestamp",
+   "Created", "Expires", "Length", "ReferenceList", "ValueType", "Type", "EncryptedHeader",
+   "http://docs.oasis-open.org/wss/oasis-wss-wssecurity-secext-1.1.xsd", "RequestSecurityTokenResponseCollection",
+   "http://schemas.xmlsoap.org/ws/2005/02/trust", "http://schemas.xmlsoap.org/ws/2005/02/trust#BinarySecret",
+   "http://schemas.microsoft.com/ws/2006/02/transactions", "s", "Fault", "MustUnderstand", "role", "relay", "Code",
+   "Reason", "Text", "Node", "Role", "Detail", "Value", "Subcode", "NotUnderstood", "qname", "", "From", "FaultTo",
+   "EndpointReference", "PortType", "ServiceName", "PortName", "ReferenceProperties", "RelationshipType", "Reply", "a",
+   "http://schemas.xmlsoap.org/ws/2006/02/addressingidentity", "Identity", "Spn", "Upn", "Rsa", "Dns",
+   "X509v3Certificate", "http://www.w3.org/2005/08/addressing/fault", "ReferenceParameters", "IsReferenceParameter",
+   "http://www.w3.org/2005/08/addressing/reply", "http://www.w3.org/2005/08/addressing/none", "Metadata",
+   "http://schemas.xmlsoap.org/ws/2004/08/addressing", "http://schemas.xmlsoap.org/ws/2004/08/addressing/role/anonymous",
+   "http://schemas.xmlsoap.org/ws/2004/08/addressing/fault", "http://schemas.xmlsoap.org/ws/2004/06/addressingex",
+   "RedirectTo", "Via", "http://www.w3.org/2001/10/xml-exc-c14n#", "PrefixList", "InclusiveNamespaces", "ec",
+   "SecurityContextToken", "Generation", "Label", "Offset", "Properties", "Cookie", "wsc",
+   "http://schemas.xmlsoap.org/ws/2004/04/sc", "http://schemas.xmlsoap.org/ws/2004/04/security/sc/dk",
+   "http://schemas.xmlsoap.org/ws/2004/04/security/sc/sct", "http://schemas.xmlsoap.org/ws/2004/04/security/trust/RST/SCT",
+   "http://schemas.xmlsoap.org/ws/2004/04/security/trust/RSTR/SCT", "RenewNeeded",
+];
+
+/// Element of an envelope, the names keep their prefix and the namespaces are the xmlns attributes.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct XmlElement {
+   pub prefix: String,
+   pub name: String,
+   /// (prefix, name, value), "xmlns" prefix or name for the namespace declarations
+   pub attributes: Vec<(String, String, String)>,
+   pub children: Vec<XmlNode>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum XmlNode {
+   Element(XmlElement),
+   Text(String),
+}
+
+/// Function to split a qualified name like s:Envelope in (prefix, name).
+fn split_name(qualified: &str) -> (String, String) {
+   match qualified.split_once(':') {
+      Some((prefix, name)) => (prefix.to_string(), name.to_string()),
+      None => (String::new(), qualified.to_string()),
+   }
+}
+
+impl XmlElement {
+   pub fn new(qualified: &str) -> XmlElement {
+      let (prefix, name) = split_name(qualified);
+      XmlElement { prefix, name, ..Default::default() }
+   }
+
+   pub fn attribute(mut self, qualified: &str, value: &str) -> XmlElement {
+      let (prefix, name) = split_name(qualified);
+      self.attributes.push((prefix, name, value.to_string()));
+      self
+   }
+
+   pub fn child(mut self, child: XmlElement) -> XmlElement {
+      self.children.push(XmlNode::Element(child));
+      self
+   }
+
+   pub fn text(mut self, text: &str) -> XmlElement {
+      self.children.push(XmlNode::Text(text.to_string()));
+      self
+   }
+
+   /// Child elements, whatever their prefix.
+   pub fn elements(&self) -> impl Iterator<Item = &XmlElement> {
+      self.children.iter().filter_map(|child| match child {
+         XmlNode::Element(element) => Some(element),
+         XmlNode::Text(_) => None,
+      })
+   }
+
+   /// Function to find the first element of this name (without prefix) in the tree, depth first.
+   pub fn find(&self, name: &str) -> Option<&XmlElement> {
+      if self.name == name {
+         return Some(self)
+      }
+      self.elements().find_map(|element| element.find(name))
+   }
+
+   /// Text of the element and of its children.
+   pub fn text_content(&self) -> String {
+      self.children.iter().map(|child| match child {
+         XmlNode::Element(element) => element.text_content(),
+         XmlNode::Text(text) => text.to_owned(),
+      }).collect()
+   }
+
+   /// Value of an attribute, whatever its prefix.
+   pub fn attribute_value(&self, name: &str) -> Option<&str> {
+      self.attributes.iter().find(|(_, attribute, _)| attribute == name).map(|(_, _, value)| value.as_str())
+   }
+}
+
+/// Strings sent by the peer during the session, ids 1, 3, 5...
+#[derive(Debug, Default)]
+pub struct SessionDictionary {
+   strings: Vec<String>,
+}
+
+/// Function to encode an envelope of the session: empty string table then the records.
+pub fn encode_envelope(root: &XmlElement) -> Vec<u8> {
+   let mut buffer = vec![0];
+   write_element(root, &mut buffer);
+   buffer
+}
+
+fn write_string(value: &str, buffer: &mut Vec<u8>) {
+   encode_size(value.len(), buffer);
+   buffer.extend_from_slice(value.as_bytes());
+}
+
+fn prefix_letter(prefix: &str) -> Option<u8> {
+   match prefix.as_bytes() {
+      [letter @ b'a'..=b'z'] => Some(letter - b'a'),
+      _ => None,
+   }
+}
+
+fn write_text(text: &str, buffer: &mut Vec<u8>) {
+   let len = text.len();
+   if len <= u8::MAX as usize {
+      buffer.extend_from_slice(&[CHARS8_TEXT, len as u8]);
+   } else if len <= u16::MAX as usize {
+      buffer.push(CHARS16_TEXT);
+      buffer.extend_from_slice(&(len as u16).to_le_bytes());
+   } else {
+      buffer.push(CHARS32_TEXT);
+      buffer.extend_from_slice(&(len as i32).to_le_bytes());
+   }
+   buffer.extend_from_slice(text.as_bytes());
+}
+
+fn write_element(element: &XmlElement, buffer: &mut Vec<u8>) {
+   match prefix_letter(&element.prefix) {
+      _ if element.prefix.is_empty() => buffer.push(SHORT_ELEMENT),
+      Some(letter) => buffer.push(PREFIX_ELEMENT_A + letter),
+      None => {
+         buffer.push(ELEMENT);
+         write_string(&element.prefix, buffer);
+      }
+   }
+   write_string(&element.name, buffer);
+   for (prefix, name, value) in &element.attributes {
+      if prefix.is_empty() && name == "xmlns" {
+         buffer.push(SHORT_XMLNS_ATTRIBUTE);
+         write_string(value, buffer);
+         continue
+      }
+      if prefix == "xmlns" {
+         buffer.push(XMLNS_ATTRIBUTE);
+         write_string(name, buffer);
+         write_string(value, buffer);
+         continue
+      }
+      match prefix_letter(prefix) {
+         _ if prefix.is_empty() => buffer.push(SHORT_ATTRIBUTE),
+         Some(letter) => buffer.push(PREFIX_ATTRIBUTE_A + letter),
+         None => {
+            buffer.push(ATTRIBUTE);
+            write_string(prefix, buffer);
+         }
+      }
+      write_string(name, buffer);
+      write_text(value, buffer);
+   }
+   for child in &element.children {
+      match child {
+         XmlNode::Element(child) => write_element(child, buffer),
+         XmlNode::Text(text) => write_text(text, buffer),
+      }
+   }
+   buffer.push(END_ELEMENT);
+}
+
+/// Function to decode an envelope of the session, its strings are added to the dictionary first.
+pub fn decode_envelope(data: &[u8], dictionary: &mut SessionDictionary) -> Result<XmlElement> {
+   let (table_size, start) = decode_size(data).ok_or_else(|| invalid("bad string table size"))?;
+   let table = data.get(start..start + table_size).ok_or_else(|| invalid("string table out of the envelope"))?;
+   let mut reader = Reader { data: table, position: 0, dictionary };
+   let mut strings = Vec::new();
+   while reader.position < table.len() {
+      strings.push(reader.string()?);
+   }
+   dictionary.strings.extend(strings);
+   Reader { data: &data[start + table_size..], position: 0, dictionary }.document()
+}
+
+struct Reader<'a> {
+   data: &'a [u8],
+   position: usize,
+   dictionary: &'a SessionDictionary,
+}
+
+impl<'a> Reader<'a> {
+   fn bytes(&mut self, len: usize) -> Result<&'a [u8]> {
+      let bytes = self.data.get(self.position..self.position + len).ok_or_else(|| invalid("record out of the envelope"))?;
+      self.position += len;
+      Ok(bytes)
+   }
+
+   fn byte(&mut self) -> Result<u8> {
+      Ok(self.bytes(1)?[0])
+   }
+
+   fn array<const N: usize>(&mut self) -> Result<[u8; N]> {
+      let mut array = [0u8; N];
+      array.copy_from_slice(self.bytes(N)?);
+      Ok(array)
+   }
+
+   fn size(&mut self) -> Result<usize> {
+      let (size, len) = decode_size(&self.data[self.position.min(self.data.len())..]).ok_or_else(|| invalid("bad size"))?;
+      self.position += len;
+      Ok(size)
+   }
+
+   fn utf8(&mut self, len: usize) -> Result<String> {
+      String::from_utf8(self.bytes(len)?.to_vec()).map_err(|_| invalid("string not in UTF-8"))
+   }
+
+   fn string(&mut self) -> Result<String> {
+      let len = self.size()?;
+      self.utf8(len)
+   }
+
+   fn dictionary_string(&mut self) -> Result<String> {
+      let id = self.size()?;
+      Ok(self.lookup(id))
+   }
+
+   fn lookup(&self, id: usize) -> String {
+      let string = if id & 1 == 1 { self.dictionary.strings.get(id / 2).map(String::as_str) } else { STATIC_DICTIONARY.get(id / 2).copied() };
+      string.map(str::to_string).unwrap_or_else(|| format!("dictionary-{}", id))
+   }
+
+   /// Function to read the root element and its children.
+   fn document(&mut self) -> Result<XmlElement> {
+      let mut stack: Vec<XmlElement> = Vec::new();
+      loop {
+         let record = self.byte()?;
+         match record {
+            END_ELEMENT => {
+               if let Some(root) = close_element(&mut stack)? {
+                  return Ok(root)
+               }
+            }
+            COMMENT => {
+               self.string()?;
+            }
+            SHORT_ATTRIBUTE..=0x3F => {
+               let attribute = self.attribute(record)?;
+               stack.last_mut().ok_or_else(|| invalid("attribute out of an element"))?.attributes.push(attribute);
+            }
+            SHORT_ELEMENT..=PREFIX_ELEMENT_Z => {
+               let (prefix, name) = match record {
+                  SHORT_ELEMENT => (String::new(), self.string()?),
+                  ELEMENT => (self.string()?, self.string()?),
+                  SHORT_DICTIONARY_ELEMENT => (String::new(), self.dictionary_string()?),
+                  DICTIONARY_ELEMENT => (self.string()?, self.dictionary_string()?),
+                  PREFIX_DICTIONARY_ELEMENT_A..=0x5D => (letter(record - PREFIX_DICTIONARY_ELEMENT_A), self.dictionary_string()?),
+                  _ => (letter(record - PREFIX_ELEMENT_A), self.string()?),
+               };
+               stack.push(XmlElement { prefix, name, ..Default::default() });
+            }
+            ZERO_TEXT..=QNAME_DICTIONARY_TEXT | 0xBD => {
+               let text = self.text(record & !1)?;
+               stack.last_mut().ok_or_else(|| invalid("text out of an element"))?.children.push(XmlNode::Text(text));
+               if record & 1 == 1 {
+                  if let Some(root) = close_element(&mut stack)? {
+                     return Ok(root)
+                  }
+               }
+            }
+            _ => return Err(invalid(format!("unsupported record 0x{:02x}", record))),
+         }
+      }
+   }
+
+   /// Function to read an attribute record: (prefix, name, value).
+   fn attribute(&mut self, record: u8) -> Result<(String, String, String)> {
+      Ok(match record {
+         SHORT_XMLNS_ATTRIBUTE => (String::new(), "xmlns".to_string(), self.string()?),
+         XMLNS_ATTRIBUTE => ("xmlns".to_string(), self.string()?, self.string()?),
+         SHORT_DICTIONARY_XMLNS_ATTRIBUTE => (String::new(), "xmlns".to_string(), self.dictionary_string()?),
+         DICTIONARY_XMLNS_ATTRIBUTE => ("xmlns".to_string(), self.string()?, self.dictionary_string()?),
+         _ => {
+            let (prefix, name) = match record {
+               SHORT_ATTRIBUTE => (String::new(), self.string()?),
+               ATTRIBUTE => (self.string()?, self.string()?),
+               SHORT_DICTIONARY_ATTRIBUTE => (String::new(), self.dictionary_string()?),
+               DICTIONARY_ATTRIBUTE => (self.string()?, self.dictionary_string()?),
+               PREFIX_DICTIONARY_ATTRIBUTE_A..=0x25 => (letter(record - PREFIX_DICTIONARY_ATTRIBUTE_A), self.dictionary_string()?),
+               _ => (letter(record - PREFIX_ATTRIBUTE_A), self.string()?),
+            };
+            let text = self.byte()?;
+            if !(ZERO_TEXT..=QNAME_DICTIONARY_TEXT).contains(&text) || text & 1 == 1 {
+               return Err(invalid(format!("record 0x{:02x} in the value of attribute {}", text, name)))
+            }
+            (prefix, name, self.text(text)?)
+         }
+      })
+   }
+
+   /// Function to read the value of a text record as its XML text.
+   fn text(&mut self, record: u8) -> Result<String> {
+      Ok(match record {
+         ZERO_TEXT => "0".to_string(),
+         ONE_TEXT => "1".to_string(),
+         FALSE_TEXT => "false".to_string(),
+         TRUE_TEXT => "true".to_string(),
+         INT8_TEXT => (self.byte()? as i8).to_string(),
+         INT16_TEXT => i16::from_le_bytes(self.array()?).to_string(),
+         INT32_TEXT => i32::from_le_bytes(self.array()?).to_string(),
+         INT64_TEXT => i64::from_le_bytes(self.array()?).to_string(),
+         FLOAT_TEXT => f32::from_le_bytes(self.array()?).to_string(),
+         DOUBLE_TEXT => f64::from_le_bytes(self.array()?).to_string(),
+         DECIMAL_TEXT => decimal(&self.array()?),
+         DATETIME_TEXT => datetime(u64::from_le_bytes(self.array()?)),
+         CHARS8_TEXT | CHARS16_TEXT | CHARS32_TEXT | BYTES8_TEXT | BYTES16_TEXT | BYTES32_TEXT
+         | UNICODE_CHARS8_TEXT | UNICODE_CHARS16_TEXT | UNICODE_CHARS32_TEXT => {
+            let len = match record {
+               CHARS8_TEXT | BYTES8_TEXT | UNICODE_CHARS8_TEXT => self.byte()? as usize,
+               CHARS16_TEXT | BYTES16_TEXT | UNICODE_CHARS16_TEXT => u16::from_le_bytes(self.array()?) as usize,
+               _ => i32::from_le_bytes(self.array()?).max(0) as usize,
+            };
+            match record {
+               CHARS8_TEXT | CHARS16_TEXT | CHARS32_TEXT => self.utf8(len)?,
+               BYTES8_TEXT | BYTES16_TEXT | BYTES32_TEXT => base64_encode(self.bytes(len)?),
+               _ => {
+                  let units: Vec<u16> = self.bytes(len)?.chunks_exact(2).map(|unit| u16::from_le_bytes([unit[0], unit[1]])).collect();
+                  String::from_utf16_lossy(&units)
+               }
+            }
+         }
+         START_LIST_TEXT => {
+            let mut items = Vec::new();
+            loop {
+               match self.byte()? {
+                  END_LIST_TEXT => break,
+                  item => items.push(self.text(item)?),
+               }
+            }
+            items.join(" ")
+         }
+         EMPTY_TEXT => String::new(),
+         DICTIONARY_TEXT => self.dictionary_string()?,
+         UNIQUE_ID_TEXT => format!("urn:uuid:{}", guid(&self.array()?)),
+         TIMESPAN_TEXT => timespan(i64::from_le_bytes(self.array()?)),
+         UUID_TEXT => guid(&self.array()?),
+         UINT64_TEXT => u64::from_le_bytes(self.array()?).to_string(),
+         BOOL_TEXT => (self.byte()? != 0).to_string(),
+         QNAME_DICTIONARY_TEXT => {
+            let prefix = letter(self.byte()?);
+            format!("{}:{}", prefix, self.dictionary_string()?)
+         }
+         _ => return Err(invalid(format!("unsupported text record 0x{:02x}", record))),
+      })
+   }
+}
+
+/// Function to end the element on top of the stack, return the root when it is the one ended.
+fn close_element(stack: &mut Vec<XmlElement>) -> Result<Option<XmlElement>> {
+   let element = stack.pop().ok_or_else(|| invalid("end of an element not opened"))?;
+   match stack.last_mut() {
+      Some(parent) => {
+         parent.children.push(XmlNode::Element(element));
+         Ok(None)
+      }
+      None => Ok(Some(element)),
+   }
+}
+
+fn letter(index: u8) -> String {
+   ((b'a' + index) as char).to_string()
+}
+
+/// Function to format a GUID in the byte order of .NET, lower case.
+pub fn guid(raw: &[u8; 16]) -> String {
+   format!(
+      "{:08x}-{:04x}-{:04x}-{}-{}",
+      u32::from_le_bytes([raw[0], raw[1], raw[2], raw[3]]),
+      u16::from_le_bytes([raw[4], raw[5]]),
+      u16::from_le_bytes([raw[6], raw[7]]),
+      raw[8..10].iter().map(|byte| format!("{:02x}", byte)).collect::<String>(),
+      raw[10..].iter().map(|byte| format!("{:02x}", byte)).collect::<String>(),
+   )
+}
+
+/// Function to format a decimal: 2 reserved bytes, scale, sign, 96 bits integer (high 32 bits, low 64 bits).
+fn decimal(raw: &[u8; 16]) -> String {
+   let (scale, negative) = (raw[2] as usize, raw[3] & 0x80 != 0);
+   let high = u32::from_le_bytes([raw[4], raw[5], raw[6], raw[7]]) as u128;
+   let low = u64::from_le_bytes([raw[8], raw[9], raw[10], raw[11], raw[12], raw[13], raw[14], raw[15]]) as u128;
+   let digits = format!("{:0>width$}", (high << 64 | low).to_string(), width = scale + 1);
+   let (integer, fraction) = digits.split_at(digits.len() - scale);
+   let sign = if negative { "-" } else { "" };
+   if scale == 0 { format!("{}{}", sign, integer) } else { format!("{}{}.{}", sign, integer, fraction) }
+}
+
+/// Function to format a DateTime: 62 bits of 100ns ticks since 0001-01-01 and 2 bits of kind (UTC 1).
+fn datetime(raw: u64) -> String {
+   let ticks = (raw & 0x3FFF_FFFF_FFFF_FFFF) as i64;
+   let start = NaiveDate::from_ymd_opt(1, 1, 1).and_then(|date| date.and_hms_opt(0, 0, 0)).unwrap_or_default();
+   let time = start + Duration::seconds(ticks / 10_000_000);
+   let fraction = format!("{:07}", ticks % 10_000_000);
+   let fraction = fraction.trim_end_matches('0');
+   format!(
+      "{}{}{}{}",
+      time.format("%Y-%m-%dT%H:%M:%S"),
+      if fraction.is_empty() { "" } else { "." },
+      fraction,
+      if raw >> 62 == 1 { "Z" } else { "" },
+   )
+}
+
+/// Function to format a TimeSpan of 100ns ticks as an xsd:duration.
+fn timespan(ticks: i64) -> String {
+   let sign = if ticks < 0 { "-" } else { "" };
+   let ticks = ticks.unsigned_abs();
+   let (days, rest) = (ticks / 864_000_000_000, ticks % 864_000_000_000);
+   let (hours, minutes, seconds) = (rest / 36_000_000_000, rest / 600_000_000 % 60, rest / 10_000_000 % 60);
+   let fraction = format!("{:07}", rest % 10_000_000);
+   let fraction = fraction.trim_end_matches('0');
+   let mut duration = format!("{}P", sign);
+   if days > 0 {
+      duration.push_str(&format!("{}D", days));
+   }
+   duration.push('T');
+   if hours > 0 {
+      duration.push_str(&format!("{}H", hours));
+   }
+   if minutes > 0 {
+      duration.push_str(&format!("{}M", minutes));
+   }
+   if fraction.is_empty() {
+      duration.push_str(&format!("{}S", seconds));
+   } else {
+      duration.push_str(&format!("{}.{}S", seconds, fraction));
+   }
+   duration
+}
+
+fn invalid<E: Into<Box<dyn std::error::Error + Send + Sync>>>(reason: E) -> Error {
+   Error::new(ErrorKind::InvalidData, reason)
+}
+
+/// Test functions
+#[test]
+pub fn test_nbfx() {
+   // Our envelopes decode to the same tree
+   let envelope = XmlElement::new("s:Envelope")
+      .attribute("xmlns:s", "http://www.w3.org/2003/05/soap-envelope")
+      .child(XmlElement::new("s:Header").child(XmlElement::new("wsa:Action").attribute("s:mustUnderstand", "1").text("urn:action")))
+      .child(XmlElement::new("s:Body").child(XmlElement::new("Filter").attribute("Dialect", "urn:dialect").text(&"x".repeat(300))));
+   let encoded = encode_envelope(&envelope);
+   assert_eq!(encoded[..3], [0, 0x70, 8]);
+   let mut dictionary = SessionDictionary::default();
+   assert_eq!(decode_envelope(&encoded, &mut dictionary).unwrap(), envelope);
+
+   // Envelope of WCF: in-band strings (id 1 and 3), static strings, prefixed dictionary records and typed texts
+   let data = [
+      0x18, 0x0B, b'E', b'n', b'u', b'm', b'e', b'r', b'a', b't', b'i', b'o', b'n', 0x0B, b'E', b'x', b'p', b'i', b'r', b'e',
+      b's', b'-', b'v', b'a', b'l',
+      0x56, 0x02, // <s:Envelope
+      0x0B, 0x01, b's', 0x04, // xmlns:s="http://www.w3.org/2003/05/soap-envelope"
+      0x56, 0x0E, // <s:Body>
+      0x42, 0x01, // <Enumeration>
+      0x0D, 0x03, 0x88, 0x2A, // b:Expires-val="42"
+      0x98, 0x02, b'o', b'k', // ok
+      0x87, // true</Enumeration>
+      0xAD, 0x33, 0x22, 0x11, 0x00, 0x55, 0x44, 0x77, 0x66, 0x88, 0x99, 0xAA, 0xBB, 0xCC, 0xDD, 0xEE, 0xFF, // unique id</s:Body>
+      0x01, // </s:Envelope>
+   ];
+   let envelope = decode_envelope(&data, &mut dictionary).unwrap();
+   assert_eq!(envelope.name, "Envelope");
+   assert_eq!(envelope.attributes, vec![("xmlns".to_string(), "s".to_string(), "http://www.w3.org/2003/05/soap-envelope".to_string())]);
+   let enumeration = envelope.find("Enumeration").unwrap();
+   assert_eq!(enumeration.attribute_value("Expires-val"), Some("42"));
+   assert_eq!(enumeration.text_content(), "oktrue");
+   assert_eq!(envelope.find("Body").unwrap().text_content(), "oktrueurn:uuid:00112233-4455-6677-8899-aabbccddeeff");
+   // The in-band strings last for the session
+   assert_eq!(decode_envelope(&[0x00, 0x42, 0x03, 0x01], &mut dictionary).unwrap().name, "Expires-val");
+   assert!(decode_envelope(&[0x00, 0x42, 0x03], &mut dictionary).is_err());
+
+   assert_eq!(decimal(&[0, 0, 2, 0x80, 0, 0, 0, 0, 0x39, 0x30, 0, 0, 0, 0, 0, 0]), "-123.45");
+   assert_eq!(datetime(1 << 62 | 638_000_000_000_000_000), "2022-09-28T22:13:20Z");
+   assert_eq!(timespan(36_000_000_000 + 15_000_000), "PT1H1.5S");
+}
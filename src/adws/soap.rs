@@ -0,0 +1,164 @@
+//! SOAP messages of the ADWS enumeration
+//!
+//! The objects are searched with WS-Enumeration and the LDAP query dialect of ADWS [MS-WSDS]: an Enumerate request
+//! with the LDAP filter, base and scope and the attributes to return, then one Pull request per page with the
+//! enumeration context until EndOfSequence. The items of a page are the objects, named after their class, with one
+//! element per attribute and one ad:value element per value, the binary values in base64.
+use chrono::DateTime;
+use ldap3::{Scope, SearchEntry};
+use std::io::{Error, Result};
+
+use crate::adws::nbfx::{guid, XmlElement};
+use crate::modules::offline::{base64_decode, canonical_attribute, new_entry, push_value, BINARY_ATTRIBUTES};
+
+const SOAP: &str = "http://www.w3.org/2003/05/soap-envelope";
+const ADDRESSING: &str = "http://www.w3.org/2005/08/addressing";
+const ANONYMOUS: &str = "http://www.w3.org/2005/08/addressing/anonymous";
+const ENUMERATION: &str = "http://schemas.xmlsoap.org/ws/2004/09/enumeration";
+const ENUMERATE_ACTION: &str = "http://schemas.xmlsoap.org/ws/2004/09/enumeration/Enumerate";
+const PULL_ACTION: &str = "http://schemas.xmlsoap.org/ws/2004/09/enumeration/Pull";
+const ACTIVE_DIRECTORY: &str = "http://schemas.microsoft.com/2008/1/ActiveDirectory";
+const ACTIVE_DIRECTORY_DATA: &str = "http://schemas.microsoft.com/2008/1/ActiveDirectory/Data";
+const LDAP_QUERY_DIALECT: &str = "http://schemas.microsoft.com/2008/1/ActiveDirectory/Dialect/LdapQuery";
+const XPATH_DIALECT: &str = "http://schemas.microsoft.com/2008/1/ActiveDirectory/Dialect/XPath-Level-1";
+/// Directory service of the requests, the domain NC of the LDAP service.
+const LDAP_INSTANCE: &str = "ldap:389";
+/// Synthetic attributes of ADWS returned with every object, not LDAP attributes.
+const SYNTHETIC_ATTRIBUTES: [&str; 3] = ["objectReferenceProperty", "container-hierarchy-parent", "relativeDistinguishedName"];
+
+/// Function to build an envelope: addressing headers of the action and body.
+fn envelope(action: &str, to: &str, body: XmlElement) -> XmlElement {
+   let message_id: [u8; 16] = rand::random();
+   XmlElement::new("s:Envelope")
+      .attribute("xmlns:s", SOAP)
+      .attribute("xmlns:a", ADDRESSING)
+      .attribute("xmlns:ad", ACTIVE_DIRECTORY)
+      .attribute("xmlns:addata", ACTIVE_DIRECTORY_DATA)
+      .attribute("xmlns:wsen", ENUMERATION)
+      .child(XmlElement::new("s:Header")
+         .child(XmlElement::new("a:Action").attribute("s:mustUnderstand", "1").text(action))
+         .child(XmlElement::new("ad:instance").text(LDAP_INSTANCE))
+         .child(XmlElement::new("a:MessageID").text(&format!("urn:uuid:{}", guid(&message_id))))
+         .child(XmlElement::new("a:ReplyTo").child(XmlElement::new("a:Address").text(ANONYMOUS)))
+         .child(XmlElement::new("a:To").attribute("s:mustUnderstand", "1").text(to)))
+      .child(XmlElement::new("s:Body").child(body))
+}
+
+/// Function to build the Enumerate request of an LDAP search.
+pub fn enumerate_request(to: &str, base: &str, scope: Scope, filter: &str, attributes: &[&str]) -> XmlElement {
+   let scope = match scope {
+      Scope::Base => "base",
+      Scope::OneLevel => "onelevel",
+      Scope::Subtree => "subtree",
+   };
+   // The DN is the key of the entries, always requested
+   let mut selection = XmlElement::new("ad:Selection").attribute("Dialect", XPATH_DIALECT);
+   for attribute in std::iter::once(&"distinguishedName").chain(attributes.iter().filter(|name| !name.eq_ignore_ascii_case("distinguishedName"))) {
+      selection = selection.child(XmlElement::new("ad:SelectionProperty").text(&format!("addata:{}", attribute)));
+   }
+   let query = XmlElement::new("adlq:LdapQuery")
+      .attribute("xmlns:adlq", LDAP_QUERY_DIALECT)
+      .child(XmlElement::new("adlq:Filter").text(filter))
+      .child(XmlElement::new("adlq:BaseObject").text(base))
+      .child(XmlElement::new("adlq:Scope").text(scope));
+   envelope(ENUMERATE_ACTION, to, XmlElement::new("wsen:Enumerate")
+      .child(XmlElement::new("wsen:Filter").attribute("Dialect", LDAP_QUERY_DIALECT).child(query))
+      .child(selection))
+}
+
+/// Function to build the Pull request of the next page of an enumeration.
+pub fn pull_request(to: &str, context: &str, max_elements: usize) -> XmlElement {
+   envelope(PULL_ACTION, to, XmlElement::new("wsen:Pull")
+      .child(XmlElement::new("wsen:EnumerationContext").text(context))
+      .child(XmlElement::new("wsen:MaxElements").text(&max_elements.to_string())))
+}
+
+/// Page of an enumeration: context of the next Pull, objects of the page, end of the results.
+#[derive(Debug)]
+pub struct EnumerationPage {
+   pub context: Option<String>,
+   pub entries: Vec<SearchEntry>,
+   pub end: bool,
+}
+
+/// Function to read the EnumerateResponse or PullResponse of an envelope, a SOAP fault fails with its reason.
+pub fn parse_response(envelope: &XmlElement) -> Result<EnumerationPage> {
+   let response = envelope.find("EnumerateResponse").or_else(|| envelope.find("PullResponse")).ok_or_else(|| {
+      let reason = envelope.find("Reason").or_else(|| envelope.find("Body")).map(XmlElement::text_content).unwrap_or_default();
+      Error::other(format!("ADWS fault: {}", reason.trim()))
+   })?;
+   let entries = response.find("Items")
+      .map(|items| items.elements().filter_map(item_entry).collect())
+      .unwrap_or_default();
+   Ok(EnumerationPage {
+      context: response.find("EnumerationContext").map(XmlElement::text_content),
+      entries,
+      end: response.find("EndOfSequence").is_some(),
+   })
+}
+
+/// Function to convert one object of the items in a search entry like the LDAP ones.
+pub fn item_entry(item: &XmlElement) -> Option<SearchEntry> {
+   let mut entry = new_entry(String::new());
+   for attribute in item.elements() {
+      if SYNTHETIC_ATTRIBUTES.contains(&attribute.name.as_str()) {
+         continue
+      }
+      let binary = BINARY_ATTRIBUTES.contains(&canonical_attribute(&attribute.name).as_str());
+      for value in attribute.elements() {
+         let text = value.text_content();
+         let value_type = value.attribute_value("type").unwrap_or_default();
+         let raw = if binary || value_type.ends_with("base64Binary") {
+            match base64_decode(&text) {
+               Some(raw) => raw,
+               None => continue,
+            }
+         } else if value_type.ends_with("dateTime") {
+            // Same generalized time as LDAP for the time attributes
+            DateTime::parse_from_rfc3339(&text).map(|time| time.format("%Y%m%d%H%M%S.0Z").to_string()).unwrap_or(text).into_bytes()
+         } else {
+            text.into_bytes()
+         };
+         if attribute.name == "distinguishedName" {
+            entry.dn = String::from_utf8_lossy(&raw).to_string();
+         }
+         push_value(&mut entry, &attribute.name, raw);
+      }
+   }
+   if entry.dn.is_empty() { None } else { Some(entry) }
+}
+
+/// Test functions
+#[test]
+pub fn test_soap() {
+   let request = enumerate_request("net.tcp://dc01:9389/ActiveDirectoryWebServices/Windows/Enumeration", "DC=essos,DC=local", Scope::Subtree, "(objectClass=*)", &["objectSid", "distinguishedName"]);
+   assert_eq!(request.find("Action").unwrap().text_content(), ENUMERATE_ACTION);
+   assert_eq!(request.find("Scope").unwrap().text_content(), "subtree");
+   let selection: Vec<String> = request.find("Selection").unwrap().elements().map(XmlElement::text_content).collect();
+   assert_eq!(selection, vec!["addata:distinguishedName", "addata:objectSid"]);
+   assert_eq!(pull_request("to", "context", 256).find("MaxElements").unwrap().text_content(), "256");
+
+   let value = |text: &str, value_type: &str| XmlElement::new("ad:value").attribute("xsi:type", value_type).text(text);
+   let item = XmlElement::new("addata:user")
+      .child(XmlElement::new("ad:objectReferenceProperty").child(value("5b7b6b0c-0000-0000-0000-000000000000", "xsd:string")))
+      .child(XmlElement::new("ad:distinguishedName").child(value("CN=khal.drogo,CN=Users,DC=essos,DC=local", "xsd:string")))
+      .child(XmlElement::new("addata:objectSid").child(value("AQEAAAAAAAUgAAAA", "xsd:base64Binary")))
+      .child(XmlElement::new("addata:memberOf").child(value("CN=A", "xsd:string")).child(value("CN=B", "xsd:string")))
+      .child(XmlElement::new("addata:whenCreated").child(value("2022-09-28T22:13:20Z", "xsd:dateTime")));
+   let response = XmlElement::new("s:Envelope").child(XmlElement::new("s:Body").child(XmlElement::new("wsen:PullResponse")
+      .child(XmlElement::new("wsen:EnumerationContext").text("next"))
+      .child(XmlElement::new("wsen:Items").child(item))
+      .child(XmlElement::new("wsen:EndOfSequence"))));
+   let page = parse_response(&response).unwrap();
+   assert_eq!((page.context.as_deref(), page.end, page.entries.len()), (Some("next"), true, 1));
+   let entry = &page.entries[0];
+   assert_eq!(entry.dn, "CN=khal.drogo,CN=Users,DC=essos,DC=local");
+   assert_eq!(entry.bin_attrs["objectSid"], vec![vec![1, 1, 0, 0, 0, 0, 0, 5, 32, 0, 0, 0]]);
+   assert_eq!(entry.attrs["memberOf"], vec!["CN=A", "CN=B"]);
+   assert_eq!(entry.attrs["whenCreated"], vec!["20220928221320.0Z"]);
+   assert!(!entry.attrs.contains_key("objectReferenceProperty"));
+
+   let fault = XmlElement::new("s:Envelope").child(XmlElement::new("s:Body").child(XmlElement::new("s:Fault")
+      .child(XmlElement::new("s:Reason").child(XmlElement::new("s:Text").text("The supplied filter is invalid")))));
+   assert!(parse_response(&fault).unwrap_err().to_string().contains("The supplied filter is invalid"));
+}
@@ -1,25 +1,120 @@
 //! Parsing arguments
 use clap::{App, Arg};
+use crate::ldap::{PAGE_SIZE, STEALTH_PAGE_SIZE};
+use crate::modules::sampling::parse_sample;
+use crate::secret::{SecretString, PASSWORD_ENV, ENCRYPT_PASSWORD_ENV, read_cred_file, read_password_file, prompt_password, prompt_secret};
+use log::{error, info, warn};
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Options {
     pub username: String,
-    pub password: String,
+    /// None without -p, until resolve_credentials finds one
+    pub password: Option<SecretString>,
+    pub cred_file: String,
     pub domain: String,
     pub ldapfqdn: String,
     pub ip: String,
+    pub dc: String,
+    pub site: String,
     pub port: String,
     pub ldaps: bool,
+    /// "ldap" or "adws" (Active Directory Web Services, TCP 9389)
+    pub protocol: String,
+    pub sspi: bool,
+    pub socks: String,
     pub path: String,
     pub name_server: String,
     pub dns_tcp: bool,
     pub fqdn_resolver: bool,
+    pub stale_days: u32,
+    pub exclude_stale: bool,
+    pub builtin_edges: bool,
+    pub edge_rules: String,
+    pub extended_rights: bool,
+    pub schema_guids: bool,
+    pub sample: u8,
+    pub edge_justification: bool,
+    pub edge_provenance: bool,
+    pub repl_metadata: bool,
+    pub risk_scores: bool,
+    pub adminsdholder: bool,
+    pub memberships: bool,
+    pub reports: bool,
+    pub html_report: bool,
+    pub attack_surface: bool,
+    pub honeypots: bool,
+    pub password_scan: bool,
+    pub sccm: bool,
+    pub hybrid: bool,
+    pub exchange: bool,
+    pub mssql: bool,
+    pub machine_quota: bool,
+    pub sysvol: String,
+    pub relay_scan: String,
+    pub relay_ldap_bind: bool,
+    pub webclient: bool,
+    pub webclient_threads: usize,
+    pub coercion: bool,
+    pub path_query: String,
+    pub query: String,
+    pub profile: String,
+    pub stealth: bool,
+    pub acl_only: bool,
+    pub include_deleted: bool,
+    pub follow_referrals: bool,
+    pub low_memory: bool,
+    pub chunk_size: usize,
+    pub parse_threads: usize,
+    pub retries: u8,
+    pub page_size: i32,
+    pub ldap_filter: String,
+    pub search_base: String,
+    pub control_socket: String,
+    pub targets: String,
+    pub cache_dir: String,
+    pub from_cache: bool,
+    pub input_format: String,
+    pub input_path: String,
     pub zip: bool,
+    pub pretty: bool,
+    pub stdout_ndjson: bool,
+    pub output_format: String,
+    pub bh_version: String,
+    pub object_props: String,
+    pub diff: String,
+    pub acl_baseline: String,
+    pub log_json: bool,
+    pub metrics: bool,
+    pub metrics_port: String,
+    /// "not set", "password" or the recipient.pub file of --encrypt-output
+    pub encrypt_output: String,
+    pub encrypt_password_file: String,
+    /// Password of --encrypt-output and --decrypt, never read from the command line
+    pub encrypt_password: Option<SecretString>,
+    pub decrypt: String,
+    pub upload: String,
+    pub upload_auth: Option<SecretString>,
+    pub upload_fallback_dir: String,
+    pub entra_token: Option<SecretString>,
     pub verbose: log::LevelFilter,
+    /// Zip archive returned by run_collection instead of written, set by the library callers
+    pub in_memory: bool,
+    /// No password prompt and no change of the environment variables, set by the C entry point
+    pub non_interactive: bool,
 }
 
+/// Function to get the options of the command line, exit on an invalid command line, --help and --version.
 pub fn extract_args() -> Options {
-    let matches = App::new("RustHound")
+    extract_args_from(std::env::args_os()).unwrap_or_else(|err| err.exit())
+}
+
+/// Function to get the options of an argument list, the first argument is the program name.
+pub fn extract_args_from<I, T>(args: I) -> Result<Options, clap::Error>
+where
+    I: IntoIterator<Item = T>,
+    T: Into<std::ffi::OsString> + Clone,
+{
+    let app = App::new("RustHound")
         .version("1.0.6")
         .author("g0h4n https://twitter.com/g0h4n_0")
         .about("Active Directory data collector for BloodHound.")
@@ -36,7 +131,14 @@ pub fn extract_args() -> Options {
                 .short("p")
                 .long("ldappassword")
                 .takes_value(true)
-                .help("Ldap password to use")
+                .help("Ldap password to use, else RUSTHOUND_PASSWORD, --cred-file or a prompt")
+                .required(false),
+        )
+        .arg(
+            Arg::with_name("cred-file")
+                .long("cred-file")
+                .takes_value(true)
+                .help("netrc-like file with the credentials: machine <domain|dc> login <user> password <password>")
                 .required(false),
         )
         .arg(
@@ -45,7 +147,7 @@ pub fn extract_args() -> Options {
                 .long("domain")
                 .takes_value(true)
                 .help("Domain name like: G0H4N.LAB")
-                .required(true),
+                .required_unless("decrypt"),
         )
         .arg(
             Arg::with_name("ldapfqdn")
@@ -63,6 +165,20 @@ pub fn extract_args() -> Options {
                 .help("Domain Controller IP address")
                 .required(false),
         )
+        .arg(
+            Arg::with_name("dc")
+                .long("dc")
+                .takes_value(true)
+                .help("Domain Controller to use (FQDN or IP address), without -i and -f the DC is found in the DNS SRV records")
+                .required(false),
+        )
+        .arg(
+            Arg::with_name("site")
+                .long("site")
+                .takes_value(true)
+                .help("AD site to prefer the DCs of _ldap._tcp.<site>._sites.dc._msdcs.<domain> in the DC discovery")
+                .required(false),
+        )
         .arg(
             Arg::with_name("ldapport")
                 .short("P")
@@ -78,6 +194,28 @@ pub fn extract_args() -> Options {
                 .help("Prepare ldaps request. Like ldaps://G0H4N.LAB/")
                 .required(false),
         )
+        .arg(
+            Arg::with_name("protocol")
+                .long("protocol")
+                .takes_value(true)
+                .possible_values(&["ldap", "adws"])
+                .help("Protocol of the collection, default is ldap. adws: SOAP queries to the Active Directory Web Services (TCP 9389) with NTLM, when 389/636 are filtered or watched")
+                .required(false),
+        )
+        .arg(
+            Arg::with_name("sspi")
+                .long("sspi")
+                .takes_value(false)
+                .help("Windows only: bind with the credentials of the current logon session (SSPI Negotiate), no username or password")
+                .required(false),
+        )
+        .arg(
+            Arg::with_name("socks")
+                .long("socks")
+                .takes_value(true)
+                .help("SOCKS5 proxy of a relay tool like ntlmrelayx (127.0.0.1:1080) to collect with a relayed LDAP session, -u is the relayed user")
+                .required(false),
+        )
         .arg(
             Arg::with_name("path")
                 .short("o")
@@ -108,6 +246,349 @@ pub fn extract_args() -> Options {
                 .help("[MODULE] Use fqdn-resolver module to get computers IP address")
                 .required(false),
         )
+        .arg(
+            Arg::with_name("no-builtin-edges")
+                .long("no-builtin-edges")
+                .takes_value(false)
+                .help("Don't add synthetic edges for Account/Server/Backup/Print Operators and DnsAdmins")
+                .required(false),
+        )
+        .arg(
+            Arg::with_name("edge-rules")
+                .long("edge-rules")
+                .takes_value(true)
+                .help("YAML ruleset file with your own synthetic edges, see resources/synthetic_edges.yaml")
+                .required(false),
+        )
+        .arg(
+            Arg::with_name("extended-rights")
+                .long("extended-rights")
+                .takes_value(false)
+                .help("Collect CN=Extended-Rights to name custom extended rights granted in ACEs")
+                .required(false),
+        )
+        .arg(
+            Arg::with_name("no-schema-guids")
+                .long("no-schema-guids")
+                .takes_value(false)
+                .help("Don't collect the schema GUIDs from CN=Schema, use the builtin table to resolve ACE object types")
+                .required(false),
+        )
+        .arg(
+            Arg::with_name("edge-justification")
+                .long("edge-justification")
+                .takes_value(false)
+                .help("Add a readable Justification to ACL edges: ACE index, object, right, principal and inheritance source")
+                .required(false),
+        )
+        .arg(
+            Arg::with_name("edge-provenance")
+                .long("edge-provenance")
+                .takes_value(false)
+                .help("Write the ACE (type, mask, inheritance, object GUID) or the attribute producing every edge in <domain>_edge_provenance.json")
+                .required(false),
+        )
+        .arg(
+            Arg::with_name("repl-metadata")
+                .long("repl-metadata")
+                .takes_value(false)
+                .help("Collect the replication metadata: time and DC of the last change of the descriptor, members and SPNs")
+                .required(false),
+        )
+        .arg(
+            Arg::with_name("risk-scores")
+                .long("risk-scores")
+                .takes_value(false)
+                .help("[MODULE] Write a ranked CSV of per-object risk scores (weighted inbound control, hops to Tier 0)")
+                .required(false),
+        )
+        .arg(
+            Arg::with_name("adminsdholder")
+                .long("adminsdholder")
+                .takes_value(false)
+                .help("[MODULE] Write the AdminSDHolder ACL in JSON and a CSV of the protected objects (adminCount=1) and the principals added to it")
+                .required(false),
+        )
+        .arg(
+            Arg::with_name("memberships")
+                .long("memberships")
+                .takes_value(false)
+                .help("[MODULE] Write a CSV of the effective group memberships, nested groups and primary groups flattened")
+                .required(false),
+        )
+        .arg(
+            Arg::with_name("reports")
+                .long("reports")
+                .takes_value(false)
+                .help("[MODULE] Print and write in JSON the Kerberoastable, AS-REP roastable, reversible encryption and DES-only accounts, the krbtgt and machine password ages")
+                .required(false),
+        )
+        .arg(
+            Arg::with_name("html-report")
+                .long("html-report")
+                .takes_value(false)
+                .help("[MODULE] Write a standalone HTML summary: object counts, top findings and data-quality warnings")
+                .required(false),
+        )
+        .arg(
+            Arg::with_name("attack-surface")
+                .long("attack-surface")
+                .takes_value(false)
+                .help("[MODULE] Write a Markdown summary of the dangerous edges and a Graphviz graph of the principals reaching Tier 0")
+                .required(false),
+        )
+        .arg(
+            Arg::with_name("honeypots")
+                .long("honeypots")
+                .takes_value(false)
+                .help("[MODULE] Write in JSON the likely honey accounts and canary objects to stay away from")
+                .required(false),
+        )
+        .arg(
+            Arg::with_name("password-scan")
+                .long("password-scan")
+                .takes_value(false)
+                .help("[MODULE] Write in JSON the passwords found in the description, info and userPassword attributes")
+                .required(false),
+        )
+        .arg(
+            Arg::with_name("sccm")
+                .long("sccm")
+                .takes_value(false)
+                .help("[MODULE] Write in JSON the SCCM sites, management points and site servers, sccmroles set on the computers")
+                .required(false),
+        )
+        .arg(
+            Arg::with_name("hybrid")
+                .long("hybrid")
+                .takes_value(false)
+                .help("[MODULE] Write in JSON the Azure AD Connect accounts and servers, Seamless SSO and tenant, hybridrole set on the objects")
+                .required(false),
+        )
+        .arg(
+            Arg::with_name("exchange")
+                .long("exchange")
+                .takes_value(false)
+                .help("[MODULE] Write in JSON the Exchange servers, roles and RBAC groups, flag the PrivExchange domains")
+                .required(false),
+        )
+        .arg(
+            Arg::with_name("mssql")
+                .long("mssql")
+                .takes_value(false)
+                .help("[MODULE] Write in JSON the MSSQL instances of the MSSQLSvc SPNs and the SQLAdmin edge hints")
+                .required(false),
+        )
+        .arg(
+            Arg::with_name("machine-quota")
+                .long("machine-quota")
+                .takes_value(false)
+                .help("[MODULE] Write in JSON the MachineAccountQuota, the computer creators of each OU and the pre-Windows 2000 computers")
+                .required(false),
+        )
+        .arg(
+            Arg::with_name("sysvol")
+                .long("sysvol")
+                .takes_value(true)
+                .value_name("sysvol_dir")
+                .help("[MODULE] Decrypt the Group Policy Preferences passwords (cpassword) of this local copy of SYSVOL in <domain>_gpp.json, AdminTo, CanRDP, CanPSRemote and ExecuteDCOM from the GPO local groups")
+                .required(false),
+        )
+        .arg(
+            Arg::with_name("relay-scan")
+                .long("relay-scan")
+                .takes_value(true)
+                .possible_values(&["dc", "servers"])
+                .help("[MODULE] Connect to the DCs (and servers) to check SMB signing, LDAP signing and LDAPS, relay targets in <domain>_relay.json")
+                .required(false),
+        )
+        .arg(
+            Arg::with_name("relay-ldap-bind")
+                .long("relay-ldap-bind")
+                .takes_value(false)
+                .requires("relay-scan")
+                .help("Check LDAP signing in --relay-scan with a simple bind of -u/-p on TCP 389: the password is sent in cleartext to every DC")
+                .required(false),
+        )
+        .arg(
+            Arg::with_name("webclient")
+                .long("webclient")
+                .takes_value(false)
+                .help("[MODULE] Probe the WebClient service (DAV RPC SERVICE pipe) of the computers over SMB with -u/-p, webclientrunning set on the computers")
+                .required(false),
+        )
+        .arg(
+            Arg::with_name("webclient-threads")
+                .long("webclient-threads")
+                .takes_value(true)
+                .validator(|v| v.parse::<usize>().map(|_| ()).map_err(|_| "webclient-threads must be a number of threads".to_string()))
+                .help("Computers probed at the same time by --webclient, default is 16")
+                .required(false),
+        )
+        .arg(
+            Arg::with_name("coercion")
+                .long("coercion")
+                .takes_value(false)
+                .help("[MODULE] Probe the MS-RPRN, MS-EFSR and MS-FSRVP pipes of the computers over SMB with -u/-p, spoolerrunning, efsrpcexposed and fsrvpexposed set on the computers")
+                .required(false),
+        )
+        .arg(
+            Arg::with_name("path-query")
+                .long("path")
+                .value_name("query")
+                .takes_value(true)
+                .help("[MODULE] Shortest path between two objects without Neo4j, like \"from:jon.snow to:Domain Admins\", written in <domain>_path.json")
+                .required(false),
+        )
+        .arg(
+            Arg::with_name("query")
+                .long("query")
+                .takes_value(true)
+                .possible_values(&["kerberoastable_high_value", "asreproastable_high_value", "dcsync_principals", "unconstrained_delegation", "tier0_members"])
+                .help("[MODULE] Print the result of a canned query on the collected objects as a table, without Neo4j")
+                .required(false),
+        )
+        .arg(
+            Arg::with_name("ldap-filter")
+                .long("ldap-filter")
+                .takes_value(true)
+                .help("LDAP filter AND-ed with the collection filter. Example: (admincount=1)")
+                .required(false),
+        )
+        .arg(
+            Arg::with_name("search-base")
+                .long("search-base")
+                .takes_value(true)
+                .help("Base DN of the collection instead of the domain. Example: OU=SERVERS,DC=ESSOS,DC=LOCAL")
+                .required(false),
+        )
+        .arg(
+            Arg::with_name("targets")
+                .long("targets")
+                .takes_value(true)
+                .help("File with sAMAccountNames, DNs or SIDs, collect only these objects and their direct relationships")
+                .required(false),
+        )
+        .arg(
+            Arg::with_name("cache-dir")
+                .long("cache-dir")
+                .takes_value(true)
+                .help("Write the raw LDAP entries in this directory to parse them again with --from-cache")
+                .required(false),
+        )
+        .arg(
+            Arg::with_name("from-cache")
+                .long("from-cache")
+                .takes_value(false)
+                .help("Parse the raw LDAP entries of the cache (--cache-dir, default .rusthound_cache) without requesting the DC")
+                .required(false),
+        )
+        .arg(
+            Arg::with_name("input")
+                .long("input")
+                .takes_value(true)
+                .number_of_values(2)
+                .value_names(&["format", "path"])
+                .help("Parse an export without requesting the DC: ldif <file> or ldapdomaindump <dir>")
+                .required(false),
+        )
+        .arg(
+            Arg::with_name("control-socket")
+                .long("control-socket")
+                .takes_value(true)
+                .help("Unix socket to query the progress (status) or stop and flush the collection (stop)")
+                .required(false),
+        )
+        .arg(
+            Arg::with_name("profile")
+                .long("profile")
+                .takes_value(true)
+                .possible_values(&["quick", "full", "stealth"])
+                .help("Collection profile, the flags given are kept. quick: no CN=Schema search, no fqdn-resolver, 1 reconnection, pages of 999 objects. full: fqdn-resolver, extended rights, extended properties, roastable, AdminSDHolder and machine quota reports, pages of 500 objects. stealth: --stealth without reconnection, pages of 100 objects")
+                .required(false),
+        )
+        .arg(
+            Arg::with_name("stealth")
+                .long("stealth")
+                .takes_value(false)
+                .help("Small LDAP pages with random delays, DC-only: disable fqdn-resolver, extended rights, CN=Schema searches, --webclient and --coercion")
+                .required(false),
+        )
+        .arg(
+            Arg::with_name("acl-only")
+                .long("acl-only")
+                .takes_value(false)
+                .help("Fast collection of the ACL edges: only the identity attributes and the DACL security descriptors")
+                .required(false),
+        )
+        .arg(
+            Arg::with_name("include-deleted")
+                .long("include-deleted")
+                .takes_value(false)
+                .help("Collect the tombstoned and recycled objects too (Show Deleted/Show Recycled controls), marked isdeleted")
+                .required(false),
+        )
+        .arg(
+            Arg::with_name("follow-referrals")
+                .long("follow-referrals")
+                .takes_value(false)
+                .help("Collect the child domains referred by the searches from the forest root as their own domains, same credentials and LDAP/LDAPS")
+                .required(false),
+        )
+        .arg(
+            Arg::with_name("low-memory")
+                .long("low-memory")
+                .takes_value(false)
+                .help("Spill the users and computers on disk as they are parsed and write the JSON files object by object, kept in memory when a module or a non JSON output reads them all")
+                .required(false),
+        )
+        .arg(
+            Arg::with_name("parse-threads")
+                .long("parse-threads")
+                .takes_value(true)
+                .validator(|v| v.parse::<usize>().map(|_| ()).map_err(|_| "parse-threads must be a number of threads".to_string()))
+                .help("Threads parsing the LDAP objects and their security descriptors during the collection, default is 0 for all the CPUs")
+                .required(false),
+        )
+        .arg(
+            Arg::with_name("retries")
+                .long("retries")
+                .takes_value(true)
+                .validator(|v| v.parse::<u8>().map(|_| ()).map_err(|_| "retries must be a number between 0 and 255".to_string()))
+                .help("Reconnections when the DC drops the LDAP connection, the paged search is resumed or sent to another DC, default is 3")
+                .required(false),
+        )
+        .arg(
+            Arg::with_name("page-size")
+                .long("page-size")
+                .takes_value(true)
+                .validator(|v| v.parse::<i32>().ok().filter(|size| (1..=1000).contains(size)).map(|_| ()).ok_or_else(|| "page-size must be a number between 1 and 1000".to_string()))
+                .help("Objects per page of the LDAP paged searches, default is 999 (100 with --stealth) or the one of the --profile")
+                .required(false),
+        )
+        .arg(
+            Arg::with_name("stale-days")
+                .long("stale-days")
+                .takes_value(true)
+                .validator(|v| v.parse::<u32>().map(|_| ()).map_err(|_| "stale-days must be a number of days".to_string()))
+                .help("Computers without logon and password change for N days are probably_stale, default is 90")
+                .required(false),
+        )
+        .arg(
+            Arg::with_name("exclude-stale")
+                .long("exclude-stale")
+                .takes_value(false)
+                .help("Skip the probably_stale computers in the remote collection modules (fqdn-resolver)")
+                .required(false),
+        )
+        .arg(
+            Arg::with_name("sample")
+                .long("sample")
+                .takes_value(true)
+                .validator(|v| parse_sample(&v).map(|_| ()).ok_or("sample must be a percentage between 1% and 100%".to_string()))
+                .help("Collect only N% of regular users and computers, privileged objects are always collected. Example: 10%")
+                .required(false),
+        )
         .arg(
             Arg::with_name("zip")
                 .long("zip")
@@ -116,26 +597,255 @@ pub fn extract_args() -> Options {
                 .help("RustHound will compress the JSON files into a zip archive")
                 .required(false),
         )
+        .arg(
+            Arg::with_name("output-format")
+                .long("output-format")
+                .takes_value(true)
+                .possible_values(&["json", "csv", "graph"])
+                .help("csv also writes users, computers, groups and edges CSV files, graph a generic nodes and edges JSON file, alongside the JSON files, default is json")
+                .required(false),
+        )
+        .arg(
+            Arg::with_name("bh-version")
+                .long("bh-version")
+                .takes_value(true)
+                .possible_values(&["4.2", "4.3", "ce"])
+                .help("BloodHound release of the JSON files: meta version, property names and the edges it knows, default is 4.2+ with every edge")
+                .required(false),
+        )
+        .arg(
+            Arg::with_name("object-props")
+                .long("object-props")
+                .takes_value(true)
+                .possible_values(&["default", "extended"])
+                .help("extended also harvests department, manager, telephoneNumber, profilePath, info and scriptPath in the user, group and computer properties, whenChanged and the timestamps in ISO 8601, default is the BloodHound properties only")
+                .required(false),
+        )
+        .arg(
+            Arg::with_name("chunk-size")
+                .long("chunk-size")
+                .takes_value(true)
+                .validator(|v| v.parse::<usize>().map(|_| ()).map_err(|_| "chunk-size must be a number of objects".to_string()))
+                .help("Split the users, groups and computers JSON files in numbered files of this number of objects, default is 0 for one file")
+                .required(false),
+        )
+        .arg(
+            Arg::with_name("diff")
+                .long("diff")
+                .takes_value(true)
+                .value_name("old_dir")
+                .help("Compare the objects with the JSON files of a previous run in this directory, added, removed and changed objects and edges in <domain>_diff.json")
+                .required(false),
+        )
+        .arg(
+            Arg::with_name("acl-baseline")
+                .long("acl-baseline")
+                .takes_value(true)
+                .value_name("baseline_dir")
+                .help("Compare the owners and explicit ACEs with the <domain>_descriptors.json of a previous run in this directory, ACEs added per object in <domain>_acl_baseline.json")
+                .required(false),
+        )
+        .arg(
+            Arg::with_name("pretty")
+                .long("pretty")
+                .takes_value(false)
+                .help("Write indented JSON files, easier to read and diff")
+                .required(false),
+        )
+        .arg(
+            Arg::with_name("stdout-ndjson")
+                .long("stdout-ndjson")
+                .takes_value(false)
+                .help("Write every object and edge as one JSON line on stdout at the end of the collection instead of the JSON files, logs and report tables stay on stderr")
+                .required(false),
+        )
+        .arg(
+            Arg::with_name("log-json")
+                .long("log-json")
+                .takes_value(false)
+                .help("Write the logs, the progress and the run summary as JSON lines on stderr")
+                .required(false),
+        )
+        .arg(
+            Arg::with_name("metrics")
+                .long("metrics")
+                .takes_value(false)
+                .help("Write the run metrics (objects, edges, phase durations, LDAP retries) in a Prometheus text file")
+                .required(false),
+        )
+        .arg(
+            Arg::with_name("metrics-port")
+                .long("metrics-port")
+                .takes_value(true)
+                .validator(|v| v.parse::<u16>().map(|_| ()).map_err(|_| "metrics-port must be a port number".to_string()))
+                .help("Serve the run metrics on http://127.0.0.1:<port>/metrics during the collection")
+                .required(false),
+        )
+        .arg(
+            Arg::with_name("encrypt-output")
+                .long("encrypt-output")
+                .value_name("recipient.pub")
+                .takes_value(true)
+                .min_values(0)
+                .max_values(1)
+                .help("Encrypt the output files at rest, <file>.age for the age recipients of recipient.pub, else <file>.enc with a password (AES-256-GCM) from RUSTHOUND_ENCRYPT_PASSWORD, --encrypt-password-file or a prompt")
+                .required(false),
+        )
+        .arg(
+            Arg::with_name("encrypt-password-file")
+                .long("encrypt-password-file")
+                .takes_value(true)
+                .help("File with the password of --encrypt-output and --decrypt on its first line")
+                .required(false),
+        )
+        .arg(
+            Arg::with_name("decrypt")
+                .long("decrypt")
+                .takes_value(true)
+                .help("Decrypt a <file>.enc of --encrypt-output with its password and exit, a <file>.age is decrypted with age -d")
+                .required(false),
+        )
         .arg(
             Arg::with_name("v")
                 .short("v")
                 .multiple(true)
                 .help("Sets the level of verbosity"),
+        );
+    // Options of the optional features, unknown to the builds without them
+    #[cfg(feature = "upload")]
+    let app = app
+        .arg(
+            Arg::with_name("upload")
+                .long("upload")
+                .takes_value(true)
+                .help("Send the zipped JSON files in chunks to this HTTPS endpoint (tus resumable upload) instead of writing them on disk")
+                .required(false),
         )
-        .get_matches();
+        .arg(
+            Arg::with_name("upload-auth")
+                .long("upload-auth")
+                .takes_value(true)
+                .env("RUSTHOUND_UPLOAD_AUTH")
+                .hide_env_values(true)
+                .help("Authorization header of the --upload requests. Example: 'Bearer <token>'")
+                .required(false),
+        )
+        .arg(
+            Arg::with_name("upload-fallback-dir")
+                .long("upload-fallback-dir")
+                .takes_value(true)
+                .requires("upload")
+                .help("Write the zip archive (encrypted with --encrypt-output) in this directory when the --upload fails, instead of failing the run")
+                .required(false),
+        );
+    #[cfg(feature = "entra")]
+    let app = app
+        .arg(
+            Arg::with_name("entra-token")
+                .long("entra-token")
+                .takes_value(true)
+                .env("RUSTHOUND_ENTRA_TOKEN")
+                .hide_env_values(true)
+                .help("[MODULE] Microsoft Graph access token, collect the Entra ID users, groups and roles in <domain>_entra.json (AzureHound format)")
+                .required(false),
+        );
+    let matches = app.get_matches_from_safe(args)?;
 
     let username = matches.value_of("ldapusername").unwrap_or("not set");
-    let password = matches.value_of("ldappassword").unwrap_or("not set");
+    let password = matches.value_of("ldappassword").map(SecretString::new);
+    let cred_file = matches.value_of("cred-file").unwrap_or("not set");
     let domain = matches.value_of("domain").unwrap_or("not set");
     let ldapfqdn = matches.value_of("ldapfqdn").unwrap_or("not set");
     let ip = matches.value_of("ldapip").unwrap_or("not set");
+    let dc = matches.value_of("dc").unwrap_or("not set");
+    let site = matches.value_of("site").unwrap_or("not set");
     let port = matches.value_of("ldapport").unwrap_or("not set");
     let ldaps = matches.is_present("ldaps");
+    let protocol = matches.value_of("protocol").unwrap_or("ldap");
+    let sspi = matches.is_present("sspi");
+    let socks = matches.value_of("socks").unwrap_or("not set");
     let path = matches.value_of("path").unwrap_or("./");
     let ns = matches.value_of("name-server").unwrap_or("127.0.0.1");
     let tcp = matches.is_present("dns-tcp");
-    let fqdn_resolver = matches.is_present("fqdn-resolver");
+    // Collection profile: defaults of the flags not given
+    let profile = matches.value_of("profile").unwrap_or("not set");
+    let full = profile == "full";
+    let stealth = matches.is_present("stealth") || profile == "stealth";
+    let acl_only = matches.is_present("acl-only");
+    let include_deleted = matches.is_present("include-deleted");
+    let follow_referrals = matches.is_present("follow-referrals");
+    let low_memory = matches.is_present("low-memory");
+    let chunk_size = matches.value_of("chunk-size").and_then(|v| v.parse::<usize>().ok()).unwrap_or(0);
+    let parse_threads = matches.value_of("parse-threads").and_then(|v| v.parse::<usize>().ok()).unwrap_or(0);
+    let ldap_filter = matches.value_of("ldap-filter").unwrap_or("not set");
+    let search_base = matches.value_of("search-base").unwrap_or("not set");
+    let control_socket = matches.value_of("control-socket").unwrap_or("not set");
+    let targets = matches.value_of("targets").unwrap_or("not set");
+    let cache_dir = matches.value_of("cache-dir").unwrap_or("not set");
+    let from_cache = matches.is_present("from-cache");
+    let input: Vec<&str> = matches.values_of("input").map(|values| values.collect()).unwrap_or_default();
+    let input_format = input.get(0).copied().unwrap_or("not set");
+    let input_path = input.get(1).copied().unwrap_or("not set");
+    let fqdn_resolver = (matches.is_present("fqdn-resolver") || full) && !stealth;
+    let retries = matches.value_of("retries").and_then(|v| v.parse::<u8>().ok()).unwrap_or(match profile {
+        "quick" => 1,
+        "stealth" => 0,
+        _ => 3,
+    });
+    let page_size = matches.value_of("page-size").and_then(|v| v.parse::<i32>().ok()).unwrap_or(match profile {
+        _ if stealth => STEALTH_PAGE_SIZE,
+        "full" => 500,
+        _ => PAGE_SIZE,
+    });
+    let stale_days = matches.value_of("stale-days").and_then(|v| v.parse::<u32>().ok()).unwrap_or(90);
+    let exclude_stale = matches.is_present("exclude-stale");
+    let builtin_edges = !matches.is_present("no-builtin-edges");
+    let edge_rules = matches.value_of("edge-rules").unwrap_or("not set");
+    let extended_rights = matches.is_present("extended-rights") || full;
+    let schema_guids = !matches.is_present("no-schema-guids") && profile != "quick";
+    let sample = matches.value_of("sample").and_then(parse_sample).unwrap_or(100);
+    let edge_justification = matches.is_present("edge-justification");
+    let edge_provenance = matches.is_present("edge-provenance");
+    let repl_metadata = matches.is_present("repl-metadata");
+    let risk_scores = matches.is_present("risk-scores");
+    let memberships = matches.is_present("memberships");
+    let adminsdholder = matches.is_present("adminsdholder") || full;
+    let reports = matches.is_present("reports") || full;
+    let html_report = matches.is_present("html-report");
+    let attack_surface = matches.is_present("attack-surface");
+    let honeypots = matches.is_present("honeypots");
+    let password_scan = matches.is_present("password-scan");
+    let sccm = matches.is_present("sccm");
+    let hybrid = matches.is_present("hybrid");
+    let exchange = matches.is_present("exchange");
+    let mssql = matches.is_present("mssql");
+    let machine_quota = matches.is_present("machine-quota") || full;
+    let sysvol = matches.value_of("sysvol").unwrap_or("not set");
+    let relay_scan = matches.value_of("relay-scan").unwrap_or("not set");
+    let relay_ldap_bind = matches.is_present("relay-ldap-bind");
+    let webclient = matches.is_present("webclient");
+    let webclient_threads = matches.value_of("webclient-threads").and_then(|v| v.parse::<usize>().ok()).unwrap_or(crate::modules::webclient::WEBCLIENT_THREADS);
+    let coercion = matches.is_present("coercion");
+    let path_query = matches.value_of("path-query").unwrap_or("not set");
+    let query = matches.value_of("query").unwrap_or("not set");
     let zip = matches.is_present("zip");
+    let pretty = matches.is_present("pretty");
+    let stdout_ndjson = matches.is_present("stdout-ndjson");
+    let output_format = matches.value_of("output-format").unwrap_or("json");
+    let bh_version = matches.value_of("bh-version").unwrap_or("not set");
+    let object_props = matches.value_of("object-props").unwrap_or(if full { "extended" } else { "default" });
+    let diff = matches.value_of("diff").unwrap_or("not set");
+    let acl_baseline = matches.value_of("acl-baseline").unwrap_or("not set");
+    let log_json = matches.is_present("log-json");
+    let metrics = matches.is_present("metrics");
+    let metrics_port = matches.value_of("metrics-port").unwrap_or("not set");
+    let encrypt_output = if matches.is_present("encrypt-output") { matches.value_of("encrypt-output").unwrap_or("password") } else { "not set" };
+    let encrypt_password_file = matches.value_of("encrypt-password-file").unwrap_or("not set");
+    let decrypt = matches.value_of("decrypt").unwrap_or("not set");
+    let upload = matches.value_of("upload").unwrap_or("not set");
+    let upload_auth = matches.value_of("upload-auth").map(SecretString::new);
+    let upload_fallback_dir = matches.value_of("upload-fallback-dir").unwrap_or("not set");
+    let entra_token = matches.value_of("entra-token").map(SecretString::new);
 
     // Set log level
     let v = match matches.occurrences_of("v") {
@@ -144,19 +854,189 @@ pub fn extract_args() -> Options {
         _ => log::LevelFilter::Trace,
     };
 
-    Options {
+    Ok(Options {
         username: username.to_string(),
-        password: password.to_string(),
+        password: password,
+        cred_file: cred_file.to_string(),
         domain: domain.to_string(),
         ldapfqdn: ldapfqdn.to_string(),
         ip: ip.to_string(),
+        dc: dc.to_string(),
+        site: site.to_string(),
         port: port.to_string(),
         ldaps: ldaps,
+        protocol: protocol.to_string(),
+        sspi: sspi,
+        socks: socks.to_string(),
         path: path.to_string(),
         name_server: ns.to_string(),
         dns_tcp: tcp,
         fqdn_resolver: fqdn_resolver,
+        stale_days: stale_days,
+        exclude_stale: exclude_stale,
+        builtin_edges: builtin_edges,
+        edge_rules: edge_rules.to_string(),
+        extended_rights: extended_rights,
+        schema_guids: schema_guids,
+        sample: sample,
+        edge_justification: edge_justification,
+        edge_provenance: edge_provenance,
+        repl_metadata: repl_metadata,
+        risk_scores: risk_scores,
+        memberships: memberships,
+        adminsdholder: adminsdholder,
+        reports: reports,
+        html_report: html_report,
+        attack_surface: attack_surface,
+        honeypots: honeypots,
+        password_scan: password_scan,
+        sccm: sccm,
+        hybrid: hybrid,
+        exchange: exchange,
+        mssql: mssql,
+        machine_quota: machine_quota,
+        sysvol: sysvol.to_string(),
+        relay_scan: relay_scan.to_string(),
+        relay_ldap_bind: relay_ldap_bind,
+        webclient: webclient,
+        webclient_threads: webclient_threads,
+        coercion: coercion,
+        path_query: path_query.to_string(),
+        query: query.to_string(),
+        profile: profile.to_string(),
+        stealth: stealth,
+        acl_only: acl_only,
+        include_deleted: include_deleted,
+        follow_referrals: follow_referrals,
+        low_memory: low_memory,
+        chunk_size: chunk_size,
+        parse_threads: parse_threads,
+        retries: retries,
+        page_size: page_size,
+        ldap_filter: ldap_filter.to_string(),
+        search_base: search_base.to_string(),
+        control_socket: control_socket.to_string(),
+        targets: targets.to_string(),
+        cache_dir: cache_dir.to_string(),
+        from_cache: from_cache,
+        input_format: input_format.to_string(),
+        input_path: input_path.to_string(),
         zip: zip,
+        pretty: pretty,
+        stdout_ndjson: stdout_ndjson,
+        output_format: output_format.to_string(),
+        bh_version: bh_version.to_string(),
+        object_props: object_props.to_string(),
+        diff: diff.to_string(),
+        acl_baseline: acl_baseline.to_string(),
+        log_json: log_json,
+        metrics: metrics,
+        metrics_port: metrics_port.to_string(),
+        encrypt_output: encrypt_output.to_string(),
+        encrypt_password_file: encrypt_password_file.to_string(),
+        encrypt_password: None,
+        decrypt: decrypt.to_string(),
+        upload: upload.to_string(),
+        upload_auth: upload_auth,
+        upload_fallback_dir: upload_fallback_dir.to_string(),
+        entra_token: entra_token,
         verbose: v,
+        in_memory: false,
+        non_interactive: false,
+    })
+}
+
+/// Function to read the credentials missing on the command line: the password from RUSTHOUND_PASSWORD,
+/// then the login and the password from --cred-file, then the password from a prompt without echo.
+pub fn resolve_credentials(common_args: &mut Options) {
+    // The relay tool binds with the relayed session, no password needed
+    if common_args.sspi || !common_args.decrypt.contains("not set") || !common_args.socks.contains("not set") || common_args.from_cache || !common_args.input_format.contains("not set") {
+        return
+    }
+    if common_args.password.is_none() {
+        if let Ok(password) = std::env::var(PASSWORD_ENV) {
+            info!("Password read from {}", PASSWORD_ENV);
+            // Not inherited by the processes started later, the environment of a host process is left untouched
+            if !common_args.non_interactive {
+                std::env::remove_var(PASSWORD_ENV);
+            }
+            common_args.password = Some(SecretString::from(password));
+        }
+    }
+    if !common_args.cred_file.contains("not set") {
+        let machines = [&common_args.domain, &common_args.ldapfqdn, &common_args.ip, &common_args.dc];
+        match read_cred_file(&common_args.cred_file, &machines) {
+            Ok(Some(credential)) => {
+                if common_args.username.contains("not set") {
+                    if let Some(login) = credential.login {
+                        common_args.username = login;
+                    }
+                }
+                if common_args.password.is_none() && credential.password.is_some() {
+                    info!("Password read from {}", &common_args.cred_file);
+                    common_args.password = credential.password;
+                }
+            }
+            Ok(None) => warn!("No entry for {} in {}", &common_args.domain, &common_args.cred_file),
+            Err(err) => error!("Can't read credential file {}. Reason: {err}", &common_args.cred_file),
+        }
+    }
+    // Without username the kerberos session is used
+    if common_args.password.is_none() && !common_args.username.contains("not set") {
+        if common_args.non_interactive {
+            error!("No password for {}, set it in the options or in {}", &common_args.username, PASSWORD_ENV);
+            return
+        }
+        match prompt_password(&common_args.username) {
+            Ok(password) => common_args.password = Some(password),
+            Err(err) => error!("Can't read the password from the terminal. Reason: {err}"),
+        }
     }
 }
+
+/// Function to read the password of --encrypt-output and --decrypt: from RUSTHOUND_ENCRYPT_PASSWORD,
+/// then from --encrypt-password-file, then from a prompt without echo.
+pub fn resolve_encrypt_password(common_args: &mut Options) {
+    if common_args.encrypt_output != "password" && common_args.decrypt.contains("not set") {
+        return
+    }
+    if let Ok(password) = std::env::var(ENCRYPT_PASSWORD_ENV) {
+        info!("Password of the output files read from {}", ENCRYPT_PASSWORD_ENV);
+        if !common_args.non_interactive {
+            std::env::remove_var(ENCRYPT_PASSWORD_ENV);
+        }
+        common_args.encrypt_password = Some(SecretString::from(password));
+        return
+    }
+    if !common_args.encrypt_password_file.contains("not set") {
+        match read_password_file(&common_args.encrypt_password_file) {
+            Ok(password) => {
+                info!("Password of the output files read from {}", &common_args.encrypt_password_file);
+                common_args.encrypt_password = Some(password);
+                return
+            }
+            Err(err) => error!("Can't read password file {}. Reason: {err}", &common_args.encrypt_password_file),
+        }
+    }
+    if common_args.non_interactive {
+        return
+    }
+    match prompt_secret("Password of the output files: ") {
+        Ok(password) => common_args.encrypt_password = Some(password),
+        Err(err) => error!("Can't read the password from the terminal. Reason: {err}"),
+    }
+}
+
+/// Test functions
+#[test]
+pub fn test_profile_page_size() {
+    let page_size = |args: &[&str]| extract_args_from([&["rusthound", "-d", "essos.local"], args].concat()).unwrap().page_size;
+    assert_eq!(page_size(&[]), PAGE_SIZE);
+    assert_eq!(page_size(&["--profile", "quick"]), 999);
+    assert_eq!(page_size(&["--profile", "full"]), 500);
+    assert_eq!(page_size(&["--profile", "stealth"]), STEALTH_PAGE_SIZE);
+    assert_eq!(page_size(&["--profile", "full", "--stealth"]), STEALTH_PAGE_SIZE);
+    // The flags given are kept
+    assert_eq!(page_size(&["--profile", "stealth", "--page-size", "50"]), 50);
+    assert!(extract_args_from(["rusthound", "-d", "essos.local", "--page-size", "1001"]).is_err());
+}
@@ -1,13 +1,26 @@
 //! Launch and end banners
-use colored::*;
+//!
+//! Built with the `minimal` feature there is no banner, and without the `console` feature the progress bar is
+//! a no-op `ProgressBar`, only the progress events are recorded, and `Colorize` writes the strings plain.
+#[cfg(feature = "console")]
+pub use colored::Colorize;
 use crate::enums::date::{return_current_date,return_current_time};
-use indicatif::{ProgressBar, ProgressStyle};
+#[cfg(feature = "console")]
+pub use indicatif::ProgressBar;
+#[cfg(feature = "console")]
+use indicatif::{ProgressDrawTarget, ProgressStyle};
+#[cfg(feature = "console")]
+use crate::modules::telemetry::log_json;
+use crate::modules::telemetry::record_progress;
 
 /// Banner when RustHound start.
 pub fn print_banner() {
+    if cfg!(feature = "minimal") {
+        return
+    }
     // https://docs.rs/colored/2.0.0/x86_64-pc-windows-msvc/colored/control/fn.set_virtual_terminal.html
-    #[cfg(windows)]
-    control::set_virtual_terminal(true).unwrap();
+    #[cfg(all(windows, feature = "console"))]
+    colored::control::set_virtual_terminal(true).unwrap();
 
     // Banner for RustHound
     println!("{}","---------------------------------------------------".clear().bold());
@@ -22,6 +35,9 @@ pub fn print_banner() {
 
 /// Banner when RustHound finish.
 pub fn print_end_banner() {
+    if cfg!(feature = "minimal") {
+        return
+    }
     // End banner for RustHound
     println!("\n{} Enumeration Completed at {} on {}! Happy Graphing!\n",
         "RustHound".truecolor(247,76,0,),
@@ -30,16 +46,71 @@ pub fn print_end_banner() {
     );
 }
 
+/// Colors of the builds without the console feature, the strings are written plain.
+#[cfg(not(feature = "console"))]
+pub trait Colorize {
+    fn normal(self) -> String;
+    fn clear(self) -> String;
+    fn bold(self) -> String;
+    fn red(self) -> String;
+    fn green(self) -> String;
+    fn yellow(self) -> String;
+    fn truecolor(self, r: u8, g: u8, b: u8) -> String;
+}
+
+#[cfg(not(feature = "console"))]
+impl Colorize for &str {
+    fn normal(self) -> String { self.to_string() }
+    fn clear(self) -> String { self.to_string() }
+    fn bold(self) -> String { self.to_string() }
+    fn red(self) -> String { self.to_string() }
+    fn green(self) -> String { self.to_string() }
+    fn yellow(self) -> String { self.to_string() }
+    fn truecolor(self, _r: u8, _g: u8, _b: u8) -> String { self.to_string() }
+}
+
+/// Progress bar of the builds without the console feature, nothing is drawn.
+#[cfg(not(feature = "console"))]
+#[derive(Clone, Debug, Default)]
+pub struct ProgressBar;
+
+#[cfg(not(feature = "console"))]
+impl ProgressBar {
+    pub fn new(_len: u64) -> ProgressBar {
+        ProgressBar
+    }
+
+    pub fn finish_and_clear(&self) {}
+}
+
 /// Progress Bar used in RustHound.
+#[cfg(feature = "console")]
 pub fn progress_bar(
 	pb: ProgressBar,
 	message: String,
 	count: u64,
     end_message: String,
 ) {
+	// Progress events instead of the bar in JSON mode
+	record_progress(&message, count, &end_message);
+	if log_json() || cfg!(feature = "minimal") {
+		pb.set_draw_target(ProgressDrawTarget::hidden());
+		return
+	}
 	pb.set_style(ProgressStyle::with_template("{prefix:.bold.dim}{spinner} {wide_msg}")
 		.unwrap()
         .tick_chars("⠁⠂⠄⡀⢀⠠⠐⠈ "));
 	pb.inc(count);
 	pb.with_message(format!("{}: {}{}",message,count,end_message));
-}
\ No newline at end of file
+}
+
+/// Progress Bar used in RustHound, only the progress events without the console feature.
+#[cfg(not(feature = "console"))]
+pub fn progress_bar(
+	_pb: ProgressBar,
+	message: String,
+	count: u64,
+    end_message: String,
+) {
+	record_progress(&message, count, &end_message);
+}
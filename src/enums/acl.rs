@@ -1,14 +1,52 @@
 extern crate lazy_static;
 
 use lazy_static::lazy_static;
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicBool, Ordering};
 
 use crate::enums::constants::*;
 use crate::enums::secdesc::*;
 use crate::enums::sid::{bin_to_string, sid_maker};
+use crate::enums::extrights::get_extended_right_name;
+use crate::enums::schema::get_schema_guid;
 use crate::json::templates::*;
 use bitflags::bitflags;
-use log::trace;
+use log::{debug, trace, warn};
+
+/// Add a readable "Justification" to every ACL edge (--edge-justification).
+static EDGE_JUSTIFICATION: AtomicBool = AtomicBool::new(false);
+
+/// Function to enable the "Justification" property on ACL edges.
+pub fn set_edge_justification(enabled: bool) {
+    EDGE_JUSTIFICATION.store(enabled, Ordering::Relaxed);
+}
+
+/// Add the ACE producing every ACL edge in a "Provenance" property (--edge-provenance).
+static EDGE_PROVENANCE: AtomicBool = AtomicBool::new(false);
+
+/// Function to enable the "Provenance" property on ACL edges, moved in a sidecar file before the JSON files are written.
+pub fn set_edge_provenance(enabled: bool) {
+    EDGE_PROVENANCE.store(enabled, Ordering::Relaxed);
+}
+
+/// Keep the owner and the explicit ACEs of every descriptor (--acl-baseline).
+static ACL_BASELINE: AtomicBool = AtomicBool::new(false);
+
+lazy_static! {
+    /// distinguishedName => (owner SID, explicit ACEs)
+    static ref DESCRIPTORS: Mutex<BTreeMap<String, (String, Vec<String>)>> = Mutex::new(BTreeMap::new());
+}
+
+/// Function to enable the descriptors recording of --acl-baseline.
+pub fn set_acl_baseline(enabled: bool) {
+    ACL_BASELINE.store(enabled, Ordering::Relaxed);
+}
+
+/// Function to take the descriptors recorded during the parsing.
+pub fn recorded_descriptors() -> BTreeMap<String, (String, Vec<String>)> {
+    DESCRIPTORS.lock().map(|mut descriptors| std::mem::take(&mut *descriptors)).unwrap_or_default()
+}
 
 /// This function allows to parse the attribut nTSecurityDescriptor from secdesc.rs
 /// <http://www.selfadsi.org/deep-inside/ad-security-descriptors.htm#SecurityDescriptorStructure>
@@ -22,43 +60,35 @@ pub fn parse_ntsecuritydescriptor(
 ) -> Vec<serde_json::value::Value> {
     let mut relations_dacl: Vec<serde_json::value::Value> = Vec::new();
     let relations_sacl: Vec<serde_json::value::Value> = Vec::new();
-    let secdesc: SecurityDescriptor;
     let mut owner_sid: String = "".to_string();
-    #[warn(unused_assignments)]
-    let sacl: Acl;
-    let dacl: Acl;
+    let object_dn = valjson["Properties"]["distinguishedname"].as_str().unwrap_or("").to_string();
 
-    secdesc = SecurityDescriptor::parse(&nt).unwrap().1;
-    trace!("SECURITY-DESCRIPTOR: {:?}", secdesc);
+    // A malformed descriptor is logged and skipped, the run continues with the next object
+    let secdesc = match SecurityDescriptor::parse_complete(nt) {
+        Ok(secdesc) => secdesc,
+        Err(err) => {
+            warn!("Malformed nTSecurityDescriptor for {}: {}", &object_dn, err);
+            return relations_dacl;
+        }
+    };
+    trace!("SECURITY-DESCRIPTOR: {:?}", secdesc.header);
 
     // Check for ACL protected for Bloodhound4.1+
     // IsACLProtected
-    let acl_is_protected = has_control(secdesc.control, SecurityDescriptorFlags::DACL_PROTECTED);
+    let acl_is_protected = has_control(secdesc.header.control, SecurityDescriptorFlags::DACL_PROTECTED);
     //trace!("{} acl_is_protected: {:?}",valjson["Properties"]["name"],acl_is_protected);
     valjson["IsACLProtected"] = acl_is_protected.into();
 
-    if secdesc.offset_owner as usize != 0 {
-        owner_sid = sid_maker(
-            LdapSid::parse(&nt[secdesc.offset_owner as usize..])
-                .unwrap()
-                .1,
-            domain,
-        );
+    if let Some(sid) = secdesc.owner {
+        owner_sid = sid_maker(sid, domain);
         trace!("OWNER-SID: {:?}", owner_sid);
     }
 
-    if secdesc.offset_group as usize != 0 {
-        let group_sid = sid_maker(
-            LdapSid::parse(&nt[secdesc.offset_group as usize..])
-                .unwrap()
-                .1,
-            domain,
-        );
-        trace!("GROUP-SID: {:?}", group_sid);
+    if let Some(sid) = secdesc.group {
+        trace!("GROUP-SID: {:?}", sid_maker(sid, domain));
     }
 
-    if secdesc.offset_sacl as usize != 0 {
-        sacl = Acl::parse(&nt[secdesc.offset_sacl as usize..]).unwrap().1;
+    if let Some(sacl) = secdesc.sacl {
         trace!("SACL: {:?}", sacl);
         let aces = sacl.data;
         trace!("ACES in SACL.DATA: {:?}", aces);
@@ -76,10 +106,23 @@ pub fn parse_ntsecuritydescriptor(
         return relations_sacl;
     }
 
-    if secdesc.offset_dacl as usize != 0 {
-        dacl = Acl::parse(&nt[secdesc.offset_dacl as usize..]).unwrap().1;
+    if let Some(dacl) = secdesc.dacl {
         trace!("DACL: {:?}", dacl);
         let aces = dacl.data;
+        // Deny ACEs for Everyone or Authenticated Users are unusual, deception tools use them on canary objects
+        if everyone_denied(&aces, domain) {
+            valjson["Properties"]["everyone_denied"] = true.into();
+        }
+        // Principals creating computers in an OU or container choose their msDS-AllowedToActOnBehalfOfOtherIdentity (RBCD)
+        if ["ou", "container", "domain"].contains(&entry_type.as_str()) {
+            let creators = computer_creators(&aces, domain, &entry_type);
+            if !creators.is_empty() {
+                valjson["Properties"]["computercreators"] = creators.into();
+            }
+        }
+        if ACL_BASELINE.load(Ordering::Relaxed) {
+            record_descriptor(&object_dn, &owner_sid, &aces, domain);
+        }
         ace_maker(
             valjson,
             domain,
@@ -96,6 +139,58 @@ pub fn parse_ntsecuritydescriptor(
     return relations_dacl;
 }
 
+/// Function to know if the DACL denies any right to Everyone or Authenticated Users on the object itself.
+pub fn everyone_denied(aces: &[Ace], domain: &String) -> bool {
+    aces.iter().any(|ace| {
+        (ace.ace_type == ACCESS_DENIED_ACE_TYPE || ace.ace_type == ACCESS_DENIED_OBJECT_ACE_TYPE)
+            && ace.ace_flags & INHERIT_ONLY_ACE != INHERIT_ONLY_ACE
+            && AceFormat::get_sid(ace.data.to_owned())
+                .map(|sid| {
+                    let sid = sid_maker(sid, domain);
+                    sid.ends_with(EVERYONE_SID) || sid.ends_with(AUTHENTICATED_USERS_SID)
+                })
+                .unwrap_or(false)
+    })
+}
+
+/// Function to get the principals allowed to create computer objects in a container (CreateChild on the
+/// computer class or on every class). Creator Owner and Local System are ignored.
+pub fn computer_creators(aces: &[Ace], domain: &String, entry_type: &String) -> Vec<String> {
+    let mut creators: Vec<String> = Vec::new();
+    for ace in aces {
+        if (ace.ace_type != 0x05 && ace.ace_type != 0x00) || ace.ace_flags & INHERIT_ONLY_ACE == INHERIT_ONLY_ACE {
+            continue;
+        }
+        let mask = match AceFormat::get_mask(ace.data.to_owned()) {
+            Some(mask) => AccessMask::from_bits_truncate(mask),
+            None => continue,
+        };
+        if !mask.contains(AccessMask::ADS_RIGHT_DS_CREATE_CHILD) {
+            continue;
+        }
+        if ace.ace_type == 0x05 {
+            let flags = AceFormat::get_flags(ace.data.to_owned()).map(|flags| flags.bits()).unwrap_or(0);
+            let guid = |value: Option<u128>| bin_to_string(&value.unwrap_or(0).to_be_bytes().to_vec()).to_lowercase();
+            if flags & ACE_INHERITED_OBJECT_TYPE_PRESENT == ACE_INHERITED_OBJECT_TYPE_PRESENT
+                && !ace_applies(&guid(AceFormat::get_inherited_object_type(ace.data.to_owned())), entry_type)
+            {
+                continue;
+            }
+            if flags & ACE_OBJECT_TYPE_PRESENT == ACE_OBJECT_TYPE_PRESENT
+                && guid(AceFormat::get_object_type(ace.data.to_owned())) != objecttype_guid("computer")
+            {
+                continue;
+            }
+        }
+        if let Some(sid) = AceFormat::get_sid(ace.data.to_owned()).map(|sid| sid_maker(sid, domain)) {
+            if !sid.ends_with("S-1-3-0") && !sid.ends_with("S-1-5-18") && !creators.contains(&sid) {
+                creators.push(sid);
+            }
+        }
+    }
+    creators
+}
+
 /// Parse ace in acl and get correct values (thanks fox-it for bloodhound.py works)
 /// <https://github.com/fox-it/BloodHound.py/blob/master/bloodhound/enumeration/acls.py>
 fn ace_maker(
@@ -119,19 +214,64 @@ fn ace_maker(
         "S-1-5-18".to_string(),
         "S-1-5-10".to_string(),
     ]; //, "S-1-1-0".to_string(), "S-1-5-10".to_string(), "S-1-5-11".to_string()];
-    if ignoresids.iter().any(|i| !osid.contains(i)) {
+    let owner_ignored = osid.is_empty() || ignoresids.iter().any(|i| osid.contains(i));
+
+    // OWNER RIGHTS (S-1-3-4) ACEs replace the implicit READ_CONTROL and WRITE_DAC of the owner,
+    // the owner only gets what these ACEs grant.
+    // <https://learn.microsoft.com/en-us/windows/win32/secauthz/ownership-of-objects>
+    let owner_rights = aces.iter().any(|ace| {
+        (ace.ace_type == 0x05 || ace.ace_type == 0x00)
+            && ace.ace_flags & INHERIT_ONLY_ACE != INHERIT_ONLY_ACE
+            && AceFormat::get_sid(ace.data.to_owned())
+                .map(|sid| sid_maker(sid, domain).ends_with(OWNER_RIGHTS_SID))
+                .unwrap_or(false)
+    });
+    trace!("OWNER RIGHTS ACE present: {:?}", owner_rights);
+    let justify = EDGE_JUSTIFICATION.load(Ordering::Relaxed);
+    let provenance = EDGE_PROVENANCE.load(Ordering::Relaxed);
+    let object_dn = valjson["Properties"]["distinguishedname"].as_str().unwrap_or("").to_string();
+    if !owner_ignored && !owner_rights {
             relations.push(build_relation(osid,"Owns".to_string(),"Base".to_string(),false,));
+            let owns = relations.len() - 1;
+            if justify {
+                relations[owns]["Justification"] = format!("Owner of {}: Owns granted to {}", &object_dn, osid).into();
+            }
+            if provenance {
+                relations[owns]["Provenance"] = serde_json::json!({ "Source": "nTSecurityDescriptor", "Owner": osid });
+            }
     }
 
-    for ace in aces {
+    // Relations built for one ACE are justified when the next ACE starts (first relation, ACE index)
+    let mut pending = (relations.len(), 0);
+    let mut pending_provenance = serde_json::value::Value::Null;
+    for (index, ace) in aces.into_iter().enumerate() {
+        if justify {
+            justify_relations(relations, pending, &object_dn);
+        }
+        if provenance {
+            set_provenance(relations, pending.0, &pending_provenance);
+            pending_provenance = ace_provenance(&ace, index);
+        }
+        if justify || provenance {
+            pending = (relations.len(), index);
+        }
         if ace.ace_type != 0x05 && ace.ace_type != 0x00 {
             trace!("Don't care about acetype {:?}", ace.ace_type);
             continue;
         }
 
-        let sid = sid_maker(AceFormat::get_sid(ace.data.to_owned()).unwrap(), domain);
+        let mut sid = sid_maker(AceFormat::get_sid(ace.data.to_owned()).unwrap(), domain);
         trace!("SID for this ACE: {}", &sid);
 
+        // Rights granted to OWNER RIGHTS are the rights of the current owner
+        if sid.ends_with(OWNER_RIGHTS_SID) {
+            if owner_ignored {
+                continue;
+            }
+            trace!("OWNER RIGHTS ACE applied to owner {}", osid);
+            sid = osid.to_owned();
+        }
+
         // Check if sid is in the ignored list
         if ignoresids.iter().any(|i| sid.contains(i)) {
             continue;
@@ -144,6 +284,14 @@ fn ace_maker(
             ace.ace_flags
         );
 
+        // https://github.com/BloodHoundAD/SharpHoundCommon/blob/main/src/CommonLib/Processors/ACLProcessor.cs
+        // INHERIT_ONLY_ACE means the ACE is only there to be propagated to child objects,
+        // it never grants anything on this object (inherited copy or not).
+        if ace.ace_flags & INHERIT_ONLY_ACE == INHERIT_ONLY_ACE {
+            trace!("QUIT: has_flag(ACE.INHERIT_ONLY_ACE)");
+            continue;
+        }
+
         // https://github.com/fox-it/BloodHound.py/blob/645082e3462c93f31b571db945cde1fd7b837fb9/bloodhound/enumeration/acls.py#L74
         if ace.ace_type == 0x05 {
             trace!("TYPE: 0x05");
@@ -176,25 +324,12 @@ fn ace_maker(
             // Get the Flag for the ace.datas
             let flags = AceFormat::get_flags(ace.data.to_owned()).unwrap().bits();
 
-            // https://github.com/fox-it/BloodHound.py/blob/645082e3462c93f31b571db945cde1fd7b837fb9/bloodhound/enumeration/acls.py#L77
-            if (ace.ace_flags & INHERITED_ACE != INHERITED_ACE)
-                && (ace.ace_flags & INHERIT_ONLY_ACE == INHERIT_ONLY_ACE)
-            {
-                // ACE is set on this object, but only inherited, so not applicable to us
-                trace!("QUIT: has_flag(ACE.INHERITED_ACE)");
-                continue;
-            }
-
             // https://github.com/fox-it/BloodHound.py/blob/645082e3462c93f31b571db945cde1fd7b837fb9/bloodhound/enumeration/acls.py#L82
-            //let ace_object_flags = AceFormat::get_flags(ace.data.to_owned()).unwrap();
             if (ace.ace_flags & INHERITED_ACE == INHERITED_ACE)
                 && (&flags & ACE_INHERITED_OBJECT_TYPE_PRESENT == ACE_INHERITED_OBJECT_TYPE_PRESENT)
             {
-                // ACE is set on this object, but only inherited, so not applicable to us
-                // need to verify if the ACE applies to this object type #todo
-                // Verify if the ACE applies to this object type
-                // if not ace_applies(ace_object.acedata.get_inherited_object_type().lower(), entrytype, objecttype_guid_map):
-                // continue
+                // ACE was inherited from a parent but only for one class of child objects,
+                // verify if the ACE applies to this object type.
                 // https://github.com/fox-it/BloodHound.py/blob/645082e3462c93f31b571db945cde1fd7b837fb9/bloodhound/enumeration/acls.py#L85
                 let ace_guid =
                     bin_to_string(&inherited_object_type.to_be_bytes().to_vec()).to_lowercase();
@@ -205,7 +340,7 @@ fn ace_maker(
             }
 
             let mask = match AceFormat::get_mask(ace.data.to_owned()) {
-                Some(mask) => mask,
+                Some(mask) => AccessMask::from_bits_truncate(mask),
                 None => continue,
             };
             trace!("ACE MASK for ACETYPE 0x05: {:?}", mask);
@@ -214,11 +349,7 @@ fn ace_maker(
             trace!("ACE GUID for ACETYPE 0x05: {:?}", ace_guid);
 
             // https://github.com/fox-it/BloodHound.py/blob/645082e3462c93f31b571db945cde1fd7b837fb9/bloodhound/enumeration/acls.py#L92
-            if ((MaskFlags::GENERIC_ALL.bits() | mask) == mask)
-                || ((MaskFlags::WRITE_DACL.bits() | mask) == mask)
-                || ((MaskFlags::WRITE_OWNER.bits() | mask) == mask)
-                || ((MaskFlags::GENERIC_WRITE.bits() | mask) == mask)
-            {
+            if mask.grants_object_control() {
                 trace!("MATCH: 0");
                 trace!(
                     "ACE MASK contain: GENERIC_ALL or WRITE_DACL or WRITE_OWNER or GENERIC_WRITE"
@@ -229,7 +360,7 @@ fn ace_maker(
                     trace!("QUIT: 1");
                     continue;
                 }
-                if (MaskFlags::GENERIC_ALL.bits() | mask) == mask {
+                if mask.grants_generic_all() {
                     trace!("MATCH: 1");
                     if entry_type == "computer"
                         && (&flags & ACE_OBJECT_TYPE_PRESENT == ACE_OBJECT_TYPE_PRESENT)
@@ -237,11 +368,7 @@ fn ace_maker(
                     {
                         trace!("MATCH: 2");
 
-                        let null: String = "NULL".to_string();
-                        if &ace_guid
-                            == OBJECTTYPE_GUID_HASHMAP
-                                .get("ms-mcs-admpwd")
-                                .unwrap_or(&null)
+                        if ace_guid == objecttype_guid("ms-mcs-admpwd")
                         {
                             trace!("MATCH: 3");
                             trace!("object_type ace_guid == OBJECTTYPE_GUID_HASHMAP.get('ms-mcs-admpwd')");
@@ -255,7 +382,7 @@ fn ace_maker(
                     trace!("QUIT: 2");
                     continue;
                 }
-                if (MaskFlags::GENERIC_WRITE.bits() | mask) == mask {
+                if mask.grants_generic_write() {
                     trace!("MATCH: 5");
                     relations.push(build_relation(&sid,"GenericWrite".to_string(),"".to_string(),is_inherited,));
                     if (entry_type != "domain") && (entry_type != "computer") {
@@ -263,11 +390,11 @@ fn ace_maker(
                         continue;
                     }
                 }
-                if (MaskFlags::WRITE_DACL.bits() | mask) == mask {
+                if mask.grants_write_dacl() {
                     trace!("MATCH: 6");
                     relations.push(build_relation(&sid,"WriteDacl".to_string(),"".to_string(),is_inherited,));
                 }
-                if (MaskFlags::WRITE_OWNER.bits() | mask) == mask {
+                if mask.grants_write_owner() {
                     trace!("MATCH: 7");
                     relations.push(build_relation(&sid,"WriteOwner".to_string(),"".to_string(),is_inherited,));
                 }
@@ -275,7 +402,7 @@ fn ace_maker(
 
             // Property write privileges
             // https://github.com/fox-it/BloodHound.py/blob/645082e3462c93f31b571db945cde1fd7b837fb9/bloodhound/enumeration/acls.py#L126
-            if (MaskFlags::ADS_RIGHT_DS_WRITE_PROP.bits() | mask) == mask {
+            if mask.grants_write_property() {
                 trace!("MATCH: 8");
 
                 if ((entry_type == "user") || (entry_type == "group") || (entry_type == "computer"))
@@ -299,15 +426,14 @@ fn ace_maker(
 
                 // Since BloodHound 4.1
                 // AddKeyCredentialLink write access
-                let null: String = "NULL".to_string();
                 if ((entry_type == "user") || (entry_type == "computer"))
-                && (&flags & ACE_OBJECT_TYPE_PRESENT == ACE_OBJECT_TYPE_PRESENT) && (&ace_guid == OBJECTTYPE_GUID_HASHMAP.get("ms-ds-key-credential-link").unwrap_or(&null))
+                && (&flags & ACE_OBJECT_TYPE_PRESENT == ACE_OBJECT_TYPE_PRESENT) && (ace_guid == objecttype_guid("ms-ds-key-credential-link"))
                 {
                     trace!("MATCH: 27");
                     relations.push(build_relation(&sid,"AddKeyCredentialLink".to_string(),"".to_string(),is_inherited,));
                 }
                 if (entry_type == "user")
-                && (&flags & ACE_OBJECT_TYPE_PRESENT == ACE_OBJECT_TYPE_PRESENT) && (&ace_guid == OBJECTTYPE_GUID_HASHMAP.get("mservice-principal-name").unwrap_or(&null))
+                && (&flags & ACE_OBJECT_TYPE_PRESENT == ACE_OBJECT_TYPE_PRESENT) && (ace_guid == objecttype_guid("mservice-principal-name"))
                 {
                     trace!("MATCH: 28");
                     relations.push(build_relation(&sid,"WriteSPN".to_string(),"".to_string(),is_inherited,));
@@ -315,9 +441,8 @@ fn ace_maker(
             }
             else
             {
-                if (MaskFlags::ADS_RIGHT_DS_SELF.bits() | mask) == mask {
-                    let null: String = "NULL".to_string();
-                    if (entry_type == "group") && (&ace_guid == OBJECTTYPE_GUID_HASHMAP.get("WriteMember").unwrap_or(&null))
+                if mask.grants_self_write() {
+                    if (entry_type == "group") && (ace_guid == objecttype_guid("WriteMember"))
                     {
                         trace!("MATCH: 29");
                         relations.push(build_relation(&sid,"AddSelf".to_string(),"".to_string(),is_inherited,));
@@ -327,18 +452,14 @@ fn ace_maker(
 
             // Property read privileges
             // https://github.com/fox-it/BloodHound.py/blob/645082e3462c93f31b571db945cde1fd7b837fb9/bloodhound/enumeration/acls.py#L138
-            if (MaskFlags::ADS_RIGHT_DS_READ_PROP.bits() | mask) == mask {
+            if mask.grants_read_property() {
                 trace!("MATCH: 12");
                 if (entry_type == "computer")
                     && (&flags & ACE_OBJECT_TYPE_PRESENT == ACE_OBJECT_TYPE_PRESENT)
                     && valjson["Properties"]["haslaps"].as_bool().unwrap() == true
                 {
                     trace!("MATCH: 13");
-                    let null: String = "NULL".to_string();
-                    if &ace_guid
-                        == OBJECTTYPE_GUID_HASHMAP
-                            .get("ms-mcs-admpwd")
-                            .unwrap_or(&null)
+                    if ace_guid == objecttype_guid("ms-mcs-admpwd")
                     {
                         trace!("MATCH: 14 ?");
                         relations.push(build_relation(&sid,"ReadLAPSPassword".to_string(),"".to_string(),is_inherited,));
@@ -348,7 +469,7 @@ fn ace_maker(
 
             // Extended rights
             // https://github.com/fox-it/BloodHound.py/blob/645082e3462c93f31b571db945cde1fd7b837fb9/bloodhound/enumeration/acls.py#L146
-            if (MaskFlags::ADS_RIGHT_DS_CONTROL_ACCESS.bits() | mask) == mask {
+            if mask.grants_control_access() {
                 trace!("MATCH: 15");
                 // All Extended
                 if ((entry_type == "user") || (entry_type == "domain"))
@@ -364,21 +485,21 @@ fn ace_maker(
                     trace!("MATCH: 17");
                     relations.push(build_relation(&sid,"AllExtendedRights".to_string(),"".to_string(),is_inherited,));
                 }
-                if (entry_type == "domain") && has_extended_right(&ace, GET_CHANGES) {
-                    trace!("MATCH: 18");
-                    relations.push(build_relation(&sid,"GetChanges".to_string(),"".to_string(),is_inherited,));
-                }
-                if (entry_type == "domain") && has_extended_right(&ace, GET_CHANGES_ALL) {
-                    trace!("MATCH: 19");
-                    relations.push(build_relation(&sid,"GetChangesAll".to_string(),"".to_string(),is_inherited,));
-                }
-                if (entry_type == "domain") && has_extended_right(&ace, GET_CHANGES_IN_FILTERED_SET) {
-                    trace!("MATCH: 19.2");
-                    relations.push(build_relation(&sid,"GetChangesInFilteredSet".to_string(),"".to_string(),is_inherited,));
+                // Only the rights meaningful on this object class, a ForceChangePassword inherited by an OU grants nothing
+                for (right_guid, edge) in extended_right_edges(&entry_type) {
+                    if has_extended_right(&ace, right_guid) {
+                        trace!("MATCH: 18 {}", edge);
+                        relations.push(build_relation(&sid,edge.to_string(),"".to_string(),is_inherited,));
+                    }
                 }
-                if (entry_type == "user") && has_extended_right(&ace, USER_FORCE_CHANGE_PASSWORD) {
-                    trace!("MATCH: 20");
-                    relations.push(build_relation(&sid,"ForceChangePassword".to_string(),"".to_string(),is_inherited,));
+                // Name the other extended rights, custom ones included if CN=Extended-Rights was collected
+                if &flags & ACE_OBJECT_TYPE_PRESENT == ACE_OBJECT_TYPE_PRESENT {
+                    debug!(
+                        "Extended right {} granted to {} on {}",
+                        get_extended_right_name(&ace_guid).unwrap_or(ace_guid.to_owned()),
+                        &sid,
+                        valjson["Properties"]["name"].as_str().unwrap_or("")
+                    );
                 }
             }
         }
@@ -390,41 +511,50 @@ fn ace_maker(
             let is_inherited = ace.ace_flags & INHERITED_ACE == INHERITED_ACE;
 
             let mask = match AceFormat::get_mask(ace.data.to_owned()) {
-                Some(mask) => mask,
+                Some(mask) => AccessMask::from_bits_truncate(mask),
                 None => continue,
             };
             trace!("ACE MASK for ACETYPE 0x00: {:?}", mask);
 
-            if (MaskFlags::GENERIC_ALL.bits() | mask) == mask {
+            if mask.grants_generic_all() {
                 trace!("MATCH: 21");
                 relations.push(build_relation(&sid,"GenericAll".to_string(),"".to_string(),is_inherited,));
                 trace!("QUIT: 4");
                 continue;
             }
-            if (MaskFlags::ADS_RIGHT_DS_WRITE_PROP.bits() | mask) == mask {
+            if mask.grants_write_property() {
                 trace!("MATCH: 22");
                 relations.push(build_relation(&sid,"GenericWrite".to_string(),"".to_string(),is_inherited,));
+                // Every property is writable: msDS-AllowedToActOnBehalfOfOtherIdentity and the account restrictions (RBCD)
+                if entry_type == "computer" {
+                    trace!("MATCH: 22.1");
+                    relations.push(build_relation(&sid,"AddAllowedToAct".to_string(),"".to_string(),is_inherited,));
+                    if !sid.ends_with("-512") {
+                        trace!("MATCH: 22.2");
+                        relations.push(build_relation(&sid,"WriteAccountRestrictions".to_string(),"".to_string(),is_inherited,));
+                    }
+                }
             }
-            if (MaskFlags::WRITE_OWNER.bits() | mask) == mask {
+            if mask.grants_write_owner() {
                 trace!("MATCH: 23");
                 relations.push(build_relation(&sid,"WriteOwner".to_string(),"".to_string(),is_inherited,));
             }
             // For users and domain, check extended rights
             if ((entry_type == "user") || (entry_type == "domain"))
-                && ((MaskFlags::ADS_RIGHT_DS_CONTROL_ACCESS.bits() | mask) == mask)
+                && mask.grants_control_access()
             {
                 trace!("MATCH: 24");
                 relations.push(build_relation(&sid,"AllExtendedRights".to_string(),"".to_string(),is_inherited,));
             }
             // For computer
             if (entry_type == "computer")
-                && ((MaskFlags::ADS_RIGHT_DS_CONTROL_ACCESS.bits() | mask) == mask)
+                && mask.grants_control_access()
                 && valjson["Properties"]["haslaps"].as_bool().unwrap() == true
             {
                 trace!("MATCH: 25");
                 relations.push(build_relation(&sid,"AllExtendedRights".to_string(),"".to_string(),is_inherited,));
             }
-            if (MaskFlags::WRITE_DACL.bits() | mask) == mask {
+            if mask.grants_write_dacl() {
                 trace!("MATCH: 26");
                 relations.push(build_relation(
                     &sid,
@@ -435,6 +565,75 @@ fn ace_maker(
             }
         }
     }
+    if justify {
+        justify_relations(relations, pending, &object_dn);
+    }
+    if provenance {
+        set_provenance(relations, pending.0, &pending_provenance);
+    }
+}
+
+/// Function to get the lowercase string of an object type GUID of an ACE.
+fn ace_guid(value: Option<u128>) -> Option<String> {
+    value.map(|guid| bin_to_string(&guid.to_be_bytes().to_vec()).to_lowercase())
+}
+
+/// Function to keep the owner and the explicit ACEs of one descriptor, one line per ACE:
+/// "type flags mask SID object_type inherited_object_type", "-" for an absent GUID.
+fn record_descriptor(object_dn: &String, owner_sid: &String, aces: &[Ace], domain: &String) {
+    let explicit: Vec<String> = aces.iter()
+        .filter(|ace| ace.ace_flags & INHERITED_ACE != INHERITED_ACE)
+        .filter_map(|ace| {
+            let sid = sid_maker(AceFormat::get_sid(ace.data.to_owned())?, domain);
+            let mask = AceFormat::get_mask(ace.data.to_owned())?;
+            let object_type = ace_guid(AceFormat::get_object_type(ace.data.to_owned())).unwrap_or("-".to_string());
+            let inherited_object_type = ace_guid(AceFormat::get_inherited_object_type(ace.data.to_owned())).unwrap_or("-".to_string());
+            Some(format!("{:02x} {:02x} {:08x} {} {} {}", ace.ace_type, ace.ace_flags, mask, sid, object_type, inherited_object_type))
+        })
+        .collect();
+    if let Ok(mut descriptors) = DESCRIPTORS.lock() {
+        descriptors.insert(object_dn.to_owned(), (owner_sid.to_owned(), explicit));
+    }
+}
+
+/// Function to describe one ACE: index in the DACL, type, flags, mask and object types.
+fn ace_provenance(ace: &Ace, index: usize) -> serde_json::value::Value {
+    let object_type = ace_guid(AceFormat::get_object_type(ace.data.to_owned()));
+    serde_json::json!({
+        "Source": "nTSecurityDescriptor",
+        "AceIndex": index,
+        "AceType": format!("0x{:02x}", ace.ace_type),
+        "AceFlags": format!("0x{:02x}", ace.ace_flags),
+        "AccessMask": AceFormat::get_mask(ace.data.to_owned()).map(|mask| format!("0x{:08x}", mask)),
+        "IsInherited": ace.ace_flags & INHERITED_ACE == INHERITED_ACE,
+        "ObjectType": object_type,
+        "ObjectTypeName": object_type.as_deref().and_then(get_extended_right_name),
+        "InheritedObjectType": ace_guid(AceFormat::get_inherited_object_type(ace.data.to_owned())),
+    })
+}
+
+/// Add the provenance of one ACE to the relations built from it.
+fn set_provenance(relations: &mut Vec<serde_json::value::Value>, first: usize, provenance: &serde_json::value::Value) {
+    for relation in relations.iter_mut().skip(first) {
+        relation["Provenance"] = provenance.to_owned();
+    }
+}
+
+/// Add the "Justification" to the relations built from one ACE.
+/// Like "ACE 12 on CN=X: GenericWrite granted to S-1-5-21-...-1104, inherited"
+fn justify_relations(relations: &mut Vec<serde_json::value::Value>, pending: (usize, usize), object_dn: &String) {
+    let (first, index) = pending;
+    for relation in relations.iter_mut().skip(first) {
+        let inherited = if relation["IsInherited"].as_bool().unwrap_or(false) { ", inherited" } else { "" };
+        relation["Justification"] = format!(
+            "ACE {} on {}: {} granted to {}{}",
+            index,
+            object_dn,
+            relation["RightName"].as_str().unwrap_or(""),
+            relation["PrincipalSID"].as_str().unwrap_or(""),
+            inherited,
+        ).into();
+    }
 }
 
 /// Make Relation
@@ -465,11 +664,11 @@ fn can_write_property(ace: &Ace, bin_property: &str) -> bool {
 
     // If not found, then assume can't write. Should not happen, but missing some parsers.
     let mask = match AceFormat::get_mask(ace.data.to_owned()) {
-        Some(mask) => mask,
+        Some(mask) => AccessMask::from_bits_truncate(mask),
         None => return false,
     };
 
-    if (MaskFlags::ADS_RIGHT_DS_WRITE_PROP.bits() | mask) != mask {
+    if !mask.grants_write_property() {
         //if not ace_object.acedata.mask.has_priv(ACCESS_MASK.ADS_RIGHT_DS_WRITE_PROP):
         return false;
     }
@@ -500,6 +699,22 @@ fn can_write_property(ace: &Ace, bin_property: &str) -> bool {
     return false;
 }
 
+/// Extended rights turned into edges: (right GUID, edge, entry types where the right applies).
+const EXTENDED_RIGHT_EDGES: [(&str, &str, &[&str]); 4] = [
+    (GET_CHANGES, "GetChanges", &["domain"]),
+    (GET_CHANGES_ALL, "GetChangesAll", &["domain"]),
+    (GET_CHANGES_IN_FILTERED_SET, "GetChangesInFilteredSet", &["domain"]),
+    (USER_FORCE_CHANGE_PASSWORD, "ForceChangePassword", &["user"]),
+];
+
+/// Get the extended rights (GUID, edge) applying to one entry type.
+pub fn extended_right_edges(entry_type: &String) -> Vec<(&'static str, &'static str)> {
+    EXTENDED_RIGHT_EDGES.iter()
+        .filter(|(_, _, entry_types)| entry_types.contains(&entry_type.as_str()))
+        .map(|(right_guid, edge, _)| (*right_guid, *edge))
+        .collect()
+}
+
 /// Checks if the access is sufficient to control the right with the given GUID.
 /// <https://github.com/fox-it/BloodHound.py/blob/645082e3462c93f31b571db945cde1fd7b837fb9/bloodhound/enumeration/acls.py#L211>
 fn has_extended_right(ace: &Ace, bin_right_guid: &str) -> bool {
@@ -509,10 +724,10 @@ fn has_extended_right(ace: &Ace, bin_right_guid: &str) -> bool {
     // [MS-ADTS] section 5.1.3.2: https://msdn.microsoft.com/en-us/library/cc223511.aspx
 
     let mask = match AceFormat::get_mask(ace.data.to_owned()) {
-        Some(mask) => mask,
+        Some(mask) => AccessMask::from_bits_truncate(mask),
         None => return false,
     };
-    if (MaskFlags::ADS_RIGHT_DS_CONTROL_ACCESS.bits() | mask) != mask {
+    if !mask.grants_control_access() {
         // if not ace_object.acedata.mask.has_priv(ACCESS_MASK.ADS_RIGHT_DS_CONTROL_ACCESS):
         trace!("has_extended_right : return false for ADS_RIGHT_DS_CONTROL_ACCESS != mask");
         return false;
@@ -554,18 +769,40 @@ fn ace_applies(ace_guid: &String, entry_type: &String) -> bool {
 
     trace!("ACE GUID: {}", &ace_guid);
 
-    let null: String = "NULL".to_string();
-    trace!(
-        "OBJECTTYPE_GUID_HASHMAP: {}",
-        OBJECTTYPE_GUID_HASHMAP.get(entry_type).unwrap_or(&null)
-    );
-
-    if &ace_guid == &OBJECTTYPE_GUID_HASHMAP.get(entry_type).unwrap_or(&null) {
-        return true;
+    // AD matches the inherited object type against the whole objectClass chain,
+    // so a computer also inherits ACEs targeting the user class.
+    for class in entry_type_classes(entry_type) {
+        let class_guid = objecttype_guid(class);
+        trace!("OBJECTTYPE_GUID_HASHMAP: {}", &class_guid);
+        if ace_guid == &class_guid {
+            return true;
+        }
     }
     return false;
 }
 
+/// Get the GUID of one schema attribute or class, from the live schema if it was collected
+/// and from OBJECTTYPE_GUID_HASHMAP otherwise.
+fn objecttype_guid(name: &str) -> String {
+    get_schema_guid(name)
+        .or(OBJECTTYPE_GUID_HASHMAP.get(name).map(|guid| guid.to_owned()))
+        .unwrap_or("NULL".to_string())
+}
+
+/// Schema class names (OBJECTTYPE_GUID_HASHMAP keys) matching a RustHound entry type.
+fn entry_type_classes(entry_type: &String) -> Vec<&'static str> {
+    match entry_type.as_str() {
+        "user" => vec!["user"],
+        "computer" => vec!["computer", "user"],
+        "group" => vec!["group"],
+        "ou" => vec!["organizational-unit"],
+        "gpo" => vec!["group-policy-container"],
+        "container" => vec!["container"],
+        "domain" => vec!["domain-dns", "domain"],
+        _ => vec![],
+    }
+}
+
 /// Function to check the user can read Service Account password
 pub fn parse_gmsa(
     processed_aces: &mut Vec<serde_json::value::Value>,
@@ -582,9 +819,10 @@ pub fn parse_gmsa(
     }
 }
 
-// Access Mask contain value?
 bitflags! {
-    struct MaskFlags: u32 {
+    /// Access mask of an ACE.
+    /// <https://learn.microsoft.com/en-us/openspecs/windows_protocols/ms-adts/990fb975-ab31-4bc1-8b75-5da132cd4584>
+    pub struct AccessMask: u32 {
         // These constants are only used when WRITING
         // and are then translated into their actual rights
         const SET_GENERIC_READ        = 0x80000000;
@@ -620,6 +858,53 @@ bitflags! {
     }
 }
 
+impl AccessMask {
+    /// The right contains every bit of GENERIC_ALL.
+    pub fn grants_generic_all(&self) -> bool {
+        self.contains(AccessMask::GENERIC_ALL)
+    }
+
+    /// The right contains every bit of GENERIC_WRITE.
+    pub fn grants_generic_write(&self) -> bool {
+        self.contains(AccessMask::GENERIC_WRITE)
+    }
+
+    /// WriteDacl: the DACL can be changed.
+    pub fn grants_write_dacl(&self) -> bool {
+        self.contains(AccessMask::WRITE_DACL)
+    }
+
+    /// WriteOwner: the owner can be changed.
+    pub fn grants_write_owner(&self) -> bool {
+        self.contains(AccessMask::WRITE_OWNER)
+    }
+
+    /// Write of one property, or of all of them without object type.
+    pub fn grants_write_property(&self) -> bool {
+        self.contains(AccessMask::ADS_RIGHT_DS_WRITE_PROP)
+    }
+
+    /// Read of one property, or of all of them without object type.
+    pub fn grants_read_property(&self) -> bool {
+        self.contains(AccessMask::ADS_RIGHT_DS_READ_PROP)
+    }
+
+    /// Validated writes (AddSelf).
+    pub fn grants_self_write(&self) -> bool {
+        self.contains(AccessMask::ADS_RIGHT_DS_SELF)
+    }
+
+    /// Extended rights.
+    pub fn grants_control_access(&self) -> bool {
+        self.contains(AccessMask::ADS_RIGHT_DS_CONTROL_ACCESS)
+    }
+
+    /// GenericAll, GenericWrite, WriteDacl or WriteOwner: the object can be taken over.
+    pub fn grants_object_control(&self) -> bool {
+        self.grants_generic_all() || self.grants_generic_write() || self.grants_write_dacl() || self.grants_write_owner()
+    }
+}
+
 bitflags! {
     struct SecurityDescriptorFlags: u16 {
         const SELF_RELATIVE = 0b1000000000000000;
@@ -642,7 +927,7 @@ bitflags! {
 }
 
 fn has_control(secdesc_control: u16, flag: SecurityDescriptorFlags) -> bool {
-    let flags = SecurityDescriptorFlags::from_bits(secdesc_control).unwrap();
+    let flags = SecurityDescriptorFlags::from_bits_truncate(secdesc_control);
     flags.contains(flag)
 }
 
@@ -14812,4 +15097,144 @@ lazy_static! {
         );
         map
     };
-}
\ No newline at end of file
+}
+/// Test functions
+#[test]
+#[rustfmt::skip]
+pub fn test_malformed_secdesc() {
+    let mut valjson = serde_json::json!({"Properties": {"name": "JON.SNOW@ESSOS.LOCAL", "distinguishedname": "CN=JON SNOW,CN=USERS,DC=ESSOS,DC=LOCAL"}});
+    let domain = "ESSOS.LOCAL".to_string();
+    let parse = |valjson: &mut serde_json::value::Value, nt: Vec<u8>| {
+        parse_ntsecuritydescriptor(valjson, &nt, "User".to_string(), &HashMap::new(), &HashMap::new(), &domain)
+    };
+    // Truncated header
+    assert!(parse(&mut valjson, vec![0x01, 0x00, 0x04, 0x8c]).is_empty());
+    // Owner and DACL offsets out of the descriptor
+    let header = vec![0x01, 0x00, 0x04, 0x8c, 0xff, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0xff, 0xff, 0xff, 0xff];
+    assert!(parse(&mut valjson, header.to_owned()).is_empty());
+    // DACL with an ace_size lower than the ACE header
+    let mut nt = header;
+    nt[4] = 0;
+    nt[16] = 20;
+    nt[17..20].copy_from_slice(&[0x00, 0x00, 0x00]);
+    nt.extend_from_slice(&[0x04, 0x00, 0x10, 0x00, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x02, 0x00, 0x00, 0x00, 0x00, 0x00]);
+    assert!(parse(&mut valjson, nt).is_empty());
+    assert!(valjson["IsACLProtected"].is_null());
+}
+
+#[test]
+pub fn test_access_mask() {
+    // GenericAll
+    let mask = AccessMask::from_bits_truncate(0x000F01FF);
+    assert!(mask.grants_generic_all() && mask.grants_generic_write() && mask.grants_object_control());
+    // GenericWrite with an unknown bit
+    let mask = AccessMask::from_bits_truncate(0x00020028 | 0x00400000);
+    assert!(mask.grants_generic_write() && mask.grants_write_property());
+    assert!(!mask.grants_generic_all() && !mask.grants_write_dacl());
+    // ReadProperty and ExtendedRight only
+    let mask = AccessMask::from_bits_truncate(0x00000110);
+    assert!(mask.grants_read_property() && mask.grants_control_access());
+    assert!(!mask.grants_object_control() && !mask.grants_self_write());
+    assert!(AccessMask::from_bits_truncate(0x00080000).grants_object_control());
+}
+
+/// Function to build an allowed ACE of the test descriptors: an object ACE (0x05) with the object type
+/// and the inherited object type GUIDs when one is given, else an ACE (0x00).
+#[cfg(test)]
+fn test_ace(flags: u8, mask: u32, object_type: Option<&str>, inherited_object_type: Option<&str>, sid: &str) -> Vec<u8> {
+    use crate::modules::offline::{sid_to_bytes, guid_to_bytes};
+
+    let mut body = mask.to_le_bytes().to_vec();
+    let object = object_type.is_some() || inherited_object_type.is_some();
+    if object {
+        let object_flags = object_type.map_or(0, |_| ACE_OBJECT_TYPE_PRESENT) | inherited_object_type.map_or(0, |_| ACE_INHERITED_OBJECT_TYPE_PRESENT);
+        body.extend(object_flags.to_le_bytes());
+        body.extend(object_type.into_iter().chain(inherited_object_type).flat_map(|guid| guid_to_bytes(guid).unwrap()));
+    }
+    body.extend(sid_to_bytes(sid).unwrap());
+    let mut ace = vec![if object { 0x05 } else { 0x00 }, flags, (body.len() + 4) as u8, 0x00];
+    ace.extend(body);
+    ace
+}
+
+/// Function to build a self-relative test descriptor with only a DACL of the ACEs.
+#[cfg(test)]
+fn test_descriptor(aces: &[Vec<u8>]) -> Vec<u8> {
+    let aces = aces.concat();
+    let mut nt = vec![0x01, 0x00, 0x04, 0x80, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x14, 0x00, 0x00, 0x00];
+    nt.extend([0x04, 0x00, (aces.len() + ACL_HEADER_SIZE) as u8, 0x00, 0x03, 0x00, 0x00, 0x00]);
+    nt.extend(aces);
+    nt
+}
+
+#[test]
+pub fn test_rbcd_edges() {
+    // Object ACE (0x05) with WriteProperty on one attribute or property set, or ACE (0x00) with WriteProperty on all
+    let nt = test_descriptor(&[
+        test_ace(0x00, 0x20, Some(ALLOWED_TO_ACT), None, "S-1-5-21-1-2-3-1104"),
+        test_ace(0x00, 0x20, Some(USER_ACCOUNT_RESTRICTIONS_SET), None, "S-1-5-21-1-2-3-1105"),
+        test_ace(0x00, 0x20, None, None, "S-1-5-21-1-2-3-1106"),
+    ]);
+
+    let mut valjson = serde_json::json!({"Properties": {"name": "MEEREEN.ESSOS.LOCAL", "distinguishedname": "CN=MEEREEN,OU=DOMAIN CONTROLLERS,DC=ESSOS,DC=LOCAL", "haslaps": false}});
+    let relations = parse_ntsecuritydescriptor(&mut valjson, &nt, "computer".to_string(), &HashMap::new(), &HashMap::new(), &"ESSOS.LOCAL".to_string());
+    let edges: Vec<(&str, &str)> = relations.iter()
+        .map(|relation| (relation["PrincipalSID"].as_str().unwrap(), relation["RightName"].as_str().unwrap()))
+        .collect();
+    assert_eq!(edges, vec![
+        ("S-1-5-21-1-2-3-1104", "AddAllowedToAct"),
+        ("S-1-5-21-1-2-3-1105", "WriteAccountRestrictions"),
+        ("S-1-5-21-1-2-3-1106", "GenericWrite"),
+        ("S-1-5-21-1-2-3-1106", "AddAllowedToAct"),
+        ("S-1-5-21-1-2-3-1106", "WriteAccountRestrictions"),
+    ]);
+}
+
+#[test]
+pub fn test_ace_inheritance() {
+    // GenericAll object ACEs (0x05) inherited for one class of child objects only,
+    // and a GenericAll ACE (0x00) only propagated to the child objects
+    let nt = test_descriptor(&[
+        test_ace(INHERITED_ACE, 0x000F01FF, None, OBJECTTYPE_GUID_HASHMAP.get("user").map(String::as_str), "S-1-5-21-1-2-3-1104"),
+        test_ace(INHERITED_ACE, 0x000F01FF, None, OBJECTTYPE_GUID_HASHMAP.get("group").map(String::as_str), "S-1-5-21-1-2-3-1105"),
+        test_ace(INHERIT_ONLY_ACE | 0x02, 0x000F01FF, None, None, "S-1-5-21-1-2-3-1106"),
+    ]);
+
+    let edges = |entry_type: &str| -> Vec<(String, String, bool)> {
+        let mut valjson = serde_json::json!({"Properties": {"name": "X", "distinguishedname": "CN=X,DC=ESSOS,DC=LOCAL", "haslaps": false}});
+        parse_ntsecuritydescriptor(&mut valjson, &nt, entry_type.to_string(), &HashMap::new(), &HashMap::new(), &"ESSOS.LOCAL".to_string())
+            .iter().map(|relation| (
+                relation["PrincipalSID"].as_str().unwrap().to_string(),
+                relation["RightName"].as_str().unwrap().to_string(),
+                relation["IsInherited"].as_bool().unwrap(),
+            )).collect()
+    };
+    // The user class ACE reaches a computer through the objectClass chain, the group class ACE
+    // and the inherit-only ACE give nothing
+    let generic_all = vec![("S-1-5-21-1-2-3-1104".to_string(), "GenericAll".to_string(), true)];
+    assert_eq!(edges("computer"), generic_all);
+    assert_eq!(edges("user"), generic_all);
+    assert_eq!(edges("group"), vec![("S-1-5-21-1-2-3-1105".to_string(), "GenericAll".to_string(), true)]);
+    assert!(edges("ou").is_empty());
+}
+
+#[test]
+pub fn test_extended_right_edges() {
+    // Object ACEs (0x05) with ControlAccess on one extended right
+    let nt = test_descriptor(&[
+        test_ace(0x00, 0x100, Some(USER_FORCE_CHANGE_PASSWORD), None, "S-1-5-21-1-2-3-1104"),
+        test_ace(0x00, 0x100, Some(GET_CHANGES_ALL), None, "S-1-5-21-1-2-3-1105"),
+        test_ace(0x00, 0x100, Some(GET_CHANGES_IN_FILTERED_SET), None, "S-1-5-21-1-2-3-1106"),
+    ]);
+
+    let edges = |entry_type: &str| -> Vec<String> {
+        let mut valjson = serde_json::json!({"Properties": {"name": "X", "distinguishedname": "CN=X,DC=ESSOS,DC=LOCAL", "haslaps": false}});
+        parse_ntsecuritydescriptor(&mut valjson, &nt, entry_type.to_string(), &HashMap::new(), &HashMap::new(), &"ESSOS.LOCAL".to_string())
+            .iter().map(|relation| relation["RightName"].as_str().unwrap().to_string()).collect()
+    };
+    assert_eq!(edges("user"), vec!["ForceChangePassword"]);
+    assert_eq!(edges("domain"), vec!["GetChangesAll", "GetChangesInFilteredSet"]);
+    assert!(edges("ou").is_empty());
+    assert!(edges("computer").is_empty());
+    assert!(edges("group").is_empty());
+}
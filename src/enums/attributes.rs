@@ -0,0 +1,237 @@
+//! LDAP attributes requested by RustHound and their availability per schema version
+//!
+//! The schema objectVersion tells which Windows Server schema the forest runs, attributes
+//! newer than this version are not requested. Attributes added by a schema extension
+//! (legacy and Windows LAPS) are only requested if present in the collected schema.
+//! <https://learn.microsoft.com/en-us/windows-server/identity/ad-ds/deploy/find-active-directory-schema>
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use crate::enums::schema::{get_schema_guid, schema_loaded};
+
+/// Schema objectVersion per Windows Server release.
+pub const SCHEMA_VERSION_2000: u32 = 13;
+pub const SCHEMA_VERSION_2003: u32 = 30;
+pub const SCHEMA_VERSION_2008: u32 = 44;
+pub const SCHEMA_VERSION_2008_R2: u32 = 47;
+pub const SCHEMA_VERSION_2012: u32 = 56;
+pub const SCHEMA_VERSION_2012_R2: u32 = 69;
+pub const SCHEMA_VERSION_2016: u32 = 87;
+
+/// One LDAP attribute requested to the DC.
+pub struct LdapAttribute {
+    /// lDAPDisplayName
+    pub name: &'static str,
+    /// First schema objectVersion with this attribute
+    pub min_schema_version: u32,
+    /// cn of the attribute if it comes from a schema extension
+    pub extension_cn: Option<&'static str>,
+}
+
+const fn core(name: &'static str) -> LdapAttribute {
+    LdapAttribute { name: name, min_schema_version: SCHEMA_VERSION_2000, extension_cn: None }
+}
+
+const fn since(name: &'static str, min_schema_version: u32) -> LdapAttribute {
+    LdapAttribute { name: name, min_schema_version: min_schema_version, extension_cn: None }
+}
+
+const fn extension(name: &'static str, cn: &'static str) -> LdapAttribute {
+    LdapAttribute { name: name, min_schema_version: SCHEMA_VERSION_2000, extension_cn: Some(cn) }
+}
+
+/// All attributes needed by the parser.
+pub const LDAP_ATTRIBUTES: &[LdapAttribute] = &[
+    core("objectClass"),
+    core("cn"),
+    core("name"),
+    core("distinguishedName"),
+    core("objectGUID"),
+    core("uSNChanged"),
+    core("objectSid"),
+    core("sIDHistory"),
+    core("sAMAccountName"),
+    core("displayName"),
+    core("description"),
+    core("title"),
+    core("mail"),
+    core("userPrincipalName"),
+    core("altSecurityIdentities"),
+    core("homeDirectory"),
+    core("scriptPath"),
+    core("userAccountControl"),
+    core("primaryGroupID"),
+    core("adminCount"),
+    core("pwdLastSet"),
+    core("lastLogon"),
+    core("lastLogonTimestamp"),
+    core("whenCreated"),
+    core("isDeleted"),
+    core("isCriticalSystemObject"),
+    core("servicePrincipalName"),
+    core("member"),
+    core("userPassword"),
+    core("unixUserPassword"),
+    core("unicodePwd"),
+    core("msSFU30Password"),
+    core("dNSHostName"),
+    core("operatingSystem"),
+    core("operatingSystemServicePack"),
+    core("gPLink"),
+    core("gPOptions"),
+    core("gPCFileSysPath"),
+    core("ms-DS-MachineAccountQuota"),
+    core("minPwdLength"),
+    core("pwdHistoryLength"),
+    core("pwdProperties"),
+    core("maxPwdAge"),
+    core("minPwdAge"),
+    core("lockoutThreshold"),
+    core("lockoutDuration"),
+    core("lockOutObservationWindow"),
+    core("trustPartner"),
+    core("trustDirection"),
+    core("trustType"),
+    core("trustAttributes"),
+    core("flatName"),
+    core("securityIdentifier"),
+    core("nTSecurityDescriptor"),
+    core("fSMORoleOwner"),
+    core("managedBy"),
+    since("msDS-Behavior-Version", SCHEMA_VERSION_2003),
+    since("msDS-AllowedToDelegateTo", SCHEMA_VERSION_2003),
+    since("msDS-SupportedEncryptionTypes", SCHEMA_VERSION_2008),
+    since("msDS-PSOAppliesTo", SCHEMA_VERSION_2008),
+    since("msDS-PasswordSettingsPrecedence", SCHEMA_VERSION_2008),
+    since("msDS-MinimumPasswordLength", SCHEMA_VERSION_2008),
+    since("msDS-PasswordHistoryLength", SCHEMA_VERSION_2008),
+    since("msDS-PasswordComplexityEnabled", SCHEMA_VERSION_2008),
+    since("msDS-PasswordReversibleEncryptionEnabled", SCHEMA_VERSION_2008),
+    since("msDS-MaximumPasswordAge", SCHEMA_VERSION_2008),
+    since("msDS-MinimumPasswordAge", SCHEMA_VERSION_2008),
+    since("msDS-LockoutThreshold", SCHEMA_VERSION_2008),
+    since("msDS-LockoutDuration", SCHEMA_VERSION_2008),
+    since("msDS-LockoutObservationWindow", SCHEMA_VERSION_2008),
+    since("msDS-RevealedUsers", SCHEMA_VERSION_2008),
+    since("msDS-RevealOnDemandGroup", SCHEMA_VERSION_2008),
+    since("msDS-NeverRevealGroup", SCHEMA_VERSION_2008),
+    since("msDS-KrbTgtLink", SCHEMA_VERSION_2008),
+    since("msDS-HostServiceAccount", SCHEMA_VERSION_2008_R2),
+    since("msDS-HostServiceAccountBL", SCHEMA_VERSION_2008_R2),
+    since("msDS-AllowedToActOnBehalfOfOtherIdentity", SCHEMA_VERSION_2012),
+    since("msDS-GroupMSAMembership", SCHEMA_VERSION_2012),
+    since("msDS-DeviceID", SCHEMA_VERSION_2012_R2),
+    since("msDS-KeyCredentialLink", SCHEMA_VERSION_2016),
+    extension("ms-Mcs-AdmPwd", "ms-mcs-admpwd"),
+    extension("ms-Mcs-AdmPwdExpirationTime", "ms-mcs-admpwdexpirationtime"),
+    extension("msLAPS-Password", "ms-laps-password"),
+    extension("msLAPS-EncryptedPassword", "ms-laps-encryptedpassword"),
+    extension("msLAPS-PasswordExpirationTime", "ms-laps-passwordexpirationtime"),
+    extension("mSSMSSiteCode", "ms-sms-site-code"),
+    extension("mSSMSMPName", "ms-sms-mp-name"),
+];
+
+/// Attributes of the ACL-only collection (--acl-only): the type and identity of the objects, the primary group and
+/// the security descriptor.
+pub const ACL_ONLY_ATTRIBUTES: &[&str] = &[
+    "objectClass",
+    "objectSid",
+    "objectGUID",
+    "distinguishedName",
+    "sAMAccountName",
+    "name",
+    "primaryGroupID",
+    "nTSecurityDescriptor",
+];
+
+/// Attributes harvested in the user, group and computer properties with --object-props extended: (attribute, property).
+/// Free text fields which often hold passwords or targeting data (phone, manager, team).
+pub const EXTENDED_ATTRIBUTES: &[(&str, &str)] = &[
+    ("description", "description"),
+    ("mail", "email"),
+    ("title", "title"),
+    ("department", "department"),
+    ("manager", "manager"),
+    ("telephoneNumber", "telephonenumber"),
+    ("homeDirectory", "homedirectory"),
+    ("scriptPath", "logonscript"),
+    ("profilePath", "profilepath"),
+    ("info", "info"),
+];
+
+/// Harvest the EXTENDED_ATTRIBUTES (--object-props extended).
+static EXTENDED_PROPERTIES: AtomicBool = AtomicBool::new(false);
+
+/// Function to select the object properties mode: "default" or "extended".
+pub fn set_object_props(mode: &str) {
+    EXTENDED_PROPERTIES.store(mode == "extended", Ordering::Relaxed);
+}
+
+/// Function to know if the EXTENDED_ATTRIBUTES are harvested.
+pub fn extended_properties() -> bool {
+    EXTENDED_PROPERTIES.load(Ordering::Relaxed)
+}
+
+/// Function to get the EXTENDED_ATTRIBUTES not requested by default.
+pub fn extended_attributes() -> Vec<&'static str> {
+    EXTENDED_ATTRIBUTES.iter()
+        .map(|(name, _)| *name)
+        .filter(|name| !LDAP_ATTRIBUTES.iter().any(|attribute| attribute.name == *name))
+        .collect()
+}
+
+/// Function to set the EXTENDED_ATTRIBUTES of one object in its properties, the attribute names are case insensitive.
+pub fn add_extended_properties(properties: &mut serde_json::value::Value, result_attrs: &HashMap<String, Vec<String>>) {
+    for (name, values) in result_attrs {
+        if let Some((_, property)) = EXTENDED_ATTRIBUTES.iter().find(|(attribute, _)| attribute.eq_ignore_ascii_case(name)) {
+            if let Some(value) = values.first() {
+                properties[*property] = value.to_owned().into();
+            }
+        }
+    }
+}
+
+/// Function to get the attributes to request for one schema objectVersion.
+pub fn negotiate_attributes(schema_version: u32) -> Vec<&'static str> {
+    LDAP_ATTRIBUTES.iter()
+        .filter(|attribute| attribute.min_schema_version <= schema_version)
+        .filter(|attribute| match attribute.extension_cn {
+            Some(cn) => !schema_loaded() || get_schema_guid(cn).is_some(),
+            None => true,
+        })
+        .map(|attribute| attribute.name)
+        .collect()
+}
+
+/// Test functions
+#[test]
+pub fn test_negotiate_attributes() {
+    let attributes_2008 = negotiate_attributes(SCHEMA_VERSION_2008);
+    assert!(attributes_2008.contains(&"msDS-SupportedEncryptionTypes"));
+    assert!(!attributes_2008.contains(&"msDS-AllowedToActOnBehalfOfOtherIdentity"));
+    assert!(!attributes_2008.contains(&"msDS-KeyCredentialLink"));
+
+    let attributes_2016 = negotiate_attributes(SCHEMA_VERSION_2016);
+    assert!(attributes_2016.contains(&"msDS-KeyCredentialLink"));
+    assert!(attributes_2016.contains(&"nTSecurityDescriptor"));
+    assert_eq!(negotiate_attributes(u32::MAX).len(), LDAP_ATTRIBUTES.len());
+}
+
+#[test]
+pub fn test_extended_properties() {
+    assert_eq!(extended_attributes(), vec!["department", "manager", "telephoneNumber", "profilePath", "info"]);
+    let result_attrs = HashMap::from([
+        ("scriptPath".to_string(), vec!["logon.bat".to_string()]),
+        ("info".to_string(), vec!["Temporary password: Summer2023!".to_string()]),
+        ("telephonenumber".to_string(), vec!["+33 1 02 03 04 05".to_string()]),
+        ("sAMAccountName".to_string(), vec!["jon.snow".to_string()]),
+    ]);
+    let mut properties = serde_json::json!({"name": "JON.SNOW@ESSOS.LOCAL", "logonscript": null});
+    add_extended_properties(&mut properties, &result_attrs);
+    assert_eq!(properties, serde_json::json!({
+        "name": "JON.SNOW@ESSOS.LOCAL",
+        "logonscript": "logon.bat",
+        "info": "Temporary password: Summer2023!",
+        "telephonenumber": "+33 1 02 03 04 05",
+    }));
+}
@@ -0,0 +1,56 @@
+//! Explicit certificate mappings of altSecurityIdentities
+//! <https://learn.microsoft.com/en-us/entra/identity/authentication/concept-certificate-based-authentication-certificateuserids>
+//! <https://support.microsoft.com/en-us/topic/kb5014754-certificate-based-authentication-changes-on-windows-domain-controllers-ad2c23b0-15d8-4340-a468-4d4f3b188f16>
+//!
+//! Since KB5014754 only the strong mappings (issuer and serial number, SKI, SHA1 of the public key) are
+//! accepted in Full Enforcement mode. A weak mapping (subject, issuer and subject, RFC822) can be matched by a
+//! certificate with the same subject or email issued to another principal (ESC14).
+
+/// Mapping types of altSecurityIdentities: (tag, name, strong).
+const MAPPING_TYPES: [(&str, &str, bool); 6] = [
+    ("<I>", "X509IssuerSubject", false),
+    ("<S>", "X509SubjectOnly", false),
+    ("<RFC822>", "X509RFC822", false),
+    ("<SKI>", "X509SKI", true),
+    ("<SHA1-PUKEY>", "X509SHA1PublicKey", true),
+    ("<SR>", "X509IssuerSerialNumber", true),
+];
+
+/// Function to get the type of one altSecurityIdentities value and if it is a strong mapping.
+/// The Kerberos principal mappings (Kerberos:user@REALM) are not certificate mappings.
+pub fn mapping_type(value: &str) -> Option<(&'static str, bool)> {
+    let upper = value.trim().to_uppercase();
+    let mapping = upper.strip_prefix("X509:")?;
+    // <I>issuer<SR>serial is the only mapping with two tags ending by a strong one
+    if mapping.starts_with("<I>") && mapping.contains("<SR>") {
+        return Some(("X509IssuerSerialNumber", true))
+    }
+    MAPPING_TYPES.iter()
+        .find(|(tag, _, _)| mapping.starts_with(tag))
+        .map(|(_, name, strong)| (*name, *strong))
+}
+
+/// Function to set the certificate mapping properties from the altSecurityIdentities values.
+pub fn set_certificate_mappings(properties: &mut serde_json::value::Value, values: &[String]) {
+    let types: Vec<(&str, bool)> = values.iter().filter_map(|value| mapping_type(value)).collect();
+    properties["altsecurityidentities"] = values.to_vec().into();
+    properties["certificatemappingtypes"] = types.iter().map(|(name, _)| name.to_string()).collect::<Vec<String>>().into();
+    properties["strongcertificatemapping"] = types.iter().any(|(_, strong)| *strong).into();
+    properties["weakcertificatemapping"] = types.iter().any(|(_, strong)| !*strong).into();
+}
+
+/// Test functions
+#[test]
+pub fn test_certificate_mappings() {
+    assert_eq!(mapping_type("X509:<I>DC=local,DC=essos,CN=ESSOS-CA<S>CN=Jon Snow"), Some(("X509IssuerSubject", false)));
+    assert_eq!(mapping_type("X509:<I>DC=local,DC=essos,CN=ESSOS-CA<SR>2200000006ba0d3c"), Some(("X509IssuerSerialNumber", true)));
+    assert_eq!(mapping_type("x509:<RFC822>jon.snow@essos.local"), Some(("X509RFC822", false)));
+    assert_eq!(mapping_type("X509:<SHA1-PUKEY>7b1c4f0c2b3f4e2d9a1e"), Some(("X509SHA1PublicKey", true)));
+    assert_eq!(mapping_type("Kerberos:jon@MIT.ESSOS.LOCAL"), None);
+
+    let mut properties = serde_json::json!({});
+    set_certificate_mappings(&mut properties, &["X509:<S>CN=Jon Snow".to_string(), "X509:<SKI>0f3a2b".to_string()]);
+    assert_eq!(properties["certificatemappingtypes"], serde_json::json!(["X509SubjectOnly", "X509SKI"]));
+    assert_eq!(properties["strongcertificatemapping"], true);
+    assert_eq!(properties["weakcertificatemapping"], true);
+}
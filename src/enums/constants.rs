@@ -21,4 +21,8 @@ pub const GET_CHANGES_IN_FILTERED_SET: &str = "89e95b76-444d-4c62-991a-0facbeda6
 pub const WRITE_MEMBER: &str = "bf9679c0-0de6-11d0-a285-00aa003049e2";
 pub const USER_FORCE_CHANGE_PASSWORD: &str = "00299570-246d-11d0-a768-00aa006e0529";
 pub const ALLOWED_TO_ACT: &str = "3f78c3e5-f79a-46bd-a0b8-9d18116ddc79";
-pub const USER_ACCOUNT_RESTRICTIONS_SET: &str = "4c164200-20c0-11d0-a768-00aa006e0529";
\ No newline at end of file
+pub const USER_ACCOUNT_RESTRICTIONS_SET: &str = "4c164200-20c0-11d0-a768-00aa006e0529";
+// WELL-KNOWN SIDS
+pub const OWNER_RIGHTS_SID: &str = "S-1-3-4";
+pub const EVERYONE_SID: &str = "S-1-1-0";
+pub const AUTHENTICATED_USERS_SID: &str = "S-1-5-11";
@@ -1,21 +1,109 @@
-use chrono::{NaiveDateTime, Local};
+//! Conversion of the Active Directory times
+//!
+//! BloodHound expects the times in epoch seconds, Active Directory writes them in three formats:
+//! - FILETIME: 100 nanoseconds since 1601-01-01 (lastLogon, pwdLastSet, accountExpires...), 0 and 0x7FFFFFFFFFFFFFFF
+//!   mean never,
+//! - GeneralizedTime: "yyyyMMddHHmmss.0Z" (whenCreated, whenChanged, currentTime of the RootDSE),
+//! - intervals: negative 100 nanoseconds (maxPwdAge, lockoutDuration...), 0x8000000000000000 means never.
+//!
+//! With --object-props extended, every epoch property is also written in ISO 8601 (`whencreated_iso`).
+use chrono::{DateTime, Local, NaiveDateTime, SecondsFormat, Utc};
+use std::collections::HashMap;
 //use log::trace;
 
+/// Seconds between 1601-01-01 (FILETIME) and 1970-01-01 (epoch).
+pub const FILETIME_EPOCH_OFFSET_S: i64 = 11_644_473_600;
+/// FILETIME ticks of 100 nanoseconds in one second.
+pub const FILETIME_TICKS_PER_S: i64 = 10_000_000;
+/// Epoch properties written in ISO 8601 too with --object-props extended.
+pub const TIMESTAMP_PROPERTIES: [&str; 9] = [
+    "whencreated",
+    "whenchanged",
+    "whendeleted",
+    "lastlogon",
+    "lastlogontimestamp",
+    "pwdlastset",
+    "ntsecuritydescriptorlastchanged",
+    "memberlastchanged",
+    "serviceprincipalnamelastchanged",
+];
+/// GeneralizedTime attributes harvested as epoch properties with --object-props extended: (attribute, property).
+pub const EXTENDED_TIME_ATTRIBUTES: [(&str, &str); 1] = [
+    ("whenChanged", "whenchanged"),
+];
+
 /// Change date timestamp format to epoch format.
 pub fn convert_timestamp(timestamp: i64) -> i64
 {
-    let offset: i64 = 134774*24*60*60;
-    let epoch: i64 = timestamp/10000000-offset;
-    return epoch
+    timestamp / FILETIME_TICKS_PER_S - FILETIME_EPOCH_OFFSET_S
+}
+
+/// Function to convert a FILETIME attribute value to epoch, None for never (0 or 0x7FFFFFFFFFFFFFFF) and invalid values.
+pub fn filetime_to_epoch(value: &str) -> Option<i64> {
+    value.trim().parse::<i64>().ok()
+        .filter(|filetime| filetime.is_positive() && *filetime != i64::MAX)
+        .map(convert_timestamp)
+}
+
+/// Function to convert an epoch to FILETIME.
+pub fn epoch_to_filetime(epoch: i64) -> i64 {
+    (epoch + FILETIME_EPOCH_OFFSET_S) * FILETIME_TICKS_PER_S
+}
+
+/// Function to parse a GeneralizedTime value "yyyyMMddHHmmss.0Z", the fraction is ignored.
+pub fn parse_generalized_time(value: &str) -> Option<DateTime<Utc>> {
+    let seconds = value.split(|c| c == '.' || c == 'Z').next()?;
+    let time = NaiveDateTime::parse_from_str(seconds, "%Y%m%d%H%M%S").ok()?;
+    Some(DateTime::from_naive_utc_and_offset(time, Utc))
 }
 
+/// Function to convert a GeneralizedTime value to epoch.
+pub fn generalized_time_to_epoch(value: &str) -> Option<i64> {
+    parse_generalized_time(value).map(|time| time.timestamp())
+}
+
+/// GeneralizedTime "yyyyMMddHHmmss.0z" to epoch format, 0 for an invalid value.
 pub fn string_to_epoch(date: &String) -> i64 {
-    // yyyyMMddHHmmss.0z to epoch format
-    let split = date.split(".");
-    let vec = split.collect::<Vec<&str>>();
-    let date = NaiveDateTime::parse_from_str(&vec[0],"%Y%m%d%H%M%S").unwrap();
-    //trace!("whencreated timestamp: {:?}", date.timestamp());
-    return date.timestamp()
+    generalized_time_to_epoch(date).unwrap_or(0)
+}
+
+/// Function to convert an ISO 8601 time ("2023-10-01T12:00:00Z") to epoch.
+pub fn iso_to_epoch(value: &str) -> Option<i64> {
+    DateTime::parse_from_rfc3339(value.trim()).ok().map(|time| time.timestamp())
+}
+
+/// Function to write an epoch in ISO 8601, None for the epochs of never (0 and below).
+pub fn epoch_to_iso(epoch: i64) -> Option<String> {
+    DateTime::from_timestamp(epoch, 0)
+        .filter(|_| epoch.is_positive())
+        .map(|time| time.to_rfc3339_opts(SecondsFormat::Secs, true))
+}
+
+/// Function to convert an interval attribute value (negative 100 nanoseconds) in seconds, None for never.
+pub fn interval_to_seconds(interval: &str) -> Option<i64> {
+    match interval.trim().parse::<i64>() {
+        Ok(i64::MIN) | Err(_) => None,
+        Ok(value) => Some(value.abs() / FILETIME_TICKS_PER_S),
+    }
+}
+
+/// Function to convert an interval attribute value in minutes, -1 means never.
+pub fn interval_to_minutes(interval: &str) -> i64 {
+    interval_to_seconds(interval).map(|seconds| seconds / 60).unwrap_or(-1)
+}
+
+/// Function to set the EXTENDED_TIME_ATTRIBUTES in epoch and the TIMESTAMP_PROPERTIES in ISO 8601 (--object-props extended).
+pub fn add_iso_timestamps(properties: &mut serde_json::value::Value, result_attrs: &HashMap<String, Vec<String>>) {
+    for (attribute, property) in EXTENDED_TIME_ATTRIBUTES {
+        if let Some(epoch) = result_attrs.get(attribute).and_then(|values| values.first()).and_then(|value| generalized_time_to_epoch(value)) {
+            properties[property] = epoch.into();
+        }
+    }
+    for property in TIMESTAMP_PROPERTIES {
+        if let Some(iso) = properties.get(property).and_then(|epoch| epoch.as_i64()).and_then(epoch_to_iso) {
+            properties[format!("{}_iso", property)] = iso.into();
+        }
+    }
 }
 
 /// Function to return current hours.
@@ -37,4 +125,43 @@ pub fn return_current_fulldate() -> String
 {
     let now = Local::now();
     return now.to_string()
-}
\ No newline at end of file
+}
+
+/// Test functions
+#[test]
+pub fn test_convert_times() {
+    // FILETIME
+    assert_eq!(filetime_to_epoch("133406208000000000"), Some(1696147200));
+    assert_eq!(filetime_to_epoch("0"), None);
+    assert_eq!(filetime_to_epoch("9223372036854775807"), None);
+    assert_eq!(filetime_to_epoch("never"), None);
+    assert_eq!(epoch_to_filetime(1696147200), 133406208000000000);
+    assert_eq!(convert_timestamp(116444736000000000), 0);
+
+    // GeneralizedTime
+    let time = parse_generalized_time("20261016123456.0Z").unwrap();
+    assert_eq!(time.to_rfc3339(), "2026-10-16T12:34:56+00:00");
+    assert_eq!(parse_generalized_time("20261016123456Z").unwrap(), time);
+    assert!(parse_generalized_time("not a time").is_none());
+    assert_eq!(string_to_epoch(&"20231001120000.0Z".to_string()), 1696161600);
+    assert_eq!(string_to_epoch(&"".to_string()), 0);
+
+    // ISO 8601
+    assert_eq!(iso_to_epoch("2023-10-01T12:00:00Z"), Some(1696161600));
+    assert_eq!(epoch_to_iso(1696161600).as_deref(), Some("2023-10-01T12:00:00Z"));
+    assert_eq!(epoch_to_iso(-1), None);
+
+    // Intervals
+    assert_eq!(interval_to_seconds("-18000000000"), Some(1800));
+    assert_eq!(interval_to_minutes("-36288000000000"), 42 * 24 * 60);
+    assert_eq!(interval_to_minutes(&i64::MIN.to_string()), -1);
+
+    let result_attrs = HashMap::from([("whenChanged".to_string(), vec!["20231002083000.0Z".to_string()])]);
+    let mut properties = serde_json::json!({"whencreated": 1696161600, "lastlogon": -1, "pwdlastset": 0});
+    add_iso_timestamps(&mut properties, &result_attrs);
+    assert_eq!(properties["whencreated_iso"], "2023-10-01T12:00:00Z");
+    assert_eq!(properties["whenchanged"], 1696235400);
+    assert_eq!(properties["whenchanged_iso"], "2023-10-02T08:30:00Z");
+    assert!(properties.get("lastlogon_iso").is_none());
+    assert!(properties.get("pwdlastset_iso").is_none());
+}
@@ -0,0 +1,119 @@
+//! Resilient decoding of the attribute values
+//!
+//! The DC converts the UTF-16 strings of the directory to UTF-8, an unpaired surrogate or a value written as raw
+//! bytes (userParameters, some userPassword) is not valid UTF-8 and ldap3 moves the whole attribute in `bin_attrs`,
+//! where the string parsers never see it. Before parsing, the attributes of `bin_attrs` that are not binary by
+//! definition are decoded back in `attrs`:
+//! - UTF-16LE looking values (every other byte null) are decoded as UTF-16, the others as UTF-8,
+//! - the invalid sequences are replaced by U+FFFD, the trailing nulls removed and the embedded nulls replaced,
+//! - a value still made of control characters is written in hex (`0x...`).
+use ldap3::SearchEntry;
+use log::trace;
+
+/// Attributes with a binary syntax, kept in bin_attrs for their parsers.
+pub const BINARY_ATTRIBUTES: [&str; 27] = [
+    "objectSid",
+    "objectGUID",
+    "nTSecurityDescriptor",
+    "sIDHistory",
+    "securityIdentifier",
+    "schemaIDGUID",
+    "attributeSecurityGUID",
+    "msDS-AllowedToActOnBehalfOfOtherIdentity",
+    "msDS-GroupMSAMembership",
+    "msDS-ManagedPassword",
+    "msDS-ManagedPasswordId",
+    "msDS-ManagedPasswordPreviousId",
+    "msDS-GenerationId",
+    "msLAPS-EncryptedPassword",
+    "msLAPS-EncryptedPasswordHistory",
+    "msLAPS-EncryptedDSRMPassword",
+    "msLAPS-EncryptedDSRMPasswordHistory",
+    "mS-DS-ConsistencyGuid",
+    "msExchMailboxGuid",
+    "msExchMailboxSecurityDescriptor",
+    "userCertificate",
+    "cACertificate",
+    "logonHours",
+    "thumbnailPhoto",
+    "jpegPhoto",
+    "dnsRecord",
+    "pKIExpirationPeriod",
+];
+
+/// Function to know if an attribute has a binary syntax.
+pub fn is_binary_attribute(name: &str) -> bool {
+    BINARY_ATTRIBUTES.iter().any(|binary| binary.eq_ignore_ascii_case(name))
+}
+
+/// Function to remove the trailing nulls of a string and replace the embedded ones.
+pub fn clean_nulls(value: &str) -> String {
+    value.trim_end_matches('\0').replace('\0', "\u{FFFD}")
+}
+
+/// Function to know if a value looks like UTF-16LE text: even length and at least half of the high bytes null.
+fn looks_utf16le(value: &[u8]) -> bool {
+    value.len() >= 2 && value.chunks_exact(2).remainder().is_empty() && value.iter().skip(1).step_by(2).filter(|byte| **byte == 0).count() * 4 >= value.len()
+}
+
+/// Function to decode one value lossy, in hex when it doesn't look like text.
+pub fn decode_attribute_value(value: &[u8]) -> String {
+    let text = if looks_utf16le(value) {
+        let units: Vec<u16> = value.chunks_exact(2).map(|unit| u16::from_le_bytes([unit[0], unit[1]])).collect();
+        String::from_utf16_lossy(&units)
+    } else {
+        String::from_utf8_lossy(value).into_owned()
+    };
+    let text = clean_nulls(&text);
+    let unreadable = text.chars().filter(|c| *c == '\u{FFFD}' || (c.is_control() && !matches!(c, '\t' | '\n' | '\r'))).count();
+    if unreadable * 4 > text.chars().count() {
+        format!("0x{}", value.iter().map(|byte| format!("{:02x}", byte)).collect::<String>())
+    } else {
+        text
+    }
+}
+
+/// Function to decode the text attributes of an entry ldap3 left in bin_attrs and clean the nulls of the others.
+pub fn normalize_entry(mut entry: SearchEntry) -> SearchEntry {
+    for values in entry.attrs.values_mut() {
+        for value in values.iter_mut().filter(|value| value.contains('\0')) {
+            *value = clean_nulls(value);
+        }
+    }
+    let names: Vec<String> = entry.bin_attrs.keys().filter(|name| !is_binary_attribute(name)).cloned().collect();
+    for name in names {
+        if let Some(values) = entry.bin_attrs.remove(&name) {
+            trace!("{}: {} is not valid UTF-8, decoded lossy", entry.dn, name);
+            entry.attrs.entry(name).or_default().extend(values.iter().map(|value| decode_attribute_value(value)));
+        }
+    }
+    entry
+}
+
+/// Test functions
+#[test]
+pub fn test_normalize_entry() {
+    use std::collections::HashMap;
+    // CESU-8 encoded unpaired surrogate, as written by the DC for an invalid UTF-16 string
+    assert_eq!(decode_attribute_value(b"Jon \xed\xa0\x80Snow of Winterfell"), "Jon \u{FFFD}\u{FFFD}\u{FFFD}Snow of Winterfell");
+    assert_eq!(decode_attribute_value(b"S\x00n\x00o\x00w\x00\x00\x00"), "Snow");
+    assert_eq!(decode_attribute_value(&[0x4a, 0, 0x6f, 0, 0x6e, 0, 0x00, 0xd8, 0x53, 0, 0x6e, 0, 0x6f, 0, 0x77, 0]), "Jon\u{FFFD}Snow");
+    assert_eq!(decode_attribute_value(&[0x01, 0x02, 0xff, 0x9c, 0x10]), "0x0102ff9c10");
+    assert_eq!(clean_nulls("jon\0snow\0\0"), "jon\u{FFFD}snow");
+
+    let entry = SearchEntry {
+        dn: "CN=JON SNOW,CN=USERS,DC=ESSOS,DC=LOCAL".to_string(),
+        attrs: HashMap::from([("description".to_string(), vec!["Lord\0Commander\0".to_string()])]),
+        bin_attrs: HashMap::from([
+            ("objectSid".to_string(), vec![vec![1, 5, 0, 0, 0, 0, 0, 5, 21, 0, 0, 0]]),
+            ("userParameters".to_string(), vec![vec![0x20, 0x00, 0x50, 0x00, 0x03, 0x00, 0x00, 0x00]]),
+            ("userPassword".to_string(), vec![b"Winter\xffIsComing".to_vec()]),
+        ]),
+    };
+    let entry = normalize_entry(entry);
+    assert_eq!(entry.attrs["description"], vec!["Lord\u{FFFD}Commander"]);
+    assert_eq!(entry.attrs["userPassword"], vec!["Winter\u{FFFD}IsComing"]);
+    assert_eq!(entry.attrs["userParameters"], vec!["0x2000500003000000"]);
+    assert!(entry.bin_attrs.contains_key("objectSid"));
+    assert!(!entry.bin_attrs.contains_key("userPassword"));
+}
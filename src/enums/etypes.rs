@@ -0,0 +1,57 @@
+//! Kerberos encryption types of msDS-SupportedEncryptionTypes
+//! <https://learn.microsoft.com/en-us/openspecs/windows_protocols/ms-kile/6cfc7b50-11ed-4b4d-846d-6f08f0812919>
+//!
+//! Without the attribute the KDC uses its default etypes, RC4 included.
+
+/// DES-CBC-CRC
+pub const ETYPE_DES_CBC_CRC: u32 = 0x1;
+/// DES-CBC-MD5
+pub const ETYPE_DES_CBC_MD5: u32 = 0x2;
+/// RC4-HMAC
+pub const ETYPE_RC4_HMAC: u32 = 0x4;
+/// AES128-CTS-HMAC-SHA1-96
+pub const ETYPE_AES128: u32 = 0x8;
+/// AES256-CTS-HMAC-SHA1-96
+pub const ETYPE_AES256: u32 = 0x10;
+
+const ETYPES: [(u32, &str); 5] = [
+    (ETYPE_DES_CBC_CRC, "DES-CBC-CRC"),
+    (ETYPE_DES_CBC_MD5, "DES-CBC-MD5"),
+    (ETYPE_RC4_HMAC, "RC4"),
+    (ETYPE_AES128, "AES128"),
+    (ETYPE_AES256, "AES256"),
+];
+
+/// Function to get the readable encryption types from msDS-SupportedEncryptionTypes.
+pub fn decode_supported_etypes(value: u32) -> Vec<String> {
+    ETYPES.iter()
+        .filter(|(flag, _)| value & flag != 0)
+        .map(|(_, name)| name.to_string())
+        .collect()
+}
+
+/// Function to set the encryption types properties from the msDS-SupportedEncryptionTypes value.
+pub fn set_supported_etypes(properties: &mut serde_json::value::Value, value: &str) {
+    let etypes = match value.trim().parse::<i64>() {
+        // Stored as a signed 32 bits integer
+        Ok(etypes) => etypes as u32,
+        Err(_) => return,
+    };
+    properties["supportedencryptiontypes"] = decode_supported_etypes(etypes).into();
+    properties["supportsaes"] = (etypes & (ETYPE_AES128 | ETYPE_AES256) != 0).into();
+    properties["desonly"] = (etypes & (ETYPE_DES_CBC_CRC | ETYPE_DES_CBC_MD5) != 0
+        && etypes & (ETYPE_RC4_HMAC | ETYPE_AES128 | ETYPE_AES256) == 0).into();
+}
+
+/// Test functions
+#[test]
+pub fn test_decode_supported_etypes() {
+    assert_eq!(decode_supported_etypes(0x1C), vec!["RC4", "AES128", "AES256"]);
+    assert_eq!(decode_supported_etypes(0x3), vec!["DES-CBC-CRC", "DES-CBC-MD5"]);
+    let mut properties = serde_json::json!({});
+    set_supported_etypes(&mut properties, "4");
+    assert_eq!(properties["supportsaes"], false);
+    assert_eq!(properties["desonly"], false);
+    set_supported_etypes(&mut properties, "3");
+    assert_eq!(properties["desonly"], true);
+}
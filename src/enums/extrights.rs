@@ -0,0 +1,143 @@
+extern crate lazy_static;
+
+use lazy_static::lazy_static;
+use std::collections::HashMap;
+use std::sync::RwLock;
+use ldap3::SearchEntry;
+use log::trace;
+
+/// Function to get the name of one control access right from its GUID (rightsGuid).
+/// Rights collected from CN=Extended-Rights take precedence over the static table
+/// so custom rights defined in the forest are named too.
+pub fn get_extended_right_name(guid: &str) -> Option<String> {
+    let guid = guid.to_lowercase();
+    if let Ok(live) = LIVE_EXTENDED_RIGHTS.read() {
+        if let Some(name) = live.get(&guid) {
+            return Some(name.to_owned())
+        }
+    }
+    EXTENDED_RIGHTS_GUID_HASHMAP.get(guid.as_str()).map(|name| name.to_string())
+}
+
+/// Function to register the controlAccessRight objects from CN=Extended-Rights,CN=Configuration.
+/// Return the number of rights which were not in the static table.
+pub fn add_live_extended_rights(entries: &Vec<SearchEntry>) -> usize {
+    let mut custom = 0;
+    let mut live = match LIVE_EXTENDED_RIGHTS.write() {
+        Ok(live) => live,
+        Err(_) => return 0,
+    };
+    for entry in entries {
+        let guid = match entry.attrs.get("rightsGuid") {
+            Some(guid) => guid[0].to_lowercase(),
+            None => continue,
+        };
+        let name = entry.attrs.get("cn")
+            .or(entry.attrs.get("displayName"))
+            .map(|name| name[0].to_owned())
+            .unwrap_or(guid.to_owned());
+        if !EXTENDED_RIGHTS_GUID_HASHMAP.contains_key(guid.as_str()) {
+            trace!("Custom extended right {}: {}", &name, &guid);
+            custom += 1;
+        }
+        live.insert(guid, name);
+    }
+    custom
+}
+
+lazy_static! {
+    static ref LIVE_EXTENDED_RIGHTS: RwLock<HashMap<String, String>> = RwLock::new(HashMap::new());
+}
+
+// EXTENDED_RIGHTS_GUID_HASHMAP with all know control access rights (rightsGuid => cn)
+// <https://learn.microsoft.com/en-us/windows/win32/adschema/extended-rights>
+lazy_static! {
+    static ref EXTENDED_RIGHTS_GUID_HASHMAP: HashMap<&'static str, &'static str> = {
+        let mut map = HashMap::new();
+        map.insert("ee914b82-0a98-11d1-adbb-00c04fd8d5cd", "Abandon-Replication");
+        map.insert("440820ad-65b4-11d1-a3da-0000f875ae0d", "Add-GUID");
+        map.insert("1abd7cf8-0a99-11d1-adbb-00c04fd8d5cd", "Allocate-Rids");
+        map.insert("68b1d179-0d15-4d4f-ab71-46152e79a7bc", "Allowed-To-Authenticate");
+        map.insert("edacfd8f-ffb3-11d1-b41d-00a0c968f939", "Apply-Group-Policy");
+        map.insert("0e10c968-78fb-11d2-90d4-00c04f79dc55", "Certificate-Enrollment");
+        map.insert("a05b8cc2-17bc-4802-a710-e7c15ab866a2", "Certificate-AutoEnrollment");
+        map.insert("014bf69c-7b3b-11d1-85f6-08002be74fab", "Change-Domain-Master");
+        map.insert("cc17b1fb-33d9-11d2-97d4-00c04fd8d5cd", "Change-Infrastructure-Master");
+        map.insert("bae50096-4752-11d1-9052-00c04fc2d4cf", "Change-PDC");
+        map.insert("d58d5f36-0a98-11d1-adbb-00c04fd8d5cd", "Change-Rid-Master");
+        map.insert("e12b56b6-0a95-11d1-adbb-00c04fd8d5cd", "Change-Schema-Master");
+        map.insert("e2a36dc9-ae17-47c3-b58b-be34c55ba633", "Create-Inbound-Forest-Trust");
+        map.insert("fec364e0-0a98-11d1-adbb-00c04fd8d5cd", "Do-Garbage-Collection");
+        map.insert("ab721a52-1e2f-11d0-9819-00aa0040529b", "Domain-Administer-Server");
+        map.insert("c7407360-20bf-11d0-a768-00aa006e0529", "Domain-Password");
+        map.insert("b8119fd0-04f6-4762-ab7a-4986c76b3f9a", "Domain-Other-Parameters");
+        map.insert("69ae6200-7f46-11d2-b9ad-00c04f79f805", "DS-Check-Stale-Phantoms");
+        map.insert("3e0f7e18-2c7a-4c10-ba82-4d926db99a3e", "DS-Clone-Domain-Controller");
+        map.insert("2f16c4a5-b98e-432c-952a-cb388ba33f2e", "DS-Execute-Intentions-Script");
+        map.insert("9923a32a-3607-11d2-b9be-0000f87a36b2", "DS-Install-Replica");
+        map.insert("4ecc03fe-ffc0-4947-b630-eb672a8a9dbc", "DS-Query-Self-Quota");
+        map.insert("1131f6aa-9c07-11d1-f79f-00c04fc2dcd2", "DS-Replication-Get-Changes");
+        map.insert("1131f6ad-9c07-11d1-f79f-00c04fc2dcd2", "DS-Replication-Get-Changes-All");
+        map.insert("89e95b76-444d-4c62-991a-0facbeda640c", "DS-Replication-Get-Changes-In-Filtered-Set");
+        map.insert("1131f6ac-9c07-11d1-f79f-00c04fc2dcd2", "DS-Replication-Manage-Topology");
+        map.insert("f98340fb-7c5b-4cdb-a00b-2ebdfa115a96", "DS-Replication-Monitor-Topology");
+        map.insert("1131f6ab-9c07-11d1-f79f-00c04fc2dcd2", "DS-Replication-Synchronize");
+        map.insert("1131f6ae-9c07-11d1-f79f-00c04fc2dcd2", "Read-Only-Replication-Secret-Synchronization");
+        map.insert("084c93a2-620d-4879-a836-f0ae47de0e89", "DS-Read-Partition-Secrets");
+        map.insert("94825a8d-b171-4116-8146-1e34d8f54401", "DS-Write-Partition-Secrets");
+        map.insert("4125c71f-7fac-4ff0-bcb7-f09a41325286", "DS-Set-Owner");
+        map.insert("88a9933e-e5c8-4f2a-9dd7-2527416b8092", "DS-Bypass-Quota");
+        map.insert("9b026da6-0d3c-465c-8bee-5199d7165cba", "DS-Validated-Write-Computer");
+        map.insert("05c74c5e-4deb-43b4-bd9f-86664c2a7fd5", "Enable-Per-User-Reversibly-Encrypted-Password");
+        map.insert("b7b1b3de-ab09-4242-9e30-9980e5d322f7", "Generate-RSoP-Logging");
+        map.insert("b7b1b3dd-ab09-4242-9e30-9980e5d322f7", "Generate-RSoP-Planning");
+        map.insert("7c0e2a7c-a419-48e4-a995-10180aad54dd", "Manage-Optional-Features");
+        map.insert("ba33815a-4f93-4c76-87f3-57574bff8109", "Migrate-SID-History");
+        map.insert("b4e60130-df3f-11d1-9c86-006008764d0e", "msmq-Open-Connector");
+        map.insert("06bd3201-df3e-11d1-9c86-006008764d0e", "msmq-Peek");
+        map.insert("4b6e08c3-df3c-11d1-9c86-006008764d0e", "msmq-Peek-computer-Journal");
+        map.insert("4b6e08c1-df3c-11d1-9c86-006008764d0e", "msmq-Peek-Dead-Letter");
+        map.insert("06bd3200-df3e-11d1-9c86-006008764d0e", "msmq-Receive");
+        map.insert("4b6e08c2-df3c-11d1-9c86-006008764d0e", "msmq-Receive-computer-Journal");
+        map.insert("4b6e08c0-df3c-11d1-9c86-006008764d0e", "msmq-Receive-Dead-Letter");
+        map.insert("06bd3203-df3e-11d1-9c86-006008764d0e", "msmq-Receive-journal");
+        map.insert("06bd3202-df3e-11d1-9c86-006008764d0e", "msmq-Send");
+        map.insert("a1990816-4298-11d1-ade2-00c04fd8d5cd", "Open-Address-Book");
+        map.insert("45ec5156-db7e-47bb-b53f-dbeb2d03c40f", "Reanimate-Tombstones");
+        map.insert("0bc1554e-0a99-11d1-adbb-00c04fd8d5cd", "Recalculate-Hierarchy");
+        map.insert("62dd28a8-7f46-11d2-b9ad-00c04f79f805", "Recalculate-Security-Inheritance");
+        map.insert("ab721a56-1e2f-11d0-9819-00aa0040529b", "Receive-As");
+        map.insert("9432c620-033c-4db7-8b58-14ef6d0bf477", "Refresh-Group-Cache");
+        map.insert("1a60ea8d-58a6-4b20-bcdc-fb71eb8a9ff8", "Reload-SSL-Certificate");
+        map.insert("7726b9d5-a4b4-4288-a6b2-dce952e80a7f", "Run-Protect-Admin-Groups-Task");
+        map.insert("91d67418-0135-4acc-8d79-c08e857cfbec", "SAM-Enumerate-Entire-Domain");
+        map.insert("ab721a54-1e2f-11d0-9819-00aa0040529b", "Send-As");
+        map.insert("ab721a55-1e2f-11d0-9819-00aa0040529b", "Send-To");
+        map.insert("ccc2dc7d-a6ad-4a7a-8846-c04e3cc53501", "Unexpire-Password");
+        map.insert("280f369c-67c7-438e-ae98-1d46f3c6f541", "Update-Password-Not-Required-Bit");
+        map.insert("be2bb760-7f46-11d2-b9ad-00c04f79f805", "Update-Schema-Cache");
+        map.insert("ab721a53-1e2f-11d0-9819-00aa0040529b", "User-Change-Password");
+        map.insert("00299570-246d-11d0-a768-00aa006e0529", "User-Force-Change-Password");
+        map.insert("6db69a1c-9422-11d1-aebd-0000f80367c1", "Terminal-Server");
+        map.insert("5805bc62-bdc9-4428-a5e2-856a0f4c185e", "Terminal-Server-License-Server");
+        map.insert("ffa6f046-ca4b-4feb-b40d-04dfee722543", "MS-TS-GatewayAccess");
+        // Validated writes
+        map.insert("72e39547-7b18-11d1-adef-00c04fd8d5cd", "Validated-DNS-Host-Name");
+        map.insert("f3a64788-5306-11d1-a9c5-0000f80367c1", "Validated-SPN");
+        map.insert("80863791-dbe9-4eb8-837e-7f0ab55d9ac7", "Validated-MS-DS-Additional-DNS-Host-Name");
+        map.insert("d31a8757-2447-4545-8081-3bb610cacbf2", "Validated-MS-DS-Behavior-Version");
+        map.insert("bf9679c0-0de6-11d0-a285-00aa003049e2", "Self-Membership");
+        // Property sets
+        map.insert("77b5b886-944a-11d1-aebd-0000f80367c1", "Personal-Information");
+        map.insert("e48d0154-bcf8-11d1-8702-00c04fb96050", "Public-Information");
+        map.insert("4c164200-20c0-11d0-a768-00aa006e0529", "User-Account-Restrictions");
+        map.insert("5f202010-79a5-11d0-9020-00c04fc2d4cf", "User-Logon");
+        map.insert("59ba2f42-79a2-11d0-9020-00c04fc2d3cf", "General-Information");
+        map.insert("bc0ac240-79a9-11d0-9020-00c04fc2d4cf", "Membership");
+        map.insert("e45795b3-9455-11d1-aebd-0000f80367c1", "Web-Information");
+        map.insert("e45795b2-9455-11d1-aebd-0000f80367c1", "Email-Information");
+        map.insert("037088f8-0ae1-11d2-b422-00a0c968f939", "RAS-Information");
+        map.insert("91e647de-d96f-4b70-9557-d63ff4f3ccd8", "Private-Information");
+        map
+    };
+}
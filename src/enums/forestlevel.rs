@@ -2,6 +2,7 @@
 pub fn get_forest_level(level: String) -> String
 {
     match level.as_str() {
+        "10" => { return "2025".to_string(); },
         "7" => { return "2016".to_string(); },
         "6" => { return "2012 R2".to_string(); },
         "5" => { return "2012".to_string(); },
@@ -1,40 +1,54 @@
 use regex::Regex;
 use crate::json::templates::*;
 
+/// gPLink link options: the link is disabled.
+pub const GPLINK_DISABLED: u32 = 0x1;
+/// gPLink link options: the link is enforced, it ignores the block inheritance of the child OUs.
+pub const GPLINK_ENFORCED: u32 = 0x2;
+/// gPOptions: the OU blocks the inheritance of the GPOs linked above it.
+pub const GPOPTIONS_BLOCK_INHERITANCE: u32 = 0x1;
+
 /// Function to parse gplink and push it in json format
+/// gPLink is like `[LDAP://cn={GUID},cn=policies,cn=system,DC=domain,DC=local;0][LDAP://...;2]`
+/// <https://learn.microsoft.com/en-us/openspecs/windows_protocols/ms-gpol/08090b22-bc16-49f4-8e10-f27a8fb16d18>
 pub fn parse_gplink(all_link: String) -> Vec<serde_json::value::Value>
 {
    let mut gplinks: Vec<serde_json::value::Value> = Vec::new();
 
-   let re = Regex::new(r"[a-zA-Z0-9-]{36}").unwrap();
-   let mut cpaths: Vec<String> = Vec::new();
-   for cpath in re.captures_iter(&all_link)
-   {
-      cpaths.push(cpath[0].to_owned().to_string());
-   }
-
-   let re2 = Regex::new(r"[;][0-4]{1}").unwrap();
-   let mut status: Vec<String> = Vec::new();
-   for enforced in re2.captures_iter(&all_link){
-      status.push(enforced[0].to_owned().to_string());
-   }
-
-   for i in 0..cpaths.len()
+   let re = Regex::new(r"(?i)\[LDAP://[^;\]]*\{([a-f0-9-]{36})\}[^;\]]*;(\d+)\]").unwrap();
+   for link in re.captures_iter(&all_link)
    {
-      let mut gplink = bh_41::prepare_gplink_json_template();
-      gplink["GUID"] = cpaths[i].to_string().into();
-      
-      // Thanks to: https://techibee.com/group-policies/find-link-status-and-enforcement-status-of-group-policies-using-powershell/2424
-      if status[i].to_string().contains(";2"){
-         gplink["IsEnforced"] = true.into();
-      }
-      if status[i].to_string().contains(";3"){
-         gplink["IsEnforced"] = true.into();
+      let options = link[2].parse::<u32>().unwrap_or(0);
+      // Disabled links don't apply any policy
+      if options & GPLINK_DISABLED != 0 {
+         continue
       }
+      let mut gplink = bh_41::prepare_gplink_json_template();
+      gplink["GUID"] = link[1].to_uppercase().into();
+      gplink["IsEnforced"] = (options & GPLINK_ENFORCED != 0).into();
 
-      //trace!("gpo link: {:?}",cpaths[i]);
+      //trace!("gpo link: {:?}",&link[1]);
       gplinks.push(gplink);
    }
 
    return gplinks
-}
\ No newline at end of file
+}
+
+/// Function to know if an OU blocks the GPO inheritance from its gPOptions value.
+pub fn parse_gpoptions(gpoptions: &str) -> bool
+{
+   gpoptions.trim().parse::<u32>().map(|options| options & GPOPTIONS_BLOCK_INHERITANCE != 0).unwrap_or(false)
+}
+
+/// Test functions
+#[test]
+pub fn test_parse_gplink() {
+   let gplinks = parse_gplink("[LDAP://cn={31B2F340-016D-11D2-945F-00C04FB984F9},cn=policies,cn=system,DC=essos,DC=local;0][LDAP://cn={6ac1786c-016f-11d2-945f-00c04fb984f9},cn=policies,cn=system,DC=essos,DC=local;2][LDAP://cn={B4F6A9D1-2E45-4B8C-9F16-4C1D5E8A7B21},cn=policies,cn=system,DC=essos,DC=local;1]".to_string());
+   assert_eq!(gplinks.len(), 2);
+   assert_eq!(gplinks[0]["GUID"], "31B2F340-016D-11D2-945F-00C04FB984F9");
+   assert_eq!(gplinks[0]["IsEnforced"], false);
+   assert_eq!(gplinks[1]["GUID"], "6AC1786C-016F-11D2-945F-00C04FB984F9");
+   assert_eq!(gplinks[1]["IsEnforced"], true);
+   assert!(parse_gpoptions("1"));
+   assert!(!parse_gpoptions("0"));
+}
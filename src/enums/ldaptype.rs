@@ -1,5 +1,7 @@
 use ldap3::{SearchEntry};
 use std::collections::HashMap;
+
+use crate::modules::hybrid::DEVICE_REGISTRATION_CONTAINER;
 //use log::trace;
 
 /// Enum to get ldap object type.
@@ -13,12 +15,30 @@ pub enum Type {
     ForeignSecurityPrincipal,
     Container,
     Trust,
+    PasswordSettings,
+    FsmoRole,
+    ExchangeOrganization,
+    ExchangeServer,
+    SccmObject,
+    DeviceRegistration,
     Unknown
 }
 
+/// Classes of the default containers which are not a "container", collected as Container for the Contains edges.
+pub const DEFAULT_CONTAINER_CLASSES: [&str; 4] = [
+    "builtinDomain",
+    "lostAndFound",
+    "msDS-QuotaContainer",
+    "msTPM-InformationObjectsContainer",
+];
+
+/// Classes of the SCCM objects published in the domain: sites, management points and PXE service points.
+pub const SCCM_CLASSES: [&str; 3] = ["mSSMSSite", "mSSMSManagementPoint", "intellimirrorSCP"];
+
 /// Get object type, like ("user","group","computer","ou", "container", "gpo", "domain" "trust").
 pub fn get_type(result: SearchEntry) -> std::result::Result<Type, Type>
 {
+    let result_dn = result.dn.to_uppercase();
     let result_attrs: HashMap<String, Vec<String>>;
     result_attrs = result.attrs;
 
@@ -67,11 +87,40 @@ pub fn get_type(result: SearchEntry) -> std::result::Result<Type, Type>
         {
             return Ok(Type::ForeignSecurityPrincipal)
         }
-        // Type is Container
-        if key == "objectClass" && (value.contains(&String::from("top")) && value.contains(&String::from("container"))) && !value.contains(&String::from("groupPolicyContainer"))
+        // Type is Container, with the default containers of other classes (CN=Builtin, CN=LostAndFound...)
+        if key == "objectClass" && ((value.contains(&String::from("top")) && value.contains(&String::from("container"))) || DEFAULT_CONTAINER_CLASSES.iter().any(|class| value.contains(&class.to_string()))) && !value.contains(&String::from("groupPolicyContainer"))
         {
             return Ok(Type::Container)
         }
+        // Type is fine-grained password policy (PSO)
+        if key == "objectClass" && value.contains(&String::from("msDS-PasswordSettings"))
+        {
+            return Ok(Type::PasswordSettings)
+        }
+        // Type is a FSMO role object: schema, partitions, RID manager or infrastructure
+        if key == "objectClass" && ["dMD", "crossRefContainer", "rIDManager", "infrastructureUpdate"].iter().any(|class| value.contains(&class.to_string()))
+        {
+            return Ok(Type::FsmoRole)
+        }
+        // Type is an Exchange organization of the configuration NC
+        if key == "objectClass" && value.contains(&String::from("msExchOrganizationContainer"))
+        {
+            return Ok(Type::ExchangeOrganization)
+        }
+        // Type is an Exchange server of the configuration NC
+        if key == "objectClass" && value.contains(&String::from("msExchExchangeServer"))
+        {
+            return Ok(Type::ExchangeServer)
+        }
+        // Type is a SCCM site or management point of CN=System Management, or a PXE service point
+        if key == "objectClass" && SCCM_CLASSES.iter().any(|class| value.contains(&class.to_string()))
+        {
+            return Ok(Type::SccmObject)
+        }
+        if key == "objectClass" && value.contains(&String::from("serviceConnectionPoint")) && result_dn.contains(DEVICE_REGISTRATION_CONTAINER)
+        {
+            return Ok(Type::DeviceRegistration)
+        }
         // Type is Trust domain
         if key == "objectClass" && value.contains(&String::from("trustedDomain"))
         {
@@ -79,4 +128,19 @@ pub fn get_type(result: SearchEntry) -> std::result::Result<Type, Type>
         }
     }
     return Err(Type::Unknown)
-}
\ No newline at end of file
+}
+/// Test functions
+#[test]
+pub fn test_get_type_containers() {
+    let entry = |classes: &[&str]| SearchEntry {
+        dn: "CN=X,DC=ESSOS,DC=LOCAL".to_string(),
+        attrs: HashMap::from([("objectClass".to_string(), classes.iter().map(|class| class.to_string()).collect())]),
+        bin_attrs: HashMap::new(),
+    };
+    assert!(matches!(get_type(entry(&["top", "container"])), Ok(Type::Container)));
+    assert!(matches!(get_type(entry(&["top", "builtinDomain"])), Ok(Type::Container)));
+    assert!(matches!(get_type(entry(&["top", "lostAndFound"])), Ok(Type::Container)));
+    assert!(matches!(get_type(entry(&["top", "msDS-QuotaContainer"])), Ok(Type::Container)));
+    assert!(matches!(get_type(entry(&["top", "container", "groupPolicyContainer"])), Ok(Type::Gpo)));
+    assert!(matches!(get_type(entry(&["top", "domain", "domainDNS"])), Ok(Type::Domain)));
+}
@@ -15,6 +15,12 @@ pub use secdesc::*;
 pub use spntasks::*;
 #[doc(inline)]
 pub use gplink::*;
+#[doc(inline)]
+pub use extrights::*;
+#[doc(inline)]
+pub use schema::*;
+#[doc(inline)]
+pub use attributes::*;
 
 pub mod uacflags;
 pub mod ldaptype;
@@ -26,4 +32,12 @@ pub mod secdesc;
 pub mod spntasks;
 pub mod gplink;
 pub mod constants;
-pub mod trusts;
\ No newline at end of file
+pub mod trusts;
+pub mod extrights;
+pub mod schema;
+pub mod attributes;
+pub mod pwdpolicy;
+pub mod etypes;
+pub mod certmapping;
+pub mod decoding;
+pub mod replmeta;
\ No newline at end of file
@@ -0,0 +1,71 @@
+//! Default domain password policy and fine-grained password policies (PSO)
+//!
+//! The default policy is read on the domain object, the PSOs are the msDS-PasswordSettings objects
+//! in CN=Password Settings Container,CN=System and apply to the users and groups of msDS-PSOAppliesTo.
+//! <https://learn.microsoft.com/en-us/windows-server/identity/ad-ds/get-started/adac/fine-grained-password-policies>
+use std::collections::HashMap;
+
+use crate::enums::date::interval_to_minutes;
+
+/// pwdProperties: passwords must be complex.
+pub const DOMAIN_PASSWORD_COMPLEX: u32 = 0x1;
+/// pwdProperties: passwords are stored with reversible encryption.
+pub const DOMAIN_PASSWORD_STORE_CLEARTEXT: u32 = 0x10;
+
+/// Policy attributes of the domain object and of a PSO, with the BloodHound property name.
+/// (domain attribute, PSO attribute, property, duration)
+const POLICY_ATTRIBUTES: [(&str, &str, &str, bool); 7] = [
+    ("minPwdLength", "msDS-MinimumPasswordLength", "minpwdlength", false),
+    ("pwdHistoryLength", "msDS-PasswordHistoryLength", "pwdhistorylength", false),
+    ("lockoutThreshold", "msDS-LockoutThreshold", "lockoutthreshold", false),
+    ("maxPwdAge", "msDS-MaximumPasswordAge", "maxpwdage", true),
+    ("minPwdAge", "msDS-MinimumPasswordAge", "minpwdage", true),
+    ("lockoutDuration", "msDS-LockoutDuration", "lockoutduration", true),
+    ("lockOutObservationWindow", "msDS-LockoutObservationWindow", "lockoutobservationwindow", true),
+];
+
+/// Function to set the password policy properties from the domain object or a PSO attributes.
+pub fn set_password_policy(properties: &mut serde_json::value::Value, attrs: &HashMap<String, Vec<String>>, pso: bool) {
+    for (domain_attribute, pso_attribute, property, duration) in POLICY_ATTRIBUTES {
+        let attribute = if pso { pso_attribute } else { domain_attribute };
+        let value = match attrs.get(attribute).and_then(|v| v.first()) {
+            Some(value) => value,
+            None => continue,
+        };
+        properties[property] = if duration {
+            interval_to_minutes(value).into()
+        } else {
+            value.parse::<i64>().unwrap_or(0).into()
+        };
+    }
+    if pso {
+        if let Some(complex) = attrs.get("msDS-PasswordComplexityEnabled").and_then(|v| v.first()) {
+            properties["pwdcomplexity"] = complex.eq_ignore_ascii_case("TRUE").into();
+        }
+        if let Some(reversible) = attrs.get("msDS-PasswordReversibleEncryptionEnabled").and_then(|v| v.first()) {
+            properties["pwdreversibleencryption"] = reversible.eq_ignore_ascii_case("TRUE").into();
+        }
+    } else if let Some(flags) = attrs.get("pwdProperties").and_then(|v| v.first()).and_then(|v| v.parse::<u32>().ok()) {
+        properties["pwdcomplexity"] = (flags & DOMAIN_PASSWORD_COMPLEX != 0).into();
+        properties["pwdreversibleencryption"] = (flags & DOMAIN_PASSWORD_STORE_CLEARTEXT != 0).into();
+    }
+}
+
+/// Test functions
+#[test]
+pub fn test_set_password_policy() {
+    let mut attrs: HashMap<String, Vec<String>> = HashMap::new();
+    attrs.insert("minPwdLength".to_string(), vec!["7".to_string()]);
+    attrs.insert("maxPwdAge".to_string(), vec!["-36288000000000".to_string()]);
+    attrs.insert("lockoutDuration".to_string(), vec!["-18000000000".to_string()]);
+    attrs.insert("minPwdAge".to_string(), vec![i64::MIN.to_string()]);
+    attrs.insert("pwdProperties".to_string(), vec!["17".to_string()]);
+    let mut properties = serde_json::json!({});
+    set_password_policy(&mut properties, &attrs, false);
+    assert_eq!(properties["minpwdlength"], 7);
+    assert_eq!(properties["maxpwdage"], 42 * 24 * 60);
+    assert_eq!(properties["lockoutduration"], 30);
+    assert_eq!(properties["minpwdage"], -1);
+    assert_eq!(properties["pwdcomplexity"], true);
+    assert_eq!(properties["pwdreversibleencryption"], true);
+}
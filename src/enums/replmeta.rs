@@ -0,0 +1,179 @@
+//! Replication metadata of the sensitive attributes (--repl-metadata)
+//!
+//! The DC keeps for every attribute the time and the DC of its last originating change in msDS-ReplAttributeMetaData,
+//! and for every value of a linked attribute (member) its creation, deletion and last change in msDS-ReplValueMetaData.
+//! Both are constructed attributes, written in XML:
+//!
+//! ```text
+//! <DS_REPL_ATTR_META_DATA>
+//!     <pszAttributeName>nTSecurityDescriptor</pszAttributeName>
+//!     <dwVersion>4</dwVersion>
+//!     <ftimeLastOriginatingChange>2023-10-01T12:00:00Z</ftimeLastOriginatingChange>
+//!     <pszLastOriginatingDsaDN>CN=NTDS Settings,CN=MEEREEN,CN=Servers,...</pszLastOriginatingDsaDN>
+//!     ...
+//! </DS_REPL_ATTR_META_DATA>
+//! ```
+//!
+//! The last change of nTSecurityDescriptor, member and servicePrincipalName is set in the object properties
+//! (`ntsecuritydescriptorlastchanged`, `ntsecuritydescriptorlastchangeddc`, `ntsecuritydescriptorversion`...).
+//! The metadata of the member values is kept until the end of the run for the MemberOf edges of --edge-provenance.
+//! The descriptor is replicated as a whole: its last change is the last change of any of its ACEs.
+use lazy_static::lazy_static;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+
+use crate::enums::date::iso_to_epoch;
+
+/// Constructed attributes requested with --repl-metadata.
+pub const REPL_METADATA_REQUEST: [&str; 2] = ["msDS-ReplAttributeMetaData", "msDS-ReplValueMetaData"];
+/// Attributes whose last change is set in the object properties.
+pub const REPL_METADATA_ATTRIBUTES: [&str; 3] = ["nTSecurityDescriptor", "member", "servicePrincipalName"];
+
+/// Request and parse the replication metadata (--repl-metadata).
+static REPL_METADATA: AtomicBool = AtomicBool::new(false);
+
+lazy_static! {
+    /// Group distinguishedName => metadata of its member values
+    static ref MEMBER_CHANGES: Mutex<HashMap<String, Vec<ReplMetadata>>> = Mutex::new(HashMap::new());
+}
+
+/// Function to enable the replication metadata collection.
+pub fn set_repl_metadata(enabled: bool) {
+    REPL_METADATA.store(enabled, Ordering::Relaxed);
+}
+
+/// Function to know if the replication metadata is collected.
+pub fn repl_metadata() -> bool {
+    REPL_METADATA.load(Ordering::Relaxed)
+}
+
+/// Last originating change of one attribute, or of one value of a linked attribute.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ReplMetadata {
+    pub attribute: String,
+    /// DN of the value, only for the linked attributes
+    pub value: Option<String>,
+    pub version: u32,
+    /// Epoch of the last originating change
+    pub last_change: Option<i64>,
+    /// Epoch of the value creation and deletion, only for the linked attributes
+    pub created: Option<i64>,
+    pub deleted: Option<i64>,
+    /// Server name of the DC of the last originating change
+    pub dc: String,
+}
+
+/// Function to get the text of an XML element, with the entities decoded.
+fn xml_element(xml: &str, tag: &str) -> Option<String> {
+    let start = xml.find(&format!("<{}>", tag))? + tag.len() + 2;
+    let end = start + xml[start..].find(&format!("</{}>", tag))?;
+    let text = xml[start..end].trim()
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&amp;", "&");
+    Some(text)
+}
+
+/// Function to convert a metadata time to epoch, None for the null time (1601-01-01) of a value never deleted.
+pub fn metadata_time(time: &str) -> Option<i64> {
+    iso_to_epoch(time).filter(|epoch| *epoch > 0)
+}
+
+/// Function to get the server name of a DSA: "CN=NTDS Settings,CN=MEEREEN,CN=Servers,..." => "MEEREEN".
+pub fn dsa_server(dsa_dn: &str) -> String {
+    dsa_dn.split(',').nth(1)
+        .and_then(|rdn| rdn.split_once('='))
+        .map(|(_, name)| name.to_uppercase())
+        .unwrap_or_default()
+}
+
+/// Function to parse one value of msDS-ReplAttributeMetaData or msDS-ReplValueMetaData.
+pub fn parse_repl_metadata(xml: &str) -> Option<ReplMetadata> {
+    Some(ReplMetadata {
+        attribute: xml_element(xml, "pszAttributeName")?,
+        value: xml_element(xml, "pszObjectDn").filter(|dn| !dn.is_empty()),
+        version: xml_element(xml, "dwVersion").and_then(|version| version.parse::<u32>().ok()).unwrap_or(0),
+        last_change: xml_element(xml, "ftimeLastOriginatingChange").and_then(|time| metadata_time(&time)),
+        created: xml_element(xml, "ftimeCreated").and_then(|time| metadata_time(&time)),
+        deleted: xml_element(xml, "ftimeDeleted").and_then(|time| metadata_time(&time)),
+        dc: xml_element(xml, "pszLastOriginatingDsaDN").map(|dn| dsa_server(&dn)).unwrap_or_default(),
+    })
+}
+
+/// Function to get the metadata of the attribute or value changed last.
+fn last_changed<'a>(metadata: impl Iterator<Item = &'a ReplMetadata>) -> Option<&'a ReplMetadata> {
+    metadata.max_by_key(|metadata| metadata.last_change)
+}
+
+/// Function to set the last change of the REPL_METADATA_ATTRIBUTES in the object properties and keep the metadata
+/// of the member values. A member added or removed on a group with linked value replication changes only the value
+/// metadata, the last change of member is the last change of its values.
+pub fn set_repl_metadata_properties(
+    object: &mut serde_json::value::Value,
+    result_attrs: &HashMap<String, Vec<String>>,
+) {
+    let metadata = |name: &str| -> Vec<ReplMetadata> {
+        result_attrs.iter()
+            .filter(|(attribute, _)| attribute.eq_ignore_ascii_case(name))
+            .flat_map(|(_, values)| values.iter().filter_map(|value| parse_repl_metadata(value)))
+            .collect()
+    };
+    let attributes = metadata("msDS-ReplAttributeMetaData");
+    let values = metadata("msDS-ReplValueMetaData");
+    for name in REPL_METADATA_ATTRIBUTES {
+        let of_attribute = |metadata: &&ReplMetadata| metadata.attribute.eq_ignore_ascii_case(name);
+        let last = last_changed(values.iter().filter(of_attribute)).or_else(|| last_changed(attributes.iter().filter(of_attribute)));
+        if let Some(last) = last.filter(|last| last.last_change.is_some()) {
+            let property = name.to_lowercase();
+            object["Properties"][format!("{}lastchanged", property)] = last.last_change.into();
+            object["Properties"][format!("{}lastchangeddc", property)] = last.dc.to_owned().into();
+            object["Properties"][format!("{}version", property)] = last.version.into();
+        }
+    }
+    let members: Vec<ReplMetadata> = values.into_iter().filter(|metadata| metadata.attribute.eq_ignore_ascii_case("member") && metadata.value.is_some()).collect();
+    if let (Some(dn), false) = (object["Properties"]["distinguishedname"].as_str(), members.is_empty()) {
+        MEMBER_CHANGES.lock().unwrap().insert(dn.to_uppercase(), members);
+    }
+}
+
+/// Function to get the metadata of one member value of a group.
+pub fn member_change(group_dn: &str, member_dn: &str) -> Option<ReplMetadata> {
+    MEMBER_CHANGES.lock().unwrap()
+        .get(&group_dn.to_uppercase())
+        .and_then(|members| members.iter().find(|metadata| metadata.value.as_deref().map_or(false, |dn| dn.eq_ignore_ascii_case(member_dn))))
+        .cloned()
+}
+
+/// Test functions
+#[test]
+pub fn test_repl_metadata() {
+    let descriptor = "<DS_REPL_ATTR_META_DATA>\n\t<pszAttributeName>nTSecurityDescriptor</pszAttributeName>\n\t<dwVersion>4</dwVersion>\n\t<ftimeLastOriginatingChange>2023-10-01T12:00:00Z</ftimeLastOriginatingChange>\n\t<uuidLastOriginatingDsaInvocationID>5b2c8e1a-6f3d-4c1e-9a7b-0d2e4f6a8c10</uuidLastOriginatingDsaInvocationID>\n\t<usnOriginatingChange>41023</usnOriginatingChange>\n\t<usnLocalChange>41023</usnLocalChange>\n\t<pszLastOriginatingDsaDN>CN=NTDS Settings,CN=MEEREEN,CN=Servers,CN=Default-First-Site-Name,CN=Sites,CN=Configuration,DC=SEVENKINGDOMS,DC=LOCAL</pszLastOriginatingDsaDN>\n</DS_REPL_ATTR_META_DATA>\n\0";
+    let member = "<DS_REPL_VALUE_META_DATA>\n\t<pszAttributeName>member</pszAttributeName>\n\t<pszObjectDn>CN=Viserys &amp; Drogo,CN=Users,DC=essos,DC=local</pszObjectDn>\n\t<cbData>0</cbData>\n\t<pbData></pbData>\n\t<ftimeDeleted>1601-01-01T00:00:00Z</ftimeDeleted>\n\t<ftimeCreated>2023-10-02T08:30:00Z</ftimeCreated>\n\t<dwVersion>1</dwVersion>\n\t<ftimeLastOriginatingChange>2023-10-02T08:30:00Z</ftimeLastOriginatingChange>\n\t<pszLastOriginatingDsaDN>CN=NTDS Settings,CN=BRAAVOS,CN=Servers,CN=Default-First-Site-Name,CN=Sites,CN=Configuration,DC=SEVENKINGDOMS,DC=LOCAL</pszLastOriginatingDsaDN>\n</DS_REPL_VALUE_META_DATA>\n";
+
+    let metadata = parse_repl_metadata(descriptor).unwrap();
+    assert_eq!(metadata.attribute, "nTSecurityDescriptor");
+    assert_eq!(metadata.version, 4);
+    assert_eq!(metadata.last_change, Some(1696161600));
+    assert_eq!(metadata.dc, "MEEREEN");
+    assert_eq!(metadata.value, None);
+    let metadata = parse_repl_metadata(member).unwrap();
+    assert_eq!(metadata.value.as_deref(), Some("CN=Viserys & Drogo,CN=Users,DC=essos,DC=local"));
+    assert_eq!(metadata.deleted, None);
+    assert!(parse_repl_metadata("<DS_REPL_ATTR_META_DATA></DS_REPL_ATTR_META_DATA>").is_none());
+
+    let attrs: HashMap<String, Vec<String>> = vec![
+        ("msDS-ReplAttributeMetaData".to_string(), vec![descriptor.to_string()]),
+        ("msDS-ReplValueMetaData".to_string(), vec![member.to_string()]),
+    ].into_iter().collect();
+    let mut group_json = serde_json::json!({"Properties": {"distinguishedname": "CN=DRAGONRIDERS,CN=USERS,DC=ESSOS,DC=LOCAL"}});
+    set_repl_metadata_properties(&mut group_json, &attrs);
+    assert_eq!(group_json["Properties"]["ntsecuritydescriptorlastchanged"], 1696161600);
+    assert_eq!(group_json["Properties"]["ntsecuritydescriptorlastchangeddc"], "MEEREEN");
+    assert_eq!(group_json["Properties"]["memberlastchangeddc"], "BRAAVOS");
+    assert!(group_json["Properties"].get("serviceprincipalnamelastchanged").is_none());
+    let change = member_change("CN=DragonRiders,CN=Users,DC=essos,DC=local", "CN=VISERYS & DROGO,CN=USERS,DC=ESSOS,DC=LOCAL").unwrap();
+    assert_eq!(change.created, Some(1696235400));
+}
@@ -0,0 +1,80 @@
+//! Schema GUIDs collected live from CN=Schema,CN=Configuration
+//!
+//! Attribute and class GUIDs (schemaIDGUID) are forest specific for every schema extension
+//! (LAPS ms-Mcs-AdmPwd for example), so the names are resolved from the collected schema first.
+//! The map is cached on disk keyed by the schema objectVersion.
+use lazy_static::lazy_static;
+use log::{debug, trace};
+use std::collections::HashMap;
+use std::fs;
+use std::sync::RwLock;
+
+/// Directory where the schema GUID caches are written.
+pub const SCHEMA_CACHE_DIR: &str = ".rusthound_cache";
+
+lazy_static! {
+    static ref LIVE_SCHEMA_GUIDS: RwLock<HashMap<String, String>> = RwLock::new(HashMap::new());
+}
+
+/// Function to get the schemaIDGUID of one attribute or class from its lowercase cn.
+pub fn get_schema_guid(name: &str) -> Option<String> {
+    match LIVE_SCHEMA_GUIDS.read() {
+        Ok(live) => live.get(&name.to_lowercase()).map(|guid| guid.to_owned()),
+        Err(_) => None,
+    }
+}
+
+/// Function to know if the schema was collected for this run.
+pub fn schema_loaded() -> bool {
+    LIVE_SCHEMA_GUIDS.read().map(|live| !live.is_empty()).unwrap_or(false)
+}
+
+/// Function to get the name (lowercase cn) of one schemaIDGUID.
+pub fn get_schema_name(guid: &str) -> Option<String> {
+    let guid = guid.to_lowercase();
+    match LIVE_SCHEMA_GUIDS.read() {
+        Ok(live) => live.iter().find(|(_, value)| **value == guid).map(|(name, _)| name.to_owned()),
+        Err(_) => None,
+    }
+}
+
+/// Function to get a copy of the schema map collected for this run.
+pub fn live_schema_guids() -> HashMap<String, String> {
+    LIVE_SCHEMA_GUIDS.read().map(|live| live.to_owned()).unwrap_or_default()
+}
+
+/// Function to register the schema map (lowercase cn => lowercase schemaIDGUID).
+pub fn add_live_schema_guids(guids: HashMap<String, String>) {
+    if let Ok(mut live) = LIVE_SCHEMA_GUIDS.write() {
+        for (name, guid) in guids {
+            trace!("Schema GUID {}: {}", &name, &guid);
+            live.insert(name.to_lowercase(), guid.to_lowercase());
+        }
+    }
+}
+
+/// Function to get the cache file path for one domain and schema objectVersion.
+pub fn schema_cache_path(domain: &String, version: &String) -> String {
+    format!("{}/schema_{}_{}.json", SCHEMA_CACHE_DIR, domain.to_lowercase(), version)
+}
+
+/// Function to load the schema map from the disk cache.
+pub fn load_schema_cache(path: &String) -> Option<HashMap<String, String>> {
+    let content = fs::read_to_string(path).ok()?;
+    match serde_json::from_str::<HashMap<String, String>>(&content) {
+        Ok(guids) => Some(guids),
+        Err(err) => {
+            debug!("Ignoring schema cache {}: {}", path, err);
+            None
+        }
+    }
+}
+
+/// Function to write the schema map in the disk cache.
+pub fn save_schema_cache(path: &String, guids: &HashMap<String, String>) {
+    let res = fs::create_dir_all(SCHEMA_CACHE_DIR)
+        .and_then(|_| fs::write(path, serde_json::to_string(guids).unwrap_or_default()));
+    if let Err(err) = res {
+        debug!("Unable to write schema cache {}: {}", path, err);
+    }
+}
@@ -5,6 +5,9 @@ use nom::{
 };
 
 use crate::enums::constants::*;
+use crate::errors::{Error, Kind};
+use std::fmt;
+use std::str::FromStr;
 
 // https://github.com/fox-it/dissect.cstruct/blob/master/examples/secdesc.py
 // http://www.selfadsi.org/deep-inside/ad-security-descriptors.htm#SecurityDescriptorStructure
@@ -49,6 +52,51 @@ impl SecurityDescriptor {
     );
 }
 
+/// Security descriptor with the structures read at its offsets.
+#[derive(Debug)]
+pub struct SecurityDescriptorContent {
+    pub header: SecurityDescriptor,
+    pub owner: Option<LdapSid>,
+    pub group: Option<LdapSid>,
+    pub sacl: Option<Acl>,
+    pub dacl: Option<Acl>,
+}
+
+impl SecurityDescriptor {
+    /// Parse the header then the owner, the group, the SACL and the DACL at their offsets.
+    /// An offset of 0 means the structure is absent, an offset or a size out of the buffer is an error.
+    pub fn parse_complete(buf: &[u8]) -> std::result::Result<SecurityDescriptorContent, String> {
+        let header = SecurityDescriptor::parse(buf)
+            .map_err(|_| format!("SECURITY_DESCRIPTOR header is truncated ({} bytes)", buf.len()))?
+            .1;
+        Ok(SecurityDescriptorContent {
+            owner: parse_at(buf, header.offset_owner, LdapSid::parse, "owner SID")?,
+            group: parse_at(buf, header.offset_group, LdapSid::parse, "group SID")?,
+            sacl: parse_at(buf, header.offset_sacl, Acl::parse, "SACL")?,
+            dacl: parse_at(buf, header.offset_dacl, Acl::parse, "DACL")?,
+            header,
+        })
+    }
+}
+
+/// Function to parse one structure of a security descriptor at its offset.
+fn parse_at<'a, T>(
+    buf: &'a [u8],
+    offset: u32,
+    parser: fn(&'a [u8]) -> IResult<&'a [u8], T>,
+    name: &str,
+) -> std::result::Result<Option<T>, String> {
+    if offset == 0 {
+        return Ok(None)
+    }
+    let data = buf.get(offset as usize..)
+        .ok_or_else(|| format!("{} offset {} is out of the {} bytes", name, offset, buf.len()))?;
+    parser(data).map(|(_, value)| Some(value)).map_err(|err| match err {
+        Err::Error(err) | Err::Failure(err) => format!("{} at offset {} is malformed ({:?})", name, offset, err.code),
+        Err::Incomplete(_) => format!("{} at offset {} is truncated", name, offset),
+    })
+}
+
 /// Strcuture for Sid Identified Authority network packet.
 /// <https://docs.microsoft.com/en-us/openspecs/windows_protocols/ms-dtyp/c6ce4275-3d90-4890-ab3a-514745e4637e>
 #[derive(Debug, Clone)]
@@ -100,6 +148,78 @@ impl LdapSid {
     );
 }
 
+impl LdapSid {
+    /// Get the identifier authority, a 48 bits big-endian value.
+    pub fn authority(&self) -> u64 {
+        self.identifier_authority.value.iter().fold(0, |authority, byte| authority << 8 | *byte as u64)
+    }
+
+    /// Get the binary format of the SID, as stored in objectSid.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut raw: Vec<u8> = vec![self.revision, self.sub_authority.len() as u8];
+        raw.extend_from_slice(&self.identifier_authority.value);
+        for sub_authority in &self.sub_authority {
+            raw.extend_from_slice(&sub_authority.to_le_bytes());
+        }
+        raw
+    }
+}
+
+/// Canonical string of the SID: "S-1-5-21-...".
+/// The authority is written in hexadecimal when it does not fit in 32 bits.
+/// <https://learn.microsoft.com/en-us/openspecs/windows_protocols/ms-dtyp/c92a27b1-c772-4fa7-a432-15df5f1b66a1>
+impl fmt::Display for LdapSid {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let authority = self.authority();
+        if authority >> 32 == 0 {
+            write!(f, "S-{}-{}", self.revision, authority)?;
+        } else {
+            write!(f, "S-{}-0x{:012X}", self.revision, authority)?;
+        }
+        for sub_authority in &self.sub_authority {
+            write!(f, "-{}", sub_authority)?;
+        }
+        Ok(())
+    }
+}
+
+/// Parse a SID string: "S-1-5-21-...", at most 15 sub-authorities.
+impl FromStr for LdapSid {
+    type Err = Error;
+
+    fn from_str(sid: &str) -> std::result::Result<Self, Self::Err> {
+        parse_sid_string(sid).ok_or_else(|| Error::new(Kind::ParseError).desc(format!("invalid SID string {:?}", sid)))
+    }
+}
+
+fn parse_sid_string(sid: &str) -> Option<LdapSid> {
+    let sid = sid.trim();
+    let parts: Vec<&str> = sid.strip_prefix("S-").or_else(|| sid.strip_prefix("s-"))?.split('-').collect();
+    if parts.len() < 2 || parts.len() > 17 {
+        return None
+    }
+    let revision = parts[0].parse::<u8>().ok()?;
+    let authority = match parts[1].strip_prefix("0x").or_else(|| parts[1].strip_prefix("0X")) {
+        Some(hex) => u64::from_str_radix(hex, 16).ok()?,
+        None => parts[1].parse::<u64>().ok()?,
+    };
+    if authority >> 48 != 0 {
+        return None
+    }
+    let sub_authority = parts[2..].iter().map(|part| part.parse::<u32>().ok()).collect::<Option<Vec<u32>>>()?;
+    Some(LdapSid {
+        revision,
+        sub_authority_count: sub_authority.len() as u8,
+        identifier_authority: LdapSidIdentifiedAuthority { value: authority.to_be_bytes()[2..].to_vec() },
+        sub_authority,
+    })
+}
+
+/// Size of the ACL header, included in acl_size.
+pub const ACL_HEADER_SIZE: usize = 8;
+/// Size of the ACE header, included in ace_size.
+pub const ACE_HEADER_SIZE: usize = 4;
+
 /// Structure for Acl network packet.
 /// <https://docs.microsoft.com/en-us/openspecs/windows_protocols/ms-dtyp/20233ed8-a6c6-4097-aafa-dd545ed24428>
 #[derive(Debug)]
@@ -114,27 +234,24 @@ pub struct Acl {
 }
 
 impl Acl {
-    named!(
-        pub parse<Self>,
-        do_parse!(
-            acl_revision: le_u8
-            >> sbz1: le_u8
-            >> acl_size: le_u16
-            >> ace_count: le_u16
-            >> sbz2: le_u16
-            >> data: count!(Ace::parse, ace_count as usize)
-            >> ({
-                Acl {
-                    acl_revision,
-                    sbz1,
-                    acl_size,
-                    ace_count,
-                    sbz2,
-                    data
-                }
-            })
-        )
-    );
+    /// Parse the ACL header then its ACEs, the ACEs are read inside acl_size only.
+    pub fn parse(input: &[u8]) -> IResult<&[u8], Self> {
+        let (input, acl_revision) = le_u8(input)?;
+        let (input, sbz1) = le_u8(input)?;
+        let (input, acl_size) = combinator::verify(le_u16, |size: &u16| *size as usize >= ACL_HEADER_SIZE)(input)?;
+        let (input, ace_count) = le_u16(input)?;
+        let (input, sbz2) = le_u16(input)?;
+        let (input, aces) = bytes::complete::take(acl_size as usize - ACL_HEADER_SIZE)(input)?;
+        let (_, data) = multi::count(Ace::parse, ace_count as usize)(aces)?;
+        Ok((input, Acl {
+            acl_revision,
+            sbz1,
+            acl_size,
+            ace_count,
+            sbz2,
+            data
+        }))
+    }
 }
 
 /// Structure for Ace network packet.
@@ -149,28 +266,25 @@ pub struct Ace {
 }
 
 impl Ace {
-    named!(
-        pub parse<Self>,
-        do_parse!(
-            ace_type: le_u8
-            >> ace_flags: le_u8
-            >> ace_size: le_u16
-            >> data: switch!(value!(ace_type as u8),
-                ACCESS_ALLOWED_ACE_TYPE => call!(AccessAllowedAce::parse)|
-                ACCESS_DENIED_ACE_TYPE => call!(AccessAllowedAce::parse)|
-                ACCESS_ALLOWED_OBJECT_ACE_TYPE => call!(AccessAllowedObjectAce::parse)|
-                ACCESS_DENIED_OBJECT_ACE_TYPE => call!(AccessAllowedObjectAce::parse)
-            )
-            >> ({
-                Ace {
-                    ace_type,
-                    ace_flags,
-                    ace_size,
-                    data
-                }
-            })
-        )
-    );
+    /// Parse the ACE header then its data, the data is read inside ace_size only.
+    /// The ACE types without any edge (audit, callback...) are skipped with an empty data.
+    pub fn parse(input: &[u8]) -> IResult<&[u8], Self> {
+        let (input, ace_type) = le_u8(input)?;
+        let (input, ace_flags) = le_u8(input)?;
+        let (input, ace_size) = combinator::verify(le_u16, |size: &u16| *size as usize >= ACE_HEADER_SIZE)(input)?;
+        let (input, body) = bytes::complete::take(ace_size as usize - ACE_HEADER_SIZE)(input)?;
+        let data = match ace_type {
+            ACCESS_ALLOWED_ACE_TYPE | ACCESS_DENIED_ACE_TYPE => AccessAllowedAce::parse(body)?.1,
+            ACCESS_ALLOWED_OBJECT_ACE_TYPE | ACCESS_DENIED_OBJECT_ACE_TYPE => AccessAllowedObjectAce::parse(body)?.1,
+            _ => AceFormat::Empty,
+        };
+        Ok((input, Ace {
+            ace_type,
+            ace_flags,
+            ace_size,
+            data
+        }))
+    }
 }
 
 /// Enum to get the same ouput for data switch in Ace structure.
@@ -299,13 +413,8 @@ bitflags! {
 impl ObjectAceFlags {
     named!(
         pub parse<ObjectAceFlags>,
-        do_parse!(
-            flags: le_u32
-            >> ({
-                // Will never fail
-                ObjectAceFlags::from_bits(flags).unwrap()
-            })
-        )
+        // The reserved bits set by some DCs are ignored
+        map!(le_u32, ObjectAceFlags::from_bits_truncate)
     );
 }
 
@@ -397,3 +506,156 @@ pub fn test_acl_admin() {
     assert_eq!(result.acl_size, 1140);
     println!("ACL: {:?}",result);
 }
+
+#[test]
+#[rustfmt::skip]
+pub fn test_ace_size() {
+    // ace_size lower than the ACE header
+    assert!(Ace::parse(&[0x00, 0x00, 0x02, 0x00, 0xbd, 0x01, 0x0f, 0x00]).is_err());
+    // ace_size greater than the remaining bytes
+    assert!(Ace::parse(&[0x00, 0x00, 0x18, 0x00, 0xbd, 0x01, 0x0f, 0x00]).is_err());
+    // SID longer than ace_size
+    assert!(Ace::parse(&[0x00, 0x00, 0x0c, 0x00, 0xbd, 0x01, 0x0f, 0x00, 0x01, 0x02, 0x00, 0x00, 0x00, 0x00, 0x00, 0x05]).is_err());
+    // Unknown ACE type (system audit) skipped, the next ACE is read after ace_size
+    let (rest, ace) = Ace::parse(&[0x02, 0x40, 0x08, 0x00, 0xff, 0xff, 0xff, 0xff, 0x00]).unwrap();
+    assert_eq!(rest, &[0x00]);
+    assert!(matches!(ace.data, AceFormat::Empty));
+    // acl_size lower than the ACL header
+    assert!(Acl::parse(&[0x04, 0x00, 0x04, 0x00, 0x00, 0x00, 0x00, 0x00]).is_err());
+}
+
+/// Deterministic fuzzing of the parsers: truncated, mutated and random buffers must return an error or a value, never panic.
+#[test]
+#[rustfmt::skip]
+pub fn test_secdesc_fuzz() {
+    let original = vec![
+        // SECURITY_DESCRIPTOR: owner at 20, DACL at 36
+        0x01, 0x00, 0x04, 0x8c, 0x14, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x24, 0x00, 0x00, 0x00,
+        // Owner SID
+        0x01, 0x02, 0x00, 0x00, 0x00, 0x00, 0x00, 0x05, 0x20, 0x00, 0x00, 0x00, 0x20, 0x02, 0x00, 0x00,
+        // ACL: 76 bytes, 2 ACEs
+        0x04, 0x00, 0x4c, 0x00, 0x02, 0x00, 0x00, 0x00,
+        0x00, 0x12, 0x18, 0x00, 0xbd, 0x01, 0x0f, 0x00,
+        0x01, 0x02, 0x00, 0x00, 0x00, 0x00, 0x00, 0x05, 0x20, 0x00, 0x00, 0x00, 0x20, 0x02, 0x00, 0x00,
+        0x05, 0x12, 0x2c, 0x00, 0x94, 0x00, 0x02, 0x00, 0x02, 0x00, 0x00, 0x00,
+        0xba, 0x7a, 0x96, 0xbf, 0xe6, 0x0d, 0xd0, 0x11, 0xa2, 0x85, 0x00, 0xaa, 0x00, 0x30, 0x49, 0xe2,
+        0x01, 0x02, 0x00, 0x00, 0x00, 0x00, 0x00, 0x05, 0x20, 0x00, 0x00, 0x00, 0x2a, 0x02, 0x00, 0x00,
+    ];
+    assert_eq!(Acl::parse(&original[36..]).unwrap().1.data.len(), 2);
+
+    let parse_all = |data: &[u8]| {
+        let _ = SecurityDescriptor::parse(data);
+        for offset in 0..data.len() {
+            let _ = LdapSid::parse(&data[offset..]);
+            let _ = Acl::parse(&data[offset..]);
+            let _ = Ace::parse(&data[offset..]);
+        }
+    };
+
+    // Every truncation
+    for len in 0..original.len() {
+        parse_all(&original[..len]);
+    }
+    // xorshift, the same buffers at every run
+    let mut state: u64 = 0x2545_f491_4f6c_dd1d;
+    let mut next = || {
+        state ^= state << 13;
+        state ^= state >> 7;
+        state ^= state << 17;
+        state
+    };
+    // Mutated bytes
+    for _ in 0..2000 {
+        let mut mutated = original.to_owned();
+        for _ in 0..(next() % 4 + 1) {
+            let position = next() as usize % mutated.len();
+            mutated[position] = next() as u8;
+        }
+        parse_all(&mutated);
+    }
+    // Random buffers
+    for _ in 0..200 {
+        let random: Vec<u8> = (0..next() % 128).map(|_| next() as u8).collect();
+        parse_all(&random);
+    }
+}
+
+#[test]
+#[rustfmt::skip]
+pub fn test_object_ace_reserved_flags() {
+    // ACCESS_ALLOWED_OBJECT_ACE with reserved bits in the flags (0x80000001)
+    let original_ace_object = vec![
+        0x05, 0x12, 0x2c, 0x00,
+            // Mask
+            0x94, 0x00, 0x02, 0x00,
+            // Flags: ACE_OBJECT_PRESENT and reserved bits
+            0x01, 0x00, 0x00, 0x80,
+            // Object GUID
+            0xba, 0x7a, 0x96, 0xbf, 0xe6, 0x0d, 0xd0, 0x11, 0xa2, 0x85, 0x00, 0xaa, 0x00, 0x30, 0x49, 0xe2,
+            // Sid
+            0x01, 0x02, 0x00, 0x00, 0x00, 0x00, 0x00, 0x05, 0x20, 0x00, 0x00, 0x00, 0x2a, 0x02, 0x00, 0x00
+    ];
+    let result = Ace::parse(&original_ace_object).unwrap().1;
+    assert_eq!(AceFormat::get_flags(result.data.to_owned()).unwrap(), ObjectAceFlags::ACE_OBJECT_PRESENT);
+    assert!(AceFormat::get_object_type(result.data.to_owned()).is_some());
+    assert!(AceFormat::get_inherited_object_type(result.data).is_none());
+
+    // Only reserved bits: no GUID
+    let flags = ObjectAceFlags::parse(&[0x00, 0xff, 0xff, 0xfc]).unwrap().1;
+    assert!(flags.is_empty());
+}
+
+#[test]
+pub fn test_sid_string() {
+    let raw = vec![0x01, 0x05, 0x00, 0x00, 0x00, 0x00, 0x00, 0x05, 0x15, 0x00, 0x00, 0x00, 0xe8, 0xc0, 0xf8, 0x7a, 0xfa, 0x0f, 0x9e, 0xac, 0x5c, 0xef, 0xbe, 0x73, 0x00, 0x02, 0x00, 0x00];
+    let sid = LdapSid::parse(&raw).unwrap().1;
+    assert_eq!(sid.to_string(), "S-1-5-21-2063122664-2896039930-1941892956-512");
+    let parsed: LdapSid = "S-1-5-21-2063122664-2896039930-1941892956-512".parse().unwrap();
+    assert_eq!(parsed.to_bytes(), raw);
+    assert_eq!("S-1-5-32-544".parse::<LdapSid>().unwrap().to_string(), "S-1-5-32-544");
+    assert_eq!("S-1-1-0".parse::<LdapSid>().unwrap().to_bytes(), vec![0x01, 0x01, 0, 0, 0, 0, 0, 0x01, 0, 0, 0, 0]);
+    // Authority greater than 32 bits
+    let hex = "S-1-0x0100000000FF-7".parse::<LdapSid>().unwrap();
+    assert_eq!(hex.authority(), 0x0100000000FF);
+    assert_eq!(hex.to_string(), "S-1-0x0100000000FF-7");
+
+    for invalid in ["", "S-1", "X-1-5-32", "S-1-5-abc", "S-1-5-4294967296", "S-1-0x01000000000000-1", "S-1-5-1-2-3-4-5-6-7-8-9-10-11-12-13-14-15-16"] {
+        assert!(invalid.parse::<LdapSid>().is_err(), "{}", invalid);
+    }
+}
+
+#[test]
+#[rustfmt::skip]
+pub fn test_parse_complete() {
+    let original = vec![
+        // SECURITY_DESCRIPTOR: owner at 20, group at 36, no SACL, DACL at 52
+        0x01, 0x00, 0x04, 0x8c, 0x14, 0x00, 0x00, 0x00, 0x24, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x34, 0x00, 0x00, 0x00,
+        // Owner: S-1-5-32-544
+        0x01, 0x02, 0x00, 0x00, 0x00, 0x00, 0x00, 0x05, 0x20, 0x00, 0x00, 0x00, 0x20, 0x02, 0x00, 0x00,
+        // Group: S-1-5-32-544
+        0x01, 0x02, 0x00, 0x00, 0x00, 0x00, 0x00, 0x05, 0x20, 0x00, 0x00, 0x00, 0x20, 0x02, 0x00, 0x00,
+        // DACL: 2 ACEs
+        0x04, 0x00, 0x4c, 0x00, 0x02, 0x00, 0x00, 0x00,
+        0x00, 0x12, 0x18, 0x00, 0xbd, 0x01, 0x0f, 0x00,
+        0x01, 0x02, 0x00, 0x00, 0x00, 0x00, 0x00, 0x05, 0x20, 0x00, 0x00, 0x00, 0x20, 0x02, 0x00, 0x00,
+        0x05, 0x12, 0x2c, 0x00, 0x94, 0x00, 0x02, 0x00, 0x02, 0x00, 0x00, 0x00,
+        0xba, 0x7a, 0x96, 0xbf, 0xe6, 0x0d, 0xd0, 0x11, 0xa2, 0x85, 0x00, 0xaa, 0x00, 0x30, 0x49, 0xe2,
+        0x01, 0x02, 0x00, 0x00, 0x00, 0x00, 0x00, 0x05, 0x20, 0x00, 0x00, 0x00, 0x2a, 0x02, 0x00, 0x00,
+    ];
+
+    let result = SecurityDescriptor::parse_complete(&original).unwrap();
+    assert_eq!(result.header.control, 0x8c04);
+    assert_eq!(result.owner.unwrap().to_string(), "S-1-5-32-544");
+    assert_eq!(result.group.unwrap().to_string(), "S-1-5-32-544");
+    assert!(result.sacl.is_none());
+    let dacl = result.dacl.unwrap();
+    assert_eq!(dacl.data.len(), 2);
+    assert_eq!(AceFormat::get_sid(dacl.data[1].data.to_owned()).unwrap().to_string(), "S-1-5-32-554");
+
+    // DACL offset out of the buffer
+    let mut broken = original.to_owned();
+    broken[16] = 0xff;
+    assert!(SecurityDescriptor::parse_complete(&broken).unwrap_err().contains("DACL offset 255"));
+    assert!(SecurityDescriptor::parse_complete(&original[..60]).is_err());
+    assert!(SecurityDescriptor::parse_complete(&original[..10]).is_err());
+}
@@ -2,29 +2,23 @@ use crate::enums::secdesc::LdapSid;
 //use log::trace;
 
 /// Function to make SID String from ldap_sid struct
+/// The well-known SIDs (S-1-5-32-544...) are prefixed with the domain like BloodHound.
 pub fn sid_maker(sid: LdapSid, domain: &String) -> String {
-    let mut sub: String = "".to_owned();
-    for v in sid.sub_authority {
-        sub.push_str(&"-".to_owned());
-        sub.push_str(&v.to_string());
+    let result = sid.to_string();
+    if result.len() <= 16 {
+        return format!("{}-{}", domain.to_uppercase(), result)
     }
+    result
+}
 
-    let mut result: String = "S-".to_owned();
-    result.push_str(&sid.revision.to_string());
-    result.push_str(&"-");
-    result.push_str(&sid.identifier_authority.value[5].to_string());
-    result.push_str(&sub);
-
-    let mut final_sid: String = "".to_owned();
-    if result.len() <= 16 {
-        final_sid.push_str(&domain.to_uppercase());
-        final_sid.push_str(&"-".to_owned());
-        final_sid.push_str(&result.to_owned());
-    } else {
-        final_sid = result;
+/// Function to make the primary group SID from the object SID and the primaryGroupID RID.
+pub fn primary_group_sid(sid: &str, rid: &str) -> Option<String> {
+    let rid = rid.trim().parse::<u32>().ok()?;
+    let (domain_sid, _) = sid.rsplit_once('-')?;
+    if !domain_sid.starts_with("S-1-5-21-") {
+        return None
     }
-    //trace!("sid_maker value: {}",final_sid);
-    return final_sid;
+    Some(format!("{}-{}", domain_sid, rid))
 }
 
 /// Change SID value to correct format.
@@ -160,54 +154,10 @@ pub fn bin_to_string(raw_guid: &Vec<u8>) -> String
     return str_guid  
 }
 
-/* Another way to decode objectSID binary to string value. 
-// src: https://docs.microsoft.com/en-us/openspecs/windows_protocols/ms-dtyp/f992ad60-0fe4-4b87-9fed-beb478836861
-pub fn decode_sid(raw_sid: &Vec<u8>, domain: &String) -> String
-{
-    let mut str_sid: String = "".to_owned();
-    if raw_sid.len() <= 16 {
-        str_sid.push_str(&domain.to_uppercase());
-        str_sid.push_str(&"-S-".to_owned());
-    }
-    else
-    {
-        str_sid.push_str(&"S-".to_owned());
-    }
-    
-    // get byte(0) - revision level
-    let revision = String::from(raw_sid[0].to_string());
-    str_sid.push_str(&revision);
-
-    //next byte byte(1) - count of sub-authorities
-    let count_sub_auths = usize::from(raw_sid[1]) & 0xFF;
-    
-    //byte(2-7) - 48 bit authority ([Big-Endian])
-    let mut authority = 0;
-    //String rid = "";
-    for i in 2..=7 
-    {
-        authority = (usize::from(raw_sid[i])) << (8 * (5 - (i - 2)));
-    }
-    str_sid.push_str(&"-".to_owned());
-    str_sid.push_str(&authority.to_string());
-
-    //iterate all the sub-auths and then countSubAuths x 32 bit sub authorities ([Little-Endian])
-    let mut offset = 8;
-    let size = 4; //4 bytes for each sub auth
-
-    for _j in 0..count_sub_auths
-    {
-        let mut sub_authority = 0;
-        for k in 0..size
-        {
-            sub_authority |= (usize::from(raw_sid[offset + k] & 0xFF)) << (8 * k);
-        }
-        // format it
-        str_sid.push_str(&"-".to_owned());
-        str_sid.push_str(&sub_authority.to_string());
-        offset += size;
-    }
-    
-    return str_sid   
+/// Test functions
+#[test]
+pub fn test_primary_group_sid() {
+    assert_eq!(primary_group_sid("S-1-5-21-1-2-3-1104", "513"), Some("S-1-5-21-1-2-3-513".to_string()));
+    assert_eq!(primary_group_sid("S-1-5-21-1-2-3-1104", ""), None);
+    assert_eq!(primary_group_sid("", "513"), None);
 }
-*/
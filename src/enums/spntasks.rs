@@ -1,3 +1,4 @@
+use std::collections::HashMap;
 use serde_json::json;
 use crate::json::templates::*;
 //use log::trace;
@@ -6,47 +7,136 @@ use crate::json::templates::*;
 /// <https://github.com/BloodHoundAD/SharpHound3/blob/master/SharpHound3/Tasks/SPNTasks.cs#L22>
 pub fn check_spn(serviceprincipalname: &String) -> serde_json::value::Value
 {
-   let mut mssqlsvc_spn = bh_41::prepare_mssqlsvc_spn_json_template();
-   if serviceprincipalname.to_lowercase().contains("mssqlsvc")
-   {
-      //trace!("{:?}",serviceprincipalname);
-      if serviceprincipalname.to_lowercase().contains(":")
-      {
-         let split = serviceprincipalname.split(":");
-         let vec = split.collect::<Vec<&str>>();
-         let mut fqdn = vec[0].to_owned();
-         let value = vec[1].to_owned();
-
-         //trace!("{:?}",value);
-         let port = value.parse::<i32>().unwrap_or(1433);
-
+   match parse_spn(serviceprincipalname) {
+      Some((service, host, port)) if service.eq_ignore_ascii_case("mssqlsvc") => {
          // I temporarily add the fqdn which will be replaced by the SID at the end of the parsing.
          // This avoids making a new request to the LDAP server and parsing off-line.
-         let split = fqdn.split("/");
-         let vec = split.collect::<Vec<&str>>();
-         fqdn = vec[1].to_owned().to_uppercase();
+         let mut mssqlsvc_spn = bh_41::prepare_mssqlsvc_spn_json_template();
+         mssqlsvc_spn["ComputerSID"] = host.into();
+         mssqlsvc_spn["Port"] = port.unwrap_or(1433).into();
+         mssqlsvc_spn
+      }
+      _ => json!({}),
+   }
+}
 
-         //trace!("{:?}",fqdn);
-         mssqlsvc_spn["ComputerSID"] = fqdn.into();
-         mssqlsvc_spn["Port"] = port.into();
+/// Function to make the SPNTargets of an account: SQLAdmin for every MSSQLSvc instance and HasSPNConfigured
+/// for every host of its SPNs, the hosts are replaced by the SID of the computers in the checker.
+pub fn spn_targets(serviceprincipalnames: &[String]) -> Vec<serde_json::value::Value>
+{
+   let mut targets: Vec<serde_json::value::Value> = Vec::new();
+   for serviceprincipalname in serviceprincipalnames {
+      let mssqlsvc_spn = check_spn(serviceprincipalname);
+      if mssqlsvc_spn.get("Port").is_some() && !targets.contains(&mssqlsvc_spn) {
+         targets.push(mssqlsvc_spn);
       }
-      else
-      {
-         // I temporarily add the fqdn which will be replaced by the SID at the end of the parsing.
-         // This avoids making a new request to the LDAP server and parsing off-line.
-         let split = serviceprincipalname.split("/");
-         let vec = split.collect::<Vec<&str>>();
-         let fqdn = vec[1].to_owned().to_uppercase();
-         let port = 1433;
-         
-         //trace!("{:?}",fqdn);
-         mssqlsvc_spn["ComputerSID"] = fqdn.into();
-         mssqlsvc_spn["Port"] = port.into();
+      if let Some((_, host, port)) = parse_spn(serviceprincipalname) {
+         if !targets.iter().any(|target| target["Service"] == "HasSPNConfigured" && target["ComputerSID"] == host.as_str()) {
+            targets.push(json!({ "ComputerSID": host, "Port": port, "Service": "HasSPNConfigured" }));
+         }
+      }
+   }
+   targets
+}
+
+/// Function to split a servicePrincipalName `service/host[:port][/name]` in (service, host, port).
+/// The host is uppercased without trailing dot, the port is None for a named instance (MSSQLSvc/SQL01:SQLEXPRESS).
+pub fn parse_spn(serviceprincipalname: &str) -> Option<(String, String, Option<u16>)>
+{
+   let mut parts = serviceprincipalname.trim().splitn(3, '/');
+   let service = parts.next()?.trim();
+   let host_port = parts.next()?.trim();
+   let (host, port) = match host_port.rsplit_once(':') {
+      Some((host, port)) => (host, port.parse::<u16>().ok()),
+      None => (host_port, None),
+   };
+   let host = host.trim_end_matches('.').to_uppercase();
+   if service.is_empty() || host.is_empty() {
+      return None
+   }
+   Some((service.to_string(), host, port))
+}
+
+/// Function to map the short hostnames to the FQDNs of the collected computers, the ambiguous names are left out.
+pub fn short_hostnames<'a>(fqdns: impl Iterator<Item = &'a String>) -> HashMap<String, String>
+{
+   let mut short_fqdn: HashMap<String, String> = HashMap::new();
+   let mut ambiguous: Vec<String> = Vec::new();
+   for fqdn in fqdns {
+      let fqdn = fqdn.to_uppercase();
+      let short = match fqdn.split_once('.') {
+         Some((short, _)) => short.to_string(),
+         None => continue,
+      };
+      match short_fqdn.get(&short) {
+         Some(known) if *known != fqdn => ambiguous.push(short),
+         _ => { short_fqdn.insert(short, fqdn); }
       }
    }
-   else
-   {
-      mssqlsvc_spn = json!({});
+   for short in ambiguous {
+      short_fqdn.remove(&short);
    }
-   return mssqlsvc_spn
-}
\ No newline at end of file
+   short_fqdn
+}
+
+/// Function to normalize the SPNs of an object: `service/FQDN[:port]` deduplicated, and the hosts they point at.
+pub fn normalize_spns(serviceprincipalnames: &[String], short_fqdn: &HashMap<String, String>) -> (Vec<String>, Vec<String>)
+{
+   let mut spns: Vec<String> = Vec::new();
+   let mut hosts: Vec<String> = Vec::new();
+   for (service, host, port) in serviceprincipalnames.iter().filter_map(|spn| parse_spn(spn)) {
+      let host = if host.contains('.') { host } else { short_fqdn.get(&host).cloned().unwrap_or(host) };
+      let spn = match port {
+         Some(port) => format!("{}/{}:{}", service, host, port),
+         None => format!("{}/{}", service, host),
+      };
+      if !spns.iter().any(|known| known.eq_ignore_ascii_case(&spn)) {
+         spns.push(spn);
+      }
+      if !hosts.contains(&host) {
+         hosts.push(host);
+      }
+   }
+   (spns, hosts)
+}
+
+/// Test functions
+#[test]
+pub fn test_parse_spn() {
+   assert_eq!(parse_spn("MSSQLSvc/sql01.essos.local:1433"), Some(("MSSQLSvc".to_string(), "SQL01.ESSOS.LOCAL".to_string(), Some(1433))));
+   assert_eq!(parse_spn("MSSQLSvc/SQL01:SQLEXPRESS"), Some(("MSSQLSvc".to_string(), "SQL01".to_string(), None)));
+   assert_eq!(parse_spn("ldap/meereen.essos.local./ESSOS"), Some(("ldap".to_string(), "MEEREEN.ESSOS.LOCAL".to_string(), None)));
+   assert_eq!(parse_spn("invalid"), None);
+
+   let computers = vec!["SQL01.ESSOS.LOCAL".to_string(), "WEB.ESSOS.LOCAL".to_string(), "WEB.SEVENKINGDOMS.LOCAL".to_string()];
+   let short_fqdn = short_hostnames(computers.iter());
+   assert_eq!(short_fqdn.get("SQL01").map(|fqdn| fqdn.as_str()), Some("SQL01.ESSOS.LOCAL"));
+   assert_eq!(short_fqdn.get("WEB"), None);
+
+   let spns = vec!["MSSQLSvc/SQL01:1433".to_string(), "MSSQLSvc/sql01.essos.local:1433".to_string(), "HTTP/web".to_string()];
+   let (spns, hosts) = normalize_spns(&spns, &short_fqdn);
+   assert_eq!(spns, vec!["MSSQLSvc/SQL01.ESSOS.LOCAL:1433", "HTTP/WEB"]);
+   assert_eq!(hosts, vec!["SQL01.ESSOS.LOCAL", "WEB"]);
+}
+
+#[test]
+pub fn test_spn_targets() {
+   assert_eq!(check_spn(&"MSSQLSvc/SQL01.essos.local:SQLEXPRESS".to_string())["Port"], 1433);
+   assert_eq!(check_spn(&"HTTP/web.essos.local".to_string()), json!({}));
+   let spns = vec![
+      "MSSQLSvc/sql01.essos.local:1433".to_string(),
+      "MSSQLSvc/sql01.essos.local".to_string(),
+      "MSSQLSvc/sql01.essos.local:1434".to_string(),
+      "HTTP/web.essos.local".to_string(),
+   ];
+   let targets = spn_targets(&spns);
+   let services: Vec<(&str, &str, i64)> = targets.iter().map(|target| (
+      target["Service"].as_str().unwrap(), target["ComputerSID"].as_str().unwrap(), target["Port"].as_i64().unwrap_or(0),
+   )).collect();
+   assert_eq!(services, vec![
+      ("SQLAdmin", "SQL01.ESSOS.LOCAL", 1433),
+      ("HasSPNConfigured", "SQL01.ESSOS.LOCAL", 1433),
+      ("SQLAdmin", "SQL01.ESSOS.LOCAL", 1434),
+      ("HasSPNConfigured", "WEB.ESSOS.LOCAL", 0),
+   ]);
+}
@@ -19,28 +19,30 @@ bitflags! {
 }
 
 /// Get the trust flags from "trustDomain".
+/// The bits unknown by RustHound are ignored.
 pub fn get_trust_flag(trustflag: u32, trust_json: &mut serde_json::value::Value)
 {
+   let flags = Flags::from_bits_truncate(trustflag);
    let mut is_transitive = false;
    let mut sid_filtering = false;
 
-   if (Flags::WITHIN_FOREST.bits() | trustflag) == trustflag
+   if flags.contains(Flags::WITHIN_FOREST)
    {
       let trust_type = "ParentChild"; //0 = ParentChild
       trust_json["TrustType"] = trust_type.into();
       is_transitive = true;
-      if (Flags::QUARANTINED_DOMAIN.bits() | trustflag) == trustflag {
+      if flags.contains(Flags::QUARANTINED_DOMAIN) {
          sid_filtering = true;
       }
    }
-   else if (Flags::FOREST_TRANSITIVE.bits() | trustflag) == trustflag
+   else if flags.contains(Flags::FOREST_TRANSITIVE)
    {
       let trust_type = "Forest"; //2 = Forest
       trust_json["TrustType"] = trust_type.into();
       is_transitive = true;
       sid_filtering = true;
    }
-   else if (Flags::TREAT_AS_EXTERNAL.bits() | trustflag) == trustflag || (Flags::CROSS_ORGANIZATION.bits() | trustflag) == trustflag
+   else if flags.contains(Flags::TREAT_AS_EXTERNAL) || flags.contains(Flags::CROSS_ORGANIZATION)
    {
       let trust_type = "External"; //3 = External
       trust_json["TrustType"] = trust_type.into();
@@ -51,7 +53,7 @@ pub fn get_trust_flag(trustflag: u32, trust_json: &mut serde_json::value::Value)
    {
       let trust_type = "Unknown"; //4 = Unknown
       trust_json["TrustType"] = trust_type.into();
-      if (Flags::NON_TRANSITIVE.bits() | trustflag) != trustflag {
+      if !flags.contains(Flags::NON_TRANSITIVE) {
          is_transitive = true;
       }
       sid_filtering = true;
@@ -60,4 +62,19 @@ pub fn get_trust_flag(trustflag: u32, trust_json: &mut serde_json::value::Value)
    // change value in mut vec json
    trust_json["SidFilteringEnabled"] = sid_filtering.into();
    trust_json["IsTransitive"] = is_transitive.into();
-}
\ No newline at end of file
+}
+/// Test functions
+#[test]
+pub fn test_trust_flags() {
+   let mut trust_json = serde_json::json!({});
+   // FOREST_TRANSITIVE with TRUST_ATTRIBUTE_ENABLE_TGT_DELEGATION (0x800) and reserved bits
+   get_trust_flag(0x00F00808, &mut trust_json);
+   assert_eq!(trust_json["TrustType"], "Forest");
+   assert_eq!(trust_json["IsTransitive"], true);
+
+   // Only unknown bits
+   get_trust_flag(0x80000000, &mut trust_json);
+   assert_eq!(trust_json["TrustType"], "Unknown");
+   assert_eq!(trust_json["IsTransitive"], true);
+   assert_eq!(trust_json["SidFilteringEnabled"], true);
+}
@@ -23,103 +23,178 @@ bitflags! {
         const DONT_REQ_PRE_AUTH = 0x400000;
         const PASSWORD_EXPIRED = 0x800000;
         const TRUSTED_TO_AUTH_FOR_DELEGATION = 0x1000000;
+        const NO_AUTH_DATA_REQUIRED = 0x2000000;
         const PARTIAL_SECRETS_ACCOUNT = 0x04000000;
     }
 }
 
 
+/// Get the flags value of an LDAP integer attribute (userAccountControl, trustAttributes...).
+/// LDAP integers are signed 32 bits: a value with the highest bit set is negative.
+pub fn parse_flags(value: &str) -> u32 {
+    value.trim().parse::<u32>()
+        .or_else(|_| value.trim().parse::<i32>().map(|value| value as u32))
+        .unwrap_or(0)
+}
+
 /// Get the UAC flags from "userAccountControl" LDAP attribut.
+/// The bits unknown by RustHound are ignored.
 pub fn get_flag(uac: u32) -> Vec<String>
 {
+    let flags = Flags::from_bits_truncate(uac);
     let mut uac_flags: Vec<String> = Vec::new();
 
-    if (Flags::SCRIPT.bits() | uac) == uac
+    if flags.contains(Flags::SCRIPT)
     {
         uac_flags.push("Script".to_string());
     }
-    if (Flags::ACCOUNT_DISABLE.bits() | uac) == uac
+    if flags.contains(Flags::ACCOUNT_DISABLE)
     {
         uac_flags.push("AccountDisable".to_string());
     }
-    if (Flags::HOME_DIR_REQUIRED.bits() | uac) == uac
+    if flags.contains(Flags::HOME_DIR_REQUIRED)
     {
         uac_flags.push("HomeDirRequired".to_string());
     }
-    if (Flags::LOCKOUT.bits() | uac) == uac
+    if flags.contains(Flags::LOCKOUT)
     {
         uac_flags.push("Lockout".to_string());
     }
-    if (Flags::PASSWORD_NOT_REQUIRED.bits() | uac) == uac
+    if flags.contains(Flags::PASSWORD_NOT_REQUIRED)
     {
         uac_flags.push("PasswordNotRequired".to_string());
     }
-    if (Flags::PASSWORD_CANT_CHANGE.bits() | uac) == uac
+    if flags.contains(Flags::PASSWORD_CANT_CHANGE)
     {
         uac_flags.push("PasswordCantChange".to_string());
     }
-    if (Flags::ENCRYPTED_TEXT_PWD_ALLOWED.bits() | uac) == uac
+    if flags.contains(Flags::ENCRYPTED_TEXT_PWD_ALLOWED)
     {
         uac_flags.push("EncryptedTextPwdAllowed".to_string());
     }
-    if (Flags::TEMP_DUPLICATE_ACCOUNT.bits() | uac) == uac
+    if flags.contains(Flags::TEMP_DUPLICATE_ACCOUNT)
     {
         uac_flags.push("TempDuplicateAccount".to_string());
     }
-    if (Flags::NORMAL_ACCOUNT.bits() | uac) == uac
+    if flags.contains(Flags::NORMAL_ACCOUNT)
     {
         uac_flags.push("NormalAccount".to_string());
     }
-    if (Flags::INTER_DOMAIN_TRUST_ACCOUNT.bits() | uac) == uac
+    if flags.contains(Flags::INTER_DOMAIN_TRUST_ACCOUNT)
     {
         uac_flags.push("InterdomainTrustAccount".to_string());
     }
-    if (Flags::WORKSTATION_TRUST_ACCOUNT.bits() | uac) == uac
+    if flags.contains(Flags::WORKSTATION_TRUST_ACCOUNT)
     {
         uac_flags.push("WorkstationTrustAccount".to_string());
     }
-    if (Flags::SERVER_TRUST_ACCOUNT.bits() | uac) == uac
+    if flags.contains(Flags::SERVER_TRUST_ACCOUNT)
     {
         uac_flags.push("ServerTrustAccount".to_string());
     }
-    if (Flags::DONT_EXPIRE_PASSWORD.bits() | uac) == uac
+    if flags.contains(Flags::DONT_EXPIRE_PASSWORD)
     {
         uac_flags.push("DontExpirePassword".to_string());
     }
-    if (Flags::MNS_LOGON_ACCOUNT.bits() | uac) == uac
+    if flags.contains(Flags::MNS_LOGON_ACCOUNT)
     {
         uac_flags.push("MnsLogonAccount".to_string());
     }
-    if (Flags::SMART_CARD_REQUIRED.bits() | uac) == uac
+    if flags.contains(Flags::SMART_CARD_REQUIRED)
     {
         uac_flags.push("SmartcardRequired".to_string());
     }
-    if (Flags::TRUSTED_FOR_DELEGATION.bits() | uac) == uac
+    if flags.contains(Flags::TRUSTED_FOR_DELEGATION)
     {
         uac_flags.push("TrustedForDelegation".to_string());
     }
-    if (Flags::NOT_DELEGATED.bits() | uac) == uac
+    if flags.contains(Flags::NOT_DELEGATED)
     {
         uac_flags.push("NotDelegated".to_string());
     }
-    if (Flags::USE_DES_KEY_ONLY.bits() | uac) == uac
+    if flags.contains(Flags::USE_DES_KEY_ONLY)
     {
         uac_flags.push("UseDesKeyOnly".to_string());
     }
-    if (Flags::DONT_REQ_PRE_AUTH.bits() | uac) == uac
+    if flags.contains(Flags::DONT_REQ_PRE_AUTH)
     {
         uac_flags.push("DontReqPreauth".to_string());
     }
-    if (Flags::PASSWORD_EXPIRED.bits() | uac) == uac
+    if flags.contains(Flags::PASSWORD_EXPIRED)
     {
         uac_flags.push("PasswordExpired".to_string());
     }
-    if (Flags::TRUSTED_TO_AUTH_FOR_DELEGATION.bits() | uac) == uac
+    if flags.contains(Flags::TRUSTED_TO_AUTH_FOR_DELEGATION)
     {
         uac_flags.push("TrustedToAuthForDelegation".to_string());
     }
-    if (Flags::PARTIAL_SECRETS_ACCOUNT.bits() | uac) == uac
+    if flags.contains(Flags::NO_AUTH_DATA_REQUIRED)
+    {
+        uac_flags.push("NoAuthDataRequired".to_string());
+    }
+    if flags.contains(Flags::PARTIAL_SECRETS_ACCOUNT)
     {
         uac_flags.push("PartialSecretsAccount".to_string());
     }
     return uac_flags
-}
\ No newline at end of file
+}
+
+/// Property of every UAC bit on the user and computer nodes, SharpHound names when SharpHound has the property.
+/// ACCOUNT_DISABLE is the "enabled" property. LOCKOUT has no property: the DC does not maintain the bit in
+/// userAccountControl, a locked account only has a lockoutTime, so the bit would always be false.
+const UAC_PROPERTIES: [(Flags, &str); 21] = [
+    (Flags::SCRIPT, "logonscriptenabled"),
+    (Flags::HOME_DIR_REQUIRED, "homedirrequired"),
+    (Flags::PASSWORD_NOT_REQUIRED, "passwordnotreqd"),
+    (Flags::PASSWORD_CANT_CHANGE, "passwordcantchange"),
+    (Flags::ENCRYPTED_TEXT_PWD_ALLOWED, "encryptedtextpwdallowed"),
+    (Flags::TEMP_DUPLICATE_ACCOUNT, "tempduplicateaccount"),
+    (Flags::NORMAL_ACCOUNT, "normalaccount"),
+    (Flags::INTER_DOMAIN_TRUST_ACCOUNT, "interdomaintrustaccount"),
+    (Flags::WORKSTATION_TRUST_ACCOUNT, "workstationtrustaccount"),
+    (Flags::SERVER_TRUST_ACCOUNT, "isdc"),
+    (Flags::DONT_EXPIRE_PASSWORD, "pwdneverexpires"),
+    (Flags::MNS_LOGON_ACCOUNT, "mnslogonaccount"),
+    (Flags::SMART_CARD_REQUIRED, "smartcardrequired"),
+    (Flags::TRUSTED_FOR_DELEGATION, "unconstraineddelegation"),
+    (Flags::NOT_DELEGATED, "sensitive"),
+    (Flags::USE_DES_KEY_ONLY, "usedeskeyonly"),
+    (Flags::DONT_REQ_PRE_AUTH, "dontreqpreauth"),
+    (Flags::PASSWORD_EXPIRED, "passwordexpired"),
+    (Flags::TRUSTED_TO_AUTH_FOR_DELEGATION, "trustedtoauth"),
+    (Flags::NO_AUTH_DATA_REQUIRED, "noauthdatarequired"),
+    (Flags::PARTIAL_SECRETS_ACCOUNT, "partialsecretsaccount"),
+];
+
+/// Get the boolean properties of "userAccountControl", every bit known by RustHound set to true or false.
+pub fn uac_properties(uac: u32) -> Vec<(&'static str, bool)>
+{
+    let flags = Flags::from_bits_truncate(uac);
+    let mut properties = vec![("enabled", !flags.contains(Flags::ACCOUNT_DISABLE))];
+    properties.extend(UAC_PROPERTIES.iter().map(|(flag, property)| (*property, flags.contains(*flag))));
+    properties
+}
+/// Test functions
+#[test]
+pub fn test_uac_flags() {
+    // NORMAL_ACCOUNT | DONT_EXPIRE_PASSWORD and USE_AES_KEYS (0x80000000), unknown by RustHound
+    let uac = parse_flags("-2147417600");
+    assert_eq!(uac, 0x80010200);
+    assert_eq!(get_flag(uac), vec!["NormalAccount", "DontExpirePassword"]);
+    assert_eq!(get_flag(parse_flags("34082816")), vec!["WorkstationTrustAccount", "TrustedForDelegation", "NoAuthDataRequired"]);
+    assert_eq!(parse_flags("not a number"), 0);
+}
+
+#[test]
+pub fn test_uac_properties() {
+    // ACCOUNT_DISABLE | NORMAL_ACCOUNT | NOT_DELEGATED | SMART_CARD_REQUIRED
+    let properties = uac_properties(0x140202);
+    assert_eq!(properties.len(), 22);
+    let enabled: Vec<&str> = properties.iter().filter(|(_, value)| *value).map(|(property, _)| *property).collect();
+    assert_eq!(enabled, vec!["normalaccount", "smartcardrequired", "sensitive"]);
+    assert_eq!(properties[0], ("enabled", false));
+    // Domain controller
+    assert!(uac_properties(0x82000).contains(&("isdc", true)));
+    assert!(uac_properties(0x82000).contains(&("unconstraineddelegation", true)));
+    assert!(!uac_properties(0x10).iter().any(|(property, _)| *property == "lockedout"));
+}
@@ -2,6 +2,7 @@
 use ldap3::LdapError;
 use std::error::Error as StdError;
 use std::fmt;
+use crate::secret::scrub;
 //use std::num::ParseIntError;
 use std::sync::Arc;
 
@@ -25,6 +26,8 @@ pub enum Kind {
     LdapError,
     /// Parse Error
     ParseError,
+    /// Upload of the zip archive failed, the archive is lost
+    Upload,
     /// Other
     Other,
 }
@@ -84,6 +87,7 @@ impl Error {
             Kind::Connection(Connection::Host) => "[!] LDAP Connection Failed, No Route To Host.\n",
             Kind::LdapError => &"LDAP Error.\n",
             Kind::ParseError => &"Parsing Json Error.\n",
+            Kind::Upload => "[!] Upload Failed, Zip Archive Not Sent.\n",
             Kind::Other => "[!] Other Error\n",
         }
     }
@@ -106,10 +110,10 @@ impl fmt::Debug for Error {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         f.write_str(self.kind_description())?;
         if let Some(ref desc) = self.desc {
-            write!(f, ": {}", desc)?;
+            write!(f, ": {}", scrub(desc))?;
         }
         if let Some(ref cause) = self.cause {
-            write!(f, ": {:?}", cause)?;
+            write!(f, ": {}", scrub(&format!("{:?}", cause)))?;
         }
         Ok(())
     }
@@ -119,10 +123,10 @@ impl fmt::Display for Error {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         f.write_str(self.kind_description())?;
         if let Some(ref desc) = self.desc {
-            write!(f, ": {}", desc)?;
+            write!(f, ": {}", scrub(desc))?;
         }
         if let Some(ref cause) = self.cause {
-            write!(f, ": {}", cause)?;
+            write!(f, ": {}", scrub(&cause.to_string()))?;
         }
         Ok(())
     }
@@ -0,0 +1,214 @@
+//! C entry point of the shared library
+//!
+//! The crate is also built as a C dynamic library (`librusthound.so`, `rusthound.dll`, `librusthound.dylib`) with
+//! `cargo build --release --lib`, to run a collection in-process from a program able to call a C function:
+//!
+//! ```c
+//! // config: JSON object of the long options of the command line, a flag is true, the verbosity is "v": 1 or 2
+//! // {"domain": "essos.local", "ldapusername": "khal.drogo", "ldappassword": "horse", "zip": true, "dirpath": "/tmp/out"}
+//! // return: JSON status {"status": "ok", "path": "/tmp/out"} or {"status": "error", "error": "..."}
+//! char *rusthound_collect(const char *config);
+//! // free the status returned by rusthound_collect and rusthound_collect_zip
+//! void rusthound_free(char *status);
+//!
+//! // same collection, the zip archive of the JSON files is returned in *zip instead of written in the output directory
+//! char *rusthound_collect_zip(const char *config, uint8_t **zip, size_t *zip_len);
+//! // free the archive returned by rusthound_collect_zip
+//! void rusthound_free_zip(uint8_t *zip, size_t zip_len);
+//! ```
+//!
+//! The archive is encrypted with "encrypt-output" in the config, the path of a recipient.pub file, or true with
+//! the password in RUSTHOUND_ENCRYPT_PASSWORD or "encrypt-password-file". The report modules and the sidecar files
+//! (skipped objects, metrics, crash log) are still written in the output directory.
+//! The password is read from the config or from RUSTHOUND_PASSWORD, there is no prompt in-process and the
+//! environment variables of the caller are never removed.
+//! A panic of the collection is returned as an error status, it never unwinds in the caller.
+//! Every call is a new run: the verbosity, "log-json", the progress counters and the stop request of the
+//! control socket are those of its config, not of a previous call. The control socket and the metrics endpoint
+//! are closed when the call returns. One collection runs at a time, a call made during another one returns
+//! `{"status": "error", "error": "busy"}`.
+use serde_json::value::Value;
+use std::ffi::{CStr, CString};
+use std::os::raw::c_char;
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::sync::{Mutex, Once, TryLockError};
+use zeroize::Zeroizing;
+
+use crate::args::extract_args_from;
+use crate::modules::{set_log_json, ControlLogger};
+use crate::run::run_collection;
+
+static LOGGER: Once = Once::new();
+/// Held during a collection, the run state of the modules is global to the process.
+static RUNNING: Mutex<()> = Mutex::new(());
+
+/// Function to convert the JSON config in a command line:
+/// `{"domain": "essos.local", "zip": true, "v": 2}` is `rusthound --domain=essos.local --zip -vv`.
+pub fn config_args(config: &str) -> Result<Vec<String>, String> {
+   let config: Value = serde_json::from_str(config).map_err(|err| format!("invalid JSON config: {err}"))?;
+   let options = config.as_object().ok_or("the config is not a JSON object")?;
+   let mut args = vec!["rusthound".to_string()];
+   for (name, value) in options {
+      match value {
+         Value::Bool(true) => args.push(format!("--{}", name)),
+         Value::Bool(false) | Value::Null => {}
+         Value::Number(count) if name == "v" => args.push(format!("-{}", "v".repeat(count.as_u64().unwrap_or(1).clamp(1, 2) as usize))),
+         // --name=value, a value starting with - is not taken for an option
+         Value::String(value) => args.push(format!("--{}={}", name, value)),
+         Value::Number(value) => args.push(format!("--{}={}", name, value)),
+         _ => return Err(format!("unsupported value of {}", name)),
+      }
+   }
+   Ok(args)
+}
+
+fn error_status(error: &str) -> Value {
+   serde_json::json!({ "status": "error", "error": error })
+}
+
+/// Function to run one collection with a JSON config, return the JSON status.
+pub fn collect_json(config: &str) -> Value {
+   collect_config(config, false).0
+}
+
+/// Function to run one collection with a JSON config, return the JSON status and the zip archive with in_memory.
+pub fn collect_config(config: &str, in_memory: bool) -> (Value, Option<Vec<u8>>) {
+   // A panic of a previous call left the lock poisoned, the run state is reset by every run
+   let _running = match RUNNING.try_lock() {
+      Ok(running) => running,
+      Err(TryLockError::Poisoned(poisoned)) => poisoned.into_inner(),
+      Err(TryLockError::WouldBlock) => return (error_status("busy"), None),
+   };
+   let args = match config_args(config) {
+      Ok(args) => Zeroizing::new(args),
+      Err(err) => return (error_status(&err), None),
+   };
+   let mut options = match extract_args_from(args.iter()) {
+      Ok(options) => options,
+      Err(err) => return (error_status(&err.message), None),
+   };
+   options.in_memory = in_memory;
+   options.non_interactive = true;
+   // One logger for the process, open to every level: the verbosity of each call is the global max level
+   LOGGER.call_once(|| {
+      ControlLogger::init(env_logger::Builder::new()
+         .filter(Some("rusthound"), log::LevelFilter::Trace)
+         .filter_level(log::LevelFilter::Error)
+         .build());
+      crate::secret::install_panic_hook();
+   });
+   log::set_max_level(options.verbose);
+   set_log_json(options.log_json);
+   let path = options.path.to_owned();
+   let runtime = match tokio::runtime::Runtime::new() {
+      Ok(runtime) => runtime,
+      Err(err) => return (error_status(&format!("tokio runtime not started: {err}")), None),
+   };
+   match runtime.block_on(run_collection(options)) {
+      Ok(Some(archive)) => (serde_json::json!({ "status": "ok", "size": archive.len() }), Some(archive)),
+      Ok(None) if in_memory => (error_status("no archive, nothing collected"), None),
+      Ok(None) => (serde_json::json!({ "status": "ok", "path": path }), None),
+      Err(err) => (error_status(&err.to_string()), None),
+   }
+}
+
+fn status_string(status: Value) -> *mut c_char {
+   CString::new(status.to_string()).map(CString::into_raw).unwrap_or(std::ptr::null_mut())
+}
+
+/// Function to run one collection with a JSON config (UTF-8, NUL terminated), return the JSON status.
+///
+/// # Safety
+///
+/// `config` is NULL or a valid NUL terminated string. The status is freed with `rusthound_free`.
+#[no_mangle]
+pub unsafe extern "C" fn rusthound_collect(config: *const c_char) -> *mut c_char {
+   let status = if config.is_null() {
+      error_status("no config")
+   } else {
+      let config = Zeroizing::new(CStr::from_ptr(config).to_string_lossy().into_owned());
+      catch_unwind(AssertUnwindSafe(|| collect_json(&config))).unwrap_or_else(|_| error_status("collection panicked"))
+   };
+   status_string(status)
+}
+
+/// Function to run one collection with a JSON config, the zip archive is returned in `zip` and `zip_len`
+/// (NULL and 0 on error), return the JSON status.
+///
+/// # Safety
+///
+/// `config` is NULL or a valid NUL terminated string, `zip` and `zip_len` are valid pointers. The status is freed
+/// with `rusthound_free`, the archive with `rusthound_free_zip`.
+#[no_mangle]
+pub unsafe extern "C" fn rusthound_collect_zip(config: *const c_char, zip: *mut *mut u8, zip_len: *mut usize) -> *mut c_char {
+   if zip.is_null() || zip_len.is_null() {
+      return status_string(error_status("no zip output pointers"))
+   }
+   *zip = std::ptr::null_mut();
+   *zip_len = 0;
+   if config.is_null() {
+      return status_string(error_status("no config"))
+   }
+   let config = Zeroizing::new(CStr::from_ptr(config).to_string_lossy().into_owned());
+   let (status, archive) = catch_unwind(AssertUnwindSafe(|| collect_config(&config, true)))
+      .unwrap_or_else(|_| (error_status("collection panicked"), None));
+   if let Some(archive) = archive {
+      *zip_len = archive.len();
+      *zip = Box::into_raw(archive.into_boxed_slice()) as *mut u8;
+   }
+   status_string(status)
+}
+
+/// Function to free a status returned by `rusthound_collect`.
+///
+/// # Safety
+///
+/// `status` is NULL or a pointer returned by `rusthound_collect`, freed once.
+#[no_mangle]
+pub unsafe extern "C" fn rusthound_free(status: *mut c_char) {
+   if !status.is_null() {
+      drop(CString::from_raw(status));
+   }
+}
+
+/// Function to free an archive returned by `rusthound_collect_zip`.
+///
+/// # Safety
+///
+/// `zip` is NULL or the archive pointer returned by `rusthound_collect_zip` with its length, freed once.
+#[no_mangle]
+pub unsafe extern "C" fn rusthound_free_zip(zip: *mut u8, zip_len: usize) {
+   if !zip.is_null() {
+      drop(Box::from_raw(std::ptr::slice_from_raw_parts_mut(zip, zip_len)));
+   }
+}
+
+/// Test functions
+#[test]
+pub fn test_config_args() {
+   let args = config_args(r#"{"domain": "essos.local", "ldappassword": "-horse", "zip": true, "ldaps": false, "v": 2, "stale-days": 90}"#).unwrap();
+   assert_eq!(args, vec!["rusthound", "--domain=essos.local", "--ldappassword=-horse", "--zip", "-vv", "--stale-days=90"]);
+   let options = extract_args_from(args.iter()).unwrap();
+   assert_eq!(options.domain, "essos.local");
+   assert_eq!(options.password.unwrap().expose(), "-horse");
+   assert!(options.zip);
+   assert_eq!(options.verbose, log::LevelFilter::Trace);
+   assert!(config_args("[\"--domain\"]").is_err());
+   assert!(config_args(r#"{"targets": ["a", "b"]}"#).is_err());
+
+   let status = collect_json(r#"{"not-an-option": true}"#);
+   assert_eq!(status["status"], "error");
+   let running = RUNNING.lock().unwrap();
+   assert_eq!(collect_json(r#"{"domain": "essos.local"}"#).to_string(), r#"{"status":"error","error":"busy"}"#);
+   drop(running);
+   let status = unsafe { rusthound_collect(std::ptr::null()) };
+   assert_eq!(unsafe { CStr::from_ptr(status) }.to_str().unwrap(), r#"{"status":"error","error":"no config"}"#);
+   unsafe { rusthound_free(status) };
+
+   let (mut zip, mut zip_len) = (std::ptr::null_mut(), 0);
+   let status = unsafe { rusthound_collect_zip(std::ptr::null(), &mut zip, &mut zip_len) };
+   assert!(zip.is_null() && zip_len == 0);
+   unsafe { rusthound_free(status) };
+   let archive = Box::into_raw(vec![0x50u8, 0x4b, 3, 4].into_boxed_slice()) as *mut u8;
+   unsafe { rusthound_free_zip(archive, 4) };
+}
@@ -1,11 +1,25 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use crate::json::checker::lookup::{LookupMap, par_for_each};
 use regex::Regex;
 //use log::{info,debug,trace};
+use log::warn;
 use crate::json::templates::*;
 use crate::ldap::prepare_ldap_dc;
-use indicatif::ProgressBar;
+use crate::enums::spntasks::normalize_spns;
+use crate::banner::ProgressBar;
 use crate::banner::progress_bar;
+use crate::modules::synthetic_edges::add_edge;
 use std::convert::TryInto;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// Well-known high value groups: Domain Admins, Domain Controllers, Schema Admins, Enterprise Admins,
+/// Key Admins, Enterprise Key Admins, Administrators, Account/Server/Print/Backup Operators, Enterprise Domain Controllers.
+const HIGH_VALUE_SID_SUFFIXES: [&str; 12] = ["-512", "-516", "-518", "-519", "-526", "-527", "-S-1-5-32-544", "-S-1-5-32-548", "-S-1-5-32-549", "-S-1-5-32-550", "-S-1-5-32-551", "-S-1-5-9"];
+/// Exchange groups controlling the domain: Exchange Windows Permissions holds WriteDacl on the domain (PrivExchange),
+/// Exchange Trusted Subsystem is one of its members and Organization Management manages both.
+const EXCHANGE_HIGH_VALUE_GROUPS: [&str; 3] = ["EXCHANGE WINDOWS PERMISSIONS", "EXCHANGE TRUSTED SUBSYSTEM", "ORGANIZATION MANAGEMENT"];
+/// OU of the Exchange security groups.
+pub const EXCHANGE_GROUPS_OU: &str = "OU=MICROSOFT EXCHANGE SECURITY GROUPS,";
 
 /// Function to add default groups
 /// <https://github.com/fox-it/BloodHound.py/blob/645082e3462c93f31b571db945cde1fd7b837fb9/bloodhound/enumeration/memberships.py#L411>
@@ -212,94 +226,65 @@ pub fn add_default_users(vec_users: &mut Vec<serde_json::value::Value>, domain:
     name.push_str(&domain.to_uppercase());
     ntauthority_user["Properties"]["name"] = name.into();
     ntauthority_user["ObjectIdentifier"] = sid.into();
-    ntauthority_user["Properties"]["domainsid"] = vec_users[0]["Properties"]["domainsid"].as_str().unwrap().to_string().into();
+    // vec_users can be empty with --ldap-filter or --search-base
+    let domainsid = vec_users.first().and_then(|user| user["Properties"]["domainsid"].as_str()).unwrap_or("").to_string();
+    ntauthority_user["Properties"]["domainsid"] = domainsid.into();
 
     vec_users.push(ntauthority_user);
 }
 
 /// This function is to push user SID in ChildObjects bh4.1+
-pub fn add_childobjects_members(vec_replaced: &mut Vec<serde_json::value::Value>, dn_sid: &HashMap<String, String>,  sid_type: &HashMap<String, String>)
+/// The AffectedComputers of an OU stop at the child OUs blocking the inheritance, unless one of its links is enforced.
+pub fn add_childobjects_members(vec_replaced: &mut Vec<serde_json::value::Value>, dn_sid: &LookupMap,  sid_type: &LookupMap, blocking: &HashSet<String>)
 {
     // Needed for progress bar stats
     let pb = ProgressBar::new(1);
-    let mut count = 0;
-    let total = vec_replaced.len();
+    let count = AtomicU64::new(0);
+    let total = vec_replaced.len() as u64;
         
     //trace!("add_childobjects_members");
 
-    for object in vec_replaced
+    // dn_sid and sid_type are frozen, the objects are split between threads
+    par_for_each(vec_replaced, |object|
     {
         // Manage progress bar
-		count += 1;
-        let pourcentage = 100 * count / total;
-        progress_bar(pb.to_owned(),"Adding childobjects members".to_string(),pourcentage.try_into().unwrap(),"%".to_string());
+        let pourcentage = 100 * (count.fetch_add(1, Ordering::Relaxed) + 1) / total;
+        progress_bar(pb.to_owned(),"Adding childobjects members".to_string(),pourcentage,"%".to_string());
 
         let mut direct_members: Vec<serde_json::value::Value> = Vec::new();
         let mut affected_computers: Vec<serde_json::value::Value> = Vec::new();
 
         let null: String = "NULL".to_string();
         let dn = object["Properties"]["distinguishedname"].as_str().unwrap().to_string().to_uppercase();
-        let mut name = object["Properties"]["name"].as_str().unwrap().to_string();
         let sid = dn_sid.get(&object["Properties"]["distinguishedname"].as_str().unwrap().to_string()).unwrap_or(&null);
-        let otype = sid_type.get(sid).unwrap();
+        let otype = sid_type.get(sid).map(|t| t.as_str()).unwrap_or("Container");
         //trace!("SID OBJECT: {:?} : {:?} : {:?}",&dn,&sid,&otype);
+        let enforced = has_enforced_link(object);
 
-        if otype != "Domain"
-        {
-            let split = name.split("@");
-            let vec = split.collect::<Vec<&str>>();
-            name = vec[0].to_owned();
-        }
-
+        // Contains edges for the direct children, GPOs linked to an OU apply to every computer below it
+        let subtree = format!(",{}", &dn);
         for value in dn_sid 
         {
-            let dn_object = value.0.to_string().to_uppercase();
-            //trace!("{:?}", &dn_object);
-            let split = dn_object.split(",");
-            let vec = split.collect::<Vec<&str>>();
-            let mut first = vec[1].to_owned();
-            //trace!("{:?}", &first);
-            let split = first.split("=");
-            let vec = split.collect::<Vec<&str>>();
-            if vec.len() >= 2 {
-                //trace!("{:?}", &vec.len());
-                first = vec[1].to_owned();
-            }
-            else
+            let dn_object = value.0.to_uppercase();
+            if !dn_object.ends_with(&subtree)
             {
                 continue
             }
-            //trace!("{:?}", &first);
+            let object_type = match sid_type.get(value.1) {
+                Some(object_type) => object_type,
+                None => continue,
+            };
+            let mut member = bh_41::prepare_member_json_template();
+            member["ObjectIdentifier"] = value.1.to_owned().into();
+            member["ObjectType"] = object_type.to_owned().into();
 
-            if otype != "Domain"{
-                if (dn_object.contains(&dn)) && (&dn_object != &dn) && (&first == &name)
-                {
-                    let mut object = bh_41::prepare_member_json_template();
-                    object["ObjectIdentifier"] = value.1.as_str().to_string().into();
-                    let object_type = sid_type.get(&value.1.as_str().to_string()).unwrap();
-                    object["ObjectType"] = object_type.to_string().into();
-                    direct_members.push(object.to_owned());
-
-                    // if the direct object is one computer add it in affected_computers to push it in OU 
-                    if object_type.to_string() == "Computer" 
-                    {
-                        affected_computers.push(object.to_owned());
-                    }
-                }
+            if object_type == "Computer" && (enforced || !is_blocked_below(&dn_object, &dn, blocking))
+            {
+                affected_computers.push(member.to_owned());
             }
-            else
+            if parent_dn(&dn_object) == Some(dn.as_str())
             {
-                let mut object = bh_41::prepare_member_json_template();                 
-                let split = name.split(".");
-                let vec = split.collect::<Vec<&str>>();
-                let cn = vec[0].to_owned();
-                if first.contains(&cn)
-                {
-                    object["ObjectIdentifier"] = value.1.as_str().to_string().into();
-                    let object_type = sid_type.get(&value.1.as_str().to_string()).unwrap();
-                    object["ObjectType"] = object_type.to_string().into();
-                    direct_members.push(object);
-                }
+                direct_members.push(member);
             }
         }
         //trace!("direct_members for Object '{}': {:?}",name,direct_members);
@@ -309,12 +294,413 @@ pub fn add_childobjects_members(vec_replaced: &mut Vec<serde_json::value::Value>
         {
             object["GPOChanges"]["AffectedComputers"] = affected_computers.into();
         }
-    }
+    });
     pb.finish_and_clear();
 }
 
+/// Function to get the DNs (uppercase) of the OUs blocking the GPO inheritance.
+pub fn blocking_ous(vec_ous: &[serde_json::value::Value]) -> HashSet<String>
+{
+    vec_ous.iter()
+        .filter(|ou| ou["Properties"]["blocksinheritance"].as_bool().unwrap_or(false))
+        .filter_map(|ou| ou["Properties"]["distinguishedname"].as_str().map(|dn| dn.to_uppercase()))
+        .collect()
+}
+
+/// Function to know if one of the OUs between an object and the container of the link blocks the inheritance.
+/// The container itself is not checked, its own links apply even when it blocks the inheritance.
+pub fn is_blocked_below(dn_object: &str, dn_container: &str, blocking: &HashSet<String>) -> bool
+{
+    let mut current = parent_dn(dn_object);
+    while let Some(dn) = current {
+        if dn == dn_container {
+            return false
+        }
+        if blocking.contains(dn) {
+            return true
+        }
+        current = parent_dn(dn);
+    }
+    false
+}
+
+/// Function to know if one of the GPO links of an OU or a domain is enforced.
+fn has_enforced_link(object: &serde_json::value::Value) -> bool
+{
+    object["Links"].as_array().into_iter().flatten().any(|link| link["IsEnforced"].as_bool().unwrap_or(false))
+}
+
+/// This function sets probably_stale on the computers: disabled, or no logon and no machine password
+/// change since stale_days (lastLogonTimestamp is replicated with up to 14 days of delay).
+/// A computer that never logged on is stale when created before the threshold.
+pub fn add_stale_computers(vec_computers: &mut Vec<serde_json::value::Value>, stale_days: u32, now: i64)
+{
+    let threshold = now - stale_days as i64 * 24 * 60 * 60;
+    for computer in vec_computers.iter_mut()
+    {
+        let enabled = computer["Properties"]["enabled"].as_bool().unwrap_or(true);
+        let last_activity = [
+            computer["Properties"]["lastlogontimestamp"].as_i64().unwrap_or(-1),
+            computer["Properties"]["pwdlastset"].as_i64().unwrap_or(-1),
+        ].iter().copied().max().unwrap_or(-1);
+        let last_activity = if last_activity > 0 { last_activity } else { computer["Properties"]["whencreated"].as_i64().unwrap_or(-1) };
+        let stale = !enabled || (last_activity > 0 && last_activity < threshold);
+        computer["Properties"]["probably_stale"] = stale.into();
+    }
+}
+
+/// This function adds the SPNs normalized as service/FQDN[:port] (normalizedspns) and the hosts they point
+/// at (spnhosts), the short hostnames are resolved with the names of the collected computers (short_fqdn).
+pub fn add_normalized_spns(vec_src: &mut Vec<serde_json::value::Value>, short_fqdn: &HashMap<String, String>)
+{
+    for object in vec_src.iter_mut()
+    {
+        let serviceprincipalnames: Vec<String> = match object["Properties"]["serviceprincipalnames"].as_array() {
+            Some(spns) => spns.iter().filter_map(|spn| spn.as_str().map(|spn| spn.to_string())).collect(),
+            None => continue,
+        };
+        let (spns, hosts) = normalize_spns(&serviceprincipalnames, short_fqdn);
+        object["Properties"]["normalizedspns"] = spns.into();
+        object["Properties"]["spnhosts"] = hosts.into();
+    }
+}
+
+/// This function adds the users and computers in the Members of their primary group (PrimaryGroupSID).
+/// The primary group membership is not in the member attribute, the MemberOf edge of Domain Users,
+/// Domain Computers or Domain Controllers is emitted from both sides.
+pub fn add_primary_group_members(
+    vec_groups: &mut Vec<serde_json::value::Value>,
+    vec_users: &Vec<serde_json::value::Value>,
+    vec_computers: &Vec<serde_json::value::Value>,
+)
+{
+    let mut primary_members: PrimaryMembers = HashMap::new();
+    for (object_type, objects) in [("User", vec_users), ("Computer", vec_computers)] {
+        for object in objects.iter() {
+            add_primary_member(&mut primary_members, object, object_type);
+        }
+    }
+    set_primary_group_members(vec_groups, &primary_members);
+}
+
+/// Primary group SID => members (SID, type).
+pub type PrimaryMembers = HashMap<String, Vec<(String, &'static str)>>;
+
+/// Function to keep the primary group of one user or computer, added in the group by [`set_primary_group_members`].
+pub fn add_primary_member(primary_members: &mut PrimaryMembers, object: &serde_json::value::Value, object_type: &'static str)
+{
+    if let (Some(sid), Some(group)) = (object["ObjectIdentifier"].as_str(), object["PrimaryGroupSID"].as_str()) {
+        primary_members.entry(group.to_string()).or_default().push((sid.to_string(), object_type));
+    }
+}
+
+/// Function to add the users and computers kept by [`add_primary_member`] in the Members of their primary group.
+pub fn set_primary_group_members(vec_groups: &mut Vec<serde_json::value::Value>, primary_members: &PrimaryMembers)
+{
+    for group in vec_groups.iter_mut()
+    {
+        let sid = group["ObjectIdentifier"].as_str().unwrap_or("").to_string();
+        let members = match primary_members.get(&sid) {
+            Some(members) => members,
+            None => continue,
+        };
+        let known: HashSet<String> = group["Members"].as_array().into_iter().flatten()
+            .filter_map(|member| member["ObjectIdentifier"].as_str().map(|sid| sid.to_string()))
+            .collect();
+        if !group["Members"].is_array() {
+            group["Members"] = serde_json::json!([]);
+        }
+        let group_members = group["Members"].as_array_mut().unwrap();
+        for (member, object_type) in members {
+            if !known.contains(member) {
+                group_members.push(serde_json::json!({"ObjectIdentifier": member, "ObjectType": object_type}));
+            }
+        }
+    }
+}
+
+/// Computer properties holding DNs until the checker, replaced by SIDs.
+const RODC_DN_PROPERTIES: [&str; 5] = ["managedby", "rodckrbtgt", "rodcrevealedusers", "rodcallowedtoreveal", "rodcdeniedtoreveal"];
+
+/// This function replaces the RODC DNs by SIDs and sets what each read-only DC caches and who manages it:
+/// - the managedBy principal is local administrator of the RODC (AdminTo edge),
+/// - the accounts whose secrets are cached (msDS-RevealedUsers) get the RODCs in `revealedonrodcs`,
+///   an administrator of one of these RODCs can dump their hashes.
+pub fn add_rodc_properties(
+    vec_computers: &mut Vec<serde_json::value::Value>,
+    vec_users: &mut Vec<serde_json::value::Value>,
+    dn_sid: &LookupMap,
+    sid_type: &LookupMap,
+)
+{
+    let mut revealed_on: HashMap<String, Vec<String>> = HashMap::new();
+    resolve_rodc_properties(vec_computers, dn_sid, sid_type, &mut revealed_on);
+    add_revealed_on_rodcs(vec_users, &revealed_on);
+    add_revealed_on_rodcs(vec_computers, &revealed_on);
+}
+
+/// Function to replace the RODC DNs of the computers by SIDs, the RODCs caching each account are added in revealed_on.
+pub fn resolve_rodc_properties(
+    vec_computers: &mut Vec<serde_json::value::Value>,
+    dn_sid: &LookupMap,
+    sid_type: &LookupMap,
+    revealed_on: &mut HashMap<String, Vec<String>>,
+)
+{
+    for computer in vec_computers.iter_mut()
+    {
+        for property in RODC_DN_PROPERTIES {
+            let resolved = match &computer["Properties"][property] {
+                serde_json::value::Value::String(dn) => dn_sid.get(dn).map(|sid| sid.to_owned().into()),
+                serde_json::value::Value::Array(dns) => Some(dns.iter().filter_map(|dn| dn.as_str().and_then(|dn| dn_sid.get(dn)).cloned()).collect::<Vec<String>>().into()),
+                _ => continue,
+            };
+            match (resolved, computer["Properties"].as_object_mut()) {
+                (Some(resolved), Some(properties)) => { properties.insert(property.to_string(), resolved); }
+                (None, Some(properties)) => { properties.remove(property); }
+                _ => {}
+            }
+        }
+        if !computer["Properties"]["isrodc"].as_bool().unwrap_or(false)
+        {
+            continue
+        }
+        let name = computer["Properties"]["name"].as_str().unwrap_or("").to_string();
+        if let Some(manager) = computer["Properties"]["managedby"].as_str().map(|sid| sid.to_string())
+        {
+            let mut member = bh_41::prepare_member_json_template();
+            member["ObjectIdentifier"] = manager.to_owned().into();
+            member["ObjectType"] = sid_type.get(&manager).map(|t| t.as_str()).unwrap_or("Group").into();
+            if let Some(results) = computer["LocalAdmins"]["Results"].as_array_mut().filter(|results| !results.contains(&member)) {
+                results.push(member);
+            }
+            computer["LocalAdmins"]["Collected"] = true.into();
+        }
+        for sid in computer["Properties"]["rodcrevealedusers"].as_array().into_iter().flatten().filter_map(|sid| sid.as_str())
+        {
+            revealed_on.entry(sid.to_string()).or_default().push(name.to_owned());
+        }
+    }
+}
+
+/// Function to set `revealedonrodcs` on the accounts cached by the RODCs of [`resolve_rodc_properties`].
+pub fn add_revealed_on_rodcs(vec_objects: &mut Vec<serde_json::value::Value>, revealed_on: &HashMap<String, Vec<String>>)
+{
+    for object in vec_objects.iter_mut()
+    {
+        if let Some(rodcs) = object["ObjectIdentifier"].as_str().and_then(|sid| revealed_on.get(sid))
+        {
+            object["Properties"]["revealedonrodcs"] = rodcs.to_owned().into();
+        }
+    }
+}
+
+/// This function links the computers and the (group) managed service accounts they use, from msDS-HostServiceAccount
+/// on the computers and its back link on the accounts: `hostedserviceaccounts` and `hostcomputers` replaced by SIDs,
+/// and a HostsServiceAccount edge from the computer to the account. The service runs with the account on the
+/// computer, its password is readable from the computer (ReadGMSAPassword) or its LSA secrets.
+pub fn add_service_account_hosts(
+    vec_users: &mut Vec<serde_json::value::Value>,
+    vec_computers: &mut Vec<serde_json::value::Value>,
+    dn_sid: &LookupMap,
+)
+{
+    // (computer SID, account SID)
+    let mut links: HashSet<(String, String)> = HashSet::new();
+    for computer in vec_computers.iter() {
+        add_service_account_links(&mut links, computer, "Computer", dn_sid);
+    }
+    for user in vec_users.iter() {
+        add_service_account_links(&mut links, user, "User", dn_sid);
+    }
+    let mut links: Vec<(String, String)> = links.into_iter().collect();
+    links.sort();
+    set_service_account_hosts(vec_users, vec_computers, &links);
+}
+
+/// Function to add the (computer SID, account SID) links of one computer (hostedserviceaccounts) or one user (hostcomputers).
+/// Return true when the object has the property, replaced by [`set_service_account_hosts`].
+pub fn add_service_account_links(links: &mut HashSet<(String, String)>, object: &serde_json::value::Value, object_type: &str, dn_sid: &LookupMap) -> bool
+{
+    let property = if object_type == "Computer" { "hostedserviceaccounts" } else { "hostcomputers" };
+    let sid = object["ObjectIdentifier"].as_str().unwrap_or("").to_string();
+    let resolved = object["Properties"][property].as_array().into_iter().flatten()
+        .filter_map(|dn| dn.as_str().and_then(|dn| dn_sid.get(dn)).cloned());
+    if object_type == "Computer" {
+        links.extend(resolved.map(|account| (sid.to_owned(), account)));
+    } else {
+        links.extend(resolved.map(|computer| (computer, sid.to_owned())));
+    }
+    object["Properties"].get(property).is_some()
+}
+
+/// Function to replace `hostedserviceaccounts` and `hostcomputers` by the SIDs of the sorted links, with the
+/// HostsServiceAccount edges. The users and the computers can be given apart, one of them empty.
+pub fn set_service_account_hosts(
+    vec_users: &mut Vec<serde_json::value::Value>,
+    vec_computers: &mut Vec<serde_json::value::Value>,
+    links: &Vec<(String, String)>,
+)
+{
+    for computer in vec_computers.iter_mut().filter(|computer| computer["Properties"].get("hostedserviceaccounts").is_some()) {
+        computer["Properties"]["hostedserviceaccounts"] = serde_json::json!([]);
+    }
+    for user in vec_users.iter_mut().filter(|user| user["Properties"].get("hostcomputers").is_some()) {
+        user["Properties"]["hostcomputers"] = serde_json::json!([]);
+    }
+    let computer_type = "Computer".to_string();
+    for (computer_sid, account_sid) in links.iter() {
+        if let Some(computer) = vec_computers.iter_mut().find(|computer| computer["ObjectIdentifier"] == computer_sid.as_str()) {
+            push_property(computer, "hostedserviceaccounts", account_sid);
+        }
+        if let Some(account) = vec_users.iter_mut().chain(vec_computers.iter_mut()).find(|account| account["ObjectIdentifier"] == account_sid.as_str()) {
+            push_property(account, "hostcomputers", computer_sid);
+            add_edge(account, computer_sid, &computer_type, "HostsServiceAccount");
+        }
+    }
+}
+
+/// Function to push one value in an array property, created if missing.
+fn push_property(object: &mut serde_json::value::Value, property: &str, value: &str) {
+    if !object["Properties"][property].is_array() {
+        object["Properties"][property] = serde_json::json!([]);
+    }
+    if let Some(values) = object["Properties"][property].as_array_mut().filter(|values| !values.iter().any(|known| known == value)) {
+        values.push(value.into());
+    }
+}
+
+/// Password policy properties copied on the users from their effective policy.
+const USER_POLICY_PROPERTIES: [&str; 5] = ["minpwdlength", "pwdhistorylength", "pwdcomplexity", "maxpwdage", "lockoutthreshold"];
+
+/// This function sets the effective password policy on the users: the PSO with the lowest precedence
+/// applied on the user, else on its groups (nested), else the default domain policy.
+pub fn add_password_policies(
+    vec_users: &mut Vec<serde_json::value::Value>,
+    vec_groups: &mut Vec<serde_json::value::Value>,
+    vec_domains: &Vec<serde_json::value::Value>,
+    vec_psos: &Vec<serde_json::value::Value>,
+)
+{
+    let effective = effective_password_policies(vec_users, vec_groups, vec_psos);
+    set_password_policies(vec_users, vec_groups, vec_domains, vec_psos, &effective);
+}
+
+/// Principal SID => (applied directly, precedence, PSO index).
+pub type EffectivePolicies = HashMap<String, (bool, i64, usize)>;
+
+/// Function to know if a PSO applies directly to an object, from the DNs of msDS-PSOAppliesTo.
+pub fn is_pso_target(object: &serde_json::value::Value, vec_psos: &[serde_json::value::Value]) -> bool
+{
+    let dn = match object["Properties"]["distinguishedname"].as_str() {
+        Some(dn) => dn,
+        None => return false,
+    };
+    vec_psos.iter().any(|pso| pso["AppliesTo"].as_array().into_iter().flatten().any(|target| target.as_str().is_some_and(|target| target.eq_ignore_ascii_case(dn))))
+}
+
+/// Function to get the PSO of the principals, vec_users holds at least the users a PSO applies to directly ([`is_pso_target`]).
+pub fn effective_password_policies(
+    vec_users: &Vec<serde_json::value::Value>,
+    vec_groups: &Vec<serde_json::value::Value>,
+    vec_psos: &Vec<serde_json::value::Value>,
+) -> EffectivePolicies
+{
+    let mut dn_to_sid: HashMap<String, String> = HashMap::new();
+    let mut group_members: HashMap<String, Vec<String>> = HashMap::new();
+    for object in vec_users.iter().chain(vec_groups.iter()) {
+        if let (Some(dn), Some(sid)) = (object["Properties"]["distinguishedname"].as_str(), object["ObjectIdentifier"].as_str()) {
+            dn_to_sid.insert(dn.to_uppercase(), sid.to_string());
+        }
+    }
+    for group in vec_groups.iter() {
+        let members = group["Members"].as_array().map(|members| members.iter()
+            .filter_map(|member| member["ObjectIdentifier"].as_str().map(|sid| sid.to_string()))
+            .collect()).unwrap_or_default();
+        group_members.insert(group["ObjectIdentifier"].as_str().unwrap_or("").to_string(), members);
+    }
+
+    let mut effective: EffectivePolicies = HashMap::new();
+    let mut keep_best = |sid: &String, candidate: (bool, i64, usize)| {
+        let better = match effective.get(sid) {
+            // Direct PSO first, then the lowest precedence
+            Some(current) => (!candidate.0, candidate.1) < (!current.0, current.1),
+            None => true,
+        };
+        if better {
+            effective.insert(sid.to_owned(), candidate);
+        }
+    };
+    for (index, pso) in vec_psos.iter().enumerate() {
+        let precedence = pso["Properties"]["precedence"].as_i64().unwrap_or(i64::MAX);
+        for dn in pso["AppliesTo"].as_array().into_iter().flatten().filter_map(|dn| dn.as_str()) {
+            let sid = match dn_to_sid.get(dn) {
+                Some(sid) => sid.to_owned(),
+                None => continue,
+            };
+            keep_best(&sid, (true, precedence, index));
+            // Members of the group, nested groups included
+            let mut queue: Vec<String> = vec![sid.to_owned()];
+            let mut seen: HashSet<String> = HashSet::new();
+            while let Some(group) = queue.pop() {
+                for member in group_members.get(&group).into_iter().flatten() {
+                    if seen.insert(member.to_owned()) {
+                        keep_best(member, (false, precedence, index));
+                        queue.push(member.to_owned());
+                    }
+                }
+            }
+        }
+    }
+    effective
+}
+
+/// Function to set the policy of [`effective_password_policies`] on the users and the groups, given apart or together.
+pub fn set_password_policies(
+    vec_users: &mut Vec<serde_json::value::Value>,
+    vec_groups: &mut Vec<serde_json::value::Value>,
+    vec_domains: &Vec<serde_json::value::Value>,
+    vec_psos: &Vec<serde_json::value::Value>,
+    effective: &EffectivePolicies,
+)
+{
+    let default_policy = vec_domains.first().map(|domain| domain["Properties"].to_owned()).unwrap_or_default();
+    for user in vec_users.iter_mut() {
+        let sid = user["ObjectIdentifier"].as_str().unwrap_or("").to_string();
+        let (name, policy) = match effective.get(&sid) {
+            Some((_, _, index)) => (vec_psos[*index]["Properties"]["name"].to_owned(), &vec_psos[*index]["Properties"]),
+            None => ("DEFAULT DOMAIN POLICY".into(), &default_policy),
+        };
+        user["Properties"]["passwordpolicy"] = name;
+        for property in USER_POLICY_PROPERTIES {
+            if !policy[property].is_null() {
+                user["Properties"][property] = policy[property].to_owned();
+            }
+        }
+    }
+    for group in vec_groups.iter_mut() {
+        let sid = group["ObjectIdentifier"].as_str().unwrap_or("").to_string();
+        if let Some((true, _, index)) = effective.get(&sid) {
+            group["Properties"]["passwordpolicy"] = vec_psos[*index]["Properties"]["name"].to_owned();
+        }
+    }
+}
+
+/// Function to get the parent DN, the first RDN ends at the first comma not escaped.
+pub fn parent_dn(dn: &str) -> Option<&str> {
+    let mut escaped = false;
+    for (i, c) in dn.char_indices() {
+        match c {
+            '\\' if !escaped => escaped = true,
+            ',' if !escaped => return Some(&dn[i + 1..]),
+            _ => escaped = false,
+        }
+    }
+    None
+}
+
 /// This function check Guid for all Gplink to replace with correct guid
-pub fn replace_guid_gplink(vec_replaced: &mut Vec<serde_json::value::Value>, dn_sid: &HashMap<String, String>)
+pub fn replace_guid_gplink(vec_replaced: &mut Vec<serde_json::value::Value>, dn_sid: &LookupMap)
 {
     // Needed for progress bar stats
     let pb = ProgressBar::new(1);
@@ -335,7 +721,7 @@ pub fn replace_guid_gplink(vec_replaced: &mut Vec<serde_json::value::Value>, dn_
                 for value in dn_sid 
                 {
                   //trace!("{:?}",&vec_replaced[i]["Links"][j]["Guid"].as_str().unwrap().to_string());
-                  if value.0.contains(&vec_replaced[i]["Links"][j]["GUID"].as_str().unwrap().to_string())
+                  if value.0.to_uppercase().contains(&vec_replaced[i]["Links"][j]["GUID"].as_str().unwrap().to_uppercase())
                   {
                         vec_replaced[i]["Links"][j]["GUID"] = value.1.to_owned().into();
                   }
@@ -347,31 +733,16 @@ pub fn replace_guid_gplink(vec_replaced: &mut Vec<serde_json::value::Value>, dn_
 }
 
 /// This function will ad domainsid for gpos and for ous
-pub fn add_domain_sid(vec_replaced: &mut Vec<serde_json::value::Value>, dn_sid: &HashMap<String, String>)
+/// The domain SID is the one of the domain object, or the domain part of the smallest SID without it:
+/// the same for every run, whatever the order of the DN/SID map.
+pub fn add_domain_sid(vec_replaced: &mut Vec<serde_json::value::Value>, dn_sid: &LookupMap)
 {
-    // Needed for progress bar stats
-    let pb = ProgressBar::new(1);
-    let mut count = 0;
-    let total = vec_replaced.len();
-
-    let mut domain_sid = "".to_owned();
-    for value in dn_sid 
-    {
-        // Manage progress bar
-		count += 1;
-        let pourcentage = 100 * count / total;
-        progress_bar(pb.to_owned(),"Getting domain SID".to_string(),pourcentage.try_into().unwrap(),"%".to_string());
-
-        let sid = value.1.to_owned();
-        let re = Regex::new(r"^S-[0-9]{1}-[0-9]{1}-[0-9]{1,}-[0-9]{1,}-[0-9]{1,}-[0-9]{1,}").unwrap();
-        for value in re.captures_iter(&sid) 
-        {
-            domain_sid = value[0].to_owned().to_string();
-            break
-        }
-        break
-    }
-    pb.finish_and_clear();
+    let re = Regex::new(r"^S-[0-9]{1}-[0-9]{1}-[0-9]{1,}-[0-9]{1,}-[0-9]{1,}-[0-9]{1,}").unwrap();
+    let domain_sid = dn_sid.iter()
+        .filter_map(|(dn, sid)| Some((!dn.get(..3).is_some_and(|rdn| rdn.eq_ignore_ascii_case("DC=")), re.find(sid)?.as_str())))
+        .min()
+        .map(|(_, sid)| sid.to_owned())
+        .unwrap_or_default();
     //trace!("domain_sid: {:?}",&domain_sid);
 
     // Needed for progress bar stats
@@ -394,26 +765,36 @@ pub fn add_domain_sid(vec_replaced: &mut Vec<serde_json::value::Value>, dn_sid:
 }
 
 /// This function push computer sid in domain GpoChanges
-pub fn add_affected_computers(vec_domains: &mut Vec<serde_json::value::Value>, sid_type: &HashMap<String, String>)
+/// The computers below an OU blocking the inheritance are left out, unless one of the domain links is enforced.
+pub fn add_affected_computers(vec_domains: &mut Vec<serde_json::value::Value>, dn_sid: &LookupMap, sid_type: &LookupMap, blocking: &HashSet<String>)
 {
     let mut vec_affected_computers: Vec<serde_json::value::Value> = Vec::new();
 
-    for value in sid_type
+    let domain = match vec_domains.first_mut() {
+        Some(domain) => domain,
+        None => return,
+    };
+    let dn = domain["Properties"]["distinguishedname"].as_str().unwrap_or("").to_uppercase();
+    let enforced = has_enforced_link(domain);
+
+    for value in dn_sid
     {
-        if value.1 == "Computer"
+        if sid_type.get(value.1).map(|t| t.as_str()) == Some("Computer")
+            && (enforced || !is_blocked_below(&value.0.to_uppercase(), &dn, blocking))
         {
             let mut json_template_object = bh_41::prepare_member_json_template();
             json_template_object["ObjectType"] = "Computer".into();
-            json_template_object["ObjectIdentifier"] = value.0.to_owned().to_string().into();
+            json_template_object["ObjectIdentifier"] = value.1.to_owned().to_string().into();
             vec_affected_computers.push(json_template_object);
         }
     }
 
-    vec_domains[0]["GPOChanges"]["AffectedComputers"] = vec_affected_computers.into();
+    domain["GPOChanges"]["AffectedComputers"] = vec_affected_computers.into();
 }
 
 /// This function is to replace fqdn by sid in users SPNTargets:ComputerSID
-pub fn replace_fqdn_by_sid(vec_src: &mut Vec<serde_json::value::Value>, fqdn_sid: &HashMap<String, String>) 
+/// The short hostnames are resolved with the collected computers (short_fqdn), HasSPNConfigured is kept for the collected computers only.
+pub fn replace_fqdn_by_sid(vec_src: &mut Vec<serde_json::value::Value>, fqdn_sid: &LookupMap, short_fqdn: &HashMap<String, String>)
 {
     // Needed for progress bar stats
     let pb = ProgressBar::new(1);
@@ -431,10 +812,21 @@ pub fn replace_fqdn_by_sid(vec_src: &mut Vec<serde_json::value::Value>, fqdn_sid
             for j in 0..vec_src[i]["SPNTargets"].as_array().unwrap().len()
             {
                let default = &vec_src[i]["SPNTargets"][j]["ComputerSID"].as_str().unwrap().to_string();
-               let sid = fqdn_sid.get(&vec_src[i]["SPNTargets"][j]["ComputerSID"].as_str().unwrap().to_string()).unwrap_or(default);
+               let fqdn = short_fqdn.get(default).unwrap_or(default);
+               let sid = fqdn_sid.get(fqdn).unwrap_or(default);
                //trace!("SPNTargets: {} = {}",&vec_users[i]["SPNTargets"][j]["ComputerSID"].to_string(),&sid);
                vec_src[i]["SPNTargets"][j]["ComputerSID"] = sid.to_owned().into();
             }
+            if let Some(targets) = vec_src[i]["SPNTargets"].as_array_mut() {
+               let mut seen: Vec<serde_json::value::Value> = Vec::new();
+               targets.retain(|target| {
+                  let resolved = target["Service"] != "HasSPNConfigured" || target["ComputerSID"].as_str().unwrap_or("").starts_with("S-1-");
+                  let key = serde_json::json!([target["ComputerSID"], target["Port"], target["Service"]]);
+                  let keep = resolved && !seen.contains(&key);
+                  seen.push(key);
+                  keep
+               });
+            }
         }
         if vec_src[i]["AllowedToDelegate"].as_array().unwrap_or(&Vec::new()).len() != 0 {
             for j in 0..vec_src[i]["AllowedToDelegate"].as_array().unwrap().len()
@@ -450,7 +842,7 @@ pub fn replace_fqdn_by_sid(vec_src: &mut Vec<serde_json::value::Value>, fqdn_sid
 }
 
 /// This function is to check and replace object name by SID in group members.
-pub fn replace_sid_members(vec_groups: &mut Vec<serde_json::value::Value>, dn_sid: &HashMap<String, String>, sid_type: &HashMap<String, String>, vec_trusts: &Vec<serde_json::value::Value>)
+pub fn replace_sid_members(vec_groups: &mut Vec<serde_json::value::Value>, dn_sid: &LookupMap, sid_type: &LookupMap, vec_trusts: &Vec<serde_json::value::Value>)
 {
     // Needed for progress bar stats
     let pb = ProgressBar::new(1);
@@ -549,6 +941,460 @@ pub fn add_trustdomain(vec_domains: &mut Vec<serde_json::value::Value>, vec_trus
         for trust in vec_trusts {
             trusts.push(trust.to_owned());
         }
-        vec_domains[0]["Trusts"] = trusts.to_owned().into();
+        if let Some(domain) = vec_domains.first_mut() {
+            domain["Trusts"] = trusts.to_owned().into();
+        }
+    }
+}
+/// This function add the DCSync edge for principals with GetChanges and GetChangesAll on the domain
+/// and SyncLAPSPassword for GetChanges and GetChangesInFilteredSet, like SharpHound.
+/// <https://github.com/BloodHoundAD/SharpHoundCommon/blob/v3/src/CommonLib/Processors/ACLProcessor.cs>
+pub fn add_dcsync_edges(vec_domains: &mut Vec<serde_json::value::Value>)
+{
+    for domain in vec_domains.iter_mut()
+    {
+        let aces = match domain["Aces"].as_array() {
+            Some(aces) => aces.to_owned(),
+            None => continue,
+        };
+
+        // PrincipalSID => (PrincipalType, rights, all ACEs inherited)
+        let mut rights: HashMap<String, (serde_json::value::Value, Vec<String>, bool)> = HashMap::new();
+        for ace in &aces {
+            let right = ace["RightName"].as_str().unwrap_or("");
+            if right != "GetChanges" && right != "GetChangesAll" && right != "GetChangesInFilteredSet" {
+                continue
+            }
+            let sid = ace["PrincipalSID"].as_str().unwrap_or("").to_string();
+            let entry = rights.entry(sid).or_insert((ace["PrincipalType"].to_owned(), Vec::new(), true));
+            entry.1.push(right.to_string());
+            entry.2 = entry.2 && ace["IsInherited"].as_bool().unwrap_or(false);
+        }
+
+        let mut edges: Vec<serde_json::value::Value> = Vec::new();
+        for (sid, (principal_type, granted, is_inherited)) in rights {
+            if !granted.iter().any(|right| right == "GetChanges") {
+                continue
+            }
+            for (needed, edge) in [("GetChangesAll", "DCSync"), ("GetChangesInFilteredSet", "SyncLAPSPassword")].iter() {
+                if granted.iter().any(|right| right == needed)
+                    && !aces.iter().any(|ace| ace["RightName"] == *edge && ace["PrincipalSID"] == sid.as_str())
+                {
+                    let mut relation = bh_41::prepare_acl_relation_template();
+                    relation["RightName"] = edge.to_string().into();
+                    relation["IsInherited"] = is_inherited.into();
+                    relation["PrincipalSID"] = sid.to_owned().into();
+                    relation["PrincipalType"] = principal_type.to_owned();
+                    edges.push(relation);
+                }
+            }
+        }
+
+        if let Some(aces) = domain["Aces"].as_array_mut() {
+            aces.extend(edges);
+        }
+    }
+}
+
+/// This function set highvalue on the well-known privileged groups and on the Exchange groups when Exchange is installed
+/// (organization in the configuration NC or groups in the Exchange OU). The WriteDacl ACEs of the Exchange groups on
+/// the domain are marked PrivExchange: any Exchange server can grant itself DCSync.
+/// <https://dirkjanm.io/abusing-exchange-one-api-call-away-from-domain-admin/>
+pub fn add_high_value(vec_groups: &mut Vec<serde_json::value::Value>, vec_domains: &mut Vec<serde_json::value::Value>)
+{
+    let exchange = vec_domains.iter().any(|domain| domain["Properties"]["exchangeorganization"].is_string());
+    let mut exchange_sids: HashSet<String> = HashSet::new();
+    for group in vec_groups.iter_mut() {
+        let sid = group["ObjectIdentifier"].as_str().unwrap_or("").to_uppercase();
+        let name = group["Properties"]["name"].as_str().unwrap_or("").to_uppercase();
+        let dn = group["Properties"]["distinguishedname"].as_str().unwrap_or("").to_uppercase();
+        let is_exchange = EXCHANGE_HIGH_VALUE_GROUPS.iter().any(|group| name.split('@').next() == Some(*group))
+            && (exchange || dn.contains(EXCHANGE_GROUPS_OU));
+        if is_exchange {
+            exchange_sids.insert(sid.to_owned());
+        }
+        if is_exchange || HIGH_VALUE_SID_SUFFIXES.iter().any(|suffix| sid.ends_with(suffix)) {
+            group["Properties"]["highvalue"] = true.into();
+        }
     }
-}
\ No newline at end of file
+
+    for domain in vec_domains.iter_mut() {
+        let name = domain["Properties"]["name"].as_str().unwrap_or("").to_string();
+        for ace in domain["Aces"].as_array_mut().into_iter().flatten() {
+            let principal = ace["PrincipalSID"].as_str().unwrap_or("").to_uppercase();
+            if ace["RightName"] == "WriteDacl" && exchange_sids.contains(&principal) {
+                warn!("PrivExchange: Exchange group {} has WriteDacl on the domain {}", principal, name);
+                ace["PrivExchange"] = true.into();
+            }
+        }
+    }
+}
+
+/// This function complete the edges "Justification" with the principal names
+/// and the parent object where the inherited ACE is defined.
+pub fn add_edge_justification_details(vec_objects: &mut [&mut Vec<serde_json::value::Value>])
+{
+    // ObjectIdentifier => name and DN => not inherited edges (PrincipalSID, RightName)
+    let mut sid_name: HashMap<String, String> = HashMap::new();
+    let mut dn_aces: HashMap<String, Vec<(String, String)>> = HashMap::new();
+    for objects in vec_objects.iter() {
+        for object in objects.iter() {
+            if let (Some(sid), Some(name)) = (object["ObjectIdentifier"].as_str(), object["Properties"]["name"].as_str()) {
+                sid_name.insert(sid.to_string(), name.to_string());
+            }
+            let dn = object["Properties"]["distinguishedname"].as_str().unwrap_or("").to_uppercase();
+            let aces: Vec<(String, String)> = object["Aces"].as_array().unwrap_or(&Vec::new()).iter()
+                .filter(|ace| !ace["IsInherited"].as_bool().unwrap_or(false))
+                .map(|ace| (ace["PrincipalSID"].as_str().unwrap_or("").to_string(), ace["RightName"].as_str().unwrap_or("").to_string()))
+                .collect();
+            if !dn.is_empty() && !aces.is_empty() {
+                dn_aces.insert(dn, aces);
+            }
+        }
+    }
+
+    for objects in vec_objects.iter_mut() {
+        for object in objects.iter_mut() {
+            let dn = object["Properties"]["distinguishedname"].as_str().unwrap_or("").to_uppercase();
+            let aces = match object["Aces"].as_array_mut() {
+                Some(aces) => aces,
+                None => continue,
+            };
+            for ace in aces.iter_mut() {
+                let mut justification = match ace["Justification"].as_str() {
+                    Some(justification) => justification.to_string(),
+                    None => continue,
+                };
+                let sid = ace["PrincipalSID"].as_str().unwrap_or("").to_string();
+                if let Some(name) = sid_name.get(&sid) {
+                    justification = justification.replace(&format!("granted to {}", sid), &format!("granted to {}", name));
+                }
+                if ace["IsInherited"].as_bool().unwrap_or(false) {
+                    let right = ace["RightName"].as_str().unwrap_or("").to_string();
+                    if let Some(parent) = find_inheritance_source(&dn, &sid, &right, &dn_aces) {
+                        justification = justification.replace(", inherited", &format!(", inherited from {}", parent));
+                    }
+                }
+                ace["Justification"] = justification.into();
+            }
+        }
+    }
+}
+
+/// Find the nearest parent DN with the same explicit edge.
+fn find_inheritance_source(dn: &String, sid: &String, right: &String, dn_aces: &HashMap<String, Vec<(String, String)>>) -> Option<String>
+{
+    let mut parent = dn.as_str();
+    while let Some(pos) = parent.find(',') {
+        parent = &parent[pos + 1..];
+        if let Some(aces) = dn_aces.get(parent) {
+            if aces.iter().any(|(ace_sid, ace_right)| ace_sid == sid && ace_right == right) {
+                return Some(parent.to_string())
+            }
+        }
+    }
+    None
+}
+
+/// Test functions
+#[test]
+pub fn test_add_dcsync_edges() {
+    let mut vec_domains = vec![serde_json::json!({
+        "Aces": [
+            {"RightName": "GetChanges", "IsInherited": false, "PrincipalSID": "S-1-5-21-1-2-3-1104", "PrincipalType": "User"},
+            {"RightName": "GetChangesAll", "IsInherited": false, "PrincipalSID": "S-1-5-21-1-2-3-1104", "PrincipalType": "User"},
+            {"RightName": "GetChanges", "IsInherited": true, "PrincipalSID": "S-1-5-21-1-2-3-1105", "PrincipalType": "Group"},
+            {"RightName": "GetChangesInFilteredSet", "IsInherited": true, "PrincipalSID": "S-1-5-21-1-2-3-1105", "PrincipalType": "Group"},
+            {"RightName": "GetChangesAll", "IsInherited": false, "PrincipalSID": "S-1-5-21-1-2-3-1106", "PrincipalType": "User"}
+        ]
+    })];
+    add_dcsync_edges(&mut vec_domains);
+    add_dcsync_edges(&mut vec_domains);
+
+    let aces = vec_domains[0]["Aces"].as_array().unwrap();
+    let edges: Vec<(&str, &str, bool)> = aces[5..].iter()
+        .map(|ace| (ace["RightName"].as_str().unwrap(), ace["PrincipalSID"].as_str().unwrap(), ace["IsInherited"].as_bool().unwrap()))
+        .collect();
+    assert_eq!(aces.len(), 7);
+    assert!(edges.contains(&("DCSync", "S-1-5-21-1-2-3-1104", false)));
+    assert!(edges.contains(&("SyncLAPSPassword", "S-1-5-21-1-2-3-1105", true)));
+}
+
+#[test]
+pub fn test_add_edge_justification_details() {
+    let mut vec_ous = vec![serde_json::json!({
+        "ObjectIdentifier": "OU-GUID",
+        "Properties": {"name": "SERVERS@ESSOS.LOCAL", "distinguishedname": "OU=SERVERS,DC=ESSOS,DC=LOCAL"},
+        "Aces": [
+            {"RightName": "GenericWrite", "IsInherited": false, "PrincipalSID": "S-1-5-21-1-2-3-1105", "PrincipalType": "Group",
+             "Justification": "ACE 3 on OU=SERVERS,DC=ESSOS,DC=LOCAL: GenericWrite granted to S-1-5-21-1-2-3-1105"}
+        ]
+    })];
+    let mut vec_groups = vec![serde_json::json!({
+        "ObjectIdentifier": "S-1-5-21-1-2-3-1105",
+        "Properties": {"name": "DRAGONRIDER@ESSOS.LOCAL", "distinguishedname": "CN=DRAGONRIDER,CN=USERS,DC=ESSOS,DC=LOCAL"},
+        "Aces": []
+    })];
+    let mut vec_computers = vec![serde_json::json!({
+        "ObjectIdentifier": "S-1-5-21-1-2-3-1001",
+        "Properties": {"name": "BRAAVOS.ESSOS.LOCAL", "distinguishedname": "CN=BRAAVOS,OU=SERVERS,DC=ESSOS,DC=LOCAL"},
+        "Aces": [
+            {"RightName": "GenericWrite", "IsInherited": true, "PrincipalSID": "S-1-5-21-1-2-3-1105", "PrincipalType": "Group",
+             "Justification": "ACE 12 on CN=BRAAVOS,OU=SERVERS,DC=ESSOS,DC=LOCAL: GenericWrite granted to S-1-5-21-1-2-3-1105, inherited"}
+        ]
+    })];
+    add_edge_justification_details(&mut [&mut vec_ous, &mut vec_groups, &mut vec_computers]);
+    assert_eq!(
+        vec_computers[0]["Aces"][0]["Justification"],
+        "ACE 12 on CN=BRAAVOS,OU=SERVERS,DC=ESSOS,DC=LOCAL: GenericWrite granted to DRAGONRIDER@ESSOS.LOCAL, inherited from OU=SERVERS,DC=ESSOS,DC=LOCAL"
+    );
+    assert_eq!(
+        vec_ous[0]["Aces"][0]["Justification"],
+        "ACE 3 on OU=SERVERS,DC=ESSOS,DC=LOCAL: GenericWrite granted to DRAGONRIDER@ESSOS.LOCAL"
+    );
+}
+
+#[test]
+pub fn test_add_childobjects_members() {
+    let mut vec_ous = vec![serde_json::json!({
+        "Properties": {"distinguishedname": "OU=SERVERS,DC=ESSOS,DC=LOCAL", "name": "SERVERS@ESSOS.LOCAL"},
+        "ChildObjects": [],
+        "GPOChanges": {"AffectedComputers": []}
+    })];
+    let dn_sid: LookupMap = vec![
+        ("OU=SERVERS,DC=ESSOS,DC=LOCAL", "OU-GUID"),
+        ("OU=SQL,OU=SERVERS,DC=ESSOS,DC=LOCAL", "SQL-GUID"),
+        ("CN=SRV01,OU=SERVERS,DC=ESSOS,DC=LOCAL", "S-1-5-21-1-2-3-1201"),
+        ("CN=SQL01,OU=SQL,OU=SERVERS,DC=ESSOS,DC=LOCAL", "S-1-5-21-1-2-3-1202"),
+        ("CN=SERVERS\\, OLD,OU=ARCHIVE,DC=ESSOS,DC=LOCAL", "S-1-5-21-1-2-3-1203"),
+    ].into_iter().map(|(k, v)| (k.to_string(), v.to_string())).collect();
+    let sid_type: LookupMap = vec![
+        ("OU-GUID", "OU"),
+        ("SQL-GUID", "OU"),
+        ("S-1-5-21-1-2-3-1201", "Computer"),
+        ("S-1-5-21-1-2-3-1202", "Computer"),
+        ("S-1-5-21-1-2-3-1203", "Computer"),
+    ].into_iter().map(|(k, v)| (k.to_string(), v.to_string())).collect();
+    add_childobjects_members(&mut vec_ous, &dn_sid, &sid_type, &HashSet::new());
+
+    let children: Vec<&str> = vec_ous[0]["ChildObjects"].as_array().unwrap().iter().map(|c| c["ObjectIdentifier"].as_str().unwrap()).collect();
+    assert_eq!(children.len(), 2);
+    assert!(children.contains(&"SQL-GUID") && children.contains(&"S-1-5-21-1-2-3-1201"));
+    assert_eq!(vec_ous[0]["GPOChanges"]["AffectedComputers"].as_array().unwrap().len(), 2);
+    assert_eq!(parent_dn("CN=SERVERS\\, OLD,OU=ARCHIVE,DC=ESSOS,DC=LOCAL"), Some("OU=ARCHIVE,DC=ESSOS,DC=LOCAL"));
+
+    // OU=SQL blocks the inheritance: SQL01 is only affected by the enforced links of OU=SERVERS
+    let blocking_ou = serde_json::json!({"Properties": {"distinguishedname": "OU=SQL,OU=SERVERS,DC=ESSOS,DC=LOCAL", "blocksinheritance": true}});
+    let blocking = blocking_ous(&[blocking_ou]);
+    add_childobjects_members(&mut vec_ous, &dn_sid, &sid_type, &blocking);
+    let affected: Vec<&str> = vec_ous[0]["GPOChanges"]["AffectedComputers"].as_array().unwrap().iter().map(|c| c["ObjectIdentifier"].as_str().unwrap()).collect();
+    assert_eq!(affected, vec!["S-1-5-21-1-2-3-1201"]);
+    vec_ous[0]["Links"] = serde_json::json!([{"GUID": "8A1F6D3C-1E2B-4C5D-9E8F-0A1B2C3D4E5F", "IsEnforced": true}]);
+    add_childobjects_members(&mut vec_ous, &dn_sid, &sid_type, &blocking);
+    assert_eq!(vec_ous[0]["GPOChanges"]["AffectedComputers"].as_array().unwrap().len(), 2);
+
+    let mut vec_domains = vec![serde_json::json!({"Properties": {"distinguishedname": "DC=ESSOS,DC=LOCAL"}, "Links": [], "GPOChanges": {"AffectedComputers": []}})];
+    add_affected_computers(&mut vec_domains, &dn_sid, &sid_type, &blocking);
+    assert_eq!(vec_domains[0]["GPOChanges"]["AffectedComputers"].as_array().unwrap().len(), 2);
+    assert!(!is_blocked_below("CN=SQL01,OU=SQL,OU=SERVERS,DC=ESSOS,DC=LOCAL", "OU=SQL,OU=SERVERS,DC=ESSOS,DC=LOCAL", &blocking));
+}
+
+#[test]
+pub fn test_add_password_policies() {
+    let mut vec_users = vec![
+        serde_json::json!({"ObjectIdentifier": "S-1-5-21-1-2-3-1104", "Properties": {"distinguishedname": "CN=JON,CN=USERS,DC=ESSOS,DC=LOCAL"}}),
+        serde_json::json!({"ObjectIdentifier": "S-1-5-21-1-2-3-1105", "Properties": {"distinguishedname": "CN=ARYA,CN=USERS,DC=ESSOS,DC=LOCAL"}}),
+        serde_json::json!({"ObjectIdentifier": "S-1-5-21-1-2-3-1106", "Properties": {"distinguishedname": "CN=SANSA,CN=USERS,DC=ESSOS,DC=LOCAL"}}),
+    ];
+    let mut vec_groups = vec![
+        serde_json::json!({"ObjectIdentifier": "S-1-5-21-1-2-3-1200", "Properties": {"distinguishedname": "CN=STARKS,CN=USERS,DC=ESSOS,DC=LOCAL"},
+            "Members": [{"ObjectIdentifier": "S-1-5-21-1-2-3-1104"}, {"ObjectIdentifier": "S-1-5-21-1-2-3-1105"}]}),
+    ];
+    let vec_domains = vec![serde_json::json!({"Properties": {"minpwdlength": 7}})];
+    let vec_psos = vec![
+        serde_json::json!({"Properties": {"name": "WEAK", "precedence": 10, "minpwdlength": 4}, "AppliesTo": ["CN=STARKS,CN=USERS,DC=ESSOS,DC=LOCAL"]}),
+        serde_json::json!({"Properties": {"name": "STRONG", "precedence": 20, "minpwdlength": 14}, "AppliesTo": ["CN=JON,CN=USERS,DC=ESSOS,DC=LOCAL"]}),
+    ];
+    add_password_policies(&mut vec_users, &mut vec_groups, &vec_domains, &vec_psos);
+
+    assert_eq!(vec_users[0]["Properties"]["passwordpolicy"], "STRONG");
+    assert_eq!(vec_users[1]["Properties"]["passwordpolicy"], "WEAK");
+    assert_eq!(vec_users[1]["Properties"]["minpwdlength"], 4);
+    assert_eq!(vec_users[2]["Properties"]["passwordpolicy"], "DEFAULT DOMAIN POLICY");
+    assert_eq!(vec_users[2]["Properties"]["minpwdlength"], 7);
+    assert_eq!(vec_groups[0]["Properties"]["passwordpolicy"], "WEAK");
+}
+
+#[test]
+pub fn test_add_stale_computers() {
+    let now = 1700000000;
+    let mut vec_computers = vec![
+        serde_json::json!({"Properties": {"enabled": true, "lastlogontimestamp": now - 10 * 86400, "pwdlastset": now - 200 * 86400}}),
+        serde_json::json!({"Properties": {"enabled": true, "lastlogontimestamp": now - 100 * 86400, "pwdlastset": now - 95 * 86400}}),
+        serde_json::json!({"Properties": {"enabled": false, "lastlogontimestamp": now, "pwdlastset": now}}),
+        serde_json::json!({"Properties": {"enabled": true, "lastlogontimestamp": -1, "pwdlastset": -1, "whencreated": now - 5 * 86400}}),
+    ];
+    add_stale_computers(&mut vec_computers, 90, now);
+    assert_eq!(vec_computers[0]["Properties"]["probably_stale"], false);
+    assert_eq!(vec_computers[1]["Properties"]["probably_stale"], true);
+    assert_eq!(vec_computers[2]["Properties"]["probably_stale"], true);
+    assert_eq!(vec_computers[3]["Properties"]["probably_stale"], false);
+}
+
+#[test]
+pub fn test_add_primary_group_members() {
+    let vec_users = vec![
+        serde_json::json!({"ObjectIdentifier": "S-1-5-21-1-2-3-1104", "PrimaryGroupSID": "S-1-5-21-1-2-3-513"}),
+        serde_json::json!({"ObjectIdentifier": "S-1-5-21-1-2-3-1105", "PrimaryGroupSID": "S-1-5-21-1-2-3-513"}),
+    ];
+    let vec_computers = vec![serde_json::json!({"ObjectIdentifier": "S-1-5-21-1-2-3-1000", "PrimaryGroupSID": "S-1-5-21-1-2-3-516"})];
+    let mut vec_groups = vec![
+        serde_json::json!({"ObjectIdentifier": "S-1-5-21-1-2-3-513", "Members": [{"ObjectIdentifier": "S-1-5-21-1-2-3-1105", "ObjectType": "User"}]}),
+        serde_json::json!({"ObjectIdentifier": "S-1-5-21-1-2-3-516", "Members": []}),
+    ];
+    add_primary_group_members(&mut vec_groups, &vec_users, &vec_computers);
+    assert_eq!(vec_groups[0]["Members"].as_array().unwrap().len(), 2);
+    assert_eq!(vec_groups[0]["Members"][1]["ObjectIdentifier"], "S-1-5-21-1-2-3-1104");
+    assert_eq!(vec_groups[1]["Members"][0]["ObjectType"], "Computer");
+}
+
+#[test]
+pub fn test_add_high_value() {
+    let mut vec_groups = vec![
+        serde_json::json!({"ObjectIdentifier": "S-1-5-21-1-2-3-512", "Properties": {"name": "DOMAIN ADMINS@ESSOS.LOCAL", "highvalue": false}}),
+        serde_json::json!({"ObjectIdentifier": "ESSOS.LOCAL-S-1-5-32-548", "Properties": {"name": "ACCOUNT OPERATORS@ESSOS.LOCAL", "highvalue": false}}),
+        serde_json::json!({"ObjectIdentifier": "S-1-5-21-1-2-3-1120", "Properties": {"name": "EXCHANGE WINDOWS PERMISSIONS@ESSOS.LOCAL", "highvalue": false}}),
+        serde_json::json!({"ObjectIdentifier": "S-1-5-21-1-2-3-1121", "Properties": {"name": "ORGANIZATION MANAGEMENT@ESSOS.LOCAL", "highvalue": false}}),
+        serde_json::json!({"ObjectIdentifier": "S-1-5-21-1-2-3-513", "Properties": {"name": "DOMAIN USERS@ESSOS.LOCAL", "highvalue": false}}),
+    ];
+    let mut vec_domains = vec![serde_json::json!({"Properties": {"name": "ESSOS.LOCAL", "exchangeorganization": null}, "Aces": [
+        {"RightName": "WriteDacl", "IsInherited": false, "PrincipalSID": "S-1-5-21-1-2-3-1120", "PrincipalType": "Group"},
+    ]})];
+    // Exchange groups without Exchange
+    add_high_value(&mut vec_groups, &mut vec_domains);
+    let high_value: Vec<bool> = vec_groups.iter().map(|group| group["Properties"]["highvalue"].as_bool().unwrap()).collect();
+    assert_eq!(high_value, vec![true, true, false, false, false]);
+    assert!(vec_domains[0]["Aces"][0]["PrivExchange"].is_null());
+
+    vec_domains[0]["Properties"]["exchangeorganization"] = "ESSOS".into();
+    add_high_value(&mut vec_groups, &mut vec_domains);
+    let high_value: Vec<bool> = vec_groups.iter().map(|group| group["Properties"]["highvalue"].as_bool().unwrap()).collect();
+    assert_eq!(high_value, vec![true, true, true, true, false]);
+    assert_eq!(vec_domains[0]["Aces"][0]["PrivExchange"], true);
+}
+
+#[test]
+pub fn test_add_normalized_spns() {
+    use crate::enums::spntasks::short_hostnames;
+    let mut fqdn_sid = LookupMap::default();
+    fqdn_sid.insert("SQL01.ESSOS.LOCAL".to_string(), "S-1-5-21-1-2-3-1001".to_string());
+    let mut vec_users = vec![
+        serde_json::json!({"Properties": {"serviceprincipalnames": ["MSSQLSvc/sql01:1433", "MSSQLSvc/SQL01.essos.local:1433"]}}),
+        serde_json::json!({"Properties": {"serviceprincipalnames": []}}),
+    ];
+    add_normalized_spns(&mut vec_users, &short_hostnames(fqdn_sid.keys()));
+    assert_eq!(vec_users[0]["Properties"]["normalizedspns"], serde_json::json!(["MSSQLSvc/SQL01.ESSOS.LOCAL:1433"]));
+    assert_eq!(vec_users[0]["Properties"]["spnhosts"], serde_json::json!(["SQL01.ESSOS.LOCAL"]));
+    assert_eq!(vec_users[1]["Properties"]["normalizedspns"], serde_json::json!([]));
+}
+
+#[test]
+pub fn test_add_rodc_properties() {
+    use crate::json::parser::bh_41::dn_binary_dn;
+    assert_eq!(dn_binary_dn("B:8:0A0B0C0D:CN=Jon Snow,CN=Users,DC=essos,DC=local"), "CN=Jon Snow,CN=Users,DC=essos,DC=local");
+    assert_eq!(dn_binary_dn("CN=Jon Snow,CN=Users,DC=essos,DC=local"), "CN=Jon Snow,CN=Users,DC=essos,DC=local");
+
+    let mut dn_sid = LookupMap::default();
+    let mut sid_type = LookupMap::default();
+    for (dn, sid, object_type) in [
+        ("CN=JON SNOW,CN=USERS,DC=ESSOS,DC=LOCAL", "S-1-5-21-1-2-3-1110", "User"),
+        ("CN=RODC ADMINS,CN=USERS,DC=ESSOS,DC=LOCAL", "S-1-5-21-1-2-3-1120", "Group"),
+        ("CN=KRBTGT_8245,CN=USERS,DC=ESSOS,DC=LOCAL", "S-1-5-21-1-2-3-1121", "User"),
+        ("CN=ALLOWED RODC PASSWORD REPLICATION GROUP,CN=USERS,DC=ESSOS,DC=LOCAL", "S-1-5-21-1-2-3-571", "Group"),
+    ] {
+        dn_sid.insert(dn.to_string(), sid.to_string());
+        sid_type.insert(sid.to_string(), object_type.to_string());
+    }
+    let mut vec_computers = vec![
+        serde_json::json!({"ObjectIdentifier": "S-1-5-21-1-2-3-1130", "Properties": {"name": "VOLANTIS.ESSOS.LOCAL", "isrodc": true,
+            "managedby": "CN=RODC ADMINS,CN=USERS,DC=ESSOS,DC=LOCAL", "rodckrbtgt": "CN=KRBTGT_8245,CN=USERS,DC=ESSOS,DC=LOCAL",
+            "rodcrevealedusers": ["CN=JON SNOW,CN=USERS,DC=ESSOS,DC=LOCAL", "CN=GONE,CN=USERS,DC=ESSOS,DC=LOCAL"],
+            "rodcallowedtoreveal": ["CN=ALLOWED RODC PASSWORD REPLICATION GROUP,CN=USERS,DC=ESSOS,DC=LOCAL"]},
+            "LocalAdmins": {"Results": [], "Collected": false, "FailureReason": null}}),
+        serde_json::json!({"ObjectIdentifier": "S-1-5-21-1-2-3-1131", "Properties": {"name": "WS01.ESSOS.LOCAL", "isrodc": false,
+            "managedby": "CN=JON SNOW,CN=USERS,DC=ESSOS,DC=LOCAL"},
+            "LocalAdmins": {"Results": [], "Collected": false, "FailureReason": null}}),
+    ];
+    let mut vec_users = vec![serde_json::json!({"ObjectIdentifier": "S-1-5-21-1-2-3-1110", "Properties": {"name": "JON.SNOW@ESSOS.LOCAL"}})];
+    add_rodc_properties(&mut vec_computers, &mut vec_users, &dn_sid, &sid_type);
+    assert_eq!(vec_computers[0]["Properties"]["rodckrbtgt"], "S-1-5-21-1-2-3-1121");
+    assert_eq!(vec_computers[0]["Properties"]["rodcrevealedusers"], serde_json::json!(["S-1-5-21-1-2-3-1110"]));
+    assert_eq!(vec_computers[0]["Properties"]["rodcallowedtoreveal"], serde_json::json!(["S-1-5-21-1-2-3-571"]));
+    assert_eq!(vec_computers[0]["LocalAdmins"]["Results"], serde_json::json!([{"ObjectIdentifier": "S-1-5-21-1-2-3-1120", "ObjectType": "Group"}]));
+    assert_eq!(vec_computers[0]["LocalAdmins"]["Collected"], true);
+    assert_eq!(vec_computers[1]["Properties"]["managedby"], "S-1-5-21-1-2-3-1110");
+    assert_eq!(vec_computers[1]["LocalAdmins"]["Results"], serde_json::json!([]));
+    assert_eq!(vec_users[0]["Properties"]["revealedonrodcs"], serde_json::json!(["VOLANTIS.ESSOS.LOCAL"]));
+}
+
+#[test]
+pub fn test_add_service_account_hosts() {
+    let mut dn_sid = LookupMap::default();
+    dn_sid.insert("CN=GMSA-WEB,CN=MANAGED SERVICE ACCOUNTS,DC=ESSOS,DC=LOCAL".to_string(), "S-1-5-21-1-2-3-1150".to_string());
+    dn_sid.insert("CN=WEB01,OU=SERVERS,DC=ESSOS,DC=LOCAL".to_string(), "S-1-5-21-1-2-3-1151".to_string());
+    dn_sid.insert("CN=WEB02,OU=SERVERS,DC=ESSOS,DC=LOCAL".to_string(), "S-1-5-21-1-2-3-1152".to_string());
+    let mut vec_users = vec![serde_json::json!({"ObjectIdentifier": "S-1-5-21-1-2-3-1150", "Aces": [],
+        "Properties": {"name": "GMSA-WEB$@ESSOS.LOCAL", "hostcomputers": ["CN=WEB01,OU=SERVERS,DC=ESSOS,DC=LOCAL", "CN=WEB02,OU=SERVERS,DC=ESSOS,DC=LOCAL"]}})];
+    let mut vec_computers = vec![
+        serde_json::json!({"ObjectIdentifier": "S-1-5-21-1-2-3-1151", "Aces": [],
+            "Properties": {"name": "WEB01.ESSOS.LOCAL", "hostedserviceaccounts": ["CN=GMSA-WEB,CN=MANAGED SERVICE ACCOUNTS,DC=ESSOS,DC=LOCAL"]}}),
+        // Back link only, msDS-HostServiceAccount not readable
+        serde_json::json!({"ObjectIdentifier": "S-1-5-21-1-2-3-1152", "Aces": [], "Properties": {"name": "WEB02.ESSOS.LOCAL"}}),
+    ];
+    add_service_account_hosts(&mut vec_users, &mut vec_computers, &dn_sid);
+    assert_eq!(vec_users[0]["Properties"]["hostcomputers"], serde_json::json!(["S-1-5-21-1-2-3-1151", "S-1-5-21-1-2-3-1152"]));
+    assert_eq!(vec_computers[0]["Properties"]["hostedserviceaccounts"], serde_json::json!(["S-1-5-21-1-2-3-1150"]));
+    assert_eq!(vec_computers[1]["Properties"]["hostedserviceaccounts"], serde_json::json!(["S-1-5-21-1-2-3-1150"]));
+    let edges: Vec<(&str, &str)> = vec_users[0]["Aces"].as_array().unwrap().iter()
+        .map(|ace| (ace["PrincipalSID"].as_str().unwrap(), ace["RightName"].as_str().unwrap()))
+        .collect();
+    assert_eq!(edges, vec![("S-1-5-21-1-2-3-1151", "HostsServiceAccount"), ("S-1-5-21-1-2-3-1152", "HostsServiceAccount")]);
+}
+
+#[test]
+pub fn test_replace_fqdn_by_sid() {
+    use crate::enums::spntasks::short_hostnames;
+    let mut fqdn_sid = LookupMap::default();
+    fqdn_sid.insert("SQL01.ESSOS.LOCAL".to_string(), "S-1-5-21-1-2-3-1001".to_string());
+    let mut vec_users = vec![serde_json::json!({"SPNTargets": [
+        {"ComputerSID": "SQL01", "Port": 1433, "Service": "SQLAdmin"},
+        {"ComputerSID": "SQL01", "Port": 1433, "Service": "HasSPNConfigured"},
+        {"ComputerSID": "SQL01.ESSOS.LOCAL", "Port": 1433, "Service": "SQLAdmin"},
+        {"ComputerSID": "WEB.ESSOS.LOCAL", "Port": null, "Service": "HasSPNConfigured"},
+    ]})];
+    replace_fqdn_by_sid(&mut vec_users, &fqdn_sid, &short_hostnames(fqdn_sid.keys()));
+    assert_eq!(vec_users[0]["SPNTargets"], serde_json::json!([
+        {"ComputerSID": "S-1-5-21-1-2-3-1001", "Port": 1433, "Service": "SQLAdmin"},
+        {"ComputerSID": "S-1-5-21-1-2-3-1001", "Port": 1433, "Service": "HasSPNConfigured"},
+    ]));
+}
+
+#[test]
+pub fn test_add_domain_sid() {
+    let mut dn_sid = LookupMap::default();
+    // Foreign principal and builtin group first, the SID of the domain object wins
+    dn_sid.insert("CN=S-1-5-21-0-0-1-1000,CN=FOREIGNSECURITYPRINCIPALS,DC=ESSOS,DC=LOCAL".to_string(), "S-1-5-21-0-0-1-1000".to_string());
+    dn_sid.insert("CN=ADMINISTRATORS,CN=BUILTIN,DC=ESSOS,DC=LOCAL".to_string(), "S-1-5-32-544".to_string());
+    dn_sid.insert("CN=VAGRANT,CN=USERS,DC=ESSOS,DC=LOCAL".to_string(), "S-1-5-21-1-2-3-1000".to_string());
+    dn_sid.insert("DC=ESSOS,DC=LOCAL".to_string(), "S-1-5-21-1-2-3".to_string());
+    let mut vec_ous = vec![serde_json::json!({"Properties": {"name": "SERVERS@ESSOS.LOCAL"}})];
+    add_domain_sid(&mut vec_ous, &dn_sid);
+    assert_eq!(vec_ous[0]["Properties"]["domainsid"], "S-1-5-21-1-2-3");
+
+    // Without the domain object, the domain part of the other SIDs, and nothing to do without objects
+    dn_sid.remove("DC=ESSOS,DC=LOCAL");
+    dn_sid.remove("CN=S-1-5-21-0-0-1-1000,CN=FOREIGNSECURITYPRINCIPALS,DC=ESSOS,DC=LOCAL");
+    add_domain_sid(&mut vec_ous, &dn_sid);
+    assert_eq!(vec_ous[0]["Properties"]["domainsid"], "S-1-5-21-1-2-3");
+    add_domain_sid(&mut Vec::new(), &dn_sid);
+}
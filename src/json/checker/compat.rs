@@ -0,0 +1,128 @@
+//! BloodHound release compatibility (--bh-version)
+//!
+//! The JSON files are written for the release selected with `--bh-version`:
+//! the meta version of the files, the casing of the property names and the edges the release knows.
+//! An ACE or a SPNTarget with an edge the release does not know is removed, the ingestion of the file would fail or drop it.
+//! Without `--bh-version` the files are written for BloodHound 4.2+ with every edge.
+//!
+//! The expected output of every release is pinned in `resources/fixtures/bh_compat_<version>.json`.
+use lazy_static::lazy_static;
+use log::{debug, info};
+use serde_json::value::Value;
+use std::sync::RwLock;
+
+use crate::json::maker::bh_41::BLOODHOUND_VERSION_4;
+
+/// JSON schema of one BloodHound release.
+#[derive(Debug, PartialEq)]
+pub struct BloodHoundSchema {
+   pub name: &'static str,
+   /// "version" of the meta of every file
+   pub meta_version: i8,
+   /// Edges unknown to the release
+   pub unsupported_edges: &'static [&'static str],
+   /// Property names in lower case
+   pub lowercase_properties: bool,
+}
+
+/// Supported BloodHound releases.
+pub const BLOODHOUND_SCHEMAS: [BloodHoundSchema; 3] = [
+   BloodHoundSchema {
+      name: "4.2",
+      meta_version: 5,
+      unsupported_edges: &["GetChangesInFilteredSet", "WriteAccountRestrictions", "Enroll"],
+      lowercase_properties: false,
+   },
+   BloodHoundSchema {
+      name: "4.3",
+      meta_version: 5,
+      unsupported_edges: &["WriteAccountRestrictions", "Enroll"],
+      lowercase_properties: false,
+   },
+   BloodHoundSchema {
+      name: "ce",
+      meta_version: 6,
+      unsupported_edges: &["HasSPNConfigured", "HostsServiceAccount"],
+      lowercase_properties: true,
+   },
+];
+
+lazy_static! {
+   static ref SELECTED: RwLock<Option<&'static BloodHoundSchema>> = RwLock::new(None);
+}
+
+/// Function to get the schema of one release.
+pub fn bloodhound_schema(name: &str) -> Option<&'static BloodHoundSchema> {
+   BLOODHOUND_SCHEMAS.iter().find(|schema| schema.name.eq_ignore_ascii_case(name))
+}
+
+/// Function to select the release of the JSON files, "not set" for the default.
+pub fn set_bloodhound_schema(name: &str) {
+   let schema = bloodhound_schema(name);
+   if let Some(schema) = schema {
+      info!("JSON files written for BloodHound {}", schema.name);
+   }
+   if let Ok(mut selected) = SELECTED.write() {
+      *selected = schema;
+   }
+}
+
+/// Function to get the selected release.
+pub fn selected_schema() -> Option<&'static BloodHoundSchema> {
+   SELECTED.read().ok().and_then(|selected| *selected)
+}
+
+/// Function to get the meta version of the JSON files for the selected release.
+pub fn meta_version() -> i8 {
+   selected_schema().map(|schema| schema.meta_version).unwrap_or(BLOODHOUND_VERSION_4)
+}
+
+/// Function to adjust the objects to one release, return the number of edges removed.
+pub fn apply_schema(schema: &BloodHoundSchema, vec_objects: &mut [&mut Vec<Value>]) -> usize {
+   let mut removed = 0;
+   for objects in vec_objects.iter_mut() {
+      for object in objects.iter_mut() {
+         if let Some(aces) = object["Aces"].as_array_mut() {
+            let count = aces.len();
+            aces.retain(|ace| !schema.unsupported_edges.contains(&ace["RightName"].as_str().unwrap_or("")));
+            removed += count - aces.len();
+         }
+         if let Some(targets) = object.get_mut("SPNTargets").and_then(Value::as_array_mut) {
+            let count = targets.len();
+            targets.retain(|target| !schema.unsupported_edges.contains(&target["Service"].as_str().unwrap_or("")));
+            removed += count - targets.len();
+         }
+         if schema.lowercase_properties {
+            if let Some(properties) = object["Properties"].as_object_mut() {
+               let lowercase: serde_json::Map<String, Value> = std::mem::take(properties).into_iter()
+                  .map(|(name, value)| (name.to_lowercase(), value))
+                  .collect();
+               *properties = lowercase;
+            }
+         }
+      }
+   }
+   debug!("{} edges unknown to BloodHound {} removed", removed, schema.name);
+   removed
+}
+
+/// Test functions
+#[test]
+pub fn test_apply_schema() {
+   let input: Value = serde_json::from_str(include_str!("../../../resources/fixtures/bh_compat_input.json")).unwrap();
+   let fixtures = [
+      ("4.2", include_str!("../../../resources/fixtures/bh_compat_4.2.json")),
+      ("4.3", include_str!("../../../resources/fixtures/bh_compat_4.3.json")),
+      ("ce", include_str!("../../../resources/fixtures/bh_compat_ce.json")),
+   ];
+   for (name, fixture) in fixtures {
+      let schema = bloodhound_schema(name).unwrap();
+      let mut objects: Vec<Value> = input.as_array().unwrap().to_owned();
+      apply_schema(schema, &mut [&mut objects]);
+      let expected: Value = serde_json::from_str(fixture).unwrap();
+      assert_eq!(expected["meta_version"], schema.meta_version, "BloodHound {}", name);
+      assert_eq!(Value::from(objects), expected["data"], "BloodHound {}", name);
+   }
+   assert_eq!(bloodhound_schema("CE").unwrap().name, "ce");
+   assert!(bloodhound_schema("4.1").is_none());
+}
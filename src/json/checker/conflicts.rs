@@ -0,0 +1,185 @@
+//! Duplicate objectSid handling
+//!
+//! Replication conflicts (`CNF:` objects) and restored objects can give two LDAP objects with the same SID.
+//! BloodHound keeps the last node imported, so the authoritative object is kept: not a CNF object, then the most
+//! recently changed (uSNChanged of the collected DC, then whenChanged with --object-props extended), then the most
+//! recently created.
+//! The edges (Aces, Members) of the other objects are merged in the kept one and the other objects
+//! are written in `<dirpath>/<domain>_conflicts.csv`.
+use crate::banner::Colorize;
+use log::{info, warn};
+use std::collections::{HashMap, HashSet};
+use std::fs;
+
+use crate::json::maker::csv::csv_field;
+use crate::json::spill::{SpillStore, SPILLED_TYPES};
+
+/// One object removed because another object has the same SID.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SidConflict {
+   pub object_identifier: String,
+   pub object_type: String,
+   pub kept_dn: String,
+   pub removed_dn: String,
+}
+
+/// Function to know if a DN is a replication conflict object, like `CN=name\0ACNF:guid`.
+pub fn is_conflict_dn(dn: &str) -> bool {
+   dn.to_uppercase().contains("CNF:")
+}
+
+/// Function to remove the objects with a duplicated SID, their edges are merged in the kept object.
+/// sid_type is updated with the type of the kept object.
+pub fn resolve_duplicate_sids(
+   vec_objects: &mut [(&str, &mut Vec<serde_json::value::Value>)],
+   sid_type: &mut HashMap<String, String>,
+) -> Vec<SidConflict> {
+   // SID => (vector index, object index)
+   let mut by_sid: HashMap<String, Vec<(usize, usize)>> = HashMap::new();
+   for (v, (_, objects)) in vec_objects.iter().enumerate() {
+      for (i, object) in objects.iter().enumerate() {
+         if let Some(sid) = object["ObjectIdentifier"].as_str() {
+            by_sid.entry(sid.to_string()).or_default().push((v, i));
+         }
+      }
+   }
+
+   let mut conflicts: Vec<SidConflict> = Vec::new();
+   let mut removed: Vec<(usize, usize)> = Vec::new();
+   for (sid, mut positions) in by_sid.into_iter().filter(|(_, positions)| positions.len() > 1) {
+      // Authoritative first: not CNF, then the most recently changed, then the most recently created
+      positions.sort_by_key(|(v, i)| {
+         let properties = &vec_objects[*v].1[*i]["Properties"];
+         let cnf = is_conflict_dn(properties["distinguishedname"].as_str().unwrap_or(""));
+         let time = |property: &str| std::cmp::Reverse(properties[property].as_i64().unwrap_or(-1));
+         (cnf, time("usnchanged"), time("whenchanged"), time("whencreated"))
+      });
+      let (kept_v, kept_i) = positions[0];
+      let kept_dn = vec_objects[kept_v].1[kept_i]["Properties"]["distinguishedname"].as_str().unwrap_or("").to_string();
+      for (v, i) in positions[1..].iter() {
+         let duplicate = vec_objects[*v].1[*i].to_owned();
+         merge_edges(&mut vec_objects[kept_v].1[kept_i], &duplicate);
+         conflicts.push(SidConflict {
+            object_identifier: sid.to_owned(),
+            object_type: vec_objects[*v].0.to_string(),
+            kept_dn: kept_dn.to_owned(),
+            removed_dn: duplicate["Properties"]["distinguishedname"].as_str().unwrap_or("").to_string(),
+         });
+         removed.push((*v, *i));
+      }
+      sid_type.insert(sid, vec_objects[kept_v].0.to_string());
+   }
+
+   // Remove from the end to keep the indexes valid
+   removed.sort();
+   for (v, i) in removed.into_iter().rev() {
+      vec_objects[v].1.remove(i);
+   }
+   conflicts.sort_by(|a, b| a.object_identifier.cmp(&b.object_identifier).then(a.removed_dn.cmp(&b.removed_dn)));
+   conflicts
+}
+
+/// Function to move back in memory the spilled users and computers (--low-memory) sharing their SID with another
+/// object, resolved by [`resolve_duplicate_sids`] with the objects in memory. Only the SIDs are kept during the read.
+pub fn take_spilled_duplicates(
+   vec_users: &mut Vec<serde_json::value::Value>,
+   vec_groups: &Vec<serde_json::value::Value>,
+   vec_computers: &mut Vec<serde_json::value::Value>,
+   spill: &SpillStore,
+) -> std::io::Result<()> {
+   let mut seen: HashSet<String> = HashSet::new();
+   let mut duplicated: HashSet<String> = HashSet::new();
+   let mut add_sid = |object: &serde_json::value::Value| {
+      if let Some(sid) = object["ObjectIdentifier"].as_str() {
+         if !seen.insert(sid.to_string()) {
+            duplicated.insert(sid.to_string());
+         }
+      }
+   };
+   vec_users.iter().chain(vec_groups.iter()).chain(vec_computers.iter()).for_each(&mut add_sid);
+   for data_type in SPILLED_TYPES {
+      spill.visit(data_type, &mut add_sid)?;
+   }
+   if duplicated.is_empty() {
+      return Ok(())
+   }
+   let is_duplicated = |object: &serde_json::value::Value| object["ObjectIdentifier"].as_str().is_some_and(|sid| duplicated.contains(sid));
+   vec_users.extend(spill.take("users", is_duplicated)?);
+   vec_computers.extend(spill.take("computers", is_duplicated)?);
+   Ok(())
+}
+
+/// Function to add the Aces and Members of the removed object missing in the kept one.
+fn merge_edges(kept: &mut serde_json::value::Value, duplicate: &serde_json::value::Value) {
+   for key in ["Aces", "Members"] {
+      let edges = match duplicate[key].as_array() {
+         Some(edges) => edges,
+         None => continue,
+      };
+      if let Some(kept_edges) = kept[key].as_array_mut() {
+         for edge in edges {
+            if !kept_edges.contains(edge) {
+               kept_edges.push(edge.to_owned());
+            }
+         }
+      }
+   }
+}
+
+/// Function to write the removed objects in the conflicts report.
+pub fn make_conflicts_report(path: &String, domain: &String, conflicts: &Vec<SidConflict>) -> std::io::Result<()> {
+   if conflicts.is_empty() {
+      return Ok(())
+   }
+   warn!("{} objects with a duplicated SID, the edges are merged in the authoritative object", conflicts.len().to_string().bold());
+   let mut csv = String::from("objectidentifier,type,kept_dn,removed_dn\n");
+   for conflict in conflicts {
+      csv.push_str(&format!(
+         "{},{},{},{}\n",
+         conflict.object_identifier,
+         conflict.object_type,
+         csv_field(&conflict.kept_dn),
+         csv_field(&conflict.removed_dn),
+      ));
+   }
+   fs::create_dir_all(path)?;
+   let final_path = format!("{}/{}_conflicts.csv", path, domain.replace(".", "-").to_lowercase());
+   fs::write(&final_path, csv)?;
+   info!("{} created!", final_path.bold());
+   Ok(())
+}
+
+/// Test functions
+#[test]
+pub fn test_resolve_duplicate_sids() {
+   let mut vec_users = vec![
+      serde_json::json!({"ObjectIdentifier": "S-1-5-21-1-2-3-1104", "Properties": {"distinguishedname": "CN=JON\\0ACNF:1234,CN=USERS,DC=ESSOS,DC=LOCAL", "whencreated": 1700000000},
+         "Aces": [{"RightName": "GenericAll", "PrincipalSID": "S-1-5-21-1-2-3-1105"}]}),
+      serde_json::json!({"ObjectIdentifier": "S-1-5-21-1-2-3-1104", "Properties": {"distinguishedname": "CN=JON,CN=USERS,DC=ESSOS,DC=LOCAL", "whencreated": 1600000000},
+         "Aces": [{"RightName": "Owns", "PrincipalSID": "S-1-5-21-1-2-3-512"}]}),
+      serde_json::json!({"ObjectIdentifier": "S-1-5-21-1-2-3-1106", "Properties": {"distinguishedname": "CN=ARYA,CN=USERS,DC=ESSOS,DC=LOCAL", "whencreated": 1600000000},
+         "Aces": []}),
+   ];
+   let mut vec_groups: Vec<serde_json::value::Value> = Vec::new();
+   let mut sid_type: HashMap<String, String> = HashMap::new();
+   let conflicts = resolve_duplicate_sids(&mut [("User", &mut vec_users), ("Group", &mut vec_groups)], &mut sid_type);
+
+   assert_eq!(conflicts.len(), 1);
+   assert_eq!(conflicts[0].kept_dn, "CN=JON,CN=USERS,DC=ESSOS,DC=LOCAL");
+   assert_eq!(vec_users.len(), 2);
+   assert_eq!(vec_users[0]["Properties"]["distinguishedname"], "CN=JON,CN=USERS,DC=ESSOS,DC=LOCAL");
+   assert_eq!(vec_users[0]["Aces"].as_array().unwrap().len(), 2);
+   assert_eq!(sid_type["S-1-5-21-1-2-3-1104"], "User");
+
+   // Restored object: the most recently changed is kept, not the most recently created
+   let mut vec_users = vec![
+      serde_json::json!({"ObjectIdentifier": "S-1-5-21-1-2-3-1107", "Properties": {"distinguishedname": "CN=SANSA,OU=OLD,DC=ESSOS,DC=LOCAL", "whencreated": 1700000000, "usnchanged": 12000}, "Aces": []}),
+      serde_json::json!({"ObjectIdentifier": "S-1-5-21-1-2-3-1107", "Properties": {"distinguishedname": "CN=SANSA,OU=NEW,DC=ESSOS,DC=LOCAL", "whencreated": 1600000000, "usnchanged": 45000}, "Aces": []}),
+      serde_json::json!({"ObjectIdentifier": "S-1-5-21-1-2-3-1108", "Properties": {"distinguishedname": "CN=BRAN,OU=OLD,DC=ESSOS,DC=LOCAL", "whenchanged": 1690000000}, "Aces": []}),
+      serde_json::json!({"ObjectIdentifier": "S-1-5-21-1-2-3-1108", "Properties": {"distinguishedname": "CN=BRAN,OU=NEW,DC=ESSOS,DC=LOCAL", "whenchanged": 1695000000}, "Aces": []}),
+   ];
+   let conflicts = resolve_duplicate_sids(&mut [("User", &mut vec_users)], &mut sid_type);
+   assert_eq!(conflicts.len(), 2);
+   assert_eq!(conflicts[0].kept_dn, "CN=SANSA,OU=NEW,DC=ESSOS,DC=LOCAL");
+   assert_eq!(conflicts[1].kept_dn, "CN=BRAN,OU=NEW,DC=ESSOS,DC=LOCAL");
+}
@@ -0,0 +1,71 @@
+//! Frozen SID/type lookup maps for the edge pass
+//!
+//! The parser fills dn_sid, sid_type and fqdn_sid object by object. Once every object is parsed
+//! the maps are frozen in FxHashMaps which are only read, so the checker can share them
+//! between worker threads without any lock.
+use rustc_hash::FxHashMap;
+use std::collections::HashMap;
+
+use crate::enums::spntasks::short_hostnames;
+
+/// Read-only map used by the checker.
+pub type LookupMap = FxHashMap<String, String>;
+
+/// Maps built once after the parsing.
+pub struct LookupMaps {
+    /// DN => SID
+    pub dn_sid: LookupMap,
+    /// SID => Type
+    pub sid_type: LookupMap,
+    /// FQDN => SID
+    pub fqdn_sid: LookupMap,
+    /// Short hostname => FQDN, for the SPNs
+    pub short_fqdn: HashMap<String, String>,
+}
+
+impl LookupMaps {
+    /// Function to freeze the maps filled by the parser.
+    pub fn freeze(
+        dn_sid: HashMap<String, String>,
+        sid_type: HashMap<String, String>,
+        fqdn_sid: HashMap<String, String>,
+    ) -> LookupMaps {
+        let short_fqdn = short_hostnames(fqdn_sid.keys());
+        LookupMaps {
+            dn_sid: dn_sid.into_iter().collect(),
+            sid_type: sid_type.into_iter().collect(),
+            fqdn_sid: fqdn_sid.into_iter().collect(),
+            short_fqdn,
+        }
+    }
+}
+
+/// Function to run one closure on every object, split between the available threads.
+pub fn par_for_each<F>(objects: &mut Vec<serde_json::value::Value>, f: F)
+where
+    F: Fn(&mut serde_json::value::Value) + Sync,
+{
+    let threads = std::thread::available_parallelism().map(|n| n.get()).unwrap_or(1);
+    if threads <= 1 || objects.len() < 2 * threads {
+        objects.iter_mut().for_each(|object| f(object));
+        return
+    }
+    let chunk_size = (objects.len() + threads - 1) / threads;
+    let f = &f;
+    std::thread::scope(|scope| {
+        for chunk in objects.chunks_mut(chunk_size) {
+            scope.spawn(move || chunk.iter_mut().for_each(|object| f(object)));
+        }
+    });
+}
+
+/// Test functions
+#[test]
+pub fn test_par_for_each() {
+    let mut objects: Vec<serde_json::value::Value> = (0..1000).map(|i| serde_json::json!({"Index": i})).collect();
+    let lookups = LookupMaps::freeze(HashMap::new(), vec![("S-1-5-32-544".to_string(), "Group".to_string())].into_iter().collect(), HashMap::new());
+    par_for_each(&mut objects, |object| {
+        object["Type"] = lookups.sid_type.get("S-1-5-32-544").unwrap().to_owned().into();
+    });
+    assert!(objects.iter().enumerate().all(|(i, object)| object["Index"] == i && object["Type"] == "Group"));
+}
@@ -1,12 +1,20 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use log::{info,debug};
-use indicatif::ProgressBar;
+use crate::banner::ProgressBar;
 use crate::banner::progress_bar;
-use std::convert::TryInto;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Instant;
 
 pub mod bh_41;
+pub mod lookup;
+pub mod conflicts;
+pub mod compat;
+
+use lookup::{LookupMap, LookupMaps, par_for_each};
+use crate::json::spill::{SpillStore, for_each_page, select_objects, visit_objects};
 
 /// Functions to replace and add missing values
+/// The users and computers are checked page by page, the pages spilled by --low-memory are read and rewritten one by one.
 pub fn check_all_result(
    domain: &String,
    
@@ -19,40 +27,103 @@ pub fn check_all_result(
    _vec_fsps: &mut Vec<serde_json::value::Value>,
    vec_containers: &mut Vec<serde_json::value::Value>,
    vec_trusts: &mut Vec<serde_json::value::Value>,
+   vec_psos: &Vec<serde_json::value::Value>,
 
-   dn_sid: &mut HashMap<String, String>,
-   sid_type: &mut HashMap<String, String>,
-   fqdn_sid: &mut HashMap<String, String>,
+   lookups: &LookupMaps,
    _fqdn_ip: &mut HashMap<String, String>,
-)
+   spill: Option<&SpillStore>,
+) -> std::io::Result<()>
 {
     info!("Starting checker to replace some values...");
+    let started = Instant::now();
+    let dn_sid = &lookups.dn_sid;
+    let sid_type = &lookups.sid_type;
+    let fqdn_sid = &lookups.fqdn_sid;
     debug!("Replace SID with checker.rs started");
-    bh_41::replace_fqdn_by_sid(vec_users, &fqdn_sid);
-    bh_41::replace_fqdn_by_sid(vec_computers, &fqdn_sid);
+    for (data_type, objects) in [("users", &mut *vec_users), ("computers", &mut *vec_computers)] {
+        for_each_page(objects, spill, data_type, |page| {
+            bh_41::replace_fqdn_by_sid(page, &fqdn_sid, &lookups.short_fqdn);
+            bh_41::add_normalized_spns(page, &lookups.short_fqdn);
+        })?;
+    }
     bh_41::replace_sid_members(vec_groups, &dn_sid, &sid_type, &vec_trusts);
     debug!("Replace SID finished!");
 
     debug!("Adding defaults groups and default users");
-    bh_41::add_default_groups(vec_groups, &vec_computers, domain.to_owned());
-    bh_41::add_default_users(vec_users, domain.to_owned());
+    let delegating = select_objects(vec_computers, spill, "computers", |computer| computer["Properties"]["unconstraineddelegation"].as_bool().unwrap_or(false))?;
+    bh_41::add_default_groups(vec_groups, &delegating, domain.to_owned());
+    // The domainsid of the default users is the one of the first user, maybe spilled
+    let first_user = match spill {
+        Some(spill) if vec_users.is_empty() => spill.first("users")?,
+        _ => None,
+    };
+    match first_user {
+        Some(user) => {
+            let mut users = vec![user];
+            bh_41::add_default_users(&mut users, domain.to_owned());
+            vec_users.extend(users.into_iter().skip(1));
+        }
+        None => bh_41::add_default_users(vec_users, domain.to_owned()),
+    }
     debug!("Defaults groups and default users added!");
 
+    debug!("Adding primary group members started");
+    let mut primary_members: bh_41::PrimaryMembers = HashMap::new();
+    visit_objects(vec_users, spill, "users", |user| bh_41::add_primary_member(&mut primary_members, user, "User"))?;
+    visit_objects(vec_computers, spill, "computers", |computer| bh_41::add_primary_member(&mut primary_members, computer, "Computer"))?;
+    bh_41::set_primary_group_members(vec_groups, &primary_members);
+    drop(primary_members);
+    debug!("Primary group members added!");
+
+    debug!("Adding RODC properties started");
+    let mut revealed_on: HashMap<String, Vec<String>> = HashMap::new();
+    for_each_page(vec_computers, spill, "computers", |page| bh_41::resolve_rodc_properties(page, &dn_sid, &sid_type, &mut revealed_on))?;
+    if !revealed_on.is_empty() {
+        for (data_type, objects) in [("users", &mut *vec_users), ("computers", &mut *vec_computers)] {
+            for_each_page(objects, spill, data_type, |page| bh_41::add_revealed_on_rodcs(page, &revealed_on))?;
+        }
+    }
+    debug!("RODC properties added!");
+
+    debug!("Adding managed service account hosts started");
+    let mut links: HashSet<(String, String)> = HashSet::new();
+    let mut hosted = false;
+    visit_objects(vec_computers, spill, "computers", |computer| hosted |= bh_41::add_service_account_links(&mut links, computer, "Computer", &dn_sid))?;
+    visit_objects(vec_users, spill, "users", |user| hosted |= bh_41::add_service_account_links(&mut links, user, "User", &dn_sid))?;
+    if hosted || !links.is_empty() {
+        let mut links: Vec<(String, String)> = links.into_iter().collect();
+        links.sort();
+        for_each_page(vec_users, spill, "users", |page| bh_41::set_service_account_hosts(page, &mut Vec::new(), &links))?;
+        for_each_page(vec_computers, spill, "computers", |page| bh_41::set_service_account_hosts(&mut Vec::new(), page, &links))?;
+    }
+    debug!("Managed service account hosts added!");
+
     debug!("Adding PrincipalType for ACEs started");
-    add_type_for_ace(vec_users, &sid_type);
+    for_each_page(vec_users, spill, "users", |page| add_type_for_ace(page, &sid_type))?;
     add_type_for_ace(vec_groups, &sid_type);
-    add_type_for_ace(vec_computers, &sid_type);
+    for_each_page(vec_computers, spill, "computers", |page| {
+        add_type_for_ace(page, &sid_type);
+        add_type_for_allowtedtoact(page, &sid_type);
+    })?;
     add_type_for_ace(vec_gpos, &sid_type);
     add_type_for_ace(vec_ous, &sid_type);
     add_type_for_ace(vec_domains, &sid_type);
     add_type_for_ace(vec_containers, &sid_type);
-    add_type_for_allowtedtoact(vec_computers, &sid_type);
     debug!("PrincipalType for ACEs added!");
 
+    debug!("Adding DCSync edges started");
+    bh_41::add_dcsync_edges(vec_domains);
+    debug!("DCSync edges added!");
+
+    debug!("Adding highvalue groups started");
+    bh_41::add_high_value(vec_groups, vec_domains);
+    debug!("highvalue groups added!");
+
     debug!("Adding ChildObject members started");
-    bh_41::add_childobjects_members(vec_ous, &dn_sid, &sid_type);
-    bh_41::add_childobjects_members(vec_domains, &dn_sid, &sid_type);
-    bh_41::add_childobjects_members(vec_containers, &dn_sid, &sid_type);
+    let blocking = bh_41::blocking_ous(vec_ous);
+    bh_41::add_childobjects_members(vec_ous, &dn_sid, &sid_type, &blocking);
+    bh_41::add_childobjects_members(vec_domains, &dn_sid, &sid_type, &blocking);
+    bh_41::add_childobjects_members(vec_containers, &dn_sid, &sid_type, &blocking);
     debug!("ChildObject members added!");
 
     debug!("Adding domainsid started");
@@ -63,7 +134,7 @@ pub fn check_all_result(
     debug!("domainsid added!");
         
     debug!("Adding affected computers in domain GpoChanges");
-    bh_41::add_affected_computers(vec_domains, &sid_type);
+    bh_41::add_affected_computers(vec_domains, &dn_sid, &sid_type, &blocking);
     debug!("affected computers added!");
 
     debug!("Replacing guid for gplinks started");
@@ -71,65 +142,77 @@ pub fn check_all_result(
     bh_41::replace_guid_gplink(vec_domains, &dn_sid);
     debug!("guid for gplinks added!");
 
+    debug!("Adding password policies started");
+    // Only the users a PSO applies to directly are needed to resolve the policies, maybe spilled
+    let pso_users = if vec_psos.is_empty() {
+        Vec::new()
+    } else {
+        select_objects(vec_users, spill, "users", |user| bh_41::is_pso_target(user, vec_psos))?
+    };
+    let effective = bh_41::effective_password_policies(&pso_users, vec_groups, vec_psos);
+    for_each_page(vec_users, spill, "users", |page| bh_41::set_password_policies(page, &mut Vec::new(), vec_domains, vec_psos, &effective))?;
+    bh_41::set_password_policies(&mut Vec::new(), vec_groups, vec_domains, vec_psos, &effective);
+    debug!("Password policies added!");
+
     if vec_trusts.len() > 0 {
         debug!("Adding trust domain relation");
         bh_41::add_trustdomain(vec_domains, vec_trusts);
         debug!("Trust domain relation added!");
     }
+    debug!("Checker finished in {:?}", started.elapsed());
     info!("Checking and replacing some values finished!");
+    Ok(())
 }
 
 /// This function check PrincipalSID for all Ace and add the PrincipalType "Group","User","Computer"
-pub fn add_type_for_ace(vec_replaced: &mut Vec<serde_json::value::Value>, sid_type: &HashMap<String, String>)
+pub fn add_type_for_ace(vec_replaced: &mut Vec<serde_json::value::Value>, sid_type: &LookupMap)
 {
     // Needed for progress bar stats
     let pb = ProgressBar::new(1);
-    let mut count = 0;
-    let total = vec_replaced.len();
+    let count = AtomicU64::new(0);
+    let total = vec_replaced.len() as u64;
 
-    for i in 0..vec_replaced.len()
+    par_for_each(vec_replaced, |object|
     {
         // Manage progress bar
-		count += 1;
-        let pourcentage = 100 * count / total;
-        progress_bar(pb.to_owned(),"Adding Type for ACE objects".to_string(),pourcentage.try_into().unwrap(),"%".to_string());
+        let pourcentage = 100 * (count.fetch_add(1, Ordering::Relaxed) + 1) / total;
+        progress_bar(pb.to_owned(),"Adding Type for ACE objects".to_string(),pourcentage,"%".to_string());
 
         // ACE by ACE
-        if vec_replaced[i]["Aces"].as_array().unwrap().len() != 0 {
-            for j in 0..vec_replaced[i]["Aces"].as_array().unwrap().len()
+        if object["Aces"].as_array().unwrap().len() != 0 {
+            for j in 0..object["Aces"].as_array().unwrap().len()
             {
                 let group: String = "Group".to_string();
-                let type_object = sid_type.get(&vec_replaced[i]["Aces"][j]["PrincipalSID"].as_str().unwrap().to_string()).unwrap_or(&group);
-                vec_replaced[i]["Aces"][j]["PrincipalType"] = type_object.to_owned().into();
+                let type_object = sid_type.get(&object["Aces"][j]["PrincipalSID"].as_str().unwrap().to_string()).unwrap_or(&group);
+                object["Aces"][j]["PrincipalType"] = type_object.to_owned().into();
             }
         }
-    }
+    });
     pb.finish_and_clear();
 }
 
 /// This function check PrincipalSID for all AllowedToAct object and add the PrincipalType "Group","User","Computer"
-pub fn add_type_for_allowtedtoact(vec_replaced: &mut Vec<serde_json::value::Value>, sid_type: &HashMap<String, String>)
+pub fn add_type_for_allowtedtoact(vec_replaced: &mut Vec<serde_json::value::Value>, sid_type: &LookupMap)
 {
     // Needed for progress bar stats
     let pb = ProgressBar::new(1);
-    let mut count = 0;
-    let total = vec_replaced.len();
+    let count = AtomicU64::new(0);
+    let total = vec_replaced.len() as u64;
 
-    for i in 0..vec_replaced.len()
+    par_for_each(vec_replaced, |object|
     {
         // Manage progress bar
-		count += 1;
-        let pourcentage = 100 * count / total;
-        progress_bar(pb.to_owned(),"Adding Type for AllowedToAct objects".to_string(),pourcentage.try_into().unwrap(),"%".to_string());
+        let pourcentage = 100 * (count.fetch_add(1, Ordering::Relaxed) + 1) / total;
+        progress_bar(pb.to_owned(),"Adding Type for AllowedToAct objects".to_string(),pourcentage,"%".to_string());
 
-        if vec_replaced[i]["AllowedToAct"].as_array().unwrap().len() != 0 {
-            for j in 0..vec_replaced[i]["AllowedToAct"].as_array().unwrap().len()
+        if object["AllowedToAct"].as_array().unwrap().len() != 0 {
+            for j in 0..object["AllowedToAct"].as_array().unwrap().len()
             {
                 let default: String = "Computer".to_string();
-                let type_object = sid_type.get(&vec_replaced[i]["AllowedToAct"][j]["ObjectIdentifier"].as_str().unwrap().to_string()).unwrap_or(&default);
-                vec_replaced[i]["AllowedToAct"][j]["ObjectType"] = type_object.to_owned().into();
+                let type_object = sid_type.get(&object["AllowedToAct"][j]["ObjectIdentifier"].as_str().unwrap().to_string()).unwrap_or(&default);
+                object["AllowedToAct"][j]["ObjectType"] = type_object.to_owned().into();
             }
         }
-    }
+    });
     pb.finish_and_clear();
 }
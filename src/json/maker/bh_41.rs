@@ -1,9 +1,11 @@
 use std::fs;
 use log::{info,debug};
-use colored::Colorize;
+use crate::banner::Colorize;
 use std::collections::HashMap;
 
 use crate::json::templates::*;
+use crate::json::maker::json_to_string;
+use crate::json::checker::compat::meta_version;
 
 /// Current Bloodhound version 4.2+
 pub const BLOODHOUND_VERSION_4: i8 = 5;
@@ -14,13 +16,14 @@ pub fn add_user(
    user: Vec<serde_json::value::Value>,
    path: &String,
    json_result: &mut HashMap<String, String>,
-   zip: bool
+   zip: bool,
+   pretty: bool
 ) -> std::io::Result<()>
 {
    debug!("Making users.json");
 
    // Prepare template and get result in const var
-   let mut users_json = bh_41::prepare_final_json_file_template(BLOODHOUND_VERSION_4, "users".to_owned());
+   let mut users_json = bh_41::prepare_final_json_file_template(meta_version(), "users".to_owned());
    // Add all users found
    users_json["data"] = user.into();
    // change count number
@@ -39,12 +42,12 @@ pub fn add_user(
       final_path.push_str("/");
       final_path.push_str(domain_format);
       final_path.push_str("_users.json");
-      fs::write(&final_path,&users_json.to_string())?;
+      fs::write(&final_path, json_to_string(&mut users_json, pretty))?;
       info!("{} created!",final_path.bold());
    }
    else
    {
-      json_result.insert("users.json".to_string(),users_json.to_owned().to_string());
+      json_result.insert("users.json".to_string(), json_to_string(&mut users_json, pretty));
    }
     
    Ok(())
@@ -57,13 +60,14 @@ pub fn add_group(
    group: Vec<serde_json::value::Value>,
    path: &String,
    json_result: &mut HashMap<String, String>,
-   zip: bool
+   zip: bool,
+   pretty: bool
 ) -> std::io::Result<()>
 {
    debug!("Making groups.json");
 
    // Prepare template and get result in const var
-   let mut groups_json = bh_41::prepare_final_json_file_template(BLOODHOUND_VERSION_4, "groups".to_owned());
+   let mut groups_json = bh_41::prepare_final_json_file_template(meta_version(), "groups".to_owned());
    // Add all groups found
    groups_json["data"] = group.into();
    // change count number
@@ -82,12 +86,12 @@ pub fn add_group(
       final_path.push_str("/");
       final_path.push_str(domain_format);
       final_path.push_str("_groups.json");
-      fs::write(&final_path, &groups_json.to_string())?;
+      fs::write(&final_path, json_to_string(&mut groups_json, pretty))?;
       info!("{} created!",final_path.bold());
    }
    else
    {
-      json_result.insert("groups.json".to_string(),groups_json.to_owned().to_string());
+      json_result.insert("groups.json".to_string(), json_to_string(&mut groups_json, pretty));
    }
 
    Ok(())
@@ -98,13 +102,14 @@ pub fn add_computer(
 	domain_format: &String,
    computer: Vec<serde_json::value::Value>,
    path: &String, json_result: &mut HashMap<String, String>,
-   zip: bool
+   zip: bool,
+   pretty: bool
 ) -> std::io::Result<()>
 {
    debug!("Making computers.json");
 
    // Prepare template and get result in const var
-   let mut computers_json = bh_41::prepare_final_json_file_template(BLOODHOUND_VERSION_4, "computers".to_owned());
+   let mut computers_json = bh_41::prepare_final_json_file_template(meta_version(), "computers".to_owned());
    // Add all computers found
    computers_json["data"] = computer.into();
    // change count number
@@ -123,12 +128,12 @@ pub fn add_computer(
       final_path.push_str("/");
       final_path.push_str(domain_format);
       final_path.push_str("_computers.json");    
-      fs::write(&final_path, &computers_json.to_string())?;
+      fs::write(&final_path, json_to_string(&mut computers_json, pretty))?;
       info!("{} created!",final_path.bold());
    }
    else
    {
-      json_result.insert("computers.json".to_string(),computers_json.to_owned().to_string());
+      json_result.insert("computers.json".to_string(), json_to_string(&mut computers_json, pretty));
    }
 
    Ok(())
@@ -141,13 +146,14 @@ pub fn add_ou(
    ou: Vec<serde_json::value::Value>,
    path: &String,
    json_result: &mut HashMap<String, String>,
-   zip: bool
+   zip: bool,
+   pretty: bool
 ) -> std::io::Result<()>
 {
    debug!("Making ous.json");
 
    // Prepare template and get result in const var
-   let mut ous_json = bh_41::prepare_final_json_file_template(BLOODHOUND_VERSION_4, "ous".to_owned());
+   let mut ous_json = bh_41::prepare_final_json_file_template(meta_version(), "ous".to_owned());
    // Add all ous found
    ous_json["data"] = ou.into();
    // change count number
@@ -166,12 +172,12 @@ pub fn add_ou(
       final_path.push_str("/");
       final_path.push_str(domain_format);
       final_path.push_str("_ous.json");
-      fs::write(&final_path, &ous_json.to_string())?;
+      fs::write(&final_path, json_to_string(&mut ous_json, pretty))?;
       info!("{} created!",final_path.bold());
    }
    else
    {
-      json_result.insert("ous.json".to_string(),ous_json.to_owned().to_string());
+      json_result.insert("ous.json".to_string(), json_to_string(&mut ous_json, pretty));
    }
 
    Ok(())
@@ -183,13 +189,14 @@ pub fn add_domain(
    domain: Vec<serde_json::value::Value>,
    path: &String,
    json_result: &mut HashMap<String, String>,
-   zip: bool
+   zip: bool,
+   pretty: bool
 ) -> std::io::Result<()>
 {
    debug!("Making domains.json");
 
    // Prepare template and get result in const var
-   let mut domains_json = bh_41::prepare_final_json_file_template(BLOODHOUND_VERSION_4, "domains".to_owned());
+   let mut domains_json = bh_41::prepare_final_json_file_template(meta_version(), "domains".to_owned());
    // Add all domains found
    domains_json["data"] = domain.into();
    // change count number
@@ -208,12 +215,12 @@ pub fn add_domain(
       final_path.push_str("/");
       final_path.push_str(domain_format);
       final_path.push_str("_domains.json");    
-      fs::write(&final_path, &domains_json.to_string())?;
+      fs::write(&final_path, json_to_string(&mut domains_json, pretty))?;
       info!("{} created!",final_path.bold());
    }
    else
    {
-      json_result.insert("domains.json".to_string(),domains_json.to_owned().to_string());
+      json_result.insert("domains.json".to_string(), json_to_string(&mut domains_json, pretty));
    }
 
    Ok(())
@@ -225,13 +232,14 @@ pub fn add_gpo(
    gpo: Vec<serde_json::value::Value>,
    path: &String,
    json_result: &mut HashMap<String, String>,
-   zip: bool
+   zip: bool,
+   pretty: bool
 ) -> std::io::Result<()>
 {
    debug!("Making gpos.json");
 
    // Prepare template and get result in const var
-   let mut gpos_json = bh_41::prepare_final_json_file_template(BLOODHOUND_VERSION_4, "gpos".to_owned());
+   let mut gpos_json = bh_41::prepare_final_json_file_template(meta_version(), "gpos".to_owned());
    // Add all gpos found
    gpos_json["data"] = gpo.into();
    // change count number
@@ -250,12 +258,12 @@ pub fn add_gpo(
       final_path.push_str("/");
       final_path.push_str(domain_format);
       final_path.push_str("_gpos.json");    
-      fs::write(&final_path, &gpos_json.to_string())?;
+      fs::write(&final_path, json_to_string(&mut gpos_json, pretty))?;
       info!("{} created!",final_path.bold());
    }
    else
    {
-      json_result.insert("gpos.json".to_string(),gpos_json.to_owned().to_string());
+      json_result.insert("gpos.json".to_string(), json_to_string(&mut gpos_json, pretty));
    }
 
    Ok(())
@@ -267,13 +275,14 @@ pub fn add_container(
    container: Vec<serde_json::value::Value>,
    path: &String,
    json_result: &mut HashMap<String, String>,
-   zip: bool
+   zip: bool,
+   pretty: bool
 ) -> std::io::Result<()>
 {
    debug!("Making containers.json");
 
    // Prepare template and get result in const var
-   let mut containers_json = bh_41::prepare_final_json_file_template(BLOODHOUND_VERSION_4, "containers".to_owned());
+   let mut containers_json = bh_41::prepare_final_json_file_template(meta_version(), "containers".to_owned());
     
    // Add all containers found
    containers_json["data"] = container.into();
@@ -293,13 +302,51 @@ pub fn add_container(
       final_path.push_str("/");
       final_path.push_str(domain_format);
       final_path.push_str("_containers.json");    
-      fs::write(&final_path, &containers_json.to_string())?;
+      fs::write(&final_path, json_to_string(&mut containers_json, pretty))?;
       info!("{} created!",final_path.bold());
    }
    else
    {
-      json_result.insert("containers.json".to_string(),containers_json.to_owned().to_string());
+      json_result.insert("containers.json".to_string(), json_to_string(&mut containers_json, pretty));
    }
 
    Ok(())
-}
\ No newline at end of file
+}
+
+/// Function to create one chunk file of a type, like users_002.json (--chunk-size).
+pub fn add_chunk(
+	domain_format: &String,
+   data_type: &str,
+   file_stem: &String,
+   objects: Vec<serde_json::value::Value>,
+   path: &String,
+   json_result: &mut HashMap<String, String>,
+   zip: bool,
+   pretty: bool
+) -> std::io::Result<()>
+{
+   debug!("Making {}.json", file_stem);
+
+   // Prepare template, the meta count is the count of the chunk
+   let mut chunk_json = bh_41::prepare_final_json_file_template(meta_version(), data_type.to_owned());
+   let count = objects.len();
+   chunk_json["data"] = objects.into();
+   chunk_json["meta"]["count"] = count.into();
+   info!("{} {} parsed in {}!",count.to_string().bold(),data_type,file_stem);
+
+   // result
+   fs::create_dir_all(path)?;
+
+   if ! zip
+   {
+      let final_path = format!("{}/{}_{}.json", path, domain_format, file_stem);
+      fs::write(&final_path, json_to_string(&mut chunk_json, pretty))?;
+      info!("{} created!",final_path.bold());
+   }
+   else
+   {
+      json_result.insert(format!("{}.json", file_stem), json_to_string(&mut chunk_json, pretty));
+   }
+
+   Ok(())
+}
@@ -0,0 +1,217 @@
+//! CSV export of the principals and the edges (--output-format csv)
+//!
+//! The CSV files are written alongside the BloodHound JSON files, for the triage in a spreadsheet or an import in a SIEM:
+//! `<domain>_users.csv`, `<domain>_computers.csv` and `<domain>_groups.csv` have one line per object with its
+//! flattened properties, `<domain>_edges.csv` has one line per relationship of every object type.
+//! The CSV reports of the modules quote their fields with csv_field() too.
+use crate::banner::Colorize;
+use log::{debug, info};
+use serde_json::value::Value;
+use std::collections::HashMap;
+use std::fs;
+
+/// Local groups of the computers and their edge.
+const LOCAL_GROUP_EDGES: [(&str, &str); 4] = [
+   ("LocalAdmins", "AdminTo"),
+   ("RemoteDesktopUsers", "CanRDP"),
+   ("DcomUsers", "ExecuteDCOM"),
+   ("PSRemoteUsers", "CanPSRemote"),
+];
+/// Session collections of the computers.
+const SESSION_FIELDS: [&str; 3] = ["Sessions", "PrivilegedSessions", "RegistrySessions"];
+
+/// One relationship of the edges file.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CsvEdge {
+   pub source: String,
+   pub source_type: String,
+   pub edge: String,
+   pub target: String,
+   pub target_type: String,
+   pub inherited: bool,
+}
+
+/// Function to write the CSV files of the principals and the edges.
+pub fn make_csv_result(
+   path: &String,
+   domain_format: &String,
+   objects: &[(&str, &Vec<Value>)],
+) -> std::io::Result<()> {
+   fs::create_dir_all(path)?;
+   for (object_type, file) in [("User", "users"), ("Computer", "computers"), ("Group", "groups")] {
+      let vec_objects = match objects.iter().find(|(name, _)| *name == object_type) {
+         Some((_, vec_objects)) => vec_objects,
+         None => continue,
+      };
+      let final_path = format!("{}/{}_{}.csv", path, domain_format, file);
+      fs::write(&final_path, objects_to_csv(vec_objects))?;
+      info!("{} created!", final_path.bold());
+   }
+
+   let mut id_name: HashMap<String, String> = HashMap::new();
+   let mut edges: Vec<CsvEdge> = Vec::new();
+   for (object_type, vec_objects) in objects {
+      for object in vec_objects.iter() {
+         if let (Some(id), Some(name)) = (object["ObjectIdentifier"].as_str(), object["Properties"]["name"].as_str()) {
+            id_name.insert(id.to_uppercase(), name.to_string());
+         }
+         edges.extend(object_edges(object, object_type));
+      }
+   }
+   let name = |id: &String| id_name.get(&id.to_uppercase()).cloned().unwrap_or_default();
+
+   let mut csv = String::from("source,sourcetype,sourceidentifier,edge,target,targettype,targetidentifier,inherited\n");
+   for edge in &edges {
+      csv.push_str(&format!(
+         "{},{},{},{},{},{},{},{}\n",
+         csv_field(&name(&edge.source)),
+         edge.source_type,
+         csv_field(&edge.source),
+         edge.edge,
+         csv_field(&name(&edge.target)),
+         edge.target_type,
+         csv_field(&edge.target),
+         edge.inherited,
+      ));
+   }
+   debug!("{} edges in CSV", edges.len());
+   let final_path = format!("{}/{}_edges.csv", path, domain_format);
+   fs::write(&final_path, csv)?;
+   info!("{} created!", final_path.bold());
+   Ok(())
+}
+
+/// Function to write the objects of one type, one column per property in the order of their first appearance.
+pub fn objects_to_csv(objects: &Vec<Value>) -> String {
+   let mut columns: Vec<&String> = Vec::new();
+   for object in objects {
+      for key in object["Properties"].as_object().into_iter().flat_map(|properties| properties.keys()) {
+         if !columns.contains(&key) {
+            columns.push(key);
+         }
+      }
+   }
+
+   let mut csv = String::from("objectidentifier");
+   for column in &columns {
+      csv.push(',');
+      csv.push_str(&csv_field(column));
+   }
+   csv.push('\n');
+   for object in objects {
+      csv.push_str(&csv_field(&flatten_value(&object["ObjectIdentifier"])));
+      for column in &columns {
+         csv.push(',');
+         csv.push_str(&csv_field(&flatten_value(&object["Properties"][column.as_str()])));
+      }
+      csv.push('\n');
+   }
+   csv
+}
+
+/// Function to flatten one property: the arrays are joined with ";" and null is empty.
+pub fn flatten_value(value: &Value) -> String {
+   match value {
+      Value::Null => String::new(),
+      Value::String(text) => text.to_owned(),
+      Value::Array(values) => values.iter().map(flatten_value).collect::<Vec<String>>().join(";"),
+      other => other.to_string(),
+   }
+}
+
+/// Function to get every relationship of one object: ACEs, members, delegations, local groups, sessions, links...
+pub fn object_edges(object: &Value, object_type: &str) -> Vec<CsvEdge> {
+   let mut edges: Vec<CsvEdge> = Vec::new();
+   let id = match object["ObjectIdentifier"].as_str() {
+      Some(id) => id.to_string(),
+      None => return edges,
+   };
+   let items = |field: &Value| field.as_array().cloned().unwrap_or_default();
+   let text = |value: &Value, default: &str| value.as_str().unwrap_or(default).to_string();
+   let mut push = |source: String, source_type: String, edge: String, target: String, target_type: String, inherited: bool| {
+      if !source.is_empty() && !target.is_empty() {
+         edges.push(CsvEdge { source, source_type, edge, target, target_type, inherited });
+      }
+   };
+
+   for ace in items(&object["Aces"]) {
+      push(text(&ace["PrincipalSID"], ""), text(&ace["PrincipalType"], "Base"), text(&ace["RightName"], ""), id.to_owned(), object_type.to_string(), ace["IsInherited"].as_bool().unwrap_or(false));
+   }
+   for member in items(&object["Members"]) {
+      push(text(&member["ObjectIdentifier"], ""), text(&member["ObjectType"], "Base"), "MemberOf".to_string(), id.to_owned(), object_type.to_string(), false);
+   }
+   for (field, edge) in [("AllowedToDelegate", "AllowedToDelegate"), ("HasSIDHistory", "HasSIDHistory"), ("ChildObjects", "Contains")] {
+      for target in items(&object[field]) {
+         push(id.to_owned(), object_type.to_string(), edge.to_string(), text(&target["ObjectIdentifier"], ""), text(&target["ObjectType"], "Base"), false);
+      }
+   }
+   for principal in items(&object["AllowedToAct"]) {
+      push(text(&principal["ObjectIdentifier"], ""), text(&principal["ObjectType"], "Base"), "AllowedToAct".to_string(), id.to_owned(), object_type.to_string(), false);
+   }
+   for target in items(&object["SPNTargets"]) {
+      push(id.to_owned(), object_type.to_string(), text(&target["Service"], "SPNTarget"), text(&target["ComputerSID"], ""), "Computer".to_string(), false);
+   }
+   for (field, edge) in LOCAL_GROUP_EDGES {
+      for member in items(&object[field]["Results"]) {
+         push(text(&member["ObjectIdentifier"], ""), text(&member["ObjectType"], "Base"), edge.to_string(), id.to_owned(), object_type.to_string(), false);
+      }
+   }
+   for field in SESSION_FIELDS {
+      for session in items(&object[field]["Results"]) {
+         push(id.to_owned(), object_type.to_string(), "HasSession".to_string(), text(&session["UserSID"], ""), "User".to_string(), false);
+      }
+   }
+   for link in items(&object["Links"]) {
+      push(text(&link["GUID"], ""), "GPO".to_string(), "GPLink".to_string(), id.to_owned(), object_type.to_string(), false);
+   }
+   edges
+}
+
+/// Function to write one CSV field: quoted when it holds a comma, a quote or a line break (RFC 4180),
+/// prefixed with ' when it starts with = + - or @, not run as a formula by a spreadsheet (CWE-1236).
+pub fn csv_field(value: &str) -> String {
+   let value = if value.starts_with(['=', '+', '-', '@']) {
+      format!("'{}", value)
+   } else {
+      value.to_owned()
+   };
+   if value.contains([',', '"', '\n', '\r']) {
+      format!("\"{}\"", value.replace("\"", "\"\""))
+   } else {
+      value
+   }
+}
+
+/// Test functions
+#[test]
+pub fn test_csv_export() {
+   let users = vec![
+      serde_json::json!({"ObjectIdentifier": "S-1-5-21-1-2-3-1104", "Properties": {"name": "JON.SNOW@ESSOS.LOCAL", "enabled": true, "serviceprincipalnames": ["HTTP/a", "HTTP/b"], "description": "Lord, \"Commander\""},
+         "Aces": [{"RightName": "GenericWrite", "IsInherited": true, "PrincipalSID": "S-1-5-21-1-2-3-512", "PrincipalType": "Group"}],
+         "SPNTargets": [{"ComputerSID": "S-1-5-21-1-2-3-1001", "Port": 1433, "Service": "SQLAdmin"}]}),
+      serde_json::json!({"ObjectIdentifier": "S-1-5-21-1-2-3-1105", "Properties": {"name": "SAM@ESSOS.LOCAL", "lastlogon": 0}}),
+   ];
+   assert_eq!(
+      objects_to_csv(&users),
+      "objectidentifier,name,enabled,serviceprincipalnames,description,lastlogon\n\
+      S-1-5-21-1-2-3-1104,JON.SNOW@ESSOS.LOCAL,true,HTTP/a;HTTP/b,\"Lord, \"\"Commander\"\"\",\n\
+      S-1-5-21-1-2-3-1105,SAM@ESSOS.LOCAL,,,,0\n"
+   );
+   assert_eq!(csv_field("Line\r\nbreak"), "\"Line\r\nbreak\"");
+   assert_eq!(csv_field("=HYPERLINK(\"http://evil\")"), "\"'=HYPERLINK(\"\"http://evil\"\")\"");
+   assert_eq!(csv_field("@SUM(1+1)"), "'@SUM(1+1)");
+   assert_eq!(csv_field("-1"), "'-1");
+   assert_eq!(csv_field("JON.SNOW@ESSOS.LOCAL"), "JON.SNOW@ESSOS.LOCAL");
+
+   let edges = object_edges(&users[0], "User");
+   assert_eq!(edges.len(), 2);
+   assert_eq!((edges[0].source.as_str(), edges[0].edge.as_str(), edges[0].inherited), ("S-1-5-21-1-2-3-512", "GenericWrite", true));
+   assert_eq!((edges[1].edge.as_str(), edges[1].target.as_str()), ("SQLAdmin", "S-1-5-21-1-2-3-1001"));
+
+   let computer = serde_json::json!({"ObjectIdentifier": "S-1-5-21-1-2-3-1001",
+      "LocalAdmins": {"Results": [{"ObjectIdentifier": "S-1-5-21-1-2-3-1104", "ObjectType": "User"}], "Collected": true},
+      "Sessions": {"Results": [{"ComputerSID": "S-1-5-21-1-2-3-1001", "UserSID": "S-1-5-21-1-2-3-1105"}]}});
+   let edges = object_edges(&computer, "Computer");
+   assert_eq!((edges[0].source.as_str(), edges[0].edge.as_str(), edges[0].target_type.as_str()), ("S-1-5-21-1-2-3-1104", "AdminTo", "Computer"));
+   assert_eq!((edges[1].source.as_str(), edges[1].edge.as_str(), edges[1].target.as_str()), ("S-1-5-21-1-2-3-1001", "HasSession", "S-1-5-21-1-2-3-1105"));
+}
@@ -0,0 +1,105 @@
+//! Generic graph export (--output-format graph)
+//!
+//! One JSON file with the nodes and the edges of every object type, in a neutral schema for the tools other
+//! than BloodHound (Gephi with a JSON importer, networkx, custom scripts):
+//!
+//! ```text
+//! {"meta": {"domain": "ESSOS.LOCAL", "nodes": 2, "edges": 1},
+//!  "nodes": [{"id": "S-1-5-21-...-1104", "type": "User", "label": "JON.SNOW@ESSOS.LOCAL", "properties": {...}}, ...],
+//!  "edges": [{"source": "S-1-5-21-...-1104", "target": "S-1-5-21-...-512", "type": "MemberOf", "properties": {"inherited": false}}]}
+//! ```
+//!
+//! The principals only known from an edge (well-known SIDs, foreign principals) are nodes without properties.
+//! The result is written in `<dirpath>/<domain>_graph.json`.
+use crate::banner::Colorize;
+use log::info;
+use serde_json::value::Value;
+use std::collections::HashSet;
+use std::fs;
+
+use crate::json::maker::csv::object_edges;
+use crate::json::maker::json_to_string;
+
+/// Function to build the graph of the objects.
+pub fn make_graph(domain: &String, objects: &[(&str, &Vec<Value>)]) -> Value {
+   let mut nodes: Vec<Value> = Vec::new();
+   let mut edges: Vec<Value> = Vec::new();
+   let mut known: HashSet<String> = HashSet::new();
+   let mut endpoints: Vec<(String, String)> = Vec::new();
+
+   for (object_type, vec_objects) in objects {
+      for object in vec_objects.iter() {
+         let id = match object["ObjectIdentifier"].as_str() {
+            Some(id) => id.to_string(),
+            None => continue,
+         };
+         nodes.push(serde_json::json!({
+            "id": id,
+            "type": object_type,
+            "label": object["Properties"]["name"].as_str().unwrap_or(&id),
+            "properties": object["Properties"],
+         }));
+         known.insert(id.to_uppercase());
+         for edge in object_edges(object, object_type) {
+            endpoints.push((edge.source.to_owned(), edge.source_type.to_owned()));
+            endpoints.push((edge.target.to_owned(), edge.target_type.to_owned()));
+            edges.push(serde_json::json!({
+               "source": edge.source,
+               "target": edge.target,
+               "type": edge.edge,
+               "properties": {"inherited": edge.inherited},
+            }));
+         }
+      }
+   }
+
+   // Nodes of the principals only known from an edge
+   for (id, node_type) in endpoints {
+      if known.insert(id.to_uppercase()) {
+         nodes.push(serde_json::json!({"id": id, "type": node_type, "label": id, "properties": {}}));
+      }
+   }
+
+   serde_json::json!({
+      "meta": {"domain": domain.to_uppercase(), "nodes": nodes.len(), "edges": edges.len()},
+      "nodes": nodes,
+      "edges": edges,
+   })
+}
+
+/// Function to write the graph JSON file.
+pub fn make_graph_result(
+   path: &String,
+   domain: &String,
+   domain_format: &String,
+   objects: &[(&str, &Vec<Value>)],
+   pretty: bool,
+) -> std::io::Result<()> {
+   let mut graph = make_graph(domain, objects);
+   info!("{} nodes and {} edges in the graph", graph["meta"]["nodes"].to_string().bold(), graph["meta"]["edges"].to_string().bold());
+   fs::create_dir_all(path)?;
+   let final_path = format!("{}/{}_graph.json", path, domain_format);
+   fs::write(&final_path, json_to_string(&mut graph, pretty))?;
+   info!("{} created!", final_path.bold());
+   Ok(())
+}
+
+/// Test functions
+#[test]
+pub fn test_make_graph() {
+   let users = vec![
+      serde_json::json!({"ObjectIdentifier": "S-1-5-21-1-2-3-1104", "Properties": {"name": "JON.SNOW@ESSOS.LOCAL", "enabled": true},
+         "Aces": [{"RightName": "GenericAll", "IsInherited": true, "PrincipalSID": "ESSOS.LOCAL-S-1-5-32-544", "PrincipalType": "Group"}]}),
+   ];
+   let groups = vec![
+      serde_json::json!({"ObjectIdentifier": "S-1-5-21-1-2-3-512", "Properties": {"name": "DOMAIN ADMINS@ESSOS.LOCAL"},
+         "Members": [{"ObjectIdentifier": "S-1-5-21-1-2-3-1104", "ObjectType": "User"}], "Aces": []}),
+   ];
+   let graph = make_graph(&"essos.local".to_string(), &[("User", &users), ("Group", &groups)]);
+   assert_eq!(graph["meta"], serde_json::json!({"domain": "ESSOS.LOCAL", "nodes": 3, "edges": 2}));
+   assert_eq!(graph["nodes"][0]["label"], "JON.SNOW@ESSOS.LOCAL");
+   assert_eq!(graph["nodes"][0]["properties"]["enabled"], true);
+   // Administrators only known from the ACE
+   assert_eq!(graph["nodes"][2], serde_json::json!({"id": "ESSOS.LOCAL-S-1-5-32-544", "type": "Group", "label": "ESSOS.LOCAL-S-1-5-32-544", "properties": {}}));
+   assert_eq!(graph["edges"][1], serde_json::json!({"source": "S-1-5-21-1-2-3-1104", "target": "S-1-5-21-1-2-3-512", "type": "MemberOf", "properties": {"inherited": false}}));
+}
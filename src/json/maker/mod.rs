@@ -1,6 +1,8 @@
 use std::collections::HashMap;
-use colored::Colorize;
+use crate::banner::Colorize;
 use log::{info,trace};
+use crate::json::checker::bh_41::is_pso_target;
+use crate::json::spill::{SpillStore, select_objects};
 
 extern crate zip;
 use std::fs::File;
@@ -9,54 +11,136 @@ use zip::result::ZipResult;
 use zip::write::{FileOptions, ZipWriter};
 
 pub mod bh_41;
+pub mod csv;
+pub mod graph;
+pub mod ndjson;
+pub mod opengraph;
+pub mod stream;
 
-/// This function will create json output and zip output
+/// This function will create json output and zip output, the CSV files with --output-format csv
+/// and the generic graph file with --output-format graph, streamed object by object with --low-memory.
+/// With --chunk-size the users, groups and computers are split in numbered files.
+/// The objects and their edges are sorted first, two runs on the same domain give the same files.
+/// The PSOs are written in the OpenGraph file `<domain>_psos.json`, see [`opengraph`].
+/// With in_memory (--upload) nothing is written on disk, the zip archive is returned.
+/// The users and computers spilled during the collection are merged in the files from the spill.
 pub fn make_result(
     zip: bool,
+    in_memory: bool,
+    pretty: bool,
+    low_memory: bool,
+    chunk_size: usize,
+    output_format: &String,
     path: &String,
     domain: &String,
-    vec_users: Vec<serde_json::value::Value>,
-    vec_groups: Vec<serde_json::value::Value>,
-    vec_computers: Vec<serde_json::value::Value>,
-    vec_ous: Vec<serde_json::value::Value>,
-    vec_domains: Vec<serde_json::value::Value>,
-    vec_gpos: Vec<serde_json::value::Value>,
-    vec_containers: Vec<serde_json::value::Value>,
-) -> std::io::Result<()>
+    mut vec_users: Vec<serde_json::value::Value>,
+    mut vec_groups: Vec<serde_json::value::Value>,
+    mut vec_computers: Vec<serde_json::value::Value>,
+    mut vec_ous: Vec<serde_json::value::Value>,
+    mut vec_domains: Vec<serde_json::value::Value>,
+    mut vec_gpos: Vec<serde_json::value::Value>,
+    mut vec_containers: Vec<serde_json::value::Value>,
+    vec_psos: &[serde_json::value::Value],
+    spill: Option<&SpillStore>,
+) -> std::io::Result<Option<Vec<u8>>>
 {
+   let zip = zip || in_memory;
    // Format domain name
    let domain_format = domain.replace(".", "-").to_lowercase();
 
+   // Deterministic order of the objects and edges
+   for objects in [&mut vec_users, &mut vec_groups, &mut vec_computers, &mut vec_ous, &mut vec_domains, &mut vec_gpos, &mut vec_containers] {
+      sort_objects(objects);
+   }
+
+   // CSV or graph files alongside the json files
+   if output_format != "json" && !in_memory {
+      let objects = crate::modules::collection(&vec_users, &vec_groups, &vec_computers, &vec_ous, &vec_domains, &vec_gpos, &vec_containers);
+      match output_format.as_str() {
+         "csv" => csv::make_csv_result(path, &domain_format, &objects)?,
+         "graph" => graph::make_graph_result(path, domain, &domain_format, &objects, pretty)?,
+         _ => {}
+      }
+   }
+
    // Hashmap for json files
    let mut json_result = HashMap::new();
 
-   // Add all in json files
-   bh_41::add_user(
-		&domain_format,
-      vec_users,
-      path,&mut json_result,
-      zip,
-   )?;
-   bh_41::add_group(
-		&domain_format,
-      vec_groups,
-      path,
-      &mut json_result,
-      zip,
-   )?;
-   bh_41::add_computer(
-		&domain_format,
-      vec_computers,
-      path,
-      &mut json_result,
-      zip,
-   )?;
+   // PSO nodes and their edges, resolved before the users and groups are moved in the files
+   // Only the users a PSO applies to are needed, some may be spilled
+   let pso_users = if vec_psos.is_empty() {
+      Vec::new()
+   } else {
+      select_objects(&vec_users, spill, "users", |user| is_pso_target(user, vec_psos))?
+   };
+   if let Some(mut graph) = opengraph::pso_graph(vec_psos, &pso_users, &vec_groups) {
+      opengraph::add_psos(&domain_format, &mut graph, path, &mut json_result, zip, pretty)?;
+   }
+
+   if low_memory && !in_memory {
+      let mut files: Vec<stream::StreamedObjects> = Vec::new();
+      for (data_type, objects, chunked) in [
+         ("users", vec_users, true),
+         ("groups", vec_groups, true),
+         ("computers", vec_computers, true),
+         ("ous", vec_ous, false),
+         ("domains", vec_domains, false),
+         ("gpos", vec_gpos, false),
+         ("containers", vec_containers, false),
+      ] {
+         let count = objects.len() + spill.map(|spill| spill.count(data_type)).unwrap_or(0);
+         let objects: Box<dyn Iterator<Item = std::io::Result<serde_json::value::Value>>> = match spill {
+            Some(spill) => Box::new(spill.merged(data_type, objects)?),
+            None => Box::new(objects.into_iter().map(Ok)),
+         };
+         files.push(stream::StreamedObjects { data_type, count, chunk_size: if chunked { chunk_size } else { 0 }, objects });
+      }
+      return stream::make_streamed_result(zip, path, &domain_format, files, json_result).map(|_| None)
+   }
+
+   // Add all in json files, numbered files for the big types with --chunk-size
+   if is_chunked(&vec_users, chunk_size) {
+      add_chunks(&domain_format, "users", vec_users, chunk_size, path, &mut json_result, zip, pretty)?;
+   } else {
+      bh_41::add_user(
+         &domain_format,
+         vec_users,
+         path,&mut json_result,
+         zip,
+         pretty,
+      )?;
+   }
+   if is_chunked(&vec_groups, chunk_size) {
+      add_chunks(&domain_format, "groups", vec_groups, chunk_size, path, &mut json_result, zip, pretty)?;
+   } else {
+      bh_41::add_group(
+         &domain_format,
+         vec_groups,
+         path,
+         &mut json_result,
+         zip,
+         pretty,
+      )?;
+   }
+   if is_chunked(&vec_computers, chunk_size) {
+      add_chunks(&domain_format, "computers", vec_computers, chunk_size, path, &mut json_result, zip, pretty)?;
+   } else {
+      bh_41::add_computer(
+         &domain_format,
+         vec_computers,
+         path,
+         &mut json_result,
+         zip,
+         pretty,
+      )?;
+   }
    bh_41::add_ou(
 		&domain_format,
       vec_ous,
       path,
       &mut json_result,
       zip,
+      pretty,
    )?;
    bh_41::add_domain(
 		&domain_format,
@@ -64,6 +148,7 @@ pub fn make_result(
       path,
       &mut json_result,
       zip,
+      pretty,
    )?;
    bh_41::add_gpo(
 		&domain_format,
@@ -71,6 +156,7 @@ pub fn make_result(
       path,
       &mut json_result,
       zip,
+      pretty,
    )?;
    bh_41::add_container(
 		&domain_format,
@@ -78,17 +164,116 @@ pub fn make_result(
       path,
       &mut json_result,
       zip,
+      pretty,
    )?;
-   // All in zip file
+   // All in zip file, kept in memory with --upload
+   if in_memory {
+      let mut archive = std::io::Cursor::new(Vec::new());
+      create_zip_archive(&mut archive, &json_result)?;
+      return Ok(Some(archive.into_inner()))
+   }
    if zip {
       make_a_zip(
          &domain_format,
          path,
          &json_result);
    }
+   Ok(None)
+}
+
+/// Function to know if the objects of one type are split in several files.
+fn is_chunked(objects: &[serde_json::value::Value], chunk_size: usize) -> bool
+{
+   chunk_size > 0 && objects.len() > chunk_size
+}
+
+/// Function to split the objects of one type in files of chunk_size objects: users_001, users_002...
+/// One file named like the type when chunk_size is 0 or not reached.
+pub fn chunk_objects(
+   data_type: &str,
+   objects: Vec<serde_json::value::Value>,
+   chunk_size: usize,
+) -> Vec<(String, Vec<serde_json::value::Value>)>
+{
+   if !is_chunked(&objects, chunk_size) {
+      return vec![(data_type.to_string(), objects)]
+   }
+   let mut chunks: Vec<(String, Vec<serde_json::value::Value>)> = Vec::new();
+   let mut objects = objects.into_iter();
+   loop {
+      let chunk: Vec<serde_json::value::Value> = objects.by_ref().take(chunk_size).collect();
+      if chunk.is_empty() {
+         return chunks
+      }
+      chunks.push((format!("{}_{:03}", data_type, chunks.len() + 1), chunk));
+   }
+}
+
+fn add_chunks(
+   domain_format: &String,
+   data_type: &str,
+   objects: Vec<serde_json::value::Value>,
+   chunk_size: usize,
+   path: &String,
+   json_result: &mut HashMap<String, String>,
+   zip: bool,
+   pretty: bool,
+) -> std::io::Result<()>
+{
+   for (stem, chunk) in chunk_objects(data_type, objects, chunk_size) {
+      bh_41::add_chunk(domain_format, data_type, &stem, chunk, path, json_result, zip, pretty)?;
+   }
    Ok(())
 }
 
+/// Function to serialize one JSON file, pretty (--pretty) or compact.
+///
+/// Numeric policy: BloodHound expects integers for the epochs, counts and flags. A float without
+/// fractional part (like 1.68e9 from a computation) is written as an integer, i64 and u64 values
+/// are never converted to floats so large values keep their precision.
+/// The value is normalized in place, a whole file is never copied to be written.
+pub fn json_to_string(value: &mut serde_json::value::Value, pretty: bool) -> String
+{
+   normalize_numbers(value);
+   if pretty {
+      serde_json::to_string_pretty(value).unwrap_or_default()
+   } else {
+      value.to_string()
+   }
+}
+
+/// Edges of the objects sorted by sort_objects(), the other arrays keep the order of the directory (gPLink order).
+pub const SORTED_EDGE_FIELDS: [&str; 7] = ["Aces", "Members", "ChildObjects", "AllowedToDelegate", "AllowedToAct", "HasSIDHistory", "SPNTargets"];
+
+/// Function to sort the objects by ObjectIdentifier and their edges, the order of the LDAP answers changes between runs.
+pub fn sort_objects(objects: &mut [serde_json::value::Value])
+{
+   objects.sort_by(|a, b| a["ObjectIdentifier"].as_str().unwrap_or("").cmp(b["ObjectIdentifier"].as_str().unwrap_or("")));
+   for object in objects.iter_mut() {
+      for field in SORTED_EDGE_FIELDS {
+         if let Some(edges) = object[field].as_array_mut() {
+            edges.sort_by_cached_key(|edge| edge.to_string());
+         }
+      }
+   }
+}
+
+/// Function to write the integral floats as integers, recursively.
+pub fn normalize_numbers(value: &mut serde_json::value::Value)
+{
+   match value {
+      serde_json::value::Value::Number(number) => {
+         if let Some(float) = number.as_f64().filter(|_| number.is_f64()) {
+            if float.fract() == 0.0 && float >= i64::MIN as f64 && float < i64::MAX as f64 {
+               *value = (float as i64).into();
+            }
+         }
+      }
+      serde_json::value::Value::Array(array) => array.iter_mut().for_each(normalize_numbers),
+      serde_json::value::Value::Object(object) => object.values_mut().for_each(normalize_numbers),
+      _ => {}
+   }
+}
 
 /// Function to compress the JSON files into a zip archive
 fn make_a_zip(
@@ -124,4 +309,44 @@ fn create_zip_archive<T: Seek + Write>(zip_filename: &mut T,json_result: &HashMa
 
    writer.finish()?;
    Ok(())
-}
\ No newline at end of file
+}
+
+/// Test functions
+#[test]
+pub fn test_json_to_string() {
+   let mut value = serde_json::json!({"whencreated": 1696000000.0, "score": 0.5, "pwdlastset": 133400000000000000i64, "usn": u64::MAX, "list": [2.0]});
+   let json = json_to_string(&mut value, false);
+   assert!(json.contains("\"whencreated\":1696000000,"));
+   assert!(json.contains("\"score\":0.5"));
+   assert!(json.contains("\"pwdlastset\":133400000000000000"));
+   assert!(json.contains(&format!("\"usn\":{}", u64::MAX)));
+   assert!(json.contains("\"list\":[2]"));
+   assert!(json_to_string(&mut value, true).contains("\n  \"whencreated\": 1696000000"));
+}
+
+#[test]
+pub fn test_chunk_objects() {
+   let users: Vec<serde_json::value::Value> = (0..5).map(|i| serde_json::json!({"ObjectIdentifier": format!("S-1-5-21-1-2-3-{}", 1100 + i)})).collect();
+   let chunks = chunk_objects("users", users.to_owned(), 2);
+   let stems: Vec<&str> = chunks.iter().map(|(stem, _)| stem.as_str()).collect();
+   assert_eq!(stems, vec!["users_001", "users_002", "users_003"]);
+   assert_eq!(chunks.iter().map(|(_, chunk)| chunk.len()).collect::<Vec<usize>>(), vec![2, 2, 1]);
+   assert_eq!(chunks[2].1[0]["ObjectIdentifier"], "S-1-5-21-1-2-3-1104");
+   assert_eq!(chunk_objects("users", users.to_owned(), 0)[0].0, "users");
+   assert_eq!(chunk_objects("users", users, 5).len(), 1);
+}
+
+#[test]
+pub fn test_sort_objects() {
+   let ace = |sid: &str, right: &str| serde_json::json!({"RightName": right, "IsInherited": false, "PrincipalSID": sid, "PrincipalType": "Group"});
+   let mut objects = vec![
+      serde_json::json!({"ObjectIdentifier": "S-1-5-21-1-2-3-1105", "Aces": [ace("S-1-5-21-1-2-3-512", "Owns"), ace("S-1-5-21-1-2-3-512", "GenericAll")],
+         "Links": [{"IsEnforced": false, "GUID": "B"}, {"IsEnforced": false, "GUID": "A"}]}),
+      serde_json::json!({"ObjectIdentifier": "S-1-5-21-1-2-3-1104", "Members": [{"ObjectIdentifier": "S-1-5-21-1-2-3-1106"}, {"ObjectIdentifier": "S-1-5-21-1-2-3-1100"}]}),
+   ];
+   sort_objects(&mut objects);
+   assert_eq!(objects[0]["ObjectIdentifier"], "S-1-5-21-1-2-3-1104");
+   assert_eq!(objects[0]["Members"][0]["ObjectIdentifier"], "S-1-5-21-1-2-3-1100");
+   assert_eq!(objects[1]["Aces"][0]["RightName"], "GenericAll");
+   assert_eq!(objects[1]["Links"][0]["GUID"], "B");
+}
@@ -0,0 +1,88 @@
+//! NDJSON pipe mode (--stdout-ndjson)
+//!
+//! Every object and every edge is written as one JSON line on stdout, for jq, custom pipelines or a SIEM forwarder,
+//! instead of the BloodHound JSON files. The logs, the progress bar and the banners stay on stderr.
+//!
+//! ```text
+//! {"record":"node","type":"User","id":"S-1-5-21-...-1104","object":{"ObjectIdentifier":"S-1-5-21-...-1104","Properties":{...},...}}
+//! {"record":"edge","type":"MemberOf","source":"S-1-5-21-...-1104","sourcetype":"User","target":"S-1-5-21-...-512","targettype":"Group","inherited":false}
+//! ```
+//!
+//! The lines are written in the writing phase, like the JSON files: the edges are only complete once every object
+//! is collected and checked (DNs and names replaced by SIDs, members, GPO links), so nothing is written during the
+//! LDAP collection. The edges of an object follow its node line, the lines are flushed after every object type. The tables of
+//! `--reports` and `--query` are printed on stderr in this mode.
+use crate::banner::Colorize;
+use log::info;
+use serde_json::value::Value;
+use std::io::{BufWriter, Write};
+
+use crate::json::maker::csv::object_edges;
+use crate::json::maker::{normalize_numbers, sort_objects};
+
+/// Function to write the node and edge lines of one object, return the number of edges.
+pub fn object_records<W: Write>(writer: &mut W, object_type: &str, object: &Value) -> std::io::Result<usize> {
+   let mut object = object.to_owned();
+   normalize_numbers(&mut object);
+   let node = serde_json::json!({
+      "record": "node",
+      "type": object_type,
+      "id": object["ObjectIdentifier"],
+      "object": object,
+   });
+   writeln!(writer, "{}", node)?;
+   let edges = object_edges(&node["object"], object_type);
+   for edge in edges.iter() {
+      let record = serde_json::json!({
+         "record": "edge",
+         "type": edge.edge,
+         "source": edge.source,
+         "sourcetype": edge.source_type,
+         "target": edge.target,
+         "targettype": edge.target_type,
+         "inherited": edge.inherited,
+      });
+      writeln!(writer, "{}", record)?;
+   }
+   Ok(edges.len())
+}
+
+/// Function to stream every object and edge as NDJSON, sorted like the JSON files.
+pub fn make_ndjson_result<W: Write>(writer: W, vec_objects: &mut [(&str, &mut Vec<Value>)]) -> std::io::Result<()> {
+   let mut writer = BufWriter::new(writer);
+   let mut nodes = 0;
+   let mut edges = 0;
+   for (object_type, objects) in vec_objects.iter_mut() {
+      sort_objects(objects);
+      for object in objects.iter() {
+         edges += object_records(&mut writer, object_type, object)?;
+         nodes += 1;
+      }
+      writer.flush()?;
+   }
+   info!("{} nodes and {} edges streamed on stdout", nodes.to_string().bold(), edges.to_string().bold());
+   Ok(())
+}
+
+/// Test functions
+#[test]
+pub fn test_ndjson_records() {
+   let mut vec_groups = vec![
+      serde_json::json!({"ObjectIdentifier": "S-1-5-21-1-2-3-512", "Properties": {"name": "DOMAIN ADMINS@ESSOS.LOCAL", "whencreated": 1696000000.0},
+         "Members": [{"ObjectIdentifier": "S-1-5-21-1-2-3-1104", "ObjectType": "User"}]}),
+   ];
+   let mut vec_users = vec![
+      serde_json::json!({"ObjectIdentifier": "S-1-5-21-1-2-3-1105", "Properties": {"name": "VISERYS@ESSOS.LOCAL"}}),
+      serde_json::json!({"ObjectIdentifier": "S-1-5-21-1-2-3-1104", "Properties": {"name": "DAENERYS@ESSOS.LOCAL"}}),
+   ];
+   let mut output: Vec<u8> = Vec::new();
+   make_ndjson_result(&mut output, &mut [("User", &mut vec_users), ("Group", &mut vec_groups)]).unwrap();
+   let lines: Vec<Value> = String::from_utf8(output).unwrap().lines().map(|line| serde_json::from_str(line).unwrap()).collect();
+   assert_eq!(lines.len(), 4);
+   assert_eq!(lines[0]["id"], "S-1-5-21-1-2-3-1104");
+   assert_eq!(lines[2]["object"]["Properties"]["whencreated"], 1696000000);
+   assert_eq!(lines[3]["record"], "edge");
+   assert_eq!(lines[3]["type"], "MemberOf");
+   assert_eq!(lines[3]["source"], "S-1-5-21-1-2-3-1104");
+   assert_eq!(lines[3]["targettype"], "Group");
+}
@@ -0,0 +1,109 @@
+//! Fine-grained password policies as BloodHound CE OpenGraph nodes
+//!
+//! BloodHound has no node type for the PSOs (msDS-PasswordSettings), they are written in the OpenGraph format of
+//! BloodHound CE in `<domain>_psos.json`, imported with the other files: one `PasswordSettings` node per PSO and one
+//! `PSOAppliesTo` edge to every user and group of its msDS-PSOAppliesTo.
+//!
+//! ```text
+//! {"graph": {"nodes": [{"id": "<objectGUID>", "kinds": ["PasswordSettings"], "properties": {"name": "WEAK", "minpwdlength": 4, ...}}],
+//!            "edges": [{"kind": "PSOAppliesTo", "start": {"value": "<objectGUID>", "match_by": "id"}, "end": {"value": "S-1-5-21-...-1200", "match_by": "id"}}]}}
+//! ```
+//!
+//! The effective policy of every user is also its `passwordpolicy` property, set by the checker.
+//! No file is written when the domain has no PSO or when the PSO container can not be read.
+use crate::banner::Colorize;
+use log::{debug, info};
+use serde_json::value::Value;
+use std::collections::HashMap;
+use std::fs;
+
+use crate::json::maker::json_to_string;
+
+/// OpenGraph kind of the PSO nodes.
+pub const PSO_KIND: &str = "PasswordSettings";
+/// OpenGraph kind of the edges from a PSO to the principals it applies to.
+pub const PSO_EDGE_KIND: &str = "PSOAppliesTo";
+
+/// Function to build the OpenGraph of the PSOs, None without PSO.
+/// The DNs of msDS-PSOAppliesTo are replaced by the SIDs of the collected users and groups.
+pub fn pso_graph(
+   vec_psos: &[Value],
+   vec_users: &[Value],
+   vec_groups: &[Value],
+) -> Option<Value> {
+   let dn_sid: HashMap<String, &str> = vec_users.iter().chain(vec_groups.iter())
+      .filter_map(|object| Some((object["Properties"]["distinguishedname"].as_str()?.to_uppercase(), object["ObjectIdentifier"].as_str()?)))
+      .collect();
+   let mut nodes: Vec<Value> = Vec::new();
+   let mut edges: Vec<Value> = Vec::new();
+   for pso in vec_psos {
+      let id = match pso["ObjectIdentifier"].as_str() {
+         Some(id) => id,
+         None => continue,
+      };
+      // OpenGraph properties are primitives or arrays, the unset policy values are left out
+      let properties: serde_json::Map<String, Value> = pso["Properties"].as_object().into_iter().flatten()
+         .filter(|(_, value)| !value.is_null())
+         .map(|(key, value)| (key.to_owned(), value.to_owned()))
+         .collect();
+      nodes.push(serde_json::json!({ "id": id, "kinds": [PSO_KIND], "properties": properties }));
+      for dn in pso["AppliesTo"].as_array().into_iter().flatten().filter_map(|dn| dn.as_str()) {
+         match dn_sid.get(&dn.to_uppercase()) {
+            Some(sid) => edges.push(serde_json::json!({
+               "kind": PSO_EDGE_KIND,
+               "start": { "value": id, "match_by": "id" },
+               "end": { "value": sid, "match_by": "id" },
+            })),
+            None => debug!("PSO {} applies to {} not collected", id, dn),
+         }
+      }
+   }
+   if nodes.is_empty() {
+      return None
+   }
+   Some(serde_json::json!({ "graph": { "nodes": nodes, "edges": edges } }))
+}
+
+/// Function to write the OpenGraph file of the PSOs, or add it in the zip.
+pub fn add_psos(
+   domain_format: &String,
+   graph: &mut Value,
+   path: &String,
+   json_result: &mut HashMap<String, String>,
+   zip: bool,
+   pretty: bool,
+) -> std::io::Result<()> {
+   info!("{} PSOs parsed!", graph["graph"]["nodes"].as_array().map(|nodes| nodes.len()).unwrap_or(0).to_string().bold());
+   if zip {
+      json_result.insert("psos.json".to_string(), json_to_string(graph, pretty));
+      return Ok(())
+   }
+   fs::create_dir_all(path)?;
+   let final_path = format!("{}/{}_psos.json", path, domain_format);
+   fs::write(&final_path, json_to_string(graph, pretty))?;
+   info!("{} created!", final_path.bold());
+   Ok(())
+}
+
+/// Test functions
+#[test]
+pub fn test_pso_graph() {
+   let vec_users = vec![serde_json::json!({"ObjectIdentifier": "S-1-5-21-1-2-3-1104", "Properties": {"distinguishedname": "CN=JON,CN=USERS,DC=ESSOS,DC=LOCAL"}})];
+   let vec_groups = vec![serde_json::json!({"ObjectIdentifier": "S-1-5-21-1-2-3-1200", "Properties": {"distinguishedname": "CN=STARKS,CN=USERS,DC=ESSOS,DC=LOCAL"}})];
+   let vec_psos = vec![
+      serde_json::json!({"ObjectIdentifier": "6C1B3A2F-9E4D-4F8A-B2C1-0D5E7F9A1B3C", "Properties": {"name": "WEAK", "precedence": 10, "minpwdlength": 4, "maxpwdage": null},
+         "AppliesTo": ["CN=STARKS,CN=USERS,DC=ESSOS,DC=LOCAL", "CN=JON,CN=USERS,DC=ESSOS,DC=LOCAL", "CN=GONE,CN=USERS,DC=ESSOS,DC=LOCAL"]}),
+      serde_json::json!({"ObjectIdentifier": null, "Properties": {"name": "NO GUID"}, "AppliesTo": []}),
+   ];
+   let graph = pso_graph(&vec_psos, &vec_users, &vec_groups).unwrap();
+   let nodes = graph["graph"]["nodes"].as_array().unwrap();
+   assert_eq!(nodes.len(), 1);
+   assert_eq!(nodes[0]["kinds"], serde_json::json!([PSO_KIND]));
+   assert_eq!(nodes[0]["properties"]["minpwdlength"], 4);
+   assert!(nodes[0]["properties"].get("maxpwdage").is_none());
+   let edges = graph["graph"]["edges"].as_array().unwrap();
+   assert_eq!(edges.len(), 2);
+   assert_eq!((edges[0]["kind"].as_str(), edges[0]["end"]["value"].as_str()), (Some(PSO_EDGE_KIND), Some("S-1-5-21-1-2-3-1200")));
+   assert_eq!(edges[1]["start"]["value"], "6C1B3A2F-9E4D-4F8A-B2C1-0D5E7F9A1B3C");
+   assert!(pso_graph(&[], &vec_users, &vec_groups).is_none());
+}
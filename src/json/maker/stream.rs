@@ -0,0 +1,126 @@
+//! Low memory writing of the JSON files (--low-memory)
+//!
+//! The default writer builds every JSON file as a string, and keeps all of them in memory for the zip.
+//! With `--low-memory` every object is serialized and dropped one by one in the file, and the files of the zip
+//! are spilled in `<dirpath>/.<domain>_<type>.json.part` then streamed in the archive. The users and computers
+//! spilled during the collection come from the merge of their sorted segments (see [`crate::json::spill`]),
+//! they are never all in memory. The files are compact, `--pretty` is ignored.
+use crate::banner::Colorize;
+use log::{debug, info};
+use serde_json::value::Value;
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{BufWriter, Result, Write};
+use zip::write::{FileOptions, ZipWriter};
+
+use crate::json::maker::normalize_numbers;
+use crate::json::checker::compat::meta_version;
+use crate::json::templates::bh_41::prepare_final_json_file_template;
+
+/// Objects of one type to write, already sorted.
+pub struct StreamedObjects<'a> {
+   pub data_type: &'a str,
+   pub count: usize,
+   /// Numbered files of chunk_size objects above chunk_size (--chunk-size), 0 for one file
+   pub chunk_size: usize,
+   pub objects: Box<dyn Iterator<Item = Result<Value>> + 'a>,
+}
+
+/// Function to write one BloodHound JSON file object by object, the objects are dropped once written.
+pub fn write_json_stream<W: Write>(writer: &mut W, data_type: &str, objects: impl Iterator<Item = Result<Value>>) -> Result<usize> {
+   let mut count = 0;
+   writer.write_all(b"{\"data\":[")?;
+   for object in objects {
+      let mut object = object?;
+      if count > 0 {
+         writer.write_all(b",")?;
+      }
+      normalize_numbers(&mut object);
+      serde_json::to_writer(&mut *writer, &object)?;
+      count += 1;
+   }
+   // The count is known once the objects are written, the meta comes last
+   let mut meta = prepare_final_json_file_template(meta_version(), data_type.to_owned())["meta"].to_owned();
+   meta["count"] = count.into();
+   writer.write_all(b"],\"meta\":")?;
+   serde_json::to_writer(&mut *writer, &meta)?;
+   writer.write_all(b"}")?;
+   Ok(count)
+}
+
+/// Function to write the JSON files or the zip of every type, one type at a time.
+/// The small files already serialized (the PSOs OpenGraph) are added in the zip as they are.
+pub fn make_streamed_result(
+   zip: bool,
+   path: &String,
+   domain_format: &String,
+   objects: Vec<StreamedObjects>,
+   serialized: HashMap<String, String>,
+) -> Result<()> {
+   fs::create_dir_all(path)?;
+   let mut spilled: Vec<(String, String)> = Vec::new();
+   for mut streamed in objects {
+      let data_type = streamed.data_type;
+      // (file name like users or users_002 with --chunk-size, objects in the file)
+      let files: Vec<(String, usize)> = if streamed.chunk_size > 0 && streamed.count > streamed.chunk_size {
+         (1..=streamed.count.div_ceil(streamed.chunk_size))
+            .map(|number| (format!("{}_{:03}", data_type, number), streamed.chunk_size))
+            .collect()
+      } else {
+         vec![(data_type.to_string(), usize::MAX)]
+      };
+      for (file_stem, limit) in files {
+         let final_path = if zip {
+            format!("{}/.{}_{}.json.part", path, domain_format, file_stem)
+         } else {
+            format!("{}/{}_{}.json", path, domain_format, file_stem)
+         };
+         let mut writer = BufWriter::new(File::create(&final_path)?);
+         let count = write_json_stream(&mut writer, data_type, streamed.objects.by_ref().take(limit))?;
+         writer.flush()?;
+         info!("{} {} parsed!", count.to_string().bold(), data_type);
+         if zip {
+            debug!("{} spilled in {}", data_type, &final_path);
+            spilled.push((format!("{}.json", file_stem), final_path));
+         } else {
+            info!("{} created!", final_path.bold());
+         }
+      }
+   }
+
+   if zip {
+      let final_path = format!("{}/{}_rusthound_result.zip", path, domain_format);
+      let mut writer = ZipWriter::new(File::create(&final_path)?);
+      for (name, part) in &spilled {
+         writer.start_file(name, FileOptions::default())?;
+         std::io::copy(&mut File::open(part)?, &mut writer)?;
+         fs::remove_file(part)?;
+      }
+      let mut names: Vec<&String> = serialized.keys().collect();
+      names.sort();
+      for name in names {
+         writer.start_file(name, FileOptions::default())?;
+         writer.write_all(serialized[name].as_bytes())?;
+      }
+      writer.finish()?;
+      info!("{} created!", final_path.bold());
+   }
+   Ok(())
+}
+
+/// Test functions
+#[test]
+pub fn test_write_json_stream() {
+   let users = vec![
+      serde_json::json!({"ObjectIdentifier": "S-1-5-21-1-2-3-1104", "Properties": {"whencreated": 1696000000.0}}),
+      serde_json::json!({"ObjectIdentifier": "S-1-5-21-1-2-3-1105", "Properties": {"whencreated": 1696000001}}),
+   ];
+   let mut expected = prepare_final_json_file_template(meta_version(), "users".to_owned());
+   expected["data"] = users.to_owned().into();
+   expected["meta"]["count"] = 2.into();
+
+   let mut buffer: Vec<u8> = Vec::new();
+   assert_eq!(write_json_stream(&mut buffer, "users", users.into_iter().map(Ok)).unwrap(), 2);
+   // Same file as the default writer
+   assert_eq!(String::from_utf8(buffer).unwrap(), crate::json::maker::json_to_string(&mut expected, false));
+}
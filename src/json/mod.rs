@@ -7,4 +7,5 @@ pub use templates::*;
 pub mod checker;
 pub mod maker;
 pub mod parser;
+pub mod spill;
 pub mod templates;
\ No newline at end of file
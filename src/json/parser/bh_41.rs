@@ -1,5 +1,5 @@
 
-use colored::Colorize;
+use crate::banner::Colorize;
 use ldap3::SearchEntry;
 use log::{info, debug, trace};
 use regex::Regex;
@@ -7,14 +7,21 @@ use serde_json::json;
 use std::collections::HashMap;
 
 use crate::enums::acl::{parse_ntsecuritydescriptor,parse_gmsa};
-use crate::enums::date::{convert_timestamp,string_to_epoch};
+use crate::enums::attributes::{add_extended_properties, extended_properties};
+use crate::enums::date::{add_iso_timestamps, filetime_to_epoch, generalized_time_to_epoch};
 use crate::enums::forestlevel::get_forest_level;
-use crate::enums::gplink::parse_gplink;
+use crate::enums::gplink::{parse_gplink, parse_gpoptions};
 use crate::enums::secdesc::LdapSid;
-use crate::enums::sid::{decode_guid, objectsid_to_vec8, sid_maker};
-use crate::enums::spntasks::check_spn;
-use crate::enums::uacflags::get_flag;
+use crate::enums::sid::{decode_guid, objectsid_to_vec8, primary_group_sid, sid_maker};
+use crate::enums::spntasks::spn_targets;
+use crate::enums::uacflags::{parse_flags, uac_properties};
 use crate::enums::trusts::get_trust_flag;
+use crate::enums::pwdpolicy::set_password_policy;
+use crate::enums::etypes::{decode_supported_etypes, set_supported_etypes};
+use crate::enums::certmapping::set_certificate_mappings;
+use crate::enums::replmeta::{repl_metadata, set_repl_metadata_properties};
+use crate::modules::adminsdholder::is_adminsdholder;
+use crate::modules::exchange::exchange_server_roles;
 
 use crate::json::templates::bh_41::*;
 //use crate::errors::{Error, Result};
@@ -30,6 +37,10 @@ function 7 : ForeignSecurityPrincipal
 function 8 : containers
 function 9 : trust domain
 function 10: unknown values
+function 11: password settings (PSO)
+function 12: FSMO role owners and Exchange organization
+function 13: deleted objects (--include-deleted)
+function 14: device registration service connection point (--hybrid)
 */
 
 /*****************************************
@@ -133,42 +144,12 @@ pub fn parse_user(
                 user_json["Properties"]["logonscript"] = logonscript.to_owned().into();
             }
             "userAccountControl" => {
-                let uac = &result_attrs["userAccountControl"][0].parse::<u32>().unwrap();
-                let uac_flags = get_flag(*uac);
-                //trace!("UAC : {:?}",uac_flags);
-                for flag in uac_flags {
-                    if flag.contains("AccountDisable") {
-                        let enabled = false;
-                        user_json["Properties"]["enabled"] = enabled.into();
-                    };
-                    //if flag.contains("Lockout") { let enabled = true; user_json["Properties"]["enabled"] = enabled.into(); };
-                    if flag.contains("PasswordNotRequired") {
-                        let password_not_reqd = true;
-                        user_json["Properties"]["passwordnotreqd"] = password_not_reqd.into();
-                    };
-                    if flag.contains("DontExpirePassword") {
-                        let pwd_never_expires = true;
-                        user_json["Properties"]["pwdneverexpires"] = pwd_never_expires.into();
-                    };
-                    if flag.contains("TrustedForDelegation") {
-                        let trusted_for_delegation = true;
-                        user_json["Properties"]["unconstraineddelegation"] =
-                            trusted_for_delegation.into();
-                    };
-                    if flag.contains("NotDelegated") {
-                        let not_delegated = true;
-                        user_json["Properties"]["unconstraineddelegation"] = not_delegated.into();
-                    };
-                    if flag.contains("DontReqPreauth") {
-                        let dont_req_preauth = true;
-                        user_json["Properties"]["dontreqpreauth"] = dont_req_preauth.into();
-                    };
-                    //if flag.contains("PasswordExpired") { let password_expired = true; user_json["Properties"]["pwdneverexpires"] = password_expired.into(); };
-                    if flag.contains("TrustedToAuthForDelegation") {
-                        let trusted_to_auth_for_delegation = true;
-                        user_json["Properties"]["trustedtoauth"] = trusted_to_auth_for_delegation.into();
-                    };
+                let uac = parse_flags(&result_attrs["userAccountControl"][0]);
+                for (property, value) in uac_properties(uac) {
+                    user_json["Properties"][property] = value.into();
                 }
+                // Name used by RustHound before the SharpHound names
+                user_json["Properties"]["reversibleencryption"] = user_json["Properties"]["encryptedtextpwdallowed"].to_owned();
             }
             "msDS-AllowedToDelegateTo"  => {
                 //trace!(" AllowToDelegateTo: {:?}",&value);
@@ -193,52 +174,48 @@ pub fn parse_user(
                 }
                 user_json["AllowedToDelegate"] = vec_members.to_owned().into();
             }
+            "msDS-SupportedEncryptionTypes" => {
+                set_supported_etypes(&mut user_json["Properties"], &result_attrs["msDS-SupportedEncryptionTypes"][0]);
+            }
+            "userPrincipalName" => {
+                user_json["Properties"]["userprincipalname"] = result_attrs["userPrincipalName"][0].to_owned().into();
+            }
+            "altSecurityIdentities" => {
+                set_certificate_mappings(&mut user_json["Properties"], value);
+            }
             "lastLogon" => {
-                let lastlogon = &result_attrs["lastLogon"][0].parse::<i64>().unwrap();
-                if lastlogon.is_positive() {
-                    let epoch = convert_timestamp(*lastlogon);
+                if let Some(epoch) = filetime_to_epoch(&value[0]) {
                     user_json["Properties"]["lastlogon"] = epoch.into();
                 }
             }
             "lastLogonTimestamp" => {
-                let lastlogontimestamp = &result_attrs["lastLogonTimestamp"][0]
-                    .parse::<i64>()
-                    .unwrap_or(0);
-                if lastlogontimestamp.is_positive() {
-                    let epoch = convert_timestamp(*lastlogontimestamp);
+                if let Some(epoch) = filetime_to_epoch(&value[0]) {
                     user_json["Properties"]["lastlogontimestamp"] = epoch.into();
                 }
             }
             "pwdLastSet" => {
-                let pwdlastset = &result_attrs["pwdLastSet"][0].parse::<i64>().unwrap();
-                if pwdlastset.is_positive() {
-                    let epoch = convert_timestamp(*pwdlastset);
+                if let Some(epoch) = filetime_to_epoch(&value[0]) {
                     user_json["Properties"]["pwdlastset"] = epoch.into();
                 }
             }
+            "uSNChanged" => {
+                // Most recent object of a duplicated SID, see resolve_duplicate_sids
+                if let Ok(usn) = value[0].parse::<i64>() {
+                    user_json["Properties"]["usnchanged"] = usn.into();
+                }
+            }
             "whenCreated" => {
-               let whencreated = &result_attrs["whenCreated"][0];
-               let epoch = string_to_epoch(&whencreated);
-               if epoch.is_positive() {
-                   user_json["Properties"]["whencreated"] = epoch.into();
-               }
-           }
+                if let Some(epoch) = generalized_time_to_epoch(&result_attrs["whenCreated"][0]).filter(|epoch| epoch.is_positive()) {
+                    user_json["Properties"]["whencreated"] = epoch.into();
+                }
+            }
             "servicePrincipalName" => {
                 let mut result: Vec<String> = Vec::new();
-                // SPNTargets values
-                let mut targets: Vec<serde_json::value::Value> = Vec::new();
-
-                let mut added: bool = false;
                 for value in &result_attrs["servicePrincipalName"] {
                     result.push(value.to_owned());
-
-                    // Checking the spn for service-account (mssql?)
-                    let target = check_spn(value).to_owned();
-                    if target.to_string().contains("Port") && !added {
-                        targets.push(target.to_owned());
-                        added = true;
-                    }
                 }
+                // SPNTargets values: SQLAdmin for the MSSQL instances, HasSPNConfigured for the hosts
+                let targets = spn_targets(&result);
                 user_json["Properties"]["serviceprincipalnames"] = result.to_owned().into();
                 let hasspn = true;
                 user_json["Properties"]["hasspn"] = hasspn.into();
@@ -247,16 +224,16 @@ pub fn parse_user(
             "primaryGroupID" => {
                 group_id = value[0].to_owned();
             }
-            "IsDeleted" => {
-                // OID to use: 1.2.840.113556.1.4.417
-                // https://ldapwiki.com/wiki/IsDeleted
-                let is_deleted = true;
-                //trace!("isDeleted: {:?}",&result_attrs["IsDeleted"][0]);
-                user_json["IsDeleted"] = is_deleted.to_owned().into();
+            "msDS-HostServiceAccountBL" => {
+                // Computers using this (group) managed service account, DNs replaced by their SID in the checker
+                user_json["Properties"]["hostcomputers"] = value.iter().map(|dn| dn.to_uppercase()).collect::<Vec<String>>().into();
             }
             _ => {}
         }
     }
+    if extended_properties() {
+        add_extended_properties(&mut user_json["Properties"], &result_attrs);
+    }
 
     // For all, bins attributs
     let mut sid: String = "".to_owned();
@@ -319,15 +296,10 @@ pub fn parse_user(
         }
     }
 
-    // primaryGroupID if group_id is set
-    #[allow(irrefutable_let_patterns)]
-    if let id = group_id {
-        let re = Regex::new(r"S-.*-").unwrap();
-        let part1 = re.find(&sid).unwrap();
-        let mut primary_group_id: String = "".to_owned();
-        primary_group_id.push_str(&part1.as_str());
-        primary_group_id.push_str(&id.as_str());
-        user_json["PrimaryGroupSID"] = primary_group_id.to_owned().into();
+    // primaryGroupID, never in the member attribute of the group: unset if not readable
+    match primary_group_sid(&sid, &group_id) {
+        Some(primary_group_sid) => user_json["PrimaryGroupSID"] = primary_group_sid.into(),
+        None => debug!("No primaryGroupID for {}", &sid),
     }
 
     // Push DN and SID in HashMap
@@ -344,6 +316,13 @@ pub fn parse_user(
         "User".to_string(),
     );
 
+    if repl_metadata() {
+        set_repl_metadata_properties(&mut user_json, &result_attrs);
+    }
+    set_deleted_properties(&mut user_json, &result_attrs);
+    if extended_properties() {
+        add_iso_timestamps(&mut user_json["Properties"], &result_attrs);
+    }
     return user_json;
 }
 
@@ -434,20 +413,23 @@ pub fn parse_group(
                     group_json["Properties"]["domainsid"] = domain_sid[0].to_owned().to_string().into();
                 }*/
             }
+            "uSNChanged" => {
+                // Most recent object of a duplicated SID, see resolve_duplicate_sids
+                if let Ok(usn) = result_attrs["uSNChanged"][0].parse::<i64>() {
+                    group_json["Properties"]["usnchanged"] = usn.into();
+                }
+            }
             "whenCreated" => {
-                let whencreated = &result_attrs["whenCreated"][0];
-                let epoch = string_to_epoch(&whencreated);
-                if epoch.is_positive() {
+                if let Some(epoch) = generalized_time_to_epoch(&result_attrs["whenCreated"][0]).filter(|epoch| epoch.is_positive()) {
                     group_json["Properties"]["whencreated"] = epoch.into();
                 }
             }
-            "IsDeleted" => {
-                let is_deleted = true;
-                group_json["IsDeleted"] = is_deleted.to_owned().into();
-            }
             _ => {}
         }
     }
+    if extended_properties() {
+        add_extended_properties(&mut group_json["Properties"], &result_attrs);
+    }
 
     // For all, bins attributs
     for (key, value) in &result_bin {
@@ -495,6 +477,13 @@ pub fn parse_group(
         "Group".to_string(),
     );
 
+    if repl_metadata() {
+        set_repl_metadata_properties(&mut group_json, &result_attrs);
+    }
+    set_deleted_properties(&mut group_json, &result_attrs);
+    if extended_properties() {
+        add_iso_timestamps(&mut group_json["Properties"], &result_attrs);
+    }
     return group_json;
 }
 
@@ -571,6 +560,16 @@ pub fn parse_computer(
                 let operatingsystem = &result_attrs["operatingSystem"][0];
                 computer_json["Properties"]["operatingsystem"] = operatingsystem.to_owned().into();
             }
+            "msDS-SupportedEncryptionTypes" => {
+                set_supported_etypes(&mut computer_json["Properties"], &result_attrs["msDS-SupportedEncryptionTypes"][0]);
+            }
+            "altSecurityIdentities" => {
+                set_certificate_mappings(&mut computer_json["Properties"], value);
+            }
+            "adminCount" => {
+                let isadmin = &result_attrs["adminCount"][0];
+                computer_json["Properties"]["admincount"] = (isadmin == "1").into();
+            }
             //"operatingSystemServicePack" => {
             //    //operatingsystem
             //    let mut operating_system_servicepack = "".to_owned();
@@ -589,25 +588,23 @@ pub fn parse_computer(
                 computer_json["Members"] = vec_localadmins.to_owned().into();
             }
             "lastLogonTimestamp" => {
-                let lastlogontimestamp = &result_attrs["lastLogonTimestamp"][0]
-                    .parse::<i64>()
-                    .unwrap();
-                if lastlogontimestamp.is_positive() {
-                    let epoch = convert_timestamp(*lastlogontimestamp);
+                if let Some(epoch) = filetime_to_epoch(&value[0]) {
                     computer_json["Properties"]["lastlogontimestamp"] = epoch.into();
                 }
             }
             "pwdLastSet" => {
-                let pwdlastset = &result_attrs["pwdLastSet"][0].parse::<i64>().unwrap();
-                if pwdlastset.is_positive() {
-                    let epoch = convert_timestamp(*pwdlastset);
+                if let Some(epoch) = filetime_to_epoch(&value[0]) {
                     computer_json["Properties"]["pwdlastset"] = epoch.into();
                 }
             }
+            "uSNChanged" => {
+                // Most recent object of a duplicated SID, see resolve_duplicate_sids
+                if let Ok(usn) = value[0].parse::<i64>() {
+                    computer_json["Properties"]["usnchanged"] = usn.into();
+                }
+            }
             "whenCreated" => {
-                let whencreated = &result_attrs["whenCreated"][0];
-                let epoch = string_to_epoch(&whencreated);
-                if epoch.is_positive() {
+                if let Some(epoch) = generalized_time_to_epoch(&result_attrs["whenCreated"][0]).filter(|epoch| epoch.is_positive()) {
                     computer_json["Properties"]["whencreated"] = epoch.into();
                 }
             }
@@ -620,38 +617,11 @@ pub fn parse_computer(
                 computer_json["Properties"]["serviceprincipalnames"] = result.to_owned().into();
             }
             "userAccountControl" => {
-                //userAccountControl
-                let uac = &result_attrs["userAccountControl"][0]
-                    .parse::<u32>()
-                    .unwrap();
-                let uac_flags = get_flag(*uac);
-                //trace!("UAC : {:?}",uac_flags);
-                for flag in uac_flags {
-                    if flag.contains("AccountDisable") {
-                        let enabled = false;
-                        computer_json["Properties"]["enabled"] = enabled.into();
-                    };
-                    //if flag.contains("Lockout") { let enabled = true; computer_json["Properties"]["enabled"] = enabled.into(); };
-
-                    // https://beta.hackndo.com/constrained-unconstrained-delegation/#constrained--unconstrained-delegation
-                    // https://beta.hackndo.com/unconstrained-delegation-attack/
-                    if flag.contains("TrustedForDelegation") {
-                        let trusted_for_delegation = true;
-                        computer_json["Properties"]["unconstraineddelegation"] =
-                            trusted_for_delegation.into();
-                    };
-                    if flag.contains("NotDelegated") {
-                        let not_delegated = true;
-                        computer_json["Properties"]["unconstraineddelegation"] =
-                            not_delegated.into();
-                    };
-                    //if flag.contains("PasswordExpired") { let password_expired = true; computer_json["Properties"]["pwdneverexpires"] = password_expired.into(); };
-                    if flag.contains("TrustedToAuthForDelegation") {
-                        let trusted_to_auth_for_delegation = true;
-                        computer_json["Properties"]["unconstraineddelegation"] =
-                            trusted_to_auth_for_delegation.into();
-                        computer_json["Properties"]["trustedtoauth"] = true.into();
-                    };
+                // https://beta.hackndo.com/constrained-unconstrained-delegation/#constrained--unconstrained-delegation
+                // https://beta.hackndo.com/unconstrained-delegation-attack/
+                let uac = parse_flags(&result_attrs["userAccountControl"][0]);
+                for (property, value) in uac_properties(uac) {
+                    computer_json["Properties"][property] = value.into();
                 }
             }
             "msDS-AllowedToDelegateTo"  => {
@@ -697,13 +667,38 @@ pub fn parse_computer(
                 // primaryGroupID
                 group_id = result_attrs["primaryGroupID"][0].to_owned();
             }
-            "IsDeleted" => {
-                let is_deleted = true;
-                computer_json["IsDeleted"] = is_deleted.to_owned().into();
+            // RODC, the DNs are replaced by their SID in the checker
+            "managedBy" => {
+                computer_json["Properties"]["managedby"] = value[0].to_uppercase().into();
+            }
+            "msDS-KrbTgtLink" => {
+                computer_json["Properties"]["rodckrbtgt"] = value[0].to_uppercase().into();
+            }
+            "msDS-RevealedUsers" => {
+                let mut revealed: Vec<String> = value.iter().map(|dn_binary| dn_binary_dn(dn_binary).to_uppercase()).collect();
+                // One value per secret attribute cached (unicodePwd, supplementalCredentials...)
+                revealed.sort();
+                revealed.dedup();
+                computer_json["Properties"]["rodcrevealedusers"] = revealed.into();
+            }
+            "msDS-RevealOnDemandGroup" => {
+                computer_json["Properties"]["rodcallowedtoreveal"] = value.iter().map(|dn| dn.to_uppercase()).collect::<Vec<String>>().into();
+            }
+            "msDS-NeverRevealGroup" => {
+                computer_json["Properties"]["rodcdeniedtoreveal"] = value.iter().map(|dn| dn.to_uppercase()).collect::<Vec<String>>().into();
+            }
+            "msDS-HostServiceAccount" => {
+                // (Group) managed service accounts used by this computer, DNs replaced by their SID in the checker
+                computer_json["Properties"]["hostedserviceaccounts"] = value.iter().map(|dn| dn.to_uppercase()).collect::<Vec<String>>().into();
             }
             _ => {}
         }
     }
+    // Read-only DC: Read-only Domain Controllers primary group or partial secrets account
+    computer_json["Properties"]["isrodc"] = (group_id == "521" || computer_json["Properties"]["partialsecretsaccount"].as_bool().unwrap_or(false)).into();
+    if extended_properties() {
+        add_extended_properties(&mut computer_json["Properties"], &result_attrs);
+    }
     // For all, bins attributs
     for (key, value) in &result_bin {
         match key.as_str() {
@@ -761,15 +756,10 @@ pub fn parse_computer(
             _ => {}
         }
     }
-    // primaryGroupID if group_id is set
-    #[allow(irrefutable_let_patterns)]
-    if let id = group_id {
-        let re = Regex::new(r"S-.*-").unwrap();
-        let part1 = re.find(&sid).unwrap();
-        let mut primary_group_id: String = "".to_owned();
-        primary_group_id.push_str(&part1.as_str());
-        primary_group_id.push_str(&id.as_str());
-        computer_json["PrimaryGroupSID"] = primary_group_id.to_owned().into();
+    // primaryGroupID, never in the member attribute of the group: unset if not readable, a DC is not in 515
+    match primary_group_sid(&sid, &group_id) {
+        Some(primary_group_sid) => computer_json["PrimaryGroupSID"] = primary_group_sid.into(),
+        None => debug!("No primaryGroupID for {}", &sid),
     }
 
     // Push DN and SID in HashMap
@@ -811,6 +801,13 @@ pub fn parse_computer(
         "".to_string(),
     );
 
+    if repl_metadata() {
+        set_repl_metadata_properties(&mut computer_json, &result_attrs);
+    }
+    set_deleted_properties(&mut computer_json, &result_attrs);
+    if extended_properties() {
+        add_iso_timestamps(&mut computer_json["Properties"], &result_attrs);
+    }
     return computer_json;
 }
 
@@ -867,18 +864,15 @@ pub fn parse_ou(
                 ou_json["Properties"]["description"] = description.to_owned().into();
             }
             "whenCreated" => {
-                let whencreated = &result_attrs["whenCreated"][0];
-                let epoch = string_to_epoch(&whencreated);
-                if epoch.is_positive() {
+                if let Some(epoch) = generalized_time_to_epoch(&result_attrs["whenCreated"][0]).filter(|epoch| epoch.is_positive()) {
                     ou_json["Properties"]["whencreated"] = epoch.into();
                 }
             }
             "gPLink" => {
                 ou_json["Links"] = parse_gplink(result_attrs["gPLink"][0].to_string()).into();
             }
-            "IsDeleted" => {
-                let is_deleted = true;
-                ou_json["IsDeleted"] = is_deleted.to_owned().into();
+            "gPOptions" => {
+                ou_json["Properties"]["blocksinheritance"] = parse_gpoptions(&result_attrs["gPOptions"][0]).into();
             }
             _ => {}
         }
@@ -926,6 +920,13 @@ pub fn parse_ou(
         "OU".to_string(),
     );
 
+    if repl_metadata() {
+        set_repl_metadata_properties(&mut ou_json, &result_attrs);
+    }
+    set_deleted_properties(&mut ou_json, &result_attrs);
+    if extended_properties() {
+        add_iso_timestamps(&mut ou_json["Properties"], &result_attrs);
+    }
     return ou_json;
 }
 /*****************************************
@@ -990,10 +991,12 @@ pub fn parse_domain(
                 let level = get_forest_level(result_attrs["msDS-Behavior-Version"][0].to_string());
                 domain_json["Properties"]["functionallevel"] = level.into();
             }
+            // The domain head holds the PDC emulator role
+            "fSMORoleOwner" => {
+                domain_json["Properties"]["pdcemulator"] = fsmo_role_server(&value[0]).into();
+            }
             "whenCreated" => {
-                let whencreated = &result_attrs["whenCreated"][0];
-                let epoch = string_to_epoch(&whencreated);
-                if epoch.is_positive() {
+                if let Some(epoch) = generalized_time_to_epoch(&result_attrs["whenCreated"][0]).filter(|epoch| epoch.is_positive()) {
                     domain_json["Properties"]["whencreated"] = epoch.into();
                 }
             }
@@ -1010,17 +1013,17 @@ pub fn parse_domain(
             // The number of computer accounts that a user is allowed to create in a domain.
             "ms-DS-MachineAccountQuota" => {
                 let machine_account_quota = result_attrs["ms-DS-MachineAccountQuota"][0].parse::<i32>().unwrap_or(0);
+                domain_json["Properties"]["machineaccountquota"] = machine_account_quota.into();
                 if machine_account_quota > 0 {
                     info!("MachineAccountQuota: {}",machine_account_quota.to_string().yellow().bold());
                 }
             }
-            "IsDeleted" => {
-                let is_deleted = true;
-                domain_json["IsDeleted"] = is_deleted.to_owned().into();
-            }
             _ => {}
         }
     }
+    // Default password policy of the domain
+    set_password_policy(&mut domain_json["Properties"], &result_attrs, false);
+
     // For all, bins attributs
     for (key, value) in &result_bin {
         match key.as_str() {
@@ -1073,6 +1076,13 @@ pub fn parse_domain(
         "Domain".to_string(),
     );
 
+    if repl_metadata() {
+        set_repl_metadata_properties(&mut domain_json, &result_attrs);
+    }
+    set_deleted_properties(&mut domain_json, &result_attrs);
+    if extended_properties() {
+        add_iso_timestamps(&mut domain_json["Properties"], &result_attrs);
+    }
     return domain_json;
 }
 /*****************************************
@@ -1128,9 +1138,7 @@ pub fn parse_gpo(
                 gpo_json["Properties"]["description"] = description.to_owned().into();
             }
             "whenCreated" => {
-                let whencreated = &result_attrs["whenCreated"][0];
-                let epoch = string_to_epoch(&whencreated);
-                if epoch.is_positive() {
+                if let Some(epoch) = generalized_time_to_epoch(&result_attrs["whenCreated"][0]).filter(|epoch| epoch.is_positive()) {
                     gpo_json["Properties"]["whencreated"] = epoch.into();
                 }
             }
@@ -1138,10 +1146,6 @@ pub fn parse_gpo(
                 let gpcpath = &result_attrs["gPCFileSysPath"][0];
                 gpo_json["Properties"]["gpcpath"] = gpcpath.to_owned().into();
             }
-            "IsDeleted" => {
-                let is_deleted = true;
-                gpo_json["IsDeleted"] = is_deleted.to_owned().into();
-            }
             _ => {}
         }
     }
@@ -1186,6 +1190,13 @@ pub fn parse_gpo(
         "Gpo".to_string(),
     );
 
+    if repl_metadata() {
+        set_repl_metadata_properties(&mut gpo_json, &result_attrs);
+    }
+    set_deleted_properties(&mut gpo_json, &result_attrs);
+    if extended_properties() {
+        add_iso_timestamps(&mut gpo_json["Properties"], &result_attrs);
+    }
     return gpo_json;
 }
 /*****************************************
@@ -1249,9 +1260,7 @@ pub fn parse_fsp(
                 }
             }
             "whenCreated" => {
-                let whencreated = &result_attrs["whenCreated"][0];
-                let epoch = string_to_epoch(&whencreated);
-                if epoch.is_positive() {
+                if let Some(epoch) = generalized_time_to_epoch(&result_attrs["whenCreated"][0]).filter(|epoch| epoch.is_positive()) {
                     fsp_json["Properties"]["whencreated"] = epoch.into();
                 }
             }
@@ -1267,10 +1276,6 @@ pub fn parse_fsp(
                     fsp_json["Properties"]["domainsid"] = domain_sid[0].to_owned().to_string().into();
                 }
             }
-            "IsDeleted" => {
-                let is_deleted = true;
-                fsp_json["IsDeleted"] = is_deleted.to_owned().into();
-            }
             _ => {}
         }
     }
@@ -1289,6 +1294,13 @@ pub fn parse_fsp(
         fsp_json["Properties"]["type"].as_str().unwrap().to_string(),
     );
 
+    if repl_metadata() {
+        set_repl_metadata_properties(&mut fsp_json, &result_attrs);
+    }
+    set_deleted_properties(&mut fsp_json, &result_attrs);
+    if extended_properties() {
+        add_iso_timestamps(&mut fsp_json["Properties"], &result_attrs);
+    }
     return fsp_json;
 }
 
@@ -1329,6 +1341,11 @@ pub fn parse_container(
     let mut container_json = prepare_container_json_template();
 
     container_json["Properties"]["domain"] = domain.to_owned().to_uppercase().into();
+    // AdminSDHolder ACL is copied on every protected object (adminCount=1) by SDProp
+    if is_adminsdholder(&result_dn) {
+        container_json["Properties"]["adminsdholder"] = true.into();
+        container_json["Properties"]["highvalue"] = true.into();
+    }
     container_json["Properties"]["distinguishedname"] = result_dn.into();
     // With a check
     for (key, _value) in &result_attrs {
@@ -1364,10 +1381,6 @@ pub fn parse_container(
                 );
                 container_json["Aces"] = relations_ace.into();
             }
-            "IsDeleted" => {
-                let is_deleted = true;
-                container_json["IsDeleted"] = is_deleted.to_owned().into();
-            }
             _ => {}
         }
     }
@@ -1386,6 +1399,13 @@ pub fn parse_container(
         "Container".to_string(),
     );
 
+    if repl_metadata() {
+        set_repl_metadata_properties(&mut container_json, &result_attrs);
+    }
+    set_deleted_properties(&mut container_json, &result_attrs);
+    if extended_properties() {
+        add_iso_timestamps(&mut container_json["Properties"], &result_attrs);
+    }
     return container_json;
 }
 
@@ -1436,9 +1456,13 @@ pub fn parse_trust(result: SearchEntry, domain: &String) -> serde_json::value::V
                 }
             }
             "trustAttributes" => {
-                let trustflag: u32 = result_attrs["trustAttributes"][0].parse::<u32>().unwrap_or(0);
+                let trustflag: u32 = parse_flags(&result_attrs["trustAttributes"][0]);
                 get_trust_flag(trustflag, &mut trust_json);
             }
+            "msDS-SupportedEncryptionTypes" => {
+                let etypes = result_attrs["msDS-SupportedEncryptionTypes"][0].parse::<i64>().unwrap_or(0) as u32;
+                trust_json["SupportedEncryptionTypes"] = decode_supported_etypes(etypes).into();
+            }
             _ => {}
         }
     }
@@ -1487,4 +1511,311 @@ pub fn parse_unknown(result: SearchEntry, _domain: &String) -> serde_json::value
     //}
 
     return unknown_json
-}
\ No newline at end of file
+}
+/*****************************************
+******************************************
+11- Function to parse password settings (PSO)
+******************************************
+*****************************************/
+/// Function to parse and replace value in json template for one fine-grained password policy (msDS-PasswordSettings).
+pub fn parse_pso(result: SearchEntry, _domain: &String) -> serde_json::value::Value  {
+
+    let result_dn: String;
+    result_dn = result.dn.to_uppercase();
+
+    let result_attrs: HashMap<String, Vec<String>>;
+    result_attrs = result.attrs;
+    let result_bin: HashMap<String, Vec<Vec<u8>>>;
+    result_bin = result.bin_attrs;
+
+    let mut pso_json = prepare_pso_json_template();
+
+    // Debug for current object
+    debug!("Parse PasswordSettings: {}", result_dn);
+
+    pso_json["Properties"]["distinguishedname"] = result_dn.into();
+    for (key, value) in &result_attrs {
+        match key.as_str() {
+            "name" => {
+                pso_json["Properties"]["name"] = value[0].to_uppercase().into();
+            }
+            "msDS-PasswordSettingsPrecedence" => {
+                pso_json["Properties"]["precedence"] = value[0].parse::<i64>().unwrap_or(i64::MAX).into();
+            }
+            "msDS-PSOAppliesTo" => {
+                let applies_to: Vec<String> = value.iter().map(|dn| dn.to_uppercase()).collect();
+                pso_json["AppliesTo"] = applies_to.into();
+            }
+            _ => {}
+        }
+    }
+    if let Some(guid) = result_bin.get("objectGUID").and_then(|value| value.first()) {
+        pso_json["ObjectIdentifier"] = decode_guid(guid).into();
+    }
+    set_password_policy(&mut pso_json["Properties"], &result_attrs, true);
+
+    return pso_json
+}
+
+/*****************************************
+******************************************
+12- Function to parse FSMO role owners
+******************************************
+*****************************************/
+/// Function to parse one FSMO role object: the schema NC head (schema master), CN=Partitions (domain naming master
+/// and forest functional level), CN=RID Manager$ (RID master) or CN=Infrastructure (infrastructure master).
+/// Return the domain properties to set, the owners are the server names resolved by set_fsmo_roles.
+pub fn parse_fsmo_role(result: SearchEntry, _domain: &String) -> serde_json::value::Value  {
+
+    let result_dn = result.dn.to_uppercase();
+    let result_attrs: HashMap<String, Vec<String>> = result.attrs;
+
+    // Debug for current object
+    debug!("Parse FSMO role: {}", result_dn);
+
+    let classes = result_attrs.get("objectClass").cloned().unwrap_or_default();
+    let role = if classes.contains(&"dMD".to_string()) {
+        "schemamaster"
+    } else if classes.contains(&"crossRefContainer".to_string()) {
+        "domainnamingmaster"
+    } else if classes.contains(&"rIDManager".to_string()) {
+        "ridmaster"
+    } else {
+        "infrastructuremaster"
+    };
+    let mut role_json = json!({});
+    if let Some(owner) = result_attrs.get("fSMORoleOwner") {
+        role_json[role] = fsmo_role_server(&owner[0]).into();
+    }
+    if let Some(level) = result_attrs.get("msDS-Behavior-Version").filter(|_| role == "domainnamingmaster") {
+        role_json["forestfunctionallevel"] = get_forest_level(level[0].to_string()).into();
+    }
+    return role_json
+}
+
+/// Function to get the server name of a fSMORoleOwner value:
+/// CN=NTDS Settings,CN=DC01,CN=Servers,CN=Default-First-Site-Name,CN=Sites,CN=Configuration,DC=ESSOS,DC=LOCAL => DC01
+pub fn fsmo_role_server(owner: &String) -> String {
+    owner.split(',')
+        .nth(1)
+        .and_then(|rdn| rdn.strip_prefix("CN="))
+        .unwrap_or(owner)
+        .to_uppercase()
+}
+
+/// Function to parse one Exchange organization (msExchOrganizationContainer) of the configuration NC.
+/// Return the domain properties to set.
+pub fn parse_exchange_organization(result: SearchEntry, _domain: &String) -> serde_json::value::Value  {
+    let name = result.attrs.get("name").map(|name| name[0].to_owned()).unwrap_or_default();
+    info!("Exchange organization {} found", name.bold());
+    json!({"exchangeorganization": name})
+}
+
+/// Function to parse one Exchange server (msExchExchangeServer) of the configuration NC.
+pub fn parse_exchange_server(result: SearchEntry, _domain: &String) -> serde_json::value::Value  {
+    let attribute = |name: &str| result.attrs.get(name).and_then(|values| values.first()).map(|value| value.to_owned());
+    // ncacn_ip_tcp:ex01.essos.local
+    let fqdn = result.attrs.get("networkAddress").into_iter().flatten()
+        .find_map(|address| address.strip_prefix("ncacn_ip_tcp:"))
+        .map(|fqdn| fqdn.to_uppercase());
+    let roles = attribute("msExchCurrentServerRoles").map(|roles| parse_flags(&roles)).unwrap_or(0);
+    json!({
+        "name": attribute("name").unwrap_or_default().to_uppercase(),
+        "fqdn": fqdn,
+        "roles": exchange_server_roles(roles),
+        "version": attribute("serialNumber"),
+        "distinguishedname": result.dn.to_uppercase(),
+    })
+}
+
+/// Function to parse one SCCM object: site or management point of CN=System Management, PXE service point of a computer.
+pub fn parse_sccm_object(result: SearchEntry, _domain: &String) -> serde_json::value::Value  {
+    let attribute = |name: &str| result.attrs.get(name).and_then(|values| values.first()).map(|value| value.to_owned());
+    let classes = result.attrs.get("objectClass").cloned().unwrap_or_default();
+    let dn = result.dn.to_uppercase();
+    let (object_type, host) = if classes.iter().any(|class| class == "mSSMSManagementPoint") {
+        ("ManagementPoint", attribute("mSSMSMPName").or_else(|| attribute("dNSHostName")))
+    } else if classes.iter().any(|class| class == "mSSMSSite") {
+        ("Site", None)
+    } else {
+        // CN=<computer>-Remote-Installation-Services,<computer DN>
+        ("PxeServer", dn.split_once(',').map(|(_, parent)| parent.to_string()))
+    };
+    json!({
+        "type": object_type,
+        "distinguishedname": dn,
+        "sitecode": attribute("mSSMSSiteCode"),
+        "host": host.map(|host| host.to_uppercase()),
+    })
+}
+
+/// Function to parse the device registration service connection point: tenant of the hybrid joined devices.
+pub fn parse_device_registration(result: SearchEntry, _domain: &String) -> serde_json::value::Value  {
+    // keywords: azureADName:essos.onmicrosoft.com, azureADId:<tenant GUID>
+    let keyword = |name: &str| result.attrs.get("keywords").into_iter().flatten()
+        .find_map(|keyword| keyword.split_once(':').filter(|(key, _)| key.eq_ignore_ascii_case(name)).map(|(_, value)| value.to_owned()));
+    json!({
+        "distinguishedname": result.dn.to_uppercase(),
+        "tenantname": keyword("azureADName").map(|name| name.to_lowercase()),
+        "tenantid": keyword("azureADId"),
+    })
+}
+
+/// Function to get the DN of a DN-Binary value "B:<hex length>:<hex>:<DN>" (msDS-RevealedUsers).
+pub fn dn_binary_dn(value: &str) -> &str {
+    value.strip_prefix("B:")
+        .and_then(|rest| rest.splitn(3, ':').nth(2))
+        .unwrap_or(value)
+}
+
+/// Function to mark a tombstoned or recycled object returned with --include-deleted: isdeleted, isrecycled,
+/// lastknownparent, whendeleted (its last change) and the name without the "\nDEL:<guid>" suffix of the DC.
+pub fn set_deleted_properties(
+    object: &mut serde_json::value::Value,
+    result_attrs: &HashMap<String, Vec<String>>,
+) {
+    let attribute = |name: &str| result_attrs.get(name).and_then(|values| values.first());
+    if !attribute("isDeleted").map_or(false, |value| value.eq_ignore_ascii_case("TRUE")) {
+        return
+    }
+    object["IsDeleted"] = true.into();
+    object["Properties"]["isdeleted"] = true.into();
+    object["Properties"]["isrecycled"] = attribute("isRecycled").map_or(false, |value| value.eq_ignore_ascii_case("TRUE")).into();
+    if let Some(parent) = attribute("lastKnownParent") {
+        object["Properties"]["lastknownparent"] = parent.to_uppercase().into();
+    }
+    if let Some(whenchanged) = attribute("whenChanged") {
+        object["Properties"]["whendeleted"] = generalized_time_to_epoch(whenchanged).into();
+    }
+    if let Some(name) = object["Properties"]["name"].as_str().filter(|name| name.contains("\nDEL:")) {
+        // "JON SNOW\nDEL:<guid>@ESSOS.LOCAL"
+        let (rdn, rest) = name.split_once("\nDEL:").unwrap_or((name, ""));
+        let suffix = rest.find('@').map(|at| &rest[at..]).unwrap_or("");
+        object["Properties"]["name"] = format!("{}{}", rdn, suffix).into();
+    }
+}
+
+/// Function to set the FSMO roles, the forest functional level and the Exchange organization on the domain,
+/// with the FQDN of the collected DC holding a role, else its server name (DC of another domain of the forest).
+pub fn set_fsmo_roles(
+    vec_domains: &mut Vec<serde_json::value::Value>,
+    vec_roles: &Vec<serde_json::value::Value>,
+    vec_computers: &Vec<serde_json::value::Value>,
+) {
+    let fqdn = |server: &str| -> String {
+        let account = format!("{}$", server);
+        vec_computers.iter()
+            .find(|computer| computer["Properties"]["samaccountname"].as_str().map_or(false, |name| name.eq_ignore_ascii_case(&account)))
+            .and_then(|computer| computer["Properties"]["name"].as_str())
+            .unwrap_or(server)
+            .to_uppercase()
+    };
+    for domain in vec_domains.iter_mut() {
+        for role in vec_roles {
+            for (key, value) in role.as_object().into_iter().flatten() {
+                domain["Properties"][key] = value.to_owned();
+            }
+        }
+        for key in ["pdcemulator", "ridmaster", "infrastructuremaster", "schemamaster", "domainnamingmaster"] {
+            if let Some(server) = domain["Properties"][key].as_str().map(|server| server.to_string()) {
+                domain["Properties"][key] = fqdn(&server).into();
+            }
+        }
+    }
+}
+
+/// Test functions
+#[test]
+pub fn test_fsmo_roles() {
+    let entry = |dn: &str, class: &str, owner: &str| SearchEntry {
+        dn: dn.to_string(),
+        attrs: vec![
+            ("objectClass".to_string(), vec!["top".to_string(), class.to_string()]),
+            ("fSMORoleOwner".to_string(), vec![owner.to_string()]),
+            ("msDS-Behavior-Version".to_string(), vec!["7".to_string()]),
+        ].into_iter().collect(),
+        bin_attrs: HashMap::new(),
+    };
+    let ntds = |server: &str| format!("CN=NTDS Settings,CN={},CN=Servers,CN=Default-First-Site-Name,CN=Sites,CN=Configuration,DC=SEVENKINGDOMS,DC=LOCAL", server);
+    let domain = "ESSOS.LOCAL".to_string();
+    let roles = vec![
+        parse_fsmo_role(entry("CN=RID Manager$,CN=System,DC=ESSOS,DC=LOCAL", "rIDManager", &ntds("Meereen")), &domain),
+        parse_fsmo_role(entry("CN=Partitions,CN=Configuration,DC=SEVENKINGDOMS,DC=LOCAL", "crossRefContainer", &ntds("Kingslanding")), &domain),
+    ];
+    assert_eq!(roles[0], json!({"ridmaster": "MEEREEN"}));
+    assert_eq!(roles[1], json!({"domainnamingmaster": "KINGSLANDING", "forestfunctionallevel": "2016"}));
+
+    let mut domains = vec![prepare_domain_json_template()];
+    domains[0]["Properties"]["pdcemulator"] = "MEEREEN".into();
+    let computers = vec![json!({"Properties": {"name": "MEEREEN.ESSOS.LOCAL", "samaccountname": "meereen$"}})];
+    set_fsmo_roles(&mut domains, &roles, &computers);
+    assert_eq!(domains[0]["Properties"]["pdcemulator"], "MEEREEN.ESSOS.LOCAL");
+    assert_eq!(domains[0]["Properties"]["ridmaster"], "MEEREEN.ESSOS.LOCAL");
+    // DC of the forest root domain, not collected
+    assert_eq!(domains[0]["Properties"]["domainnamingmaster"], "KINGSLANDING");
+    assert_eq!(domains[0]["Properties"]["forestfunctionallevel"], "2016");
+    assert_eq!(domains[0]["Properties"]["schemamaster"], serde_json::Value::Null);
+}
+
+#[test]
+pub fn test_deleted_properties() {
+    let attrs: HashMap<String, Vec<String>> = vec![
+        ("isDeleted".to_string(), vec!["TRUE".to_string()]),
+        ("isRecycled".to_string(), vec!["FALSE".to_string()]),
+        ("lastKnownParent".to_string(), vec!["OU=Admins,DC=essos,DC=local".to_string()]),
+        ("whenChanged".to_string(), vec!["20231001120000.0Z".to_string()]),
+    ].into_iter().collect();
+    let mut group_json = prepare_group_json_template();
+    group_json["Properties"]["name"] = "DRAGONRIDERS\nDEL:5b2c8e1a-6f3d-4c1e-9a7b-0d2e4f6a8c10@ESSOS.LOCAL".into();
+    set_deleted_properties(&mut group_json, &attrs);
+    assert_eq!(group_json["IsDeleted"], true);
+    assert_eq!(group_json["Properties"]["name"], "DRAGONRIDERS@ESSOS.LOCAL");
+    assert_eq!(group_json["Properties"]["isdeleted"], true);
+    assert_eq!(group_json["Properties"]["isrecycled"], false);
+    assert_eq!(group_json["Properties"]["lastknownparent"], "OU=ADMINS,DC=ESSOS,DC=LOCAL");
+    assert_eq!(group_json["Properties"]["whendeleted"], 1696161600);
+
+    // Live object: untouched
+    let mut user_json = prepare_user_json_template();
+    set_deleted_properties(&mut user_json, &HashMap::new());
+    assert_eq!(user_json["IsDeleted"], false);
+    assert!(user_json["Properties"].get("isdeleted").is_none());
+}
+
+#[test]
+pub fn test_primary_group() {
+    // S-1-5-21-1-2-3-1110: revision, sub-authority count, identifier authority, sub-authorities
+    let mut object_sid: Vec<u8> = vec![1, 5, 0, 0, 0, 0, 0, 5];
+    for sub_authority in [21u32, 1, 2, 3, 1110] {
+        object_sid.extend_from_slice(&sub_authority.to_le_bytes());
+    }
+    let user = |primary_group_id: Option<&str>| {
+        let mut attrs: HashMap<String, Vec<String>> = HashMap::from([
+            ("sAMAccountName".to_string(), vec!["khal.drogo".to_string()]),
+        ]);
+        if let Some(rid) = primary_group_id {
+            attrs.insert("primaryGroupID".to_string(), vec![rid.to_string()]);
+        }
+        SearchEntry {
+            dn: "CN=Khal Drogo,CN=Users,DC=essos,DC=local".to_string(),
+            attrs,
+            bin_attrs: HashMap::from([("objectSid".to_string(), vec![object_sid.to_owned()])]),
+        }
+    };
+    let domain = "ESSOS.LOCAL".to_string();
+    let (mut dn_sid, mut sid_type) = (HashMap::new(), HashMap::new());
+    let user_json = parse_user(user(Some("512")), &domain, &mut dn_sid, &mut sid_type);
+    assert_eq!(user_json["PrimaryGroupSID"], "S-1-5-21-1-2-3-512");
+    // Not readable (ACL-only run, restricted ACL): no invented Domain Users membership
+    let user_json = parse_user(user(None), &domain, &mut dn_sid, &mut sid_type);
+    assert_eq!(user_json["PrimaryGroupSID"], serde_json::Value::Null);
+
+    let mut computer = user(None);
+    computer.dn = "CN=MEEREEN,OU=Domain Controllers,DC=essos,DC=local".to_string();
+    computer.attrs.insert("sAMAccountName".to_string(), vec!["MEEREEN$".to_string()]);
+    let computer_json = parse_computer(computer.clone(), &domain, &mut dn_sid, &mut sid_type, &mut HashMap::new(), &mut HashMap::new());
+    assert_eq!(computer_json["PrimaryGroupSID"], serde_json::Value::Null);
+    computer.attrs.insert("primaryGroupID".to_string(), vec!["516".to_string()]);
+    let computer_json = parse_computer(computer, &domain, &mut dn_sid, &mut sid_type, &mut HashMap::new(), &mut HashMap::new());
+    assert_eq!(computer_json["PrimaryGroupSID"], "S-1-5-21-1-2-3-516");
+}
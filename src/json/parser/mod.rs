@@ -1,15 +1,131 @@
 use std::collections::HashMap;
 use ldap3::SearchEntry;
 use regex::Regex;
-use indicatif::ProgressBar;
+use crate::banner::ProgressBar;
 use crate::banner::progress_bar;
-use std::convert::TryInto;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use tokio::sync::mpsc::{channel, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
 
 use crate::enums::ldaptype::*;
-use log::{info};
+use crate::enums::decoding::normalize_entry;
+use crate::modules::skipped::isolate;
+use crate::modules::sccm::record_sccm_object;
+use crate::modules::hybrid::record_hybrid_object;
+use crate::modules::exchange::record_exchange_server;
+use crate::modules::synthetic_edges::is_domain_controller;
+use crate::json::spill::{SpillStore, select_objects};
+use log::{info, debug, error};
 
 pub mod bh_41;
 
+/// Objects and lookup maps filled by the parser.
+#[derive(Default)]
+struct ParsedObjects {
+    users: Vec<serde_json::value::Value>,
+    groups: Vec<serde_json::value::Value>,
+    computers: Vec<serde_json::value::Value>,
+    ous: Vec<serde_json::value::Value>,
+    domains: Vec<serde_json::value::Value>,
+    gpos: Vec<serde_json::value::Value>,
+    fsps: Vec<serde_json::value::Value>,
+    containers: Vec<serde_json::value::Value>,
+    trusts: Vec<serde_json::value::Value>,
+    psos: Vec<serde_json::value::Value>,
+    roles: Vec<serde_json::value::Value>,
+    dn_sid: HashMap<String, String>,
+    sid_type: HashMap<String, String>,
+    fqdn_sid: HashMap<String, String>,
+    fqdn_ip: HashMap<String, String>,
+}
+
+/// Pages of LDAP entries waiting for a parsing worker, per worker.
+const PIPELINE_PAGES_PER_WORKER: usize = 2;
+
+/// Parsing workers fed page by page during the LDAP collection (--parse-threads).
+/// The security descriptors are parsed while the next pages are requested, the bounded channel
+/// suspends the collection task (not the tokio worker) when the workers are late to keep the memory low.
+/// With --low-memory the users and computers of every page are written in the spill store once parsed.
+pub struct ParsePipeline {
+    sender: Option<Sender<(usize, Vec<SearchEntry>)>>,
+    workers: Vec<JoinHandle<Vec<(usize, ParsedObjects)>>>,
+    pages: AtomicUsize,
+    entries: AtomicU64,
+}
+
+impl ParsePipeline {
+    /// Function to start the parsing workers (0 for all the available threads).
+    pub fn start(domain: &String, threads: usize, spill: Option<Arc<SpillStore>>) -> ParsePipeline {
+        let threads = parse_threads(threads);
+        let (sender, receiver) = channel::<(usize, Vec<SearchEntry>)>(threads * PIPELINE_PAGES_PER_WORKER);
+        let receiver = Arc::new(Mutex::new(receiver));
+        debug!("Parsing the LDAP pages with {} threads during the collection", threads);
+        let workers = (0..threads).map(|_| {
+            let receiver = Arc::clone(&receiver);
+            let domain = domain.to_owned();
+            let spill = spill.clone();
+            std::thread::spawn(move || {
+                let mut parsed: Vec<(usize, ParsedObjects)> = Vec::new();
+                loop {
+                    // Parsing threads out of the runtime, the blocking receive does not hold a tokio worker
+                    let page = receiver.lock().ok().and_then(|mut receiver| receiver.blocking_recv());
+                    match page {
+                        Some((index, entries)) => {
+                            let mut objects = parse_entries(&domain, entries, None);
+                            if let Some(spill) = &spill {
+                                spill_objects(spill, &mut objects);
+                            }
+                            parsed.push((index, objects))
+                        }
+                        None => break,
+                    }
+                }
+                parsed
+            })
+        }).collect();
+        ParsePipeline {
+            sender: Some(sender),
+            workers,
+            pages: AtomicUsize::new(0),
+            entries: AtomicU64::new(0),
+        }
+    }
+
+    /// Function to send one page of entries to the workers, wait if they are all busy.
+    pub async fn send(&self, entries: Vec<SearchEntry>) {
+        if entries.is_empty() {
+            return
+        }
+        let index = self.pages.fetch_add(1, Ordering::Relaxed);
+        self.entries.fetch_add(entries.len() as u64, Ordering::Relaxed);
+        if let Some(sender) = &self.sender {
+            let _ = sender.send((index, entries)).await;
+        }
+    }
+
+    /// Function to get the number of entries sent to the workers.
+    pub fn entries(&self) -> u64 {
+        self.entries.load(Ordering::Relaxed)
+    }
+
+    /// Function to wait for the workers, return the parsed pages in the collection order.
+    fn finish(mut self) -> Vec<ParsedObjects> {
+        // The workers stop when the channel is closed and empty
+        self.sender = None;
+        let mut parsed: Vec<(usize, ParsedObjects)> = self.workers.drain(..)
+            .flat_map(|worker| worker.join().expect("parser thread panicked"))
+            .collect();
+        parsed.sort_by_key(|(index, _)| *index);
+        parsed.into_iter().map(|(_, parsed)| parsed).collect()
+    }
+}
+
+/// Function to get the number of parsing threads, 0 for all the available threads.
+pub fn parse_threads(threads: usize) -> usize {
+    if threads == 0 { std::thread::available_parallelism().map(|n| n.get()).unwrap_or(1) } else { threads }
+}
+
 /// Function to get type for object by object
 pub fn parse_result_type(
     domain: &String,
@@ -24,114 +140,263 @@ pub fn parse_result_type(
     vec_fsps: &mut Vec<serde_json::value::Value>,
     vec_containers: &mut Vec<serde_json::value::Value>,
     vec_trusts: &mut Vec<serde_json::value::Value>,
+    vec_psos: &mut Vec<serde_json::value::Value>,
 
     dn_sid: &mut HashMap<String, String>,
     sid_type: &mut HashMap<String, String>,
     fqdn_sid: &mut HashMap<String, String>,
     fqdn_ip: &mut HashMap<String, String>,
 )   
+{
+    par_parse_result_type(
+        domain, result, 1, None, None,
+        vec_users, vec_groups, vec_computers, vec_ous, vec_domains, vec_gpos, vec_fsps, vec_containers, vec_trusts, vec_psos,
+        dn_sid, sid_type, fqdn_sid, fqdn_ip,
+    );
+}
+
+/// Function to get type for object by object, the entries are split between threads (0 for all the available threads).
+/// Every thread fills its own objects and maps, merged in the order of the entries: same result as one thread.
+/// The pages already parsed by the pipeline during the collection come first.
+/// With --low-memory the users and computers are written in the spill store instead of vec_users and vec_computers.
+/// The FSMO role owners and the Exchange organization are set on the domain once all the computers are parsed.
+pub fn par_parse_result_type(
+    domain: &String,
+    result: Vec<SearchEntry>,
+    threads: usize,
+    pipeline: Option<ParsePipeline>,
+    spill: Option<&SpillStore>,
+
+    vec_users: &mut Vec<serde_json::value::Value>,
+    vec_groups: &mut Vec<serde_json::value::Value>,
+    vec_computers: &mut Vec<serde_json::value::Value>,
+    vec_ous: &mut Vec<serde_json::value::Value>,
+    vec_domains: &mut Vec<serde_json::value::Value>,
+    vec_gpos: &mut Vec<serde_json::value::Value>,
+    vec_fsps: &mut Vec<serde_json::value::Value>,
+    vec_containers: &mut Vec<serde_json::value::Value>,
+    vec_trusts: &mut Vec<serde_json::value::Value>,
+    vec_psos: &mut Vec<serde_json::value::Value>,
+
+    dn_sid: &mut HashMap<String, String>,
+    sid_type: &mut HashMap<String, String>,
+    fqdn_sid: &mut HashMap<String, String>,
+    fqdn_ip: &mut HashMap<String, String>,
+)
 {
     // Needed for progress bar stats
     let pb = ProgressBar::new(1);
-    let mut count = 0;
-    let total = result.len();
+    let count = AtomicU64::new(0);
+    let total = result.len() as u64;
 
     info!("Starting the LDAP objects parsing...");
+    let mut chunks: Vec<ParsedObjects> = match pipeline {
+        Some(pipeline) => pipeline.finish(),
+        None => Vec::new(),
+    };
+    let threads = parse_threads(threads);
+    chunks.extend(if threads <= 1 || result.len() < 2 * threads {
+        vec![parse_entries(domain, result, Some((&pb, &count, total)))]
+    } else {
+        debug!("Parsing {} LDAP objects with {} threads", total, threads);
+        let chunk_size = (result.len() + threads - 1) / threads;
+        let mut entries = result.into_iter();
+        let mut chunks: Vec<Vec<SearchEntry>> = Vec::new();
+        loop {
+            let chunk: Vec<SearchEntry> = entries.by_ref().take(chunk_size).collect();
+            if chunk.is_empty() {
+                break
+            }
+            chunks.push(chunk);
+        }
+        let (pb, count) = (&pb, &count);
+        std::thread::scope(|scope| {
+            let handles: Vec<_> = chunks.into_iter()
+                .map(|chunk| scope.spawn(move || parse_entries(domain, chunk, Some((pb, count, total)))))
+                .collect();
+            handles.into_iter().map(|handle| handle.join().expect("parser thread panicked")).collect::<Vec<ParsedObjects>>()
+        })
+    });
+    let mut vec_roles: Vec<serde_json::value::Value> = Vec::new();
+    for mut parsed in chunks {
+        if let Some(spill) = spill {
+            spill_objects(spill, &mut parsed);
+        }
+        vec_users.extend(parsed.users);
+        vec_groups.extend(parsed.groups);
+        vec_computers.extend(parsed.computers);
+        vec_ous.extend(parsed.ous);
+        vec_domains.extend(parsed.domains);
+        vec_gpos.extend(parsed.gpos);
+        vec_fsps.extend(parsed.fsps);
+        vec_containers.extend(parsed.containers);
+        vec_trusts.extend(parsed.trusts);
+        vec_psos.extend(parsed.psos);
+        vec_roles.extend(parsed.roles);
+        dn_sid.extend(parsed.dn_sid);
+        sid_type.extend(parsed.sid_type);
+        fqdn_sid.extend(parsed.fqdn_sid);
+        fqdn_ip.extend(parsed.fqdn_ip);
+    }
+    match spill {
+        // The role owners are domain controllers, read back from the spilled computers
+        Some(spill) => {
+            let owners = select_objects(vec_computers, Some(spill), "computers", is_domain_controller).unwrap_or_else(|err| {
+                error!("Can't read the spilled computers, FSMO role owners not resolved. Reason: {err}");
+                Vec::new()
+            });
+            bh_41::set_fsmo_roles(vec_domains, &vec_roles, &owners);
+        }
+        None => bh_41::set_fsmo_roles(vec_domains, &vec_roles, vec_computers),
+    }
+    pb.finish_and_clear();
+    info!("Parsing LDAP objects finished!");
+}
+
+/// Function to write the parsed users and computers in the spill store, kept in memory when the write fails.
+fn spill_objects(spill: &SpillStore, parsed: &mut ParsedObjects)
+{
+    for (data_type, objects) in [("users", &mut parsed.users), ("computers", &mut parsed.computers)] {
+        if let Err(err) = spill.write(data_type, objects) {
+            error!("Can't spill {} {}, kept in memory. Reason: {err}", objects.len(), data_type);
+        }
+    }
+}
+
+/// Function to parse one list of entries, with the shared progress bar (bar, parsed objects, total).
+fn parse_entries(domain: &String, result: Vec<SearchEntry>, progress: Option<(&ProgressBar, &AtomicU64, u64)>) -> ParsedObjects
+{
+    let mut parsed = ParsedObjects::default();
     for entry in result {
+        // Invalid UTF-8 text values decoded lossy before the parsers
+        let entry = normalize_entry(entry);
         // Start parsing with Type matching
         let cloneresult = entry.clone();
         let atype = get_type(entry).unwrap_or(Type::Unknown);
+        // Every entry is parsed apart, an entry the parser fails on is skipped and reported
         match atype {
             Type::User => {
-                let user = parse_user(
+                if let Some(user) = isolate(cloneresult, "User", |cloneresult| parse_user(
                     cloneresult,
                     domain,
-                    dn_sid,
-                    sid_type,
-                );
-                vec_users.push(user);
+                    &mut parsed.dn_sid,
+                    &mut parsed.sid_type,
+                )) {
+                    parsed.users.push(user);
+                }
             }
             Type::Group => {
-                let group = parse_group(
+                if let Some(group) = isolate(cloneresult, "Group", |cloneresult| parse_group(
                     cloneresult,
                     domain,
-                    dn_sid,
-                    sid_type,
-                );
-                vec_groups.push(group);
+                    &mut parsed.dn_sid,
+                    &mut parsed.sid_type,
+                )) {
+                    parsed.groups.push(group);
+                }
             }
             Type::Computer => {
-                let computer = parse_computer(
+                if let Some(computer) = isolate(cloneresult, "Computer", |cloneresult| parse_computer(
                     cloneresult,
                     domain,
-                    dn_sid,
-                    sid_type,
-                    fqdn_sid,
-                    fqdn_ip,
-                );
-                vec_computers.push(computer);
+                    &mut parsed.dn_sid,
+                    &mut parsed.sid_type,
+                    &mut parsed.fqdn_sid,
+                    &mut parsed.fqdn_ip,
+                )) {
+                    parsed.computers.push(computer);
+                }
             }
             Type::Ou => {
-                let ou = parse_ou(
+                if let Some(ou) = isolate(cloneresult, "OU", |cloneresult| parse_ou(
                     cloneresult,
                     domain,
-                    dn_sid,
-                    sid_type,
-                );
-                vec_ous.push(ou);
+                    &mut parsed.dn_sid,
+                    &mut parsed.sid_type,
+                )) {
+                    parsed.ous.push(ou);
+                }
             }
             Type::Domain => {
-                let domain = parse_domain(
+                if let Some(domain) = isolate(cloneresult, "Domain", |cloneresult| parse_domain(
                     cloneresult,
                     domain,
-                    dn_sid,
-                    sid_type,
-                );
-                vec_domains.push(domain);
+                    &mut parsed.dn_sid,
+                    &mut parsed.sid_type,
+                )) {
+                    parsed.domains.push(domain);
+                }
             }
             Type::Gpo => {
-                let gpo = parse_gpo(
+                if let Some(gpo) = isolate(cloneresult, "GPO", |cloneresult| parse_gpo(
                     cloneresult,
                     domain,
-                    dn_sid,
-                    sid_type,
-                );
-                vec_gpos.push(gpo);
+                    &mut parsed.dn_sid,
+                    &mut parsed.sid_type,
+                )) {
+                    parsed.gpos.push(gpo);
+                }
             }
             Type::ForeignSecurityPrincipal => {
-                let security_principal = parse_fsp(
+                if let Some(security_principal) = isolate(cloneresult, "ForeignSecurityPrincipal", |cloneresult| parse_fsp(
                     cloneresult,
                     domain,
-                    dn_sid,
-                    sid_type,
-                );
-                vec_fsps.push(security_principal);
+                    &mut parsed.dn_sid,
+                    &mut parsed.sid_type,
+                )) {
+                    parsed.fsps.push(security_principal);
+                }
             }
             Type::Container => {
-                let re = Regex::new(r"[0-9a-z-A-Z]{1,}-[0-9a-z-A-Z]{1,}-[0-9a-z-A-Z]{1,}-[0-9a-z-A-Z]{1,}").unwrap();
-                if re.is_match(&cloneresult.dn.to_uppercase()) 
-                {
-                    //trace!("Container not to add: {}",&cloneresult.dn.to_uppercase());
-                    continue
-                }
-                let re = Regex::new(r"CN=DOMAINUPDATES,CN=SYSTEM,").unwrap();
-                if re.is_match(&cloneresult.dn.to_uppercase()) 
+                if is_skipped_container(&cloneresult.dn)
                 {
                     //trace!("Container not to add: {}",&cloneresult.dn.to_uppercase());
                     continue
                 }
                 //trace!("Container: {}",&cloneresult.dn.to_uppercase());
-                let container = parse_container(
+                if let Some(container) = isolate(cloneresult, "Container", |cloneresult| parse_container(
                     cloneresult,
                     domain,
-                    dn_sid,
-                    sid_type,
-                );
-                vec_containers.push(container);
+                    &mut parsed.dn_sid,
+                    &mut parsed.sid_type,
+                )) {
+                    parsed.containers.push(container);
+                }
             }
             Type::Trust => {
-                let trust = parse_trust(cloneresult, domain);
-                vec_trusts.push(trust);
+                if let Some(trust) = isolate(cloneresult, "Trust", |cloneresult| parse_trust(cloneresult, domain)) {
+                    parsed.trusts.push(trust);
+                }
+            }
+            Type::PasswordSettings => {
+                if let Some(pso) = isolate(cloneresult, "PasswordSettings", |cloneresult| parse_pso(cloneresult, domain)) {
+                    parsed.psos.push(pso);
+                }
+            }
+            Type::FsmoRole => {
+                if let Some(role) = isolate(cloneresult, "FsmoRole", |cloneresult| parse_fsmo_role(cloneresult, domain)) {
+                    parsed.roles.push(role);
+                }
+            }
+            Type::ExchangeOrganization => {
+                if let Some(organization) = isolate(cloneresult, "ExchangeOrganization", |cloneresult| parse_exchange_organization(cloneresult, domain)) {
+                    parsed.roles.push(organization);
+                }
+            }
+            Type::ExchangeServer => {
+                if let Some(server) = isolate(cloneresult, "ExchangeServer", |cloneresult| parse_exchange_server(cloneresult, domain)) {
+                    record_exchange_server(server);
+                }
+            }
+            Type::SccmObject => {
+                if let Some(object) = isolate(cloneresult, "SccmObject", |cloneresult| parse_sccm_object(cloneresult, domain)) {
+                    record_sccm_object(object);
+                }
+            }
+            Type::DeviceRegistration => {
+                if let Some(object) = isolate(cloneresult, "DeviceRegistration", |cloneresult| parse_device_registration(cloneresult, domain)) {
+                    record_hybrid_object(object);
+                }
             }
             Type::Unknown => {
                 let _unknown = parse_unknown(cloneresult, domain);
@@ -139,12 +404,12 @@ pub fn parse_result_type(
         }
         // Manage progress bar
         // Pourcentage (%) = 100 x Valeur partielle/Valeur totale
-		count += 1;
-        let pourcentage = 100 * count / total;
-        progress_bar(pb.to_owned(),"Parsing LDAP objects".to_string(),pourcentage.try_into().unwrap(),"%".to_string());
+        if let Some((pb, count, total)) = progress {
+            let pourcentage = 100 * (count.fetch_add(1, Ordering::Relaxed) + 1) / total;
+            progress_bar(pb.to_owned(),"Parsing LDAP objects".to_string(),pourcentage,"%".to_string());
+        }
     }
-    pb.finish_and_clear();
-    info!("Parsing LDAP objects finished!");
+    parsed
 }
 
 
@@ -220,6 +485,15 @@ pub fn parse_fsp(
     bh_41::parse_fsp(result, domain, dn_sid, sid_type)
 }
 
+/// Containers not collected: the GUID named ones (GPO subtree, DomainUpdates operations) and CN=DomainUpdates.
+/// The default containers (CN=Users, CN=Computers, CN=Managed Service Accounts, CN=System...) are collected
+/// even in a domain with hyphens in its name.
+pub fn is_skipped_container(dn: &str) -> bool {
+    let dn = dn.to_uppercase();
+    let re = Regex::new(r"\{?[0-9A-F]{8}-[0-9A-F]{4}-[0-9A-F]{4}-[0-9A-F]{4}-[0-9A-F]{12}\}?").unwrap();
+    re.is_match(&dn) || dn.contains("CN=DOMAINUPDATES,CN=SYSTEM,")
+}
+
 /// Parse Containers object. Select parser based on BH version new in BH4.1+
 pub fn parse_container(
     result: SearchEntry,
@@ -238,10 +512,98 @@ pub fn parse_trust(
     bh_41::parse_trust(result, _domain)
 }
 
+/// Parse fine-grained password policy object. Select parser based on BH version.
+pub fn parse_pso(
+    result: SearchEntry, 
+    _domain: &String,
+) -> serde_json::value::Value {
+    bh_41::parse_pso(result, _domain)
+}
+
+/// Parse FSMO role object. Select parser based on BH version.
+pub fn parse_fsmo_role(
+    result: SearchEntry, 
+    _domain: &String,
+) -> serde_json::value::Value {
+    bh_41::parse_fsmo_role(result, _domain)
+}
+
+/// Parse Exchange server object. Select parser based on BH version.
+pub fn parse_exchange_server(
+    result: SearchEntry,
+    domain: &String,
+) -> serde_json::value::Value {
+    bh_41::parse_exchange_server(result, domain)
+}
+
+/// Parse SCCM object. Select parser based on BH version.
+pub fn parse_sccm_object(
+    result: SearchEntry,
+    domain: &String,
+) -> serde_json::value::Value {
+    bh_41::parse_sccm_object(result, domain)
+}
+
+/// Parse device registration service connection point. Select parser based on BH version.
+pub fn parse_device_registration(
+    result: SearchEntry,
+    domain: &String,
+) -> serde_json::value::Value {
+    bh_41::parse_device_registration(result, domain)
+}
+
+/// Parse Exchange organization object. Select parser based on BH version.
+pub fn parse_exchange_organization(
+    result: SearchEntry, 
+    _domain: &String,
+) -> serde_json::value::Value {
+    bh_41::parse_exchange_organization(result, _domain)
+}
+
 /// Parse unknown object. Select parser based on BH version.
 pub fn parse_unknown(
     result: SearchEntry, 
     _domain: &String,
 ) -> serde_json::value::Value {
     bh_41::parse_unknown(result, _domain)
-}
\ No newline at end of file
+}
+/// Test functions
+#[test]
+pub fn test_parse_pipeline() {
+    let ou = |name: &str| SearchEntry {
+        dn: format!("OU={},DC=ESSOS,DC=LOCAL", name),
+        attrs: vec![
+            ("objectClass".to_string(), vec!["top".to_string(), "organizationalUnit".to_string()]),
+            ("name".to_string(), vec![name.to_string()]),
+        ].into_iter().collect(),
+        bin_attrs: HashMap::new(),
+    };
+    let domain = "ESSOS.LOCAL".to_string();
+    let pipeline = ParsePipeline::start(&domain, 2, None);
+    let runtime = tokio::runtime::Builder::new_current_thread().build().unwrap();
+    for page in [vec!["A", "B"], vec!["C"], vec!["D", "E"]] {
+        runtime.block_on(pipeline.send(page.into_iter().map(ou).collect()));
+    }
+    assert_eq!(pipeline.entries(), 5);
+
+    let (mut users, mut groups, mut computers, mut ous, mut domains) = (Vec::new(), Vec::new(), Vec::new(), Vec::new(), Vec::new());
+    let (mut gpos, mut fsps, mut containers, mut trusts, mut psos) = (Vec::new(), Vec::new(), Vec::new(), Vec::new(), Vec::new());
+    let (mut dn_sid, mut sid_type, mut fqdn_sid, mut fqdn_ip) = (HashMap::new(), HashMap::new(), HashMap::new(), HashMap::new());
+    par_parse_result_type(
+        &domain, vec![ou("F")], 1, Some(pipeline), None,
+        &mut users, &mut groups, &mut computers, &mut ous, &mut domains, &mut gpos, &mut fsps, &mut containers, &mut trusts, &mut psos,
+        &mut dn_sid, &mut sid_type, &mut fqdn_sid, &mut fqdn_ip,
+    );
+    let names: Vec<&str> = ous.iter().map(|ou| ou["Properties"]["name"].as_str().unwrap()).collect();
+    assert_eq!(names, vec!["A@ESSOS.LOCAL", "B@ESSOS.LOCAL", "C@ESSOS.LOCAL", "D@ESSOS.LOCAL", "E@ESSOS.LOCAL", "F@ESSOS.LOCAL"]);
+}
+
+#[test]
+pub fn test_is_skipped_container() {
+    assert!(!is_skipped_container("CN=Users,DC=north-sevenkingdoms-local-lab,DC=local"));
+    assert!(!is_skipped_container("CN=Managed Service Accounts,DC=ESSOS,DC=LOCAL"));
+    assert!(!is_skipped_container("CN=Builtin,DC=ESSOS,DC=LOCAL"));
+    assert!(is_skipped_container("CN=Machine,CN={31B2F340-016D-11D2-945F-00C04FB984F9},CN=Policies,CN=System,DC=ESSOS,DC=LOCAL"));
+    assert!(is_skipped_container("CN=Operations,CN=DomainUpdates,CN=System,DC=ESSOS,DC=LOCAL"));
+    assert!(is_skipped_container("CN=6E157EDD-4E8E-4A12-A3C2-DE85FA1BBB7E,CN=Operations,CN=DomainUpdates,CN=System,DC=ESSOS,DC=LOCAL"));
+}
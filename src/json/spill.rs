@@ -0,0 +1,294 @@
+//! Users and computers spilled on disk (--low-memory)
+//!
+//! With `--low-memory` the users and computers, most of the objects of a forest, are not held in memory during
+//! the run. The parsing workers write them in JSON Lines segments of `<dirpath>/.<domain>_spill/` as the LDAP
+//! pages are parsed, the checker rewrites the segments one by one with the frozen lookup maps, and the writer
+//! sorts every segment then merges them object by object in the JSON files (see [`SpillStore::merged`]).
+//! Only the lookup maps, the other types and one segment at a time are in memory, the directory is removed with
+//! the store. Nothing is spilled when a module or an output reads all the objects, see
+//! [`crate::modules::reads_collection`].
+use log::debug;
+use serde_json::value::Value;
+use std::fs::{self, File};
+use std::io::{BufRead, BufReader, BufWriter, Result, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Mutex, MutexGuard};
+
+use crate::json::maker::sort_objects;
+
+/// Types spilled by --low-memory, named like their JSON files.
+pub const SPILLED_TYPES: [&str; 2] = ["users", "computers"];
+/// Objects per segment, about one LDAP page.
+pub const SEGMENT_OBJECTS: usize = 1000;
+/// Segments read at once by the merge, more sorted segments are first merged in bigger ones.
+const MERGE_WAYS: usize = 64;
+
+/// One JSON Lines file of spilled objects.
+struct Segment {
+   data_type: String,
+   path: PathBuf,
+   count: usize,
+}
+
+/// Segments of the spilled objects, written by the parsing workers and rewritten by the checker.
+pub struct SpillStore {
+   dir: PathBuf,
+   segments: Mutex<Vec<Segment>>,
+   next: AtomicUsize,
+}
+
+impl SpillStore {
+   /// Function to create the spill directory `<dirpath>/.<domain>_spill`.
+   pub fn create(path: &String, domain: &String) -> Result<SpillStore> {
+      let dir = PathBuf::from(format!("{}/.{}_spill", path, domain.replace(".", "-").to_lowercase()));
+      fs::create_dir_all(&dir)?;
+      debug!("Users and computers spilled in {}", dir.display());
+      Ok(SpillStore {
+         dir,
+         segments: Mutex::new(Vec::new()),
+         next: AtomicUsize::new(0),
+      })
+   }
+
+   /// Function to write objects of one type in new segments, the vector is emptied once written.
+   /// The objects stay in the vector when the write fails.
+   pub fn write(&self, data_type: &str, objects: &mut Vec<Value>) -> Result<()> {
+      let mut written: Vec<Segment> = Vec::new();
+      for chunk in objects.chunks(SEGMENT_OBJECTS) {
+         let path = self.segment_path(data_type);
+         if let Err(err) = write_segment(&path, chunk.iter()) {
+            let _ = fs::remove_file(&path);
+            written.iter().for_each(|segment| { let _ = fs::remove_file(&segment.path); });
+            return Err(err)
+         }
+         written.push(Segment { data_type: data_type.to_string(), path, count: chunk.len() });
+      }
+      self.segments().extend(written);
+      objects.clear();
+      Ok(())
+   }
+
+   /// Function to get the number of spilled objects of one type.
+   pub fn count(&self, data_type: &str) -> usize {
+      self.segments().iter().filter(|segment| segment.data_type == data_type).map(|segment| segment.count).sum()
+   }
+
+   /// Function to run f on every segment of one type, the segment is written back once changed.
+   pub fn rewrite(&self, data_type: &str, mut f: impl FnMut(&mut Vec<Value>)) -> Result<()> {
+      for segment in self.segments().iter_mut().filter(|segment| segment.data_type == data_type) {
+         let mut objects = read_segment(&segment.path)?.collect::<Result<Vec<Value>>>()?;
+         f(&mut objects);
+         // Written aside then renamed, a failed write never leaves a truncated segment
+         let part = segment.path.with_extension("part");
+         write_segment(&part, objects.iter())?;
+         fs::rename(&part, &segment.path)?;
+         segment.count = objects.len();
+      }
+      Ok(())
+   }
+
+   /// Function to read the spilled objects of one type one by one.
+   pub fn visit(&self, data_type: &str, mut f: impl FnMut(&Value)) -> Result<()> {
+      for segment in self.segments().iter().filter(|segment| segment.data_type == data_type) {
+         for object in read_segment(&segment.path)? {
+            f(&object?);
+         }
+      }
+      Ok(())
+   }
+
+   /// Function to get the first spilled object of one type.
+   pub fn first(&self, data_type: &str) -> Result<Option<Value>> {
+      for segment in self.segments().iter().filter(|segment| segment.data_type == data_type && segment.count > 0) {
+         if let Some(object) = read_segment(&segment.path)?.next() {
+            return object.map(Some)
+         }
+      }
+      Ok(None)
+   }
+
+   /// Function to remove the spilled objects matching a filter, they are returned.
+   pub fn take(&self, data_type: &str, mut filter: impl FnMut(&Value) -> bool) -> Result<Vec<Value>> {
+      let mut taken: Vec<Value> = Vec::new();
+      self.rewrite(data_type, |objects| {
+         let (matched, kept): (Vec<Value>, Vec<Value>) = objects.drain(..).partition(|object| filter(object));
+         taken.extend(matched);
+         *objects = kept;
+      })?;
+      Ok(taken)
+   }
+
+   /// Function to merge the objects in memory and the spilled objects of one type, sorted like [`sort_objects`].
+   /// Every segment is sorted, then read object by object: the merge holds one object per segment.
+   pub fn merged(&self, data_type: &str, mut objects: Vec<Value>) -> Result<MergedObjects> {
+      sort_objects(&mut objects);
+      self.rewrite(data_type, |segment| sort_objects(segment))?;
+      let mut segments = self.segments();
+      let (mut sorted, others): (Vec<Segment>, Vec<Segment>) = segments.drain(..).partition(|segment| segment.data_type == data_type);
+      segments.extend(others);
+      // Not too many files open at once
+      while sorted.len() > MERGE_WAYS {
+         let mut merged: Vec<Segment> = Vec::new();
+         while !sorted.is_empty() {
+            let group: Vec<Segment> = sorted.drain(..MERGE_WAYS.min(sorted.len())).collect();
+            let path = self.segment_path(data_type);
+            let count = write_segment_results(&path, MergedObjects::new(Vec::new(), &group)?)?;
+            group.iter().for_each(|segment| { let _ = fs::remove_file(&segment.path); });
+            merged.push(Segment { data_type: data_type.to_string(), path, count });
+         }
+         sorted = merged;
+      }
+      let merge = MergedObjects::new(objects, &sorted);
+      segments.extend(sorted);
+      merge
+   }
+
+   fn segment_path(&self, data_type: &str) -> PathBuf {
+      self.dir.join(format!("{}_{:06}.jsonl", data_type, self.next.fetch_add(1, Ordering::Relaxed)))
+   }
+
+   fn segments(&self) -> MutexGuard<'_, Vec<Segment>> {
+      // A panicking parser thread leaves the list of segments valid
+      self.segments.lock().unwrap_or_else(|err| err.into_inner())
+   }
+}
+
+impl Drop for SpillStore {
+   fn drop(&mut self) {
+      if let Err(err) = fs::remove_dir_all(&self.dir) {
+         debug!("Can't remove {}. Reason: {err}", self.dir.display());
+      }
+   }
+}
+
+/// Sorted objects of the memory and of the segments, merged by ObjectIdentifier.
+pub struct MergedObjects {
+   sources: Vec<Box<dyn Iterator<Item = Result<Value>>>>,
+   heads: Vec<Option<Value>>,
+}
+
+impl MergedObjects {
+   fn new(objects: Vec<Value>, segments: &[Segment]) -> Result<MergedObjects> {
+      let mut sources: Vec<Box<dyn Iterator<Item = Result<Value>>>> = vec![Box::new(objects.into_iter().map(Ok))];
+      for segment in segments {
+         sources.push(Box::new(read_segment(&segment.path)?));
+      }
+      let heads = sources.iter_mut().map(|source| source.next().transpose()).collect::<Result<Vec<Option<Value>>>>()?;
+      Ok(MergedObjects { sources, heads })
+   }
+}
+
+impl Iterator for MergedObjects {
+   type Item = Result<Value>;
+
+   fn next(&mut self) -> Option<Result<Value>> {
+      // The first source wins between equal identifiers, like the stable sort
+      let index = self.heads.iter().enumerate()
+         .filter_map(|(index, head)| head.as_ref().map(|head| (index, head["ObjectIdentifier"].as_str().unwrap_or(""))))
+         .min_by(|a, b| a.1.cmp(b.1))?.0;
+      let object = self.heads[index].take();
+      match self.sources[index].next() {
+         Some(Ok(next)) => self.heads[index] = Some(next),
+         Some(Err(err)) => return Some(Err(err)),
+         None => {}
+      }
+      object.map(Ok)
+   }
+}
+
+/// Function to run f on the objects in memory, then on the spilled segments of the same type one by one.
+pub fn for_each_page(objects: &mut Vec<Value>, spill: Option<&SpillStore>, data_type: &str, mut f: impl FnMut(&mut Vec<Value>)) -> Result<()> {
+   f(objects);
+   match spill {
+      Some(spill) => spill.rewrite(data_type, f),
+      None => Ok(()),
+   }
+}
+
+/// Function to read the objects in memory, then the spilled objects of the same type.
+pub fn visit_objects(objects: &[Value], spill: Option<&SpillStore>, data_type: &str, mut f: impl FnMut(&Value)) -> Result<()> {
+   objects.iter().for_each(&mut f);
+   match spill {
+      Some(spill) => spill.visit(data_type, f),
+      None => Ok(()),
+   }
+}
+
+/// Function to copy the objects in memory and spilled matching a filter.
+pub fn select_objects(objects: &[Value], spill: Option<&SpillStore>, data_type: &str, mut filter: impl FnMut(&Value) -> bool) -> Result<Vec<Value>> {
+   let mut selected: Vec<Value> = Vec::new();
+   visit_objects(objects, spill, data_type, |object| {
+      if filter(object) {
+         selected.push(object.to_owned());
+      }
+   })?;
+   Ok(selected)
+}
+
+fn read_segment(path: &Path) -> Result<impl Iterator<Item = Result<Value>>> {
+   let lines = BufReader::new(File::open(path)?).lines();
+   Ok(lines.map(|line| line.and_then(|line| serde_json::from_str::<Value>(&line).map_err(std::io::Error::from))))
+}
+
+fn write_segment<'a>(path: &Path, objects: impl Iterator<Item = &'a Value>) -> Result<()> {
+   let mut writer = BufWriter::new(File::create(path)?);
+   for object in objects {
+      serde_json::to_writer(&mut writer, object)?;
+      writer.write_all(b"\n")?;
+   }
+   writer.flush()
+}
+
+fn write_segment_results(path: &Path, objects: impl Iterator<Item = Result<Value>>) -> Result<usize> {
+   let mut writer = BufWriter::new(File::create(path)?);
+   let mut count = 0;
+   for object in objects {
+      serde_json::to_writer(&mut writer, &object?)?;
+      writer.write_all(b"\n")?;
+      count += 1;
+   }
+   writer.flush()?;
+   Ok(count)
+}
+
+/// Test functions
+#[test]
+pub fn test_spill_store() {
+   let path = std::env::temp_dir().join(format!("rusthound_spill_{}", std::process::id())).to_string_lossy().to_string();
+   let domain = "essos.local".to_string();
+   let user = |rid: usize| serde_json::json!({"ObjectIdentifier": format!("S-1-5-21-1-2-3-{}", rid), "Aces": [], "Properties": {"whencreated": 1696000000.0}});
+   let spill = SpillStore::create(&path, &domain).unwrap();
+
+   // Out of order pages, more segments than merged at once
+   let rids: Vec<usize> = (0..SEGMENT_OBJECTS * (MERGE_WAYS + 2)).map(|i| (i * 7919) % 100_000 + 1000).collect();
+   for page in rids.chunks(SEGMENT_OBJECTS) {
+      let mut users: Vec<Value> = page.iter().map(|rid| user(*rid)).collect();
+      spill.write("users", &mut users).unwrap();
+      assert!(users.is_empty());
+   }
+   assert_eq!(spill.count("users"), rids.len());
+   assert_eq!(spill.count("computers"), 0);
+
+   // Checked page by page, read back and taken out
+   for_each_page(&mut vec![user(1), user(2)], Some(&spill), "users", |page| page.iter_mut().for_each(|user| user["Properties"]["checked"] = true.into())).unwrap();
+   assert_eq!(spill.first("users").unwrap().unwrap()["Properties"]["checked"], true);
+   let taken = spill.take("users", |user| user["ObjectIdentifier"] == "S-1-5-21-1-2-3-1000").unwrap();
+   assert_eq!(taken.len(), 1);
+   assert_eq!(spill.count("users"), rids.len() - 1);
+   assert_eq!(select_objects(&[user(1)], Some(&spill), "users", |user| user["ObjectIdentifier"].as_str().unwrap().ends_with("-1")).unwrap().len(), 1);
+
+   // Same order as the objects sorted in memory
+   let mut expected: Vec<Value> = rids.iter().filter(|rid| **rid != 1000).map(|rid| user(*rid)).chain([user(1)]).collect();
+   sort_objects(&mut expected);
+   let merged: Vec<Value> = spill.merged("users", vec![user(1)]).unwrap().collect::<Result<Vec<Value>>>().unwrap();
+   assert_eq!(merged.len(), expected.len());
+   assert!(merged.iter().zip(expected.iter()).all(|(merged, expected)| merged["ObjectIdentifier"] == expected["ObjectIdentifier"]));
+   assert_eq!(merged[1]["Properties"]["checked"], true);
+
+   let dir = format!("{}/.essos-local_spill", path);
+   assert!(Path::new(&dir).exists());
+   drop(spill);
+   assert!(!Path::new(&dir).exists());
+   let _ = fs::remove_dir_all(&path);
+}
@@ -21,6 +21,11 @@ pub fn prepare_user_json_template() -> serde_json::value::Value
          "pwdneverexpires": false,
          "enabled": true,
          "trustedtoauth": false,  
+         "reversibleencryption": false,
+         "usedeskeyonly": false,
+         "supportedencryptiontypes": [],
+         "supportsaes": false,
+         "desonly": false,
          "lastlogon": -1,
          "lastlogontimestamp": -1,
          "pwdlastset": -1,
@@ -62,6 +67,7 @@ pub fn prepare_group_json_template() -> serde_json::value::Value
          "name": "name@domain.com",
          "distinguishedname": "DN",
          "admincount": false,
+         "highvalue": false,
          "description": null,
          "whencreated": -1
       },
@@ -83,6 +89,7 @@ pub fn prepare_computer_json_template() -> serde_json::value::Value
          "distinguishedname": "DN",
          "samaccountname": null,
          "domainsid": "SID",
+         "admincount": false,
          "haslaps": false,
          "description": null,
          "whencreated": -1,
@@ -94,9 +101,13 @@ pub fn prepare_computer_json_template() -> serde_json::value::Value
          "pwdlastset": -1,
          "serviceprincipalnames": [],
          "operatingsystem": null,
+         "supportedencryptiontypes": [],
+         "supportsaes": false,
+         "desonly": false,
+         "probably_stale": false,
          "sidhistory": [],
       },
-      "PrimaryGroupSID": "PGSID",
+      "PrimaryGroupSID": null,
       "Aces": [],
       "AllowedToDelegate": [],
       "AllowedToAct": [],
@@ -211,6 +222,13 @@ pub fn prepare_domain_json_template() -> serde_json::value::Value
          "highvalue": true,
          "whencreated": -1,
          "functionallevel": "Unknown",
+         "forestfunctionallevel": null,
+         "pdcemulator": null,
+         "ridmaster": null,
+         "infrastructuremaster": null,
+         "schemamaster": null,
+         "domainnamingmaster": null,
+         "exchangeorganization": null,
       },
       // Todo
       "GPOChanges": {
@@ -261,6 +279,30 @@ pub fn prepare_container_json_template() -> serde_json::value::Value
    });
 }
 
+/// Return the json template for one fine-grained password policy (PSO), applied on users and groups by the checker
+/// and written as an OpenGraph node
+pub fn prepare_pso_json_template() -> serde_json::value::Value
+{
+   return json!({
+      "ObjectIdentifier": null,
+      "Properties": {
+         "name": "PSO",
+         "distinguishedname": "DN",
+         "precedence": null,
+         "minpwdlength": null,
+         "pwdhistorylength": null,
+         "pwdcomplexity": null,
+         "pwdreversibleencryption": null,
+         "maxpwdage": null,
+         "minpwdage": null,
+         "lockoutthreshold": null,
+         "lockoutduration": null,
+         "lockoutobservationwindow": null
+      },
+      "AppliesTo": [],
+   });
+}
+
 /// Return the json template for one member
 pub fn prepare_member_json_template() -> serde_json::value::Value
 {
@@ -360,6 +402,7 @@ pub fn prepare_trust_json_template() -> serde_json::value::Value
       "IsTransitive": null,
       "SidFilteringEnabled": null,
       "TrustDirection": 0,
-      "TrustType": 0
+      "TrustType": 0,
+      "SupportedEncryptionTypes": []
    });
 }
\ No newline at end of file
@@ -6,144 +6,892 @@
 //!
 //! Example in rust
 //!
+//! ```ignore
+//! let options = extract_args()?;
+//! let (entries, referrals) = ldap_search(&options, &Vec::new(), None).await?;
 //! ```
-//! let search = ldap_search(...)
-//! ```
-use crate::errors::{Result};
-use colored::Colorize;
-use ldap3::adapters::{Adapter, EntriesOnly};
-use ldap3::{adapters::PagedResults, controls::RawControl, LdapConnAsync, LdapConnSettings};
-use ldap3::{Scope, SearchEntry};
-use log::{debug, error, info};
-use std::process;
-use indicatif::ProgressBar;
+use crate::args::Options;
+use crate::errors::{Error, Kind, Result};
+use crate::banner::Colorize;
+use ldap3::adapters::{Adapter, EntriesOnly, PagedResults as PagedResultsAdapter};
+use ldap3::controls::{ControlType, PagedResults, RawControl};
+use ldap3::{LdapConnAsync, LdapConnSettings, LdapError};
+use ldap3::{parse_refs, Scope, SearchEntry, SearchResult};
+use log::{debug, error, info, trace, warn};
+use crate::banner::ProgressBar;
 use crate::banner::progress_bar;
+use crate::enums::extrights::add_live_extended_rights;
+use crate::enums::schema::{add_live_schema_guids, load_schema_cache, save_schema_cache, schema_cache_path};
+use crate::enums::sid::decode_guid;
+use crate::enums::attributes::{extended_attributes, extended_properties, negotiate_attributes, ACL_ONLY_ATTRIBUTES};
+use crate::enums::date::{parse_generalized_time, EXTENDED_TIME_ATTRIBUTES};
+use crate::enums::replmeta::{repl_metadata, REPL_METADATA_REQUEST};
+use std::collections::{HashMap, HashSet};
+use crate::modules::sampling::keep_sampled_entry;
+use crate::modules::targets::{targets_filters, related_filters, RELATED_DN_ATTRIBUTES};
+use crate::modules::control::{set_objects, stop_requested, set_expected_objects, expected_objects, progress_line};
+use crate::modules::metrics::add_ldap_retry;
+use crate::modules::socks::{start_socks_forwarder, SOCKS_BIND_PASSWORD};
+use crate::json::parser::ParsePipeline;
+use crate::secret::SecretString;
+use rand::Rng;
+use std::time::{Duration, Instant};
+
+#[cfg(not(any(feature = "rustls", feature = "native-tls")))]
+compile_error!("LDAPS needs a TLS backend, build with the rustls (default) or the native-tls feature");
 
-/// Function to request all AD values.
+/// Default page size of the LDAP paged searches (--page-size), under the MaxPageSize of 1000 of the DCs.
+pub const PAGE_SIZE: i32 = 999;
+/// Default page size and delay window between two LDAP requests in stealth mode.
+pub const STEALTH_PAGE_SIZE: i32 = 100;
+pub const STEALTH_JITTER_MS: (u64, u64) = (1000, 5000);
+/// SD flags of LDAP_SERVER_SD_FLAGS_OID: OWNER (1), GROUP (2), DACL (4).
+const SD_FLAGS_OWNER_GROUP_DACL: u8 = 7;
+const SD_FLAGS_DACL: u8 = 4;
+/// LDAP_SERVER_SHOW_DELETED_OID and LDAP_SERVER_SHOW_RECYCLED_OID (2008 R2 and later, ignored before).
+const SHOW_DELETED_OID: &str = "1.2.840.113556.1.4.417";
+const SHOW_RECYCLED_OID: &str = "1.2.840.113556.1.4.2064";
+/// Attributes of the deleted objects requested with --include-deleted, isDeleted is always requested.
+const DELETED_ATTRIBUTES: [&str; 3] = ["isRecycled", "lastKnownParent", "whenChanged"];
+/// LDAP result codes worth a reconnection: busy and unavailable.
+const RETRY_RESULT_CODES: [u32; 2] = [51, 52];
+/// Maximum delay between two reconnections.
+const RETRY_MAX_DELAY_S: u64 = 30;
+/// Delay between two progress lines in the logs during the collection.
+const PROGRESS_LOG_INTERVAL_S: u64 = 30;
+/// Maximum clock skew accepted by the KDC (Kerberos policy default, 5 minutes).
+const MAX_CLOCK_SKEW_S: i64 = 300;
+/// LDAP result code of a base DN held by another DC (LDAP_REFERRAL).
+const REFERRAL_RESULT_CODE: u32 = 10;
+/// Naming contexts followed at most with --follow-referrals, a loop of referrals stops there.
+pub const MAX_REFERRALS: usize = 16;
+
+/// Function to request all AD values with the connection and search options of the collection,
+/// with the naming contexts of the referrals to follow (--follow-referrals).
 pub async fn ldap_search(
-    ldaps: bool,
-    ip: &String,
-    port: &String,
-    domain: &String,
-    ldapfqdn: &String,
-    username: &String,
-    password: &String,
-) -> Result<Vec<SearchEntry>> {
+    common_args: &Options,
+    targets: &Vec<String>,
+    pipeline: Option<&ParsePipeline>,
+) -> Result<(Vec<SearchEntry>, Vec<Referral>)> {
+    let (ldaps, ip, port, domain, ldapfqdn) = (common_args.ldaps, &common_args.ip, &common_args.port, &common_args.domain, &common_args.ldapfqdn);
+    let (username, password, sspi, socks) = (&common_args.username, &common_args.password, common_args.sspi, &common_args.socks);
+    let (extended_rights, schema_guids, sample, stealth) = (common_args.extended_rights, common_args.schema_guids, common_args.sample, common_args.stealth);
+    let (ldap_filter, search_base, retries) = (&common_args.ldap_filter, &common_args.search_base, common_args.retries);
+    let (acl_only, include_deleted, follow_referrals) = (common_args.acl_only, common_args.include_deleted, common_args.follow_referrals);
     // 0- Construct LDAP args
-    let ldap_args = ldap_constructor(ldaps, ip, port, domain, ldapfqdn, username, password);
+    // Through the SOCKS proxy of a relay tool, the relay tool binds with the relayed session whatever the password
+    let relayed = !socks.contains("not set");
+    let password_set = password.is_some();
+    let password = match password {
+        Some(password) => password.to_owned(),
+        None if relayed => SecretString::new(SOCKS_BIND_PASSWORD),
+        None => SecretString::default(),
+    };
+    let mut ldap_args = ldap_constructor(ldaps, ip, port, domain, ldapfqdn, username, &password);
+    if relayed {
+        if username.contains("not set") {
+            error!("Need the username of the relayed session to bind through the SOCKS proxy. Please use '{}'\n", "-u DOMAIN/USER".bold());
+            return Err(Error::new(Kind::Other).desc("username of the relayed session needed"));
+        }
+        let host = if ip.contains("not set") { domain } else { ip };
+        let ldaps = ldaps || port == "636";
+        let dc_port = port.parse::<u16>().unwrap_or(if ldaps { 636 } else { 389 });
+        match start_socks_forwarder(socks, host, dc_port) {
+            Ok(local_port) => ldap_args.s_url = format!("{}://127.0.0.1:{}", if ldaps { "ldaps" } else { "ldap" }, local_port),
+            Err(err) => {
+                error!("Failed to start the SOCKS forwarder. Reason: {err}\n");
+                return Err(Error::new(Kind::Other).desc(format!("SOCKS forwarder not started: {err}")));
+            }
+        }
+    }
 
     // 1- LDAP connection
+    // SSPI is the GSSAPI bind on Windows, with the tickets of the current logon session
+    if sspi && !cfg!(windows) {
+        error!("SSPI authentication is only available on Windows builds, use a kerberos session (KRB5CCNAME) instead\n");
+        return Err(Error::new(Kind::Other).desc("SSPI authentication only available on Windows"));
+    }
+    if sspi && (password_set || !username.contains("not set")) {
+        warn!("Username and password ignored, binding with the current logon session (SSPI)");
+    }
+    let kerberos = !relayed && (sspi || (!password_set && username.contains("not set")));
+    if kerberos && !cfg!(feature = "gssapi") {
+        error!("Kerberos bind not available in this build, use a username and password or build with the '{}' feature\n", "gssapi".bold());
+        return Err(Error::new(Kind::Other).desc("kerberos bind not available in this build"));
+    }
+    if kerberos && ldapfqdn.contains("not set") {
+        error!("Need Domain Controler FQDN to bind GSSAPI connection. Please use '{}'\n", "-f DC01.DOMAIN.LAB".bold());
+        return Err(Error::new(Kind::Other).desc("domain controller FQDN needed for the kerberos bind"));
+    }
+    // The authenticators are built by the system GSSAPI library with the local clock, the skew can only be reported
+    let skew = if kerberos { ldap_clock_skew(&ldap_args.s_url).await } else { None };
+    if let Some(skew) = skew.filter(|skew| skew.abs() > MAX_CLOCK_SKEW_S) {
+        warn!("{}", clock_skew_hint(skew));
+    }
+    let mut ldap = match ldap_bind(&ldap_args.s_url, ldapfqdn, &ldap_args, kerberos).await {
+        Ok(ldap) => {
+            info!("Connected to {} Active Directory!", domain.to_uppercase().bold().green());
+            info!("Starting data collection...");
+            ldap
+        },
+        Err(err) => {
+            error!("Failed to authenticate to {} Active Directory. Reason: {err}\n", domain.to_uppercase().bold().red());
+            if let Some(skew) = skew.filter(|skew| skew.abs() > MAX_CLOCK_SKEW_S) {
+                error!("The kerberos bind probably failed on the clock skew. {}", clock_skew_hint(skew));
+            }
+            return Err(err);
+        }
+    };
+
+    // 2- Schema version, schema GUIDs and extended rights to know which attributes exist and resolve the ACEs
+    let (schema_nc, version) = match ldap_schema_version(&mut ldap).await {
+        Ok(schema) => schema,
+        Err(err) => {
+            error!("Failed to read the schema version, requesting all attributes. Reason: {err}");
+            (String::new(), String::new())
+        }
+    };
+    if schema_guids && !schema_nc.is_empty() {
+        // Stealth mode only uses the cache, CN=Schema search is too noisy
+        match ldap_schema_guids(&mut ldap, domain, &schema_nc, &version, stealth).await {
+            Ok(count) => info!("{} schema GUIDs loaded", count.to_string().bold()),
+            Err(err) => error!("Failed to collect schema GUIDs from CN=Schema, using the builtin table. Reason: {err}"),
+        }
+    }
+    // Request the controlAccessRight objects to name custom extended rights, before the collection:
+    // the parsing pipeline resolves the ACEs while the pages come
+    if extended_rights && !stealth {
+        match ldap_extended_rights(&mut ldap).await {
+            Ok(rights) => {
+                let custom = add_live_extended_rights(&rights);
+                info!("{} extended rights collected ({} custom)", rights.len().to_string().bold(), custom.to_string().bold());
+            },
+            Err(err) => error!("Failed to collect extended rights from CN=Extended-Rights. Reason: {err}"),
+        }
+    }
+    let attributes = requested_attributes(acl_only, include_deleted, &version);
+
+    // 3- Set control LDAP_SERVER_SD_FLAGS_OID to get nTSecurityDescriptor
+    // https://ldapwiki.com/wiki/LDAP_SERVER_SD_FLAGS_OID
+    // Owner, group and DACL, only the DACL in ACL-only mode: smaller descriptors but no Owns edges
+    let sd_flags = if acl_only { SD_FLAGS_DACL } else { SD_FLAGS_OWNER_GROUP_DACL };
+    let ctrls = RawControl {
+        ctype: String::from("1.2.840.113556.1.4.801"),
+        crit: true,
+        val: Some(vec![48,132,00,00,00,3,2,1,sd_flags]),
+    };
+
+    // The other DCs of the domain to fail over if this one drops the connection
+    let mut hosts = vec![(ldap_args.s_url.to_owned(), ldapfqdn.to_owned())];
+    if retries > 0 && !stealth && !relayed {
+        match ldap_domain_controllers(&mut ldap, &ldap_args.s_dc).await {
+            Ok(dcs) => hosts.extend(failover_hosts(&dcs, ip, ldapfqdn).into_iter()
+                .map(|dc| (prepare_ldap_url(ldaps, &dc, port, domain), dc))),
+            Err(err) => debug!("Failed to list the domain controllers for the fail-over. Reason: {err}"),
+        }
+        debug!("{} DCs available for the fail-over", hosts.len() - 1);
+    }
+    let mut session = LdapSession {
+        ldap,
+        args: ldap_args,
+        hosts,
+        current: 0,
+        kerberos,
+        retries,
+        sd_control: ctrls,
+        deleted_controls: if include_deleted { deleted_object_controls() } else { Vec::new() },
+        sent: 0,
+        referrals: Vec::new(),
+    };
+
+    // 4- Prepare filter and base DN
+    let s_filter = prepare_ldap_filter(ldap_filter);
+    let s_base = if search_base.contains("not set") { session.args.s_dc.to_owned() } else { search_base.to_owned() };
+    debug!("Filter: {}", &s_filter);
+    debug!("Search base: {}", &s_base);
+
+    // 5- Request LDAP
+    let mut rs: Vec<SearchEntry> = Vec::new();
+    let page_size = common_args.page_size;
+    if stealth {
+        info!("Stealth mode: pages of {} objects with {}-{}ms jitter", page_size, STEALTH_JITTER_MS.0, STEALTH_JITTER_MS.1);
+        jitter().await;
+    }
+    if targets.is_empty() {
+        // Approximate number of objects for the ETA, the stealth mode sends no extra request
+        if !stealth {
+            match ldap_count_entries(&mut session, &s_base, &s_filter).await {
+                Ok(expected) => {
+                    info!("About {} objects to collect", expected.to_string().bold());
+                    set_expected_objects(expected);
+                }
+                Err(err) => debug!("Failed to count the objects to collect. Reason: {err}"),
+            }
+        }
+        ldap_collect(&mut session, &s_base, &s_filter, attributes.to_owned(), page_size, sample, stealth, pipeline, &mut rs).await?;
+    } else {
+        // Only the targets, then their direct relationships anywhere in the domain
+        let mut attributes = attributes.to_owned();
+        for attribute in RELATED_DN_ATTRIBUTES {
+            if !attributes.contains(&attribute) {
+                attributes.push(attribute);
+            }
+        }
+        for filter in targets_filters(targets) {
+            if stop_requested() {
+                break;
+            }
+            ldap_collect(&mut session, &s_base, &format!("(&{}{})", &s_filter, filter), attributes.to_owned(), page_size, sample, stealth, None, &mut rs).await?;
+        }
+        let count = rs.len();
+        let s_dc = session.args.s_dc.to_owned();
+        for filter in related_filters(&rs) {
+            if stop_requested() {
+                break;
+            }
+            ldap_collect(&mut session, &s_dc, &filter, attributes.to_owned(), page_size, 100, stealth, None, &mut rs).await?;
+        }
+        let mut collected: HashSet<String> = HashSet::new();
+        rs.retain(|entry| collected.insert(entry.dn.to_uppercase()));
+        info!("{} targets and {} related objects collected", count.to_string().bold(), (rs.len() - count).to_string().bold());
+    }
+
+    // 5b- The child domains of a forest root search come as referrals, returned with --follow-referrals to be
+    // collected as their own domains by the caller
+    let follow = follow_referrals && targets.is_empty() && !relayed;
+    let mut referrals: Vec<Referral> = Vec::new();
+    for referral in referred_naming_contexts(&std::mem::take(&mut session.referrals)) {
+        if referral.base.eq_ignore_ascii_case(&s_base) {
+            continue
+        }
+        if follow {
+            referrals.push(referral);
+        } else {
+            warn!("{} not collected, referral to {}. Use {} or run rusthound on this domain", referral.base.bold(), referral.url, "--follow-referrals".bold());
+        }
+    }
+    if follow_referrals && relayed {
+        warn!("Referrals not followed through the SOCKS proxy, the relayed session is bound to one DC");
+    }
+
+    // 6- Request the FSMO role objects and the Exchange organizations of the configuration NC, parsed with the domain objects
+    if !acl_only && !stealth && !schema_nc.is_empty() {
+        match ldap_configuration_entries(&mut session.ldap, &schema_nc).await {
+            Ok(entries) => rs.extend(entries),
+            Err(err) => debug!("Failed to collect the forest FSMO roles. Reason: {err}"),
+        }
+    }
+
+    // 7- Terminate the connection to the server
+    session.ldap.unbind().await?;
+    
+    // 8- return the vector with the result
+    return Ok((rs, referrals));
+}
+
+/// Function to get the attributes to request for the schema version and the collection options.
+pub fn requested_attributes(acl_only: bool, include_deleted: bool, version: &String) -> Vec<&'static str> {
+    let attributes = if acl_only {
+        info!("ACL-only mode: {} attributes and the DACL requested", ACL_ONLY_ATTRIBUTES.len());
+        ACL_ONLY_ATTRIBUTES.to_vec()
+    } else {
+        let mut attributes = negotiate_attributes(version.parse::<u32>().unwrap_or(u32::MAX));
+        if extended_properties() {
+            attributes.extend(extended_attributes());
+            attributes.extend(EXTENDED_TIME_ATTRIBUTES.iter().map(|(attribute, _)| *attribute));
+        }
+        if repl_metadata() {
+            attributes.extend(REPL_METADATA_REQUEST);
+        }
+        attributes
+    };
+    let attributes = if include_deleted {
+        info!("Tombstoned and recycled objects included");
+        attributes.iter().copied().chain(DELETED_ATTRIBUTES.iter().copied().filter(|name| !attributes.contains(name))).collect()
+    } else {
+        attributes
+    };
+    debug!("Schema version {}: {} attributes requested", version, attributes.len());
+    attributes
+}
+
+/// Function to open one LDAP connection and bind it with the credentials or the kerberos session.
+async fn ldap_bind(url: &str, ldapfqdn: &String, ldap_args: &LdapArgs, kerberos: bool) -> Result<ldap3::Ldap> {
     let consettings = LdapConnSettings::new().set_no_tls_verify(true);
-    let (conn, mut ldap) = LdapConnAsync::with_settings(consettings, &ldap_args.s_url).await?;
+    let (conn, mut ldap) = LdapConnAsync::with_settings(consettings, url).await?;
     ldap3::drive!(conn);
 
-
-    if !&password.contains("not set") || !&username.contains("not set") {
+    if !kerberos {
         debug!("Trying to connect with simple_bind() function (username:password)");
-        let res = ldap.simple_bind(&ldap_args.s_username, &ldap_args.s_password).await?.success();
-        match res {
-            Ok(_res) => {
-                info!("Connected to {} Active Directory!", domain.to_uppercase().bold().green());
-                info!("Starting data collection...");
-            },
-            Err(err) => {
-                error!("Failed to authenticate to {} Active Directory. Reason: {err}\n", domain.to_uppercase().bold().red());
-                process::exit(0x0100);
+        ldap.simple_bind(&ldap_args.s_username, ldap_args.s_password.expose()).await?.success()?;
+    } else {
+        // cross-krb5 negotiates with SSPI on Windows and with the GSSAPI credential cache elsewhere
+        #[cfg(feature = "gssapi")]
+        {
+            debug!("Trying to connect with sasl_gssapi_bind() function (kerberos session)");
+            ldap.sasl_gssapi_bind(ldapfqdn).await?.success()?;
+        }
+        #[cfg(not(feature = "gssapi"))]
+        return Err(Error::new(Kind::Other).desc(format!("Kerberos bind to {} not available, built without the gssapi feature", ldapfqdn)));
+    }
+    Ok(ldap)
+}
+
+/// Function to get the clock skew with the DC in seconds (DC time - local time), from the rootDSE currentTime
+/// read with an anonymous bind. None when the DC time can not be read.
+async fn ldap_clock_skew(url: &str) -> Option<i64> {
+    let consettings = LdapConnSettings::new().set_no_tls_verify(true);
+    let (conn, mut ldap) = LdapConnAsync::with_settings(consettings, url).await.ok()?;
+    ldap3::drive!(conn);
+    let current_time = ldap_base_attribute(&mut ldap, "", "currentTime").await;
+    let _ = ldap.unbind().await;
+    let dc_time = parse_generalized_time(&current_time.ok()?)?;
+    let skew = dc_time.timestamp() - chrono::Utc::now().timestamp();
+    debug!("Clock skew with the DC: {}s", skew);
+    Some(skew)
+}
+
+/// Function to explain how to fix a clock skew greater than the KDC tolerance.
+pub fn clock_skew_hint(skew: i64) -> String {
+    let direction = if skew > 0 { "behind" } else { "ahead of" };
+    format!(
+        "Local clock is {}s {} the DC, kerberos tolerates {}s. Sync the clock (ntpdate/rdate with the DC) or run rusthound with faketime \"{}{} seconds\"",
+        skew.abs(), direction, MAX_CLOCK_SKEW_S, if skew > 0 { "+" } else { "-" }, skew.abs()
+    )
+}
+
+/// LDAP connection able to bind again and fail over to another DC when the DC drops it.
+struct LdapSession {
+    ldap: ldap3::Ldap,
+    args: LdapArgs,
+    /// (url, fqdn) of the DCs, the first one is the DC of the command line
+    hosts: Vec<(String, String)>,
+    current: usize,
+    kerberos: bool,
+    retries: u8,
+    sd_control: RawControl,
+    /// Show Deleted and Show Recycled controls with --include-deleted
+    deleted_controls: Vec<RawControl>,
+    /// Entries sent to the parsing pipeline instead of rs
+    sent: u64,
+    /// LDAP URLs of the referrals returned by the searches
+    referrals: Vec<String>,
+}
+
+impl LdapSession {
+    /// Function to request one page of a paged search, resumed with the cookie of the previous page.
+    async fn search_page(
+        &mut self,
+        base: &str,
+        filter: &str,
+        attributes: &Vec<&'static str>,
+        page_size: i32,
+        cookie: &Vec<u8>,
+    ) -> std::result::Result<SearchResult, LdapError> {
+        let paged: RawControl = PagedResults { size: page_size, cookie: cookie.to_owned() }.into();
+        let mut controls = vec![self.sd_control.to_owned(), paged];
+        controls.extend(self.deleted_controls.iter().cloned());
+        self.ldap
+            .with_controls(controls)
+            .search(base, Scope::Subtree, filter, attributes.to_owned())
+            .await
+    }
+
+    /// Function to get the values of the ranged attributes of an entry (member;range=0-1499 above MaxValRange):
+    /// the next ranges are requested on the object until the last one (member;range=1500-*).
+    async fn complete_ranged_attributes(&mut self, entry: &mut SearchEntry) -> Result<()> {
+        let ranged: Vec<String> = entry.attrs.keys().filter(|name| parse_range(name).is_some()).cloned().collect();
+        for name in ranged {
+            let (attribute, _, mut end) = match parse_range(&name) {
+                Some(range) => range,
+                None => continue,
+            };
+            let mut values = entry.attrs.remove(&name).unwrap_or_default();
+            // The values collected so far are kept if a range request fails
+            let mut result: std::result::Result<(), LdapError> = Ok(());
+            while let Some(last) = end {
+                let request = format!("{};range={}-*", attribute, last + 1);
+                let next = match self.ldap.search(&entry.dn, Scope::Base, "(objectClass=*)", vec![request.as_str()]).await.and_then(|res| res.success()) {
+                    Ok((rs, _res)) => rs.into_iter().next().map(SearchEntry::construct),
+                    Err(err) => {
+                        result = Err(err);
+                        break
+                    }
+                };
+                let (next_name, next_values) = match next.and_then(|next| next.attrs.into_iter().find(|(name, _)| {
+                    parse_range(name).map(|(name, _, _)| name.eq_ignore_ascii_case(&attribute)).unwrap_or(false)
+                })) {
+                    Some(next) => next,
+                    None => break,
+                };
+                end = parse_range(&next_name).and_then(|(_, _, end)| end);
+                values.extend(next_values);
             }
+            debug!("{}: {} values of {}", entry.dn, values.len(), attribute);
+            entry.attrs.entry(attribute).or_default().extend(values);
+            result?;
         }
+        Ok(())
     }
-    else
-    {
-        debug!("Trying to connect with sasl_gssapi_bind() function (kerberos session)");
-        if !&ldapfqdn.contains("not set"){
-            let res = ldap.sasl_gssapi_bind(ldapfqdn).await?.success();
-            match res {
-                Ok(_res) => {
-                    info!("Connected to {} Active Directory!", domain.to_uppercase().bold().green());
-                    info!("Starting data collection...");
-                },
+
+    /// Function to bind a new connection after the attempt-th failure: the same DC first, then the other DCs.
+    /// Return true if the connection is on the same DC, the paged search cookie can be used again.
+    async fn reconnect(&mut self, attempt: u8) -> Result<bool> {
+        let delay = retry_delay(attempt);
+        debug!("Waiting {}s before the reconnection", delay.as_secs());
+        tokio::time::sleep(delay).await;
+        let mut last_error = None;
+        for offset in 0..self.hosts.len() {
+            let index = (self.current + offset) % self.hosts.len();
+            let (url, fqdn) = &self.hosts[index];
+            match ldap_bind(url, fqdn, &self.args, self.kerberos).await {
+                Ok(ldap) => {
+                    info!("Reconnected to {}", url.bold());
+                    self.ldap = ldap;
+                    let same = index == self.current;
+                    self.current = index;
+                    return Ok(same)
+                }
                 Err(err) => {
-                    error!("Failed to authenticate to {} Active Directory. Reason: {err}\n", domain.to_uppercase().bold().red());
-                    process::exit(0x0100);
+                    warn!("Failed to reconnect to {}. Reason: {err}", url);
+                    last_error = Some(err);
                 }
             }
         }
-        else
-        {
-            error!("Need Domain Controler FQDN to bind GSSAPI connection. Please use '{}'\n", "-f DC01.DOMAIN.LAB".bold());
-            process::exit(0x0100);
+        Err(last_error.unwrap_or_else(Error::new_host))
+    }
+}
+
+/// Function to get the controls returning the tombstoned and recycled objects, not critical for the DCs without
+/// the recycle bin.
+pub fn deleted_object_controls() -> Vec<RawControl> {
+    [SHOW_DELETED_OID, SHOW_RECYCLED_OID].iter()
+        .map(|oid| RawControl { ctype: oid.to_string(), crit: false, val: None })
+        .collect()
+}
+
+/// Function to get the delay before the attempt-th reconnection: 2, 4, 8... seconds.
+pub fn retry_delay(attempt: u8) -> Duration {
+    Duration::from_secs(RETRY_MAX_DELAY_S.min(1 << attempt.min(8)))
+}
+
+/// Function to keep the DCs to fail over to, without the DC of the command line.
+pub fn failover_hosts(dcs: &Vec<String>, ip: &String, ldapfqdn: &String) -> Vec<String> {
+    let mut hosts: Vec<String> = Vec::new();
+    for dc in dcs {
+        let dc = dc.to_lowercase();
+        if dc.eq_ignore_ascii_case(ip) || dc.eq_ignore_ascii_case(ldapfqdn) || hosts.contains(&dc) {
+            continue
         }
+        hosts.push(dc);
     }
+    hosts
+}
 
-    // 2- Set control LDAP_SERVER_SD_FLAGS_OID to get nTSecurityDescriptor
-    // https://ldapwiki.com/wiki/LDAP_SERVER_SD_FLAGS_OID
-    let ctrls = RawControl {
-        ctype: String::from("1.2.840.113556.1.4.801"),
-        crit: true,
-        // flag to 7 or 5?
-        val: Some(vec![48,132,00,00,00,3,2,1,7]),
+/// Function to run one paged search and push the entries in rs, or send them page by page to the parsing pipeline.
+/// A dropped connection is bound again and the search resumed with the last cookie,
+/// on another DC the search starts again and the objects already collected are skipped.
+async fn ldap_collect(
+    session: &mut LdapSession,
+    base: &str,
+    filter: &str,
+    attributes: Vec<&'static str>,
+    page_size: i32,
+    sample: u8,
+    stealth: bool,
+    pipeline: Option<&ParsePipeline>,
+    rs: &mut Vec<SearchEntry>,
+) -> Result<()> {
+    // every 999 max value in ldap response (err 4 ldap)
+    // Without the presence of the SD flags control, the server returns an SD only when the SD attribute name is explicitly mentioned in the requested attribute list.
+    // https://docs.microsoft.com/en-us/openspecs/windows_protocols/ms-adts/932a7a8d-8c93-4448-8093-c79b7d9ba499
+	let pb = ProgressBar::new(1);
+	let mut count = 0;	
+	let mut skipped = 0;
+    let mut cookie: Vec<u8> = Vec::new();
+    let mut attempts: u8 = 0;
+    let mut resumed = false;
+    // DNs returned by this search, skipped if the search starts again
+    let mut seen: HashSet<String> = HashSet::new();
+    let started = Instant::now();
+    let mut last_log = Instant::now();
+    loop {
+        let res = match session.search_page(base, filter, &attributes, page_size, &cookie).await {
+            Ok(result) if result.1.rc == 0 => Ok(result),
+            Ok(result) if result.1.rc == REFERRAL_RESULT_CODE => {
+                // The base DN is not held by this DC
+                warn!("{} is not on this DC, referral to {}", base, result.1.refs.join(", "));
+                session.referrals.extend(result.1.refs);
+                break
+            }
+            Ok(result) if resumed && !cookie.is_empty() && !RETRY_RESULT_CODES.contains(&result.1.rc) => {
+                // The DC doesn't know the cookie on the new connection
+                warn!("Paged search cookie refused ({}), starting the search again, {} objects already collected", result.1, seen.len().to_string().bold());
+                cookie.clear();
+                resumed = false;
+                continue
+            }
+            Ok(result) if !RETRY_RESULT_CODES.contains(&result.1.rc) => {
+                error!("No data collected! Reason: {}", result.1);
+                return Err(Error::new(Kind::LdapError).desc(format!("no data collected: {}", result.1)));
+            }
+            Ok(result) => Err(result.1.to_string()),
+            Err(err) => Err(err.to_string()),
+        };
+        let SearchResult(entries, result) = match res {
+            Ok(result) => result,
+            Err(reason) => {
+                attempts += 1;
+                add_ldap_retry();
+                if attempts > session.retries {
+                    error!("No data collected! Reason: {reason}");
+                    return Err(Error::new(Kind::LdapError).desc(format!("no data collected: {reason}")));
+                }
+                warn!("LDAP search interrupted after {} objects, reconnection {}/{}. Reason: {reason}", count, attempts, session.retries);
+                match session.reconnect(attempts).await {
+                    Ok(true) => resumed = true,
+                    Ok(false) => {
+                        cookie.clear();
+                        resumed = false;
+                    }
+                    Err(err) => debug!("No DC available. Reason: {err}"),
+                }
+                continue
+            }
+        };
+
+        let page_start = rs.len();
+        for entry in entries {
+            // Search continuation references to the naming contexts below the base, the child domains
+            if entry.is_ref() {
+                session.referrals.extend(parse_refs(entry.0));
+                continue;
+            }
+            if entry.is_intermediate() {
+                continue;
+            }
+            let mut entry = SearchEntry::construct(entry);
+            if !seen.insert(entry.dn.to_uppercase()) {
+                continue;
+            }
+            // Attributes above MaxValRange (1500 values, 5000 on some DCs) come by ranges
+            if entry.attrs.keys().any(|name| parse_range(name).is_some()) {
+                if let Err(err) = session.complete_ranged_attributes(&mut entry).await {
+                    warn!("Values of {} incomplete, range retrieval failed. Reason: {err}", entry.dn);
+                }
+            }
+            //trace!("{:?}", &entry);
+            // Manage progress bar
+            count += 1;
+            progress_bar(pb.to_owned(),"LDAP objects retreived".to_string(),count,"#".to_string());
+            set_objects(session.sent + rs.len() as u64 + 1);
+            // Graceful stop from the control socket, keep what is collected
+            if stop_requested() {
+                break;
+            }
+            // Drop regular users and computers out of the sample
+            if !keep_sampled_entry(&entry, sample) {
+                skipped += 1;
+                continue;
+            }
+            // Push all result in rs vec()
+            rs.push(entry);
+        }
+        if let Some(pipeline) = pipeline {
+            session.sent += (rs.len() - page_start) as u64;
+            pipeline.send(rs.split_off(page_start)).await;
+        }
+        // Progress in the logs for the long collections
+        if last_log.elapsed() >= Duration::from_secs(PROGRESS_LOG_INTERVAL_S) {
+            info!("{}", progress_line(count, expected_objects(), started.elapsed()));
+            last_log = Instant::now();
+        }
+
+        cookie = result.ctrls.iter()
+            .find(|ctrl| matches!(ctrl.0, Some(ControlType::PagedResults)))
+            .map(|ctrl| ctrl.1.parse::<PagedResults>().cookie)
+            .unwrap_or_default();
+        if cookie.is_empty() {
+            break;
+        }
+        if stop_requested() {
+            // Abandon the running paged search with a page of 0 objects, the server drops the result set
+            let _ = session.search_page(base, filter, &attributes, 0, &cookie).await;
+            break;
+        }
+        // The next page is requested when this one is consumed, wait before
+        if stealth {
+            jitter().await;
+        }
+    }
+	pb.finish_and_clear();
+    if sample < 100 {
+        info!("Sampling {}% of regular users and computers: {} objects skipped", sample.to_string().bold(), skipped.to_string().bold());
+    }
+
+    if stop_requested() {
+        info!("Collection stopped, {} objects kept", (session.sent + rs.len() as u64).to_string().bold());
+    } else {
+        info!("All data collected!");
+    }
+    Ok(())
+}
+
+/// Naming context of a referral, with the URL and the host of its DC.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Referral {
+    /// ldap://host[:port]
+    pub url: String,
+    pub host: String,
+    /// DN of the naming context
+    pub base: String,
+    /// DNS name of the naming context: DC=child,DC=essos,DC=local is child.essos.local
+    pub domain: String,
+}
+
+/// Function to parse a referral URL: "ldap://child.essos.local/DC=child,DC=essos,DC=local" is the naming context
+/// DC=child,DC=essos,DC=local on child.essos.local. The DN is percent-decoded, the scope and filter dropped.
+pub fn parse_referral(url: &str) -> Option<Referral> {
+    let (scheme, rest) = url.split_once("://")?;
+    if !scheme.eq_ignore_ascii_case("ldap") && !scheme.eq_ignore_ascii_case("ldaps") {
+        return None
+    }
+    let (authority, dn) = rest.split_once('/')?;
+    let dn = dn.split('?').next().unwrap_or("");
+    let bytes = dn.as_bytes();
+    let mut decoded: Vec<u8> = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        match (bytes[i], dn.get(i + 1..i + 3).and_then(|hex| u8::from_str_radix(hex, 16).ok())) {
+            (b'%', Some(byte)) => {
+                decoded.push(byte);
+                i += 3;
+            }
+            (byte, _) => {
+                decoded.push(byte);
+                i += 1;
+            }
+        }
+    }
+    let base = String::from_utf8(decoded).ok()?;
+    let host = authority.split(':').next().unwrap_or("");
+    if host.is_empty() || base.is_empty() {
+        return None
+    }
+    let domain = base.split(',')
+        .filter_map(|rdn| rdn.trim().split_once('='))
+        .filter(|(name, _)| name.eq_ignore_ascii_case("DC"))
+        .map(|(_, value)| value.to_lowercase())
+        .collect::<Vec<String>>()
+        .join(".");
+    Some(Referral { url: format!("{}://{}", scheme.to_lowercase(), authority), host: host.to_string(), base, domain })
+}
+
+/// Function to keep the referrals to the domain naming contexts, once each. The DNS application partitions
+/// (DomainDnsZones, ForestDnsZones) and the configuration are not domains.
+pub fn referred_naming_contexts(referrals: &[String]) -> Vec<Referral> {
+    let mut contexts: Vec<Referral> = Vec::new();
+    for referral in referrals.iter().filter_map(|url| parse_referral(url)) {
+        let first = referral.base.split(',').next().unwrap_or("").trim().to_uppercase();
+        if !first.starts_with("DC=") || first == "DC=DOMAINDNSZONES" || first == "DC=FORESTDNSZONES" {
+            debug!("Referral to {} ignored", referral.base);
+            continue
+        }
+        if !contexts.iter().any(|context| context.base.eq_ignore_ascii_case(&referral.base)) {
+            contexts.push(referral);
+        }
+    }
+    contexts
+}
+
+/// Function to split a ranged attribute name: "member;range=0-1499" is ("member", 0, Some(1499)),
+/// the last range "member;range=1500-*" is ("member", 1500, None).
+pub fn parse_range(name: &str) -> Option<(String, usize, Option<usize>)> {
+    let (attribute, range) = name.split_once(";range=")?;
+    let (start, end) = range.split_once('-')?;
+    let start = start.parse::<usize>().ok()?;
+    let end = if end == "*" { None } else { Some(end.parse::<usize>().ok()?) };
+    Some((attribute.to_string(), start, end))
+}
+
+/// Function to count the entries of a search, without attribute ("1.1"): the DNs only, for the ETA.
+async fn ldap_count_entries(session: &mut LdapSession, base: &str, filter: &str) -> Result<u64> {
+    let attributes = vec!["1.1"];
+    let mut cookie: Vec<u8> = Vec::new();
+    let mut count: u64 = 0;
+    loop {
+        let SearchResult(entries, result) = session.search_page(base, filter, &attributes, PAGE_SIZE, &cookie).await?;
+        let result = result.success()?;
+        count += entries.iter().filter(|entry| !entry.is_ref() && !entry.is_intermediate()).count() as u64;
+        cookie = result.ctrls.iter()
+            .find(|ctrl| matches!(ctrl.0, Some(ControlType::PagedResults)))
+            .map(|ctrl| ctrl.1.parse::<PagedResults>().cookie)
+            .unwrap_or_default();
+        if cookie.is_empty() || stop_requested() {
+            return Ok(count)
+        }
+    }
+}
+
+/// Function to read one attribute from the base object of a DN ("" for rootDSE).
+async fn ldap_base_attribute(ldap: &mut ldap3::Ldap, base: &str, attribute: &str) -> Result<String> {
+    let (rs, _res) = ldap.search(
+        base,
+        Scope::Base,
+        "(objectClass=*)",
+        vec![attribute],
+    ).await?.success()?;
+    let value = match rs.into_iter().next() {
+        Some(entry) => {
+            let entry = SearchEntry::construct(entry);
+            entry.attrs.get(attribute).map(|v| v[0].to_owned()).unwrap_or_default()
+        },
+        None => String::new(),
     };
-    ldap.with_controls(ctrls.to_owned());
+    debug!("{}: {}", attribute, &value);
+    Ok(value)
+}
 
-    // 3- Prepare filter
-    let s_filter: &str = "(objectClass=*)";
+/// Function to request the forest FSMO role objects: the schema NC head (schema master) and
+/// CN=Partitions,CN=Configuration (domain naming master and forest functional level),
+/// then the Exchange organizations of CN=Microsoft Exchange,CN=Services,CN=Configuration and their servers,
+/// and the device registration service connection point of the Azure AD tenant.
+async fn ldap_configuration_entries(ldap: &mut ldap3::Ldap, schema_nc: &String) -> Result<Vec<SearchEntry>> {
+    let config_nc = ldap_base_attribute(ldap, "", "configurationNamingContext").await?;
+    let mut entries: Vec<SearchEntry> = Vec::new();
+    for base in [schema_nc.to_owned(), format!("CN=Partitions,{}", config_nc)] {
+        let (rs, _res) = ldap.search(
+            &base,
+            Scope::Base,
+            "(objectClass=*)",
+            vec!["objectClass", "distinguishedName", "fSMORoleOwner", "msDS-Behavior-Version"],
+        ).await?.success()?;
+        entries.extend(rs.into_iter().map(SearchEntry::construct));
+    }
+    // No such object (32) without Exchange
+    let SearchResult(rs, res) = ldap.search(
+        &format!("CN=Microsoft Exchange,CN=Services,{}", config_nc),
+        Scope::OneLevel,
+        "(objectClass=msExchOrganizationContainer)",
+        vec!["objectClass", "distinguishedName", "name"],
+    ).await?;
+    if res.rc == 0 && !rs.is_empty() {
+        entries.extend(rs.into_iter().map(SearchEntry::construct));
+        let (rs, _res) = ldap.search(
+            &format!("CN=Microsoft Exchange,CN=Services,{}", config_nc),
+            Scope::Subtree,
+            "(objectClass=msExchExchangeServer)",
+            vec!["objectClass", "distinguishedName", "name", "networkAddress", "serialNumber", "msExchCurrentServerRoles"],
+        ).await?.success()?;
+        entries.extend(rs.into_iter().map(SearchEntry::construct));
+    }
+    // No such object (32) without hybrid joined devices
+    let SearchResult(rs, res) = ldap.search(
+        &format!("CN=Device Registration Configuration,CN=Services,{}", config_nc),
+        Scope::OneLevel,
+        "(objectClass=serviceConnectionPoint)",
+        vec!["objectClass", "distinguishedName", "keywords"],
+    ).await?;
+    if res.rc == 0 {
+        entries.extend(rs.into_iter().map(SearchEntry::construct));
+    }
+    Ok(entries)
+}
+
+/// Function to request the controlAccessRight objects from CN=Extended-Rights,CN=Configuration.
+async fn ldap_extended_rights(ldap: &mut ldap3::Ldap) -> Result<Vec<SearchEntry>> {
+    let config_nc = ldap_base_attribute(ldap, "", "configurationNamingContext").await?;
+
+    let (rs, _res) = ldap.search(
+        &format!("CN=Extended-Rights,{}", config_nc),
+        Scope::OneLevel,
+        "(objectClass=controlAccessRight)",
+        vec!["cn", "displayName", "rightsGuid"],
+    ).await?.success()?;
+    Ok(rs.into_iter().map(SearchEntry::construct).collect())
+}
+
+/// Function to get the schema naming context and its objectVersion.
+async fn ldap_schema_version(ldap: &mut ldap3::Ldap) -> Result<(String, String)> {
+    let schema_nc = ldap_base_attribute(ldap, "", "schemaNamingContext").await?;
+    let version = ldap_base_attribute(ldap, &schema_nc, "objectVersion").await?;
+    Ok((schema_nc, version))
+}
+
+/// Function to build the schema map (cn => schemaIDGUID) from CN=Schema,CN=Configuration.
+/// The map is cached on disk keyed by the schema objectVersion. Return the number of GUIDs loaded.
+async fn ldap_schema_guids(ldap: &mut ldap3::Ldap, domain: &String, schema_nc: &String, version: &String, cache_only: bool) -> Result<usize> {
+    let cache = schema_cache_path(domain, version);
+    if let Some(guids) = load_schema_cache(&cache) {
+        debug!("Schema GUIDs loaded from cache {}", &cache);
+        let count = guids.len();
+        add_live_schema_guids(guids);
+        return Ok(count)
+    }
+    if cache_only {
+        debug!("No schema cache {}, using the builtin table", &cache);
+        return Ok(0)
+    }
 
-    // 4- Request LDAP
-    let mut rs: Vec<SearchEntry> = Vec::new();
-    // every 999 max value in ldap response (err 4 ldap)
     let adapters: Vec<Box<dyn Adapter<_,_>>> = vec![
         Box::new(EntriesOnly::new()),
-        Box::new(PagedResults::new(999)),
+        Box::new(PagedResultsAdapter::new(PAGE_SIZE)),
     ];
-
-    // Streaming search with adaptaters and filters
     let mut search = ldap.streaming_search_with(
-        adapters, // Adapter which fetches Search results with a Paged Results control.
-        &ldap_args.s_dc, 
-        Scope::Subtree,
-        s_filter,
-        vec!["*", "nTSecurityDescriptor"], 
-        // Without the presence of this control, the server returns an SD only when the SD attribute name is explicitly mentioned in the requested attribute list.
-        // https://docs.microsoft.com/en-us/openspecs/windows_protocols/ms-adts/932a7a8d-8c93-4448-8093-c79b7d9ba499
+        adapters,
+        schema_nc,
+        Scope::OneLevel,
+        "(|(objectClass=attributeSchema)(objectClass=classSchema))",
+        vec!["cn", "schemaIDGUID"],
     ).await?;
-
-    // Wait and get next values
-	let pb = ProgressBar::new(1);
-	let mut count = 0;	
+    let mut guids: HashMap<String, String> = HashMap::new();
     while let Some(entry) = search.next().await? {
         let entry = SearchEntry::construct(entry);
-		//trace!("{:?}", &entry);
-		// Manage progress bar
-		count += 1;
-		progress_bar(pb.to_owned(),"LDAP objects retreived".to_string(),count,"#".to_string());	
-        // Push all result in rs vec()
-        rs.push(entry);
-    }
-	pb.finish_and_clear();
-
-    let res = search.finish().await.success();
-    match res {
-        Ok(_res) => info!("All data collected!"),
-        Err(err) => {
-            error!("No data collected! Reason: {err}");
-            process::exit(0x0100);
+        let name = match entry.attrs.get("cn") {
+            Some(name) => name[0].to_lowercase(),
+            None => continue,
+        };
+        if let Some(guid) = entry.bin_attrs.get("schemaIDGUID") {
+            guids.insert(name, decode_guid(&guid[0]).to_lowercase());
         }
     }
+    search.finish().await.success()?;
 
-    // 5- Terminate the connection to the server
-    ldap.unbind().await?;
-    
-    // 6- return the vector with the result
-    return Ok(rs);
+    save_schema_cache(&cache, &guids);
+    let count = guids.len();
+    add_live_schema_guids(guids);
+    Ok(count)
+}
+
+/// Function to list the dNSHostName of the domain controllers (SERVER_TRUST_ACCOUNT).
+async fn ldap_domain_controllers(ldap: &mut ldap3::Ldap, base: &str) -> Result<Vec<String>> {
+    let (rs, _res) = ldap.search(
+        base,
+        Scope::Subtree,
+        "(&(objectCategory=computer)(userAccountControl:1.2.840.113556.1.4.803:=8192))",
+        vec!["dNSHostName"],
+    ).await?.success()?;
+    Ok(rs.into_iter()
+        .map(SearchEntry::construct)
+        .filter_map(|entry| entry.attrs.get("dNSHostName").and_then(|values| values.first().cloned()))
+        .collect())
+}
+
+/// Function to wait a random delay in the stealth jitter window.
+pub async fn jitter() {
+    let delay = rand::thread_rng().gen_range(STEALTH_JITTER_MS.0..=STEALTH_JITTER_MS.1);
+    trace!("Stealth mode: waiting {}ms", delay);
+    tokio::time::sleep(Duration::from_millis(delay)).await;
 }
 
 /// Structure containing the LDAP connection arguments.
+#[derive(Clone)]
 struct LdapArgs {
     s_url: String,
     s_dc: String,
     _s_email: String,
     s_username: String,
-    s_password: String,
+    s_password: SecretString,
 }
 
 /// Function to prepare LDAP arguments.
@@ -154,7 +902,7 @@ fn ldap_constructor(
     domain: &String,
     ldapfqdn: &String,
     username: &String,
-    password: &String,
+    password: &SecretString,
 ) -> LdapArgs {
     // Prepare ldap url
     let s_url = prepare_ldap_url(ldaps, ip, port, domain);
@@ -162,9 +910,8 @@ fn ldap_constructor(
     // Prepare full DC chain
     let s_dc = prepare_ldap_dc(domain);
 
-    // Format username and password in str
+    // Format username in str, the password stays wrapped to never be logged
     let s_username: &str = &username[..];
-    let s_password: &str = &password[..];
 
     // Format email
     let mut _s_email: String = "".to_owned();
@@ -184,7 +931,7 @@ fn ldap_constructor(
     debug!("Domain: {}", domain);
     debug!("Username: {}", s_username);
     debug!("Email: {}", _s_email.to_lowercase());
-    debug!("Password: {}", s_password);
+    debug!("Password: {}", password);
     debug!("DC: {}", s_dc);
 
     LdapArgs {
@@ -192,7 +939,7 @@ fn ldap_constructor(
         s_dc: s_dc.to_string(),
         _s_email: _s_email.to_string().to_lowercase(),
         s_username: s_username.to_string(),
-        s_password: s_password.to_string(),
+        s_password: password.to_owned(),
     }
 }
 
@@ -231,6 +978,20 @@ fn prepare_ldap_url(ldaps: bool, ip: &String, port: &String, domain: &String) ->
     }
 }
 
+/// Function to AND the --ldap-filter value with the default filter.
+pub fn prepare_ldap_filter(ldap_filter: &String) -> String {
+    let default_filter = "(objectClass=*)";
+    if ldap_filter.contains("not set") || ldap_filter.trim().is_empty() {
+        return default_filter.to_string()
+    }
+    let filter = ldap_filter.trim();
+    if filter.starts_with('(') {
+        format!("(&{}{})", default_filter, filter)
+    } else {
+        format!("(&{}({}))", default_filter, filter)
+    }
+}
+
 /// Function to prepare LDAP DC from DOMAIN.LOCAL
 pub fn prepare_ldap_dc(domain: &String) -> String {
     let mut dc: String = "".to_owned();
@@ -262,3 +1023,58 @@ pub fn prepare_ldap_dc(domain: &String) -> String {
         return dc[..].to_string();
     }
 }
+
+/// Test functions
+#[test]
+pub fn test_failover() {
+    assert_eq!(retry_delay(1), Duration::from_secs(2));
+    assert_eq!(retry_delay(3), Duration::from_secs(8));
+    assert_eq!(retry_delay(10), Duration::from_secs(RETRY_MAX_DELAY_S));
+    let dcs = vec!["DC01.essos.local".to_string(), "dc02.essos.local".to_string(), "DC02.ESSOS.LOCAL".to_string()];
+    assert_eq!(failover_hosts(&dcs, &"not set".to_string(), &"dc01.essos.local".to_string()), vec!["dc02.essos.local"]);
+}
+
+#[test]
+pub fn test_parse_range() {
+    assert_eq!(parse_range("member;range=0-1499"), Some(("member".to_string(), 0, Some(1499))));
+    assert_eq!(parse_range("member;range=1500-*"), Some(("member".to_string(), 1500, None)));
+    assert_eq!(parse_range("msDS-RevealedUsers;range=0-4999"), Some(("msDS-RevealedUsers".to_string(), 0, Some(4999))));
+    assert_eq!(parse_range("member"), None);
+    assert_eq!(parse_range("member;range=a-*"), None);
+}
+
+#[test]
+pub fn test_clock_skew() {
+    let time = parse_generalized_time("20261016123456.0Z").unwrap();
+    assert_eq!(time.to_rfc3339(), "2026-10-16T12:34:56+00:00");
+    assert_eq!(parse_generalized_time("20261016123456Z").unwrap(), time);
+    assert!(parse_generalized_time("not a time").is_none());
+    assert!(clock_skew_hint(3600).contains("3600s behind the DC"));
+    assert!(clock_skew_hint(-600).contains("faketime \"-600 seconds\""));
+}
+
+#[test]
+pub fn test_referrals() {
+    let referral = parse_referral("ldap://child.essos.local/DC=child,DC=essos,DC=local").unwrap();
+    assert_eq!(referral.url, "ldap://child.essos.local");
+    assert_eq!(referral.host, "child.essos.local");
+    assert_eq!(referral.base, "DC=child,DC=essos,DC=local");
+    assert_eq!(referral.domain, "child.essos.local");
+    let referral = parse_referral("LDAP://dc01.child.essos.local:389/OU=Dothraki%20Sea,DC=child,DC=essos,DC=local??sub").unwrap();
+    assert_eq!(referral.url, "ldap://dc01.child.essos.local:389");
+    assert_eq!(referral.host, "dc01.child.essos.local");
+    assert_eq!(referral.base, "OU=Dothraki Sea,DC=child,DC=essos,DC=local");
+    assert!(parse_referral("https://child.essos.local/DC=child").is_none());
+    assert!(parse_referral("ldap://child.essos.local/").is_none());
+
+    let referrals: Vec<String> = vec![
+        "ldap://ForestDnsZones.essos.local/DC=ForestDnsZones,DC=essos,DC=local",
+        "ldap://DomainDnsZones.essos.local/DC=DomainDnsZones,DC=essos,DC=local",
+        "ldap://essos.local/CN=Configuration,DC=essos,DC=local",
+        "ldap://child.essos.local/DC=child,DC=essos,DC=local",
+        "ldap://CHILD.ESSOS.LOCAL/DC=CHILD,DC=ESSOS,DC=LOCAL",
+    ].into_iter().map(String::from).collect();
+    let contexts = referred_naming_contexts(&referrals);
+    assert_eq!(contexts.len(), 1);
+    assert_eq!(contexts[0].base, "DC=child,DC=essos,DC=local");
+}
@@ -38,18 +38,17 @@
 //!    -n, --name-server <name-server>      Alternative IP address name server to use for queries
 //!    -o, --dirpath <path>                 Path where you would like to save json files
 //!```
-//! Or build your own using the ldap_search() function:
-//! ```
-//!let result = ldap_search(
-//!    &ldaps,
-//!    &ip,
-//!    &port,
-//!    &domain,
-//!    &ldapfqdn,
-//!    &username,
-//!    &password,
-//!);
+//! Or build your own using the ldap_search() function, with the options of a command line:
+//! ```no_run
+//!# async fn collect() -> Result<(), Box<dyn std::error::Error>> {
+//!let options = rusthound::args::extract_args_from(["rusthound", "-d", "essos.local", "-u", "khal.drogo", "-p", "horse"])?;
+//!let (entries, referrals) = rusthound::ldap_search(&options, &Vec::new(), None).await?;
+//!# Ok(())
+//!# }
 //!```
+//! Or run a whole collection with the options of the command line, `run_collection()` of the binary,
+//! also called by the C entry point `rusthound_collect()` of the dynamic library (see [`ffi`]).
+//!
 //! Here is an example of how to use rusthound:
 //! ![demo](https://raw.githubusercontent.com/OPENCYBER-FR/RustHound/main/img/demo.gif)
 //! 
@@ -57,6 +56,10 @@ pub mod args;
 pub mod banner;
 pub mod errors;
 pub mod ldap;
+pub mod adws;
+pub mod secret;
+pub mod run;
+pub mod ffi;
 
 pub mod enums;
 pub mod json;
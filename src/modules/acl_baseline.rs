@@ -0,0 +1,198 @@
+//! ACL drift against a baseline descriptor set (--acl-baseline)
+//!
+//! The owner and the explicit ACEs of every nTSecurityDescriptor are written in `<dirpath>/<domain>_descriptors.json`,
+//! `--acl-baseline <dir>` compares them with the descriptor set of a previous run in `<dir>` and writes the ACEs added
+//! (and removed) per object in `<dirpath>/<domain>_acl_baseline.json`, like a GenericAll added to a service account.
+//!
+//! The inherited ACEs are not compared: an ACE added on an OU is reported once on the OU, not on every child object.
+//! The first run with `--acl-baseline` only writes the descriptor set, it is the baseline of the next runs.
+use crate::banner::Colorize;
+use log::{info, warn};
+use serde_json::value::Value;
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::path::Path;
+
+use crate::enums::acl::recorded_descriptors;
+use crate::enums::extrights::get_extended_right_name;
+
+/// Rights of an access mask, from the most to the least dangerous.
+const ACE_RIGHTS: [(u32, &str); 10] = [
+   (0x000F01FF, "GenericAll"),
+   (0x00040000, "WriteDacl"),
+   (0x00080000, "WriteOwner"),
+   (0x00020028, "GenericWrite"),
+   (0x00000020, "WriteProperty"),
+   (0x00000100, "ExtendedRight"),
+   (0x00000008, "Self"),
+   (0x00000001, "CreateChild"),
+   (0x00010000, "Delete"),
+   (0x00000010, "ReadProperty"),
+];
+
+/// Function to get the descriptor set file of a directory.
+pub fn descriptors_path(dir: &str, domain: &String) -> String {
+   format!("{}/{}_descriptors.json", dir, domain.replace(".", "-").to_lowercase())
+}
+
+/// Function to write a descriptor set in JSON: {"<dn>": {"owner": "<sid>", "aces": ["<ace>"]}}.
+pub fn descriptors_to_json(descriptors: &BTreeMap<String, (String, Vec<String>)>) -> Value {
+   let mut json = serde_json::Map::new();
+   for (dn, (owner, aces)) in descriptors {
+      json.insert(dn.to_owned(), serde_json::json!({ "owner": owner, "aces": aces }));
+   }
+   Value::Object(json)
+}
+
+/// Function to read a descriptor set written by descriptors_to_json.
+pub fn descriptors_from_json(json: &Value) -> BTreeMap<String, (String, Vec<String>)> {
+   let mut descriptors: BTreeMap<String, (String, Vec<String>)> = BTreeMap::new();
+   for (dn, descriptor) in json.as_object().into_iter().flatten() {
+      let aces = descriptor["aces"].as_array().into_iter().flatten().filter_map(|ace| ace.as_str().map(|ace| ace.to_string())).collect();
+      descriptors.insert(dn.to_owned(), (descriptor["owner"].as_str().unwrap_or("").to_string(), aces));
+   }
+   descriptors
+}
+
+/// Function to describe one recorded ACE "type flags mask SID object_type inherited_object_type".
+pub fn describe_ace(ace: &str, names: &HashMap<String, String>) -> Value {
+   let fields: Vec<&str> = ace.split_whitespace().collect();
+   let field = |index: usize| fields.get(index).copied().filter(|value| *value != "-");
+   let ace_type = u8::from_str_radix(field(0).unwrap_or("0"), 16).unwrap_or(0);
+   let mask = u32::from_str_radix(field(2).unwrap_or("0"), 16).unwrap_or(0);
+   // A right already included in a right listed before is not repeated (GenericWrite includes WriteProperty)
+   let mut rights: Vec<&str> = Vec::new();
+   let mut granted: u32 = 0;
+   for (bits, right) in ACE_RIGHTS {
+      if mask & bits == bits && granted & bits != bits {
+         rights.push(right);
+         granted |= bits;
+      }
+   }
+   let principal = field(3).unwrap_or("");
+   serde_json::json!({
+      "type": if ace_type == 0x01 || ace_type == 0x06 { "Deny" } else { "Allow" },
+      "principal": principal,
+      "principalname": names.get(principal),
+      "rights": rights,
+      "mask": format!("0x{}", field(2).unwrap_or("0")),
+      "objecttype": field(4),
+      "objecttypename": field(4).and_then(get_extended_right_name),
+      "inheritedobjecttype": field(5),
+      "flags": format!("0x{}", field(1).unwrap_or("0")),
+   })
+}
+
+/// Function to compare two descriptor sets, the objects of both sets with other explicit ACEs or another owner.
+pub fn compare_descriptors(
+   baseline: &BTreeMap<String, (String, Vec<String>)>,
+   current: &BTreeMap<String, (String, Vec<String>)>,
+   objects: &HashMap<String, (String, String)>,
+   names: &HashMap<String, String>,
+) -> Vec<Value> {
+   let mut changes: Vec<Value> = Vec::new();
+   for (dn, (owner, aces)) in current {
+      let (old_owner, old_aces) = match baseline.get(dn) {
+         Some(descriptor) => descriptor,
+         None => continue,
+      };
+      let added: Vec<Value> = aces.iter().filter(|ace| !old_aces.contains(ace)).map(|ace| describe_ace(ace, names)).collect();
+      let removed: Vec<Value> = old_aces.iter().filter(|ace| !aces.contains(ace)).map(|ace| describe_ace(ace, names)).collect();
+      if added.is_empty() && removed.is_empty() && owner == old_owner {
+         continue
+      }
+      let (name, objectid) = objects.get(&dn.to_uppercase()).cloned().unwrap_or_default();
+      let mut change = serde_json::json!({
+         "distinguishedname": dn,
+         "name": name,
+         "objectid": objectid,
+         "aces_added": added,
+         "aces_removed": removed,
+      });
+      if owner != old_owner {
+         change["owner"] = serde_json::json!({ "old": old_owner, "new": owner });
+      }
+      changes.push(change);
+   }
+   changes
+}
+
+/// Function to write the descriptor set of the run and the ACL changes since the baseline.
+pub fn make_acl_baseline_report(path: &String, domain: &String, baseline_dir: &String, vec_objects: &[(&str, &Vec<Value>)]) -> std::io::Result<()> {
+   let current = recorded_descriptors();
+   let baseline_path = descriptors_path(baseline_dir, domain);
+   let baseline = if Path::new(&baseline_path).is_file() {
+      let json: Value = serde_json::from_slice(&fs::read(&baseline_path)?)
+         .map_err(|err| std::io::Error::new(std::io::ErrorKind::InvalidData, format!("{baseline_path}: {err}")))?;
+      Some(descriptors_from_json(&json))
+   } else {
+      None
+   };
+
+   fs::create_dir_all(path)?;
+   let final_path = descriptors_path(path, domain);
+   fs::write(&final_path, serde_json::to_string(&descriptors_to_json(&current)).unwrap_or_default())?;
+   info!("{} created!", final_path.bold());
+
+   let baseline = match baseline {
+      Some(baseline) => baseline,
+      None => {
+         warn!("No descriptor set in {}, this run is the ACL baseline", baseline_dir);
+         return Ok(())
+      }
+   };
+   // distinguishedName => (name, ObjectIdentifier) and SID => name
+   let mut objects: HashMap<String, (String, String)> = HashMap::new();
+   let mut names: HashMap<String, String> = HashMap::new();
+   for (_, list) in vec_objects {
+      for object in list.iter() {
+         let name = object["Properties"]["name"].as_str().unwrap_or("").to_string();
+         let id = object["ObjectIdentifier"].as_str().unwrap_or("").to_string();
+         if let Some(dn) = object["Properties"]["distinguishedname"].as_str() {
+            objects.insert(dn.to_uppercase(), (name.to_owned(), id.to_owned()));
+         }
+         names.insert(id, name);
+      }
+   }
+   let changes = compare_descriptors(&baseline, &current, &objects, &names);
+   for change in changes.iter() {
+      let added = change["aces_added"].as_array().map(|aces| aces.len()).unwrap_or(0);
+      if added > 0 || !change["owner"].is_null() {
+         warn!("{} {} ACEs added since the baseline", change["distinguishedname"].as_str().unwrap_or("").yellow().bold(), added);
+      }
+   }
+   info!("{} objects with ACL changes since {}", changes.len().to_string().bold(), baseline_dir);
+
+   let final_path = format!("{}/{}_acl_baseline.json", path, domain.replace(".", "-").to_lowercase());
+   fs::write(&final_path, serde_json::to_string_pretty(&serde_json::json!({ "baseline": baseline_dir, "changes": changes })).unwrap_or_default())?;
+   info!("{} created!", final_path.bold());
+   Ok(())
+}
+
+/// Test functions
+#[test]
+pub fn test_compare_descriptors() {
+   let dn = "CN=SQL_SVC,CN=USERS,DC=ESSOS,DC=LOCAL".to_string();
+   let default_ace = "05 00 00000130 S-1-5-10 ab721a53-1e2f-11d0-9819-00aa0040529b -".to_string();
+   let mut baseline: BTreeMap<String, (String, Vec<String>)> = BTreeMap::new();
+   baseline.insert(dn.to_owned(), ("S-1-5-21-1-2-3-512".to_string(), vec![default_ace.to_owned()]));
+   let json = descriptors_to_json(&baseline);
+   assert_eq!(descriptors_from_json(&json), baseline);
+
+   let mut current = baseline.clone();
+   current.get_mut(&dn).unwrap().1.push("00 00 000f01ff S-1-5-21-1-2-3-1105 - -".to_string());
+   let objects = HashMap::from([(dn.to_owned(), ("SQL_SVC@ESSOS.LOCAL".to_string(), "S-1-5-21-1-2-3-1110".to_string()))]);
+   let names = HashMap::from([("S-1-5-21-1-2-3-1105".to_string(), "VISERYS@ESSOS.LOCAL".to_string())]);
+   let changes = compare_descriptors(&baseline, &current, &objects, &names);
+   assert_eq!(changes.len(), 1);
+   assert_eq!(changes[0]["name"], "SQL_SVC@ESSOS.LOCAL");
+   assert_eq!(changes[0]["aces_added"][0]["principalname"], "VISERYS@ESSOS.LOCAL");
+   assert_eq!(changes[0]["aces_added"][0]["rights"], serde_json::json!(["GenericAll"]));
+   assert!(changes[0]["owner"].is_null());
+   assert!(compare_descriptors(&baseline, &baseline, &objects, &names).is_empty());
+
+   let ace = describe_ace("05 12 00000030 S-1-5-21-1-2-3-1105 bf9679c0-0de6-11d0-a285-00aa003049e2 -", &names);
+   assert_eq!(ace["rights"], serde_json::json!(["WriteProperty", "ReadProperty"]));
+   assert_eq!(ace["type"], "Allow");
+   assert!(ace["inheritedobjecttype"].is_null());
+}
@@ -0,0 +1,216 @@
+//! AdminSDHolder and adminCount reporting (--adminsdholder)
+//!
+//! Every 60 minutes SDProp copies the AdminSDHolder ACL on the protected objects (adminCount=1).
+//! A principal added to the AdminSDHolder DACL gets back its rights on every protected object,
+//! it is a common persistence technique.
+//!
+//! The AdminSDHolder container and its ACL are written as one object in `<dirpath>/<domain>_adminsdholder.json`,
+//! every ACE with the name of its principal and `IsDefault`. The control edges granted to principals out of the
+//! default ACL and the protected objects are written in `<dirpath>/<domain>_adminsdholder.csv`.
+//! The default groups of the domain are matched on the SID of the collected domain, Enterprise Admins also on the
+//! SIDs of the domains of the same forest: a group with RID 512 of another domain is not a default principal.
+use crate::banner::Colorize;
+use log::{info, warn};
+use serde_json::value::Value;
+use std::collections::HashMap;
+use std::fs;
+
+use crate::json::maker::csv::csv_field;
+
+/// DN prefix of the AdminSDHolder container.
+const ADMINSDHOLDER_DN: &str = "CN=ADMINSDHOLDER,CN=SYSTEM,";
+
+/// Groups of the domain in the default AdminSDHolder ACL: Domain Admins, Cert Publishers.
+const DEFAULT_DOMAIN_RIDS: [&str; 2] = ["512", "517"];
+/// Enterprise Admins, a group of the forest root domain.
+const ENTERPRISE_ADMINS_RID: &str = "519";
+/// Well-known principals of the default AdminSDHolder ACL: SYSTEM, SELF, Authenticated Users, Everyone,
+/// Administrators, Pre-Windows 2000 Compatible Access, Windows Authorization Access, Terminal Server License Servers.
+const DEFAULT_WELL_KNOWN_SIDS: [&str; 8] = [
+   "S-1-5-18", "S-1-5-10", "S-1-5-11", "S-1-1-0",
+   "S-1-5-32-544", "S-1-5-32-554", "S-1-5-32-560", "S-1-5-32-561",
+];
+
+/// Function to know if a DN is the AdminSDHolder container.
+pub fn is_adminsdholder(dn: &str) -> bool {
+   dn.to_uppercase().starts_with(ADMINSDHOLDER_DN)
+}
+
+/// One control edge on AdminSDHolder granted to a principal out of the default ACL.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AdminSdHolderFinding {
+   pub principal_sid: String,
+   pub principal_name: String,
+   pub principal_type: String,
+   pub right: String,
+   pub inherited: bool,
+}
+
+/// Function to know if a principal is in the default AdminSDHolder ACL.
+/// The well-known SIDs are prefixed with the domain name by RustHound: `ESSOS.LOCAL-S-1-5-32-544`.
+pub fn is_default_principal(sid: &str, domain_sid: &str, forest_sids: &[String]) -> bool {
+   if DEFAULT_WELL_KNOWN_SIDS.iter().any(|default| sid == *default || sid.ends_with(&format!("-{}", default))) {
+      return true
+   }
+   if !domain_sid.is_empty() && DEFAULT_DOMAIN_RIDS.iter().any(|rid| sid == format!("{}-{}", domain_sid, rid)) {
+      return true
+   }
+   std::iter::once(domain_sid).chain(forest_sids.iter().map(|sid| sid.as_str()))
+      .any(|forest_sid| !forest_sid.is_empty() && sid == format!("{}-{}", forest_sid, ENTERPRISE_ADMINS_RID))
+}
+
+/// Function to get the SID of the collected domain and the SIDs of the domains of its forest (ParentChild trusts).
+pub fn domain_sids(vec_domains: &[Value]) -> (String, Vec<String>) {
+   let domain = match vec_domains.first() {
+      Some(domain) => domain,
+      None => return (String::new(), Vec::new()),
+   };
+   let forest_sids = domain["Trusts"].as_array().into_iter().flatten()
+      .filter(|trust| trust["TrustType"].as_str() == Some("ParentChild"))
+      .filter_map(|trust| trust["TargetDomainSid"].as_str().map(|sid| sid.to_string()))
+      .collect();
+   (domain["ObjectIdentifier"].as_str().unwrap_or("").to_string(), forest_sids)
+}
+
+/// Function to build the AdminSDHolder object: the container and its ACEs, with the principal names
+/// and IsDefault. None when the container is not collected.
+pub fn adminsdholder_acl(
+   vec_containers: &[Value],
+   vec_domains: &[Value],
+   sid_name: &HashMap<String, String>,
+) -> Option<Value> {
+   let adminsdholder = vec_containers.iter()
+      .find(|container| is_adminsdholder(container["Properties"]["distinguishedname"].as_str().unwrap_or("")))?;
+   let (domain_sid, forest_sids) = domain_sids(vec_domains);
+   if domain_sid.is_empty() {
+      warn!("AdminSDHolder: domain SID unknown, only the well-known principals are default");
+   }
+   let aces: Vec<Value> = adminsdholder["Aces"].as_array().into_iter().flatten().map(|ace| {
+      let sid = ace["PrincipalSID"].as_str().unwrap_or("");
+      serde_json::json!({
+         "RightName": ace["RightName"],
+         "PrincipalSID": sid,
+         "PrincipalName": sid_name.get(sid).cloned().unwrap_or_default(),
+         "PrincipalType": ace["PrincipalType"],
+         "IsInherited": ace["IsInherited"],
+         "IsDefault": is_default_principal(sid, &domain_sid, &forest_sids),
+      })
+   }).collect();
+   Some(serde_json::json!({
+      "ObjectIdentifier": adminsdholder["ObjectIdentifier"],
+      "Properties": {
+         "name": adminsdholder["Properties"]["name"],
+         "distinguishedname": adminsdholder["Properties"]["distinguishedname"],
+         "domainsid": domain_sid,
+      },
+      "Aces": aces,
+   }))
+}
+
+/// Function to list the control edges on AdminSDHolder granted to non-default principals.
+pub fn adminsdholder_findings(acl: &Value) -> Vec<AdminSdHolderFinding> {
+   acl["Aces"].as_array().into_iter().flatten()
+      .filter(|ace| !ace["IsDefault"].as_bool().unwrap_or(false))
+      .map(|ace| AdminSdHolderFinding {
+         principal_sid: ace["PrincipalSID"].as_str().unwrap_or("").to_string(),
+         principal_name: ace["PrincipalName"].as_str().unwrap_or("").to_string(),
+         principal_type: ace["PrincipalType"].as_str().unwrap_or("").to_string(),
+         right: ace["RightName"].as_str().unwrap_or("").to_string(),
+         inherited: ace["IsInherited"].as_bool().unwrap_or(false),
+      })
+      .collect()
+}
+
+/// Function to write the AdminSDHolder object and the report with the protected objects.
+pub fn make_adminsdholder_report(
+   path: &String,
+   domain: &String,
+   vec_containers: &Vec<serde_json::value::Value>,
+   vec_domains: &Vec<serde_json::value::Value>,
+   vec_principals: &[(&str, &Vec<serde_json::value::Value>)],
+) -> std::io::Result<()> {
+   let mut sid_name: HashMap<String, String> = HashMap::new();
+   for (_, objects) in vec_principals {
+      for object in objects.iter() {
+         if let (Some(sid), Some(name)) = (object["ObjectIdentifier"].as_str(), object["Properties"]["name"].as_str()) {
+            sid_name.insert(sid.to_string(), name.to_string());
+         }
+      }
+   }
+
+   fs::create_dir_all(path)?;
+   let acl = adminsdholder_acl(vec_containers, vec_domains, &sid_name);
+   match &acl {
+      Some(acl) => {
+         let final_path = format!("{}/{}_adminsdholder.json", path, domain.replace(".", "-").to_lowercase());
+         fs::write(&final_path, serde_json::to_string_pretty(acl).unwrap_or_default())?;
+         info!("{} created!", final_path.bold());
+      }
+      None => warn!("AdminSDHolder container not collected, no ACL to check"),
+   }
+   let findings = acl.as_ref().map(adminsdholder_findings).unwrap_or_default();
+   let mut csv = String::from("category,name,type,objectidentifier,right,inherited\n");
+   for finding in &findings {
+      warn!("{} has {} on AdminSDHolder, out of the default ACL", finding.principal_name.bold().red(), finding.right.bold());
+      csv.push_str(&format!(
+         "nondefault_ace,{},{},{},{},{}\n",
+         csv_field(&finding.principal_name),
+         finding.principal_type,
+         finding.principal_sid,
+         finding.right,
+         finding.inherited,
+      ));
+   }
+   let mut protected = 0;
+   for (object_type, objects) in vec_principals {
+      for object in objects.iter().filter(|object| object["Properties"]["admincount"].as_bool().unwrap_or(false)) {
+         protected += 1;
+         csv.push_str(&format!(
+            "protected,{},{},{},,\n",
+            csv_field(object["Properties"]["name"].as_str().unwrap_or("")),
+            object_type,
+            object["ObjectIdentifier"].as_str().unwrap_or(""),
+         ));
+      }
+   }
+   info!("AdminSDHolder: {} non-default ACEs, {} protected objects (adminCount=1)", findings.len().to_string().bold(), protected.to_string().bold());
+
+   let final_path = format!("{}/{}_adminsdholder.csv", path, domain.replace(".", "-").to_lowercase());
+   fs::write(&final_path, csv)?;
+   info!("{} created!", final_path.bold());
+   Ok(())
+}
+
+/// Test functions
+#[test]
+pub fn test_adminsdholder_findings() {
+   let vec_containers = vec![
+      serde_json::json!({"Properties": {"distinguishedname": "CN=USERS,DC=ESSOS,DC=LOCAL"},
+         "Aces": [{"RightName": "GenericAll", "PrincipalSID": "S-1-5-21-1-2-3-1104", "PrincipalType": "User", "IsInherited": false}]}),
+      serde_json::json!({"ObjectIdentifier": "8F3A2C1D-0000-4000-8000-000000000001",
+         "Properties": {"name": "ADMINSDHOLDER@ESSOS.LOCAL", "distinguishedname": "CN=ADMINSDHOLDER,CN=SYSTEM,DC=ESSOS,DC=LOCAL"},
+         "Aces": [
+            {"RightName": "Owns", "PrincipalSID": "S-1-5-21-1-2-3-512", "PrincipalType": "Group", "IsInherited": false},
+            {"RightName": "GenericAll", "PrincipalSID": "ESSOS.LOCAL-S-1-5-32-544", "PrincipalType": "Group", "IsInherited": false},
+            {"RightName": "GenericAll", "PrincipalSID": "S-1-5-21-1-2-3-1104", "PrincipalType": "User", "IsInherited": false},
+            {"RightName": "GenericAll", "PrincipalSID": "S-1-5-21-9-8-7-512", "PrincipalType": "Group", "IsInherited": false},
+            {"RightName": "GenericAll", "PrincipalSID": "S-1-5-21-4-5-6-519", "PrincipalType": "Group", "IsInherited": false}
+         ]}),
+   ];
+   let vec_domains = vec![serde_json::json!({"ObjectIdentifier": "S-1-5-21-1-2-3",
+      "Trusts": [{"TargetDomainSid": "S-1-5-21-4-5-6", "TrustType": "ParentChild"}, {"TargetDomainSid": "S-1-5-21-9-8-7", "TrustType": "Forest"}]})];
+   let sid_name: HashMap<String, String> = vec![("S-1-5-21-1-2-3-1104".to_string(), "KHAL.DROGO@ESSOS.LOCAL".to_string())].into_iter().collect();
+   let acl = adminsdholder_acl(&vec_containers, &vec_domains, &sid_name).unwrap();
+   assert_eq!(acl["Properties"]["domainsid"], "S-1-5-21-1-2-3");
+   assert_eq!(acl["Aces"].as_array().unwrap().len(), 5);
+   assert_eq!(acl["Aces"][2]["PrincipalName"], "KHAL.DROGO@ESSOS.LOCAL");
+   // Domain Admins of a domain of another forest is not a default principal, Enterprise Admins of the root is
+   let findings = adminsdholder_findings(&acl);
+   assert_eq!(findings.len(), 2);
+   assert_eq!(findings[0].principal_name, "KHAL.DROGO@ESSOS.LOCAL");
+   assert_eq!(findings[0].right, "GenericAll");
+   assert_eq!(findings[1].principal_sid, "S-1-5-21-9-8-7-512");
+   assert!(!is_default_principal("S-1-5-21-1-2-3-512", "", &[]));
+   assert!(adminsdholder_acl(&vec_containers[..1], &vec_domains, &sid_name).is_none());
+   assert!(is_adminsdholder("CN=AdminSDHolder,CN=System,DC=essos,DC=local"));
+}
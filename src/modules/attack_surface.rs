@@ -0,0 +1,291 @@
+//! Attack surface summary in Markdown and Graphviz (--attack-surface)
+//!
+//! A readable summary for the engagement reports, without BloodHound:
+//! - dangerous edges: DCSync capable principals, control of the domain object and unconstrained delegation hosts,
+//!   the Tier 0 principals holding them by default are not listed,
+//! - principals reaching Tier 0 through the edges of the collection (ACEs, memberships, local groups, sessions),
+//!   with the number of hops and the next step of their shortest path.
+//!
+//! The results are written in `<dirpath>/<domain>_attack_surface.md` and `<dirpath>/<domain>_attack_surface.dot`
+//! (`dot -Tsvg <domain>_attack_surface.dot -o attack_surface.svg`).
+use crate::banner::Colorize;
+use log::{debug, info};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::fs;
+
+use crate::json::maker::csv::{object_edges, CsvEdge};
+use crate::modules::risk_score::is_tier0;
+use crate::modules::synthetic_edges::is_domain_controller;
+
+/// Maximum number of principals reaching Tier 0 in the graph, the nearest first.
+const MAX_GRAPH_PRINCIPALS: usize = 150;
+/// Principals expected to replicate or control the domain: Enterprise Domain Controllers, Local System.
+const EXPECTED_DOMAIN_SIDS: [&str; 2] = ["-S-1-5-9", "-S-1-5-18"];
+/// Rights on the domain object giving its control.
+const DOMAIN_CONTROL_RIGHTS: [&str; 5] = ["GenericAll", "GenericWrite", "WriteDacl", "WriteOwner", "Owns"];
+
+/// One principal of the report.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SurfaceEntry {
+   pub name: String,
+   pub object_type: String,
+   pub object_identifier: String,
+   /// Rights, hops or reason
+   pub detail: String,
+}
+
+/// Attack surface of the domain.
+#[derive(Debug, Clone, Default)]
+pub struct AttackSurface {
+   pub dcsync: Vec<SurfaceEntry>,
+   pub domain_control: Vec<SurfaceEntry>,
+   pub unconstrained_delegation: Vec<SurfaceEntry>,
+   /// Principals reaching Tier 0, the nearest first
+   pub tier0_reach: Vec<SurfaceEntry>,
+   /// Edges of the shortest paths to Tier 0
+   pub graph_edges: Vec<CsvEdge>,
+   pub tier0: HashSet<String>,
+}
+
+/// Function to build the attack surface from the parsed objects.
+pub fn attack_surface(vec_objects: &[(&str, &Vec<serde_json::value::Value>)]) -> AttackSurface {
+   let mut surface = AttackSurface::default();
+   let mut tier0: HashSet<String> = HashSet::new();
+   let mut nodes: HashMap<String, (String, String)> = HashMap::new();
+   let mut edges: Vec<CsvEdge> = Vec::new();
+   for (object_type, objects) in vec_objects {
+      for object in objects.iter() {
+         let id = match object["ObjectIdentifier"].as_str() {
+            Some(id) => id.to_string(),
+            None => continue,
+         };
+         if is_tier0(object_type, &id, object) {
+            tier0.insert(id.to_owned());
+         }
+         nodes.insert(id.to_owned(), (object["Properties"]["name"].as_str().unwrap_or("").to_string(), object_type.to_string()));
+         edges.extend(object_edges(object, object_type));
+      }
+   }
+   let expected = |sid: &str| tier0.contains(sid) || EXPECTED_DOMAIN_SIDS.iter().any(|expected| sid.ends_with(expected));
+   let entry = |sid: &String, principal_type: &str, detail: String| {
+      let (name, object_type) = nodes.get(sid).cloned().unwrap_or_else(|| (String::new(), principal_type.to_string()));
+      SurfaceEntry { name: if name.is_empty() { sid.to_owned() } else { name }, object_type, object_identifier: sid.to_owned(), detail }
+   };
+
+   // Rights on the domain objects
+   let mut domain_rights: HashMap<(String, String), Vec<String>> = HashMap::new();
+   for edge in edges.iter().filter(|edge| edge.target_type == "Domain") {
+      let rights = domain_rights.entry((edge.source.to_owned(), edge.source_type.to_owned())).or_default();
+      if !rights.contains(&edge.edge) {
+         rights.push(edge.edge.to_owned());
+      }
+   }
+   let mut domain_rights: Vec<((String, String), Vec<String>)> = domain_rights.into_iter().filter(|((sid, _), _)| !expected(sid)).collect();
+   domain_rights.sort();
+   for ((sid, principal_type), rights) in &domain_rights {
+      let has = |right: &str| rights.iter().any(|r| r == right);
+      if (has("GetChanges") && has("GetChangesAll")) || has("GenericAll") || has("AllExtendedRights") {
+         surface.dcsync.push(entry(sid, principal_type, rights.join(", ")));
+      }
+      let control: Vec<&String> = rights.iter().filter(|right| DOMAIN_CONTROL_RIGHTS.contains(&right.as_str())).collect();
+      if !control.is_empty() {
+         surface.domain_control.push(entry(sid, principal_type, control.iter().map(|right| right.as_str()).collect::<Vec<&str>>().join(", ")));
+      }
+   }
+
+   // Unconstrained delegation outside of the domain controllers
+   for (object_type, objects) in vec_objects.iter().filter(|(object_type, _)| *object_type == "User" || *object_type == "Computer") {
+      for object in objects.iter() {
+         if object["Properties"]["unconstraineddelegation"].as_bool().unwrap_or(false) && !is_domain_controller(object) {
+            if let Some(sid) = object["ObjectIdentifier"].as_str() {
+               let detail = if object["Properties"]["enabled"].as_bool().unwrap_or(true) { "enabled" } else { "disabled" };
+               surface.unconstrained_delegation.push(entry(&sid.to_string(), object_type, detail.to_string()));
+            }
+         }
+      }
+   }
+   surface.unconstrained_delegation.sort_by(|a, b| a.name.cmp(&b.name));
+
+   // Shortest paths to Tier 0, breadth-first search from Tier 0 on the reversed edges
+   let mut inbound: HashMap<&str, Vec<&CsvEdge>> = HashMap::new();
+   for edge in &edges {
+      if edge.source != edge.target {
+         inbound.entry(edge.target.as_str()).or_default().push(edge);
+      }
+   }
+   let mut sorted_tier0: Vec<&String> = tier0.iter().collect();
+   sorted_tier0.sort();
+   let mut hops: HashMap<&str, (usize, Option<&CsvEdge>)> = HashMap::new();
+   let mut queue: VecDeque<&str> = VecDeque::new();
+   for id in sorted_tier0 {
+      hops.insert(id.as_str(), (0, None));
+      queue.push_back(id.as_str());
+   }
+   while let Some(target) = queue.pop_front() {
+      let distance = hops[target].0;
+      for edge in inbound.get(target).into_iter().flatten() {
+         if !hops.contains_key(edge.source.as_str()) {
+            hops.insert(edge.source.as_str(), (distance + 1, Some(edge)));
+            queue.push_back(edge.source.as_str());
+         }
+      }
+   }
+   let mut reach: Vec<(usize, SurfaceEntry, &CsvEdge)> = hops.iter()
+      .filter(|(sid, _)| !expected(sid))
+      .filter_map(|(sid, (distance, edge))| {
+         let edge = (*edge)?;
+         let next = nodes.get(&edge.target).map(|(name, _)| name.to_owned()).filter(|name| !name.is_empty()).unwrap_or(edge.target.to_owned());
+         Some((*distance, entry(&sid.to_string(), &edge.source_type, format!("{} {}", edge.edge, next)), edge))
+      })
+      .collect();
+   reach.sort_by(|a, b| a.0.cmp(&b.0).then(a.1.name.cmp(&b.1.name)));
+   debug!("{} principals reaching Tier 0", reach.len());
+
+   // Graph of the nearest principals: their edge on the shortest path, up to Tier 0
+   let mut walked: HashSet<&str> = HashSet::new();
+   for (_, _, edge) in reach.iter().take(MAX_GRAPH_PRINCIPALS) {
+      let mut current = Some(*edge);
+      while let Some(edge) = current {
+         if !walked.insert(edge.source.as_str()) {
+            break
+         }
+         surface.graph_edges.push(edge.to_owned());
+         current = hops.get(edge.target.as_str()).and_then(|(_, next)| *next);
+      }
+   }
+   surface.graph_edges.sort_by(|a, b| (&a.source, &a.target, &a.edge).cmp(&(&b.source, &b.target, &b.edge)));
+   surface.tier0_reach = reach.into_iter().map(|(distance, mut principal, _)| {
+      principal.detail = format!("{} hop{} ({})", distance, if distance > 1 { "s" } else { "" }, principal.detail);
+      principal
+   }).collect();
+   surface.tier0 = tier0;
+   surface
+}
+
+/// Function to render the Markdown report.
+pub fn render_markdown(domain: &String, counts: &[(&str, usize)], surface: &AttackSurface) -> String {
+   let mut md = format!("# Attack surface - {}\n\nGenerated on {}\n\n", domain, chrono::Local::now().format("%Y-%m-%d %H:%M:%S"));
+   md.push_str("| Type | Count |\n|---|---|\n");
+   for (object_type, count) in counts {
+      md.push_str(&format!("| {} | {} |\n", object_type, count));
+   }
+   md.push_str(&format!("\n**{}** Tier 0 objects, **{}** principals reaching Tier 0.\n", surface.tier0.len(), surface.tier0_reach.len()));
+
+   for (title, column, entries) in [
+      ("DCSync capable principals", "Rights on the domain", &surface.dcsync),
+      ("Control of the domain object", "Rights", &surface.domain_control),
+      ("Unconstrained delegation hosts (domain controllers excluded)", "Account", &surface.unconstrained_delegation),
+      ("Principals reaching Tier 0", "Shortest path", &surface.tier0_reach),
+   ] {
+      md.push_str(&format!("\n## {} ({})\n\n", title, entries.len()));
+      if entries.is_empty() {
+         md.push_str("None.\n");
+         continue
+      }
+      md.push_str(&format!("| Principal | Type | {} |\n|---|---|---|\n", column));
+      for principal in entries.iter() {
+         md.push_str(&format!("| {} | {} | {} |\n", markdown_escape(&principal.name), principal.object_type, markdown_escape(&principal.detail)));
+      }
+   }
+   md
+}
+
+/// Function to render the Graphviz graph of the shortest paths to Tier 0, Tier 0 in red.
+pub fn render_dot(surface: &AttackSurface, names: &HashMap<String, String>) -> String {
+   let label = |sid: &String| names.get(sid).filter(|name| !name.is_empty()).unwrap_or(sid).to_owned();
+   let mut dot = String::from("digraph attack_surface {\n   rankdir=LR;\n   node [shape=box, style=rounded];\n");
+   let mut nodes: Vec<&String> = surface.graph_edges.iter().flat_map(|edge| [&edge.source, &edge.target]).collect();
+   nodes.sort();
+   nodes.dedup();
+   for node in nodes {
+      let color = if surface.tier0.contains(node) { ", color=red, fontcolor=red" } else { "" };
+      dot.push_str(&format!("   \"{}\" [label=\"{}\"{}];\n", dot_escape(node), dot_escape(&label(node)), color));
+   }
+   for edge in &surface.graph_edges {
+      dot.push_str(&format!("   \"{}\" -> \"{}\" [label=\"{}\"];\n", dot_escape(&edge.source), dot_escape(&edge.target), dot_escape(&edge.edge)));
+   }
+   dot.push_str("}\n");
+   dot
+}
+
+/// Function to write the Markdown report and the Graphviz graph.
+pub fn make_attack_surface_report(
+   path: &String,
+   domain: &String,
+   vec_objects: &[(&str, &Vec<serde_json::value::Value>)],
+) -> std::io::Result<()> {
+   let surface = attack_surface(vec_objects);
+   let counts: Vec<(&str, usize)> = vec_objects.iter().map(|(object_type, objects)| (*object_type, objects.len())).collect();
+   let names: HashMap<String, String> = vec_objects.iter()
+      .flat_map(|(_, objects)| objects.iter())
+      .filter_map(|object| Some((object["ObjectIdentifier"].as_str()?.to_string(), object["Properties"]["name"].as_str()?.to_string())))
+      .collect();
+   info!(
+      "{} DCSync capable principals, {} principals reaching Tier 0",
+      surface.dcsync.len().to_string().bold(),
+      surface.tier0_reach.len().to_string().bold()
+   );
+
+   fs::create_dir_all(path)?;
+   let final_path = format!("{}/{}_attack_surface", path, domain.replace(".", "-").to_lowercase());
+   fs::write(format!("{}.md", final_path), render_markdown(domain, &counts, &surface))?;
+   info!("{} created!", format!("{}.md", final_path).bold());
+   fs::write(format!("{}.dot", final_path), render_dot(&surface, &names))?;
+   info!("{} created!", format!("{}.dot", final_path).bold());
+   Ok(())
+}
+
+fn markdown_escape(text: &str) -> String {
+   text.replace('|', "\\|")
+}
+
+fn dot_escape(text: &str) -> String {
+   text.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+/// Test functions
+#[test]
+pub fn test_attack_surface() {
+   let vec_domains = vec![
+      serde_json::json!({"ObjectIdentifier": "S-1-5-21-1-2-3", "Properties": {"name": "ESSOS.LOCAL"}, "Aces": [
+         {"RightName": "GetChanges", "IsInherited": false, "PrincipalSID": "S-1-5-21-1-2-3-1110", "PrincipalType": "User"},
+         {"RightName": "GetChangesAll", "IsInherited": false, "PrincipalSID": "S-1-5-21-1-2-3-1110", "PrincipalType": "User"},
+         {"RightName": "GenericAll", "IsInherited": false, "PrincipalSID": "S-1-5-21-1-2-3-512", "PrincipalType": "Group"},
+         {"RightName": "GetChangesAll", "IsInherited": false, "PrincipalSID": "ESSOS.LOCAL-S-1-5-9", "PrincipalType": "Group"},
+      ]}),
+   ];
+   let vec_groups = vec![
+      serde_json::json!({"ObjectIdentifier": "S-1-5-21-1-2-3-512", "Properties": {"name": "DOMAIN ADMINS@ESSOS.LOCAL"}, "Aces": [
+         {"RightName": "AddMember", "IsInherited": false, "PrincipalSID": "S-1-5-21-1-2-3-1104", "PrincipalType": "User"},
+      ], "Members": []}),
+   ];
+   let vec_users = vec![
+      serde_json::json!({"ObjectIdentifier": "S-1-5-21-1-2-3-1110", "Properties": {"name": "SQL_SVC@ESSOS.LOCAL"}, "Aces": []}),
+      serde_json::json!({"ObjectIdentifier": "S-1-5-21-1-2-3-1104", "Properties": {"name": "JON|SNOW@ESSOS.LOCAL"}, "Aces": [
+         {"RightName": "ForceChangePassword", "IsInherited": false, "PrincipalSID": "S-1-5-21-1-2-3-1105", "PrincipalType": "User"},
+      ]}),
+   ];
+   let vec_computers = vec![
+      serde_json::json!({"ObjectIdentifier": "S-1-5-21-1-2-3-1001", "PrimaryGroupSID": "S-1-5-21-1-2-3-515", "Properties": {"name": "SRV01.ESSOS.LOCAL", "unconstraineddelegation": true, "enabled": true}}),
+      serde_json::json!({"ObjectIdentifier": "S-1-5-21-1-2-3-1000", "PrimaryGroupSID": "S-1-5-21-1-2-3-516", "Properties": {"name": "DC01.ESSOS.LOCAL", "unconstraineddelegation": true}}),
+   ];
+   let objects = [("User", &vec_users), ("Group", &vec_groups), ("Computer", &vec_computers), ("Domain", &vec_domains)];
+   let surface = attack_surface(&objects);
+
+   assert_eq!(surface.dcsync.len(), 1);
+   assert_eq!((surface.dcsync[0].name.as_str(), surface.dcsync[0].detail.as_str()), ("SQL_SVC@ESSOS.LOCAL", "GetChanges, GetChangesAll"));
+   assert!(surface.domain_control.is_empty());
+   assert_eq!(surface.unconstrained_delegation.len(), 1);
+   assert_eq!(surface.unconstrained_delegation[0].name, "SRV01.ESSOS.LOCAL");
+
+   let reach: Vec<(&str, &str)> = surface.tier0_reach.iter().map(|principal| (principal.object_identifier.as_str(), principal.detail.as_str())).collect();
+   assert_eq!(reach[0], ("S-1-5-21-1-2-3-1104", "1 hop (AddMember DOMAIN ADMINS@ESSOS.LOCAL)"));
+   assert_eq!(reach[2], ("S-1-5-21-1-2-3-1105", "2 hops (ForceChangePassword JON|SNOW@ESSOS.LOCAL)"));
+
+   let markdown = render_markdown(&"ESSOS.LOCAL".to_string(), &[("User", 2)], &surface);
+   assert!(markdown.contains("## DCSync capable principals (1)"));
+   assert!(markdown.contains("| JON\\|SNOW@ESSOS.LOCAL | User |"));
+   let dot = render_dot(&surface, &HashMap::new());
+   assert!(dot.contains("\"S-1-5-21-1-2-3-1105\" -> \"S-1-5-21-1-2-3-1104\" [label=\"ForceChangePassword\"];"));
+   assert!(dot.contains("\"S-1-5-21-1-2-3-512\" [label=\"S-1-5-21-1-2-3-512\", color=red, fontcolor=red];"));
+}
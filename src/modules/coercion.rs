@@ -0,0 +1,100 @@
+//! Coercion RPC interfaces exposed by the computers (--coercion)
+//!
+//! The authentication coercion techniques call RPC interfaces reachable on named pipes of IPC$:
+//! - MS-RPRN, Print Spooler (PrinterBug): `spoolss`, `spoolerrunning` property,
+//! - MS-EFSR (PetitPotam): `efsrpc`, `efsrpcexposed` property (also reachable on lsarpc, not checked),
+//! - MS-FSRVP (ShadowCoerce): `FssagentRpc` of the File Server VSS Agent service, `fsrvpexposed` property.
+//!
+//! The pipes are opened in one SMB session per computer with the collection credentials, like `--webclient`.
+//! The properties are null when the computer is unreachable, the computers exposing at least one interface are
+//! written in `<dirpath>/<domain>_coercion.json`.
+use crate::banner::Colorize;
+use log::{debug, info};
+use serde_json::value::Value;
+use std::collections::HashMap;
+use std::fs;
+
+use crate::modules::remote::{probe_pipes, remote_targets, run_probes, split_username};
+use crate::secret::SecretString;
+
+/// Named pipes of the coercion interfaces: (pipe, property, interface).
+pub const COERCION_PIPES: [(&str, &str, &str); 3] = [
+   ("spoolss", "spoolerrunning", "MS-RPRN"),
+   ("efsrpc", "efsrpcexposed", "MS-EFSR"),
+   ("FssagentRpc", "fsrvpexposed", "MS-FSRVP"),
+];
+/// Computers probed at the same time.
+pub const COERCION_THREADS: usize = 16;
+
+/// Function to set the coercion properties of one computer, return the interfaces exposed.
+pub fn set_coercion_properties(computer: &mut Value, exposed: &[Option<bool>]) -> Vec<&'static str> {
+   let mut interfaces: Vec<&'static str> = Vec::new();
+   for ((_, property, interface), exists) in COERCION_PIPES.iter().zip(exposed) {
+      computer["Properties"][*property] = (*exists).into();
+      if *exists == Some(true) {
+         interfaces.push(interface);
+      }
+   }
+   interfaces
+}
+
+/// Function to probe the coercion interfaces of the computers and write the computers exposing them.
+pub async fn make_coercion_report(
+   path: &String,
+   domain: &String,
+   username: &String,
+   password: &SecretString,
+   exclude_stale: bool,
+   vec_computers: &mut [Value],
+   fqdn_ip: &HashMap<String, String>,
+) -> std::io::Result<()> {
+   if username.contains("not set") {
+      return Err(std::io::Error::new(std::io::ErrorKind::InvalidInput, "the coercion probe needs -u and -p for the SMB session"));
+   }
+   let (user, user_domain) = split_username(username, domain);
+   let targets = remote_targets(vec_computers, fqdn_ip, exclude_stale);
+   info!("Coercion interfaces probe of {} computers started...", targets.len().to_string().bold());
+   let hosts: Vec<String> = targets.iter().map(|(_, host)| host.to_owned()).collect();
+   let pipes: Vec<&str> = COERCION_PIPES.iter().map(|(pipe, _, _)| *pipe).collect();
+   let password = password.to_owned();
+   // Blocking sockets, out of the tokio workers
+   let results = tokio::task::spawn_blocking(move || run_probes(&hosts, COERCION_THREADS, |host| {
+      probe_pipes(host, &user, &user_domain, &password, &pipes).unwrap_or_else(|err| {
+         debug!("Coercion probe of {} failed. Reason: {err}", host);
+         vec![None; COERCION_PIPES.len()]
+      })
+   })).await.map_err(std::io::Error::other)?;
+
+   let mut exposed: Vec<Value> = Vec::new();
+   for ((index, host), result) in targets.iter().zip(results) {
+      let computer = &mut vec_computers[*index];
+      let interfaces = set_coercion_properties(computer, &result);
+      if !interfaces.is_empty() {
+         info!("{} {}", computer["Properties"]["name"].as_str().unwrap_or("").yellow().bold(), interfaces.join(", "));
+         exposed.push(serde_json::json!({
+            "name": computer["Properties"]["name"],
+            "objectid": computer["ObjectIdentifier"],
+            "host": host,
+            "interfaces": interfaces,
+         }));
+      }
+   }
+   info!("{} computers exposing a coercion interface", exposed.len().to_string().bold());
+
+   fs::create_dir_all(path)?;
+   let final_path = format!("{}/{}_coercion.json", path, domain.replace(".", "-").to_lowercase());
+   fs::write(&final_path, serde_json::to_string_pretty(&exposed).unwrap_or_default())?;
+   info!("{} created!", final_path.bold());
+   Ok(())
+}
+
+/// Test functions
+#[test]
+pub fn test_coercion_properties() {
+   let mut computer = serde_json::json!({"ObjectIdentifier": "S-1-5-21-1-2-3-1000", "Properties": {"name": "MEEREEN.ESSOS.LOCAL"}});
+   let interfaces = set_coercion_properties(&mut computer, &[Some(true), Some(false), None]);
+   assert_eq!(interfaces, vec!["MS-RPRN"]);
+   assert_eq!(computer["Properties"]["spoolerrunning"], true);
+   assert_eq!(computer["Properties"]["efsrpcexposed"], false);
+   assert!(computer["Properties"]["fsrvpexposed"].is_null());
+}
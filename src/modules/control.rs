@@ -0,0 +1,287 @@
+//! Live progress over a local Unix socket (--control-socket)
+//!
+//! Another terminal can query the collection progress or request a graceful stop,
+//! the objects already collected are still parsed and written.
+//!
+//! ```bash
+//! echo status | nc -U /tmp/rusthound.sock
+//! {"phase":"LDAP collection","objects":12000,"expected":50000,"eta":133,"errors":0,"elapsed":42,"stopping":false}
+//! echo stop | nc -U /tmp/rusthound.sock
+//! ```
+use lazy_static::lazy_static;
+use log::{Log, Metadata, Record, Level, debug, info};
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+use crate::secret::scrub;
+use crate::modules::telemetry::{start_phase, log_json, emit_event, log_event};
+
+lazy_static! {
+   static ref PHASE: Mutex<String> = Mutex::new("Starting".to_string());
+   static ref STARTED: Mutex<Instant> = Mutex::new(Instant::now());
+   static ref COLLECTION_STARTED: Mutex<Option<Instant>> = Mutex::new(None);
+}
+static OBJECTS: AtomicU64 = AtomicU64::new(0);
+static EXPECTED: AtomicU64 = AtomicU64::new(0);
+static ERRORS: AtomicU64 = AtomicU64::new(0);
+static STOP: AtomicBool = AtomicBool::new(false);
+
+/// Function to reset the progress of a new run: clock, phase, counters, errors and stop request. A second run in the
+/// same process (library callers) does not inherit the stop request or the errors of the previous one.
+pub fn reset_control() {
+   if let Ok(mut started) = STARTED.lock() {
+      *started = Instant::now();
+   }
+   if let Ok(mut started) = COLLECTION_STARTED.lock() {
+      *started = None;
+   }
+   if let Ok(mut phase) = PHASE.lock() {
+      *phase = "Starting".to_string();
+   }
+   OBJECTS.store(0, Ordering::Relaxed);
+   EXPECTED.store(0, Ordering::Relaxed);
+   ERRORS.store(0, Ordering::Relaxed);
+   STOP.store(false, Ordering::Relaxed);
+}
+
+/// Function to set the current phase of the collection.
+pub fn set_phase(phase: &str) {
+   if let Ok(mut current) = PHASE.lock() {
+      *current = phase.to_string();
+   }
+   start_phase(phase);
+}
+
+/// Function to set the number of LDAP objects collected.
+pub fn set_objects(count: u64) {
+   OBJECTS.store(count, Ordering::Relaxed);
+}
+
+/// Function to set the approximate number of LDAP objects to collect (0 if unknown), the collection starts.
+pub fn set_expected_objects(count: u64) {
+   EXPECTED.store(count, Ordering::Relaxed);
+   if let Ok(mut started) = COLLECTION_STARTED.lock() {
+      *started = Some(Instant::now());
+   }
+}
+
+/// Function to get the approximate number of LDAP objects to collect, 0 if unknown.
+pub fn expected_objects() -> u64 {
+   EXPECTED.load(Ordering::Relaxed)
+}
+
+/// Function to get the seconds left to collect the expected objects at the current rate.
+pub fn eta_seconds(count: u64, expected: u64, elapsed: Duration) -> Option<u64> {
+   if count == 0 || expected == 0 {
+      return None
+   }
+   let left = expected.saturating_sub(count) as f64;
+   Some((left * elapsed.as_secs_f64() / count as f64).round() as u64)
+}
+
+/// Function to format the collection progress: objects, rate and ETA when the total is known.
+pub fn progress_line(count: u64, expected: u64, elapsed: Duration) -> String {
+   let rate = if elapsed.as_secs_f64() > 0.0 { count as f64 / elapsed.as_secs_f64() } else { 0.0 };
+   match eta_seconds(count, expected, elapsed) {
+      Some(eta) => format!("{}/~{} objects collected, {:.0} objects/s, ETA {}m{:02}s", count, expected, rate, eta / 60, eta % 60),
+      None => format!("{} objects collected, {:.0} objects/s", count, rate),
+   }
+}
+
+/// Function to know if a graceful stop was requested.
+pub fn stop_requested() -> bool {
+   STOP.load(Ordering::Relaxed)
+}
+
+/// Function to get the number of errors logged.
+pub fn error_count() -> u64 {
+   ERRORS.load(Ordering::Relaxed)
+}
+
+/// Function to get the progress in JSON.
+pub fn status() -> serde_json::value::Value {
+   let objects = OBJECTS.load(Ordering::Relaxed);
+   let expected = EXPECTED.load(Ordering::Relaxed);
+   let collection = COLLECTION_STARTED.lock().ok().and_then(|started| started.map(|started| started.elapsed()));
+   serde_json::json!({
+      "phase": PHASE.lock().map(|phase| phase.to_owned()).unwrap_or_default(),
+      "objects": objects,
+      "expected": expected,
+      "eta": collection.and_then(|elapsed| eta_seconds(objects, expected, elapsed)),
+      "errors": ERRORS.load(Ordering::Relaxed),
+      "elapsed": STARTED.lock().map(|started| started.elapsed().as_secs()).unwrap_or(0),
+      "stopping": stop_requested(),
+   })
+}
+
+/// Function to answer one command: status, stop or help.
+pub fn handle_command(command: &str) -> String {
+   match command.trim().to_lowercase().as_str() {
+      "status" | "" => status().to_string(),
+      "stop" => {
+         STOP.store(true, Ordering::Relaxed);
+         info!("Stop requested from the control socket, flushing collected objects...");
+         serde_json::json!({"stopping": true}).to_string()
+      },
+      _ => serde_json::json!({"commands": ["status", "stop"]}).to_string(),
+   }
+}
+
+/// Logger counting the errors for the status and scrubbing the secrets, the records are written by env_logger
+/// or as JSON lines with --log-json.
+pub struct ControlLogger {
+   inner: env_logger::Logger,
+}
+
+impl ControlLogger {
+   /// Function to install the logger, replace env_logger Builder::init().
+   pub fn init(inner: env_logger::Logger) {
+      let max_level = inner.filter();
+      if log::set_boxed_logger(Box::new(ControlLogger { inner: inner })).is_ok() {
+         log::set_max_level(max_level);
+      }
+   }
+}
+
+impl Log for ControlLogger {
+   fn enabled(&self, metadata: &Metadata) -> bool {
+      self.inner.enabled(metadata)
+   }
+
+   fn log(&self, record: &Record) {
+      if record.level() == Level::Error && record.target().starts_with("rusthound") {
+         ERRORS.fetch_add(1, Ordering::Relaxed);
+      }
+      // Never write a password in the logs
+      let message = record.args().to_string();
+      let scrubbed = scrub(&message);
+      if log_json() {
+         if self.inner.enabled(record.metadata()) {
+            emit_event(log_event(record, &scrubbed));
+         }
+      } else if scrubbed == message {
+         self.inner.log(record);
+      } else {
+         self.inner.log(&Record::builder()
+            .args(format_args!("{}", scrubbed))
+            .level(record.level())
+            .target(record.target())
+            .module_path(record.module_path())
+            .file(record.file())
+            .line(record.line())
+            .build());
+      }
+   }
+
+   fn flush(&self) {
+      self.inner.flush();
+   }
+}
+
+/// Delay of the listener threads between two checks of a pending client or of their stop.
+const ACCEPT_INTERVAL: Duration = Duration::from_millis(100);
+/// Time given to a control socket client to send its command.
+const CONTROL_CLIENT_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Background thread of a non-blocking listener, stopped and joined when dropped: the listener is closed
+/// at the end of the run and a library caller can open it again in its next run.
+pub struct ListenerThread {
+   stop: Arc<AtomicBool>,
+   thread: Option<std::thread::JoinHandle<()>>,
+}
+
+impl ListenerThread {
+   /// Function to call `accept` in a background thread until the handle is dropped,
+   /// `accept` returns false when no client was waiting.
+   pub fn spawn<F: FnMut() -> bool + Send + 'static>(mut accept: F) -> ListenerThread {
+      let stop = Arc::new(AtomicBool::new(false));
+      let stopped = stop.clone();
+      let thread = std::thread::spawn(move || {
+         while !stopped.load(Ordering::Relaxed) {
+            if !accept() {
+               std::thread::sleep(ACCEPT_INTERVAL);
+            }
+         }
+      });
+      ListenerThread { stop, thread: Some(thread) }
+   }
+}
+
+impl Drop for ListenerThread {
+   fn drop(&mut self) {
+      self.stop.store(true, Ordering::Relaxed);
+      if let Some(thread) = self.thread.take() {
+         let _ = thread.join();
+      }
+   }
+}
+
+/// Function to listen on the control socket in a background thread, until the returned thread is dropped.
+#[cfg(unix)]
+pub fn start_control_socket(path: &String) -> std::io::Result<ListenerThread> {
+   use std::io::{BufRead, BufReader, ErrorKind, Write};
+   use std::os::unix::net::UnixListener;
+
+   let _ = std::fs::remove_file(path);
+   let listener = UnixListener::bind(path)?;
+   listener.set_nonblocking(true)?;
+   info!("Control socket listening on {}", path);
+   Ok(ListenerThread::spawn(move || {
+      let mut stream = match listener.accept() {
+         Ok((stream, _)) => stream,
+         Err(err) if err.kind() == ErrorKind::WouldBlock => return false,
+         Err(err) => {
+            debug!("Control socket client error: {err}");
+            return false
+         }
+      };
+      // One idle client must not hold the socket, nor the end of the run
+      let _ = stream.set_nonblocking(false);
+      let _ = stream.set_read_timeout(Some(CONTROL_CLIENT_TIMEOUT));
+      let _ = stream.set_write_timeout(Some(CONTROL_CLIENT_TIMEOUT));
+      let mut command = String::new();
+      if let Ok(reader) = stream.try_clone() {
+         let _ = BufReader::new(reader).read_line(&mut command);
+      }
+      let _ = writeln!(stream, "{}", handle_command(&command));
+      true
+   }))
+}
+
+#[cfg(not(unix))]
+pub fn start_control_socket(_path: &String) -> std::io::Result<ListenerThread> {
+   Err(std::io::Error::new(std::io::ErrorKind::Unsupported, "control socket only available on Unix systems"))
+}
+
+/// Function to remove the control socket file at the end of the collection.
+pub fn stop_control_socket(path: &String) {
+   if let Err(err) = std::fs::remove_file(path) {
+      debug!("Unable to remove control socket {}: {err}", path);
+   }
+}
+
+/// Test functions
+#[test]
+pub fn test_handle_command() {
+   set_phase("LDAP collection");
+   set_objects(42);
+   let status: serde_json::value::Value = serde_json::from_str(&handle_command("status\n")).unwrap();
+   assert_eq!(status["phase"], "LDAP collection");
+   assert_eq!(status["objects"], 42);
+   assert!(handle_command("help").contains("commands"));
+   reset_control();
+   let status = self::status();
+   assert_eq!(status["objects"], 0);
+   assert_eq!(status["phase"], "Starting");
+   assert!(!stop_requested());
+}
+
+#[test]
+pub fn test_progress_line() {
+   assert_eq!(eta_seconds(1000, 4000, Duration::from_secs(10)), Some(30));
+   assert_eq!(eta_seconds(5000, 4000, Duration::from_secs(10)), Some(0));
+   assert_eq!(eta_seconds(1000, 0, Duration::from_secs(10)), None);
+   assert_eq!(progress_line(1000, 4000, Duration::from_secs(10)), "1000/~4000 objects collected, 100 objects/s, ETA 0m30s");
+   assert_eq!(progress_line(1000, 0, Duration::from_secs(4)), "1000 objects collected, 250 objects/s");
+}
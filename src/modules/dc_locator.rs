@@ -0,0 +1,176 @@
+//! Domain controller discovery with the DNS SRV records (DC locator)
+//!
+//! Without `-i` or `-f`, the DCs are read from `_ldap._tcp.<site>._sites.dc._msdcs.<domain>` with `--site`,
+//! then from `_ldap._tcp.dc._msdcs.<domain>`, sorted by priority and weight, and the first DC answering on
+//! the LDAP port is used. `--dc` pins a DC by name or IP address without any DNS query.
+//! <https://learn.microsoft.com/en-us/troubleshoot/windows-server/networking/verify-srv-dns-records-have-been-created>
+use log::{debug, info, warn};
+use crate::banner::Colorize;
+
+use trust_dns_resolver::TokioAsyncResolver;
+use trust_dns_resolver::config::*;
+
+use std::net::{IpAddr, SocketAddr};
+use std::time::Duration;
+use tokio::net::TcpStream;
+
+/// Timeout of the TCP connection to know if a DC is reachable.
+const REACHABLE_TIMEOUT_S: u64 = 3;
+
+/// One DC from a SRV record.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SrvDc {
+   pub priority: u16,
+   pub weight: u16,
+   pub target: String,
+   pub port: u16,
+}
+
+/// Function to get the SRV names to query, the site first.
+pub fn srv_names(domain: &String, site: &String) -> Vec<String> {
+   let domain = domain.to_lowercase();
+   let mut names: Vec<String> = Vec::new();
+   if !site.contains("not set") {
+      names.push(format!("_ldap._tcp.{}._sites.dc._msdcs.{}.", site, domain));
+   }
+   names.push(format!("_ldap._tcp.dc._msdcs.{}.", domain));
+   names
+}
+
+/// Function to sort the DCs: lowest priority first, then highest weight, without duplicates.
+pub fn sort_srv(mut dcs: Vec<SrvDc>) -> Vec<SrvDc> {
+   dcs.sort_by(|a, b| a.priority.cmp(&b.priority).then(b.weight.cmp(&a.weight)));
+   let mut sorted: Vec<SrvDc> = Vec::new();
+   for dc in dcs {
+      if !sorted.iter().any(|known| known.target == dc.target) {
+         sorted.push(dc);
+      }
+   }
+   sorted
+}
+
+/// Function to build the resolver: the --name-server or the system configuration.
+fn dns_resolver(dns_tcp: bool, name_server: &String) -> Option<TokioAsyncResolver> {
+   let resolver = match name_server.parse::<IpAddr>() {
+      Ok(address) if !name_server.contains("127.0.0.1") => {
+         let mut c = ResolverConfig::new();
+         c.add_name_server(NameServerConfig {
+            socket_addr: SocketAddr::new(address, 53),
+            protocol: if dns_tcp { Protocol::Tcp } else { Protocol::Udp },
+            tls_dns_name: None,
+            trust_nx_responses: false,
+            bind_addr: None,
+         });
+         TokioAsyncResolver::tokio(c, ResolverOpts::default())
+      }
+      _ => TokioAsyncResolver::tokio_from_system_conf(),
+   };
+   match resolver {
+      Ok(resolver) => Some(resolver),
+      Err(err) => {
+         warn!("Can't build the DNS resolver. Reason: {err}");
+         None
+      }
+   }
+}
+
+/// Function to read the DCs of the domain from the SRV records, the DCs of the site first.
+pub async fn discover_dcs(dns_tcp: bool, name_server: &String, domain: &String, site: &String) -> Vec<SrvDc> {
+   let resolver = match dns_resolver(dns_tcp, name_server) {
+      Some(resolver) => resolver,
+      None => return Vec::new(),
+   };
+   let mut dcs: Vec<SrvDc> = Vec::new();
+   for name in srv_names(domain, site) {
+      debug!("Resolving SRV {}", &name);
+      match resolver.srv_lookup(name.to_owned()).await {
+         Ok(records) => {
+            let found = sort_srv(records.iter().map(|srv| SrvDc {
+               priority: srv.priority(),
+               weight: srv.weight(),
+               target: srv.target().to_utf8().trim_end_matches('.').to_lowercase(),
+               port: srv.port(),
+            }).collect());
+            debug!("{} DCs in {}", found.len(), &name);
+            for dc in found {
+               if !dcs.iter().any(|known| known.target == dc.target) {
+                  dcs.push(dc);
+               }
+            }
+         }
+         Err(err) => debug!("No SRV record {}. Reason: {err}", &name),
+      }
+   }
+   dcs
+}
+
+/// Function to get the first DC answering on the LDAP port, return its FQDN and IP address.
+pub async fn reachable_dc(dcs: &Vec<SrvDc>, ldap_port: u16) -> Option<(String, String)> {
+   for dc in dcs {
+      let connect = TcpStream::connect((dc.target.as_str(), ldap_port));
+      match tokio::time::timeout(Duration::from_secs(REACHABLE_TIMEOUT_S), connect).await {
+         Ok(Ok(stream)) => {
+            let ip = stream.peer_addr().map(|address| address.ip().to_string()).unwrap_or_else(|_| dc.target.to_owned());
+            return Some((dc.target.to_owned(), ip))
+         }
+         Ok(Err(err)) => debug!("{} is not reachable on {}. Reason: {err}", &dc.target, ldap_port),
+         Err(_) => debug!("{} is not reachable on {}. Reason: timeout", &dc.target, ldap_port),
+      }
+   }
+   None
+}
+
+/// Function to choose the DC when no -i/-f is given, or the pinned --dc.
+/// Return the new (ip, ldapfqdn) or None to keep the command line ones.
+pub async fn locate_dc(
+   dc: &String,
+   ip: &String,
+   ldapfqdn: &String,
+   domain: &String,
+   site: &String,
+   ldaps: bool,
+   port: &String,
+   dns_tcp: bool,
+   name_server: &String,
+) -> Option<(String, String)> {
+   if !dc.contains("not set") {
+      info!("Using the pinned DC {}", dc.bold());
+      return match dc.parse::<IpAddr>() {
+         Ok(_) => Some((dc.to_owned(), ldapfqdn.to_owned())),
+         Err(_) => Some((dc.to_owned(), dc.to_lowercase())),
+      }
+   }
+   if !ip.contains("not set") || !ldapfqdn.contains("not set") {
+      return None
+   }
+   let ldap_port = port.parse::<u16>().unwrap_or(if ldaps { 636 } else { 389 });
+   let dcs = discover_dcs(dns_tcp, name_server, domain, site).await;
+   if dcs.is_empty() {
+      warn!("No DC found in the SRV records of {}, connecting to the domain name", domain.bold());
+      return None
+   }
+   info!("{} DCs found in the SRV records of {}", dcs.len().to_string().bold(), domain.bold());
+   match reachable_dc(&dcs, ldap_port).await {
+      Some((fqdn, ip)) => {
+         info!("Using the DC {} ({})", fqdn.bold(), ip);
+         Some((ip, fqdn))
+      }
+      None => {
+         warn!("No DC of {} reachable on port {}, connecting to the domain name", domain.bold(), ldap_port);
+         None
+      }
+   }
+}
+
+/// Test functions
+#[test]
+pub fn test_dc_locator() {
+   let domain = "ESSOS.LOCAL".to_string();
+   assert_eq!(srv_names(&domain, &"not set".to_string()), vec!["_ldap._tcp.dc._msdcs.essos.local."]);
+   assert_eq!(srv_names(&domain, &"Braavos".to_string())[0], "_ldap._tcp.Braavos._sites.dc._msdcs.essos.local.");
+
+   let dc = |priority: u16, weight: u16, target: &str| SrvDc { priority, weight, target: target.to_string(), port: 389 };
+   let sorted = sort_srv(vec![dc(10, 100, "dc03"), dc(0, 50, "dc01"), dc(0, 100, "dc02"), dc(0, 100, "dc01")]);
+   let targets: Vec<&str> = sorted.iter().map(|dc| dc.target.as_str()).collect();
+   assert_eq!(targets, vec!["dc02", "dc01", "dc03"]);
+}
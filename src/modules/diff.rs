@@ -0,0 +1,222 @@
+//! Changes between two collections (--diff)
+//!
+//! The objects of the run are compared with the JSON files (or the zip) of a previous run in `<old_dir>`,
+//! by ObjectIdentifier, and the report is written in `<dirpath>/<domain>_diff.json`:
+//!
+//! ```text
+//! {"old": "old_dir", "summary": {"added": 1, "removed": 0, "changed": 1},
+//!  "added": [{"id": "S-1-5-21-...-1601", "type": "User", "name": "SAMWELL.TARLY@ESSOS.LOCAL"}],
+//!  "removed": [],
+//!  "changed": [{"id": "S-1-5-21-...-512", "type": "Group", "name": "DOMAIN ADMINS@ESSOS.LOCAL",
+//!               "properties": {"description": {"old": null, "new": "..."}},
+//!               "edges_added": [{"source": "S-1-5-21-...-1601", "edge": "GenericAll", "target": "S-1-5-21-...-512"}],
+//!               "edges_removed": []}]}
+//! ```
+//!
+//! The edges are the ACEs, memberships, delegations and links of the object, so a new GenericAll on a group
+//! (ACL drift) is listed on the group. The logon timestamps change at every logon and are not compared.
+use crate::banner::Colorize;
+use log::{debug, info, warn};
+use serde_json::value::Value;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs::{self, File};
+use std::io::{Read, Result};
+use std::path::Path;
+
+use crate::json::maker::csv::object_edges;
+
+/// Properties not compared, they change without any change of the object.
+pub const DIFF_IGNORED_PROPERTIES: [&str; 2] = ["lastlogon", "lastlogontimestamp"];
+
+/// Node type of the objects of one JSON file, from the meta type of the file.
+pub fn node_type(data_type: &str) -> Option<&'static str> {
+   match data_type {
+      "users" => Some("User"),
+      "groups" => Some("Group"),
+      "computers" => Some("Computer"),
+      "ous" => Some("OU"),
+      "domains" => Some("Domain"),
+      "gpos" => Some("GPO"),
+      "containers" => Some("Container"),
+      _ => None,
+   }
+}
+
+/// Function to add the objects of one BloodHound JSON file, the other JSON files are ignored.
+fn add_json_file(content: &str, objects: &mut BTreeMap<String, (String, Value)>) {
+   let json: Value = match serde_json::from_str(content) {
+      Ok(json) => json,
+      Err(_) => return,
+   };
+   let object_type = match json["meta"]["type"].as_str().and_then(node_type) {
+      Some(object_type) => object_type,
+      None => return,
+   };
+   for object in json["data"].as_array().cloned().unwrap_or_default() {
+      if let Some(id) = object["ObjectIdentifier"].as_str() {
+         objects.insert(id.to_string(), (object_type.to_string(), object));
+      }
+   }
+}
+
+/// Function to load the objects of a previous run: its JSON files and zip files, by ObjectIdentifier.
+pub fn load_collection(dir: &String) -> Result<BTreeMap<String, (String, Value)>> {
+   let mut objects: BTreeMap<String, (String, Value)> = BTreeMap::new();
+   for entry in fs::read_dir(dir)? {
+      let file = entry?.path();
+      match file.extension().and_then(|ext| ext.to_str()) {
+         Some("json") => add_json_file(&fs::read_to_string(&file)?, &mut objects),
+         Some("zip") => {
+            let mut archive = zip::ZipArchive::new(File::open(&file)?)?;
+            for index in 0..archive.len() {
+               let mut zipped = archive.by_index(index)?;
+               if !zipped.name().ends_with(".json") {
+                  continue
+               }
+               let mut content = String::new();
+               zipped.read_to_string(&mut content)?;
+               add_json_file(&content, &mut objects);
+            }
+         }
+         Some("enc") => warn!("{} is encrypted, decrypt it with --decrypt to compare it", file.display()),
+         _ => {}
+      }
+   }
+   debug!("{} objects loaded from {}", objects.len(), dir);
+   Ok(objects)
+}
+
+fn edge_set(object: &Value, object_type: &str) -> BTreeSet<(String, String, String)> {
+   object_edges(object, object_type).into_iter().map(|edge| (edge.source, edge.edge, edge.target)).collect()
+}
+
+fn edges_to_json(edges: Vec<&(String, String, String)>) -> Value {
+   edges.into_iter()
+      .map(|(source, edge, target)| serde_json::json!({"source": source, "edge": edge, "target": target}))
+      .collect::<Vec<Value>>()
+      .into()
+}
+
+fn summary(id: &str, object_type: &str, object: &Value) -> Value {
+   serde_json::json!({"id": id, "type": object_type, "name": object["Properties"]["name"]})
+}
+
+/// Function to compare the objects of a previous run with the objects of this run.
+pub fn diff_collections(old: &BTreeMap<String, (String, Value)>, new: &[(&str, &Vec<Value>)]) -> Value {
+   let mut current: BTreeMap<&str, (&str, &Value)> = BTreeMap::new();
+   for (object_type, objects) in new {
+      for object in objects.iter() {
+         if let Some(id) = object["ObjectIdentifier"].as_str() {
+            current.insert(id, (object_type, object));
+         }
+      }
+   }
+
+   let mut added: Vec<Value> = Vec::new();
+   let mut changed: Vec<Value> = Vec::new();
+   for (id, (object_type, object)) in &current {
+      let old_object = match old.get(*id) {
+         Some((_, old_object)) => old_object,
+         None => {
+            added.push(summary(id, object_type, object));
+            continue
+         }
+      };
+
+      // Properties added, removed or modified
+      let mut properties = serde_json::Map::new();
+      let empty = serde_json::Map::new();
+      let new_properties = object["Properties"].as_object().unwrap_or(&empty);
+      let old_properties = old_object["Properties"].as_object().unwrap_or(&empty);
+      let names: BTreeSet<&String> = new_properties.keys().chain(old_properties.keys()).collect();
+      for name in names {
+         if DIFF_IGNORED_PROPERTIES.contains(&name.as_str()) {
+            continue
+         }
+         let (old_value, new_value) = (&old_object["Properties"][name.as_str()], &object["Properties"][name.as_str()]);
+         if old_value != new_value {
+            properties.insert(name.to_owned(), serde_json::json!({"old": old_value, "new": new_value}));
+         }
+      }
+
+      // Edges added or removed
+      let (old_edges, new_edges) = (edge_set(old_object, object_type), edge_set(object, object_type));
+      let edges_added: Vec<&(String, String, String)> = new_edges.difference(&old_edges).collect();
+      let edges_removed: Vec<&(String, String, String)> = old_edges.difference(&new_edges).collect();
+
+      if !properties.is_empty() || !edges_added.is_empty() || !edges_removed.is_empty() {
+         let mut change = summary(id, object_type, object);
+         change["properties"] = properties.into();
+         change["edges_added"] = edges_to_json(edges_added);
+         change["edges_removed"] = edges_to_json(edges_removed);
+         changed.push(change);
+      }
+   }
+   let removed: Vec<Value> = old.iter()
+      .filter(|(id, _)| !current.contains_key(id.as_str()))
+      .map(|(id, (object_type, object))| summary(id, object_type, object))
+      .collect();
+
+   serde_json::json!({
+      "summary": {"added": added.len(), "removed": removed.len(), "changed": changed.len()},
+      "added": added,
+      "removed": removed,
+      "changed": changed,
+   })
+}
+
+/// Function to write the changes since the run in old_dir.
+pub fn make_diff_report(path: &String, domain: &String, old_dir: &String, objects: &[(&str, &Vec<Value>)]) -> Result<()> {
+   if !Path::new(old_dir).is_dir() {
+      return Err(std::io::Error::new(std::io::ErrorKind::NotFound, format!("{} is not a directory", old_dir)))
+   }
+   let old = load_collection(old_dir)?;
+   if old.is_empty() {
+      warn!("No BloodHound JSON file in {}, every object is added", old_dir);
+   }
+   let mut report = diff_collections(&old, objects);
+   report["old"] = old_dir.to_owned().into();
+   info!("Since {}: {} objects added, {} removed, {} changed",
+      old_dir, report["summary"]["added"], report["summary"]["removed"], report["summary"]["changed"]);
+
+   fs::create_dir_all(path)?;
+   let final_path = format!("{}/{}_diff.json", path, domain.replace(".", "-").to_lowercase());
+   fs::write(&final_path, serde_json::to_string_pretty(&report).unwrap_or_default())?;
+   info!("{} created!", final_path.bold());
+   Ok(())
+}
+
+/// Test functions
+#[test]
+pub fn test_diff_collections() {
+   let mut old: BTreeMap<String, (String, Value)> = BTreeMap::new();
+   add_json_file(&serde_json::json!({
+      "data": [
+         {"ObjectIdentifier": "S-1-5-21-1-2-3-512", "Properties": {"name": "DOMAIN ADMINS@ESSOS.LOCAL", "admincount": true},
+            "Aces": [{"RightName": "Owns", "IsInherited": false, "PrincipalSID": "S-1-5-21-1-2-3-512", "PrincipalType": "Group"}]},
+         {"ObjectIdentifier": "S-1-5-21-1-2-3-1104", "Properties": {"name": "JON.SNOW@ESSOS.LOCAL", "lastlogon": 1}},
+         {"ObjectIdentifier": "S-1-5-21-1-2-3-1105", "Properties": {"name": "SAM@ESSOS.LOCAL"}},
+      ],
+      "meta": {"methods": 0, "type": "groups", "count": 3, "version": 5}
+   }).to_string(), &mut old);
+   // Not a BloodHound file
+   add_json_file("[{\"dn\": \"CN=CORRUPT,DC=ESSOS,DC=LOCAL\"}]", &mut old);
+   assert_eq!(old.len(), 3);
+   assert_eq!(old["S-1-5-21-1-2-3-1104"].0, "Group");
+
+   let groups = vec![
+      serde_json::json!({"ObjectIdentifier": "S-1-5-21-1-2-3-512", "Properties": {"name": "DOMAIN ADMINS@ESSOS.LOCAL", "admincount": true, "description": "Admins"},
+         "Aces": [{"RightName": "GenericAll", "IsInherited": false, "PrincipalSID": "S-1-5-21-1-2-3-1601", "PrincipalType": "User"}]}),
+      serde_json::json!({"ObjectIdentifier": "S-1-5-21-1-2-3-1104", "Properties": {"name": "JON.SNOW@ESSOS.LOCAL", "lastlogon": 2}}),
+      serde_json::json!({"ObjectIdentifier": "S-1-5-21-1-2-3-1601", "Properties": {"name": "SAMWELL.TARLY@ESSOS.LOCAL"}}),
+   ];
+   let report = diff_collections(&old, &[("Group", &groups)]);
+   assert_eq!(report["summary"], serde_json::json!({"added": 1, "removed": 1, "changed": 1}));
+   assert_eq!(report["added"][0]["name"], "SAMWELL.TARLY@ESSOS.LOCAL");
+   assert_eq!(report["removed"][0]["id"], "S-1-5-21-1-2-3-1105");
+   let change = &report["changed"][0];
+   assert_eq!(change["id"], "S-1-5-21-1-2-3-512");
+   assert_eq!(change["properties"], serde_json::json!({"description": {"old": null, "new": "Admins"}}));
+   assert_eq!(change["edges_added"], serde_json::json!([{"source": "S-1-5-21-1-2-3-1601", "edge": "GenericAll", "target": "S-1-5-21-1-2-3-512"}]));
+   assert_eq!(change["edges_removed"][0]["edge"], "Owns");
+}
@@ -0,0 +1,167 @@
+//! Provenance of every emitted edge (--edge-provenance)
+//!
+//! To audit why RustHound claims an edge that SharpHound doesn't, each edge of the JSON files is written in
+//! `<dirpath>/<domain>_edge_provenance.json` with what produced it:
+//! - the ACL edges: the ACE of the nTSecurityDescriptor (index in the DACL, type, flags, access mask, object type
+//!   GUID and inherited object type GUID) or the owner,
+//! - the DCSync and SyncLAPSPassword edges: the GetChanges ACEs on the domain,
+//! - the other edges: the LDAP attribute (member, msDS-AllowedToDelegateTo, gPLink...) or the remote collection.
+//!
+//! The ACE details are kept on the edges until the end of the run and removed before the JSON files are written.
+//! With `--repl-metadata` the provenance also has the time and the DC of the last change of the descriptor or the
+//! attribute (`LastChanged`, `LastChangedDC`), and for the MemberOf edges the creation of the member value.
+use crate::banner::Colorize;
+use log::info;
+use serde_json::value::Value;
+use std::collections::HashMap;
+use std::fs;
+
+use crate::enums::replmeta::member_change;
+use crate::json::maker::csv::object_edges;
+
+/// Source of the edges built from an attribute or the remote collection.
+pub const EDGE_SOURCES: [(&str, &str); 13] = [
+   ("MemberOf", "member"),
+   ("AllowedToDelegate", "msDS-AllowedToDelegateTo"),
+   ("AllowedToAct", "msDS-AllowedToActOnBehalfOfOtherIdentity"),
+   ("HasSIDHistory", "sIDHistory"),
+   ("Contains", "distinguishedName"),
+   ("GPLink", "gPLink"),
+   ("SQLAdmin", "servicePrincipalName"),
+   ("HasSPNConfigured", "servicePrincipalName"),
+   ("AdminTo", "remote collection"),
+   ("CanRDP", "remote collection"),
+   ("ExecuteDCOM", "remote collection"),
+   ("CanPSRemote", "remote collection"),
+   ("HasSession", "remote collection"),
+];
+
+/// Function to add the last change of an attribute of the object (--repl-metadata) to a provenance.
+fn add_last_change(provenance: &mut Value, properties: &Value, attribute: &str) {
+   let property = attribute.to_lowercase();
+   if let Some(time) = properties.get(format!("{}lastchanged", property)) {
+      provenance["LastChanged"] = time.to_owned();
+      provenance["LastChangedDC"] = properties[format!("{}lastchangeddc", property)].to_owned();
+   }
+}
+
+/// Function to get the provenance of the edges and remove it from the ACEs.
+pub fn edge_provenance(vec_objects: &mut [(&str, &mut Vec<Value>)]) -> Vec<Value> {
+   let mut edges: Vec<Value> = Vec::new();
+   // ObjectIdentifier => distinguishedName, to find the member values of the MemberOf edges
+   let dns: HashMap<String, String> = vec_objects.iter()
+      .flat_map(|(_, objects)| objects.iter())
+      .filter_map(|object| Some((object["ObjectIdentifier"].as_str()?.to_string(), object["Properties"]["distinguishedname"].as_str()?.to_string())))
+      .collect();
+   for (object_type, objects) in vec_objects.iter_mut() {
+      for object in objects.iter_mut() {
+         let id = object["ObjectIdentifier"].to_owned();
+         let properties = object["Properties"].to_owned();
+         if let Some(aces) = object["Aces"].as_array_mut() {
+            for ace in aces.iter_mut() {
+               let mut provenance = ace.as_object_mut().and_then(|ace| ace.remove("Provenance")).unwrap_or_else(|| {
+                  match ace["RightName"].as_str().unwrap_or("") {
+                     "DCSync" | "SyncLAPSPassword" => serde_json::json!({ "Source": "GetChanges ACEs on the domain" }),
+                     _ => serde_json::json!({ "Source": "synthetic edge" }),
+                  }
+               });
+               if provenance["Source"] == "nTSecurityDescriptor" {
+                  add_last_change(&mut provenance, &properties, "nTSecurityDescriptor");
+               }
+               edges.push(serde_json::json!({
+                  "source": ace["PrincipalSID"],
+                  "edge": ace["RightName"],
+                  "target": id,
+                  "targettype": object_type,
+                  "provenance": provenance,
+               }));
+            }
+         }
+
+         // The other edges, without the ACEs already done
+         let aces = object.get_mut("Aces").map(Value::take);
+         for edge in object_edges(object, object_type) {
+            let source = EDGE_SOURCES.iter().find(|(name, _)| *name == edge.edge).map(|(_, source)| *source).unwrap_or("servicePrincipalName");
+            let mut provenance = serde_json::json!({ "Source": source });
+            let value = match (source, properties["distinguishedname"].as_str(), dns.get(&edge.source)) {
+               ("member", Some(group), Some(member)) => member_change(group, member),
+               _ => None,
+            };
+            match value {
+               Some(value) => {
+                  provenance["Created"] = value.created.into();
+                  provenance["LastChanged"] = value.last_change.into();
+                  provenance["LastChangedDC"] = value.dc.into();
+               }
+               None => add_last_change(&mut provenance, &properties, source),
+            }
+            edges.push(serde_json::json!({
+               "source": edge.source,
+               "edge": edge.edge,
+               "target": edge.target,
+               "targettype": edge.target_type,
+               "provenance": provenance,
+            }));
+         }
+         if let Some(aces) = aces {
+            object["Aces"] = aces;
+         }
+      }
+   }
+   edges
+}
+
+/// Function to write the provenance sidecar file.
+pub fn make_edge_provenance_report(path: &String, domain: &String, vec_objects: &mut [(&str, &mut Vec<Value>)]) -> std::io::Result<()> {
+   let edges = edge_provenance(vec_objects);
+   fs::create_dir_all(path)?;
+   let final_path = format!("{}/{}_edge_provenance.json", path, domain.replace(".", "-").to_lowercase());
+   fs::write(&final_path, serde_json::to_string_pretty(&edges).unwrap_or_default())?;
+   info!("{} created!", final_path.bold());
+   Ok(())
+}
+
+/// Test functions
+#[test]
+pub fn test_edge_provenance() {
+   let mut vec_groups = vec![
+      serde_json::json!({"ObjectIdentifier": "S-1-5-21-1-2-3-512", "Properties": {"name": "DOMAIN ADMINS@ESSOS.LOCAL"},
+         "Members": [{"ObjectIdentifier": "S-1-5-21-1-2-3-1104", "ObjectType": "User"}],
+         "Aces": [{"RightName": "AddMember", "IsInherited": false, "PrincipalSID": "S-1-5-21-1-2-3-1105", "PrincipalType": "User",
+            "Provenance": {"Source": "nTSecurityDescriptor", "AceIndex": 4, "AceType": "0x05", "AccessMask": "0x00000020",
+               "ObjectType": "bf9679c0-0de6-11d0-a285-00aa003049e2"}}]}),
+   ];
+   let mut vec_domains = vec![
+      serde_json::json!({"ObjectIdentifier": "S-1-5-21-1-2-3", "Properties": {"name": "ESSOS.LOCAL"},
+         "Aces": [{"RightName": "DCSync", "IsInherited": false, "PrincipalSID": "S-1-5-21-1-2-3-1105", "PrincipalType": "User"}]}),
+   ];
+   let edges = edge_provenance(&mut [("Group", &mut vec_groups), ("Domain", &mut vec_domains)]);
+   assert_eq!(edges.len(), 3);
+   assert_eq!(edges[0]["edge"], "AddMember");
+   assert_eq!(edges[0]["provenance"]["AceIndex"], 4);
+   assert_eq!(edges[1]["edge"], "MemberOf");
+   assert_eq!(edges[1]["provenance"]["Source"], "member");
+   assert_eq!(edges[2]["provenance"]["Source"], "GetChanges ACEs on the domain");
+   assert!(edges[1]["provenance"].get("LastChanged").is_none());
+   // Removed from the JSON files, the ACEs are kept
+   assert!(vec_groups[0]["Aces"][0].get("Provenance").is_none());
+   assert_eq!(vec_groups[0]["Aces"][0]["RightName"], "AddMember");
+}
+
+#[test]
+pub fn test_edge_provenance_last_change() {
+   let mut vec_users = vec![
+      serde_json::json!({"ObjectIdentifier": "S-1-5-21-1-2-3-1110", "Properties": {"name": "SQL_SVC@ESSOS.LOCAL",
+         "ntsecuritydescriptorlastchanged": 1696161600, "ntsecuritydescriptorlastchangeddc": "MEEREEN",
+         "serviceprincipalnamelastchanged": 1696000000, "serviceprincipalnamelastchangeddc": "BRAAVOS"},
+         "SPNTargets": [{"ComputerSID": "S-1-5-21-1-2-3-1001", "Port": 1433, "Service": "SQLAdmin"}],
+         "Aces": [{"RightName": "GenericAll", "IsInherited": false, "PrincipalSID": "S-1-5-21-1-2-3-1105", "PrincipalType": "User",
+            "Provenance": {"Source": "nTSecurityDescriptor", "AceIndex": 0}}]}),
+   ];
+   let edges = edge_provenance(&mut [("User", &mut vec_users)]);
+   assert_eq!(edges.len(), 2);
+   assert_eq!(edges[0]["provenance"]["LastChanged"], 1696161600);
+   assert_eq!(edges[0]["provenance"]["LastChangedDC"], "MEEREEN");
+   assert_eq!(edges[1]["edge"], "SQLAdmin");
+   assert_eq!(edges[1]["provenance"]["LastChangedDC"], "BRAAVOS");
+}
@@ -0,0 +1,237 @@
+//! Encryption of the output files at rest (--encrypt-output, --decrypt)
+//!
+//! At the end of the run, every file written in the output directory is replaced by an encrypted file.
+//!
+//! With `--encrypt-output <recipient.pub>` the files are encrypted for the age X25519 recipients of the file
+//! (one `age1...` per line, # comments) in `<file>.age`, only the holders of the identities can read them:
+//! `age -d -i key.txt <file>.age`. Nothing on the collecting host can decrypt them.
+//!
+//! With `--encrypt-output` alone the password is read from RUSTHOUND_ENCRYPT_PASSWORD, `--encrypt-password-file`
+//! or a prompt, never from the command line, and the files are replaced by `<file>.enc`:
+//!
+//! ```text
+//! "RHENC1" | salt (16) | PBKDF2 iterations (u32 BE) | nonce (12) | AES-256-GCM ciphertext and tag
+//! ```
+//!
+//! The key is derived from the password with PBKDF2-HMAC-SHA256, the header is authenticated with the data.
+//! The iterations of a file are capped at PBKDF2_MAX_ITERATIONS, a forged header can not hang the decryption.
+//! Decrypt with `rusthound --decrypt <file>.enc`, same password sources.
+//! The plaintext files are removed once encrypted, not wiped: use an encrypted or in-memory filesystem when the
+//! disk itself can not be trusted.
+use crate::banner::Colorize;
+use log::info;
+use ring::aead::{Aad, LessSafeKey, Nonce, UnboundKey, AES_256_GCM, NONCE_LEN};
+use ring::pbkdf2;
+use ring::rand::{SecureRandom, SystemRandom};
+use std::fs;
+use std::io::{Error, ErrorKind, Result, Write};
+use std::num::NonZeroU32;
+use std::str::FromStr;
+use std::time::SystemTime;
+
+use zeroize::Zeroizing;
+
+use crate::secret::SecretString;
+
+/// Magic of the encrypted files.
+pub const ENCRYPTED_MAGIC: &[u8; 6] = b"RHENC1";
+/// Extension of the encrypted files.
+pub const ENCRYPTED_EXTENSION: &str = "enc";
+/// Extension of the files encrypted for age recipients.
+pub const AGE_EXTENSION: &str = "age";
+/// PBKDF2-HMAC-SHA256 iterations (OWASP 2023 recommendation).
+pub const PBKDF2_ITERATIONS: u32 = 600_000;
+/// Most PBKDF2 iterations read from the header of a file.
+pub const PBKDF2_MAX_ITERATIONS: u32 = 10_000_000;
+const SALT_LEN: usize = 16;
+const HEADER_LEN: usize = 6 + SALT_LEN + 4 + NONCE_LEN;
+
+/// Key of --encrypt-output: a password, or the age recipients of a recipient.pub file.
+#[derive(Clone)]
+pub enum OutputKey {
+   Password(SecretString),
+   Recipients(Vec<age::x25519::Recipient>),
+}
+
+impl OutputKey {
+   /// Function to encrypt data with the password or for the recipients.
+   pub fn encrypt(&self, plaintext: &[u8]) -> Result<Vec<u8>> {
+      match self {
+         OutputKey::Password(password) => encrypt(plaintext, password),
+         OutputKey::Recipients(recipients) => encrypt_to_recipients(plaintext, recipients),
+      }
+   }
+
+   /// Extension of the encrypted files.
+   pub fn extension(&self) -> &'static str {
+      match self {
+         OutputKey::Password(_) => ENCRYPTED_EXTENSION,
+         OutputKey::Recipients(_) => AGE_EXTENSION,
+      }
+   }
+}
+
+/// Function to get the key of --encrypt-output, None when the output is not encrypted.
+pub fn output_key(encrypt_output: &String, password: &Option<SecretString>) -> Result<Option<OutputKey>> {
+   match encrypt_output.as_str() {
+      "not set" => Ok(None),
+      "password" => match password {
+         Some(password) if !password.expose().is_empty() => Ok(Some(OutputKey::Password(password.to_owned()))),
+         _ => Err(Error::new(ErrorKind::InvalidInput, "no password for --encrypt-output")),
+      },
+      path => Ok(Some(OutputKey::Recipients(parse_recipients(&fs::read_to_string(path)?)?))),
+   }
+}
+
+/// Function to parse the age X25519 recipients of a recipient.pub file, one per line.
+pub fn parse_recipients(content: &str) -> Result<Vec<age::x25519::Recipient>> {
+   let recipients = content.lines()
+      .map(|line| line.trim())
+      .filter(|line| !line.is_empty() && !line.starts_with('#'))
+      .map(|line| age::x25519::Recipient::from_str(line)
+         .map_err(|err| Error::new(ErrorKind::InvalidData, format!("invalid age recipient {}: {}", line, err))))
+      .collect::<Result<Vec<age::x25519::Recipient>>>()?;
+   if recipients.is_empty() {
+      return Err(Error::new(ErrorKind::InvalidData, "no age recipient"))
+   }
+   Ok(recipients)
+}
+
+/// Function to encrypt data for age recipients, in the age format.
+pub fn encrypt_to_recipients(plaintext: &[u8], recipients: &[age::x25519::Recipient]) -> Result<Vec<u8>> {
+   let encryptor = age::Encryptor::with_recipients(recipients.iter().map(|recipient| recipient as &dyn age::Recipient))
+      .map_err(|err| Error::new(ErrorKind::InvalidInput, err.to_string()))?;
+   let mut data: Vec<u8> = Vec::with_capacity(plaintext.len() + 256);
+   let mut writer = encryptor.wrap_output(&mut data)?;
+   writer.write_all(plaintext)?;
+   writer.finish()?;
+   Ok(data)
+}
+
+fn derive_key(password: &SecretString, salt: &[u8], iterations: u32) -> Result<LessSafeKey> {
+   let iterations = NonZeroU32::new(iterations).ok_or_else(|| Error::new(ErrorKind::InvalidData, "Invalid PBKDF2 iterations"))?;
+   // The derived key is zeroed when dropped
+   let mut key = Zeroizing::new([0u8; 32]);
+   pbkdf2::derive(pbkdf2::PBKDF2_HMAC_SHA256, iterations, salt, password.expose().as_bytes(), key.as_mut());
+   let key = UnboundKey::new(&AES_256_GCM, key.as_ref()).map_err(|_| Error::new(ErrorKind::InvalidInput, "Invalid AES key"))?;
+   Ok(LessSafeKey::new(key))
+}
+
+/// Function to encrypt data with a password.
+pub fn encrypt(plaintext: &[u8], password: &SecretString) -> Result<Vec<u8>> {
+   encrypt_with_iterations(plaintext, password, PBKDF2_ITERATIONS)
+}
+
+fn encrypt_with_iterations(plaintext: &[u8], password: &SecretString, iterations: u32) -> Result<Vec<u8>> {
+   let rng = SystemRandom::new();
+   let mut salt = [0u8; SALT_LEN];
+   let mut nonce = [0u8; NONCE_LEN];
+   rng.fill(&mut salt).and_then(|_| rng.fill(&mut nonce)).map_err(|_| Error::other("No random source"))?;
+
+   let mut data: Vec<u8> = Vec::with_capacity(HEADER_LEN + plaintext.len() + AES_256_GCM.tag_len());
+   data.extend_from_slice(ENCRYPTED_MAGIC);
+   data.extend_from_slice(&salt);
+   data.extend_from_slice(&iterations.to_be_bytes());
+   data.extend_from_slice(&nonce);
+
+   let key = derive_key(password, &salt, iterations)?;
+   let mut in_out = plaintext.to_vec();
+   key.seal_in_place_append_tag(Nonce::assume_unique_for_key(nonce), Aad::from(&data[..HEADER_LEN]), &mut in_out)
+      .map_err(|_| Error::other("Encryption failed"))?;
+   data.extend(in_out);
+   Ok(data)
+}
+
+/// Function to decrypt data encrypted with encrypt(), fails on a wrong password or modified data.
+pub fn decrypt(data: &[u8], password: &SecretString) -> Result<Vec<u8>> {
+   if data.len() < HEADER_LEN + AES_256_GCM.tag_len() || &data[..6] != ENCRYPTED_MAGIC {
+      return Err(Error::new(ErrorKind::InvalidData, "Not a RustHound encrypted file"));
+   }
+   let salt = &data[6..6 + SALT_LEN];
+   let mut iterations = [0u8; 4];
+   iterations.copy_from_slice(&data[6 + SALT_LEN..10 + SALT_LEN]);
+   let mut nonce = [0u8; NONCE_LEN];
+   nonce.copy_from_slice(&data[10 + SALT_LEN..HEADER_LEN]);
+
+   let iterations = u32::from_be_bytes(iterations);
+   if iterations > PBKDF2_MAX_ITERATIONS {
+      return Err(Error::new(ErrorKind::InvalidData, format!("{} PBKDF2 iterations, more than {}", iterations, PBKDF2_MAX_ITERATIONS)))
+   }
+   let key = derive_key(password, salt, iterations)?;
+   let mut in_out = data[HEADER_LEN..].to_vec();
+   let plaintext_len = key.open_in_place(Nonce::assume_unique_for_key(nonce), Aad::from(&data[..HEADER_LEN]), &mut in_out)
+      .map_err(|_| Error::new(ErrorKind::InvalidData, "Wrong password or corrupted file"))?
+      .len();
+   in_out.truncate(plaintext_len);
+   Ok(in_out)
+}
+
+/// Function to encrypt the files written in the output directory since the start of the run.
+pub fn encrypt_output_files(path: &String, since: SystemTime, key: &OutputKey) -> Result<usize> {
+   let mut count = 0;
+   for entry in fs::read_dir(path)? {
+      let file = entry?.path();
+      let written = fs::metadata(&file).and_then(|metadata| metadata.modified()).map(|modified| modified >= since).unwrap_or(false);
+      if !file.is_file() || !written || file.extension().map(|ext| ext == ENCRYPTED_EXTENSION || ext == AGE_EXTENSION).unwrap_or(false) {
+         continue
+      }
+      let final_path = format!("{}.{}", file.display(), key.extension());
+      fs::write(&final_path, key.encrypt(&fs::read(&file)?)?)?;
+      fs::remove_file(&file)?;
+      info!("{} created!", final_path.bold());
+      count += 1;
+   }
+   Ok(count)
+}
+
+/// Function to decrypt one file encrypted by --encrypt-output, next to it without the .enc extension.
+pub fn decrypt_file(path: &String, password: &Option<SecretString>) -> Result<String> {
+   let password = password.as_ref().ok_or_else(|| Error::new(ErrorKind::InvalidInput, "no password for --decrypt"))?;
+   let final_path = path.strip_suffix(&format!(".{}", ENCRYPTED_EXTENSION)).map(|path| path.to_string())
+      .unwrap_or_else(|| format!("{}.dec", path));
+   fs::write(&final_path, decrypt(&fs::read(path)?, password)?)?;
+   info!("{} created!", final_path.bold());
+   Ok(final_path)
+}
+
+/// Test functions
+#[test]
+pub fn test_encrypt_output() {
+   let password = SecretString::new("Fire&Blood!");
+   let plaintext = b"{\"data\": [], \"meta\": {\"type\": \"users\"}}";
+   // The iterations are read from the header
+   let data = encrypt_with_iterations(plaintext, &password, 1000).unwrap();
+   assert_eq!(&data[..6], ENCRYPTED_MAGIC);
+   assert_eq!(data.len(), HEADER_LEN + plaintext.len() + 16);
+   assert_eq!(decrypt(&data, &password).unwrap(), plaintext.to_vec());
+   assert!(decrypt(&data, &SecretString::new("Winter is coming")).is_err());
+   let mut modified = data.to_owned();
+   modified[HEADER_LEN] ^= 1;
+   assert!(decrypt(&modified, &password).is_err());
+   assert!(decrypt(b"PK\x03\x04", &password).is_err());
+   // Iterations of a forged header over the cap
+   let mut forged = data.to_owned();
+   forged[6 + SALT_LEN..10 + SALT_LEN].copy_from_slice(&u32::MAX.to_be_bytes());
+   assert!(decrypt(&forged, &password).unwrap_err().to_string().contains("PBKDF2 iterations"));
+}
+
+#[test]
+pub fn test_encrypt_to_recipients() {
+   use std::io::Read;
+   let identity = age::x25519::Identity::generate();
+   let content = format!("# operator\n{}\n", identity.to_public());
+   let key = OutputKey::Recipients(parse_recipients(&content).unwrap());
+   assert_eq!(key.extension(), AGE_EXTENSION);
+   let plaintext = b"{\"data\": [], \"meta\": {\"type\": \"users\"}}";
+   let data = key.encrypt(plaintext).unwrap();
+   assert!(data.starts_with(b"age-encryption.org/v1"));
+   let decryptor = age::Decryptor::new(&data[..]).unwrap();
+   let mut decrypted = Vec::new();
+   decryptor.decrypt(std::iter::once(&identity as &dyn age::Identity)).unwrap().read_to_end(&mut decrypted).unwrap();
+   assert_eq!(decrypted, plaintext.to_vec());
+   assert!(parse_recipients("# nobody\n").is_err());
+   assert!(parse_recipients("age1notarecipient").is_err());
+   assert!(output_key(&"password".to_string(), &None).is_err());
+   assert!(output_key(&"password".to_string(), &Some(SecretString::default())).is_err());
+   assert!(output_key(&"not set".to_string(), &None).unwrap().is_none());
+}
@@ -0,0 +1,202 @@
+//! Entra ID collection (--entra-token), built with `cargo build --release --features entra`
+//!
+//! With an access token of Microsoft Graph (`az account get-access-token --resource https://graph.microsoft.com`,
+//! roadtx, TokenTactics...) the users, groups, group members and directory roles of the tenant are collected and
+//! written in `<dirpath>/<domain>_entra.json`, in the AzureHound format ingested by BloodHound CE:
+//! - AZTenant, AZUser, AZGroup and AZRole nodes,
+//! - AZGroupMember and AZRoleAssignment lists, from which BloodHound CE builds the AZMemberOf and AZHasRole edges,
+//! - onPremisesSecurityIdentifier on the synchronized users and groups, to link them with the collected domain.
+//!
+//! The token needs User.Read.All, Group.Read.All and RoleManagement.Read.Directory (or Directory.Read.All).
+//! The applications, service principals, devices and Azure RM resources are not collected.
+use crate::banner::Colorize;
+use log::{debug, info, warn};
+use serde_json::value::Value;
+use std::fs;
+use std::io::{Error, ErrorKind, Result};
+use std::thread;
+use std::time::Duration;
+use zeroize::Zeroizing;
+
+use crate::json::maker::json_to_string;
+use crate::secret::SecretString;
+
+/// Microsoft Graph endpoint.
+pub const GRAPH_URL: &str = "https://graph.microsoft.com/v1.0";
+/// Version of the AzureHound JSON format.
+pub const AZUREHOUND_VERSION: u64 = 5;
+/// Throttled requests (429) retried in a row.
+pub const GRAPH_RETRIES: u32 = 5;
+
+const USER_SELECT: &str = "id,displayName,userPrincipalName,mail,accountEnabled,userType,jobTitle,createdDateTime,lastPasswordChangeDateTime,onPremisesSecurityIdentifier,onPremisesSyncEnabled,onPremisesSamAccountName";
+const GROUP_SELECT: &str = "id,displayName,description,securityEnabled,mailEnabled,isAssignableToRole,createdDateTime,onPremisesSecurityIdentifier,onPremisesSyncEnabled";
+
+/// Function to convert a ureq error, the status code is kept.
+fn graph_error(err: ureq::Error) -> Error {
+   match err {
+      ureq::Error::Status(code, response) => {
+         let kind = if code == 401 || code == 403 { ErrorKind::PermissionDenied } else { ErrorKind::Other };
+         Error::new(kind, format!("HTTP {} {}", code, response.status_text()))
+      }
+      ureq::Error::Transport(transport) => Error::other(transport.to_string()),
+   }
+}
+
+/// Function to get the value of the Authorization header, the "Bearer " prefix is optional in the token.
+pub fn bearer(token: &str) -> String {
+   let token = token.trim();
+   format!("Bearer {}", token.strip_prefix("Bearer ").unwrap_or(token).trim())
+}
+
+/// Function to request every page of a Graph collection, following @odata.nextLink.
+fn graph_list(agent: &ureq::Agent, token: &SecretString, url: &str) -> Result<Vec<Value>> {
+   let mut objects: Vec<Value> = Vec::new();
+   let mut next = Some(url.to_string());
+   let mut retry = 0;
+   let authorization = Zeroizing::new(bearer(token.expose()));
+   while let Some(url) = next.take() {
+      match agent.get(&url).set("Authorization", &authorization).call() {
+         Ok(response) => {
+            let page: Value = serde_json::from_str(&response.into_string()?)?;
+            objects.extend(page["value"].as_array().into_iter().flatten().cloned());
+            next = page["@odata.nextLink"].as_str().map(|link| link.to_string());
+            retry = 0;
+         }
+         Err(ureq::Error::Status(429, response)) if retry < GRAPH_RETRIES => {
+            retry += 1;
+            let delay = response.header("Retry-After").and_then(|seconds| seconds.parse::<u64>().ok()).unwrap_or(1 << retry);
+            warn!("Microsoft Graph throttling, retry {}/{} in {}s", retry, GRAPH_RETRIES, delay);
+            thread::sleep(Duration::from_secs(delay));
+            next = Some(url);
+         }
+         Err(err) => return Err(graph_error(err)),
+      }
+   }
+   Ok(objects)
+}
+
+/// Function to wrap an object in an AzureHound entry.
+pub fn azure_entry(kind: &str, data: Value) -> Value {
+   serde_json::json!({ "kind": kind, "data": data })
+}
+
+/// Function to add the tenant id and name to a Graph object.
+fn with_tenant(mut object: Value, tenant: &Value) -> Value {
+   object["tenantId"] = tenant["id"].to_owned();
+   object["tenantName"] = tenant["displayName"].to_owned();
+   object
+}
+
+/// Function to build the AzureHound entries from the Graph objects.
+/// `members` is the list of (group id, members of the group).
+pub fn entra_entries(
+   organization: &Value,
+   users: Vec<Value>,
+   groups: Vec<Value>,
+   members: Vec<(String, Vec<Value>)>,
+   roles: Vec<Value>,
+   assignments: Vec<Value>,
+) -> Vec<Value> {
+   let tenant_id = organization["id"].as_str().unwrap_or("");
+   let default_domain = organization["verifiedDomains"].as_array().into_iter().flatten()
+      .find(|domain| domain["isDefault"].as_bool().unwrap_or(false))
+      .map(|domain| domain["name"].to_owned())
+      .unwrap_or(Value::Null);
+   let mut entries = vec![azure_entry("AZTenant", serde_json::json!({
+      "id": format!("/tenants/{}", tenant_id),
+      "tenantId": tenant_id,
+      "displayName": organization["displayName"],
+      "defaultDomain": default_domain,
+      "collected": true,
+   }))];
+   entries.extend(users.into_iter().map(|user| azure_entry("AZUser", with_tenant(user, organization))));
+   entries.extend(groups.into_iter().map(|group| azure_entry("AZGroup", with_tenant(group, organization))));
+   for (group_id, members) in members.into_iter().filter(|(_, members)| !members.is_empty()) {
+      let members: Vec<Value> = members.into_iter().map(|member| serde_json::json!({ "groupId": group_id, "member": member })).collect();
+      entries.push(azure_entry("AZGroupMember", serde_json::json!({ "groupId": group_id, "members": members })));
+   }
+   for role in roles.into_iter() {
+      let role_id = role["id"].as_str().unwrap_or("").to_string();
+      let role_assignments: Vec<&Value> = assignments.iter().filter(|assignment| assignment["roleDefinitionId"] == role_id.as_str()).collect();
+      if !role_assignments.is_empty() {
+         entries.push(azure_entry("AZRoleAssignment", serde_json::json!({
+            "roleDefinitionId": role_id,
+            "tenantId": tenant_id,
+            "roleAssignments": role_assignments,
+         })));
+      }
+      entries.push(azure_entry("AZRole", with_tenant(role, organization)));
+   }
+   entries
+}
+
+/// Function to collect the tenant, users, groups, group members and directory roles.
+pub fn collect_entra(token: &SecretString) -> Result<Vec<Value>> {
+   let agent = ureq::AgentBuilder::new().timeout(Duration::from_secs(60)).build();
+   let organization = graph_list(&agent, token, &format!("{}/organization", GRAPH_URL))?
+      .into_iter().next()
+      .ok_or_else(|| Error::new(ErrorKind::NotFound, "no organization for this token"))?;
+   info!("Entra ID tenant {} ({})", organization["displayName"].as_str().unwrap_or("").bold(), organization["id"].as_str().unwrap_or(""));
+
+   let users = graph_list(&agent, token, &format!("{}/users?$select={}&$top=999", GRAPH_URL, USER_SELECT))?;
+   let groups = graph_list(&agent, token, &format!("{}/groups?$select={}&$top=999", GRAPH_URL, GROUP_SELECT))?;
+   let mut members: Vec<(String, Vec<Value>)> = Vec::new();
+   for group_id in groups.iter().filter_map(|group| group["id"].as_str()) {
+      match graph_list(&agent, token, &format!("{}/groups/{}/members?$select=id,displayName&$top=999", GRAPH_URL, group_id)) {
+         Ok(group_members) => members.push((group_id.to_string(), group_members)),
+         Err(err) => debug!("Members of the group {} not collected. Reason: {err}", group_id),
+      }
+   }
+   let roles = graph_list(&agent, token, &format!("{}/roleManagement/directory/roleDefinitions", GRAPH_URL))?;
+   let assignments = graph_list(&agent, token, &format!("{}/roleManagement/directory/roleAssignments", GRAPH_URL))?;
+
+   let synced = users.iter().filter(|user| user["onPremisesSecurityIdentifier"].is_string()).count();
+   info!("{} users ({} synchronized from AD), {} groups, {} role assignments collected", users.len().to_string().bold(), synced, groups.len().to_string().bold(), assignments.len().to_string().bold());
+   Ok(entra_entries(&organization, users, groups, members, roles, assignments))
+}
+
+/// Function to collect the Entra ID objects and write the AzureHound JSON file.
+pub fn make_entra_result(path: &String, domain: &String, token: &SecretString, pretty: bool) -> Result<()> {
+   let entries = collect_entra(token)?;
+   let mut result = serde_json::json!({
+      "meta": { "type": "azure", "version": AZUREHOUND_VERSION, "count": entries.len() },
+      "data": entries,
+   });
+   fs::create_dir_all(path)?;
+   let final_path = format!("{}/{}_entra.json", path, domain.replace(".", "-").to_lowercase());
+   fs::write(&final_path, json_to_string(&mut result, pretty))?;
+   info!("{} created!", final_path.bold());
+   Ok(())
+}
+
+/// Test functions
+#[test]
+pub fn test_entra_entries() {
+   assert_eq!(bearer("Bearer eyJ0eXAi.x.y "), "Bearer eyJ0eXAi.x.y");
+   assert_eq!(bearer("eyJ0eXAi.x.y"), "Bearer eyJ0eXAi.x.y");
+
+   let organization = serde_json::json!({"id": "9b4e1c2a-5d3f-4e6a-8b7c-1d2e3f4a5b6c", "displayName": "Essos",
+      "verifiedDomains": [{"name": "essos.onmicrosoft.com", "isDefault": false}, {"name": "essos.com", "isDefault": true}]});
+   let users = vec![
+      serde_json::json!({"id": "u-1", "userPrincipalName": "daenerys@essos.com", "onPremisesSecurityIdentifier": "S-1-5-21-1-2-3-1110"}),
+      serde_json::json!({"id": "u-2", "userPrincipalName": "viserys@essos.com", "onPremisesSecurityIdentifier": null}),
+   ];
+   let groups = vec![serde_json::json!({"id": "g-1", "displayName": "Dragonriders"}), serde_json::json!({"id": "g-2", "displayName": "Empty"})];
+   let members = vec![
+      ("g-1".to_string(), vec![serde_json::json!({"@odata.type": "#microsoft.graph.user", "id": "u-1"})]),
+      ("g-2".to_string(), vec![]),
+   ];
+   let roles = vec![
+      serde_json::json!({"id": "62e90394-69f5-4237-9190-012177145e10", "displayName": "Global Administrator"}),
+      serde_json::json!({"id": "fe930be7-5e62-47db-91af-98c3a49a38b1", "displayName": "User Administrator"}),
+   ];
+   let assignments = vec![serde_json::json!({"id": "a-1", "principalId": "u-1", "roleDefinitionId": "62e90394-69f5-4237-9190-012177145e10", "directoryScopeId": "/"})];
+
+   let entries = entra_entries(&organization, users, groups, members, roles, assignments);
+   let kinds: Vec<&str> = entries.iter().map(|entry| entry["kind"].as_str().unwrap()).collect();
+   assert_eq!(kinds, vec!["AZTenant", "AZUser", "AZUser", "AZGroup", "AZGroup", "AZGroupMember", "AZRoleAssignment", "AZRole", "AZRole"]);
+   assert_eq!(entries[0]["data"]["defaultDomain"], "essos.com");
+   assert_eq!(entries[1]["data"]["tenantId"], "9b4e1c2a-5d3f-4e6a-8b7c-1d2e3f4a5b6c");
+   assert_eq!(entries[5]["data"]["members"][0]["member"]["id"], "u-1");
+   assert_eq!(entries[6]["data"]["roleAssignments"][0]["principalId"], "u-1");
+}
@@ -0,0 +1,167 @@
+//! Exchange infrastructure discovery (--exchange)
+//!
+//! The Exchange servers are read from `CN=Microsoft Exchange,CN=Services` of the configuration NC with their roles
+//! (msExchCurrentServerRoles) and version (serialNumber), the RBAC groups from `OU=Microsoft Exchange Security Groups`.
+//! The servers are flagged on the computers (`exchangeserver`, `exchangeroles`, `exchangeversion` properties).
+//!
+//! Exchange setup grants WriteDacl on the domain to Exchange Windows Permissions: any Exchange server can grant
+//! itself DCSync (PrivExchange). The fix sets the ACE inherit only, the domain is still vulnerable when the WriteDacl
+//! ACE applies to the domain object (marked `PrivExchange` by the checker), `privexchange` is then set on the domain.
+//! Everything is written in `<dirpath>/<domain>_exchange.json`.
+use crate::banner::Colorize;
+use lazy_static::lazy_static;
+use log::{info, warn};
+use serde_json::value::Value;
+use std::fs;
+use std::sync::Mutex;
+
+use crate::json::checker::bh_41::EXCHANGE_GROUPS_OU;
+
+/// Server roles of msExchCurrentServerRoles (Exchange 2007 to 2019).
+pub const EXCHANGE_SERVER_ROLES: [(u32, &str); 5] = [
+   (0x02, "Mailbox"),
+   (0x04, "ClientAccess"),
+   (0x10, "UnifiedMessaging"),
+   (0x20, "HubTransport"),
+   (0x40, "EdgeTransport"),
+];
+
+lazy_static! {
+   static ref EXCHANGE_SERVERS: Mutex<Vec<Value>> = Mutex::new(Vec::new());
+}
+
+/// Function to get the role names of msExchCurrentServerRoles.
+pub fn exchange_server_roles(value: u32) -> Vec<String> {
+   EXCHANGE_SERVER_ROLES.iter()
+      .filter(|(flag, _)| value & flag != 0)
+      .map(|(_, name)| name.to_string())
+      .collect()
+}
+
+/// Function to record one Exchange server found by the parser.
+pub fn record_exchange_server(server: Value) {
+   if let Ok(mut servers) = EXCHANGE_SERVERS.lock() {
+      servers.push(server);
+   }
+}
+
+/// Function to get the Exchange servers of the run.
+pub fn exchange_servers() -> Vec<Value> {
+   EXCHANGE_SERVERS.lock().map(|servers| servers.to_owned()).unwrap_or_default()
+}
+
+/// Function to flag the Exchange servers, RBAC groups and PrivExchange domains, return the report.
+pub fn exchange_infrastructure(
+   servers: &[Value],
+   vec_computers: &mut [Value],
+   vec_groups: &[Value],
+   vec_domains: &mut [Value],
+) -> Value {
+   let mut report_servers: Vec<Value> = Vec::new();
+   for server in servers {
+      let name = server["name"].as_str().unwrap_or("").to_uppercase();
+      let fqdn = server["fqdn"].as_str().unwrap_or("").to_uppercase();
+      let computer = vec_computers.iter_mut().find(|computer| {
+         let computer_name = computer["Properties"]["name"].as_str().unwrap_or("").to_uppercase();
+         (!fqdn.is_empty() && computer_name == fqdn) || computer_name.split('.').next() == Some(name.as_str())
+      });
+      let mut report_server = server.to_owned();
+      report_server["objectid"] = Value::Null;
+      if let Some(computer) = computer {
+         computer["Properties"]["exchangeserver"] = true.into();
+         computer["Properties"]["exchangeroles"] = server["roles"].to_owned();
+         computer["Properties"]["exchangeversion"] = server["version"].to_owned();
+         report_server["objectid"] = computer["ObjectIdentifier"].to_owned();
+      }
+      report_servers.push(report_server);
+   }
+
+   let rbac_groups: Vec<Value> = vec_groups.iter()
+      .filter(|group| group["Properties"]["distinguishedname"].as_str().unwrap_or("").to_uppercase().contains(EXCHANGE_GROUPS_OU))
+      .map(|group| serde_json::json!({
+         "name": group["Properties"]["name"],
+         "objectid": group["ObjectIdentifier"],
+         "members": group["Members"].as_array().map(|members| members.len()).unwrap_or(0),
+         "highvalue": group["Properties"]["highvalue"],
+      }))
+      .collect();
+
+   let mut privexchange: Vec<Value> = Vec::new();
+   for domain in vec_domains.iter_mut() {
+      let aces: Vec<Value> = domain["Aces"].as_array().into_iter().flatten()
+         .filter(|ace| ace["PrivExchange"] == true)
+         .map(|ace| serde_json::json!({"domain": domain["Properties"]["name"], "principal": ace["PrincipalSID"], "right": ace["RightName"]}))
+         .collect();
+      if !aces.is_empty() {
+         domain["Properties"]["privexchange"] = true.into();
+      }
+      privexchange.extend(aces);
+   }
+
+   serde_json::json!({
+      "servers": report_servers,
+      "rbacgroups": rbac_groups,
+      "privexchange": !privexchange.is_empty(),
+      "privexchangeaces": privexchange,
+   })
+}
+
+/// Function to flag the Exchange infrastructure and write the Exchange report.
+pub fn make_exchange_report(
+   path: &String,
+   domain: &String,
+   vec_computers: &mut [Value],
+   vec_groups: &[Value],
+   vec_domains: &mut [Value],
+) -> std::io::Result<()> {
+   let report = exchange_infrastructure(&exchange_servers(), vec_computers, vec_groups, vec_domains);
+   let count = |key: &str| report[key].as_array().map(|values| values.len()).unwrap_or(0);
+   info!("Exchange: {} servers, {} RBAC groups", count("servers").to_string().bold(), count("rbacgroups"));
+   if report["privexchange"] == true {
+      warn!("{}: the Exchange servers can grant themselves DCSync on the domain", "PrivExchange".red().bold());
+   }
+
+   fs::create_dir_all(path)?;
+   let final_path = format!("{}/{}_exchange.json", path, domain.replace(".", "-").to_lowercase());
+   fs::write(&final_path, serde_json::to_string_pretty(&report).unwrap_or_default())?;
+   info!("{} created!", final_path.bold());
+   Ok(())
+}
+
+/// Test functions
+#[test]
+pub fn test_exchange_infrastructure() {
+   use crate::json::parser::bh_41::parse_exchange_server;
+   let entry = ldap3::SearchEntry {
+      dn: "CN=EX01,CN=Servers,CN=Exchange Administrative Group (FYDIBOHF23SPDLT),CN=Administrative Groups,CN=ESSOS,CN=Microsoft Exchange,CN=Services,CN=Configuration,DC=ESSOS,DC=LOCAL".to_string(),
+      attrs: std::collections::HashMap::from([
+         ("objectClass".to_string(), vec!["top".to_string(), "server".to_string(), "msExchExchangeServer".to_string()]),
+         ("name".to_string(), vec!["EX01".to_string()]),
+         ("networkAddress".to_string(), vec!["ncacn_vns_spp:EX01".to_string(), "ncacn_ip_tcp:ex01.essos.local".to_string()]),
+         ("serialNumber".to_string(), vec!["Version 15.1 (Build 2507.6)".to_string()]),
+         ("msExchCurrentServerRoles".to_string(), vec!["38".to_string()]),
+      ]),
+      bin_attrs: std::collections::HashMap::new(),
+   };
+   let server = parse_exchange_server(entry, &"ESSOS.LOCAL".to_string());
+   assert_eq!(server["fqdn"], "EX01.ESSOS.LOCAL");
+   assert_eq!(server["roles"], serde_json::json!(["Mailbox", "ClientAccess", "HubTransport"]));
+
+   let mut computers = vec![serde_json::json!({"ObjectIdentifier": "S-1-5-21-1-2-3-1301", "Properties": {"name": "EX01.ESSOS.LOCAL"}})];
+   let groups = vec![
+      serde_json::json!({"ObjectIdentifier": "S-1-5-21-1-2-3-1110", "Properties": {"name": "EXCHANGE WINDOWS PERMISSIONS@ESSOS.LOCAL",
+         "distinguishedname": "CN=EXCHANGE WINDOWS PERMISSIONS,OU=MICROSOFT EXCHANGE SECURITY GROUPS,DC=ESSOS,DC=LOCAL", "highvalue": true},
+         "Members": [{"ObjectIdentifier": "S-1-5-21-1-2-3-1111", "ObjectType": "Group"}]}),
+      serde_json::json!({"ObjectIdentifier": "S-1-5-21-1-2-3-512", "Properties": {"name": "DOMAIN ADMINS@ESSOS.LOCAL", "distinguishedname": "CN=DOMAIN ADMINS,CN=USERS,DC=ESSOS,DC=LOCAL"}}),
+   ];
+   let mut domains = vec![serde_json::json!({"ObjectIdentifier": "S-1-5-21-1-2-3", "Properties": {"name": "ESSOS.LOCAL"},
+      "Aces": [{"RightName": "WriteDacl", "IsInherited": false, "PrincipalSID": "S-1-5-21-1-2-3-1110", "PrincipalType": "Group", "PrivExchange": true}]})];
+   let report = exchange_infrastructure(&[server], &mut computers, &groups, &mut domains);
+   assert_eq!(computers[0]["Properties"]["exchangeserver"], true);
+   assert_eq!(computers[0]["Properties"]["exchangeversion"], "Version 15.1 (Build 2507.6)");
+   assert_eq!(report["servers"][0]["objectid"], "S-1-5-21-1-2-3-1301");
+   assert_eq!(report["rbacgroups"].as_array().unwrap().len(), 1);
+   assert_eq!(report["rbacgroups"][0]["members"], 1);
+   assert_eq!(report["privexchange"], true);
+   assert_eq!(domains[0]["Properties"]["privexchange"], true);
+}
@@ -0,0 +1,177 @@
+//! Group Policy Preferences passwords (--sysvol)
+//!
+//! The Preferences XML files (Groups.xml, Services.xml, ScheduledTasks.xml, DataSources.xml, Drives.xml,
+//! Printers.xml) store the passwords in the cpassword attribute, encrypted with an AES-256 key published by
+//! Microsoft (MS14-025). RustHound has no SMB client: the SYSVOL share is read from a local copy, like
+//! `smbclient //dc/SYSVOL -c 'recurse; prompt; mget *'` or a mounted share.
+//!
+//! The files of `Policies\{GUID}` are linked to their GPO (`gpppassword` property) and the decrypted passwords
+//! are written in `<dirpath>/<domain>_gpp.json`, never logged.
+//! <https://learn.microsoft.com/en-us/openspecs/windows_protocols/ms-gppref/2c15cbf0-f086-4c74-8b70-1f2fa45dd4be>
+use aes::cipher::{generic_array::GenericArray, BlockDecrypt, KeyInit};
+use aes::Aes256;
+use crate::banner::Colorize;
+use lazy_static::lazy_static;
+use log::{debug, info, warn};
+use regex::Regex;
+use serde_json::value::Value;
+use std::fs;
+use std::path::Path;
+
+use crate::modules::offline::base64_decode;
+
+/// AES-256 key of the cpassword attribute published in MS-GPPREF.
+pub const GPP_KEY: [u8; 32] = [
+   0x4e, 0x99, 0x06, 0xe8, 0xfc, 0xb6, 0x6c, 0xc9, 0xfa, 0xf4, 0x93, 0x10, 0x62, 0x0f, 0xfe, 0xe8,
+   0xf4, 0x96, 0xe8, 0x06, 0xcc, 0x05, 0x79, 0x90, 0x20, 0x9b, 0x09, 0xa4, 0x33, 0xb6, 0x6c, 0x1b,
+];
+/// Attributes naming the account of a cpassword.
+pub const GPP_ACCOUNT_ATTRIBUTES: [&str; 4] = ["userName", "runAs", "accountName", "username"];
+
+lazy_static! {
+   static ref CPASSWORD_TAG: Regex = Regex::new(r#"<(\w+)\s[^>]*\bcpassword="([^"]+)"[^>]*>"#).unwrap();
+   static ref ATTRIBUTE: Regex = Regex::new(r#"\b(\w+)="([^"]*)""#).unwrap();
+   static ref GPO_GUID: Regex = Regex::new(r"(?i)\{[0-9a-f]{8}-[0-9a-f]{4}-[0-9a-f]{4}-[0-9a-f]{4}-[0-9a-f]{12}\}").unwrap();
+}
+
+/// Function to decrypt one cpassword: base64 without padding, AES-256-CBC with a null IV, UTF-16LE.
+pub fn decrypt_cpassword(cpassword: &str) -> Option<String> {
+   let data = base64_decode(cpassword)?;
+   if data.is_empty() || data.len() % 16 != 0 {
+      return None
+   }
+   let cipher = Aes256::new(GenericArray::from_slice(&GPP_KEY));
+   let mut previous = [0u8; 16];
+   let mut plaintext: Vec<u8> = Vec::with_capacity(data.len());
+   for chunk in data.chunks(16) {
+      let mut block = GenericArray::clone_from_slice(chunk);
+      cipher.decrypt_block(&mut block);
+      plaintext.extend(block.iter().zip(previous.iter()).map(|(byte, iv)| byte ^ iv));
+      previous.copy_from_slice(chunk);
+   }
+   // PKCS7 padding
+   let padding = *plaintext.last()? as usize;
+   if padding == 0 || padding > 16 || plaintext.len() < padding {
+      return None
+   }
+   plaintext.truncate(plaintext.len() - padding);
+   let utf16: Vec<u16> = plaintext.chunks(2).map(|pair| u16::from_le_bytes([pair[0], *pair.get(1).unwrap_or(&0)])).collect();
+   String::from_utf16(&utf16).ok()
+}
+
+/// Function to find the cpasswords of one Preferences XML file.
+pub fn scan_preferences(xml: &str) -> Vec<Value> {
+   CPASSWORD_TAG.captures_iter(xml)
+      .map(|captures| {
+         let tag = captures.get(0).map(|tag| tag.as_str()).unwrap_or("");
+         let attribute = |name: &str| ATTRIBUTE.captures_iter(tag)
+            .find(|attribute| &attribute[1] == name)
+            .map(|attribute| attribute[2].to_string());
+         let account = GPP_ACCOUNT_ATTRIBUTES.iter().find_map(|name| attribute(name).filter(|value| !value.is_empty()));
+         let cpassword = &captures[2];
+         serde_json::json!({
+            "element": &captures[1],
+            "account": account,
+            "cpassword": cpassword,
+            "password": decrypt_cpassword(cpassword),
+         })
+      })
+      .collect()
+}
+
+/// Function to get the XML files of a directory and its subdirectories.
+fn xml_files(dir: &Path, files: &mut Vec<std::path::PathBuf>) -> std::io::Result<()> {
+   for entry in fs::read_dir(dir)? {
+      let path = entry?.path();
+      if path.is_dir() {
+         xml_files(&path, files)?;
+      } else if path.extension().map(|extension| extension.eq_ignore_ascii_case("xml")).unwrap_or(false) {
+         files.push(path);
+      }
+   }
+   Ok(())
+}
+
+/// Function to scan a local copy of SYSVOL, flag the GPOs with a cpassword and return the findings.
+pub fn find_gpp_passwords(sysvol: &String, vec_gpos: &mut [Value]) -> std::io::Result<Vec<Value>> {
+   let mut files: Vec<std::path::PathBuf> = Vec::new();
+   xml_files(Path::new(sysvol), &mut files)?;
+   files.sort();
+   let mut findings: Vec<Value> = Vec::new();
+   for file in files {
+      let xml = match fs::read(&file) {
+         Ok(data) => String::from_utf8_lossy(&data).to_string(),
+         Err(err) => {
+            debug!("Ignoring {}: {}", file.display(), err);
+            continue;
+         }
+      };
+      let path = file.display().to_string();
+      let guid = GPO_GUID.find(&path).map(|guid| guid.as_str().to_uppercase());
+      let gpo = guid.as_ref().and_then(|guid| vec_gpos.iter_mut().find(|gpo| {
+         gpo["Properties"]["gpcpath"].as_str().map(|gpcpath| gpcpath.to_uppercase().contains(guid.as_str())).unwrap_or(false)
+      }));
+      let mut file_findings = scan_preferences(&xml);
+      if file_findings.is_empty() {
+         continue;
+      }
+      let (name, objectid) = match gpo {
+         Some(gpo) => {
+            gpo["Properties"]["gpppassword"] = true.into();
+            (gpo["Properties"]["name"].to_owned(), gpo["ObjectIdentifier"].to_owned())
+         }
+         None => (Value::Null, Value::Null),
+      };
+      for finding in file_findings.iter_mut() {
+         finding["file"] = path.to_owned().into();
+         finding["gpo"] = name.to_owned();
+         finding["gpoid"] = objectid.to_owned();
+      }
+      findings.extend(file_findings);
+   }
+   Ok(findings)
+}
+
+/// Function to write the GPP passwords report, the passwords are only in the file.
+pub fn make_gpp_report(path: &String, domain: &String, sysvol: &String, vec_gpos: &mut [Value]) -> std::io::Result<()> {
+   let findings = find_gpp_passwords(sysvol, vec_gpos)?;
+   if findings.is_empty() {
+      info!("No cpassword in {}", sysvol);
+   } else {
+      warn!("{} cpassword found in the Group Policy Preferences", findings.len().to_string().red().bold());
+   }
+   for finding in findings.iter() {
+      info!("{} {}", finding["account"].as_str().unwrap_or("").red().bold(), finding["file"].as_str().unwrap_or(""));
+   }
+
+   fs::create_dir_all(path)?;
+   let final_path = format!("{}/{}_gpp.json", path, domain.replace(".", "-").to_lowercase());
+   fs::write(&final_path, serde_json::to_string_pretty(&findings).unwrap_or_default())?;
+   info!("{} created!", final_path.bold());
+   Ok(())
+}
+
+/// Test functions
+#[test]
+pub fn test_gpp_passwords() {
+   assert_eq!(decrypt_cpassword("j1Uyj3Vx8TY9LtLZil2uAuZkFQA/4latT76ZwgdHdhw"), Some("Local*P4ssword!".to_string()));
+   assert_eq!(decrypt_cpassword("not base64!"), None);
+
+   let dir = std::env::temp_dir().join(format!("rusthound_gpp_{}", std::process::id()));
+   let preferences = dir.join("essos.local/Policies/{31B2F340-016D-11D2-945F-00C04FB984F9}/Machine/Preferences/Groups");
+   fs::create_dir_all(&preferences).unwrap();
+   fs::write(preferences.join("Groups.xml"), r#"<?xml version="1.0" encoding="utf-8"?>
+<Groups clsid="{3125E937-EB16-4b4c-9934-544FC6D24D26}"><User clsid="{DF5F1855-51E5-4d24-8B1A-D9BDE98BA1D1}" name="Administrator (built-in)" changed="2023-10-01 10:00:00">
+<Properties action="U" newName="" fullName="" description="" cpassword="j1Uyj3Vx8TY9LtLZil2uAuZkFQA/4latT76ZwgdHdhw" changeLogon="0" userName="Administrator (built-in)"/></User></Groups>"#).unwrap();
+   fs::write(dir.join("essos.local/Policies/{31B2F340-016D-11D2-945F-00C04FB984F9}/GPT.INI"), "[General]\r\nVersion=3\r\n").unwrap();
+   let mut gpos = vec![serde_json::json!({"ObjectIdentifier": "0F2B", "Properties": {"name": "DEFAULT DOMAIN POLICY@ESSOS.LOCAL",
+      "gpcpath": "\\\\essos.local\\sysvol\\essos.local\\Policies\\{31B2F340-016D-11D2-945F-00C04FB984F9}"}})];
+   let findings = find_gpp_passwords(&dir.display().to_string(), &mut gpos).unwrap();
+   fs::remove_dir_all(&dir).unwrap();
+   assert_eq!(findings.len(), 1);
+   assert_eq!(findings[0]["element"], "Properties");
+   assert_eq!(findings[0]["account"], "Administrator (built-in)");
+   assert_eq!(findings[0]["password"], "Local*P4ssword!");
+   assert_eq!(findings[0]["gpoid"], "0F2B");
+   assert_eq!(gpos[0]["Properties"]["gpppassword"], true);
+}
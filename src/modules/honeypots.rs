@@ -0,0 +1,171 @@
+//! Honey accounts and canary objects heuristics (--honeypots)
+//!
+//! Deception tools plant objects which look like easy targets and raise an alert when they are used:
+//! - privileged (admincount) accounts which never logged on,
+//! - Kerberoastable or AS-REP roastable accounts which never logged on,
+//! - servicePrincipalNames on a host which is not a computer of the domain,
+//! - objects denying rights to Everyone or Authenticated Users (canary objects alerting on any read),
+//! - names and descriptions with decoy keywords.
+//!
+//! Every flagged object is written with its reasons in `<dirpath>/<domain>_honeypots.json`, the objects with the
+//! most reasons first. These are heuristics: a real account created for a future admin matches them too.
+use crate::banner::Colorize;
+use log::info;
+use serde_json::value::Value;
+use std::collections::HashSet;
+use std::fs;
+
+/// Words of the names and descriptions of the decoy objects.
+pub const DECOY_KEYWORDS: [&str; 4] = ["honey", "canary", "decoy", "deception"];
+
+fn never_logged_on(object: &Value) -> bool {
+   object["Properties"]["lastlogon"].as_i64().unwrap_or(-1) <= 0
+      && object["Properties"]["lastlogontimestamp"].as_i64().unwrap_or(-1) <= 0
+}
+
+fn flag(object: &Value, property: &str) -> bool {
+   object["Properties"][property].as_bool().unwrap_or(false)
+}
+
+/// Function to get the host names of the domain computers, FQDN and short name in lower case.
+fn computer_hosts(vec_computers: &[Value]) -> HashSet<String> {
+   let mut hosts: HashSet<String> = HashSet::new();
+   for computer in vec_computers {
+      for property in ["dnshostname", "name", "samaccountname"] {
+         if let Some(host) = computer["Properties"][property].as_str() {
+            let host = host.trim_end_matches('$').to_lowercase();
+            if let Some((short, _)) = host.split_once('.') {
+               hosts.insert(short.to_string());
+            }
+            hosts.insert(host);
+         }
+      }
+   }
+   hosts
+}
+
+/// Function to get the SPNs of an object on a host which is not a domain computer.
+fn foreign_spns(object: &Value, hosts: &HashSet<String>) -> Vec<String> {
+   object["Properties"]["serviceprincipalnames"].as_array().into_iter().flatten()
+      .filter_map(|spn| spn.as_str())
+      .filter(|spn| {
+         // service/host:port/name
+         let host = spn.split('/').nth(1).unwrap_or("").split(':').next().unwrap_or("").to_lowercase();
+         !host.is_empty() && !hosts.contains(&host) && !hosts.contains(host.split('.').next().unwrap_or(""))
+      })
+      .map(|spn| spn.to_string())
+      .collect()
+}
+
+/// Function to get the reasons why one object looks like a honey account or a canary object.
+pub fn honeypot_reasons(object: &Value, object_type: &str, hosts: &HashSet<String>) -> Vec<String> {
+   let mut reasons: Vec<String> = Vec::new();
+   let is_user = object_type == "User";
+   if is_user && flag(object, "enabled") {
+      if flag(object, "admincount") && never_logged_on(object) {
+         reasons.push("Privileged account never logged on".to_string());
+      }
+      if flag(object, "hasspn") && never_logged_on(object) {
+         reasons.push("Kerberoastable account never logged on".to_string());
+      }
+      if flag(object, "dontreqpreauth") && never_logged_on(object) {
+         reasons.push("AS-REP roastable account never logged on".to_string());
+      }
+   }
+   if is_user {
+      let spns = foreign_spns(object, hosts);
+      if !spns.is_empty() {
+         reasons.push(format!("SPN on a host which is not a domain computer: {}", spns.join(", ")));
+      }
+   }
+   if flag(object, "everyone_denied") {
+      reasons.push("Rights denied to Everyone or Authenticated Users".to_string());
+   }
+   let text = format!("{} {}",
+      object["Properties"]["name"].as_str().unwrap_or(""),
+      object["Properties"]["description"].as_str().unwrap_or(""),
+   ).to_lowercase();
+   if let Some(keyword) = DECOY_KEYWORDS.iter().find(|keyword| text.contains(*keyword)) {
+      reasons.push(format!("Decoy keyword \"{}\" in the name or description", keyword));
+   }
+   reasons
+}
+
+/// Function to list the likely honey accounts and canary objects, the objects with the most reasons first.
+pub fn find_honeypots(objects: &[(&str, &Vec<Value>)]) -> Vec<Value> {
+   let hosts = objects.iter()
+      .find(|(object_type, _)| *object_type == "Computer")
+      .map(|(_, computers)| computer_hosts(computers))
+      .unwrap_or_default();
+   let mut honeypots: Vec<Value> = Vec::new();
+   for (object_type, vec_objects) in objects {
+      for object in vec_objects.iter() {
+         let reasons = honeypot_reasons(object, object_type, &hosts);
+         if !reasons.is_empty() {
+            honeypots.push(serde_json::json!({
+               "name": object["Properties"]["name"],
+               "objectid": object["ObjectIdentifier"],
+               "type": object_type,
+               "reasons": reasons,
+            }));
+         }
+      }
+   }
+   honeypots.sort_by_key(|honeypot| std::cmp::Reverse(honeypot["reasons"].as_array().map(|reasons| reasons.len()).unwrap_or(0)));
+   honeypots
+}
+
+/// Function to write the honeypots report.
+pub fn make_honeypots_report(path: &String, domain: &String, objects: &[(&str, &Vec<Value>)]) -> std::io::Result<()> {
+   let honeypots = find_honeypots(objects);
+   info!("{} likely honey accounts or canary objects", honeypots.len().to_string().bold());
+   for honeypot in honeypots.iter().take(10) {
+      info!("{} {}", honeypot["name"].as_str().unwrap_or("").yellow().bold(), honeypot["reasons"][0].as_str().unwrap_or(""));
+   }
+
+   fs::create_dir_all(path)?;
+   let final_path = format!("{}/{}_honeypots.json", path, domain.replace(".", "-").to_lowercase());
+   fs::write(&final_path, serde_json::to_string_pretty(&honeypots).unwrap_or_default())?;
+   info!("{} created!", final_path.bold());
+   Ok(())
+}
+
+/// Test functions
+#[test]
+pub fn test_find_honeypots() {
+   use crate::enums::secdesc::Ace;
+   use crate::enums::acl::everyone_denied;
+
+   // ACCESS_DENIED_ACE for S-1-1-0, then the same ACE inherit only
+   let deny = [0x01, 0x00, 0x14, 0x00, 0xff, 0x01, 0x0f, 0x00, 0x01, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0x00];
+   let mut inherit_only = deny;
+   inherit_only[1] = 0x08;
+   let domain = "ESSOS.LOCAL".to_string();
+   assert!(everyone_denied(&[Ace::parse(&deny).unwrap().1], &domain));
+   assert!(!everyone_denied(&[Ace::parse(&inherit_only).unwrap().1], &domain));
+
+   let users = vec![
+      serde_json::json!({"ObjectIdentifier": "S-1-5-21-1-2-3-1120", "Properties": {"name": "ADM_BACKUP@ESSOS.LOCAL", "enabled": true, "admincount": true,
+         "hasspn": true, "serviceprincipalnames": ["MSSQLSvc/sql-legacy.essos.local:1433"], "lastlogon": -1, "lastlogontimestamp": -1}}),
+      serde_json::json!({"ObjectIdentifier": "S-1-5-21-1-2-3-1110", "Properties": {"name": "SQL_SVC@ESSOS.LOCAL", "enabled": true, "admincount": false,
+         "hasspn": true, "serviceprincipalnames": ["MSSQLSvc/braavos.essos.local:1433", "MSSQLSvc/BRAAVOS"], "lastlogon": 1696000000, "lastlogontimestamp": 1696000000}}),
+      serde_json::json!({"ObjectIdentifier": "S-1-5-21-1-2-3-500", "Properties": {"name": "ADMINISTRATOR@ESSOS.LOCAL", "enabled": true, "admincount": true,
+         "lastlogon": 1696000000, "lastlogontimestamp": -1}}),
+   ];
+   let computers = vec![
+      serde_json::json!({"ObjectIdentifier": "S-1-5-21-1-2-3-1001", "Properties": {"name": "BRAAVOS.ESSOS.LOCAL", "samaccountname": "BRAAVOS$"}}),
+      serde_json::json!({"ObjectIdentifier": "S-1-5-21-1-2-3-1002", "Properties": {"name": "FS01.ESSOS.LOCAL", "description": "Canary file server", "everyone_denied": true}}),
+   ];
+   let honeypots = find_honeypots(&[("User", &users), ("Computer", &computers)]);
+   assert_eq!(honeypots.len(), 2);
+   assert_eq!(honeypots[0]["name"], "ADM_BACKUP@ESSOS.LOCAL");
+   assert_eq!(honeypots[0]["reasons"], serde_json::json!([
+      "Privileged account never logged on",
+      "Kerberoastable account never logged on",
+      "SPN on a host which is not a domain computer: MSSQLSvc/sql-legacy.essos.local:1433",
+   ]));
+   assert_eq!(honeypots[1]["reasons"], serde_json::json!([
+      "Rights denied to Everyone or Authenticated Users",
+      "Decoy keyword \"canary\" in the name or description",
+   ]));
+}
@@ -0,0 +1,298 @@
+//! Standalone HTML summary report (--html-report)
+//!
+//! One HTML file without external assets (inline CSS, no script) to share the collection results:
+//! - object counts,
+//! - top findings: delegation, roastable accounts, AdminSDHolder non-default ACEs,
+//! - data-quality warnings: duplicated SIDs, unresolved ACE principals, errors logged, partial collection.
+//!
+//! Certificate templates are not collected by RustHound, so there is no ESC finding in the report.
+//! The result is written in `<dirpath>/<domain>_report.html`.
+use crate::banner::Colorize;
+use log::info;
+use std::collections::{HashMap, HashSet};
+use std::fs;
+
+use crate::json::checker::conflicts::SidConflict;
+use crate::modules::adminsdholder::{adminsdholder_acl, adminsdholder_findings};
+use crate::modules::control::error_count;
+use crate::modules::roastable::roastable_accounts;
+use crate::modules::synthetic_edges::is_domain_controller;
+
+/// Maximum number of objects listed in one finding, the total is always displayed.
+const MAX_FINDING_ITEMS: usize = 25;
+
+/// One finding of the report: a title, a severity (high, medium, low) and the objects names.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Finding {
+   pub title: String,
+   pub severity: &'static str,
+   pub items: Vec<String>,
+}
+
+/// Context of the collection, for the data-quality warnings.
+#[derive(Debug, Clone, Default)]
+pub struct CollectionContext {
+   pub sample: u8,
+   pub targeted: bool,
+   pub ldap_filter: bool,
+   pub conflicts: usize,
+}
+
+fn names(objects: Vec<&serde_json::value::Value>) -> Vec<String> {
+   let mut names: Vec<String> = objects.iter()
+      .map(|object| object["Properties"]["name"].as_str().unwrap_or("").to_string())
+      .collect();
+   names.sort();
+   names
+}
+
+/// Function to build the findings from the parsed objects.
+pub fn html_findings(
+   vec_users: &Vec<serde_json::value::Value>,
+   vec_groups: &Vec<serde_json::value::Value>,
+   vec_computers: &Vec<serde_json::value::Value>,
+   vec_containers: &Vec<serde_json::value::Value>,
+   vec_domains: &Vec<serde_json::value::Value>,
+) -> Vec<Finding> {
+   let mut findings: Vec<Finding> = Vec::new();
+   let users_computers = || vec_users.iter().chain(vec_computers.iter());
+
+   // Delegation
+   findings.push(Finding {
+      title: "Unconstrained delegation (domain controllers excluded)".to_string(),
+      severity: "high",
+      items: names(users_computers()
+         .filter(|object| object["Properties"]["unconstraineddelegation"].as_bool().unwrap_or(false))
+         .filter(|object| !is_domain_controller(object))
+         .collect()),
+   });
+   findings.push(Finding {
+      title: "Constrained delegation with protocol transition".to_string(),
+      severity: "high",
+      items: names(users_computers()
+         .filter(|object| object["Properties"]["trustedtoauth"].as_bool().unwrap_or(false))
+         .collect()),
+   });
+   findings.push(Finding {
+      title: "Constrained delegation".to_string(),
+      severity: "medium",
+      items: names(users_computers()
+         .filter(|object| object["AllowedToDelegate"].as_array().map(|a| !a.is_empty()).unwrap_or(false))
+         .collect()),
+   });
+   findings.push(Finding {
+      title: "Resource-based constrained delegation".to_string(),
+      severity: "medium",
+      items: names(vec_computers.iter()
+         .filter(|object| object["AllowedToAct"].as_array().map(|a| !a.is_empty()).unwrap_or(false))
+         .collect()),
+   });
+
+   // Roastable accounts
+   let accounts = roastable_accounts(vec_users);
+   for (category, title, severity) in [
+      ("kerberoastable", "Kerberoastable accounts", "high"),
+      ("asreproastable", "AS-REP roastable accounts", "high"),
+      ("reversibleencryption", "Passwords stored with reversible encryption", "medium"),
+      ("desonly", "DES-only Kerberos accounts", "medium"),
+   ] {
+      let mut items: Vec<String> = accounts.iter()
+         .filter(|account| account.category == category)
+         .map(|account| account.name.to_owned())
+         .collect();
+      items.sort();
+      findings.push(Finding { title: title.to_string(), severity, items });
+   }
+
+   // AdminSDHolder persistence
+   let sid_name: HashMap<String, String> = users_computers().chain(vec_groups.iter())
+      .filter_map(|object| Some((object["ObjectIdentifier"].as_str()?.to_string(), object["Properties"]["name"].as_str()?.to_string())))
+      .collect();
+   findings.push(Finding {
+      title: "Non-default ACEs on AdminSDHolder".to_string(),
+      severity: "high",
+      items: adminsdholder_acl(vec_containers, vec_domains, &sid_name).as_ref().map(adminsdholder_findings).unwrap_or_default().into_iter()
+         .map(|finding| format!("{} ({})", if finding.principal_name.is_empty() { finding.principal_sid } else { finding.principal_name }, finding.right))
+         .collect(),
+   });
+
+   // Most severe first, empty findings last
+   findings.sort_by_key(|finding| (finding.items.is_empty(), severity_rank(finding.severity)));
+   findings
+}
+
+fn severity_rank(severity: &str) -> u8 {
+   match severity {
+      "high" => 0,
+      "medium" => 1,
+      _ => 2,
+   }
+}
+
+/// Function to list the data-quality warnings.
+pub fn data_quality_warnings(
+   context: &CollectionContext,
+   vec_objects: &[(&str, &Vec<serde_json::value::Value>)],
+) -> Vec<String> {
+   let mut warnings: Vec<String> = Vec::new();
+   if context.sample < 100 {
+      warnings.push(format!("Sampled collection: only {}% of the regular users and computers were collected.", context.sample));
+   }
+   if context.targeted {
+      warnings.push("Targeted collection (--targets): only the listed objects and their direct relationships were collected.".to_string());
+   }
+   if context.ldap_filter {
+      warnings.push("Custom LDAP filter or search base: the collection may be partial.".to_string());
+   }
+   if context.conflicts > 0 {
+      warnings.push(format!("{} objects with a duplicated SID were merged, see the conflicts CSV.", context.conflicts));
+   }
+   let errors = error_count();
+   if errors > 0 {
+      warnings.push(format!("{} errors were logged during the collection.", errors));
+   }
+
+   // ACE principals without object, the well-known SIDs are prefixed by the domain name
+   let known: HashSet<&str> = vec_objects.iter()
+      .flat_map(|(_, objects)| objects.iter())
+      .filter_map(|object| object["ObjectIdentifier"].as_str())
+      .collect();
+   let unresolved: HashSet<&str> = vec_objects.iter()
+      .flat_map(|(_, objects)| objects.iter())
+      .flat_map(|object| object["Aces"].as_array().into_iter().flatten())
+      .filter_map(|ace| ace["PrincipalSID"].as_str())
+      .filter(|sid| sid.starts_with("S-1-5-21-") && !known.contains(sid))
+      .collect();
+   if !unresolved.is_empty() {
+      warnings.push(format!("{} ACE principals are not in the collected objects (deleted objects or foreign domains).", unresolved.len()));
+   }
+   warnings
+}
+
+/// Function to escape a text for HTML.
+pub fn html_escape(text: &str) -> String {
+   text.replace('&', "&amp;")
+      .replace('<', "&lt;")
+      .replace('>', "&gt;")
+      .replace('"', "&quot;")
+      .replace('\'', "&#39;")
+}
+
+/// Function to render the standalone HTML report.
+pub fn render_html_report(
+   domain: &String,
+   counts: &[(&str, usize)],
+   findings: &Vec<Finding>,
+   warnings: &Vec<String>,
+) -> String {
+   let mut html = String::new();
+   html.push_str("<!DOCTYPE html>\n<html lang=\"en\">\n<head>\n<meta charset=\"utf-8\">\n");
+   html.push_str(&format!("<title>RustHound report - {}</title>\n", html_escape(domain)));
+   html.push_str("<style>\n\
+      body{font-family:sans-serif;margin:2em;color:#222}\n\
+      table{border-collapse:collapse;margin-bottom:1em}\n\
+      td,th{border:1px solid #ccc;padding:4px 10px;text-align:left}\n\
+      .high{color:#b00020;font-weight:bold}.medium{color:#c77700;font-weight:bold}.low{color:#555}\n\
+      details{margin:0.4em 0}.warning{background:#fff4e5;padding:6px 10px;margin:4px 0}\n\
+      </style>\n</head>\n<body>\n");
+   html.push_str(&format!("<h1>RustHound report - {}</h1>\n", html_escape(domain)));
+   html.push_str(&format!("<p>Generated on {}</p>\n", chrono::Local::now().format("%Y-%m-%d %H:%M:%S")));
+
+   html.push_str("<h2>Collected objects</h2>\n<table>\n<tr><th>Type</th><th>Count</th></tr>\n");
+   for (object_type, count) in counts {
+      html.push_str(&format!("<tr><td>{}</td><td>{}</td></tr>\n", object_type, count));
+   }
+   html.push_str("</table>\n");
+
+   html.push_str("<h2>Findings</h2>\n");
+   for finding in findings {
+      html.push_str(&format!(
+         "<details><summary><span class=\"{}\">{}</span> {} ({})</summary>\n<ul>\n",
+         finding.severity,
+         finding.severity.to_uppercase(),
+         html_escape(&finding.title),
+         finding.items.len(),
+      ));
+      for item in finding.items.iter().take(MAX_FINDING_ITEMS) {
+         html.push_str(&format!("<li>{}</li>\n", html_escape(item)));
+      }
+      if finding.items.len() > MAX_FINDING_ITEMS {
+         html.push_str(&format!("<li>... {} more</li>\n", finding.items.len() - MAX_FINDING_ITEMS));
+      }
+      html.push_str("</ul>\n</details>\n");
+   }
+
+   html.push_str("<h2>Data quality</h2>\n");
+   if warnings.is_empty() {
+      html.push_str("<p>No warning.</p>\n");
+   }
+   for warning in warnings {
+      html.push_str(&format!("<div class=\"warning\">{}</div>\n", html_escape(warning)));
+   }
+   html.push_str("</body>\n</html>\n");
+   html
+}
+
+/// Function to write the HTML report.
+pub fn make_html_report(
+   path: &String,
+   domain: &String,
+   context: &CollectionContext,
+   vec_objects: &[(&str, &Vec<serde_json::value::Value>)],
+) -> std::io::Result<()> {
+   let empty: Vec<serde_json::value::Value> = Vec::new();
+   let get = |object_type: &str| vec_objects.iter()
+      .find(|(t, _)| *t == object_type)
+      .map(|(_, objects)| *objects)
+      .unwrap_or(&empty);
+
+   let counts: Vec<(&str, usize)> = vec_objects.iter().map(|(object_type, objects)| (*object_type, objects.len())).collect();
+   let findings = html_findings(get("User"), get("Group"), get("Computer"), get("Container"), get("Domain"));
+   let warnings = data_quality_warnings(context, vec_objects);
+   let html = render_html_report(domain, &counts, &findings, &warnings);
+
+   fs::create_dir_all(path)?;
+   let final_path = format!("{}/{}_report.html", path, domain.replace(".", "-").to_lowercase());
+   fs::write(&final_path, html)?;
+   info!("{} created!", final_path.bold());
+   Ok(())
+}
+
+/// Function to build the collection context from the arguments and the conflicts.
+pub fn collection_context(sample: u8, targets: &String, ldap_filter: &String, search_base: &String, conflicts: &Vec<SidConflict>) -> CollectionContext {
+   CollectionContext {
+      sample,
+      targeted: !targets.contains("not set"),
+      ldap_filter: !ldap_filter.contains("not set") || !search_base.contains("not set"),
+      conflicts: conflicts.len(),
+   }
+}
+
+/// Test functions
+#[test]
+pub fn test_html_report() {
+   let vec_users = vec![
+      serde_json::json!({"ObjectIdentifier": "S-1-5-21-1-2-3-1110", "Properties": {"name": "SQL_SVC@ESSOS.LOCAL", "samaccountname": "SQL_SVC",
+         "hasspn": true, "enabled": true}, "AllowedToDelegate": [],
+         "Aces": [{"RightName": "GenericAll", "PrincipalSID": "S-1-5-21-1-2-3-9999"}]}),
+   ];
+   let vec_computers = vec![
+      serde_json::json!({"ObjectIdentifier": "S-1-5-21-1-2-3-1001", "PrimaryGroupSID": "S-1-5-21-1-2-3-516",
+         "Properties": {"name": "MEEREEN.ESSOS.LOCAL", "unconstraineddelegation": true}, "AllowedToDelegate": [], "AllowedToAct": [], "Aces": []}),
+      serde_json::json!({"ObjectIdentifier": "S-1-5-21-1-2-3-1002", "PrimaryGroupSID": "S-1-5-21-1-2-3-515",
+         "Properties": {"name": "<BRAAVOS>.ESSOS.LOCAL", "unconstraineddelegation": true}, "AllowedToDelegate": [], "AllowedToAct": [], "Aces": []}),
+   ];
+   let vec_containers: Vec<serde_json::value::Value> = Vec::new();
+   let findings = html_findings(&vec_users, &vec_containers, &vec_computers, &vec_containers, &vec_containers);
+   let unconstrained = findings.iter().find(|finding| finding.title.starts_with("Unconstrained")).unwrap();
+   assert_eq!(unconstrained.items, vec!["<BRAAVOS>.ESSOS.LOCAL".to_string()]);
+   assert_eq!(findings.iter().find(|finding| finding.title == "Kerberoastable accounts").unwrap().items.len(), 1);
+
+   let context = CollectionContext { sample: 100, ..Default::default() };
+   let warnings = data_quality_warnings(&context, &[("User", &vec_users), ("Computer", &vec_computers)]);
+   assert_eq!(warnings.len(), 1);
+
+   let html = render_html_report(&"ESSOS.LOCAL".to_string(), &[("User", 1)], &findings, &warnings);
+   assert!(html.contains("&lt;BRAAVOS&gt;.ESSOS.LOCAL"));
+   assert!(!html.contains("<script") && !html.contains("http"));
+}
@@ -0,0 +1,231 @@
+//! Azure AD Connect and hybrid identity discovery (--hybrid)
+//!
+//! Azure AD Connect (Entra Connect) synchronizes the domain with a tenant, its accounts are Tier 0 by default:
+//! - the MSOL_<id> (AAD_<id> before 2017) connector account is granted "Replicating Directory Changes (All)" on the
+//!   domain for the password hash sync, its password is stored on the AD Connect server: DCSync from this server,
+//!   its description names the server and the tenant,
+//! - AZUREADSSOACC$ is the computer account of the Seamless SSO, its Kerberos key lets forge tickets for any user
+//!   of the tenant and must be rolled over every 30 days,
+//! - the tenant of the hybrid joined devices is published in the service connection point of
+//!   CN=Device Registration Configuration,CN=Services,CN=Configuration (azureADName, azureADId keywords).
+//!
+//! The roles are set on the objects (`hybridrole` property) and written in `<dirpath>/<domain>_hybrid.json` with the
+//! rights granted to the connector accounts (directly, not through their groups).
+use crate::banner::Colorize;
+use lazy_static::lazy_static;
+use log::{info, warn};
+use regex::Regex;
+use serde_json::value::Value;
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::sync::Mutex;
+
+/// Prefixes of the Azure AD Connect connector accounts.
+pub const SYNC_ACCOUNT_PREFIXES: [&str; 2] = ["MSOL_", "AAD_"];
+/// sAMAccountName of the Seamless SSO computer account.
+pub const SEAMLESS_SSO_ACCOUNT: &str = "AZUREADSSOACC$";
+/// Container of the device registration service connection point.
+pub const DEVICE_REGISTRATION_CONTAINER: &str = "CN=DEVICE REGISTRATION CONFIGURATION,CN=SERVICES,";
+
+lazy_static! {
+   static ref HYBRID_OBJECTS: Mutex<Vec<Value>> = Mutex::new(Vec::new());
+   /// "... running on computer AADCONNECT configured to synchronize to tenant essos.onmicrosoft.com. ..."
+   static ref SYNC_DESCRIPTION: Regex = Regex::new(r"(?i)running on computer (\S+?)(?: configured to synchronize to tenant (\S+?))?\.?(?:\s|$)").unwrap();
+}
+
+/// Function to record one device registration service connection point found by the parser.
+pub fn record_hybrid_object(object: Value) {
+   if let Ok(mut objects) = HYBRID_OBJECTS.lock() {
+      objects.push(object);
+   }
+}
+
+/// Function to get the device registration service connection points of the run.
+pub fn hybrid_objects() -> Vec<Value> {
+   HYBRID_OBJECTS.lock().map(|objects| objects.to_owned()).unwrap_or_default()
+}
+
+/// Function to know if a user is an Azure AD Connect connector account.
+pub fn is_sync_account(user: &Value) -> bool {
+   let samaccountname = user["Properties"]["samaccountname"].as_str().unwrap_or("").to_uppercase();
+   let description = user["Properties"]["description"].as_str().unwrap_or("");
+   samaccountname.starts_with("MSOL_")
+      || (samaccountname.starts_with("AAD_") && (description.contains("Azure") || description.contains("Directory Synchronization")))
+}
+
+/// Function to get the AD Connect server and the tenant from the description of a connector account.
+pub fn sync_description(description: &str) -> (Option<String>, Option<String>) {
+   match SYNC_DESCRIPTION.captures(description) {
+      Some(captures) => (
+         captures.get(1).map(|server| server.as_str().to_uppercase()),
+         captures.get(2).map(|tenant| tenant.as_str().to_lowercase()),
+      ),
+      None => (None, None),
+   }
+}
+
+/// Function to count the rights granted to the connector accounts on the objects: SID => right name => count.
+pub fn sync_account_rights(objects: &[(&str, &Vec<Value>)]) -> HashMap<String, BTreeMap<String, usize>> {
+   let mut rights: HashMap<String, BTreeMap<String, usize>> = objects.iter()
+      .filter(|(object_type, _)| *object_type == "User")
+      .flat_map(|(_, users)| users.iter().filter(|user| is_sync_account(user)))
+      .filter_map(|user| user["ObjectIdentifier"].as_str().map(|sid| (sid.to_string(), BTreeMap::new())))
+      .collect();
+   for (_, list) in objects {
+      for ace in list.iter().flat_map(|object| object["Aces"].as_array().into_iter().flatten()) {
+         if let Some(granted) = ace["PrincipalSID"].as_str().and_then(|sid| rights.get_mut(sid)) {
+            *granted.entry(ace["RightName"].as_str().unwrap_or("").to_string()).or_default() += 1;
+         }
+      }
+   }
+   rights
+}
+
+/// Function to flag the hybrid identity objects and build the hybrid report.
+pub fn hybrid_identity(
+   scps: &[Value],
+   vec_users: &mut [Value],
+   vec_computers: &mut [Value],
+   rights: &HashMap<String, BTreeMap<String, usize>>,
+   now: i64,
+) -> Value {
+   let mut tenants: Vec<Value> = scps.iter().map(|scp| serde_json::json!({
+      "name": scp["tenantname"],
+      "id": scp["tenantid"],
+      "source": scp["distinguishedname"],
+   })).collect();
+
+   let mut sync_accounts: Vec<Value> = Vec::new();
+   let mut servers: Vec<String> = Vec::new();
+   for user in vec_users.iter_mut().filter(|user| is_sync_account(user)) {
+      user["Properties"]["hybridrole"] = "AADConnectSync".into();
+      let sid = user["ObjectIdentifier"].as_str().unwrap_or("").to_string();
+      let (server, tenant) = sync_description(user["Properties"]["description"].as_str().unwrap_or(""));
+      let rights = rights.get(&sid).cloned().unwrap_or_default();
+      let dcsync = rights.contains_key("DCSync") || (rights.contains_key("GetChanges") && rights.contains_key("GetChangesAll"));
+      if let Some(tenant) = tenant.as_ref().filter(|tenant| !tenants.iter().any(|known| known["name"] == tenant.as_str())) {
+         tenants.push(serde_json::json!({ "name": tenant, "id": null, "source": user["Properties"]["name"] }));
+      }
+      servers.extend(server.to_owned());
+      sync_accounts.push(serde_json::json!({
+         "name": user["Properties"]["name"],
+         "objectid": sid,
+         "enabled": user["Properties"]["enabled"],
+         "server": server,
+         "tenant": tenant,
+         "dcsync": dcsync,
+         "rights": rights,
+      }));
+   }
+
+   let mut connect_servers: Vec<Value> = Vec::new();
+   let mut seamless_sso: Vec<Value> = Vec::new();
+   for computer in vec_computers.iter_mut() {
+      let samaccountname = computer["Properties"]["samaccountname"].as_str().unwrap_or("").to_uppercase();
+      let name = computer["Properties"]["name"].as_str().unwrap_or("").to_uppercase();
+      if samaccountname == SEAMLESS_SSO_ACCOUNT {
+         computer["Properties"]["hybridrole"] = "SeamlessSSO".into();
+         let pwdlastset = computer["Properties"]["pwdlastset"].as_i64().unwrap_or(-1);
+         let controlled_by: Vec<Value> = computer["Aces"].as_array().into_iter().flatten()
+            .filter(|ace| !ace["IsInherited"].as_bool().unwrap_or(false))
+            .map(|ace| serde_json::json!({ "principal": ace["PrincipalSID"], "right": ace["RightName"] }))
+            .collect();
+         seamless_sso.push(serde_json::json!({
+            "name": computer["Properties"]["name"],
+            "objectid": computer["ObjectIdentifier"],
+            "pwdlastset": pwdlastset,
+            "agedays": if pwdlastset > 0 { Some((now - pwdlastset) / 86400) } else { None },
+            "controlledby": controlled_by,
+         }));
+      } else if servers.iter().any(|server| name == *server || name.split('.').next() == Some(server.as_str())) {
+         computer["Properties"]["hybridrole"] = "AADConnectServer".into();
+         connect_servers.push(serde_json::json!({
+            "name": computer["Properties"]["name"],
+            "objectid": computer["ObjectIdentifier"],
+         }));
+      }
+   }
+
+   serde_json::json!({
+      "tenants": tenants,
+      "syncaccounts": sync_accounts,
+      "connectservers": connect_servers,
+      "seamlesssso": seamless_sso,
+   })
+}
+
+/// Function to flag the hybrid identity objects and write the hybrid report.
+pub fn make_hybrid_report(
+   path: &String,
+   domain: &String,
+   vec_users: &mut [Value],
+   vec_computers: &mut [Value],
+   rights: &HashMap<String, BTreeMap<String, usize>>,
+) -> std::io::Result<()> {
+   let report = hybrid_identity(&hybrid_objects(), vec_users, vec_computers, rights, chrono::Utc::now().timestamp());
+   for account in report["syncaccounts"].as_array().into_iter().flatten().filter(|account| account["dcsync"] == true) {
+      warn!("{} can DCSync, its password is stored on the AD Connect server {}",
+         account["name"].as_str().unwrap_or("").red().bold(), account["server"].as_str().unwrap_or("unknown"));
+   }
+   for account in report["seamlesssso"].as_array().into_iter().flatten().filter(|account| account["agedays"].as_i64().unwrap_or(i64::MAX) > 30) {
+      warn!("{} Kerberos key not rolled over for {} days", account["name"].as_str().unwrap_or("").red().bold(),
+         account["agedays"].as_i64().map(|days| days.to_string()).unwrap_or_else(|| "ever".to_string()));
+   }
+   let count = |key: &str| report[key].as_array().map(|values| values.len()).unwrap_or(0);
+   info!("Hybrid identity: {} tenants, {} connector accounts, {} AD Connect servers, {} Seamless SSO accounts",
+      count("tenants"), count("syncaccounts").to_string().bold(), count("connectservers"), count("seamlesssso"));
+
+   fs::create_dir_all(path)?;
+   let final_path = format!("{}/{}_hybrid.json", path, domain.replace(".", "-").to_lowercase());
+   fs::write(&final_path, serde_json::to_string_pretty(&report).unwrap_or_default())?;
+   info!("{} created!", final_path.bold());
+   Ok(())
+}
+
+/// Test functions
+#[test]
+pub fn test_hybrid_identity() {
+   use crate::json::parser::bh_41::parse_device_registration;
+   let scp = parse_device_registration(ldap3::SearchEntry {
+      dn: "CN=62a0ff2e-97b9-4513-943f-0d221bd30080,CN=Device Registration Configuration,CN=Services,CN=Configuration,DC=ESSOS,DC=LOCAL".to_string(),
+      attrs: HashMap::from([("keywords".to_string(), vec!["azureADName:essos.onmicrosoft.com".to_string(), "azureADId:9b4e1c2a-5d3f-4e6a-8b7c-1d2e3f4a5b6c".to_string()])]),
+      bin_attrs: HashMap::new(),
+   }, &"ESSOS.LOCAL".to_string());
+   assert_eq!(scp["tenantname"], "essos.onmicrosoft.com");
+
+   let description = "Account created by Microsoft Azure Active Directory Connect with installation identifier 4c1e9a7b0d2e4f6a8c105b2c8e1a6f3d running on computer AADCONNECT configured to synchronize to tenant essos.onmicrosoft.com. This account must have directory replication permissions in the local Active Directory and write permission on certain attributes to enable Hybrid Deployment.";
+   assert_eq!(sync_description(description), (Some("AADCONNECT".to_string()), Some("essos.onmicrosoft.com".to_string())));
+   assert_eq!(sync_description("Service account"), (None, None));
+
+   let mut vec_users = vec![
+      serde_json::json!({"ObjectIdentifier": "S-1-5-21-1-2-3-1130", "Properties": {"name": "MSOL_4C1E9A7B0D2E@ESSOS.LOCAL",
+         "samaccountname": "MSOL_4c1e9a7b0d2e", "description": description, "enabled": true}}),
+      serde_json::json!({"ObjectIdentifier": "S-1-5-21-1-2-3-1131", "Properties": {"name": "AAD_TEST@ESSOS.LOCAL", "samaccountname": "AAD_TEST"}}),
+   ];
+   let mut vec_computers = vec![
+      serde_json::json!({"ObjectIdentifier": "S-1-5-21-1-2-3-1140", "Properties": {"name": "AADCONNECT.ESSOS.LOCAL", "samaccountname": "AADCONNECT$"}}),
+      serde_json::json!({"ObjectIdentifier": "S-1-5-21-1-2-3-1141", "Properties": {"name": "AZUREADSSOACC.ESSOS.LOCAL", "samaccountname": "AZUREADSSOACC$",
+         "pwdlastset": 1696161600 - 400 * 86400},
+         "Aces": [{"RightName": "GenericAll", "IsInherited": false, "PrincipalSID": "S-1-5-21-1-2-3-1105", "PrincipalType": "User"}]}),
+   ];
+   let vec_domains = vec![
+      serde_json::json!({"ObjectIdentifier": "S-1-5-21-1-2-3", "Properties": {"name": "ESSOS.LOCAL"}, "Aces": [
+         {"RightName": "GetChanges", "PrincipalSID": "S-1-5-21-1-2-3-1130"},
+         {"RightName": "GetChangesAll", "PrincipalSID": "S-1-5-21-1-2-3-1130"},
+         {"RightName": "DCSync", "PrincipalSID": "S-1-5-21-1-2-3-1130"}]}),
+   ];
+   let rights = sync_account_rights(&[("User", &vec_users), ("Computer", &vec_computers), ("Domain", &vec_domains)]);
+   assert_eq!(rights.len(), 1);
+   let report = hybrid_identity(&[scp], &mut vec_users, &mut vec_computers, &rights, 1696161600);
+   assert_eq!(report["tenants"].as_array().unwrap().len(), 1);
+   assert_eq!(report["tenants"][0]["id"], "9b4e1c2a-5d3f-4e6a-8b7c-1d2e3f4a5b6c");
+   assert_eq!(report["syncaccounts"].as_array().unwrap().len(), 1);
+   assert_eq!(report["syncaccounts"][0]["server"], "AADCONNECT");
+   assert_eq!(report["syncaccounts"][0]["dcsync"], true);
+   assert_eq!(report["syncaccounts"][0]["rights"]["GetChangesAll"], 1);
+   assert_eq!(vec_users[0]["Properties"]["hybridrole"], "AADConnectSync");
+   assert!(vec_users[1]["Properties"].get("hybridrole").is_none());
+   assert_eq!(vec_computers[0]["Properties"]["hybridrole"], "AADConnectServer");
+   assert_eq!(report["seamlesssso"][0]["agedays"], 400);
+   assert_eq!(report["seamlesssso"][0]["controlledby"][0]["principal"], "S-1-5-21-1-2-3-1105");
+}
@@ -0,0 +1,237 @@
+//! On-disk cache of the raw LDAP entries (--cache-dir, --from-cache)
+//!
+//! The entries collected live are written in `<cache-dir>/ldap_<domain>.bin`, a compact binary file,
+//! so another run with other output or analysis flags can parse them again without requesting the DC.
+//! The entries are keyed by objectGUID and uSNChanged: an object already cached is only replaced
+//! when its uSNChanged changed, a full collection also removes the objects not collected anymore.
+//! The live schema GUIDs are saved with the entries to resolve the ACE object types offline.
+//!
+//! The cache holds every collected attribute, LAPS passwords included: protect it like the JSON files.
+//!
+//! File format, all integers are u32 little-endian and every string or value is length-prefixed:
+//! `RHLDAP01`, domain, schema GUIDs count and (name, guid) pairs, entries count and entries
+//! (dn, attributes count and (name, values count, values), binary attributes count and (name, values count, values)).
+use crate::banner::Colorize;
+use ldap3::SearchEntry;
+use log::{debug, info};
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::io::{Error, ErrorKind, Result};
+
+use crate::enums::schema::{add_live_schema_guids, live_schema_guids, SCHEMA_CACHE_DIR};
+
+/// Magic and version of the cache file.
+const CACHE_MAGIC: &[u8; 8] = b"RHLDAP01";
+
+/// Function to get the cache directory, .rusthound_cache if not set.
+pub fn cache_dir(cache_dir: &String) -> String {
+   if cache_dir.contains("not set") { SCHEMA_CACHE_DIR.to_string() } else { cache_dir.to_owned() }
+}
+
+/// Function to get the cache file path for one domain.
+pub fn ldap_cache_path(cache_dir: &String, domain: &String) -> String {
+   format!("{}/ldap_{}.bin", cache_dir, domain.to_lowercase())
+}
+
+/// Function to get the cache key of one entry: objectGUID and uSNChanged.
+pub fn cache_key(entry: &SearchEntry) -> Option<(Vec<u8>, u64)> {
+   let guid = entry.bin_attrs.get("objectGUID").and_then(|values| values.first())?.to_owned();
+   let usn = entry.attrs.get("uSNChanged").and_then(|values| values.first()).and_then(|usn| usn.parse::<u64>().ok()).unwrap_or(0);
+   Some((guid, usn))
+}
+
+/// Function to merge the fresh entries in the cached ones.
+/// A partial collection (targets, filter, sample) keeps the cached objects not collected.
+/// Return the merged entries and the number of new or changed objects.
+pub fn merge_entries(cached: Vec<SearchEntry>, fresh: &Vec<SearchEntry>, partial: bool) -> (Vec<SearchEntry>, usize) {
+   let cached_usn: HashMap<Vec<u8>, u64> = cached.iter().filter_map(cache_key).collect();
+   let mut changed = 0;
+   let mut fresh_guids: HashSet<Vec<u8>> = HashSet::new();
+   for entry in fresh {
+      match cache_key(entry) {
+         Some((guid, usn)) => {
+            if cached_usn.get(&guid) != Some(&usn) {
+               changed += 1;
+            }
+            fresh_guids.insert(guid);
+         }
+         None => changed += 1,
+      }
+   }
+   let mut merged: Vec<SearchEntry> = Vec::new();
+   if partial {
+      merged.extend(cached.into_iter().filter(|entry| {
+         cache_key(entry).map(|(guid, _)| !fresh_guids.contains(&guid)).unwrap_or(false)
+      }));
+   }
+   merged.extend(fresh.iter().map(|entry| SearchEntry {
+      dn: entry.dn.to_owned(),
+      attrs: entry.attrs.to_owned(),
+      bin_attrs: entry.bin_attrs.to_owned(),
+   }));
+   (merged, changed)
+}
+
+fn write_bytes(buffer: &mut Vec<u8>, bytes: &[u8]) {
+   buffer.extend_from_slice(&(bytes.len() as u32).to_le_bytes());
+   buffer.extend_from_slice(bytes);
+}
+
+fn write_count(buffer: &mut Vec<u8>, count: usize) {
+   buffer.extend_from_slice(&(count as u32).to_le_bytes());
+}
+
+/// Function to serialize the entries in the cache format.
+pub fn encode_ldap_cache(domain: &String, schema_guids: &HashMap<String, String>, entries: &Vec<SearchEntry>) -> Vec<u8> {
+   let mut buffer: Vec<u8> = Vec::new();
+   buffer.extend_from_slice(CACHE_MAGIC);
+   write_bytes(&mut buffer, domain.as_bytes());
+   write_count(&mut buffer, schema_guids.len());
+   for (name, guid) in schema_guids {
+      write_bytes(&mut buffer, name.as_bytes());
+      write_bytes(&mut buffer, guid.as_bytes());
+   }
+   write_count(&mut buffer, entries.len());
+   for entry in entries {
+      write_bytes(&mut buffer, entry.dn.as_bytes());
+      write_count(&mut buffer, entry.attrs.len());
+      for (name, values) in &entry.attrs {
+         write_bytes(&mut buffer, name.as_bytes());
+         write_count(&mut buffer, values.len());
+         for value in values {
+            write_bytes(&mut buffer, value.as_bytes());
+         }
+      }
+      write_count(&mut buffer, entry.bin_attrs.len());
+      for (name, values) in &entry.bin_attrs {
+         write_bytes(&mut buffer, name.as_bytes());
+         write_count(&mut buffer, values.len());
+         for value in values {
+            write_bytes(&mut buffer, value);
+         }
+      }
+   }
+   buffer
+}
+
+/// Reader over the cache bytes.
+struct CacheReader<'a> {
+   bytes: &'a [u8],
+   offset: usize,
+}
+
+impl<'a> CacheReader<'a> {
+   fn take(&mut self, len: usize) -> Result<&'a [u8]> {
+      if self.bytes.len() - self.offset < len {
+         return Err(Error::new(ErrorKind::UnexpectedEof, "truncated LDAP cache"))
+      }
+      let slice = &self.bytes[self.offset..self.offset + len];
+      self.offset += len;
+      Ok(slice)
+   }
+
+   fn count(&mut self) -> Result<usize> {
+      let mut le = [0u8; 4];
+      le.copy_from_slice(self.take(4)?);
+      Ok(u32::from_le_bytes(le) as usize)
+   }
+
+   fn bytes(&mut self) -> Result<Vec<u8>> {
+      let len = self.count()?;
+      Ok(self.take(len)?.to_vec())
+   }
+
+   fn string(&mut self) -> Result<String> {
+      String::from_utf8(self.bytes()?).map_err(|err| Error::new(ErrorKind::InvalidData, err))
+   }
+}
+
+/// Function to parse the cache format, return the domain, the schema GUIDs and the entries.
+pub fn decode_ldap_cache(bytes: &[u8]) -> Result<(String, HashMap<String, String>, Vec<SearchEntry>)> {
+   let mut reader = CacheReader { bytes, offset: 0 };
+   if reader.take(CACHE_MAGIC.len())? != CACHE_MAGIC {
+      return Err(Error::new(ErrorKind::InvalidData, "not a RustHound LDAP cache"))
+   }
+   let domain = reader.string()?;
+   let mut schema_guids: HashMap<String, String> = HashMap::new();
+   for _ in 0..reader.count()? {
+      let name = reader.string()?;
+      schema_guids.insert(name, reader.string()?);
+   }
+   let mut entries: Vec<SearchEntry> = Vec::new();
+   for _ in 0..reader.count()? {
+      let dn = reader.string()?;
+      let mut attrs: HashMap<String, Vec<String>> = HashMap::new();
+      for _ in 0..reader.count()? {
+         let name = reader.string()?;
+         let values = (0..reader.count()?).map(|_| reader.string()).collect::<Result<Vec<String>>>()?;
+         attrs.insert(name, values);
+      }
+      let mut bin_attrs: HashMap<String, Vec<Vec<u8>>> = HashMap::new();
+      for _ in 0..reader.count()? {
+         let name = reader.string()?;
+         let values = (0..reader.count()?).map(|_| reader.bytes()).collect::<Result<Vec<Vec<u8>>>>()?;
+         bin_attrs.insert(name, values);
+      }
+      entries.push(SearchEntry { dn, attrs, bin_attrs });
+   }
+   Ok((domain, schema_guids, entries))
+}
+
+/// Function to write the collected entries in the cache, merged with the previous cache.
+pub fn save_ldap_cache(cache_dir: &String, domain: &String, entries: &Vec<SearchEntry>, partial: bool) -> Result<()> {
+   let path = ldap_cache_path(cache_dir, domain);
+   let cached = match fs::read(&path).map(|bytes| decode_ldap_cache(&bytes)) {
+      Ok(Ok((_, _, cached))) => cached,
+      Ok(Err(err)) => {
+         debug!("Ignoring LDAP cache {}: {}", &path, err);
+         Vec::new()
+      }
+      Err(_) => Vec::new(),
+   };
+   let (merged, changed) = merge_entries(cached, entries, partial);
+   fs::create_dir_all(cache_dir)?;
+   fs::write(&path, encode_ldap_cache(domain, &live_schema_guids(), &merged))?;
+   info!("{} LDAP entries cached in {} ({} new or changed)", merged.len().to_string().bold(), path.bold(), changed.to_string().bold());
+   Ok(())
+}
+
+/// Function to read the cached entries instead of requesting the DC.
+pub fn load_ldap_cache(cache_dir: &String, domain: &String) -> Result<Vec<SearchEntry>> {
+   let path = ldap_cache_path(cache_dir, domain);
+   let (cached_domain, schema_guids, entries) = decode_ldap_cache(&fs::read(&path)?)?;
+   if !cached_domain.eq_ignore_ascii_case(domain) {
+      return Err(Error::new(ErrorKind::InvalidData, format!("cache {} is for {}", path, cached_domain)))
+   }
+   add_live_schema_guids(schema_guids);
+   info!("{} LDAP entries loaded from {}", entries.len().to_string().bold(), path.bold());
+   Ok(entries)
+}
+
+/// Test functions
+#[test]
+pub fn test_ldap_cache() {
+   let entry = |dn: &str, guid: u8, usn: &str| SearchEntry {
+      dn: dn.to_string(),
+      attrs: vec![("uSNChanged".to_string(), vec![usn.to_string()]), ("name".to_string(), vec![dn.to_string()])].into_iter().collect(),
+      bin_attrs: vec![("objectGUID".to_string(), vec![vec![guid; 16]])].into_iter().collect(),
+   };
+   let cached = || vec![entry("CN=JON", 1, "100"), entry("CN=ARYA", 2, "100")];
+   let fresh = vec![entry("CN=JON", 1, "100"), entry("CN=SANSA", 3, "120")];
+
+   let (merged, changed) = merge_entries(cached(), &fresh, true);
+   assert_eq!(changed, 1);
+   assert_eq!(merged.len(), 3);
+   let (merged, _) = merge_entries(cached(), &fresh, false);
+   assert_eq!(merged.len(), 2);
+
+   let schema_guids: HashMap<String, String> = vec![("ms-mcs-admpwd".to_string(), "guid".to_string())].into_iter().collect();
+   let bytes = encode_ldap_cache(&"ESSOS.LOCAL".to_string(), &schema_guids, &merged);
+   let (domain, guids, entries) = decode_ldap_cache(&bytes).unwrap();
+   assert_eq!(domain, "ESSOS.LOCAL");
+   assert_eq!(guids, schema_guids);
+   assert_eq!(entries.len(), 2);
+   assert_eq!(entries[1].dn, "CN=SANSA");
+   assert_eq!(entries[1].bin_attrs["objectGUID"][0], vec![3u8; 16]);
+   assert!(decode_ldap_cache(&bytes[..bytes.len() - 1]).is_err());
+}
@@ -0,0 +1,159 @@
+//! Machine account quota and computer creation (--machine-quota)
+//!
+//! A computer account controlled by the attacker is the first step of most RBCD and relay attacks:
+//! - ms-DS-MachineAccountQuota lets every authenticated user create this number of computers (10 by default),
+//! - CreateChild on the computer class of an OU, a container or the domain lets a principal create them anywhere,
+//! - a computer pre-created as "pre-Windows 2000 compatible" has its lower case name as password (14 characters
+//!   at most) until its first logon: enabled, PASSWD_NOTREQD and never logged on (`pre2kcandidate` property).
+//!
+//! Everything is written in `<dirpath>/<domain>_machine_quota.json`, the creators which are not privileged first.
+use crate::banner::Colorize;
+use log::{info, warn};
+use serde_json::value::Value;
+use std::collections::HashMap;
+use std::fs;
+
+/// Well-known privileged creators: Domain Admins, Enterprise Admins, Administrators, Account Operators.
+pub const PRIVILEGED_CREATOR_SUFFIXES: [&str; 4] = ["-512", "-519", "-S-1-5-32-544", "-S-1-5-32-548"];
+
+fn flag(object: &Value, property: &str) -> bool {
+   object["Properties"][property].as_bool().unwrap_or(false)
+}
+
+/// Function to know if a computer looks pre-created as pre-Windows 2000 compatible, return the likely password.
+pub fn pre2k_password(computer: &Value) -> Option<String> {
+   let never_logged_on = computer["Properties"]["lastlogon"].as_i64().unwrap_or(-1) <= 0
+      && computer["Properties"]["lastlogontimestamp"].as_i64().unwrap_or(-1) <= 0;
+   if !(flag(computer, "enabled") && flag(computer, "passwordnotreqd") && never_logged_on) {
+      return None
+   }
+   let name = computer["Properties"]["samaccountname"].as_str()
+      .map(|name| name.trim_end_matches('$').to_string())
+      .or_else(|| computer["Properties"]["name"].as_str().and_then(|name| name.split('.').next()).map(|name| name.to_string()))?;
+   Some(name.to_lowercase().chars().take(14).collect())
+}
+
+/// Function to flag the pre-Windows 2000 computers and list the computer creators, return the report.
+/// The creators are resolved with the principals (users and groups) and the computers.
+pub fn machine_quota(
+   vec_domains: &[Value],
+   vec_computers: &mut [Value],
+   containers: &[(&str, &Vec<Value>)],
+   principals: &[(&str, &Vec<Value>)],
+) -> Value {
+   let quotas: Vec<Value> = vec_domains.iter()
+      .map(|domain| serde_json::json!({
+         "domain": domain["Properties"]["name"],
+         "machineaccountquota": domain["Properties"]["machineaccountquota"],
+      }))
+      .collect();
+
+   let mut pre2k: Vec<Value> = Vec::new();
+   for computer in vec_computers.iter_mut() {
+      if let Some(password) = pre2k_password(computer) {
+         computer["Properties"]["pre2kcandidate"] = true.into();
+         pre2k.push(serde_json::json!({
+            "name": computer["Properties"]["name"],
+            "objectid": computer["ObjectIdentifier"],
+            "candidatepassword": password,
+         }));
+      }
+   }
+
+   let mut names: HashMap<&str, (&Value, bool)> = HashMap::new();
+   for objects in principals.iter().map(|(_, objects)| objects.as_slice()).chain([&*vec_computers]) {
+      for object in objects.iter() {
+         if let Some(sid) = object["ObjectIdentifier"].as_str() {
+            names.insert(sid, (&object["Properties"]["name"], flag(object, "admincount") || flag(object, "highvalue")));
+         }
+      }
+   }
+   let mut creators: Vec<Value> = Vec::new();
+   for (object_type, objects) in containers {
+      for object in objects.iter() {
+         for sid in object["Properties"]["computercreators"].as_array().into_iter().flatten().filter_map(|sid| sid.as_str()) {
+            let (name, privileged) = names.get(sid).map(|(name, privileged)| ((*name).to_owned(), *privileged)).unwrap_or((Value::Null, false));
+            creators.push(serde_json::json!({
+               "container": object["Properties"]["name"],
+               "containerid": object["ObjectIdentifier"],
+               "containertype": object_type,
+               "principal": name,
+               "principalid": sid,
+               "privileged": privileged || PRIVILEGED_CREATOR_SUFFIXES.iter().any(|suffix| sid.ends_with(suffix)),
+            }));
+         }
+      }
+   }
+   creators.sort_by_key(|creator| creator["privileged"] == true);
+
+   serde_json::json!({
+      "machineaccountquota": quotas,
+      "pre2kcomputers": pre2k,
+      "computercreators": creators,
+   })
+}
+
+/// Function to write the machine account quota report.
+pub fn make_machine_quota_report(
+   path: &String,
+   domain: &String,
+   vec_domains: &[Value],
+   vec_computers: &mut [Value],
+   containers: &[(&str, &Vec<Value>)],
+   principals: &[(&str, &Vec<Value>)],
+) -> std::io::Result<()> {
+   let report = machine_quota(vec_domains, vec_computers, containers, principals);
+   for quota in report["machineaccountquota"].as_array().into_iter().flatten() {
+      if quota["machineaccountquota"].as_i64().unwrap_or(0) > 0 {
+         warn!("{}: any authenticated user can create {} computers in {}", "MachineAccountQuota".yellow().bold(),
+            quota["machineaccountquota"], quota["domain"].as_str().unwrap_or(""));
+      }
+   }
+   let unprivileged = report["computercreators"].as_array().into_iter().flatten().filter(|creator| creator["privileged"] == false).count();
+   info!("{} pre-Windows 2000 computers, {} unprivileged computer creators",
+      report["pre2kcomputers"].as_array().map(|computers| computers.len()).unwrap_or(0).to_string().bold(), unprivileged.to_string().bold());
+
+   fs::create_dir_all(path)?;
+   let final_path = format!("{}/{}_machine_quota.json", path, domain.replace(".", "-").to_lowercase());
+   fs::write(&final_path, serde_json::to_string_pretty(&report).unwrap_or_default())?;
+   info!("{} created!", final_path.bold());
+   Ok(())
+}
+
+/// Test functions
+#[test]
+pub fn test_machine_quota() {
+   use crate::enums::secdesc::Ace;
+   use crate::enums::acl::computer_creators;
+
+   // ACCESS_ALLOWED_OBJECT_ACE CreateChild on the computer class (bf967a86-0de6-11d0-a285-00aa003049e2) for S-1-5-21-1-2-3-1104
+   let mut create_computer = vec![0x05, 0x00, 0x38, 0x00, 0x01, 0x00, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00,
+      0x86, 0x7a, 0x96, 0xbf, 0xe6, 0x0d, 0xd0, 0x11, 0xa2, 0x85, 0x00, 0xaa, 0x00, 0x30, 0x49, 0xe2,
+      0x01, 0x05, 0x00, 0x00, 0x00, 0x00, 0x00, 0x05, 0x15, 0x00, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00,
+      0x02, 0x00, 0x00, 0x00, 0x03, 0x00, 0x00, 0x00, 0x50, 0x04, 0x00, 0x00];
+   let domain = "ESSOS.LOCAL".to_string();
+   let entry_type = "ou".to_string();
+   assert_eq!(computer_creators(&[Ace::parse(&create_computer).unwrap().1], &domain, &entry_type), vec!["S-1-5-21-1-2-3-1104"]);
+   // Same ACE on the user class (bf967aba-0de6-11d0-a285-00aa003049e2)
+   create_computer[12] = 0xba;
+   assert!(computer_creators(&[Ace::parse(&create_computer).unwrap().1], &domain, &entry_type).is_empty());
+
+   let domains = vec![serde_json::json!({"ObjectIdentifier": "S-1-5-21-1-2-3", "Properties": {"name": "ESSOS.LOCAL", "machineaccountquota": 10}})];
+   let mut computers = vec![
+      serde_json::json!({"ObjectIdentifier": "S-1-5-21-1-2-3-1301", "Properties": {"name": "YOUNGGRIFF.ESSOS.LOCAL", "samaccountname": "YOUNGGRIFF$",
+         "enabled": true, "passwordnotreqd": true, "lastlogon": -1, "lastlogontimestamp": -1}}),
+      serde_json::json!({"ObjectIdentifier": "S-1-5-21-1-2-3-1001", "Properties": {"name": "BRAAVOS.ESSOS.LOCAL", "samaccountname": "BRAAVOS$",
+         "enabled": true, "passwordnotreqd": false, "lastlogon": 1696000000, "lastlogontimestamp": 1696000000}}),
+   ];
+   let ous = vec![serde_json::json!({"ObjectIdentifier": "0F2B", "Properties": {"name": "SERVERS@ESSOS.LOCAL",
+      "computercreators": ["S-1-5-21-1-2-3-512", "S-1-5-21-1-2-3-1104"]}})];
+   let users = vec![serde_json::json!({"ObjectIdentifier": "S-1-5-21-1-2-3-1104", "Properties": {"name": "JORAH.MORMONT@ESSOS.LOCAL", "admincount": false}})];
+   let report = machine_quota(&domains, &mut computers, &[("OU", &ous)], &[("User", &users)]);
+   assert_eq!(report["machineaccountquota"][0]["machineaccountquota"], 10);
+   assert_eq!(report["pre2kcomputers"], serde_json::json!([{"name": "YOUNGGRIFF.ESSOS.LOCAL", "objectid": "S-1-5-21-1-2-3-1301", "candidatepassword": "younggriff"}]));
+   assert_eq!(computers[0]["Properties"]["pre2kcandidate"], true);
+   assert!(computers[1]["Properties"]["pre2kcandidate"].is_null());
+   assert_eq!(report["computercreators"][0]["principal"], "JORAH.MORMONT@ESSOS.LOCAL");
+   assert_eq!(report["computercreators"][0]["privileged"], false);
+   assert_eq!(report["computercreators"][1]["privileged"], true);
+}
@@ -0,0 +1,138 @@
+//! Effective group membership report (--memberships)
+//!
+//! The nested groups are flattened to know every group of a principal without Neo4j:
+//! a user member of A, itself member of B, is written as member of A (depth 1) and B (depth 2, via A).
+//! The primary group membership is included, the group Members hold it after the checker.
+//!
+//! The result is written in `<dirpath>/<domain>_memberships.csv`.
+use crate::banner::Colorize;
+use log::info;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::fs;
+
+use crate::json::maker::csv::csv_field;
+
+/// One effective membership.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Membership {
+   pub member_sid: String,
+   pub member_type: String,
+   pub group_sid: String,
+   /// 1 for a direct membership
+   pub depth: usize,
+   /// Groups between the member and the group, the direct group first
+   pub via: Vec<String>,
+}
+
+/// Function to flatten the group memberships of every member of a group.
+/// Each (member, group) is written once with the shortest path, the cycles are ignored.
+pub fn effective_memberships(vec_groups: &Vec<serde_json::value::Value>) -> Vec<Membership> {
+   // member => direct groups
+   let mut member_of: HashMap<String, Vec<String>> = HashMap::new();
+   let mut member_type: HashMap<String, String> = HashMap::new();
+   for group in vec_groups {
+      let group_sid = match group["ObjectIdentifier"].as_str() {
+         Some(sid) => sid.to_string(),
+         None => continue,
+      };
+      member_type.insert(group_sid.to_owned(), "Group".to_string());
+      for member in group["Members"].as_array().into_iter().flatten() {
+         if let Some(sid) = member["ObjectIdentifier"].as_str() {
+            member_of.entry(sid.to_string()).or_default().push(group_sid.to_owned());
+            member_type.entry(sid.to_string()).or_insert_with(|| member["ObjectType"].as_str().unwrap_or("Base").to_string());
+         }
+      }
+   }
+
+   let mut members: Vec<&String> = member_of.keys().collect();
+   members.sort();
+   let mut memberships: Vec<Membership> = Vec::new();
+   for member in members {
+      // Breadth-first, the first path to a group is the shortest
+      let mut visited: HashSet<&String> = HashSet::new();
+      visited.insert(member);
+      let mut queue: VecDeque<(&String, Vec<String>)> = VecDeque::new();
+      queue.push_back((member, Vec::new()));
+      while let Some((current, via)) = queue.pop_front() {
+         for group in member_of.get(current).into_iter().flatten() {
+            if !visited.insert(group) {
+               continue
+            }
+            memberships.push(Membership {
+               member_sid: member.to_owned(),
+               member_type: member_type.get(member).cloned().unwrap_or_default(),
+               group_sid: group.to_owned(),
+               depth: via.len() + 1,
+               via: via.to_owned(),
+            });
+            let mut next = via.to_owned();
+            next.push(group.to_owned());
+            queue.push_back((group, next));
+         }
+      }
+   }
+   memberships
+}
+
+/// Function to write the effective memberships in a CSV file.
+pub fn make_memberships_report(
+   path: &String,
+   domain: &String,
+   vec_groups: &Vec<serde_json::value::Value>,
+   vec_principals: &[(&str, &Vec<serde_json::value::Value>)],
+) -> std::io::Result<()> {
+   let mut sid_name: HashMap<String, String> = HashMap::new();
+   for (_, objects) in vec_principals {
+      for object in objects.iter() {
+         if let (Some(sid), Some(name)) = (object["ObjectIdentifier"].as_str(), object["Properties"]["name"].as_str()) {
+            sid_name.insert(sid.to_string(), name.to_string());
+         }
+      }
+   }
+   let name = |sid: &String| sid_name.get(sid).cloned().unwrap_or_else(|| sid.to_owned());
+
+   let memberships = effective_memberships(vec_groups);
+   let mut csv = String::from("member,type,memberidentifier,group,groupidentifier,depth,via\n");
+   for membership in &memberships {
+      csv.push_str(&format!(
+         "{},{},{},{},{},{},{}\n",
+         csv_field(&name(&membership.member_sid)),
+         membership.member_type,
+         membership.member_sid,
+         csv_field(&name(&membership.group_sid)),
+         membership.group_sid,
+         membership.depth,
+         csv_field(&membership.via.iter().map(&name).collect::<Vec<String>>().join(" > ")),
+      ));
+   }
+   let nested = memberships.iter().filter(|membership| membership.depth > 1).count();
+   info!("{} effective memberships ({} through nested groups)", memberships.len().to_string().bold(), nested.to_string().bold());
+
+   fs::create_dir_all(path)?;
+   let final_path = format!("{}/{}_memberships.csv", path, domain.replace(".", "-").to_lowercase());
+   fs::write(&final_path, csv)?;
+   info!("{} created!", final_path.bold());
+   Ok(())
+}
+
+/// Test functions
+#[test]
+pub fn test_effective_memberships() {
+   let vec_groups = vec![
+      serde_json::json!({"ObjectIdentifier": "A", "Members": [{"ObjectIdentifier": "JON", "ObjectType": "User"}]}),
+      serde_json::json!({"ObjectIdentifier": "B", "Members": [{"ObjectIdentifier": "A", "ObjectType": "Group"}]}),
+      // Cycle B > C > B
+      serde_json::json!({"ObjectIdentifier": "C", "Members": [{"ObjectIdentifier": "B", "ObjectType": "Group"}, {"ObjectIdentifier": "JON", "ObjectType": "User"}]}),
+      serde_json::json!({"ObjectIdentifier": "B2", "Members": [{"ObjectIdentifier": "C", "ObjectType": "Group"}]}),
+   ];
+   let memberships = effective_memberships(&vec_groups);
+   let jon: Vec<&Membership> = memberships.iter().filter(|membership| membership.member_sid == "JON").collect();
+   assert_eq!(jon.len(), 4);
+   let b = jon.iter().find(|membership| membership.group_sid == "B").unwrap();
+   assert_eq!((b.depth, b.via.to_owned()), (2, vec!["A".to_string()]));
+   let c = jon.iter().find(|membership| membership.group_sid == "C").unwrap();
+   assert_eq!(c.depth, 1);
+   let b2 = jon.iter().find(|membership| membership.group_sid == "B2").unwrap();
+   assert_eq!((b2.depth, b2.via.to_owned()), (2, vec!["C".to_string()]));
+   assert_eq!(b.member_type, "User");
+}
@@ -0,0 +1,216 @@
+//! Run metrics in the Prometheus text format (--metrics, --metrics-port)
+//!
+//! For the scheduled collections, the objects and edges per type, the duration of every phase, the objects of
+//! every collector, the LDAP reconnections and the errors are written in `<dirpath>/<domain>_metrics.prom`
+//! at the end of the run, for the node_exporter textfile collector or any automation alerting on anomalies.
+//! With `--metrics-port` the same metrics are served on `http://127.0.0.1:<port>/metrics` during the run.
+//!
+//! ```text
+//! rusthound_objects{domain="ESSOS.LOCAL",type="User"} 42
+//! rusthound_edges{domain="ESSOS.LOCAL",type="MemberOf"} 120
+//! rusthound_phase_duration_seconds{domain="ESSOS.LOCAL",phase="LDAP collection"} 3.2
+//! rusthound_ldap_retries_total{domain="ESSOS.LOCAL"} 0
+//! ```
+use crate::banner::Colorize;
+use lazy_static::lazy_static;
+use log::{debug, info};
+use serde_json::value::Value;
+use std::collections::BTreeMap;
+use std::fs;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use crate::json::maker::csv::object_edges;
+use crate::modules::control::{error_count, ListenerThread};
+use crate::modules::telemetry::run_summary;
+
+/// Objects and edges of the collection.
+#[derive(Debug, Clone, Default)]
+pub struct ObjectMetrics {
+   pub domain: String,
+   /// Object type => objects
+   pub objects: BTreeMap<String, u64>,
+   /// Edge type => edges
+   pub edges: BTreeMap<String, u64>,
+}
+
+lazy_static! {
+   static ref OBJECT_METRICS: Mutex<ObjectMetrics> = Mutex::new(ObjectMetrics::default());
+}
+static LDAP_RETRIES: AtomicU64 = AtomicU64::new(0);
+
+/// Function to count one LDAP reconnection.
+pub fn add_ldap_retry() {
+   LDAP_RETRIES.fetch_add(1, Ordering::Relaxed);
+}
+
+/// Function to count the objects and the edges of every type.
+pub fn count_objects(domain: &String, objects: &[(&str, &Vec<Value>)]) -> ObjectMetrics {
+   let mut metrics = ObjectMetrics { domain: domain.to_uppercase(), ..Default::default() };
+   for (object_type, vec_objects) in objects {
+      *metrics.objects.entry(object_type.to_string()).or_default() += vec_objects.len() as u64;
+      for object in vec_objects.iter() {
+         for edge in object_edges(object, object_type) {
+            *metrics.edges.entry(edge.edge).or_default() += 1;
+         }
+      }
+   }
+   metrics
+}
+
+/// Function to record the objects and the edges once the collection is checked.
+pub fn record_objects(domain: &String, objects: &[(&str, &Vec<Value>)]) {
+   let metrics = count_objects(domain, objects);
+   if let Ok(mut current) = OBJECT_METRICS.lock() {
+      *current = metrics;
+   }
+}
+
+/// Function to write the metrics in the Prometheus text format.
+pub fn render_metrics(metrics: &ObjectMetrics, summary: &Value, ldap_retries: u64) -> String {
+   let domain = format!("domain=\"{}\"", escape_label(&metrics.domain));
+   let seconds = |value: &Value| value.as_u64().unwrap_or(0) as f64 / 1000.0;
+   let mut text = String::new();
+   let mut family = |name: &str, kind: &str, help: &str, samples: Vec<(String, String)>| {
+      text.push_str(&format!("# HELP {} {}\n# TYPE {} {}\n", name, help, name, kind));
+      for (labels, value) in samples {
+         text.push_str(&format!("{}{{{}{}}} {}\n", name, domain, labels, value));
+      }
+   };
+
+   family("rusthound_run_duration_seconds", "gauge", "Duration of the run.",
+      vec![(String::new(), seconds(&summary["elapsed_ms"]).to_string())]);
+   family("rusthound_last_run_timestamp_seconds", "gauge", "End of the run, seconds since the epoch.",
+      vec![(String::new(), chrono::Local::now().timestamp().to_string())]);
+   family("rusthound_errors_total", "counter", "Errors logged during the run.",
+      vec![(String::new(), summary["errors"].as_u64().unwrap_or(0).to_string())]);
+   family("rusthound_ldap_retries_total", "counter", "LDAP reconnections after a dropped connection.",
+      vec![(String::new(), ldap_retries.to_string())]);
+   family("rusthound_objects", "gauge", "Objects collected per type.",
+      metrics.objects.iter().map(|(name, count)| (format!(",type=\"{}\"", escape_label(name)), count.to_string())).collect());
+   family("rusthound_edges", "gauge", "Edges collected per type.",
+      metrics.edges.iter().map(|(name, count)| (format!(",type=\"{}\"", escape_label(name)), count.to_string())).collect());
+
+   let phases: Vec<&Value> = summary["phases"].as_array().into_iter().flatten().collect();
+   family("rusthound_phase_duration_seconds", "gauge", "Duration of every phase.",
+      phases.iter().map(|phase| (format!(",phase=\"{}\"", escape_label(phase["phase"].as_str().unwrap_or(""))), seconds(&phase["elapsed_ms"]).to_string())).collect());
+   family("rusthound_phase_objects", "gauge", "Objects processed by every phase.",
+      phases.iter().map(|phase| (format!(",phase=\"{}\"", escape_label(phase["phase"].as_str().unwrap_or(""))), phase["objects"].as_u64().unwrap_or(0).to_string())).collect());
+   let mut collectors: Vec<(String, String)> = Vec::new();
+   for phase in &phases {
+      for collector in phase["collectors"].as_array().into_iter().flatten() {
+         collectors.push((
+            format!(",phase=\"{}\",collector=\"{}\"", escape_label(phase["phase"].as_str().unwrap_or("")), escape_label(collector["name"].as_str().unwrap_or(""))),
+            collector["count"].as_u64().unwrap_or(0).to_string(),
+         ));
+      }
+   }
+   family("rusthound_collector_objects", "gauge", "Last count of every collector.", collectors);
+   text
+}
+
+/// Function to get the current metrics of the run.
+pub fn current_metrics() -> String {
+   let metrics = OBJECT_METRICS.lock().map(|metrics| metrics.to_owned()).unwrap_or_default();
+   render_metrics(&metrics, &run_summary(error_count()), LDAP_RETRIES.load(Ordering::Relaxed))
+}
+
+/// Function to write the metrics file at the end of the run.
+pub fn make_metrics_file(path: &String, domain: &String) -> std::io::Result<()> {
+   fs::create_dir_all(path)?;
+   let final_path = format!("{}/{}_metrics.prom", path, domain.replace(".", "-").to_lowercase());
+   fs::write(&final_path, current_metrics())?;
+   info!("{} created!", final_path.bold());
+   Ok(())
+}
+
+/// Time given to a metrics client to send its request and read the answer.
+const METRICS_CLIENT_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(2);
+
+/// Function to serve the metrics on the loopback in a background thread, every request gets the metrics,
+/// until the returned thread is dropped.
+pub fn start_metrics_server(port: &String) -> std::io::Result<ListenerThread> {
+   use std::io::{ErrorKind, Read, Write};
+   use std::net::TcpListener;
+
+   let port = port.parse::<u16>().map_err(|err| std::io::Error::new(ErrorKind::InvalidInput, err))?;
+   let listener = TcpListener::bind(("127.0.0.1", port))?;
+   listener.set_nonblocking(true)?;
+   info!("Metrics served on http://127.0.0.1:{}/metrics", port);
+   Ok(ListenerThread::spawn(move || {
+      let mut stream = match listener.accept() {
+         Ok((stream, _)) => stream,
+         Err(err) if err.kind() == ErrorKind::WouldBlock => return false,
+         Err(err) => {
+            debug!("Metrics client error: {err}");
+            return false
+         }
+      };
+      // One idle client must not hold the endpoint for the rest of the run
+      let _ = stream.set_nonblocking(false);
+      let _ = stream.set_read_timeout(Some(METRICS_CLIENT_TIMEOUT));
+      let _ = stream.set_write_timeout(Some(METRICS_CLIENT_TIMEOUT));
+      // The request is not routed, read it to not reset the connection
+      let mut request = [0u8; 1024];
+      let _ = stream.read(&mut request);
+      let body = current_metrics();
+      let _ = write!(
+         stream,
+         "HTTP/1.0 200 OK\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\n\r\n{}",
+         body.len(),
+         body
+      );
+      true
+   }))
+}
+
+fn escape_label(value: &str) -> String {
+   value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
+
+/// Test functions
+#[test]
+pub fn test_render_metrics() {
+   let users = vec![
+      serde_json::json!({"ObjectIdentifier": "S-1-5-21-1-2-3-1104", "Aces": [{"RightName": "GenericAll", "PrincipalSID": "S-1-5-21-1-2-3-512", "PrincipalType": "Group"}]}),
+      serde_json::json!({"ObjectIdentifier": "S-1-5-21-1-2-3-1105"}),
+   ];
+   let groups = vec![
+      serde_json::json!({"ObjectIdentifier": "S-1-5-21-1-2-3-512", "Members": [{"ObjectIdentifier": "S-1-5-21-1-2-3-1104", "ObjectType": "User"}, {"ObjectIdentifier": "S-1-5-21-1-2-3-1105", "ObjectType": "User"}]}),
+   ];
+   let metrics = count_objects(&"essos.local".to_string(), &[("User", &users), ("Group", &groups)]);
+   let summary = serde_json::json!({"elapsed_ms": 1500, "errors": 1, "phases": [
+      {"phase": "LDAP \"collection\"", "elapsed_ms": 1000, "objects": 3, "collectors": [{"name": "LDAP objects retreived", "count": 3}]},
+   ]});
+   let text = render_metrics(&metrics, &summary, 2);
+   assert!(text.contains("# TYPE rusthound_objects gauge\n"));
+   assert!(text.contains("rusthound_objects{domain=\"ESSOS.LOCAL\",type=\"User\"} 2\n"));
+   assert!(text.contains("rusthound_edges{domain=\"ESSOS.LOCAL\",type=\"MemberOf\"} 2\n"));
+   assert!(text.contains("rusthound_edges{domain=\"ESSOS.LOCAL\",type=\"GenericAll\"} 1\n"));
+   assert!(text.contains("rusthound_run_duration_seconds{domain=\"ESSOS.LOCAL\"} 1.5\n"));
+   assert!(text.contains("rusthound_errors_total{domain=\"ESSOS.LOCAL\"} 1\n"));
+   assert!(text.contains("rusthound_ldap_retries_total{domain=\"ESSOS.LOCAL\"} 2\n"));
+   assert!(text.contains("rusthound_phase_duration_seconds{domain=\"ESSOS.LOCAL\",phase=\"LDAP \\\"collection\\\"\"} 1\n"));
+   assert!(text.contains("collector=\"LDAP objects retreived\"} 3\n"));
+}
+
+#[test]
+pub fn test_metrics_server_idle_client() {
+   use std::io::{Read, Write};
+   use std::net::{TcpListener, TcpStream};
+
+   let port = TcpListener::bind(("127.0.0.1", 0)).unwrap().local_addr().unwrap().port();
+   let server = start_metrics_server(&port.to_string()).unwrap();
+   // A client connecting without sending its request
+   let _idle = TcpStream::connect(("127.0.0.1", port)).unwrap();
+   let mut client = TcpStream::connect(("127.0.0.1", port)).unwrap();
+   client.set_read_timeout(Some(METRICS_CLIENT_TIMEOUT * 3)).unwrap();
+   client.write_all(b"GET /metrics HTTP/1.0\r\n\r\n").unwrap();
+   let mut response = String::new();
+   client.read_to_string(&mut response).unwrap();
+   assert!(response.starts_with("HTTP/1.0 200 OK"));
+   // Stopped at the end of the run, the port is free for the next run
+   drop(server);
+   assert!(TcpStream::connect(("127.0.0.1", port)).is_err());
+   drop(start_metrics_server(&port.to_string()).unwrap());
+}
@@ -1,19 +1,429 @@
 //! List of RustHound add-on modules
 #[doc(inline)]
 pub use resolver::*;
+#[doc(inline)]
+pub use synthetic_edges::*;
+#[doc(inline)]
+pub use sampling::*;
+#[doc(inline)]
+pub use risk_score::*;
+#[doc(inline)]
+pub use control::*;
+#[doc(inline)]
+pub use targets::*;
+#[doc(inline)]
+pub use adminsdholder::*;
+#[doc(inline)]
+pub use roastable::*;
+#[doc(inline)]
+pub use html_report::*;
+#[doc(inline)]
+pub use ldap_cache::*;
+#[doc(inline)]
+pub use offline::*;
+#[doc(inline)]
+pub use telemetry::*;
+#[doc(inline)]
+pub use dc_locator::*;
+#[doc(inline)]
+pub use memberships::*;
+#[doc(inline)]
+pub use attack_surface::*;
+#[doc(inline)]
+pub use metrics::*;
+#[doc(inline)]
+pub use socks::*;
+#[doc(inline)]
+pub use encrypt_output::*;
+#[doc(inline)]
+pub use skipped::*;
+#[doc(inline)]
+pub use diff::*;
+#[doc(inline)]
+pub use honeypots::*;
+#[doc(inline)]
+pub use password_scan::*;
+#[doc(inline)]
+pub use sccm::*;
+#[doc(inline)]
+pub use exchange::*;
+#[doc(inline)]
+pub use mssql::*;
+#[doc(inline)]
+pub use machine_quota::*;
+#[doc(inline)]
+pub use gpp::*;
+#[doc(inline)]
+pub use relay_scan::*;
+#[doc(inline)]
+pub use remote::*;
+#[doc(inline)]
+pub use webclient::*;
+#[doc(inline)]
+pub use coercion::*;
+#[doc(inline)]
+pub use paths::*;
+#[doc(inline)]
+pub use query::*;
+#[doc(inline)]
+pub use edge_provenance::*;
+#[doc(inline)]
+pub use acl_baseline::*;
+#[cfg(feature = "upload")]
+#[doc(inline)]
+pub use upload::*;
+#[doc(inline)]
+pub use password_age::*;
+#[doc(inline)]
+pub use hybrid::*;
+#[doc(inline)]
+pub use restricted_groups::*;
+#[cfg(feature = "entra")]
+#[doc(inline)]
+pub use entra::*;
 pub mod resolver;
+pub mod synthetic_edges;
+pub mod sampling;
+pub mod risk_score;
+pub mod control;
+pub mod targets;
+pub mod adminsdholder;
+pub mod roastable;
+pub mod html_report;
+pub mod ldap_cache;
+pub mod offline;
+pub mod telemetry;
+pub mod dc_locator;
+pub mod memberships;
+pub mod attack_surface;
+pub mod metrics;
+pub mod socks;
+pub mod encrypt_output;
+pub mod skipped;
+pub mod diff;
+pub mod honeypots;
+pub mod password_scan;
+pub mod sccm;
+pub mod exchange;
+pub mod mssql;
+pub mod machine_quota;
+pub mod gpp;
+pub mod relay_scan;
+pub mod remote;
+pub mod webclient;
+pub mod coercion;
+pub mod paths;
+pub mod query;
+pub mod edge_provenance;
+pub mod acl_baseline;
+#[cfg(feature = "upload")]
+pub mod upload;
+pub mod password_age;
+pub mod hybrid;
+pub mod restricted_groups;
+#[cfg(feature = "entra")]
+pub mod entra;
 
 use std::collections::HashMap;
+use log::{error, warn};
 use crate::args::*;
+use crate::json::checker::conflicts::SidConflict;
+use crate::json::spill::SpillStore;
+
+/// Function to get the output of the report tables: stdout, or stderr when stdout only carries the NDJSON records.
+pub fn table_output(stdout_ndjson: bool) -> Box<dyn std::io::Write> {
+   if stdout_ndjson {
+      Box::new(std::io::stderr())
+   } else {
+      Box::new(std::io::stdout())
+   }
+}
+
+/// Function to list every object type with its objects, for the modules reading the whole collection.
+pub fn collection<'a>(
+   vec_users: &'a Vec<serde_json::value::Value>,
+   vec_groups: &'a Vec<serde_json::value::Value>,
+   vec_computers: &'a Vec<serde_json::value::Value>,
+   vec_ous: &'a Vec<serde_json::value::Value>,
+   vec_domains: &'a Vec<serde_json::value::Value>,
+   vec_gpos: &'a Vec<serde_json::value::Value>,
+   vec_containers: &'a Vec<serde_json::value::Value>,
+) -> [(&'static str, &'a Vec<serde_json::value::Value>); 7] {
+   [
+      ("User", vec_users),
+      ("Group", vec_groups),
+      ("Computer", vec_computers),
+      ("OU", vec_ous),
+      ("Domain", vec_domains),
+      ("GPO", vec_gpos),
+      ("Container", vec_containers),
+   ]
+}
+
+/// Function to list every object type with its objects, for the steps changing the whole collection.
+pub fn collection_mut<'a>(
+   vec_users: &'a mut Vec<serde_json::value::Value>,
+   vec_groups: &'a mut Vec<serde_json::value::Value>,
+   vec_computers: &'a mut Vec<serde_json::value::Value>,
+   vec_ous: &'a mut Vec<serde_json::value::Value>,
+   vec_domains: &'a mut Vec<serde_json::value::Value>,
+   vec_gpos: &'a mut Vec<serde_json::value::Value>,
+   vec_containers: &'a mut Vec<serde_json::value::Value>,
+) -> [(&'static str, &'a mut Vec<serde_json::value::Value>); 7] {
+   [
+      ("User", vec_users),
+      ("Group", vec_groups),
+      ("Computer", vec_computers),
+      ("OU", vec_ous),
+      ("Domain", vec_domains),
+      ("GPO", vec_gpos),
+      ("Container", vec_containers),
+   ]
+}
+
+/// Function to know if a module or an output of the run needs all the users and computers in memory.
+/// The objects are not spilled with --low-memory in this case.
+pub fn reads_collection(common_args: &Options) -> bool {
+   common_args.fqdn_resolver
+      || common_args.edge_justification
+      || common_args.edge_provenance
+      || common_args.risk_scores
+      || common_args.adminsdholder
+      || common_args.memberships
+      || common_args.reports
+      || common_args.html_report
+      || common_args.attack_surface
+      || common_args.honeypots
+      || common_args.password_scan
+      || common_args.sccm
+      || common_args.hybrid
+      || common_args.exchange
+      || common_args.mssql
+      || common_args.machine_quota
+      || common_args.webclient
+      || common_args.coercion
+      || common_args.metrics
+      || common_args.stdout_ndjson
+      || common_args.in_memory
+      || common_args.output_format != "json"
+      || !common_args.sysvol.contains("not set")
+      || !common_args.relay_scan.contains("not set")
+      || !common_args.path_query.contains("not set")
+      || !common_args.query.contains("not set")
+      || !common_args.diff.contains("not set")
+      || !common_args.acl_baseline.contains("not set")
+      || !common_args.metrics_port.contains("not set")
+      || !common_args.upload.contains("not set")
+}
 
 pub async fn run_modules(
    common_args: &Options, 
    fqdn_ip: &mut HashMap<String, String>, 
-   vec_computers: &mut Vec<serde_json::value::Value>
+   vec_users: &mut Vec<serde_json::value::Value>,
+   vec_groups: &mut Vec<serde_json::value::Value>,
+   vec_computers: &mut Vec<serde_json::value::Value>,
+   vec_ous: &mut Vec<serde_json::value::Value>,
+   vec_domains: &mut Vec<serde_json::value::Value>,
+   vec_gpos: &mut Vec<serde_json::value::Value>,
+   vec_containers: &mut Vec<serde_json::value::Value>,
+   conflicts: &Vec<SidConflict>,
+   spill: Option<&SpillStore>,
 ) {
    // Running module to resolve FQDN to IP address?
    if common_args.fqdn_resolver {
-      fqdn_resolver(common_args.dns_tcp, &common_args.ip, &common_args.name_server, fqdn_ip, &vec_computers).await;
+      fqdn_resolver(common_args.dns_tcp, &common_args.ip, &common_args.name_server, fqdn_ip, &vec_computers, common_args.exclude_stale).await;
+   }
+
+   // Running module to add synthetic edges (builtin groups and --edge-rules ruleset)?
+   let rules = load_edge_rules(common_args.builtin_edges, &common_args.edge_rules);
+   if !rules.is_empty() {
+      if let Err(err) = add_synthetic_edges(&rules, vec_users, vec_groups, vec_computers, vec_domains, spill) {
+         error!("Error while adding synthetic edges. Reason: {err}");
+      }
+   }
+
+   // Running module to rank objects in a CSV file?
+   if common_args.risk_scores {
+      let objects = collection(vec_users, vec_groups, vec_computers, vec_ous, vec_domains, vec_gpos, vec_containers);
+      if let Err(err) = isolate_collector("Risk scores", || make_risk_scores(&common_args.path, &common_args.domain, &objects)) {
+         error!("Error while writing risk scores. Reason: {err}");
+      }
+   }
+
+   // Running module to report the AdminSDHolder ACL and the protected objects?
+   if common_args.adminsdholder {
+      let principals = [
+         ("User", &*vec_users),
+         ("Group", &*vec_groups),
+         ("Computer", &*vec_computers),
+      ];
+      if let Err(err) = isolate_collector("AdminSDHolder", || make_adminsdholder_report(&common_args.path, &common_args.domain, vec_containers, vec_domains, &principals)) {
+         error!("Error while writing AdminSDHolder report. Reason: {err}");
+      }
+   }
+
+   // Running module to flatten the nested group memberships in a CSV file?
+   if common_args.memberships {
+      let principals = [
+         ("User", &*vec_users),
+         ("Group", &*vec_groups),
+         ("Computer", &*vec_computers),
+      ];
+      if let Err(err) = isolate_collector("Memberships", || make_memberships_report(&common_args.path, &common_args.domain, vec_groups, &principals)) {
+         error!("Error while writing memberships report. Reason: {err}");
+      }
+   }
+
+   // Running module to flag the SCCM site servers, management points and PXE servers?
+   if common_args.sccm {
+      if let Err(err) = isolate_collector("SCCM", || make_sccm_report(&common_args.path, &common_args.domain, vec_computers, vec_containers)) {
+         error!("Error while writing SCCM report. Reason: {err}");
+      }
+   }
+
+   // Running module to flag the Azure AD Connect accounts and servers and the Seamless SSO account?
+   if common_args.hybrid {
+      let rights = sync_account_rights(&collection(vec_users, vec_groups, vec_computers, vec_ous, vec_domains, vec_gpos, vec_containers));
+      if let Err(err) = isolate_collector("Hybrid identity", || make_hybrid_report(&common_args.path, &common_args.domain, vec_users, vec_computers, &rights)) {
+         error!("Error while writing hybrid identity report. Reason: {err}");
+      }
+   }
+
+   // Running module to flag the Exchange servers, RBAC groups and PrivExchange domains?
+   if common_args.exchange {
+      if let Err(err) = isolate_collector("Exchange", || make_exchange_report(&common_args.path, &common_args.domain, vec_computers, vec_groups, vec_domains)) {
+         error!("Error while writing Exchange report. Reason: {err}");
+      }
+   }
+
+   // Running module to list the MSSQL instances of the MSSQLSvc SPNs?
+   if common_args.mssql {
+      if let Err(err) = isolate_collector("MSSQL", || make_mssql_report(&common_args.path, &common_args.domain, vec_users, vec_computers, vec_groups)) {
+         error!("Error while writing MSSQL report. Reason: {err}");
+      }
+   }
+
+   // Running module to report the machine account quota, the computer creators and the pre-Windows 2000 computers?
+   if common_args.machine_quota {
+      let containers = [
+         ("OU", &*vec_ous),
+         ("Container", &*vec_containers),
+         ("Domain", &*vec_domains),
+      ];
+      let principals = [
+         ("User", &*vec_users),
+         ("Group", &*vec_groups),
+      ];
+      if let Err(err) = isolate_collector("Machine account quota", || make_machine_quota_report(&common_args.path, &common_args.domain, vec_domains, vec_computers, &containers, &principals)) {
+         error!("Error while writing machine account quota report. Reason: {err}");
+      }
+   }
+
+   // Running module to decrypt the Group Policy Preferences passwords of a SYSVOL copy?
+   if !common_args.sysvol.contains("not set") {
+      if let Err(err) = isolate_collector("GPP passwords", || make_gpp_report(&common_args.path, &common_args.domain, &common_args.sysvol, vec_gpos)) {
+         error!("Error while writing GPP passwords report. Reason: {err}");
+      }
+      let principals = [
+         ("User", &vec_users[..]),
+         ("Group", &vec_groups[..]),
+      ];
+      if let Err(err) = isolate_collector("GPO local groups", || make_gpo_local_groups_report(&common_args.path, &common_args.domain, &common_args.sysvol, vec_computers, &principals, &[vec_ous, vec_domains], vec_gpos)) {
+         error!("Error while writing GPO local groups report. Reason: {err}");
+      }
+   }
+
+   // Without -p the SMB and LDAP probes send an empty password
+   let password = common_args.password.to_owned().unwrap_or_default();
+
+   // Running module to scan the SMB signing, LDAP signing and LDAPS of the DCs (and servers)?
+   if !common_args.relay_scan.contains("not set") {
+      if let Err(err) = make_relay_report(&common_args.path, &common_args.domain, &common_args.relay_scan, common_args.relay_ldap_bind, &common_args.username, &password, vec_computers, fqdn_ip).await {
+         error!("Error while writing relay posture report. Reason: {err}");
+      }
+   }
+
+   // The SMB probes open a session on every computer, --stealth only talks to the DC
+   if common_args.stealth && (common_args.webclient || common_args.coercion) {
+      warn!("--webclient and --coercion skipped, --stealth only talks to the DC");
+   }
+
+   // Running module to probe the WebClient service of the computers?
+   if common_args.webclient && !common_args.stealth {
+      if let Err(err) = make_webclient_report(&common_args.path, &common_args.domain, &common_args.username, &password, common_args.webclient_threads, common_args.exclude_stale, vec_computers, fqdn_ip).await {
+         error!("Error while writing WebClient report. Reason: {err}");
+      }
+   }
+
+   // Running module to probe the coercion RPC interfaces of the computers?
+   if common_args.coercion && !common_args.stealth {
+      if let Err(err) = make_coercion_report(&common_args.path, &common_args.domain, &common_args.username, &password, common_args.exclude_stale, vec_computers, fqdn_ip).await {
+         error!("Error while writing coercion report. Reason: {err}");
+      }
+   }
+
+   // The modules below only read the collection
+   let objects = collection(vec_users, vec_groups, vec_computers, vec_ous, vec_domains, vec_gpos, vec_containers);
+
+   // Running module to find the shortest path between two objects?
+   if !common_args.path_query.contains("not set") {
+      if let Err(err) = isolate_collector("Path", || make_path_report(&common_args.path, &common_args.domain, &common_args.path_query, &objects)) {
+         error!("Error while writing path report. Reason: {err}");
+      }
+   }
+
+   // Running module to answer a canned query?
+   if !common_args.query.contains("not set") {
+      if let Err(err) = isolate_collector("Query", || make_query_report(&common_args.query, &objects, &mut table_output(common_args.stdout_ndjson))) {
+         error!("Error while running query. Reason: {err}");
+      }
+   }
+
+   // Running module to report the roastable and weak credential accounts?
+   if common_args.reports {
+      if let Err(err) = isolate_collector("Roastable", || make_roastable_report(&common_args.path, &common_args.domain, vec_users, common_args.pretty, &mut table_output(common_args.stdout_ndjson))) {
+         error!("Error while writing roastable report. Reason: {err}");
+      }
+      if let Err(err) = isolate_collector("Password age", || make_password_age_report(&common_args.path, &common_args.domain, vec_users, vec_computers, common_args.pretty, &mut table_output(common_args.stdout_ndjson))) {
+         error!("Error while writing password age report. Reason: {err}");
+      }
+   }
+
+   // Running module to write the standalone HTML report?
+   if common_args.html_report {
+      let context = collection_context(common_args.sample, &common_args.targets, &common_args.ldap_filter, &common_args.search_base, conflicts);
+      if let Err(err) = isolate_collector("HTML report", || make_html_report(&common_args.path, &common_args.domain, &context, &objects)) {
+         error!("Error while writing HTML report. Reason: {err}");
+      }
+   }
+
+   // Running module to write the Markdown and Graphviz attack surface summary?
+   if common_args.attack_surface {
+      if let Err(err) = isolate_collector("Attack surface", || make_attack_surface_report(&common_args.path, &common_args.domain, &objects)) {
+         error!("Error while writing attack surface report. Reason: {err}");
+      }
+   }
+
+   // Running module to flag the likely honey accounts and canary objects?
+   if common_args.honeypots {
+      if let Err(err) = isolate_collector("Honeypots", || make_honeypots_report(&common_args.path, &common_args.domain, &objects)) {
+         error!("Error while writing honeypots report. Reason: {err}");
+      }
+   }
+
+   // Running module to find the passwords in the attributes?
+   if common_args.password_scan {
+      if let Err(err) = isolate_collector("Password scan", || make_password_report(&common_args.path, &common_args.domain, &objects)) {
+         error!("Error while writing password scan report. Reason: {err}");
+      }
+   }
+
+   // Running module to collect the Entra ID users, groups and roles?
+   #[cfg(feature = "entra")]
+   if let Some(token) = &common_args.entra_token {
+      if let Err(err) = isolate_collector("Entra ID", || make_entra_result(&common_args.path, &common_args.domain, token, common_args.pretty)) {
+         error!("Error while collecting Entra ID. Reason: {err}");
+      }
    }
 
    // Other modules need to be add here...
@@ -0,0 +1,154 @@
+//! MSSQL instances from the MSSQLSvc SPNs (--mssql)
+//!
+//! An instance registers `MSSQLSvc/host:port` and `MSSQLSvc/host:instance` on the account running the service.
+//! The SPNs of the users and computers are normalized to one entry per host, instance and port, the host is
+//! resolved to a domain computer when possible. The account running the service is sysadmin on the instance:
+//! when this account is also privileged (admincount or member of a high value group), a `SQLAdmin` edge hint from
+//! the account to the host computer is added for the follow-up tools.
+//! Everything is written in `<dirpath>/<domain>_mssql.json`.
+use crate::banner::Colorize;
+use log::info;
+use serde_json::value::Value;
+use std::collections::HashSet;
+use std::fs;
+
+/// Default instance name and port of MSSQL.
+pub const MSSQL_DEFAULT_INSTANCE: &str = "MSSQLSERVER";
+pub const MSSQL_DEFAULT_PORT: u16 = 1433;
+
+/// One MSSQLSvc SPN: host in upper case, instance name and port when known.
+#[derive(Debug, PartialEq, Eq)]
+pub struct MssqlSpn {
+   pub host: String,
+   pub instance: Option<String>,
+   pub port: Option<u16>,
+}
+
+/// Function to parse one MSSQLSvc SPN: MSSQLSvc/host, MSSQLSvc/host:1433 or MSSQLSvc/host:INSTANCE.
+pub fn parse_mssql_spn(spn: &str) -> Option<MssqlSpn> {
+   let (service, target) = spn.split_once('/')?;
+   if !service.eq_ignore_ascii_case("MSSQLSvc") {
+      return None
+   }
+   let (host, suffix) = match target.split_once(':') {
+      Some((host, suffix)) => (host, Some(suffix)),
+      None => (target, None),
+   };
+   if host.is_empty() {
+      return None
+   }
+   let (instance, port) = match suffix.filter(|suffix| !suffix.is_empty()) {
+      Some(suffix) => match suffix.parse::<u16>() {
+         Ok(port) => (None, Some(port)),
+         Err(_) => (Some(suffix.to_uppercase()), None),
+      },
+      None => (Some(MSSQL_DEFAULT_INSTANCE.to_string()), Some(MSSQL_DEFAULT_PORT)),
+   };
+   Some(MssqlSpn { host: host.to_uppercase(), instance, port })
+}
+
+/// Function to know if an account is privileged: admincount or direct member of a high value group.
+fn privileged(account: &Value, admin_members: &HashSet<String>) -> bool {
+   account["Properties"]["admincount"].as_bool().unwrap_or(false)
+      || account["ObjectIdentifier"].as_str().map(|sid| admin_members.contains(sid)).unwrap_or(false)
+}
+
+/// Function to list the MSSQL instances and the SQLAdmin edge hints.
+pub fn mssql_inventory(vec_users: &[Value], vec_computers: &[Value], vec_groups: &[Value]) -> Value {
+   let admin_members: HashSet<String> = vec_groups.iter()
+      .filter(|group| group["Properties"]["highvalue"].as_bool().unwrap_or(false))
+      .flat_map(|group| group["Members"].as_array().cloned().unwrap_or_default())
+      .filter_map(|member| member["ObjectIdentifier"].as_str().map(|sid| sid.to_string()))
+      .collect();
+   let find_computer = |host: &str| vec_computers.iter().find(|computer| {
+      let name = computer["Properties"]["name"].as_str().unwrap_or("").to_uppercase();
+      name == host || (!host.contains('.') && name.split('.').next() == Some(host))
+   });
+
+   let mut instances: Vec<Value> = Vec::new();
+   let mut edges: Vec<Value> = Vec::new();
+   for (object_type, accounts) in [("User", vec_users), ("Computer", vec_computers)] {
+      for account in accounts {
+         let spns = account["Properties"]["serviceprincipalnames"].as_array().into_iter().flatten()
+            .filter_map(|spn| spn.as_str())
+            .filter_map(parse_mssql_spn);
+         for spn in spns {
+            let computer = find_computer(&spn.host);
+            let host = computer.map(|computer| computer["Properties"]["name"].to_owned()).unwrap_or_else(|| spn.host.to_owned().into());
+            let sid = &account["ObjectIdentifier"];
+            // host:port and host:instance SPNs of the same instance are merged on the account and host
+            let known = instances.iter_mut().find(|instance| instance["host"] == host && instance["accountid"] == *sid
+               && (instance["instance"].is_null() || spn.instance.is_none() || instance["instance"].as_str() == spn.instance.as_deref())
+               && (instance["port"].is_null() || spn.port.is_none() || instance["port"].as_u64() == spn.port.map(u64::from)));
+            match known {
+               Some(instance) => {
+                  if instance["instance"].is_null() { instance["instance"] = spn.instance.into(); }
+                  if instance["port"].is_null() { instance["port"] = spn.port.into(); }
+               }
+               None => instances.push(serde_json::json!({
+                  "host": host,
+                  "computerid": computer.map(|computer| computer["ObjectIdentifier"].to_owned()),
+                  "instance": spn.instance,
+                  "port": spn.port,
+                  "account": account["Properties"]["name"],
+                  "accountid": sid,
+                  "accounttype": object_type,
+               })),
+            }
+            if let Some(computer) = computer {
+               let edge = serde_json::json!({"source": sid, "target": computer["ObjectIdentifier"], "edge": "SQLAdmin"});
+               if object_type == "User" && privileged(account, &admin_members) && !edges.contains(&edge) {
+                  edges.push(edge);
+               }
+            }
+         }
+      }
+   }
+   serde_json::json!({
+      "instances": instances,
+      "edges": edges,
+   })
+}
+
+/// Function to write the MSSQL inventory.
+pub fn make_mssql_report(path: &String, domain: &String, vec_users: &[Value], vec_computers: &[Value], vec_groups: &[Value]) -> std::io::Result<()> {
+   let report = mssql_inventory(vec_users, vec_computers, vec_groups);
+   let count = |key: &str| report[key].as_array().map(|values| values.len()).unwrap_or(0);
+   info!("MSSQL: {} instances, {} SQLAdmin edge hints", count("instances").to_string().bold(), count("edges"));
+
+   fs::create_dir_all(path)?;
+   let final_path = format!("{}/{}_mssql.json", path, domain.replace(".", "-").to_lowercase());
+   fs::write(&final_path, serde_json::to_string_pretty(&report).unwrap_or_default())?;
+   info!("{} created!", final_path.bold());
+   Ok(())
+}
+
+/// Test functions
+#[test]
+pub fn test_mssql_inventory() {
+   assert_eq!(parse_mssql_spn("MSSQLSvc/braavos.essos.local:1433"), Some(MssqlSpn { host: "BRAAVOS.ESSOS.LOCAL".to_string(), instance: None, port: Some(1433) }));
+   assert_eq!(parse_mssql_spn("mssqlsvc/braavos:SQLEXPRESS"), Some(MssqlSpn { host: "BRAAVOS".to_string(), instance: Some("SQLEXPRESS".to_string()), port: None }));
+   assert_eq!(parse_mssql_spn("MSSQLSvc/sql01.essos.local"), Some(MssqlSpn { host: "SQL01.ESSOS.LOCAL".to_string(), instance: Some("MSSQLSERVER".to_string()), port: Some(1433) }));
+   assert_eq!(parse_mssql_spn("HTTP/braavos.essos.local"), None);
+
+   let users = vec![
+      serde_json::json!({"ObjectIdentifier": "S-1-5-21-1-2-3-1110", "Properties": {"name": "SQL_SVC@ESSOS.LOCAL", "admincount": false,
+         "serviceprincipalnames": ["MSSQLSvc/braavos.essos.local:1433", "MSSQLSvc/braavos.essos.local:SQLEXPRESS", "MSSQLSvc/sql-legacy.essos.local"]}}),
+      serde_json::json!({"ObjectIdentifier": "S-1-5-21-1-2-3-1111", "Properties": {"name": "JORAH.MORMONT@ESSOS.LOCAL", "serviceprincipalnames": ["HTTP/braavos"]}}),
+   ];
+   let computers = vec![serde_json::json!({"ObjectIdentifier": "S-1-5-21-1-2-3-1001", "Properties": {"name": "BRAAVOS.ESSOS.LOCAL",
+      "serviceprincipalnames": ["MSSQLSvc/BRAAVOS:1434"]}})];
+   let mut groups = vec![serde_json::json!({"ObjectIdentifier": "S-1-5-21-1-2-3-512", "Properties": {"name": "DOMAIN ADMINS@ESSOS.LOCAL", "highvalue": true},
+      "Members": []})];
+   let report = mssql_inventory(&users, &computers, &groups);
+   assert_eq!(report["instances"].as_array().unwrap().len(), 3);
+   assert_eq!(report["instances"][0], serde_json::json!({"host": "BRAAVOS.ESSOS.LOCAL", "computerid": "S-1-5-21-1-2-3-1001", "instance": "SQLEXPRESS",
+      "port": 1433, "account": "SQL_SVC@ESSOS.LOCAL", "accountid": "S-1-5-21-1-2-3-1110", "accounttype": "User"}));
+   assert_eq!(report["instances"][1]["computerid"], Value::Null);
+   assert_eq!(report["instances"][2]["accounttype"], "Computer");
+   assert!(report["edges"].as_array().unwrap().is_empty());
+
+   groups[0]["Members"] = serde_json::json!([{"ObjectIdentifier": "S-1-5-21-1-2-3-1110", "ObjectType": "User"}]);
+   let report = mssql_inventory(&users, &computers, &groups);
+   assert_eq!(report["edges"], serde_json::json!([{"source": "S-1-5-21-1-2-3-1110", "target": "S-1-5-21-1-2-3-1001", "edge": "SQLAdmin"}]));
+}
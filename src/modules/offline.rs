@@ -0,0 +1,327 @@
+//! Offline parsing of LDAP exports (--input)
+//!
+//! When only a dump was exfiltrated, the BloodHound JSON files are built without any access to the DC:
+//! - `--input ldif <file>`: an LDIF export like `ldapsearch -E '!1.2.840.113556.1.4.801=::MAMCAQc=' ... > dump.ldif`,
+//!   folded lines and base64 values (`attribute:: value`, nTSecurityDescriptor included) are decoded,
+//! - `--input ldapdomaindump <dir>`: the `domain_*.json` files of ldapdomaindump, the SIDs, GUIDs and dates
+//!   are converted back to their LDAP format. ldapdomaindump doesn't dump nTSecurityDescriptor, so no ACL edge.
+//!
+//! The entries are then parsed like the live collection.
+use chrono::{DateTime, FixedOffset};
+use crate::banner::Colorize;
+use ldap3::SearchEntry;
+use log::{debug, info, warn};
+use std::collections::HashMap;
+use std::fs;
+use std::io::{Error, ErrorKind, Result};
+
+use crate::enums::attributes::LDAP_ATTRIBUTES;
+use crate::enums::secdesc::LdapSid;
+
+/// Attributes always stored as binary values by the parser.
+pub const BINARY_ATTRIBUTES: [&str; 8] = [
+   "objectSid",
+   "objectGUID",
+   "nTSecurityDescriptor",
+   "sIDHistory",
+   "securityIdentifier",
+   "msDS-AllowedToActOnBehalfOfOtherIdentity",
+   "msDS-GroupMSAMembership",
+   "schemaIDGUID",
+];
+
+/// ldapdomaindump files with LDAP objects.
+const LDAPDOMAINDUMP_FILES: [&str; 5] = [
+   "domain_users.json",
+   "domain_groups.json",
+   "domain_computers.json",
+   "domain_policy.json",
+   "domain_trusts.json",
+];
+
+/// ldapdomaindump attributes converted in datetime, back to FILETIME.
+const FILETIME_ATTRIBUTES: [&str; 5] = ["pwdLastSet", "lastLogon", "lastLogonTimestamp", "accountExpires", "badPasswordTime"];
+/// ldapdomaindump attributes converted in datetime, back to GeneralizedTime.
+const GENERALIZED_TIME_ATTRIBUTES: [&str; 2] = ["whenCreated", "whenChanged"];
+
+/// Seconds between 1601-01-01 and 1970-01-01.
+const FILETIME_EPOCH_OFFSET: i64 = 11_644_473_600;
+
+/// Function to load the entries of an export, format is ldif or ldapdomaindump.
+pub fn load_input(format: &String, path: &String) -> Result<Vec<SearchEntry>> {
+   let entries = match format.to_lowercase().as_str() {
+      "ldif" => parse_ldif(&fs::read_to_string(path)?),
+      "ldapdomaindump" => load_ldapdomaindump(path)?,
+      _ => return Err(Error::new(ErrorKind::InvalidInput, format!("unknown input format {}, use ldif or ldapdomaindump", format))),
+   };
+   if !entries.iter().any(|entry| entry.bin_attrs.contains_key("nTSecurityDescriptor")) {
+      warn!("No nTSecurityDescriptor in {}, the ACL edges will be missing", path);
+   }
+   info!("{} LDAP entries loaded from {}", entries.len().to_string().bold(), path.bold());
+   Ok(entries)
+}
+
+/// Function to get the attribute name expected by the parser, the LDIF names are case-insensitive.
+pub fn canonical_attribute(name: &str) -> String {
+   // Options like member;range=0-1499
+   let name = name.split(';').next().unwrap_or(name);
+   BINARY_ATTRIBUTES.iter().copied()
+      .chain(LDAP_ATTRIBUTES.iter().map(|attribute| attribute.name))
+      .find(|known| known.eq_ignore_ascii_case(name))
+      .unwrap_or(name)
+      .to_string()
+}
+
+/// Function to add one value in the text or binary attributes.
+pub fn push_value(entry: &mut SearchEntry, name: &str, value: Vec<u8>) {
+   let name = canonical_attribute(name);
+   if BINARY_ATTRIBUTES.contains(&name.as_str()) {
+      entry.bin_attrs.entry(name).or_default().push(value);
+      return
+   }
+   match String::from_utf8(value) {
+      Ok(text) => entry.attrs.entry(name).or_default().push(text),
+      Err(err) => entry.bin_attrs.entry(name).or_default().push(err.into_bytes()),
+   }
+}
+
+pub fn new_entry(dn: String) -> SearchEntry {
+   SearchEntry { dn, attrs: HashMap::new(), bin_attrs: HashMap::new() }
+}
+
+/// Function to parse an LDIF export (RFC 2849).
+pub fn parse_ldif(content: &str) -> Vec<SearchEntry> {
+   // Unfold the continuation lines
+   let mut lines: Vec<String> = Vec::new();
+   for line in content.lines() {
+      let line = line.trim_end_matches('\r');
+      match line.strip_prefix(' ') {
+         Some(continuation) if !lines.is_empty() => lines.last_mut().unwrap().push_str(continuation),
+         _ => lines.push(line.to_string()),
+      }
+   }
+
+   let mut entries: Vec<SearchEntry> = Vec::new();
+   let mut current: Option<SearchEntry> = None;
+   for line in lines {
+      if line.is_empty() {
+         entries.extend(current.take());
+         continue
+      }
+      if line.starts_with('#') {
+         continue
+      }
+      let (name, value) = match line.split_once(':') {
+         Some(split) => split,
+         None => {
+            debug!("Ignoring LDIF line: {}", line);
+            continue
+         }
+      };
+      let value = if let Some(encoded) = value.strip_prefix(':') {
+         match base64_decode(encoded.trim()) {
+            Some(decoded) => decoded,
+            None => {
+               debug!("Ignoring invalid base64 value of {}", name);
+               continue
+            }
+         }
+      } else if value.starts_with('<') {
+         debug!("Ignoring URL value of {}", name);
+         continue
+      } else {
+         value.trim_start().as_bytes().to_vec()
+      };
+
+      if name.eq_ignore_ascii_case("dn") {
+         entries.extend(current.take());
+         current = Some(new_entry(String::from_utf8_lossy(&value).to_string()));
+      } else if let Some(entry) = current.as_mut() {
+         push_value(entry, name, value);
+      }
+      // Lines out of an entry (version, search, result) are ignored
+   }
+   entries.extend(current.take());
+   entries
+}
+
+/// Function to decode a base64 value (RFC 4648 alphabet, padding optional).
+pub fn base64_decode(encoded: &str) -> Option<Vec<u8>> {
+   let mut decoded: Vec<u8> = Vec::new();
+   let mut buffer: u32 = 0;
+   let mut bits = 0;
+   for c in encoded.bytes().filter(|c| !c.is_ascii_whitespace() && *c != b'=') {
+      let value = match c {
+         b'A'..=b'Z' => c - b'A',
+         b'a'..=b'z' => c - b'a' + 26,
+         b'0'..=b'9' => c - b'0' + 52,
+         b'+' => 62,
+         b'/' => 63,
+         _ => return None,
+      };
+      buffer = (buffer << 6) | value as u32;
+      bits += 6;
+      if bits >= 8 {
+         bits -= 8;
+         decoded.push((buffer >> bits) as u8);
+      }
+   }
+   Some(decoded)
+}
+
+/// Function to encode a value in base64 (RFC 4648 alphabet, with padding).
+pub fn base64_encode(data: &[u8]) -> String {
+   const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+   let mut encoded = String::new();
+   for chunk in data.chunks(3) {
+      let buffer = chunk.iter().enumerate().fold(0u32, |buffer, (i, byte)| buffer | (*byte as u32) << (16 - 8 * i));
+      for i in 0..4 {
+         encoded.push(if i <= chunk.len() { ALPHABET[(buffer >> (18 - 6 * i) & 0x3f) as usize] as char } else { '=' });
+      }
+   }
+   encoded
+}
+
+/// Function to load the ldapdomaindump JSON files of one directory.
+pub fn load_ldapdomaindump(dir: &String) -> Result<Vec<SearchEntry>> {
+   let mut entries: Vec<SearchEntry> = Vec::new();
+   for file in LDAPDOMAINDUMP_FILES {
+      let path = format!("{}/{}", dir, file);
+      let content = match fs::read_to_string(&path) {
+         Ok(content) => content,
+         Err(err) => {
+            debug!("Skipping {}: {}", path, err);
+            continue
+         }
+      };
+      let objects: Vec<serde_json::value::Value> = serde_json::from_str(&content)
+         .map_err(|err| Error::new(ErrorKind::InvalidData, format!("{}: {}", path, err)))?;
+      entries.extend(objects.iter().filter_map(ldapdomaindump_entry));
+   }
+   if entries.is_empty() {
+      return Err(Error::new(ErrorKind::NotFound, format!("no ldapdomaindump JSON file in {}", dir)))
+   }
+   Ok(entries)
+}
+
+/// Function to convert one ldapdomaindump object in a LDAP entry.
+pub fn ldapdomaindump_entry(object: &serde_json::value::Value) -> Option<SearchEntry> {
+   let mut entry = new_entry(object["dn"].as_str()?.to_string());
+   for (name, values) in object["attributes"].as_object()? {
+      let name = canonical_attribute(name);
+      let values = match values.as_array() {
+         Some(values) => values.to_owned(),
+         None => vec![values.to_owned()],
+      };
+      for value in values {
+         let text = match &value {
+            serde_json::value::Value::String(text) => text.to_owned(),
+            serde_json::value::Value::Bool(boolean) => boolean.to_string().to_uppercase(),
+            other => other.to_string(),
+         };
+         let raw = match name.as_str() {
+            "objectSid" | "sIDHistory" | "securityIdentifier" => match sid_to_bytes(&text) {
+               Some(sid) => sid,
+               None => continue,
+            },
+            "objectGUID" => match guid_to_bytes(&text) {
+               Some(guid) => guid,
+               None => continue,
+            },
+            _ if FILETIME_ATTRIBUTES.contains(&name.as_str()) => datetime_to_filetime(&text).unwrap_or(text).into_bytes(),
+            _ if GENERALIZED_TIME_ATTRIBUTES.contains(&name.as_str()) => datetime_to_generalized_time(&text).unwrap_or(text).into_bytes(),
+            _ if BINARY_ATTRIBUTES.contains(&name.as_str()) => match base64_decode(&text) {
+               Some(raw) => raw,
+               None => continue,
+            },
+            _ => text.into_bytes(),
+         };
+         push_value(&mut entry, &name, raw);
+      }
+   }
+   Some(entry)
+}
+
+/// Function to convert a SID string (S-1-5-21-...) in its binary format.
+pub fn sid_to_bytes(sid: &str) -> Option<Vec<u8>> {
+   sid.parse::<LdapSid>().ok().map(|sid| sid.to_bytes())
+}
+
+/// Function to convert a GUID string ({xxxxxxxx-xxxx-xxxx-xxxx-xxxxxxxxxxxx}) in its binary format.
+pub fn guid_to_bytes(guid: &str) -> Option<Vec<u8>> {
+   let hex: String = guid.chars().filter(|c| c.is_ascii_hexdigit()).collect();
+   if hex.len() != 32 {
+      return None
+   }
+   let bytes = (0..16).map(|i| u8::from_str_radix(&hex[i * 2..i * 2 + 2], 16).ok()).collect::<Option<Vec<u8>>>()?;
+   // The 3 first parts are little-endian
+   let mut raw = vec![bytes[3], bytes[2], bytes[1], bytes[0], bytes[5], bytes[4], bytes[7], bytes[6]];
+   raw.extend_from_slice(&bytes[8..]);
+   Some(raw)
+}
+
+fn parse_datetime(text: &str) -> Option<DateTime<FixedOffset>> {
+   DateTime::parse_from_str(text, "%Y-%m-%d %H:%M:%S%.f%:z")
+      .or_else(|_| DateTime::parse_from_str(text, "%Y-%m-%d %H:%M:%S%:z"))
+      .ok()
+}
+
+/// Function to convert a ldapdomaindump datetime in FILETIME, 1601-01-01 is 0 (never).
+pub fn datetime_to_filetime(text: &str) -> Option<String> {
+   let datetime = parse_datetime(text)?;
+   let filetime = (datetime.timestamp() + FILETIME_EPOCH_OFFSET).max(0) * 10_000_000;
+   Some(filetime.to_string())
+}
+
+/// Function to convert a ldapdomaindump datetime in GeneralizedTime.
+pub fn datetime_to_generalized_time(text: &str) -> Option<String> {
+   let datetime = parse_datetime(text)?;
+   Some(format!("{}.0Z", datetime.naive_utc().format("%Y%m%d%H%M%S")))
+}
+
+/// Test functions
+#[test]
+pub fn test_parse_ldif() {
+   let ldif = "version: 1\n\
+      \n\
+      # jon.snow, Users, essos.local\n\
+      dn: CN=jon.snow,CN=Users,DC=essos,DC=local\n\
+      objectclass: top\n\
+      objectClass: person\n\
+      objectClass: user\n\
+      sAMAccountName: jon.s\n \
+      now\n\
+      objectSid:: AQUAAAAAAAUVAAAAAQAAAAIAAAADAAAAUAQAAA==\n\
+      member;range=0-1: CN=a\n\
+      \n\
+      # search result\n\
+      search: 2\n\
+      result: 0 Success\n";
+   let entries = parse_ldif(ldif);
+   assert_eq!(entries.len(), 1);
+   assert_eq!(entries[0].dn, "CN=jon.snow,CN=Users,DC=essos,DC=local");
+   assert_eq!(entries[0].attrs["objectClass"], vec!["top", "person", "user"]);
+   assert_eq!(entries[0].attrs["sAMAccountName"], vec!["jon.snow"]);
+   assert_eq!(entries[0].attrs["member"], vec!["CN=a"]);
+   assert_eq!(entries[0].bin_attrs["objectSid"][0], sid_to_bytes("S-1-5-21-1-2-3-1104").unwrap());
+}
+
+#[test]
+pub fn test_ldapdomaindump_entry() {
+   let object = serde_json::json!({
+      "dn": "CN=jon.snow,CN=Users,DC=essos,DC=local",
+      "attributes": {
+         "objectSid": ["S-1-5-21-1-2-3-1104"],
+         "objectGUID": ["{01020304-0506-0708-090a-0b0c0d0e0f10}"],
+         "userAccountControl": [66048],
+         "pwdLastSet": ["1970-01-01 00:00:00+00:00"],
+         "whenCreated": ["2022-10-04 13:37:00+00:00"]
+      }
+   });
+   let entry = ldapdomaindump_entry(&object).unwrap();
+   assert_eq!(entry.bin_attrs["objectGUID"][0], vec![4, 3, 2, 1, 6, 5, 8, 7, 9, 10, 11, 12, 13, 14, 15, 16]);
+   assert_eq!(entry.attrs["userAccountControl"], vec!["66048"]);
+   assert_eq!(entry.attrs["pwdLastSet"], vec!["116444736000000000"]);
+   assert_eq!(entry.attrs["whenCreated"], vec!["20221004133700.0Z"]);
+   assert_eq!(base64_decode("TWFu").unwrap(), b"Man".to_vec());
+}
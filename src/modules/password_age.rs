@@ -0,0 +1,161 @@
+//! Machine account password age and krbtgt key rotation report (--reports)
+//!
+//! A computer changes its machine account password every 30 days by default. An old pwdLastSet on an enabled DC or
+//! server means the rotation is disabled (DisablePasswordChange) or broken, its keys stolen once stay valid for
+//! silver tickets. The krbtgt keys sign every TGT: until they are changed twice, a golden ticket forged with a stolen
+//! krbtgt hash stays valid, whatever the password of the other accounts.
+//!
+//! The table is printed and written in `<dirpath>/<domain>_password_age.json`:
+//! - the krbtgt accounts (krbtgt and the krbtgt_<n> of the RODCs) with their password age,
+//! - the enabled DCs and servers with a machine password older than MACHINE_PASSWORD_MAX_AGE_DAYS,
+//!   `rotationdisabled` when the computer logged on long after its last password change.
+use crate::banner::Colorize;
+use log::{info, warn};
+use serde_json::value::Value;
+use std::fs;
+use std::io::Write;
+
+use crate::json::maker::json_to_string;
+
+/// Machine password age of a DC or a server reported, the computer changes it every 30 days by default.
+pub const MACHINE_PASSWORD_MAX_AGE_DAYS: i64 = 90;
+/// krbtgt password age above which it is reported in red.
+pub const KRBTGT_PASSWORD_MAX_AGE_DAYS: i64 = 180;
+
+const DAY_S: i64 = 24 * 60 * 60;
+
+/// Password age of one account.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PasswordAge {
+   pub name: String,
+   pub object_identifier: String,
+   /// "krbtgt", "dc" or "server"
+   pub role: &'static str,
+   /// Days since pwdLastSet, None when the password was never set
+   pub age_days: Option<i64>,
+   pub pwdlastset: i64,
+   /// Logged on more than MACHINE_PASSWORD_MAX_AGE_DAYS after the password change
+   pub rotation_disabled: bool,
+}
+
+/// Function to get the days since pwdLastSet of an object.
+fn age_days(object: &Value, now: i64) -> Option<i64> {
+   object["Properties"]["pwdlastset"].as_i64().filter(|pwdlastset| *pwdlastset > 0).map(|pwdlastset| (now - pwdlastset) / DAY_S)
+}
+
+/// Function to know if a user is a krbtgt account (krbtgt, krbtgt_<n> of a RODC).
+pub fn is_krbtgt(user: &Value) -> bool {
+   let samaccountname = user["Properties"]["samaccountname"].as_str().unwrap_or("").to_lowercase();
+   samaccountname == "krbtgt" || samaccountname.strip_prefix("krbtgt_").map_or(false, |id| !id.is_empty() && id.chars().all(|c| c.is_ascii_digit()))
+}
+
+/// Function to list the krbtgt accounts and the DCs and servers with an old machine password.
+pub fn password_ages(vec_users: &[Value], vec_computers: &[Value], now: i64) -> Vec<PasswordAge> {
+   let mut ages: Vec<PasswordAge> = Vec::new();
+   let entry = |object: &Value, role: &'static str| PasswordAge {
+      name: object["Properties"]["name"].as_str().unwrap_or("").to_string(),
+      object_identifier: object["ObjectIdentifier"].as_str().unwrap_or("").to_string(),
+      role,
+      age_days: age_days(object, now),
+      pwdlastset: object["Properties"]["pwdlastset"].as_i64().unwrap_or(-1),
+      rotation_disabled: false,
+   };
+   for user in vec_users.iter().filter(|user| is_krbtgt(user)) {
+      ages.push(entry(user, "krbtgt"));
+   }
+   for computer in vec_computers.iter().filter(|computer| computer["Properties"]["enabled"].as_bool().unwrap_or(true)) {
+      let role = if computer["Properties"]["isdc"].as_bool().unwrap_or(false) {
+         "dc"
+      } else if computer["Properties"]["operatingsystem"].as_str().unwrap_or("").to_lowercase().contains("server") {
+         "server"
+      } else {
+         continue
+      };
+      let mut age = entry(computer, role);
+      if age.age_days.map_or(true, |days| days <= MACHINE_PASSWORD_MAX_AGE_DAYS) {
+         continue
+      }
+      let lastlogon = computer["Properties"]["lastlogontimestamp"].as_i64().unwrap_or(-1);
+      age.rotation_disabled = lastlogon - age.pwdlastset > MACHINE_PASSWORD_MAX_AGE_DAYS * DAY_S;
+      ages.push(age);
+   }
+   // krbtgt first, then the oldest passwords
+   ages.sort_by_key(|age| (age.role != "krbtgt", std::cmp::Reverse(age.age_days)));
+   ages
+}
+
+/// Function to print the password age table in the tables output and write the password age JSON file.
+pub fn make_password_age_report(
+   path: &String,
+   domain: &String,
+   vec_users: &[Value],
+   vec_computers: &[Value],
+   pretty: bool,
+   tables: &mut dyn Write,
+) -> std::io::Result<()> {
+   let ages = password_ages(vec_users, vec_computers, chrono::Utc::now().timestamp());
+
+   let width = ages.iter().map(|age| age.name.len()).max().unwrap_or(0).max(4);
+   writeln!(tables, "\n{:<8} {:<width$} {:<10} {}", "ROLE", "NAME", "AGE(DAYS)", "ROTATION", width = width)?;
+   let mut entries: Vec<Value> = Vec::new();
+   for age in ages.iter() {
+      let days = age.age_days.map(|days| days.to_string()).unwrap_or_else(|| "never".to_string());
+      let old = age.age_days.map_or(true, |days| days > if age.role == "krbtgt" { KRBTGT_PASSWORD_MAX_AGE_DAYS } else { MACHINE_PASSWORD_MAX_AGE_DAYS });
+      writeln!(
+         tables,
+         "{:<8} {} {:<10} {}",
+         age.role,
+         // Padding before the colors, the escape codes would break the alignment
+         if old { format!("{:<width$}", age.name, width = width).red().bold() } else { format!("{:<width$}", age.name, width = width).normal() },
+         days,
+         if age.rotation_disabled { "disabled" } else { "" },
+      )?;
+      if age.role == "krbtgt" && old {
+         warn!("{} password not changed for {} days, a golden ticket forged with its key is still valid", age.name.red().bold(), days);
+      }
+      entries.push(serde_json::json!({
+         "name": age.name,
+         "objectid": age.object_identifier,
+         "role": age.role,
+         "pwdlastset": age.pwdlastset,
+         "agedays": age.age_days,
+         "rotationdisabled": age.rotation_disabled,
+      }));
+   }
+   info!("{} old machine account passwords on DCs and servers", ages.iter().filter(|age| age.role != "krbtgt").count().to_string().bold());
+
+   fs::create_dir_all(path)?;
+   let final_path = format!("{}/{}_password_age.json", path, domain.replace(".", "-").to_lowercase());
+   fs::write(&final_path, json_to_string(&mut serde_json::json!({ "accounts": entries }), pretty))?;
+   info!("{} created!", final_path.bold());
+   Ok(())
+}
+
+/// Test functions
+#[test]
+pub fn test_password_ages() {
+   let now = 1696161600;
+   let days_ago = |days: i64| now - days * DAY_S;
+   let vec_users = vec![
+      serde_json::json!({"ObjectIdentifier": "S-1-5-21-1-2-3-502", "Properties": {"name": "KRBTGT@ESSOS.LOCAL", "samaccountname": "krbtgt", "pwdlastset": days_ago(1200)}}),
+      serde_json::json!({"ObjectIdentifier": "S-1-5-21-1-2-3-1120", "Properties": {"name": "KRBTGT_8245@ESSOS.LOCAL", "samaccountname": "krbtgt_8245", "pwdlastset": days_ago(20)}}),
+      serde_json::json!({"ObjectIdentifier": "S-1-5-21-1-2-3-1121", "Properties": {"name": "KRBTGT_SVC@ESSOS.LOCAL", "samaccountname": "krbtgt_svc", "pwdlastset": days_ago(900)}}),
+   ];
+   let vec_computers = vec![
+      serde_json::json!({"ObjectIdentifier": "S-1-5-21-1-2-3-1001", "Properties": {"name": "MEEREEN.ESSOS.LOCAL", "isdc": true, "enabled": true,
+         "pwdlastset": days_ago(400), "lastlogontimestamp": days_ago(2)}}),
+      serde_json::json!({"ObjectIdentifier": "S-1-5-21-1-2-3-1105", "Properties": {"name": "BRAAVOS.ESSOS.LOCAL", "operatingsystem": "Windows Server 2016 Standard",
+         "enabled": true, "pwdlastset": days_ago(120), "lastlogontimestamp": days_ago(110)}}),
+      serde_json::json!({"ObjectIdentifier": "S-1-5-21-1-2-3-1106", "Properties": {"name": "PENTOS.ESSOS.LOCAL", "operatingsystem": "Windows Server 2019 Standard",
+         "enabled": true, "pwdlastset": days_ago(12)}}),
+      serde_json::json!({"ObjectIdentifier": "S-1-5-21-1-2-3-1107", "Properties": {"name": "WS01.ESSOS.LOCAL", "operatingsystem": "Windows 10 Pro",
+         "enabled": true, "pwdlastset": days_ago(500)}}),
+   ];
+   let ages = password_ages(&vec_users, &vec_computers, now);
+   assert_eq!(ages.iter().map(|age| age.name.as_str()).collect::<Vec<&str>>(),
+      vec!["KRBTGT@ESSOS.LOCAL", "KRBTGT_8245@ESSOS.LOCAL", "MEEREEN.ESSOS.LOCAL", "BRAAVOS.ESSOS.LOCAL"]);
+   assert_eq!(ages[0].age_days, Some(1200));
+   assert_eq!(ages[2].role, "dc");
+   assert!(ages[2].rotation_disabled);
+   assert!(!ages[3].rotation_disabled);
+}
@@ -0,0 +1,129 @@
+//! Passwords in the free text attributes (--password-scan)
+//!
+//! Helpdesk teams often leave the initial password of an account in its description or info attribute.
+//! The collected properties are scanned for:
+//! - a password keyword followed by a value (`pwd: Summer2023!`, `password is Winter1`, `mdp=...`), high confidence,
+//! - the userPassword, unixUserPassword and unicodePwd attributes, readable passwords, high confidence,
+//! - a word with lower and upper case letters, digits and symbols, low confidence.
+//!
+//! The findings are written in `<dirpath>/<domain>_passwords.json`, the values are never logged.
+//! The info attribute is only collected with `--object-props extended`.
+use crate::banner::Colorize;
+use lazy_static::lazy_static;
+use log::{info, warn};
+use regex::Regex;
+use serde_json::value::Value;
+use std::fs;
+
+/// Free text properties scanned for the password patterns.
+pub const SCANNED_PROPERTIES: [&str; 3] = ["description", "info", "comment"];
+/// Properties holding a password as is.
+pub const PASSWORD_PROPERTIES: [&str; 3] = ["userpassword", "unixpassword", "unicodepassword"];
+
+lazy_static! {
+   static ref PASSWORD_KEYWORD: Regex = Regex::new(
+      r"(?i)\b(?:passwords?|passwd|pwd|pw|pass|mdp|mot de passe|passwort|kennwort|contrase(?:ñ|n)a|senha)\s*(?:[:=]|is|was|est)\s*(\S+)"
+   ).unwrap();
+}
+
+/// Function to know if a word looks like a complex password: lower, upper, digit and symbol, not an email or URL.
+fn complex_word(word: &str) -> bool {
+   let length = word.chars().count();
+   (8..=64).contains(&length)
+      && word.chars().any(|c| c.is_lowercase())
+      && word.chars().any(|c| c.is_uppercase())
+      && word.chars().any(|c| c.is_ascii_digit())
+      && word.chars().any(|c| !c.is_alphanumeric())
+      && !word.contains("://")
+      && !word.split_once('@').map(|(_, host)| host.contains('.')).unwrap_or(false)
+}
+
+/// Function to scan one text, return the credential-looking values and their confidence.
+pub fn scan_text(text: &str) -> Vec<(String, &'static str)> {
+   let mut findings: Vec<(String, &'static str)> = PASSWORD_KEYWORD.captures_iter(text)
+      .filter_map(|captures| captures.get(1))
+      .map(|value| (value.as_str().trim_end_matches(['.', ',', ';', ')']).to_string(), "high"))
+      .filter(|(value, _)| !value.is_empty())
+      .collect();
+   for word in text.split_whitespace().map(|word| word.trim_matches(|c| "\"'(),;".contains(c))) {
+      if complex_word(word) && !findings.iter().any(|(value, _)| word.contains(value.as_str())) {
+         findings.push((word.to_string(), "low"));
+      }
+   }
+   findings
+}
+
+/// Function to scan the properties of every object, the high confidence findings first.
+pub fn find_passwords(objects: &[(&str, &Vec<Value>)]) -> Vec<Value> {
+   let mut findings: Vec<Value> = Vec::new();
+   for (object_type, vec_objects) in objects {
+      for object in vec_objects.iter() {
+         let mut finding = |property: &str, value: String, confidence: &str| {
+            findings.push(serde_json::json!({
+               "name": object["Properties"]["name"],
+               "objectid": object["ObjectIdentifier"],
+               "type": object_type,
+               "property": property,
+               "value": value,
+               "confidence": confidence,
+            }));
+         };
+         for property in PASSWORD_PROPERTIES {
+            if let Some(value) = object["Properties"][property].as_str().filter(|value| !value.is_empty()) {
+               finding(property, value.to_string(), "high");
+            }
+         }
+         for property in SCANNED_PROPERTIES {
+            if let Some(text) = object["Properties"][property].as_str() {
+               for (value, confidence) in scan_text(text) {
+                  finding(property, value, confidence);
+               }
+            }
+         }
+      }
+   }
+   findings.sort_by_key(|finding| finding["confidence"] != "high");
+   findings
+}
+
+/// Function to write the passwords findings, the values are only in the file.
+pub fn make_password_report(path: &String, domain: &String, objects: &[(&str, &Vec<Value>)]) -> std::io::Result<()> {
+   let findings = find_passwords(objects);
+   let high = findings.iter().filter(|finding| finding["confidence"] == "high").count();
+   if high > 0 {
+      warn!("{} likely passwords in the attributes ({} low confidence)", high.to_string().bold(), findings.len() - high);
+   } else {
+      info!("No likely password in the attributes ({} low confidence)", findings.len());
+   }
+   for finding in findings.iter().filter(|finding| finding["confidence"] == "high") {
+      info!("{} {}", finding["name"].as_str().unwrap_or("").red().bold(), finding["property"].as_str().unwrap_or(""));
+   }
+
+   fs::create_dir_all(path)?;
+   let final_path = format!("{}/{}_passwords.json", path, domain.replace(".", "-").to_lowercase());
+   fs::write(&final_path, serde_json::to_string_pretty(&findings).unwrap_or_default())?;
+   info!("{} created!", final_path.bold());
+   Ok(())
+}
+
+/// Test functions
+#[test]
+pub fn test_find_passwords() {
+   assert_eq!(scan_text("New hire, pwd: Welcome1."), vec![("Welcome1".to_string(), "high")]);
+   assert_eq!(scan_text("Le mot de passe est Hiver2023"), vec![("Hiver2023".to_string(), "high")]);
+   assert_eq!(scan_text("Temp Summer2023! until monday"), vec![("Summer2023!".to_string(), "low")]);
+   assert!(scan_text("Contact John.Doe2@essos.local or https://Intranet.essos.local/Wiki2").is_empty());
+   assert!(scan_text("Built-in account for administering the computer/domain").is_empty());
+
+   let users = vec![
+      serde_json::json!({"ObjectIdentifier": "S-1-5-21-1-2-3-1110", "Properties": {"name": "SQL_SVC@ESSOS.LOCAL",
+         "description": "SQL service, Password=YouWillNotKerboroast1ngMeeeeee", "userpassword": null}}),
+      serde_json::json!({"ObjectIdentifier": "S-1-5-21-1-2-3-1111", "Properties": {"name": "VISERYS.TARGARYEN@ESSOS.LOCAL",
+         "description": "Temp Drag0n$Crown", "userpassword": "GoldCrown"}}),
+   ];
+   let findings = find_passwords(&[("User", &users)]);
+   assert_eq!(findings.len(), 3);
+   assert_eq!((findings[0]["property"].as_str(), findings[0]["value"].as_str()), (Some("description"), Some("YouWillNotKerboroast1ngMeeeeee")));
+   assert_eq!((findings[1]["property"].as_str(), findings[1]["value"].as_str()), (Some("userpassword"), Some("GoldCrown")));
+   assert_eq!((findings[2]["value"].as_str(), findings[2]["confidence"].as_str()), (Some("Drag0n$Crown"), Some("low")));
+}
@@ -0,0 +1,242 @@
+//! Shortest attack paths without Neo4j (--path)
+//!
+//! aclpwn-style question on the collected graph: `--path "from:JON.SNOW to:Domain Admins"` finds the cheapest path
+//! from the source principal to the target over the edges of the JSON files (ACEs, memberships, sessions, local
+//! groups, delegations, containers, GPO links). Each edge type has a weight: a group membership is free, an ACL abuse
+//! costs 1, an abuse needing a delegation, a SPN or a logon on the host costs 2, the edges without a known abuse are
+//! not followed.
+//!
+//! The names are matched on the object name with or without `@DOMAIN`, the sAMAccountName or the SID (every domain
+//! matches), the path is printed and written in `<dirpath>/<domain>_path.json`.
+use crate::banner::Colorize;
+use lazy_static::lazy_static;
+use log::info;
+use regex::Regex;
+use serde_json::value::Value;
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap};
+use std::fs;
+
+use crate::json::maker::csv::object_edges;
+
+/// Weight of the edges followed by the path search.
+pub const EDGE_WEIGHTS: [(&str, u32); 31] = [
+   ("MemberOf", 0),
+   ("Contains", 1),
+   ("GPLink", 1),
+   ("HasSession", 1),
+   ("AdminTo", 1),
+   ("GenericAll", 1),
+   ("GenericWrite", 1),
+   ("Owns", 1),
+   ("WriteDacl", 1),
+   ("WriteOwner", 1),
+   ("AddMember", 1),
+   ("AddSelf", 1),
+   ("ForceChangePassword", 1),
+   ("AllExtendedRights", 1),
+   ("AddKeyCredentialLink", 1),
+   ("ReadLAPSPassword", 1),
+   ("SyncLAPSPassword", 1),
+   ("ReadGMSAPassword", 1),
+   ("HostsServiceAccount", 1),
+   ("DCSync", 1),
+   ("HasSIDHistory", 1),
+   ("AllowedToDelegate", 2),
+   ("AllowedToAct", 2),
+   ("AddAllowedToAct", 2),
+   ("WriteAccountRestrictions", 2),
+   ("WriteSPN", 2),
+   ("CanRDP", 2),
+   ("CanPSRemote", 2),
+   ("ExecuteDCOM", 2),
+   ("SQLAdmin", 2),
+   ("Enroll", 2),
+];
+
+lazy_static! {
+   static ref PATH_QUERY: Regex = Regex::new(r"(?i)^\s*from:\s*(.+?)\s+to:\s*(.+?)\s*$").unwrap();
+}
+
+/// One edge of a path.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PathHop {
+   pub source: String,
+   pub edge: String,
+   pub target: String,
+}
+
+/// Objects and weighted edges of the collection.
+pub struct ObjectGraph {
+   /// ObjectIdentifier => (name, object type, object)
+   pub nodes: HashMap<String, (String, String, Value)>,
+   /// source => (edge, target, weight)
+   pub edges: HashMap<String, Vec<(String, String, u32)>>,
+}
+
+impl ObjectGraph {
+   /// Function to build the graph of every object type.
+   pub fn new(vec_objects: &[(&str, &Vec<Value>)]) -> ObjectGraph {
+      let weights: HashMap<&str, u32> = EDGE_WEIGHTS.iter().cloned().collect();
+      let mut nodes: HashMap<String, (String, String, Value)> = HashMap::new();
+      let mut edges: HashMap<String, Vec<(String, String, u32)>> = HashMap::new();
+      for (object_type, objects) in vec_objects {
+         for object in objects.iter() {
+            let id = match object["ObjectIdentifier"].as_str() {
+               Some(id) => id.to_uppercase(),
+               None => continue,
+            };
+            let name = object["Properties"]["name"].as_str().unwrap_or(&id).to_string();
+            nodes.insert(id, (name, object_type.to_string(), object.to_owned()));
+            for edge in object_edges(object, object_type) {
+               if let Some(weight) = weights.get(edge.edge.as_str()) {
+                  edges.entry(edge.source.to_uppercase()).or_default().push((edge.edge, edge.target.to_uppercase(), *weight));
+               }
+            }
+         }
+      }
+      ObjectGraph { nodes, edges }
+   }
+
+   /// Function to find the objects of a name: SID, name with or without @DOMAIN, sAMAccountName.
+   pub fn find(&self, name: &str) -> Vec<String> {
+      let name = name.trim().to_uppercase();
+      let mut found: Vec<String> = self.nodes.iter()
+         .filter(|(id, (object_name, _, object))| {
+            let object_name = object_name.to_uppercase();
+            **id == name
+               || object_name == name
+               || object_name.split('@').next() == Some(name.as_str())
+               || object["Properties"]["samaccountname"].as_str().map(|sam| sam.to_uppercase() == name).unwrap_or(false)
+         })
+         .map(|(id, _)| id.to_owned())
+         .collect();
+      found.sort();
+      found
+   }
+
+   /// Function to get the name of an object, its SID when it is not collected.
+   pub fn name(&self, id: &str) -> String {
+      self.nodes.get(id).map(|(name, _, _)| name.to_owned()).unwrap_or_else(|| id.to_string())
+   }
+
+   /// Function to get the cheapest path from one of the sources to one of the targets (Dijkstra on the edge weights).
+   pub fn shortest_path(&self, sources: &[String], targets: &[String]) -> Option<(u32, Vec<PathHop>)> {
+      let mut costs: HashMap<&str, u32> = HashMap::new();
+      let mut previous: HashMap<&str, (&str, &str)> = HashMap::new();
+      let mut queue: BinaryHeap<Reverse<(u32, &str)>> = BinaryHeap::new();
+      for source in sources {
+         costs.insert(source.as_str(), 0);
+         queue.push(Reverse((0, source.as_str())));
+      }
+      while let Some(Reverse((cost, node))) = queue.pop() {
+         if cost > costs[node] {
+            continue;
+         }
+         if targets.iter().any(|target| target == node) {
+            let mut hops: Vec<PathHop> = Vec::new();
+            let mut current = node;
+            while let Some((source, edge)) = previous.get(current) {
+               hops.push(PathHop { source: source.to_string(), edge: edge.to_string(), target: current.to_string() });
+               current = *source;
+            }
+            hops.reverse();
+            return Some((cost, hops))
+         }
+         for (edge, target, weight) in self.edges.get(node).map(|edges| edges.as_slice()).unwrap_or(&[]) {
+            let next = cost + weight;
+            if costs.get(target.as_str()).map(|known| next < *known).unwrap_or(true) {
+               costs.insert(target.as_str(), next);
+               previous.insert(target.as_str(), (node, edge.as_str()));
+               queue.push(Reverse((next, target.as_str())));
+            }
+         }
+      }
+      None
+   }
+}
+
+/// Function to split a path query `from:<name> to:<name>`.
+pub fn parse_path_query(query: &str) -> Option<(String, String)> {
+   PATH_QUERY.captures(query).map(|captures| (captures[1].to_string(), captures[2].to_string()))
+}
+
+/// Function to answer a path query, print the path and write it in JSON.
+pub fn make_path_report(path: &String, domain: &String, query: &String, vec_objects: &[(&str, &Vec<Value>)]) -> std::io::Result<()> {
+   let invalid = |reason: String| std::io::Error::new(std::io::ErrorKind::InvalidInput, reason);
+   let (from, to) = parse_path_query(query).ok_or_else(|| invalid(format!("{query} is not a path query, use \"from:<name> to:<name>\"")))?;
+   let graph = ObjectGraph::new(vec_objects);
+   let sources = graph.find(&from);
+   if sources.is_empty() {
+      return Err(invalid(format!("no collected object named {from}")))
+   }
+   let targets = graph.find(&to);
+   if targets.is_empty() {
+      return Err(invalid(format!("no collected object named {to}")))
+   }
+
+   let result = graph.shortest_path(&sources, &targets);
+   let report = match &result {
+      Some((cost, hops)) => {
+         info!("Path from {} to {}: {} edges, cost {}", from.bold(), to.bold(), hops.len().to_string().bold(), cost);
+         for hop in hops {
+            info!("{} -[{}]-> {}", graph.name(&hop.source), hop.edge.yellow().bold(), graph.name(&hop.target));
+         }
+         serde_json::json!({
+            "from": from,
+            "to": to,
+            "cost": cost,
+            "path": hops.iter().map(|hop| serde_json::json!({
+               "source": hop.source,
+               "sourcename": graph.name(&hop.source),
+               "edge": hop.edge,
+               "target": hop.target,
+               "targetname": graph.name(&hop.target),
+            })).collect::<Vec<Value>>(),
+         })
+      }
+      None => {
+         info!("No path from {} to {}", from.bold(), to.bold());
+         serde_json::json!({ "from": from, "to": to, "cost": null, "path": null })
+      }
+   };
+
+   fs::create_dir_all(path)?;
+   let final_path = format!("{}/{}_path.json", path, domain.replace(".", "-").to_lowercase());
+   fs::write(&final_path, serde_json::to_string_pretty(&report).unwrap_or_default())?;
+   info!("{} created!", final_path.bold());
+   Ok(())
+}
+
+/// Test functions
+#[test]
+pub fn test_shortest_path() {
+   assert_eq!(parse_path_query("from:JON.SNOW to:Domain Admins"), Some(("JON.SNOW".to_string(), "Domain Admins".to_string())));
+   assert_eq!(parse_path_query("JON.SNOW"), None);
+
+   let users = vec![
+      serde_json::json!({"ObjectIdentifier": "S-1-5-21-1-2-3-1104", "Properties": {"name": "JON.SNOW@ESSOS.LOCAL", "samaccountname": "jon.snow"}}),
+      serde_json::json!({"ObjectIdentifier": "S-1-5-21-1-2-3-1105", "Properties": {"name": "SAM@ESSOS.LOCAL"},
+         "Aces": [{"RightName": "ForceChangePassword", "PrincipalSID": "S-1-5-21-1-2-3-1104", "PrincipalType": "User"}]}),
+   ];
+   let groups = vec![
+      serde_json::json!({"ObjectIdentifier": "S-1-5-21-1-2-3-512", "Properties": {"name": "DOMAIN ADMINS@ESSOS.LOCAL"},
+         "Members": [{"ObjectIdentifier": "S-1-5-21-1-2-3-1106", "ObjectType": "Group"}],
+         "Aces": [{"RightName": "GenericAll", "PrincipalSID": "S-1-5-21-1-2-3-1104", "PrincipalType": "User"},
+            {"RightName": "ReadProperty", "PrincipalSID": "S-1-5-21-1-2-3-1104", "PrincipalType": "User"}]}),
+      serde_json::json!({"ObjectIdentifier": "S-1-5-21-1-2-3-1106", "Properties": {"name": "NIGHT WATCH@ESSOS.LOCAL"},
+         "Members": [{"ObjectIdentifier": "S-1-5-21-1-2-3-1105", "ObjectType": "User"}]}),
+   ];
+   let graph = ObjectGraph::new(&[("User", &users), ("Group", &groups)]);
+   assert_eq!(graph.find("jon.snow"), vec!["S-1-5-21-1-2-3-1104".to_string()]);
+   assert_eq!(graph.find("Domain Admins"), vec!["S-1-5-21-1-2-3-512".to_string()]);
+
+   // GenericAll costs 1 like ForceChangePassword and two memberships
+   let (cost, hops) = graph.shortest_path(&graph.find("jon.snow"), &graph.find("Domain Admins")).unwrap();
+   assert_eq!(cost, 1);
+   assert_eq!(hops, vec![PathHop { source: "S-1-5-21-1-2-3-1104".to_string(), edge: "GenericAll".to_string(), target: "S-1-5-21-1-2-3-512".to_string() }]);
+   let (cost, hops) = graph.shortest_path(&graph.find("SAM"), &graph.find("Domain Admins")).unwrap();
+   assert_eq!((cost, hops.len()), (0, 2));
+   assert_eq!(hops[1].edge, "MemberOf");
+   assert_eq!(graph.shortest_path(&graph.find("Domain Admins"), &graph.find("jon.snow")), None);
+}
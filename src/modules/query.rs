@@ -0,0 +1,199 @@
+//! Canned queries over the collected objects (--query)
+//!
+//! The usual first questions of the BloodHound interface, answered at the end of the run without Neo4j on the
+//! graph of `--path` and printed as a table:
+//! - `kerberoastable_high_value`: enabled users with a SPN and admincount or a path to Tier 0,
+//! - `asreproastable_high_value`: enabled users without Kerberos pre-authentication and admincount or a path to Tier 0,
+//! - `dcsync_principals`: principals with DCSync on a domain, directly or through their groups,
+//! - `unconstrained_delegation`: users and computers trusted for unconstrained delegation, the DCs excluded,
+//! - `tier0_members`: effective members of the Tier 0 groups.
+use crate::banner::Colorize;
+use log::info;
+use serde_json::value::Value;
+use std::collections::{HashMap, VecDeque};
+use std::io::Write;
+
+use crate::modules::paths::ObjectGraph;
+use crate::modules::risk_score::is_tier0;
+use crate::modules::synthetic_edges::is_domain_controller;
+
+/// Canned queries and their description.
+pub const CANNED_QUERIES: [(&str, &str); 5] = [
+   ("kerberoastable_high_value", "Kerberoastable users with admincount or a path to Tier 0"),
+   ("asreproastable_high_value", "AS-REP roastable users with admincount or a path to Tier 0"),
+   ("dcsync_principals", "Principals with DCSync on a domain"),
+   ("unconstrained_delegation", "Unconstrained delegation outside the DCs"),
+   ("tier0_members", "Effective members of the Tier 0 groups"),
+];
+
+/// One line of a query result.
+#[derive(Debug, Clone, PartialEq)]
+pub struct QueryRow {
+   pub name: String,
+   pub object_type: String,
+   pub detail: String,
+}
+
+/// Function to evaluate a canned query, the rows are sorted by name.
+pub fn run_query(query: &str, graph: &ObjectGraph) -> Vec<QueryRow> {
+   let row = |id: &str, detail: String| QueryRow {
+      name: graph.name(id),
+      object_type: graph.nodes.get(id).map(|(_, object_type, _)| object_type.to_owned()).unwrap_or_else(|| "Base".to_string()),
+      detail,
+   };
+   let mut rows: Vec<QueryRow> = Vec::new();
+   match query {
+      "kerberoastable_high_value" | "asreproastable_high_value" => {
+         let property = if query == "kerberoastable_high_value" { "hasspn" } else { "dontreqpreauth" };
+         let tier0 = tier0_ids(graph);
+         for (id, (_, object_type, object)) in &graph.nodes {
+            let properties = &object["Properties"];
+            if object_type != "User"
+               || properties[property] != true
+               || properties["enabled"] != true
+               || properties["samaccountname"].as_str().map(|sam| sam.eq_ignore_ascii_case("krbtgt")).unwrap_or(false)
+            {
+               continue
+            }
+            if properties["admincount"] == true {
+               rows.push(row(id, "admincount".to_string()));
+            } else if let Some((_, hops)) = graph.shortest_path(&[id.to_owned()], &tier0) {
+               let target = hops.last().map(|hop| graph.name(&hop.target)).unwrap_or_default();
+               rows.push(row(id, format!("{} edges to {}", hops.len(), target)));
+            }
+         }
+      }
+      "dcsync_principals" => {
+         for (id, (name, object_type, _)) in &graph.nodes {
+            if object_type != "Domain" {
+               continue
+            }
+            for (source, edges) in &graph.edges {
+               if !edges.iter().any(|(edge, target, _)| edge == "DCSync" && target == id) {
+                  continue
+               }
+               rows.push(row(source, format!("on {name}")));
+               for (member, via) in effective_members(graph, source) {
+                  rows.push(row(&member, format!("on {name} via {}", graph.name(&via))));
+               }
+            }
+         }
+      }
+      "unconstrained_delegation" => {
+         for (id, (_, object_type, object)) in &graph.nodes {
+            if (object_type == "User" || object_type == "Computer")
+               && object["Properties"]["unconstraineddelegation"] == true
+               && !is_domain_controller(object)
+            {
+               let enabled = if object["Properties"]["enabled"] == false { "disabled" } else { "enabled" };
+               rows.push(row(id, enabled.to_string()));
+            }
+         }
+      }
+      "tier0_members" => {
+         for group in tier0_ids(graph).iter().filter(|id| graph.nodes[*id].1 == "Group") {
+            for (member, via) in effective_members(graph, group) {
+               let detail = if &via == group { graph.name(group) } else { format!("{} via {}", graph.name(group), graph.name(&via)) };
+               rows.push(row(&member, detail));
+            }
+         }
+      }
+      _ => {}
+   }
+   rows.sort_by(|a, b| a.name.cmp(&b.name).then(a.detail.cmp(&b.detail)));
+   rows
+}
+
+/// Function to get the Tier 0 objects of the graph.
+fn tier0_ids(graph: &ObjectGraph) -> Vec<String> {
+   let mut ids: Vec<String> = graph.nodes.iter()
+      .filter(|(id, (_, object_type, object))| is_tier0(object_type, id, object))
+      .map(|(id, _)| id.to_owned())
+      .collect();
+   ids.sort();
+   ids
+}
+
+/// Function to get the effective members of a group with the group they are a direct member of.
+pub fn effective_members(graph: &ObjectGraph, group: &str) -> Vec<(String, String)> {
+   let mut direct: HashMap<&str, Vec<&str>> = HashMap::new();
+   for (source, edges) in &graph.edges {
+      for (edge, target, _) in edges {
+         if edge == "MemberOf" {
+            direct.entry(target.as_str()).or_default().push(source.as_str());
+         }
+      }
+   }
+   let mut members: Vec<(String, String)> = Vec::new();
+   let mut seen: Vec<&str> = vec![group];
+   let mut queue: VecDeque<&str> = VecDeque::from(vec![group]);
+   while let Some(current) = queue.pop_front() {
+      for member in direct.get(current).cloned().unwrap_or_default() {
+         if seen.contains(&member) {
+            continue
+         }
+         seen.push(member);
+         members.push((member.to_string(), current.to_string()));
+         queue.push_back(member);
+      }
+   }
+   members
+}
+
+/// Function to render the rows as a text table.
+pub fn render_table(rows: &[QueryRow]) -> String {
+   let name_width = rows.iter().map(|row| row.name.len()).max().unwrap_or(0).max(4);
+   let type_width = rows.iter().map(|row| row.object_type.len()).max().unwrap_or(0).max(4);
+   let mut table = format!("{:<name_width$} {:<type_width$} {}\n", "NAME", "TYPE", "DETAIL");
+   for row in rows {
+      table.push_str(&format!("{:<name_width$} {:<type_width$} {}\n", row.name, row.object_type, row.detail));
+   }
+   table
+}
+
+/// Function to run a canned query on the collected objects and print the result in the tables output.
+pub fn make_query_report(query: &String, vec_objects: &[(&str, &Vec<Value>)], tables: &mut dyn Write) -> std::io::Result<()> {
+   let description = CANNED_QUERIES.iter().find(|(name, _)| name == query).map(|(_, description)| *description)
+      .ok_or_else(|| std::io::Error::new(std::io::ErrorKind::InvalidInput, format!("unknown query {query}")))?;
+   let graph = ObjectGraph::new(vec_objects);
+   let rows = run_query(query, &graph);
+   writeln!(tables, "\n{}\n{}", description.bold(), render_table(&rows))?;
+   info!("{} results for {}", rows.len().to_string().bold(), query);
+   Ok(())
+}
+
+/// Test functions
+#[test]
+pub fn test_canned_queries() {
+   let users = vec![
+      serde_json::json!({"ObjectIdentifier": "S-1-5-21-1-2-3-1104", "Properties": {"name": "SQL_SVC@ESSOS.LOCAL", "samaccountname": "sql_svc", "hasspn": true, "enabled": true}}),
+      serde_json::json!({"ObjectIdentifier": "S-1-5-21-1-2-3-1105", "Properties": {"name": "WEB_SVC@ESSOS.LOCAL", "samaccountname": "web_svc", "hasspn": true, "enabled": true}}),
+      serde_json::json!({"ObjectIdentifier": "S-1-5-21-1-2-3-1106", "Properties": {"name": "DAENERYS@ESSOS.LOCAL", "enabled": true}}),
+   ];
+   let groups = vec![
+      serde_json::json!({"ObjectIdentifier": "S-1-5-21-1-2-3-512", "Properties": {"name": "DOMAIN ADMINS@ESSOS.LOCAL"},
+         "Members": [{"ObjectIdentifier": "S-1-5-21-1-2-3-1107", "ObjectType": "Group"}]}),
+      serde_json::json!({"ObjectIdentifier": "S-1-5-21-1-2-3-1107", "Properties": {"name": "TARGARYEN@ESSOS.LOCAL"},
+         "Members": [{"ObjectIdentifier": "S-1-5-21-1-2-3-1104", "ObjectType": "User"}]}),
+      serde_json::json!({"ObjectIdentifier": "S-1-5-21-1-2-3-1108", "Properties": {"name": "REPLICATORS@ESSOS.LOCAL"},
+         "Members": [{"ObjectIdentifier": "S-1-5-21-1-2-3-1106", "ObjectType": "User"}]}),
+   ];
+   let domains = vec![
+      serde_json::json!({"ObjectIdentifier": "S-1-5-21-1-2-3", "Properties": {"name": "ESSOS.LOCAL"},
+         "Aces": [{"RightName": "DCSync", "PrincipalSID": "S-1-5-21-1-2-3-1108", "PrincipalType": "Group"}]}),
+   ];
+   let graph = ObjectGraph::new(&[("User", &users), ("Group", &groups), ("Domain", &domains)]);
+
+   let rows = run_query("kerberoastable_high_value", &graph);
+   assert_eq!(rows.len(), 1);
+   assert_eq!((rows[0].name.as_str(), rows[0].detail.as_str()), ("SQL_SVC@ESSOS.LOCAL", "2 edges to DOMAIN ADMINS@ESSOS.LOCAL"));
+
+   let rows = run_query("dcsync_principals", &graph);
+   assert_eq!(rows.iter().map(|row| row.name.as_str()).collect::<Vec<&str>>(), vec!["DAENERYS@ESSOS.LOCAL", "REPLICATORS@ESSOS.LOCAL"]);
+   assert_eq!(rows[0].detail, "on ESSOS.LOCAL via REPLICATORS@ESSOS.LOCAL");
+
+   let rows = run_query("tier0_members", &graph);
+   assert_eq!(rows.len(), 2);
+   assert_eq!(rows[0].detail, "DOMAIN ADMINS@ESSOS.LOCAL via TARGARYEN@ESSOS.LOCAL");
+   assert_eq!(render_table(&rows[1..]), "NAME                  TYPE  DETAIL\nTARGARYEN@ESSOS.LOCAL Group DOMAIN ADMINS@ESSOS.LOCAL\n");
+}
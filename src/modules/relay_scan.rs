@@ -0,0 +1,230 @@
+//! NTLM relay posture of the DCs and servers (--relay-scan)
+//!
+//! The scan connects to the computers of the collection to triage the relay targets:
+//! - SMB signing: SMB2 NEGOTIATE on TCP 445, the SecurityMode of the response says if signing is required,
+//! - LDAP signing (DCs), only with --relay-ldap-bind: simple bind with the collection credentials on TCP 389,
+//!   strongAuthRequired (8) when the DC requires signing. The password goes in cleartext to every DC, even when the
+//!   collection used LDAPS or Kerberos, so the check is unknown (null) without the flag,
+//! - LDAPS (DCs): TLS connection on TCP 636.
+//!
+//! `dc` scans the DCs (isdc), `servers` also scans SMB on the enabled computers with a server operating system.
+//! The results are set on the computers (`smbsigningrequired`, `ldapsigningrequired`, `ldapsavailable`, null when
+//! unreachable) and written in `<dirpath>/<domain>_relay.json` with the relay targets first.
+use crate::banner::Colorize;
+use ldap3::{LdapConnAsync, LdapConnSettings};
+use log::{debug, info};
+use serde_json::value::Value;
+use std::collections::HashMap;
+use std::fs;
+use std::io::{Read, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::time::Duration;
+
+use crate::modules::remote::run_probes;
+use crate::secret::SecretString;
+
+/// Timeout of every connection of the scan.
+pub const RELAY_SCAN_TIMEOUT: Duration = Duration::from_secs(3);
+/// Hosts scanned at the same time for SMB.
+pub const RELAY_SCAN_THREADS: usize = 16;
+/// SMB2 dialects offered in the NEGOTIATE request: 2.0.2, 2.1, 3.0, 3.0.2.
+const SMB2_DIALECTS: [u16; 4] = [0x0202, 0x0210, 0x0300, 0x0302];
+const SMB2_NEGOTIATE_SIGNING_REQUIRED: u16 = 0x0002;
+/// LDAP result code of a simple bind refused without signing.
+const LDAP_STRONG_AUTH_REQUIRED: u32 = 8;
+
+/// Function to build the SMB2 NEGOTIATE request with its NetBIOS session header.
+/// <https://learn.microsoft.com/en-us/openspecs/windows_protocols/ms-smb2/e14db7ff-763a-4263-8b10-0c3944f52fc5>
+pub fn smb2_negotiate_request() -> Vec<u8> {
+   let mut smb: Vec<u8> = Vec::new();
+   // SMB2 header: ProtocolId, StructureSize 64, CreditCharge, Status, Command NEGOTIATE, CreditRequest 1
+   smb.extend_from_slice(b"\xfeSMB");
+   smb.extend_from_slice(&64u16.to_le_bytes());
+   smb.extend_from_slice(&[0; 6]);
+   smb.extend_from_slice(&0u16.to_le_bytes());
+   smb.extend_from_slice(&1u16.to_le_bytes());
+   // Flags, NextCommand, MessageId, Reserved, TreeId, SessionId, Signature
+   smb.extend_from_slice(&[0; 48]);
+   // NEGOTIATE: StructureSize 36, DialectCount, SecurityMode signing enabled, Reserved, Capabilities, ClientGuid, ClientStartTime
+   smb.extend_from_slice(&36u16.to_le_bytes());
+   smb.extend_from_slice(&(SMB2_DIALECTS.len() as u16).to_le_bytes());
+   smb.extend_from_slice(&1u16.to_le_bytes());
+   smb.extend_from_slice(&[0; 2 + 4 + 16 + 8]);
+   for dialect in SMB2_DIALECTS {
+      smb.extend_from_slice(&dialect.to_le_bytes());
+   }
+   let mut request = vec![0x00];
+   request.extend_from_slice(&(smb.len() as u32).to_be_bytes()[1..]);
+   request.extend(smb);
+   request
+}
+
+/// Function to read if signing is required from a SMB2 NEGOTIATE response (without the NetBIOS header).
+pub fn smb2_signing_required(response: &[u8]) -> Option<bool> {
+   if response.len() < 64 + 4 || &response[..4] != b"\xfeSMB" {
+      return None
+   }
+   let status = u32::from_le_bytes([response[8], response[9], response[10], response[11]]);
+   if status != 0 {
+      return None
+   }
+   let security_mode = u16::from_le_bytes([response[66], response[67]]);
+   Some(security_mode & SMB2_NEGOTIATE_SIGNING_REQUIRED != 0)
+}
+
+/// Function to know if one host requires SMB signing, None when it is unreachable.
+pub fn smb_signing(host: &str) -> Option<bool> {
+   let address = (host, 445).to_socket_addrs().ok()?.next()?;
+   let mut stream = TcpStream::connect_timeout(&address, RELAY_SCAN_TIMEOUT).ok()?;
+   stream.set_read_timeout(Some(RELAY_SCAN_TIMEOUT)).ok()?;
+   stream.write_all(&smb2_negotiate_request()).ok()?;
+   let mut header = [0u8; 4];
+   stream.read_exact(&mut header).ok()?;
+   let len = u32::from_be_bytes([0, header[1], header[2], header[3]]) as usize;
+   let mut response = vec![0u8; len.min(0x10000)];
+   stream.read_exact(&mut response).ok()?;
+   smb2_signing_required(&response)
+}
+
+/// Function to know if a DC requires LDAP signing, None without credentials or when the bind fails otherwise.
+async fn ldap_signing(host: &str, username: &str, password: &SecretString) -> Option<bool> {
+   if username.contains("not set") {
+      return None
+   }
+   let settings = LdapConnSettings::new().set_conn_timeout(RELAY_SCAN_TIMEOUT);
+   let (conn, mut ldap) = LdapConnAsync::with_settings(settings, &format!("ldap://{}:389", host)).await.ok()?;
+   ldap3::drive!(conn);
+   let result = ldap.simple_bind(username, password.expose()).await.ok()?;
+   let _ = ldap.unbind().await;
+   match result.rc {
+      0 => Some(false),
+      LDAP_STRONG_AUTH_REQUIRED => Some(true),
+      rc => {
+         debug!("LDAP bind on {} failed with {}", host, rc);
+         None
+      }
+   }
+}
+
+/// Function to know if a DC answers on LDAPS.
+async fn ldaps_available(host: &str) -> bool {
+   let settings = LdapConnSettings::new().set_conn_timeout(RELAY_SCAN_TIMEOUT).set_no_tls_verify(true);
+   match LdapConnAsync::with_settings(settings, &format!("ldaps://{}:636", host)).await {
+      Ok((conn, mut ldap)) => {
+         ldap3::drive!(conn);
+         let _ = ldap.unbind().await;
+         true
+      }
+      Err(_) => false,
+   }
+}
+
+/// Function to get the computers to scan: index and host, the resolved IP address when known.
+pub fn relay_scan_targets(vec_computers: &[Value], scope: &str, fqdn_ip: &HashMap<String, String>) -> Vec<(usize, String)> {
+   vec_computers.iter().enumerate()
+      .filter(|(_, computer)| {
+         let is_dc = computer["Properties"]["isdc"].as_bool().unwrap_or(false);
+         let is_server = computer["Properties"]["operatingsystem"].as_str().map(|os| os.to_uppercase().contains("SERVER")).unwrap_or(false);
+         computer["Properties"]["enabled"] != false && (is_dc || (scope == "servers" && is_server))
+      })
+      .filter_map(|(index, computer)| {
+         let name = computer["Properties"]["name"].as_str()?;
+         let host = fqdn_ip.get(name).filter(|ip| !ip.is_empty()).cloned().unwrap_or_else(|| name.to_lowercase());
+         Some((index, host))
+      })
+      .collect()
+}
+
+/// Function to get the reasons why a scanned computer is a relay target.
+pub fn relay_reasons(computer: &Value) -> Vec<String> {
+   let mut reasons: Vec<String> = Vec::new();
+   if computer["Properties"]["smbsigningrequired"] == false {
+      reasons.push("SMB signing not required".to_string());
+   }
+   if computer["Properties"]["ldapsigningrequired"] == false {
+      reasons.push("LDAP signing not required".to_string());
+   }
+   reasons
+}
+
+/// Function to scan the DCs (and servers), set the results on the computers and write the relay report.
+/// LDAP signing is checked with a cleartext simple bind only with `ldap_bind`.
+pub async fn make_relay_report(
+   path: &String,
+   domain: &String,
+   scope: &str,
+   ldap_bind: bool,
+   username: &String,
+   password: &SecretString,
+   vec_computers: &mut [Value],
+   fqdn_ip: &HashMap<String, String>,
+) -> std::io::Result<()> {
+   let targets = relay_scan_targets(vec_computers, scope, fqdn_ip);
+   info!("Relay posture scan of {} computers started...", targets.len().to_string().bold());
+
+   let hosts: Vec<String> = targets.iter().map(|(_, host)| host.to_owned()).collect();
+   // Blocking sockets, out of the tokio workers
+   let smb = tokio::task::spawn_blocking(move || run_probes(&hosts, RELAY_SCAN_THREADS, smb_signing)).await
+      .map_err(std::io::Error::other)?;
+
+   let mut report: Vec<Value> = Vec::new();
+   for ((index, host), smb_signing_required) in targets.iter().zip(smb) {
+      let computer = &mut vec_computers[*index];
+      computer["Properties"]["smbsigningrequired"] = smb_signing_required.into();
+      if computer["Properties"]["isdc"] == true {
+         computer["Properties"]["ldapsigningrequired"] = if ldap_bind { ldap_signing(host, username, password).await.into() } else { Value::Null };
+         computer["Properties"]["ldapsavailable"] = ldaps_available(host).await.into();
+      }
+      report.push(serde_json::json!({
+         "name": computer["Properties"]["name"],
+         "objectid": computer["ObjectIdentifier"],
+         "host": host,
+         "smbsigningrequired": computer["Properties"]["smbsigningrequired"],
+         "ldapsigningrequired": computer["Properties"]["ldapsigningrequired"],
+         "ldapsavailable": computer["Properties"]["ldapsavailable"],
+         "relayreasons": relay_reasons(computer),
+      }));
+   }
+   report.sort_by_key(|entry| entry["relayreasons"].as_array().map(|reasons| reasons.is_empty()).unwrap_or(true));
+   for entry in report.iter().filter(|entry| entry["relayreasons"].as_array().map(|reasons| !reasons.is_empty()).unwrap_or(false)) {
+      info!("{} {}", entry["name"].as_str().unwrap_or("").yellow().bold(), entry["relayreasons"][0].as_str().unwrap_or(""));
+   }
+
+   fs::create_dir_all(path)?;
+   let final_path = format!("{}/{}_relay.json", path, domain.replace(".", "-").to_lowercase());
+   fs::write(&final_path, serde_json::to_string_pretty(&report).unwrap_or_default())?;
+   info!("{} created!", final_path.bold());
+   Ok(())
+}
+
+/// Test functions
+#[test]
+pub fn test_relay_scan() {
+   let request = smb2_negotiate_request();
+   assert_eq!(request.len(), 4 + 64 + 36 + 2 * SMB2_DIALECTS.len());
+   assert_eq!(&request[..4], &[0x00, 0x00, 0x00, 108]);
+   assert_eq!(&request[4..8], b"\xfeSMB");
+
+   // NEGOTIATE response: header then StructureSize 65, SecurityMode, DialectRevision 3.0.2
+   let mut response = b"\xfeSMB".to_vec();
+   response.extend_from_slice(&[0x40, 0x00]);
+   response.extend_from_slice(&[0; 58]);
+   response.extend_from_slice(&[0x41, 0x00, 0x03, 0x00, 0x02, 0x03]);
+   assert_eq!(smb2_signing_required(&response), Some(true));
+   response[66] = 0x01;
+   assert_eq!(smb2_signing_required(&response), Some(false));
+   response[8] = 0x22;
+   assert_eq!(smb2_signing_required(&response), None);
+
+   let computers = vec![
+      serde_json::json!({"ObjectIdentifier": "S-1-5-21-1-2-3-1000", "Properties": {"name": "MEEREEN.ESSOS.LOCAL", "enabled": true, "isdc": true}}),
+      serde_json::json!({"ObjectIdentifier": "S-1-5-21-1-2-3-1001", "Properties": {"name": "BRAAVOS.ESSOS.LOCAL", "enabled": true, "isdc": false,
+         "operatingsystem": "Windows Server 2016 Standard Evaluation", "smbsigningrequired": false}}),
+      serde_json::json!({"ObjectIdentifier": "S-1-5-21-1-2-3-1002", "Properties": {"name": "WS01.ESSOS.LOCAL", "enabled": true, "operatingsystem": "Windows 10 Pro"}}),
+   ];
+   let fqdn_ip = HashMap::from([("MEEREEN.ESSOS.LOCAL".to_string(), "192.168.56.12".to_string())]);
+   assert_eq!(relay_scan_targets(&computers, "dc", &fqdn_ip), vec![(0, "192.168.56.12".to_string())]);
+   assert_eq!(relay_scan_targets(&computers, "servers", &fqdn_ip), vec![(0, "192.168.56.12".to_string()), (1, "braavos.essos.local".to_string())]);
+   assert_eq!(relay_reasons(&computers[1]), vec!["SMB signing not required"]);
+   assert!(relay_reasons(&computers[0]).is_empty());
+}
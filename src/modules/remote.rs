@@ -0,0 +1,514 @@
+//! Remote collection on the computers of the domain
+//!
+//! The probes connect to the computers found by the LDAP collection, `run_probes` runs one probe per host with
+//! a bounded number of threads. The SMB client is the minimum needed by the probes: SMB 2.0.2 or 2.1 dialect,
+//! NTLMv2 session with the collection credentials (messages signed when the server requires it), IPC$ tree and
+//! named pipe open.
+//! <https://learn.microsoft.com/en-us/openspecs/windows_protocols/ms-smb2/5606ad47-5ee0-437a-817e-70c366052962>
+//! <https://learn.microsoft.com/en-us/openspecs/windows_protocols/ms-nlmp/b38c36ed-2804-4868-a9ff-8dd3182128e4>
+use hmac::{Hmac, Mac};
+use log::warn;
+use md4::{Digest, Md4};
+use md5::Md5;
+use ring::hmac as ring_hmac;
+use serde_json::value::Value;
+use std::collections::HashMap;
+use std::convert::TryInto;
+use std::io::{Error, ErrorKind, Read, Result, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::time::Duration;
+use zeroize::{Zeroize, Zeroizing};
+
+use crate::enums::date::epoch_to_filetime;
+use crate::modules::skipped::catch_panic;
+use crate::secret::SecretString;
+
+/// Timeout of the connections and reads of the remote collection.
+pub const REMOTE_TIMEOUT: Duration = Duration::from_secs(3);
+
+pub const STATUS_SUCCESS: u32 = 0x00000000;
+pub const STATUS_MORE_PROCESSING_REQUIRED: u32 = 0xC0000016;
+pub const STATUS_OBJECT_NAME_NOT_FOUND: u32 = 0xC0000034;
+
+const SMB2_NEGOTIATE: u16 = 0x0000;
+const SMB2_SESSION_SETUP: u16 = 0x0001;
+const SMB2_TREE_CONNECT: u16 = 0x0003;
+const SMB2_CREATE: u16 = 0x0005;
+const SMB2_FLAGS_SIGNED: u32 = 0x00000008;
+const SMB2_SIGNING_REQUIRED: u16 = 0x0002;
+/// Dialects signed with HMAC-SHA256: 2.0.2 and 2.1.
+const SMB2_DIALECTS: [u16; 2] = [0x0202, 0x0210];
+
+/// NTLMSSP flags: UNICODE, REQUEST_TARGET, NTLM, ALWAYS_SIGN, EXTENDED_SESSIONSECURITY, TARGET_INFO, 128, 56.
+const NTLM_FLAGS: u32 = 0x00000001 | 0x00000004 | 0x00000200 | 0x00008000 | 0x00080000 | 0x00800000 | 0x20000000 | 0x80000000;
+/// NTLMSSP flags of a signed and sealed session (NegotiateStream of ADWS): the flags above with SIGN, SEAL and KEY_EXCH.
+pub const NTLM_SEAL_FLAGS: u32 = NTLM_FLAGS | 0x00000010 | 0x00000020 | NTLMSSP_NEGOTIATE_KEY_EXCH;
+const NTLMSSP_NEGOTIATE_KEY_EXCH: u32 = 0x40000000;
+const MSV_AV_TIMESTAMP: u16 = 7;
+
+/// Function to run one probe per host with at most `threads` probes at the same time, results in the hosts order.
+/// A probe panicking is written in the crash log, its host gets the default result (unknown).
+pub fn run_probes<T, F>(hosts: &[String], threads: usize, probe: F) -> Vec<T>
+where
+   T: Send + Default,
+   F: Fn(&str) -> T + Sync,
+{
+   let mut results: Vec<T> = Vec::with_capacity(hosts.len());
+   for chunk in hosts.chunks(threads.max(1)) {
+      let probe = &probe;
+      results.extend(std::thread::scope(|scope| {
+         let handles: Vec<_> = chunk.iter().map(|host| scope.spawn(move || {
+            catch_panic("Remote probe", Some(host), || probe(host)).unwrap_or_else(|reason| {
+               warn!("Probe of {} failed. Reason: {}", host, reason);
+               T::default()
+            })
+         })).collect();
+         handles.into_iter().map(|handle| handle.join().expect("Remote probe panicked")).collect::<Vec<T>>()
+      }));
+   }
+   results
+}
+
+/// Function to know if a named pipe exists from the status of its open: None when the status does not tell.
+pub fn pipe_exists(status: u32) -> Option<bool> {
+   match status {
+      STATUS_SUCCESS => Some(true),
+      STATUS_OBJECT_NAME_NOT_FOUND => Some(false),
+      _ => None,
+   }
+}
+
+/// Function to open named pipes of one host in one SMB session, Err when the session can not be opened.
+pub fn probe_pipes(host: &str, user: &str, domain: &str, password: &SecretString, pipes: &[&str]) -> Result<Vec<Option<bool>>> {
+   let mut session = Smb2Session::connect(host)?;
+   session.login(user, domain, password)?;
+   session.connect_ipc(host)?;
+   pipes.iter().map(|pipe| session.open_pipe(pipe).map(pipe_exists)).collect()
+}
+
+/// Function to get the enabled computers to probe: index and host, the resolved IP address when known.
+pub fn remote_targets(vec_computers: &[Value], fqdn_ip: &HashMap<String, String>, exclude_stale: bool) -> Vec<(usize, String)> {
+   vec_computers.iter().enumerate()
+      .filter(|(_, computer)| computer["Properties"]["enabled"] != false)
+      .filter(|(_, computer)| !(exclude_stale && computer["Properties"]["probably_stale"] == true))
+      .filter_map(|(index, computer)| {
+         let name = computer["Properties"]["name"].as_str()?;
+         let host = fqdn_ip.get(name).filter(|ip| !ip.is_empty()).cloned().unwrap_or_else(|| name.to_lowercase());
+         Some((index, host))
+      })
+      .collect()
+}
+
+/// Function to split a username in (user, domain): DOMAIN\user, user@domain or user of the default domain.
+pub fn split_username(username: &str, default_domain: &str) -> (String, String) {
+   if let Some((domain, user)) = username.split_once('\\') {
+      (user.to_string(), domain.to_string())
+   } else if let Some((user, domain)) = username.split_once('@') {
+      (user.to_string(), domain.to_string())
+   } else {
+      (username.to_string(), default_domain.to_string())
+   }
+}
+
+fn utf16le(value: &str) -> Vec<u8> {
+   value.encode_utf16().flat_map(|c| c.to_le_bytes()).collect()
+}
+
+pub fn hmac_md5(key: &[u8], data: &[&[u8]]) -> Vec<u8> {
+   let mut mac = <Hmac<Md5> as Mac>::new_from_slice(key).expect("HMAC accepts any key length");
+   for part in data {
+      mac.update(part);
+   }
+   mac.finalize().into_bytes().to_vec()
+}
+
+/// Function to compute NTOWFv2: HMAC_MD5(MD4(UTF-16LE(password)), UTF-16LE(UPPER(user) + domain)).
+/// The encoded password, the NT hash and the key are zeroed when dropped.
+pub fn ntowfv2(password: &str, user: &str, domain: &str) -> Zeroizing<Vec<u8>> {
+   let mut nt_hash = Md4::digest(Zeroizing::new(utf16le(password)).as_slice());
+   let response_key = hmac_md5(&nt_hash, &[&utf16le(&(user.to_uppercase() + domain))]);
+   nt_hash.as_mut_slice().zeroize();
+   Zeroizing::new(response_key)
+}
+
+/// Function to compute the NTLMv2 response and the session base key of a challenge.
+pub fn ntlmv2_response(
+   response_key: &[u8],
+   server_challenge: &[u8],
+   client_challenge: &[u8; 8],
+   timestamp: u64,
+   target_info: &[u8],
+) -> (Vec<u8>, Vec<u8>) {
+   let mut temp: Vec<u8> = vec![0x01, 0x01, 0, 0, 0, 0, 0, 0];
+   temp.extend_from_slice(&timestamp.to_le_bytes());
+   temp.extend_from_slice(client_challenge);
+   temp.extend_from_slice(&[0; 4]);
+   temp.extend_from_slice(target_info);
+   temp.extend_from_slice(&[0; 4]);
+   let proof = hmac_md5(response_key, &[server_challenge, &temp]);
+   let session_base_key = hmac_md5(response_key, &[&proof]);
+   let mut response = proof;
+   response.extend(temp);
+   (response, session_base_key)
+}
+
+/// Function to build the NTLMSSP NEGOTIATE message.
+pub fn ntlm_negotiate() -> Vec<u8> {
+   ntlm_negotiate_flags(NTLM_FLAGS)
+}
+
+/// Function to build the NTLMSSP NEGOTIATE message with other flags, like NTLM_SEAL_FLAGS.
+pub fn ntlm_negotiate_flags(flags: u32) -> Vec<u8> {
+   let mut message = b"NTLMSSP\0".to_vec();
+   message.extend_from_slice(&1u32.to_le_bytes());
+   message.extend_from_slice(&flags.to_le_bytes());
+   message.extend_from_slice(&[0; 16]);
+   message
+}
+
+/// NTLMSSP CHALLENGE message: flags, server challenge, target info.
+pub struct NtlmChallenge {
+   pub flags: u32,
+   pub server_challenge: Vec<u8>,
+   pub target_info: Vec<u8>,
+}
+
+/// Function to parse the NTLMSSP CHALLENGE message of a security buffer (raw or in a SPNEGO token).
+pub fn parse_ntlm_challenge(buffer: &[u8]) -> Option<NtlmChallenge> {
+   let start = buffer.windows(8).position(|window| window == b"NTLMSSP\0")?;
+   let message = &buffer[start..];
+   if message.len() < 48 || message[8..12] != 2u32.to_le_bytes() {
+      return None
+   }
+   let u16_at = |offset: usize| u16::from_le_bytes([message[offset], message[offset + 1]]) as usize;
+   let u32_at = |offset: usize| u32::from_le_bytes([message[offset], message[offset + 1], message[offset + 2], message[offset + 3]]);
+   let (info_len, info_offset) = (u16_at(40), u32_at(44) as usize);
+   Some(NtlmChallenge {
+      flags: u32_at(20),
+      server_challenge: message[24..32].to_vec(),
+      target_info: message.get(info_offset..info_offset + info_len)?.to_vec(),
+   })
+}
+
+/// Function to get the MsvAvTimestamp of the target info.
+fn target_info_timestamp(target_info: &[u8]) -> Option<u64> {
+   let mut offset = 0;
+   while offset + 4 <= target_info.len() {
+      let id = u16::from_le_bytes([target_info[offset], target_info[offset + 1]]);
+      let len = u16::from_le_bytes([target_info[offset + 2], target_info[offset + 3]]) as usize;
+      let value = target_info.get(offset + 4..offset + 4 + len)?;
+      match id {
+         0 => return None,
+         MSV_AV_TIMESTAMP if len == 8 => return Some(u64::from_le_bytes(value.try_into().ok()?)),
+         _ => offset += 4 + len,
+      }
+   }
+   None
+}
+
+/// Function to build the NTLMSSP AUTHENTICATE message, return it with the session key.
+pub fn ntlm_authenticate(challenge: &NtlmChallenge, user: &str, domain: &str, password: &str) -> (Vec<u8>, Vec<u8>) {
+   ntlm_authenticate_flags(challenge, user, domain, password, NTLM_FLAGS)
+}
+
+/// Function to build the NTLMSSP AUTHENTICATE message with the flags of the NEGOTIATE, return it with the session key.
+/// With KEY_EXCH negotiated the session key is a random one, sent encrypted with the session base key.
+pub fn ntlm_authenticate_flags(challenge: &NtlmChallenge, user: &str, domain: &str, password: &str, flags: u32) -> (Vec<u8>, Vec<u8>) {
+   let client_challenge: [u8; 8] = rand::random();
+   let now = epoch_to_filetime(chrono::Utc::now().timestamp()) as u64;
+   let timestamp = target_info_timestamp(&challenge.target_info).unwrap_or(now);
+   let response_key = ntowfv2(password, user, domain);
+   let (nt_response, session_base_key) = ntlmv2_response(&response_key, &challenge.server_challenge, &client_challenge, timestamp, &challenge.target_info);
+   let flags = flags & challenge.flags;
+   let (encrypted_session_key, session_key) = if flags & NTLMSSP_NEGOTIATE_KEY_EXCH != 0 {
+      let session_key: [u8; 16] = rand::random();
+      (Rc4::new(&session_base_key).apply(&session_key), session_key.to_vec())
+   } else {
+      (Vec::new(), session_base_key)
+   };
+
+   // EncryptedRandomSessionKey is empty without KEY_EXCH
+   let fields: [Vec<u8>; 6] = [vec![0; 24], nt_response, utf16le(domain), utf16le(user), Vec::new(), encrypted_session_key];
+   let mut message = b"NTLMSSP\0".to_vec();
+   message.extend_from_slice(&3u32.to_le_bytes());
+   let mut offset = 64u32;
+   for field in fields.iter() {
+      message.extend_from_slice(&(field.len() as u16).to_le_bytes());
+      message.extend_from_slice(&(field.len() as u16).to_le_bytes());
+      message.extend_from_slice(&offset.to_le_bytes());
+      offset += field.len() as u32;
+   }
+   message.extend_from_slice(&flags.to_le_bytes());
+   for field in fields {
+      message.extend(field);
+   }
+   (message, session_key)
+}
+
+/// RC4 of the NTLM key exchange and sealing, the key stream goes on from one message to the next.
+pub struct Rc4 {
+   state: [u8; 256],
+   i: u8,
+   j: u8,
+}
+
+impl Rc4 {
+   pub fn new(key: &[u8]) -> Rc4 {
+      let mut state = [0u8; 256];
+      for (index, value) in state.iter_mut().enumerate() {
+         *value = index as u8;
+      }
+      let mut j: u8 = 0;
+      for i in 0..256 {
+         j = j.wrapping_add(state[i]).wrapping_add(key[i % key.len()]);
+         state.swap(i, j as usize);
+      }
+      Rc4 { state, i: 0, j: 0 }
+   }
+
+   /// Function to encrypt or decrypt the next bytes of the stream.
+   pub fn apply(&mut self, data: &[u8]) -> Vec<u8> {
+      data.iter().map(|byte| {
+         self.i = self.i.wrapping_add(1);
+         self.j = self.j.wrapping_add(self.state[self.i as usize]);
+         self.state.swap(self.i as usize, self.j as usize);
+         byte ^ self.state[self.state[self.i as usize].wrapping_add(self.state[self.j as usize]) as usize]
+      }).collect()
+   }
+}
+
+/// SMB2 session on one host: connection, message counter, session and tree ids, signing key.
+pub struct Smb2Session {
+   stream: TcpStream,
+   message_id: u64,
+   session_id: u64,
+   tree_id: u32,
+   signing_required: bool,
+   signing_key: Vec<u8>,
+}
+
+/// SMB2 response: status, header and body.
+pub struct Smb2Response {
+   pub status: u32,
+   pub header: Vec<u8>,
+   pub body: Vec<u8>,
+}
+
+impl Smb2Session {
+   /// Connect to TCP 445 and negotiate the dialect.
+   pub fn connect(host: &str) -> Result<Smb2Session> {
+      let address = (host, 445).to_socket_addrs()?.next()
+         .ok_or_else(|| Error::new(ErrorKind::NotFound, format!("No address for {}", host)))?;
+      let stream = TcpStream::connect_timeout(&address, REMOTE_TIMEOUT)?;
+      stream.set_read_timeout(Some(REMOTE_TIMEOUT))?;
+      stream.set_write_timeout(Some(REMOTE_TIMEOUT))?;
+      let mut session = Smb2Session { stream, message_id: 0, session_id: 0, tree_id: 0, signing_required: false, signing_key: Vec::new() };
+
+      // NEGOTIATE: StructureSize 36, DialectCount, SecurityMode signing enabled, Reserved, Capabilities, ClientGuid, ClientStartTime
+      let mut body: Vec<u8> = Vec::new();
+      body.extend_from_slice(&36u16.to_le_bytes());
+      body.extend_from_slice(&(SMB2_DIALECTS.len() as u16).to_le_bytes());
+      body.extend_from_slice(&1u16.to_le_bytes());
+      body.extend_from_slice(&[0; 2 + 4]);
+      body.extend_from_slice(&rand::random::<[u8; 16]>());
+      body.extend_from_slice(&[0; 8]);
+      for dialect in SMB2_DIALECTS {
+         body.extend_from_slice(&dialect.to_le_bytes());
+      }
+      let response = session.request(SMB2_NEGOTIATE, &body)?;
+      if response.status != STATUS_SUCCESS || response.body.len() < 4 {
+         return Err(Error::new(ErrorKind::InvalidData, format!("SMB2 negotiate failed with 0x{:08x}", response.status)));
+      }
+      session.signing_required = u16::from_le_bytes([response.body[2], response.body[3]]) & SMB2_SIGNING_REQUIRED != 0;
+      Ok(session)
+   }
+
+   /// Authenticate with NTLMv2, the messages are signed from now on when the server requires it.
+   pub fn login(&mut self, user: &str, domain: &str, password: &SecretString) -> Result<()> {
+      let response = self.request(SMB2_SESSION_SETUP, &session_setup_body(&ntlm_negotiate()))?;
+      if response.status != STATUS_MORE_PROCESSING_REQUIRED {
+         return Err(Error::new(ErrorKind::PermissionDenied, format!("SMB2 session setup failed with 0x{:08x}", response.status)));
+      }
+      self.session_id = u64::from_le_bytes(response.header[40..48].try_into().unwrap_or_default());
+      let challenge = security_buffer(&response.body).and_then(parse_ntlm_challenge)
+         .ok_or_else(|| Error::new(ErrorKind::InvalidData, "No NTLM challenge in the SMB2 session setup"))?;
+      let (authenticate, session_key) = ntlm_authenticate(&challenge, user, domain, password.expose());
+      let response = self.request(SMB2_SESSION_SETUP, &session_setup_body(&authenticate))?;
+      if response.status != STATUS_SUCCESS {
+         return Err(Error::new(ErrorKind::PermissionDenied, format!("SMB2 authentication failed with 0x{:08x}", response.status)));
+      }
+      self.signing_key = session_key;
+      Ok(())
+   }
+
+   /// Connect the IPC$ tree of the host.
+   pub fn connect_ipc(&mut self, host: &str) -> Result<()> {
+      let path = utf16le(&format!("\\\\{}\\IPC$", host));
+      let mut body: Vec<u8> = Vec::new();
+      body.extend_from_slice(&9u16.to_le_bytes());
+      body.extend_from_slice(&0u16.to_le_bytes());
+      body.extend_from_slice(&(64u16 + 8).to_le_bytes());
+      body.extend_from_slice(&(path.len() as u16).to_le_bytes());
+      body.extend(path);
+      let response = self.request(SMB2_TREE_CONNECT, &body)?;
+      if response.status != STATUS_SUCCESS {
+         return Err(Error::new(ErrorKind::PermissionDenied, format!("SMB2 tree connect to IPC$ failed with 0x{:08x}", response.status)));
+      }
+      self.tree_id = u32::from_le_bytes(response.header[36..40].try_into().unwrap_or_default());
+      Ok(())
+   }
+
+   /// Open a named pipe of IPC$ for reading, return the status.
+   pub fn open_pipe(&mut self, pipe: &str) -> Result<u32> {
+      let name = utf16le(pipe);
+      let mut body: Vec<u8> = Vec::new();
+      body.extend_from_slice(&57u16.to_le_bytes());
+      // SecurityFlags, RequestedOplockLevel, ImpersonationLevel Impersonation, SmbCreateFlags, Reserved
+      body.extend_from_slice(&[0, 0]);
+      body.extend_from_slice(&2u32.to_le_bytes());
+      body.extend_from_slice(&[0; 16]);
+      // DesiredAccess FILE_READ_DATA, FILE_READ_EA, FILE_READ_ATTRIBUTES, READ_CONTROL, SYNCHRONIZE
+      body.extend_from_slice(&0x00120089u32.to_le_bytes());
+      body.extend_from_slice(&0u32.to_le_bytes());
+      body.extend_from_slice(&7u32.to_le_bytes());
+      // CreateDisposition FILE_OPEN, CreateOptions
+      body.extend_from_slice(&1u32.to_le_bytes());
+      body.extend_from_slice(&0u32.to_le_bytes());
+      body.extend_from_slice(&(64u16 + 56).to_le_bytes());
+      body.extend_from_slice(&(name.len() as u16).to_le_bytes());
+      body.extend_from_slice(&[0; 8]);
+      body.extend(name);
+      Ok(self.request(SMB2_CREATE, &body)?.status)
+   }
+
+   /// Send one request and read its response, signed after the session setup when the server requires it.
+   fn request(&mut self, command: u16, body: &[u8]) -> Result<Smb2Response> {
+      let sign = self.signing_required && !self.signing_key.is_empty();
+      let mut message = smb2_header(command, self.message_id, self.session_id, self.tree_id, if sign { SMB2_FLAGS_SIGNED } else { 0 });
+      message.extend_from_slice(body);
+      if sign {
+         let key = ring_hmac::Key::new(ring_hmac::HMAC_SHA256, &self.signing_key);
+         let signature = ring_hmac::sign(&key, &message);
+         message[48..64].copy_from_slice(&signature.as_ref()[..16]);
+      }
+      self.message_id += 1;
+      let mut packet = vec![0x00];
+      packet.extend_from_slice(&(message.len() as u32).to_be_bytes()[1..]);
+      packet.extend(message);
+      self.stream.write_all(&packet)?;
+
+      loop {
+         let mut netbios = [0u8; 4];
+         self.stream.read_exact(&mut netbios)?;
+         let len = u32::from_be_bytes([0, netbios[1], netbios[2], netbios[3]]) as usize;
+         let mut response = vec![0u8; len];
+         self.stream.read_exact(&mut response)?;
+         if response.len() < 64 || &response[..4] != b"\xfeSMB" {
+            return Err(Error::new(ErrorKind::InvalidData, "Not a SMB2 response"));
+         }
+         let status = u32::from_le_bytes(response[8..12].try_into().unwrap_or_default());
+         // STATUS_PENDING interim response, the final one follows
+         if status == 0x00000103 {
+            continue;
+         }
+         let body = response.split_off(64);
+         return Ok(Smb2Response { status, header: response, body })
+      }
+   }
+}
+
+/// Function to build one SMB2 header with one credit requested.
+pub fn smb2_header(command: u16, message_id: u64, session_id: u64, tree_id: u32, flags: u32) -> Vec<u8> {
+   let mut header = b"\xfeSMB".to_vec();
+   header.extend_from_slice(&64u16.to_le_bytes());
+   header.extend_from_slice(&[0; 6]);
+   header.extend_from_slice(&command.to_le_bytes());
+   header.extend_from_slice(&1u16.to_le_bytes());
+   header.extend_from_slice(&flags.to_le_bytes());
+   header.extend_from_slice(&0u32.to_le_bytes());
+   header.extend_from_slice(&message_id.to_le_bytes());
+   header.extend_from_slice(&0u32.to_le_bytes());
+   header.extend_from_slice(&tree_id.to_le_bytes());
+   header.extend_from_slice(&session_id.to_le_bytes());
+   header.extend_from_slice(&[0; 16]);
+   header
+}
+
+/// Function to build a SESSION_SETUP body around a security buffer.
+fn session_setup_body(token: &[u8]) -> Vec<u8> {
+   let mut body: Vec<u8> = Vec::new();
+   body.extend_from_slice(&25u16.to_le_bytes());
+   body.extend_from_slice(&[0, 1]);
+   body.extend_from_slice(&[0; 8]);
+   body.extend_from_slice(&(64u16 + 24).to_le_bytes());
+   body.extend_from_slice(&(token.len() as u16).to_le_bytes());
+   body.extend_from_slice(&[0; 8]);
+   body.extend_from_slice(token);
+   body
+}
+
+/// Function to get the security buffer of a SESSION_SETUP response body.
+fn security_buffer(body: &[u8]) -> Option<&[u8]> {
+   let offset = u16::from_le_bytes([*body.get(4)?, *body.get(5)?]) as usize;
+   let len = u16::from_le_bytes([*body.get(6)?, *body.get(7)?]) as usize;
+   body.get(offset.checked_sub(64)?..offset - 64 + len)
+}
+
+/// Test functions
+#[test]
+pub fn test_remote() {
+   // MS-NLMP 4.2.4 NTLMv2 authentication test vectors
+   let response_key = ntowfv2("Password", "User", "Domain");
+   assert_eq!(*response_key, vec![0x0c, 0x86, 0x8a, 0x40, 0x3b, 0xfd, 0x7a, 0x93, 0xa3, 0x00, 0x1e, 0xf2, 0x2e, 0xf0, 0x2e, 0x3f]);
+   let server_challenge = [0x01, 0x23, 0x45, 0x67, 0x89, 0xab, 0xcd, 0xef];
+   let target_info = [0x02, 0x00, 0x0c, 0x00, 0x44, 0x00, 0x6f, 0x00, 0x6d, 0x00, 0x61, 0x00, 0x69, 0x00, 0x6e, 0x00,
+      0x01, 0x00, 0x0c, 0x00, 0x53, 0x00, 0x65, 0x00, 0x72, 0x00, 0x76, 0x00, 0x65, 0x00, 0x72, 0x00, 0x00, 0x00, 0x00, 0x00];
+   let (response, session_base_key) = ntlmv2_response(&response_key, &server_challenge, &[0xaa; 8], 0, &target_info);
+   assert_eq!(&response[..16], &[0x68, 0xcd, 0x0a, 0xb8, 0x51, 0xe5, 0x1c, 0x96, 0xaa, 0xbc, 0x92, 0x7b, 0xeb, 0xef, 0x6a, 0x1c]);
+   assert_eq!(session_base_key, vec![0x8d, 0xe4, 0x0c, 0xca, 0xdb, 0xc1, 0x4a, 0x82, 0xf1, 0x5c, 0xb0, 0xad, 0x0d, 0xe9, 0x5c, 0xa3]);
+
+   let mut challenge = b"NTLMSSP\0".to_vec();
+   challenge.extend_from_slice(&2u32.to_le_bytes());
+   challenge.extend_from_slice(&[0; 8]);
+   challenge.extend_from_slice(&NTLM_FLAGS.to_le_bytes());
+   challenge.extend_from_slice(&server_challenge);
+   challenge.extend_from_slice(&[0; 8]);
+   challenge.extend_from_slice(&(target_info.len() as u16).to_le_bytes());
+   challenge.extend_from_slice(&(target_info.len() as u16).to_le_bytes());
+   challenge.extend_from_slice(&48u32.to_le_bytes());
+   challenge.extend_from_slice(&target_info);
+   let parsed = parse_ntlm_challenge(&challenge).unwrap();
+   assert_eq!(parsed.server_challenge, server_challenge);
+   assert_eq!(parsed.target_info, target_info);
+   assert_eq!(target_info_timestamp(&parsed.target_info), None);
+   let (authenticate, _) = ntlm_authenticate(&parsed, "User", "Domain", "Password");
+   assert_eq!(&authenticate[..12], b"NTLMSSP\0\x03\0\0\0");
+   assert_eq!(authenticate.len(), 64 + 24 + 16 + 28 + 36 + 4 + 12 + 8);
+
+   // RC4 test vector, and the MS-NLMP 4.2.4.2.3 encrypted session key of the key exchange
+   assert_eq!(Rc4::new(b"Key").apply(b"Plaintext"), vec![0xbb, 0xf3, 0x16, 0xe8, 0xd9, 0x40, 0xaf, 0x0a, 0xd3]);
+   assert_eq!(Rc4::new(&session_base_key).apply(&[0x55; 16]), vec![0xc5, 0xda, 0xd2, 0x54, 0x4f, 0xc9, 0x79, 0x90, 0x94, 0xce, 0x1c, 0xe9, 0x0b, 0xc9, 0xd0, 0x3e]);
+   challenge[20..24].copy_from_slice(&NTLM_SEAL_FLAGS.to_le_bytes());
+   let (authenticate, session_key) = ntlm_authenticate_flags(&parse_ntlm_challenge(&challenge).unwrap(), "User", "Domain", "Password", NTLM_SEAL_FLAGS);
+   assert_eq!(authenticate.len(), 64 + 24 + 16 + 28 + 36 + 4 + 12 + 8 + 16);
+   assert_eq!(&authenticate[60..64], &NTLM_SEAL_FLAGS.to_le_bytes());
+   assert_eq!(session_key.len(), 16);
+
+   assert_eq!(split_username("ESSOS\\daenerys.targaryen", "ESSOS.LOCAL"), ("daenerys.targaryen".to_string(), "ESSOS".to_string()));
+   assert_eq!(split_username("daenerys.targaryen@essos.local", "ESSOS.LOCAL"), ("daenerys.targaryen".to_string(), "essos.local".to_string()));
+   assert_eq!(pipe_exists(STATUS_SUCCESS), Some(true));
+   assert_eq!(pipe_exists(STATUS_OBJECT_NAME_NOT_FOUND), Some(false));
+   assert_eq!(pipe_exists(0xC0000022), None);
+   let hosts = vec!["a".to_string(), "bb".to_string(), "ccc".to_string()];
+   assert_eq!(run_probes(&hosts, 2, |host| host.len()), vec![1, 2, 3]);
+   assert_eq!(run_probes(&hosts, 2, |host| if host.len() == 2 { panic!("truncated response") } else { host.len() }), vec![1, 0, 3]);
+
+   let computers = vec![
+      serde_json::json!({"ObjectIdentifier": "S-1-5-21-1-2-3-1001", "Properties": {"name": "BRAAVOS.ESSOS.LOCAL", "enabled": true, "probably_stale": false}}),
+      serde_json::json!({"ObjectIdentifier": "S-1-5-21-1-2-3-1002", "Properties": {"name": "WS01.ESSOS.LOCAL", "enabled": false}}),
+      serde_json::json!({"ObjectIdentifier": "S-1-5-21-1-2-3-1003", "Properties": {"name": "WS02.ESSOS.LOCAL", "enabled": true, "probably_stale": true}}),
+   ];
+   let fqdn_ip = HashMap::from([("BRAAVOS.ESSOS.LOCAL".to_string(), "192.168.56.23".to_string())]);
+   assert_eq!(remote_targets(&computers, &fqdn_ip, true), vec![(0, "192.168.56.23".to_string())]);
+   assert_eq!(remote_targets(&computers, &fqdn_ip, false).len(), 2);
+}
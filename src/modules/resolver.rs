@@ -1,5 +1,5 @@
 use log::{info,debug};
-use colored::Colorize;
+use crate::banner::Colorize;
 
 use trust_dns_resolver::TokioAsyncResolver;
 use trust_dns_resolver::config::*;
@@ -11,7 +11,7 @@ use std::time::Duration;
 /// Function to resolve IP address from the ldap FQDN
 /// <https://docs.rs/trust-dns-resolver/latest/trust_dns_resolver/index.html>
 /// <https://github.com/shadowsocks/shadowsocks-rust/blob/master/crates/shadowsocks-service/src/config.rs>
-pub async fn fqdn_resolver(dns_tcp: bool, ldapip: &String, name_server: &String, fqdn_ip: &mut HashMap<String, String>, vec_computer: &Vec<serde_json::value::Value>)
+pub async fn fqdn_resolver(dns_tcp: bool, ldapip: &String, name_server: &String, fqdn_ip: &mut HashMap<String, String>, vec_computer: &Vec<serde_json::value::Value>, exclude_stale: bool)
 {
    info!("Resolving FQDN to IP address started...");
    let mut c = ResolverConfig::new();
@@ -56,6 +56,10 @@ pub async fn fqdn_resolver(dns_tcp: bool, ldapip: &String, name_server: &String,
       for i in 0..vec_computer.len()
       {
           if (vec_computer[i]["Properties"]["name"].as_str().unwrap().to_string() == value.0.to_owned().to_string()) && (vec_computer[i]["Properties"]["enabled"] == true) {
+            if exclude_stale && vec_computer[i]["Properties"]["probably_stale"] == true {
+               debug!("Skipping stale computer: {}",value.0.to_string());
+               break
+            }
             debug!("Trying to resolve FQDN: {}",value.0.to_string());
             // #todo: ()?; error management if failed message but dont exit program
             let result = resolver.lookup_ip(value.0.to_string());
@@ -0,0 +1,320 @@
+//! Local group memberships pushed by the GPOs (--sysvol)
+//!
+//! Without a SAMR session on every computer, the members of their local groups are known from the GPOs that
+//! set them, read from the local copy of SYSVOL:
+//! - Restricted Groups, `Machine\Microsoft\Windows NT\SecEdit\GptTmpl.inf` (UTF-16), `[Group Membership]` section:
+//!   `*S-1-5-32-555__Members = *S-1-5-21-...-1108` and `*S-1-5-21-...-1108__Memberof = *S-1-5-32-544`,
+//! - Group Policy Preferences, `Machine\Preferences\Groups\Groups.xml`: the members added (action ADD) to a group.
+//!
+//! The members of Administrators, Remote Desktop Users, Remote Management Users and Distributed COM Users get the
+//! AdminTo, CanRDP, CanPSRemote and ExecuteDCOM edges on every computer the GPO applies to: linked on the domain or
+//! an OU above the computer, not blocked by an OU blocking the inheritance unless the link is enforced.
+//! The GPOs are merged, the replacement of the members by a GPO of higher precedence and the security filtering
+//! are not evaluated. The memberships found are written in `<dirpath>/<domain>_gpo_local_groups.json`.
+use crate::banner::Colorize;
+use lazy_static::lazy_static;
+use log::{debug, info};
+use regex::Regex;
+use serde_json::value::Value;
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::json::checker::bh_41::parent_dn;
+
+/// Local groups giving an edge on the computer: (SID, name, computer property, edge).
+pub const LOCAL_GROUP_EDGES: [(&str, &str, &str, &str); 4] = [
+   ("S-1-5-32-544", "Administrators", "LocalAdmins", "AdminTo"),
+   ("S-1-5-32-555", "Remote Desktop Users", "RemoteDesktopUsers", "CanRDP"),
+   ("S-1-5-32-580", "Remote Management Users", "PSRemoteUsers", "CanPSRemote"),
+   ("S-1-5-32-562", "Distributed COM Users", "DcomUsers", "ExecuteDCOM"),
+];
+
+/// Containers DN => (links (GPO id, enforced), blocks inheritance).
+type GpoLinks = HashMap<String, (Vec<(String, bool)>, bool)>;
+
+lazy_static! {
+   static ref GPP_GROUP: Regex = Regex::new(r"(?is)<Group\s.*?</Group>").unwrap();
+   static ref GPP_PROPERTIES: Regex = Regex::new(r"(?is)<Properties\s[^>]*>").unwrap();
+   static ref GPP_MEMBER: Regex = Regex::new(r"(?is)<Member\s[^>]*>").unwrap();
+   static ref ATTRIBUTE: Regex = Regex::new(r#"\b(\w+)="([^"]*)""#).unwrap();
+   static ref GPO_GUID: Regex = Regex::new(r"(?i)\{[0-9a-f]{8}-[0-9a-f]{4}-[0-9a-f]{4}-[0-9a-f]{4}-[0-9a-f]{12}\}").unwrap();
+}
+
+/// Function to decode a policy file, UTF-16LE with a BOM (GptTmpl.inf) or UTF-8.
+pub fn decode_policy_file(data: &[u8]) -> String {
+   match data.strip_prefix(&[0xff, 0xfe]) {
+      Some(utf16) => {
+         let units: Vec<u16> = utf16.chunks_exact(2).map(|unit| u16::from_le_bytes([unit[0], unit[1]])).collect();
+         String::from_utf16_lossy(&units)
+      }
+      None => String::from_utf8_lossy(data).trim_start_matches('\u{feff}').to_string(),
+   }
+}
+
+/// Function to get the (local group, member) pairs of the [Group Membership] section of a GptTmpl.inf.
+pub fn parse_group_membership(inf: &str) -> Vec<(String, String)> {
+   let mut memberships: Vec<(String, String)> = Vec::new();
+   let mut in_section = false;
+   for line in inf.lines().map(|line| line.trim()) {
+      if line.starts_with('[') {
+         in_section = line.eq_ignore_ascii_case("[Group Membership]");
+         continue
+      }
+      let (key, values) = match line.split_once('=').filter(|_| in_section) {
+         Some((key, values)) => (key.trim(), values.trim()),
+         None => continue,
+      };
+      let values = values.split(',').map(|value| value.trim().trim_start_matches('*').to_string()).filter(|value| !value.is_empty());
+      let lower = key.to_lowercase();
+      if let Some(group) = lower.strip_suffix("__members").map(|_| key[..key.len() - 9].trim_start_matches('*')) {
+         memberships.extend(values.map(|member| (group.to_string(), member)));
+      } else if let Some(member) = lower.strip_suffix("__memberof").map(|_| key[..key.len() - 10].trim_start_matches('*')) {
+         memberships.extend(values.map(|group| (group, member.to_string())));
+      }
+   }
+   memberships
+}
+
+/// Function to get the (local group, member) pairs added by a Preferences Groups.xml.
+pub fn parse_groups_xml(xml: &str) -> Vec<(String, String)> {
+   let attribute = |tag: &str, name: &str| ATTRIBUTE.captures_iter(tag)
+      .find(|attribute| attribute[1].eq_ignore_ascii_case(name))
+      .map(|attribute| attribute[2].to_string())
+      .filter(|value| !value.is_empty());
+   let mut memberships: Vec<(String, String)> = Vec::new();
+   for group in GPP_GROUP.find_iter(xml).map(|group| group.as_str()) {
+      let properties = GPP_PROPERTIES.find(group).map(|tag| tag.as_str()).unwrap_or("");
+      let name = match attribute(properties, "groupSid").or_else(|| attribute(properties, "groupName")) {
+         Some(name) => name,
+         None => continue,
+      };
+      for member in GPP_MEMBER.find_iter(group).map(|member| member.as_str()) {
+         if !attribute(member, "action").map_or(false, |action| action.eq_ignore_ascii_case("ADD")) {
+            continue
+         }
+         if let Some(principal) = attribute(member, "sid").or_else(|| attribute(member, "name")) {
+            memberships.push((name.to_owned(), principal));
+         }
+      }
+   }
+   memberships
+}
+
+/// Function to get the computer property and the edge of a local group, by SID or name ("Administrators (built-in)").
+pub fn local_group_edge(group: &str) -> Option<(&'static str, &'static str)> {
+   let group = group.trim_end_matches(" (built-in)").trim_start_matches("BUILTIN\\");
+   LOCAL_GROUP_EDGES.iter()
+      .find(|(sid, name, _, _)| group.eq_ignore_ascii_case(sid) || group.eq_ignore_ascii_case(name))
+      .map(|(_, _, property, edge)| (*property, *edge))
+}
+
+/// Function to get the policy files setting local groups in a local copy of SYSVOL: (GPO folder GUID, file).
+fn policy_files(dir: &Path, files: &mut Vec<(String, PathBuf)>) -> std::io::Result<()> {
+   for entry in fs::read_dir(dir)? {
+      let path = entry?.path();
+      if path.is_dir() {
+         policy_files(&path, files)?;
+         continue
+      }
+      let display = path.display().to_string().replace('\\', "/").to_lowercase();
+      let name = path.file_name().map(|name| name.to_string_lossy().to_lowercase()).unwrap_or_default();
+      if (name == "gpttmpl.inf" || name == "groups.xml") && display.contains("/machine/") {
+         if let Some(guid) = GPO_GUID.find(&display) {
+            files.push((guid.as_str().to_uppercase(), path));
+         }
+      }
+   }
+   Ok(())
+}
+
+/// Function to resolve a member of a policy (SID or [DOMAIN\]name) in the collected principals: (SID, type).
+fn resolve_member(member: &str, domain: &str, principals: &[(&str, &[Value])]) -> Option<(String, String)> {
+   if member.to_uppercase().starts_with("S-1-") {
+      // Well-known SIDs are prefixed with the domain, like in the ACEs
+      let sid = if member.len() <= 16 { format!("{}-{}", domain.to_uppercase(), member.to_uppercase()) } else { member.to_uppercase() };
+      let object_type = principals.iter()
+         .find(|(_, objects)| objects.iter().any(|object| object["ObjectIdentifier"] == sid.as_str()))
+         .map(|(object_type, _)| object_type.to_string())
+         .unwrap_or_else(|| "Group".to_string());
+      return Some((sid, object_type))
+   }
+   let name = member.rsplit('\\').next().unwrap_or(member).to_uppercase();
+   principals.iter().find_map(|(object_type, objects)| objects.iter()
+      .find(|object| {
+         object["Properties"]["samaccountname"].as_str().map_or(false, |account| account.eq_ignore_ascii_case(&name))
+            || object["Properties"]["name"].as_str().and_then(|full| full.split('@').next()).map_or(false, |short| short == name)
+      })
+      .and_then(|object| object["ObjectIdentifier"].as_str())
+      .map(|sid| (sid.to_string(), object_type.to_string())))
+}
+
+/// Function to get the GPOs applied to a computer: the links of the containers above it, the links above an OU
+/// blocking the inheritance only if enforced.
+fn applied_gpos(computer_dn: &str, links: &GpoLinks) -> Vec<String> {
+   let mut gpos: Vec<String> = Vec::new();
+   let mut blocked = false;
+   let mut dn = parent_dn(computer_dn);
+   while let Some(container) = dn {
+      if let Some((container_links, blocks)) = links.get(&container.to_uppercase()) {
+         gpos.extend(container_links.iter().filter(|(_, enforced)| !blocked || *enforced).map(|(gpo, _)| gpo.to_owned()));
+         blocked |= *blocks;
+      }
+      dn = parent_dn(container);
+   }
+   gpos
+}
+
+/// Function to read the local groups set by the GPOs of a SYSVOL copy and add the edges on the computers
+/// they apply to, the members are searched in the principals and the computers. Return the memberships found.
+pub fn add_gpo_local_group_edges(
+   sysvol: &String,
+   domain: &String,
+   vec_computers: &mut [Value],
+   principals: &[(&str, &[Value])],
+   containers: &[&[Value]],
+   vec_gpos: &[Value],
+) -> std::io::Result<Vec<Value>> {
+   let mut files: Vec<(String, PathBuf)> = Vec::new();
+   policy_files(Path::new(sysvol), &mut files)?;
+   files.sort();
+
+   let mut links: GpoLinks = HashMap::new();
+   for container in containers.iter().flat_map(|objects| objects.iter()) {
+      let container_links = container["Links"].as_array().into_iter().flatten()
+         .filter_map(|link| link["GUID"].as_str().map(|gpo| (gpo.to_uppercase(), link["IsEnforced"].as_bool().unwrap_or(false))))
+         .collect();
+      let blocks = container["Properties"]["blocksinheritance"].as_bool().unwrap_or(false);
+      if let Some(dn) = container["Properties"]["distinguishedname"].as_str() {
+         links.insert(dn.to_uppercase(), (container_links, blocks));
+      }
+   }
+
+   let mut principals = principals.to_vec();
+   principals.push(("Computer", &*vec_computers));
+   let mut memberships: Vec<Value> = Vec::new();
+   // GPO id => [(computer property, member SID, member type)]
+   let mut gpo_members: HashMap<String, Vec<(&'static str, String, String)>> = HashMap::new();
+   for (guid, file) in files {
+      let content = match fs::read(&file) {
+         Ok(data) => decode_policy_file(&data),
+         Err(err) => {
+            debug!("Ignoring {}: {}", file.display(), err);
+            continue
+         }
+      };
+      let pairs = if file.extension().map_or(false, |extension| extension.eq_ignore_ascii_case("inf")) { parse_group_membership(&content) } else { parse_groups_xml(&content) };
+      let gpo = vec_gpos.iter().find(|gpo| gpo["Properties"]["gpcpath"].as_str().map_or(false, |gpcpath| gpcpath.to_uppercase().contains(&guid)));
+      let gpo_id = gpo.and_then(|gpo| gpo["ObjectIdentifier"].as_str()).unwrap_or(&guid[1..guid.len() - 1]).to_uppercase();
+      for (group, member) in pairs {
+         let (property, edge) = match local_group_edge(&group) {
+            Some(edge) => edge,
+            None => continue,
+         };
+         let resolved = resolve_member(&member, domain, &principals);
+         memberships.push(serde_json::json!({
+            "gpo": gpo.map(|gpo| gpo["Properties"]["name"].to_owned()).unwrap_or(Value::Null),
+            "gpoid": gpo_id,
+            "file": file.display().to_string(),
+            "localgroup": group,
+            "edge": edge,
+            "member": member,
+            "memberid": resolved.as_ref().map(|(sid, _)| sid.to_owned()),
+         }));
+         if let Some((sid, object_type)) = resolved {
+            gpo_members.entry(gpo_id.to_owned()).or_default().push((property, sid, object_type));
+         }
+      }
+   }
+
+   let mut edges = 0;
+   for computer in vec_computers.iter_mut() {
+      let dn = computer["Properties"]["distinguishedname"].as_str().unwrap_or("").to_uppercase();
+      for gpo in applied_gpos(&dn, &links) {
+         for (property, sid, object_type) in gpo_members.get(&gpo).into_iter().flatten() {
+            let member = serde_json::json!({ "ObjectIdentifier": sid, "ObjectType": object_type });
+            if let Some(results) = computer[*property]["Results"].as_array_mut().filter(|results| !results.contains(&member)) {
+               results.push(member);
+               edges += 1;
+            }
+            computer[*property]["Collected"] = true.into();
+         }
+      }
+   }
+   info!("{} AdminTo, CanRDP, CanPSRemote and ExecuteDCOM edges from the GPO local groups", edges.to_string().bold());
+   Ok(memberships)
+}
+
+/// Function to add the edges of the GPO local groups and write the memberships found.
+pub fn make_gpo_local_groups_report(
+   path: &String,
+   domain: &String,
+   sysvol: &String,
+   vec_computers: &mut [Value],
+   principals: &[(&str, &[Value])],
+   containers: &[&[Value]],
+   vec_gpos: &[Value],
+) -> std::io::Result<()> {
+   let memberships = add_gpo_local_group_edges(sysvol, domain, vec_computers, principals, containers, vec_gpos)?;
+   fs::create_dir_all(path)?;
+   let final_path = format!("{}/{}_gpo_local_groups.json", path, domain.replace(".", "-").to_lowercase());
+   fs::write(&final_path, serde_json::to_string_pretty(&memberships).unwrap_or_default())?;
+   info!("{} created!", final_path.bold());
+   Ok(())
+}
+
+/// Test functions
+#[test]
+pub fn test_gpo_local_groups() {
+   let inf = "[Unicode]\r\nUnicode=yes\r\n[Group Membership]\r\n*S-1-5-32-555__Memberof =\r\n*S-1-5-32-555__Members = *S-1-5-21-1-2-3-1108, ESSOS\\jon.snow\r\n*S-1-5-21-1-2-3-1109__Memberof = *S-1-5-32-544\r\n[Version]\r\nsignature=\"$CHICAGO$\"\r\n";
+   let mut utf16: Vec<u8> = vec![0xff, 0xfe];
+   utf16.extend(inf.encode_utf16().flat_map(|unit| unit.to_le_bytes()));
+   assert_eq!(decode_policy_file(&utf16), inf);
+   assert_eq!(parse_group_membership(inf), vec![
+      ("S-1-5-32-555".to_string(), "S-1-5-21-1-2-3-1108".to_string()),
+      ("S-1-5-32-555".to_string(), "ESSOS\\jon.snow".to_string()),
+      ("S-1-5-32-544".to_string(), "S-1-5-21-1-2-3-1109".to_string()),
+   ]);
+   let xml = r#"<?xml version="1.0" encoding="utf-8"?><Groups clsid="{3125E937-EB16-4b4c-9934-544FC6D24D26}">
+<Group clsid="{6D4A79E4-529C-4481-ABD0-F5BD7EA93BA7}" name="Remote Management Users (built-in)"><Properties action="U" groupSid="S-1-5-32-580" groupName="Remote Management Users (built-in)">
+<Members><Member name="ESSOS\Servers Ops" action="ADD" sid="S-1-5-21-1-2-3-1110"/><Member name="ESSOS\Old" action="REMOVE" sid="S-1-5-21-1-2-3-1111"/></Members></Properties></Group></Groups>"#;
+   assert_eq!(parse_groups_xml(xml), vec![("S-1-5-32-580".to_string(), "S-1-5-21-1-2-3-1110".to_string())]);
+   assert_eq!(local_group_edge("Administrators (built-in)"), Some(("LocalAdmins", "AdminTo")));
+   assert_eq!(local_group_edge("S-1-5-32-545"), None);
+
+   let dir = std::env::temp_dir().join(format!("rusthound_gpo_groups_{}", std::process::id()));
+   let gpo_dir = dir.join("essos.local/Policies/{6AC1786C-016F-11D2-945F-00C04FB984F9}/Machine");
+   fs::create_dir_all(gpo_dir.join("Microsoft/Windows NT/SecEdit")).unwrap();
+   fs::create_dir_all(gpo_dir.join("Preferences/Groups")).unwrap();
+   fs::write(gpo_dir.join("Microsoft/Windows NT/SecEdit/GptTmpl.inf"), &utf16).unwrap();
+   fs::write(gpo_dir.join("Preferences/Groups/Groups.xml"), xml).unwrap();
+   let vec_gpos = vec![serde_json::json!({"ObjectIdentifier": "8A1F6D3C-1E2B-4C5D-9E8F-0A1B2C3D4E5F", "Properties": {"name": "SERVERS ACCESS@ESSOS.LOCAL",
+      "gpcpath": "\\\\essos.local\\sysvol\\essos.local\\Policies\\{6AC1786C-016F-11D2-945F-00C04FB984F9}"}})];
+   let vec_users = vec![serde_json::json!({"ObjectIdentifier": "S-1-5-21-1-2-3-1105", "Properties": {"name": "JON.SNOW@ESSOS.LOCAL", "samaccountname": "JON.SNOW"}})];
+   let vec_groups = vec![serde_json::json!({"ObjectIdentifier": "S-1-5-21-1-2-3-1109", "Properties": {"name": "SERVER ADMINS@ESSOS.LOCAL"}})];
+   let vec_ous = vec![
+      serde_json::json!({"Links": [{"GUID": "8A1F6D3C-1E2B-4C5D-9E8F-0A1B2C3D4E5F", "IsEnforced": false}], "Properties": {"distinguishedname": "OU=SERVERS,DC=ESSOS,DC=LOCAL", "blocksinheritance": false}}),
+      serde_json::json!({"Links": [], "Properties": {"distinguishedname": "OU=ISOLATED,OU=SERVERS,DC=ESSOS,DC=LOCAL", "blocksinheritance": true}}),
+   ];
+   let vec_domains = vec![serde_json::json!({"Links": [], "Properties": {"distinguishedname": "DC=ESSOS,DC=LOCAL"}})];
+   let local_groups = serde_json::json!({"Results": [], "Collected": false, "FailureReason": null});
+   let mut vec_computers: Vec<Value> = ["CN=BRAAVOS,OU=SERVERS,DC=ESSOS,DC=LOCAL", "CN=LYS,OU=ISOLATED,OU=SERVERS,DC=ESSOS,DC=LOCAL", "CN=WS01,CN=COMPUTERS,DC=ESSOS,DC=LOCAL"].iter()
+      .map(|dn| serde_json::json!({"Properties": {"distinguishedname": dn}, "LocalAdmins": local_groups, "RemoteDesktopUsers": local_groups, "PSRemoteUsers": local_groups, "DcomUsers": local_groups}))
+      .collect();
+   let principals: [(&str, &[Value]); 2] = [("User", &vec_users), ("Group", &vec_groups)];
+   let memberships = add_gpo_local_group_edges(&dir.display().to_string(), &"essos.local".to_string(), &mut vec_computers, &principals, &[&vec_ous, &vec_domains], &vec_gpos).unwrap();
+   fs::remove_dir_all(&dir).unwrap();
+
+   assert_eq!(memberships.len(), 4);
+   assert_eq!(memberships[0]["gpo"], "SERVERS ACCESS@ESSOS.LOCAL");
+   assert_eq!(vec_computers[0]["RemoteDesktopUsers"]["Results"], serde_json::json!([
+      {"ObjectIdentifier": "S-1-5-21-1-2-3-1108", "ObjectType": "Group"},
+      {"ObjectIdentifier": "S-1-5-21-1-2-3-1105", "ObjectType": "User"},
+   ]));
+   assert_eq!(vec_computers[0]["LocalAdmins"]["Results"], serde_json::json!([{"ObjectIdentifier": "S-1-5-21-1-2-3-1109", "ObjectType": "Group"}]));
+   assert_eq!(vec_computers[0]["PSRemoteUsers"]["Results"][0]["ObjectIdentifier"], "S-1-5-21-1-2-3-1110");
+   assert_eq!(vec_computers[0]["PSRemoteUsers"]["Collected"], true);
+   // Inheritance blocked, not linked on the computers container
+   assert_eq!(vec_computers[1]["LocalAdmins"]["Results"], serde_json::json!([]));
+   assert_eq!(vec_computers[2]["LocalAdmins"]["Collected"], false);
+}
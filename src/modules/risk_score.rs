@@ -0,0 +1,206 @@
+//! Per-node risk scoring summary (--risk-scores)
+//!
+//! Ranks every object before the data reaches a graph database:
+//! - inbound control edges (ACL edges on the object) weighted by the privilege of the source,
+//!   control granted to broad groups weights more than control granted to Tier 0,
+//! - outbound reach toward Tier 0, the number of hops (ACL edges and group memberships) from the object to a Tier 0 object.
+//!
+//! The result is written in `<dirpath>/<domain>_risk_scores.csv`.
+use crate::banner::Colorize;
+use log::{debug, info};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::fs;
+
+use crate::modules::synthetic_edges::is_domain_controller;
+use crate::json::maker::csv::csv_field;
+
+/// Well-known RIDs of the Tier 0 groups (Domain Admins, Domain Controllers, Schema Admins, Enterprise Admins,
+/// Key Admins, Enterprise Key Admins, Administrators, Account/Server/Print/Backup Operators).
+const TIER0_RIDS: [&str; 11] = ["-512", "-516", "-518", "-519", "-526", "-527", "-544", "-548", "-549", "-550", "-551"];
+
+/// Broad groups (Everyone, Authenticated Users, Domain Users, Domain Computers, Users).
+const BROAD_SIDS: [&str; 5] = ["S-1-1-0", "S-1-5-11", "-513", "-515", "S-1-5-32-545"];
+
+/// Weight of one inbound control edge from its source.
+const WEIGHT_BROAD: f64 = 5.0;
+const WEIGHT_REGULAR: f64 = 1.0;
+const WEIGHT_TIER0: f64 = 0.1;
+
+/// Score of the outbound reach, divided by the number of hops to Tier 0.
+const REACH_SCORE: f64 = 10.0;
+
+/// Score of one object.
+#[derive(Debug, Clone)]
+pub struct NodeScore {
+   pub object_identifier: String,
+   pub name: String,
+   pub object_type: String,
+   pub tier0: bool,
+   pub inbound_control: usize,
+   pub weighted_inbound: f64,
+   pub hops_to_tier0: Option<usize>,
+   pub score: f64,
+}
+
+/// Function to compute the score of every object, ranked by score.
+pub fn compute_risk_scores(vec_objects: &[(&str, &Vec<serde_json::value::Value>)]) -> Vec<NodeScore> {
+   let empty: Vec<serde_json::value::Value> = Vec::new();
+   let mut nodes: HashMap<String, NodeScore> = HashMap::new();
+   // Outbound edges: source => targets
+   let mut outbound: HashMap<String, HashSet<String>> = HashMap::new();
+
+   for (object_type, objects) in vec_objects {
+      for object in objects.iter() {
+         let id = match object["ObjectIdentifier"].as_str() {
+            Some(id) => id.to_string(),
+            None => continue,
+         };
+         let tier0 = is_tier0(object_type, &id, object);
+         nodes.insert(id.to_owned(), NodeScore {
+            object_identifier: id.to_owned(),
+            name: object["Properties"]["name"].as_str().unwrap_or("").to_string(),
+            object_type: object_type.to_string(),
+            tier0,
+            inbound_control: 0,
+            weighted_inbound: 0.0,
+            hops_to_tier0: None,
+            score: 0.0,
+         });
+         for ace in object["Aces"].as_array().unwrap_or(&empty) {
+            if let Some(source) = ace["PrincipalSID"].as_str() {
+               outbound.entry(source.to_string()).or_default().insert(id.to_owned());
+            }
+         }
+         for member in object["Members"].as_array().unwrap_or(&empty) {
+            if let Some(source) = member["ObjectIdentifier"].as_str() {
+               outbound.entry(source.to_string()).or_default().insert(id.to_owned());
+            }
+         }
+      }
+   }
+
+   // Inbound control weighted by the source privilege
+   let tier0: HashSet<String> = nodes.values().filter(|node| node.tier0).map(|node| node.object_identifier.to_owned()).collect();
+   for (_, objects) in vec_objects {
+      for object in objects.iter() {
+         let id = object["ObjectIdentifier"].as_str().unwrap_or("");
+         let mut sources: HashSet<&str> = HashSet::new();
+         for ace in object["Aces"].as_array().unwrap_or(&empty) {
+            if let Some(source) = ace["PrincipalSID"].as_str() {
+               if source != id {
+                  sources.insert(source);
+               }
+            }
+         }
+         if let Some(node) = nodes.get_mut(id) {
+            node.inbound_control = sources.len();
+            node.weighted_inbound = sources.iter().map(|source| source_weight(source, &tier0)).sum();
+         }
+      }
+   }
+
+   // Hops to Tier 0, breadth-first search from Tier 0 on the reversed edges
+   let mut inbound: HashMap<&str, Vec<&str>> = HashMap::new();
+   for (source, targets) in &outbound {
+      for target in targets {
+         inbound.entry(target.as_str()).or_default().push(source.as_str());
+      }
+   }
+   let mut hops: HashMap<&str, usize> = HashMap::new();
+   let mut queue: VecDeque<&str> = VecDeque::new();
+   for id in &tier0 {
+      hops.insert(id.as_str(), 0);
+      queue.push_back(id.as_str());
+   }
+   while let Some(target) = queue.pop_front() {
+      let distance = hops[target];
+      for source in inbound.get(target).unwrap_or(&Vec::new()) {
+         if !hops.contains_key(source) {
+            hops.insert(source, distance + 1);
+            queue.push_back(source);
+         }
+      }
+   }
+
+   let mut scores: Vec<NodeScore> = nodes.into_iter().map(|(id, mut node)| {
+      node.hops_to_tier0 = hops.get(id.as_str()).copied();
+      let reach = match node.hops_to_tier0 {
+         Some(distance) if distance > 0 => REACH_SCORE / distance as f64,
+         _ => 0.0,
+      };
+      node.score = node.weighted_inbound + reach;
+      node
+   }).collect();
+   scores.sort_by(|a, b| b.score.partial_cmp(&a.score).unwrap_or(std::cmp::Ordering::Equal).then(a.name.cmp(&b.name)));
+   scores
+}
+
+/// Function to write the ranked scores in a CSV file.
+pub fn make_risk_scores(path: &String, domain: &String, vec_objects: &[(&str, &Vec<serde_json::value::Value>)]) -> std::io::Result<()> {
+   let scores = compute_risk_scores(vec_objects);
+   debug!("{} objects scored", scores.len());
+
+   let mut csv = String::from("rank,score,name,type,objectidentifier,tier0,inbound_control,weighted_inbound,hops_to_tier0\n");
+   for (rank, node) in scores.iter().enumerate() {
+      csv.push_str(&format!(
+         "{},{:.2},{},{},{},{},{},{:.2},{}\n",
+         rank + 1,
+         node.score,
+         csv_field(&node.name),
+         node.object_type,
+         csv_field(&node.object_identifier),
+         node.tier0,
+         node.inbound_control,
+         node.weighted_inbound,
+         node.hops_to_tier0.map(|hops| hops.to_string()).unwrap_or_default(),
+      ));
+   }
+
+   fs::create_dir_all(path)?;
+   let final_path = format!("{}/{}_risk_scores.csv", path, domain.replace(".", "-").to_lowercase());
+   fs::write(&final_path, csv)?;
+   info!("{} created!", final_path.bold());
+   Ok(())
+}
+
+/// Tier 0: domains, domain controllers and the well-known administrative groups.
+pub fn is_tier0(object_type: &str, id: &String, object: &serde_json::value::Value) -> bool {
+   match object_type {
+      "Domain" => true,
+      "Computer" => is_domain_controller(object),
+      "Group" => TIER0_RIDS.iter().any(|rid| id.ends_with(rid)),
+      _ => false,
+   }
+}
+
+fn source_weight(source: &str, tier0: &HashSet<String>) -> f64 {
+   if tier0.contains(source) {
+      WEIGHT_TIER0
+   } else if BROAD_SIDS.iter().any(|sid| source.ends_with(sid)) {
+      WEIGHT_BROAD
+   } else {
+      WEIGHT_REGULAR
+   }
+}
+
+/// Test functions
+#[test]
+pub fn test_compute_risk_scores() {
+   let vec_groups = vec![
+      serde_json::json!({"ObjectIdentifier": "S-1-5-21-1-2-3-512", "Properties": {"name": "DOMAIN ADMINS@ESSOS.LOCAL"}, "Aces": [],
+         "Members": [{"ObjectIdentifier": "S-1-5-21-1-2-3-1104", "ObjectType": "User"}]}),
+   ];
+   let vec_users = vec![
+      serde_json::json!({"ObjectIdentifier": "S-1-5-21-1-2-3-1104", "Properties": {"name": "DAENERYS@ESSOS.LOCAL"},
+         "Aces": [{"RightName": "GenericAll", "PrincipalSID": "S-1-5-21-1-2-3-513"}, {"RightName": "GenericAll", "PrincipalSID": "S-1-5-21-1-2-3-512"}]}),
+      serde_json::json!({"ObjectIdentifier": "S-1-5-21-1-2-3-1105", "Properties": {"name": "VISERYS@ESSOS.LOCAL"},
+         "Aces": [{"RightName": "GenericAll", "PrincipalSID": "S-1-5-21-1-2-3-512"}]}),
+   ];
+   let scores = compute_risk_scores(&[("User", &vec_users), ("Group", &vec_groups)]);
+   assert_eq!(scores[0].name, "DAENERYS@ESSOS.LOCAL");
+   assert_eq!(scores[0].hops_to_tier0, Some(1));
+   assert_eq!(scores[0].inbound_control, 2);
+   assert!((scores[0].score - (WEIGHT_BROAD + WEIGHT_TIER0 + REACH_SCORE)).abs() < 1e-9);
+   assert_eq!(scores.iter().find(|node| node.name == "VISERYS@ESSOS.LOCAL").unwrap().hops_to_tier0, None);
+   assert_eq!(csv_field(&"A,B".to_string()), "\"A,B\"");
+}
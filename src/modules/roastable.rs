@@ -0,0 +1,135 @@
+//! Kerberos roastable and weak credential accounts report (--reports)
+//!
+//! Lists the user accounts:
+//! - with a servicePrincipalName (Kerberoasting), krbtgt and the machine accounts are excluded,
+//! - with DONT_REQ_PREAUTH (AS-REP roasting),
+//! - with ENCRYPTED_TEXT_PWD_ALLOWED (password stored with reversible encryption),
+//! - with USE_DES_KEY_ONLY or only DES in msDS-SupportedEncryptionTypes (DES-only Kerberos keys).
+//!
+//! The result is printed as a table and written in `<dirpath>/<domain>_roastable.json`.
+use crate::banner::Colorize;
+use log::info;
+use std::fs;
+use std::io::Write;
+
+use crate::json::maker::json_to_string;
+
+/// Report categories: (JSON key, table label, user property).
+const CATEGORIES: [(&str, &str, &str); 4] = [
+   ("kerberoastable", "Kerberoastable", "hasspn"),
+   ("asreproastable", "AS-REP roastable", "dontreqpreauth"),
+   ("reversibleencryption", "Reversible encryption", "reversibleencryption"),
+   ("desonly", "DES only", "usedeskeyonly"),
+];
+
+/// Function to know if a user is in one category, DES-only is the UAC flag or the supported etypes.
+fn in_category(user: &serde_json::value::Value, category: &str, property: &str) -> bool {
+   user["Properties"][property].as_bool().unwrap_or(false)
+      || (category == "desonly" && user["Properties"]["desonly"].as_bool().unwrap_or(false))
+}
+
+/// One account of the report.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RoastableAccount {
+   pub category: &'static str,
+   pub name: String,
+   pub object_identifier: String,
+   pub enabled: bool,
+   pub admincount: bool,
+   pub serviceprincipalnames: Vec<String>,
+}
+
+/// Function to know if a user is krbtgt or a machine account (gMSA, trust account...).
+fn is_excluded_from_kerberoast(user: &serde_json::value::Value) -> bool {
+   let samaccountname = user["Properties"]["samaccountname"].as_str().unwrap_or("");
+   samaccountname.eq_ignore_ascii_case("krbtgt") || samaccountname.ends_with('$')
+}
+
+/// Function to list the roastable and weak credential accounts.
+pub fn roastable_accounts(vec_users: &Vec<serde_json::value::Value>) -> Vec<RoastableAccount> {
+   let mut accounts: Vec<RoastableAccount> = Vec::new();
+   for (category, _, property) in CATEGORIES {
+      for user in vec_users.iter().filter(|user| in_category(user, category, property)) {
+         if category == "kerberoastable" && is_excluded_from_kerberoast(user) {
+            continue
+         }
+         accounts.push(RoastableAccount {
+            category,
+            name: user["Properties"]["name"].as_str().unwrap_or("").to_string(),
+            object_identifier: user["ObjectIdentifier"].as_str().unwrap_or("").to_string(),
+            enabled: user["Properties"]["enabled"].as_bool().unwrap_or(true),
+            admincount: user["Properties"]["admincount"].as_bool().unwrap_or(false),
+            serviceprincipalnames: user["Properties"]["serviceprincipalnames"].as_array().into_iter().flatten()
+               .filter_map(|spn| spn.as_str().map(|spn| spn.to_string()))
+               .collect(),
+         });
+      }
+   }
+   accounts
+}
+
+/// Function to print the report table in the tables output and write the roastable JSON file.
+pub fn make_roastable_report(
+   path: &String,
+   domain: &String,
+   vec_users: &Vec<serde_json::value::Value>,
+   pretty: bool,
+   tables: &mut dyn Write,
+) -> std::io::Result<()> {
+   let accounts = roastable_accounts(vec_users);
+
+   let width = accounts.iter().map(|account| account.name.len()).max().unwrap_or(0).max(4);
+   writeln!(tables, "\n{:<22} {:<width$} {:<8} {:<10} {}", "CATEGORY", "NAME", "ENABLED", "ADMINCOUNT", "SPN", width = width)?;
+   let mut json = serde_json::json!({});
+   for (category, label, _) in CATEGORIES {
+      let mut entries: Vec<serde_json::value::Value> = Vec::new();
+      for account in accounts.iter().filter(|account| account.category == category) {
+         writeln!(
+            tables,
+            "{:<22} {} {:<8} {:<10} {}",
+            label,
+            // Padding before the colors, the escape codes would break the alignment
+            if account.enabled { format!("{:<width$}", account.name, width = width).red().bold() } else { format!("{:<width$}", account.name, width = width).normal() },
+            account.enabled,
+            account.admincount,
+            account.serviceprincipalnames.first().map(|spn| spn.as_str()).unwrap_or(""),
+         )?;
+         entries.push(serde_json::json!({
+            "name": account.name,
+            "objectid": account.object_identifier,
+            "enabled": account.enabled,
+            "admincount": account.admincount,
+            "serviceprincipalnames": account.serviceprincipalnames,
+         }));
+      }
+      info!("{} {} accounts", entries.len().to_string().bold(), label);
+      json[category] = entries.into();
+   }
+
+   fs::create_dir_all(path)?;
+   let final_path = format!("{}/{}_roastable.json", path, domain.replace(".", "-").to_lowercase());
+   fs::write(&final_path, json_to_string(&mut json, pretty))?;
+   info!("{} created!", final_path.bold());
+   Ok(())
+}
+
+/// Test functions
+#[test]
+pub fn test_roastable_accounts() {
+   let vec_users = vec![
+      serde_json::json!({"ObjectIdentifier": "S-1-5-21-1-2-3-502", "Properties": {"name": "KRBTGT@ESSOS.LOCAL", "samaccountname": "KRBTGT",
+         "hasspn": true, "serviceprincipalnames": ["kadmin/changepw"], "enabled": false}}),
+      serde_json::json!({"ObjectIdentifier": "S-1-5-21-1-2-3-1110", "Properties": {"name": "SQL_SVC@ESSOS.LOCAL", "samaccountname": "SQL_SVC",
+         "hasspn": true, "serviceprincipalnames": ["MSSQLSvc/braavos.essos.local:1433"], "enabled": true}}),
+      serde_json::json!({"ObjectIdentifier": "S-1-5-21-1-2-3-1111", "Properties": {"name": "GMSA$@ESSOS.LOCAL", "samaccountname": "GMSA$",
+         "hasspn": true, "serviceprincipalnames": ["HTTP/web.essos.local"], "enabled": true}}),
+      serde_json::json!({"ObjectIdentifier": "S-1-5-21-1-2-3-1112", "Properties": {"name": "MISSANDEI@ESSOS.LOCAL", "samaccountname": "MISSANDEI",
+         "dontreqpreauth": true, "usedeskeyonly": true, "enabled": true}}),
+   ];
+   let accounts = roastable_accounts(&vec_users);
+   assert_eq!(accounts.len(), 3);
+   assert_eq!(accounts[0].category, "kerberoastable");
+   assert_eq!(accounts[0].name, "SQL_SVC@ESSOS.LOCAL");
+   assert_eq!(accounts[1].category, "asreproastable");
+   assert_eq!(accounts[2].category, "desonly");
+}
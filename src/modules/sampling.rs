@@ -0,0 +1,105 @@
+//! Sampling mode for massive directories
+//!
+//! With `--sample N%` only N% of the regular users and computers are kept.
+//! Privileged and infrastructure objects (adminCount=1, domain controllers, krbtgt,
+//! delegation, managed service accounts) and every other object class are always collected.
+//! The choice is made on a FNV-1a hash of the distinguishedName so two runs keep the same objects,
+//! whatever the Rust release used to build RustHound.
+use ldap3::SearchEntry;
+
+use crate::enums::uacflags::{get_flag, parse_flags};
+
+/// Privileged primaryGroupID: Domain Admins, Domain Controllers, Schema Admins, Enterprise Admins, RODC, Enterprise RODC.
+const PRIVILEGED_PRIMARY_GROUP_ID: [&str; 6] = ["512", "516", "518", "519", "521", "498"];
+
+/// Function to parse the --sample value like "10%" or "10".
+pub fn parse_sample(value: &str) -> Option<u8> {
+   match value.trim().trim_end_matches('%').parse::<u8>() {
+      Ok(percent) if percent > 0 && percent <= 100 => Some(percent),
+      _ => None,
+   }
+}
+
+/// Function to know if one LDAP entry need to be collected for this sample percentage.
+pub fn keep_sampled_entry(entry: &SearchEntry, percent: u8) -> bool {
+   if percent >= 100 || !is_regular_account(entry) {
+      return true
+   }
+   (fnv1a(entry.dn.to_lowercase().as_bytes()) % 100) < percent as u64
+}
+
+/// Function to hash the DN with 64-bit FNV-1a, a fixed algorithm unlike the std DefaultHasher.
+fn fnv1a(bytes: &[u8]) -> u64 {
+   bytes.iter().fold(0xcbf29ce484222325, |hash, byte| (hash ^ *byte as u64).wrapping_mul(0x100000001b3))
+}
+
+/// Function to check if the entry is a user or computer without any privileged marker.
+fn is_regular_account(entry: &SearchEntry) -> bool {
+   let classes = match entry.attrs.get("objectClass") {
+      Some(classes) => classes,
+      None => return false,
+   };
+   if !classes.iter().any(|class| class == "user")
+      || classes.iter().any(|class| class.starts_with("msDS-") && class.ends_with("ManagedServiceAccount"))
+   {
+      return false
+   }
+   if entry.attrs.get("adminCount").map(|v| v[0] == "1").unwrap_or(false)
+      || entry.attrs.contains_key("msDS-AllowedToDelegateTo")
+      || entry.bin_attrs.contains_key("msDS-AllowedToActOnBehalfOfOtherIdentity")
+   {
+      return false
+   }
+   if let Some(pgid) = entry.attrs.get("primaryGroupID") {
+      if PRIVILEGED_PRIMARY_GROUP_ID.contains(&pgid[0].as_str()) {
+         return false
+      }
+   }
+   if let Some(name) = entry.attrs.get("sAMAccountName") {
+      if name[0].to_lowercase() == "krbtgt" {
+         return false
+      }
+   }
+   if let Some(uac) = entry.attrs.get("userAccountControl") {
+      let flags = get_flag(parse_flags(&uac[0]));
+      if flags.iter().any(|flag| flag == "TrustedForDelegation" || flag == "TrustedToAuthForDelegation" || flag == "ServerTrustAccount") {
+         return false
+      }
+   }
+   true
+}
+
+/// Test functions
+#[cfg(test)]
+fn entry(dn: &str, attrs: Vec<(&str, &str)>) -> SearchEntry {
+   let mut map: std::collections::HashMap<String, Vec<String>> = std::collections::HashMap::new();
+   for (key, value) in attrs {
+      map.entry(key.to_string()).or_insert_with(Vec::new).push(value.to_string());
+   }
+   SearchEntry { dn: dn.to_string(), attrs: map, bin_attrs: std::collections::HashMap::new() }
+}
+
+#[test]
+pub fn test_sampling() {
+   assert_eq!(parse_sample("10%"), Some(10));
+   assert_eq!(parse_sample("100"), Some(100));
+   assert_eq!(parse_sample("0%"), None);
+   assert_eq!(parse_sample("abc"), None);
+
+   let admin = entry("CN=ADMIN,DC=ESSOS,DC=LOCAL", vec![("objectClass", "top"), ("objectClass", "user"), ("adminCount", "1")]);
+   let dc = entry("CN=MEEREEN,DC=ESSOS,DC=LOCAL", vec![("objectClass", "user"), ("objectClass", "computer"), ("primaryGroupID", "516")]);
+   let group = entry("CN=DRAGONS,DC=ESSOS,DC=LOCAL", vec![("objectClass", "group")]);
+   assert!(keep_sampled_entry(&admin, 1));
+   assert!(keep_sampled_entry(&dc, 1));
+   assert!(keep_sampled_entry(&group, 1));
+
+   let users: Vec<SearchEntry> = (0..1000)
+      .map(|i| entry(&format!("CN=USER{},DC=ESSOS,DC=LOCAL", i), vec![("objectClass", "user"), ("userAccountControl", "512")]))
+      .collect();
+   let kept = users.iter().filter(|user| keep_sampled_entry(user, 10)).count();
+   assert!(kept > 50 && kept < 150);
+   assert_eq!(kept, users.iter().filter(|user| keep_sampled_entry(user, 10)).count());
+   // Same sample whatever the toolchain
+   assert_eq!(fnv1a(b"cn=user0,dc=essos,dc=local"), 4700613772741678670);
+   assert!(keep_sampled_entry(&users[0], 71) && !keep_sampled_entry(&users[0], 70));
+}
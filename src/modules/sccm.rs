@@ -0,0 +1,168 @@
+//! SCCM (MECM) infrastructure discovery (--sccm)
+//!
+//! SCCM publishes its sites and management points in `CN=System Management,CN=System` of the domain when the
+//! schema is extended, and every site server is granted GenericAll on this container to publish them.
+//! The roles found are set on the computers (`sccmroles` property) and written in `<dirpath>/<domain>_sccm.json`:
+//! - SiteServer: computer with GenericAll on CN=System Management,
+//! - ManagementPoint: host of a mSSMSManagementPoint object,
+//! - PxeServer: computer with a PXE service point (intellimirrorSCP), the PXE enabled distribution points and WDS,
+//! - Candidate: computer with SCCM, MECM or ConfigMgr in a SPN, its name or description.
+//!
+//! The distribution points without PXE are not published in the directory and can not be found by LDAP.
+use crate::banner::Colorize;
+use lazy_static::lazy_static;
+use log::info;
+use serde_json::value::Value;
+use std::fs;
+use std::sync::Mutex;
+
+/// Words of the SPNs, names and descriptions of the SCCM servers.
+pub const SCCM_KEYWORDS: [&str; 3] = ["SCCM", "MECM", "CONFIGMGR"];
+/// Name of the container of the SCCM objects.
+pub const SYSTEM_MANAGEMENT_CONTAINER: &str = "CN=SYSTEM MANAGEMENT,CN=SYSTEM,";
+
+lazy_static! {
+   static ref SCCM_OBJECTS: Mutex<Vec<Value>> = Mutex::new(Vec::new());
+}
+
+/// Function to record one SCCM object found by the parser.
+pub fn record_sccm_object(object: Value) {
+   if let Ok(mut objects) = SCCM_OBJECTS.lock() {
+      objects.push(object);
+   }
+}
+
+/// Function to get the SCCM objects of the run.
+pub fn sccm_objects() -> Vec<Value> {
+   SCCM_OBJECTS.lock().map(|objects| objects.to_owned()).unwrap_or_default()
+}
+
+fn add_role(computer: &mut Value, role: &str) {
+   if !computer["Properties"]["sccmroles"].is_array() {
+      computer["Properties"]["sccmroles"] = Vec::<Value>::new().into();
+   }
+   if let Some(roles) = computer["Properties"]["sccmroles"].as_array_mut() {
+      if !roles.iter().any(|known| known == role) {
+         roles.push(role.into());
+      }
+   }
+}
+
+fn has_keyword(computer: &Value) -> bool {
+   let mut texts: Vec<String> = computer["Properties"]["serviceprincipalnames"].as_array().into_iter().flatten()
+      .filter_map(|spn| spn.as_str().map(|spn| spn.to_string()))
+      .collect();
+   for property in ["name", "description"] {
+      texts.extend(computer["Properties"][property].as_str().map(|text| text.to_string()));
+   }
+   texts.iter().any(|text| SCCM_KEYWORDS.iter().any(|keyword| text.to_uppercase().contains(keyword)))
+}
+
+/// Function to set the SCCM roles on the computers, return the report.
+pub fn sccm_roles(objects: &[Value], vec_computers: &mut [Value], vec_containers: &[Value]) -> Value {
+   // Site servers: GenericAll on CN=System Management
+   let site_servers: Vec<String> = vec_containers.iter()
+      .filter(|container| container["Properties"]["distinguishedname"].as_str()
+         .map(|dn| dn.to_uppercase().starts_with(SYSTEM_MANAGEMENT_CONTAINER))
+         .unwrap_or(false))
+      .flat_map(|container| container["Aces"].as_array().cloned().unwrap_or_default())
+      .filter(|ace| ace["RightName"] == "GenericAll" && ace["PrincipalType"] == "Computer")
+      .filter_map(|ace| ace["PrincipalSID"].as_str().map(|sid| sid.to_string()))
+      .collect();
+
+   for computer in vec_computers.iter_mut() {
+      let sid = computer["ObjectIdentifier"].as_str().unwrap_or("").to_string();
+      let name = computer["Properties"]["name"].as_str().unwrap_or("").to_uppercase();
+      let dn = computer["Properties"]["distinguishedname"].as_str().unwrap_or("").to_uppercase();
+      if site_servers.contains(&sid) {
+         add_role(computer, "SiteServer");
+      }
+      for object in objects {
+         let host = object["host"].as_str().unwrap_or("");
+         match object["type"].as_str() {
+            Some("ManagementPoint") if !host.is_empty() && (host == name || name.split('.').next() == Some(host)) => add_role(computer, "ManagementPoint"),
+            Some("PxeServer") if !host.is_empty() && host == dn => add_role(computer, "PxeServer"),
+            _ => {}
+         }
+      }
+      if has_keyword(computer) && !computer["Properties"]["sccmroles"].is_array() {
+         add_role(computer, "Candidate");
+      }
+   }
+
+   let computers: Vec<Value> = vec_computers.iter()
+      .filter(|computer| computer["Properties"]["sccmroles"].is_array())
+      .map(|computer| serde_json::json!({
+         "name": computer["Properties"]["name"],
+         "objectid": computer["ObjectIdentifier"],
+         "roles": computer["Properties"]["sccmroles"],
+      }))
+      .collect();
+   let of_type = |object_type: &str| -> Vec<Value> {
+      objects.iter().filter(|object| object["type"] == object_type).cloned().collect()
+   };
+   serde_json::json!({
+      "sites": of_type("Site"),
+      "managementpoints": of_type("ManagementPoint"),
+      "pxeservers": of_type("PxeServer"),
+      "computers": computers,
+   })
+}
+
+/// Function to flag the SCCM servers and write the SCCM report.
+pub fn make_sccm_report(
+   path: &String,
+   domain: &String,
+   vec_computers: &mut [Value],
+   vec_containers: &[Value],
+) -> std::io::Result<()> {
+   let report = sccm_roles(&sccm_objects(), vec_computers, vec_containers);
+   let count = |key: &str| report[key].as_array().map(|values| values.len()).unwrap_or(0);
+   info!("SCCM: {} sites, {} management points, {} computers with a SCCM role",
+      count("sites"), count("managementpoints"), count("computers").to_string().bold());
+
+   fs::create_dir_all(path)?;
+   let final_path = format!("{}/{}_sccm.json", path, domain.replace(".", "-").to_lowercase());
+   fs::write(&final_path, serde_json::to_string_pretty(&report).unwrap_or_default())?;
+   info!("{} created!", final_path.bold());
+   Ok(())
+}
+
+/// Test functions
+#[test]
+pub fn test_sccm_roles() {
+   use crate::json::parser::bh_41::parse_sccm_object;
+   let entry = |dn: &str, attrs: &[(&str, &[&str])]| ldap3::SearchEntry {
+      dn: dn.to_string(),
+      attrs: attrs.iter().map(|(name, values)| (name.to_string(), values.iter().map(|value| value.to_string()).collect())).collect(),
+      bin_attrs: std::collections::HashMap::new(),
+   };
+   let domain = "ESSOS.LOCAL".to_string();
+   let objects = vec![
+      parse_sccm_object(entry("CN=SMS-Site-P01,CN=System Management,CN=System,DC=ESSOS,DC=LOCAL",
+         &[("objectClass", &["top", "mSSMSSite"]), ("mSSMSSiteCode", &["P01"])]), &domain),
+      parse_sccm_object(entry("CN=SMS-MP-P01-SCCM01.ESSOS.LOCAL,CN=System Management,CN=System,DC=ESSOS,DC=LOCAL",
+         &[("objectClass", &["top", "mSSMSManagementPoint"]), ("mSSMSMPName", &["sccm01.essos.local"]), ("mSSMSSiteCode", &["P01"])]), &domain),
+      parse_sccm_object(entry("CN=DP01-Remote-Installation-Services,CN=DP01,OU=Servers,DC=ESSOS,DC=LOCAL",
+         &[("objectClass", &["top", "leaf", "connectionPoint", "intellimirrorSCP"])]), &domain),
+   ];
+   assert_eq!(objects[0]["sitecode"], "P01");
+   assert_eq!(objects[1]["host"], "SCCM01.ESSOS.LOCAL");
+   assert_eq!(objects[2]["host"], "CN=DP01,OU=SERVERS,DC=ESSOS,DC=LOCAL");
+
+   let mut computers = vec![
+      serde_json::json!({"ObjectIdentifier": "S-1-5-21-1-2-3-1201", "Properties": {"name": "SCCM01.ESSOS.LOCAL", "distinguishedname": "CN=SCCM01,OU=SERVERS,DC=ESSOS,DC=LOCAL"}}),
+      serde_json::json!({"ObjectIdentifier": "S-1-5-21-1-2-3-1202", "Properties": {"name": "DP01.ESSOS.LOCAL", "distinguishedname": "CN=DP01,OU=SERVERS,DC=ESSOS,DC=LOCAL"}}),
+      serde_json::json!({"ObjectIdentifier": "S-1-5-21-1-2-3-1203", "Properties": {"name": "SRV03.ESSOS.LOCAL", "description": "MECM SQL database"}}),
+      serde_json::json!({"ObjectIdentifier": "S-1-5-21-1-2-3-1001", "Properties": {"name": "BRAAVOS.ESSOS.LOCAL"}}),
+   ];
+   let containers = vec![serde_json::json!({"ObjectIdentifier": "0F2B", "Properties": {"distinguishedname": "CN=SYSTEM MANAGEMENT,CN=SYSTEM,DC=ESSOS,DC=LOCAL"},
+      "Aces": [{"RightName": "GenericAll", "IsInherited": false, "PrincipalSID": "S-1-5-21-1-2-3-1201", "PrincipalType": "Computer"}]})];
+   let report = sccm_roles(&objects, &mut computers, &containers);
+   assert_eq!(computers[0]["Properties"]["sccmroles"], serde_json::json!(["SiteServer", "ManagementPoint"]));
+   assert_eq!(computers[1]["Properties"]["sccmroles"], serde_json::json!(["PxeServer"]));
+   assert_eq!(computers[2]["Properties"]["sccmroles"], serde_json::json!(["Candidate"]));
+   assert!(computers[3]["Properties"]["sccmroles"].is_null());
+   assert_eq!(report["computers"].as_array().unwrap().len(), 3);
+   assert_eq!(report["sites"][0]["sitecode"], "P01");
+}
@@ -0,0 +1,172 @@
+//! Per-object error isolation and skipped objects report
+//!
+//! One entry with a corrupt attribute (malformed security descriptor, invalid UTF-16, huge blob) must not abort
+//! the run: every entry is parsed apart, an entry failing is skipped and the others are still written.
+//! The skipped objects are written in `<dirpath>/<domain>_skipped.json` at the end of the run:
+//!
+//! ```text
+//! [{"dn": "CN=JON SNOW,CN=USERS,DC=ESSOS,DC=LOCAL", "type": "User", "reason": "index out of bounds: ..."}]
+//! ```
+//!
+//! The report modules and the remote probes are run apart the same way: a collector panicking is reported as an
+//! error and the run goes on with the next ones. Every caught panic is written in `<dirpath>/<domain>_crash.log`
+//! with the collector, the object DN or host, the panic location and the backtrace, to be attached to a bug report.
+use crate::banner::Colorize;
+use lazy_static::lazy_static;
+use ldap3::SearchEntry;
+use log::{error, info, warn};
+use serde_json::value::Value;
+use std::cell::{Cell, RefCell};
+use std::fs;
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::sync::Mutex;
+
+/// Biggest binary attribute parsed, the bigger ones are not expected in AD (nTSecurityDescriptor is under 64KB).
+pub const MAX_BINARY_ATTRIBUTE_SIZE: usize = 16 * 1024 * 1024;
+
+lazy_static! {
+   static ref SKIPPED: Mutex<Vec<Value>> = Mutex::new(Vec::new());
+   /// Crash log records of the caught panics
+   static ref CRASHES: Mutex<Vec<String>> = Mutex::new(Vec::new());
+}
+thread_local! {
+   static ISOLATED: Cell<bool> = Cell::new(false);
+   /// Location and backtrace of the last panic caught on this thread, set by the panic hook
+   static LAST_PANIC: RefCell<Option<(String, String)>> = RefCell::new(None);
+}
+
+/// Function to record one skipped object with its cause.
+pub fn record_skipped(dn: &str, object_type: &str, reason: &str) {
+   warn!("Object {} skipped. Reason: {}", dn, reason);
+   if let Ok(mut skipped) = SKIPPED.lock() {
+      skipped.push(serde_json::json!({"dn": dn, "type": object_type, "reason": reason}));
+   }
+}
+
+/// Function to get the skipped objects of the run.
+pub fn skipped_objects() -> Vec<Value> {
+   SKIPPED.lock().map(|skipped| skipped.to_owned()).unwrap_or_default()
+}
+
+/// Function to know if the current panic is caught by isolate(), the panic hook stays quiet.
+pub fn isolated_panic() -> bool {
+   ISOLATED.with(|isolated| isolated.get())
+}
+
+/// Function called by the panic hook for a caught panic: its location and backtrace go in the crash log.
+pub fn record_panic_context(location: String, backtrace: String) {
+   LAST_PANIC.with(|last| *last.borrow_mut() = Some((location, backtrace)));
+}
+
+/// Function to get the crash log records of the run.
+pub fn crash_records() -> Vec<String> {
+   CRASHES.lock().map(|crashes| crashes.to_owned()).unwrap_or_default()
+}
+
+/// Function to run a collector apart, the message of its panic (secrets scrubbed) if it panics.
+/// The panic is written in the crash log with the collector name and the object DN or host.
+pub fn catch_panic<T>(collector: &str, target: Option<&str>, run: impl FnOnce() -> T) -> std::result::Result<T, String> {
+   let previous = ISOLATED.with(|isolated| isolated.replace(true));
+   let result = catch_unwind(AssertUnwindSafe(run));
+   ISOLATED.with(|isolated| isolated.set(previous));
+   let payload = match result {
+      Ok(value) => return Ok(value),
+      Err(payload) => payload,
+   };
+   let reason = payload.downcast_ref::<&str>().map(|s| s.to_string())
+      .or_else(|| payload.downcast_ref::<String>().map(|s| s.to_owned()))
+      .unwrap_or_else(|| "collector failed".to_string());
+   let reason = crate::secret::scrub(&reason);
+   let (location, backtrace) = LAST_PANIC.with(|last| last.borrow_mut().take())
+      .unwrap_or_else(|| ("unknown location".to_string(), "no backtrace".to_string()));
+   let record = format!(
+      "[{}] {}{}\npanicked at {}: {}\n{}\n",
+      chrono::Utc::now().to_rfc3339(), collector, target.map(|target| format!(" {}", target)).unwrap_or_default(), location, reason, backtrace.trim_end(),
+   );
+   if let Ok(mut crashes) = CRASHES.lock() {
+      crashes.push(record);
+   }
+   Err(reason)
+}
+
+/// Function to run a report module apart, its panic is turned into an error and the run goes on.
+pub fn isolate_collector<T, E: From<std::io::Error>>(collector: &str, run: impl FnOnce() -> Result<T, E>) -> Result<T, E> {
+   catch_panic(collector, None, run).unwrap_or_else(|reason| Err(std::io::Error::other(format!("{} collector panicked: {}", collector, reason)).into()))
+}
+
+/// Function to check the entry before parsing it, the cause when it can not be parsed.
+pub fn check_entry(entry: &SearchEntry) -> Option<String> {
+   entry.bin_attrs.iter()
+      .flat_map(|(name, values)| values.iter().map(move |value| (name, value.len())))
+      .find(|(_, size)| *size > MAX_BINARY_ATTRIBUTE_SIZE)
+      .map(|(name, size)| format!("{} is too large ({} bytes)", name, size))
+}
+
+/// Function to parse one entry apart, the entry is skipped and recorded when the parser fails.
+pub fn isolate<T>(entry: SearchEntry, object_type: &str, parse: impl FnOnce(SearchEntry) -> T) -> Option<T> {
+   if let Some(reason) = check_entry(&entry) {
+      record_skipped(&entry.dn, object_type, &reason);
+      return None
+   }
+   let dn = entry.dn.to_owned();
+   match catch_panic(object_type, Some(&dn), || parse(entry)) {
+      Ok(value) => Some(value),
+      Err(reason) => {
+         record_skipped(&dn, object_type, &reason);
+         None
+      }
+   }
+}
+
+/// Function to write the skipped objects report and the crash log, nothing when no object is skipped
+/// and no collector panicked. The minimal build logs the panics instead of writing the crash log.
+pub fn make_skipped_report(path: &String, domain: &String) -> std::io::Result<()> {
+   let skipped = skipped_objects();
+   if !skipped.is_empty() {
+      warn!("{} objects skipped, the other objects are written", skipped.len().to_string().bold());
+      fs::create_dir_all(path)?;
+      let final_path = format!("{}/{}_skipped.json", path, domain.replace(".", "-").to_lowercase());
+      fs::write(&final_path, serde_json::to_string_pretty(&skipped).unwrap_or_default())?;
+      info!("{} created!", final_path.bold());
+   }
+   let crashes = crash_records();
+   // No log file in the minimal build, the panics are only in the log records
+   if !crashes.is_empty() && cfg!(feature = "minimal") {
+      warn!("{} panics caught", crashes.len());
+      crashes.iter().for_each(|crash| error!("{}", crash));
+   } else if !crashes.is_empty() {
+      fs::create_dir_all(path)?;
+      let final_path = format!("{}/{}_crash.log", path, domain.replace(".", "-").to_lowercase());
+      fs::write(&final_path, crashes.join("\n"))?;
+      warn!("{} panics caught, details in {}", crashes.len().to_string().bold(), final_path.bold());
+   }
+   Ok(())
+}
+
+/// Test functions
+#[test]
+pub fn test_isolate() {
+   let entry = |dn: &str, size: usize| SearchEntry {
+      dn: dn.to_string(),
+      attrs: std::collections::HashMap::new(),
+      bin_attrs: std::collections::HashMap::from([("nTSecurityDescriptor".to_string(), vec![vec![0u8; size]])]),
+   };
+   assert_eq!(isolate(entry("CN=OK,DC=ESSOS,DC=LOCAL", 20), "User", |entry| entry.bin_attrs.len()), Some(1));
+   assert_eq!(isolate(entry("CN=CORRUPT,DC=ESSOS,DC=LOCAL", 20), "User", |entry| entry.bin_attrs["nTSecurityDescriptor"][0][64]), None);
+   assert!(!isolated_panic());
+   assert_eq!(isolate(entry("CN=HUGE,DC=ESSOS,DC=LOCAL", MAX_BINARY_ATTRIBUTE_SIZE + 1), "Computer", |_| 42), None);
+
+   let skipped = skipped_objects();
+   let corrupt = skipped.iter().find(|object| object["dn"] == "CN=CORRUPT,DC=ESSOS,DC=LOCAL").unwrap();
+   assert_eq!(corrupt["type"], "User");
+   assert!(corrupt["reason"].as_str().unwrap().contains("index out of bounds"));
+   let huge = skipped.iter().find(|object| object["dn"] == "CN=HUGE,DC=ESSOS,DC=LOCAL").unwrap();
+   assert_eq!(huge["reason"], format!("nTSecurityDescriptor is too large ({} bytes)", MAX_BINARY_ATTRIBUTE_SIZE + 1));
+   assert!(crash_records().iter().any(|record| record.contains("] User CN=CORRUPT,DC=ESSOS,DC=LOCAL\npanicked at")));
+
+   let result: std::io::Result<()> = isolate_collector("Exchange", || panic!("msExchVersion missing"));
+   assert!(result.unwrap_err().to_string().contains("Exchange collector panicked: msExchVersion missing"));
+   assert_eq!(isolate_collector("SCCM", || std::io::Result::Ok(7)).unwrap(), 7);
+   assert!(crash_records().iter().any(|record| record.contains("] Exchange\npanicked at unknown location: msExchVersion missing")));
+   assert!(!isolated_panic());
+}
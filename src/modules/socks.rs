@@ -0,0 +1,133 @@
+//! Collection through the SOCKS proxy of a relay tool (--socks)
+//!
+//! A relay tool like ntlmrelayx (`-socks`) keeps the LDAP sessions it relayed and exposes them on a SOCKS5 proxy:
+//! the client connects to the DC through the proxy and the relay tool answers the bind itself, with the relayed
+//! session, whatever the password. RustHound has no SOCKS support in its LDAP client, so a local forwarder
+//! listens on the loopback and opens every connection to the DC through the proxy:
+//!
+//! ```text
+//! rusthound -d essos.local -i 192.168.56.12 -u 'ESSOS/DAENERYS.TARGARYEN' --socks 127.0.0.1:1080
+//! ```
+//!
+//! The bind is a simple bind with the username of the relayed session and a dummy password, the relay tools
+//! which only take over NTLM (Sicily) binds are not supported, the LDAP client has no NTLM bind.
+use log::{debug, info};
+use std::io::{Error, ErrorKind, Read, Result, Write};
+use std::net::{Shutdown, TcpListener, TcpStream};
+
+/// Password of the simple bind, the relay tool binds with the relayed session.
+pub const SOCKS_BIND_PASSWORD: &str = "relayed";
+
+const SOCKS_VERSION: u8 = 0x05;
+const SOCKS_NO_AUTH: u8 = 0x00;
+const SOCKS_CONNECT: u8 = 0x01;
+const SOCKS_ATYP_IPV4: u8 = 0x01;
+const SOCKS_ATYP_DOMAIN: u8 = 0x03;
+const SOCKS_ATYP_IPV6: u8 = 0x04;
+
+/// Function to build the SOCKS5 CONNECT request to host:port (RFC 1928).
+pub fn socks5_connect_request(host: &str, port: u16) -> Vec<u8> {
+   let mut request = vec![SOCKS_VERSION, SOCKS_CONNECT, 0x00];
+   match host.parse::<std::net::IpAddr>() {
+      Ok(std::net::IpAddr::V4(ip)) => {
+         request.push(SOCKS_ATYP_IPV4);
+         request.extend_from_slice(&ip.octets());
+      }
+      Ok(std::net::IpAddr::V6(ip)) => {
+         request.push(SOCKS_ATYP_IPV6);
+         request.extend_from_slice(&ip.octets());
+      }
+      Err(_) => {
+         request.push(SOCKS_ATYP_DOMAIN);
+         request.push(host.len() as u8);
+         request.extend_from_slice(host.as_bytes());
+      }
+   }
+   request.extend_from_slice(&port.to_be_bytes());
+   request
+}
+
+/// Function to open a connection to host:port through the SOCKS5 proxy, without authentication.
+pub fn socks5_connect(proxy: &str, host: &str, port: u16) -> Result<TcpStream> {
+   let mut stream = TcpStream::connect(proxy)?;
+   stream.write_all(&[SOCKS_VERSION, 0x01, SOCKS_NO_AUTH])?;
+   let mut method = [0u8; 2];
+   stream.read_exact(&mut method)?;
+   if method != [SOCKS_VERSION, SOCKS_NO_AUTH] {
+      return Err(Error::new(ErrorKind::PermissionDenied, "SOCKS proxy requires an authentication"));
+   }
+   stream.write_all(&socks5_connect_request(host, port))?;
+   let mut reply = [0u8; 4];
+   stream.read_exact(&mut reply)?;
+   if reply[1] != 0x00 {
+      return Err(Error::new(ErrorKind::ConnectionRefused, format!("SOCKS proxy error {} for {}:{}", reply[1], host, port)));
+   }
+   // Bound address of the reply, not used
+   let address_len = match reply[3] {
+      SOCKS_ATYP_IPV4 => 4,
+      SOCKS_ATYP_IPV6 => 16,
+      SOCKS_ATYP_DOMAIN => {
+         let mut len = [0u8; 1];
+         stream.read_exact(&mut len)?;
+         len[0] as usize
+      }
+      _ => return Err(Error::new(ErrorKind::InvalidData, "Invalid SOCKS reply")),
+   };
+   let mut bound = vec![0u8; address_len + 2];
+   stream.read_exact(&mut bound)?;
+   Ok(stream)
+}
+
+/// Function to start the local forwarder to host:port through the proxy, returns the loopback port to connect to.
+pub fn start_socks_forwarder(proxy: &String, host: &String, port: u16) -> Result<u16> {
+   let listener = TcpListener::bind(("127.0.0.1", 0))?;
+   let local_port = listener.local_addr()?.port();
+   info!("Connections to {}:{} forwarded through the SOCKS proxy {}", host, port, proxy);
+   let (proxy, host) = (proxy.to_owned(), host.to_owned());
+   std::thread::spawn(move || {
+      for client in listener.incoming() {
+         let client = match client {
+            Ok(client) => client,
+            Err(err) => {
+               debug!("SOCKS forwarder client error: {err}");
+               continue;
+            }
+         };
+         match socks5_connect(&proxy, &host, port) {
+            Ok(upstream) => pipe(client, upstream),
+            Err(err) => {
+               debug!("SOCKS connection to {}:{} failed. Reason: {err}", &host, port);
+               let _ = client.shutdown(Shutdown::Both);
+            }
+         }
+      }
+   });
+   Ok(local_port)
+}
+
+/// Function to copy the bytes both ways until one side closes the connection.
+fn pipe(client: TcpStream, upstream: TcpStream) {
+   let (mut client_read, mut upstream_write) = match (client.try_clone(), upstream.try_clone()) {
+      (Ok(client_read), Ok(upstream_write)) => (client_read, upstream_write),
+      _ => return,
+   };
+   let (mut upstream_read, mut client_write) = (upstream, client);
+   std::thread::spawn(move || {
+      let _ = std::io::copy(&mut client_read, &mut upstream_write);
+      let _ = upstream_write.shutdown(Shutdown::Write);
+   });
+   std::thread::spawn(move || {
+      let _ = std::io::copy(&mut upstream_read, &mut client_write);
+      let _ = client_write.shutdown(Shutdown::Write);
+   });
+}
+
+/// Test functions
+#[test]
+pub fn test_socks5_connect_request() {
+   assert_eq!(socks5_connect_request("192.168.56.12", 389), vec![5, 1, 0, 1, 192, 168, 56, 12, 0x01, 0x85]);
+   let request = socks5_connect_request("dc01.essos.local", 636);
+   assert_eq!(&request[..5], &[5, 1, 0, 3, 16]);
+   assert_eq!(&request[5..21], b"dc01.essos.local");
+   assert_eq!(&request[21..], &[0x02, 0x7C]);
+}
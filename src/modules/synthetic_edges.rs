@@ -0,0 +1,271 @@
+use log::{info,debug,error};
+use crate::banner::Colorize;
+use regex::Regex;
+use std::fs;
+
+use crate::errors::{Error, Kind, Result};
+use crate::json::spill::{SpillStore, for_each_page};
+use crate::json::templates::bh_41::prepare_acl_relation_template;
+
+/// Default ruleset shipped with RustHound (Account/Server/Backup/Print Operators and DnsAdmins).
+pub const DEFAULT_EDGE_RULES: &str = include_str!("../../resources/synthetic_edges.yaml");
+
+/// Target of a synthetic edge.
+#[derive(Debug, Clone, PartialEq)]
+pub enum EdgeTarget {
+   /// All computers with primaryGroupID 516 (Domain Controllers)
+   DomainControllers,
+   Computers,
+   Users,
+   Groups,
+   Domains,
+}
+
+impl EdgeTarget {
+   fn from_str(value: &str) -> Option<EdgeTarget> {
+      match value.to_lowercase().as_str() {
+         "domain_controllers" => Some(EdgeTarget::DomainControllers),
+         "computers" => Some(EdgeTarget::Computers),
+         "users" => Some(EdgeTarget::Users),
+         "groups" => Some(EdgeTarget::Groups),
+         "domains" => Some(EdgeTarget::Domains),
+         _ => None,
+      }
+   }
+}
+
+/// One synthetic edge rule, loaded from the YAML ruleset.
+#[derive(Debug, Clone)]
+pub struct SyntheticEdgeRule {
+   /// Well-known SID suffix like "S-1-5-32-548" or group sAMAccountName
+   pub group: Option<String>,
+   /// RightName already held on the target
+   pub right: Option<String>,
+   pub edge: String,
+   pub target: EdgeTarget,
+   pub target_filter: Option<Regex>,
+}
+
+/// Parse the synthetic edges ruleset.
+/// Only the YAML subset used by resources/synthetic_edges.yaml is supported:
+/// a list of flat mappings with scalar values.
+pub fn parse_edge_rules(content: &str) -> Result<Vec<SyntheticEdgeRule>> {
+   let mut raw_rules: Vec<Vec<(String, String)>> = Vec::new();
+   for (i, line) in content.lines().enumerate() {
+      let trimmed = line.trim();
+      if trimmed.is_empty() || trimmed.starts_with("#") {
+         continue
+      }
+      let pair = if let Some(rest) = trimmed.strip_prefix("- ") {
+         raw_rules.push(Vec::new());
+         rest
+      } else {
+         trimmed
+      };
+      let mut split = pair.splitn(2, ":");
+      let key = split.next().unwrap_or("").trim().to_lowercase();
+      let value = match split.next() {
+         Some(value) => value.trim().trim_matches('"').trim_matches('\'').to_string(),
+         None => return Err(Error::new(Kind::ParseError).desc(format!("synthetic edges ruleset line {}: expected 'key: value'", i + 1))),
+      };
+      match raw_rules.last_mut() {
+         Some(rule) => rule.push((key, value)),
+         None => return Err(Error::new(Kind::ParseError).desc(format!("synthetic edges ruleset line {}: value outside of a rule", i + 1))),
+      }
+   }
+
+   let mut rules: Vec<SyntheticEdgeRule> = Vec::new();
+   for raw in raw_rules {
+      let get = |name: &str| raw.iter().find(|(key, _)| key == name).map(|(_, value)| value.to_owned());
+      let edge = match get("edge") {
+         Some(edge) => edge,
+         None => return Err(Error::new(Kind::ParseError).desc("synthetic edges rule without 'edge'")),
+      };
+      let target = match get("target").as_deref().and_then(EdgeTarget::from_str) {
+         Some(target) => target,
+         None => return Err(Error::new(Kind::ParseError).desc(format!("synthetic edges rule '{}': unknown or missing 'target'", edge))),
+      };
+      let target_filter = match get("target_filter") {
+         Some(filter) => match Regex::new(&format!("(?i){}", filter)) {
+            Ok(re) => Some(re),
+            Err(err) => return Err(Error::new(Kind::ParseError).with(err).desc(format!("synthetic edges rule '{}': bad 'target_filter'", edge))),
+         },
+         None => None,
+      };
+      let group = get("group");
+      let right = get("right");
+      if group.is_none() && right.is_none() {
+         return Err(Error::new(Kind::ParseError).desc(format!("synthetic edges rule '{}': need 'group' or 'right'", edge)));
+      }
+      rules.push(SyntheticEdgeRule { group, right, edge, target, target_filter });
+   }
+   Ok(rules)
+}
+
+/// Load the default ruleset (unless disabled) and the user ruleset file if any.
+pub fn load_edge_rules(builtin_edges: bool, edge_rules_path: &String) -> Vec<SyntheticEdgeRule> {
+   let mut rules: Vec<SyntheticEdgeRule> = Vec::new();
+   if builtin_edges {
+      rules.extend(parse_edge_rules(DEFAULT_EDGE_RULES).expect("Default synthetic edges ruleset is invalid"));
+   }
+   if !edge_rules_path.contains("not set") {
+      match fs::read_to_string(edge_rules_path) {
+         Ok(content) => match parse_edge_rules(&content) {
+            Ok(user_rules) => {
+               info!("{} synthetic edges rules loaded from {}", user_rules.len().to_string().bold(), edge_rules_path.bold());
+               rules.extend(user_rules);
+            }
+            Err(err) => error!("Can't parse synthetic edges ruleset {}. Reason: {err}", edge_rules_path),
+         },
+         Err(err) => error!("Can't read synthetic edges ruleset {}. Reason: {err}", edge_rules_path),
+      }
+   }
+   rules
+}
+
+/// Function to add synthetic edges from the ruleset.
+/// Edges are pushed as ACEs on the target object, like any other ACL-derived edge.
+/// The users and computers spilled by --low-memory are rewritten page by page, every rule of their type at once.
+pub fn add_synthetic_edges(
+   rules: &Vec<SyntheticEdgeRule>,
+   vec_users: &mut Vec<serde_json::value::Value>,
+   vec_groups: &mut Vec<serde_json::value::Value>,
+   vec_computers: &mut Vec<serde_json::value::Value>,
+   vec_domains: &mut Vec<serde_json::value::Value>,
+   spill: Option<&SpillStore>,
+) -> std::io::Result<()> {
+   info!("Adding synthetic edges...");
+   let mut count = 0;
+   // (rule, SIDs of the rule group)
+   let mut selected: Vec<(&SyntheticEdgeRule, Vec<String>)> = Vec::new();
+   for rule in rules {
+      let group_sids = match &rule.group {
+         Some(group) => find_group_sids(vec_groups, group),
+         None => Vec::new(),
+      };
+      if rule.group.is_some() && group_sids.is_empty() {
+         debug!("No group found for rule {}", rule.edge);
+         continue
+      }
+      selected.push((rule, group_sids));
+   }
+   let targets = [
+      ("users", &mut *vec_users, vec![EdgeTarget::Users]),
+      ("groups", &mut *vec_groups, vec![EdgeTarget::Groups]),
+      ("computers", &mut *vec_computers, vec![EdgeTarget::DomainControllers, EdgeTarget::Computers]),
+      ("domains", &mut *vec_domains, vec![EdgeTarget::Domains]),
+   ];
+   for (data_type, objects, kinds) in targets {
+      let rules: Vec<&(&SyntheticEdgeRule, Vec<String>)> = selected.iter().filter(|(rule, _)| kinds.contains(&rule.target)).collect();
+      if rules.is_empty() {
+         continue
+      }
+      for_each_page(objects, spill, data_type, |page| {
+         for target in page.iter_mut() {
+            for (rule, group_sids) in rules.iter() {
+               count += add_rule_edges(rule, group_sids, target);
+            }
+         }
+      })?;
+   }
+   info!("{} synthetic edges added!", count.to_string().bold());
+   Ok(())
+}
+
+/// Function to add the edges of one rule on one target, return the number of edges added.
+fn add_rule_edges(rule: &SyntheticEdgeRule, group_sids: &Vec<String>, target: &mut serde_json::value::Value) -> usize {
+   if rule.target == EdgeTarget::DomainControllers && !is_domain_controller(target) {
+      return 0
+   }
+   if let Some(filter) = &rule.target_filter {
+      if !filter.is_match(target["Properties"]["name"].as_str().unwrap_or("")) {
+         return 0
+      }
+   }
+   let mut count = 0;
+   for (sid, principal_type) in select_principals(rule, group_sids, target) {
+      if add_edge(target, &sid, &principal_type, &rule.edge) {
+         count += 1;
+      }
+   }
+   count
+}
+
+/// Principals getting the edge on this target: members of the rule group,
+/// holders of the rule right, or both when the rule sets the two.
+fn select_principals(rule: &SyntheticEdgeRule, group_sids: &Vec<String>, target: &serde_json::value::Value) -> Vec<(String, String)> {
+   let right = match &rule.right {
+      Some(right) => right,
+      None => return group_sids.iter().map(|sid| (sid.to_owned(), "Group".to_string())).collect(),
+   };
+   let mut principals: Vec<(String, String)> = Vec::new();
+   for ace in target["Aces"].as_array().unwrap_or(&Vec::new()) {
+      if ace["RightName"].as_str().unwrap_or("") != right {
+         continue
+      }
+      let sid = ace["PrincipalSID"].as_str().unwrap_or("").to_string();
+      if rule.group.is_some() && !group_sids.contains(&sid) {
+         continue
+      }
+      let principal = (sid, ace["PrincipalType"].as_str().unwrap_or("Group").to_string());
+      if !principals.contains(&principal) {
+         principals.push(principal);
+      }
+   }
+   principals
+}
+
+/// Get the SIDs of the groups matching a well-known SID suffix or a sAMAccountName.
+pub fn find_group_sids(vec_groups: &Vec<serde_json::value::Value>, group: &String) -> Vec<String> {
+   let mut sids: Vec<String> = Vec::new();
+   let is_sid = group.to_uppercase().starts_with("S-1-");
+   for object in vec_groups {
+      let object_sid = object["ObjectIdentifier"].as_str().unwrap_or("").to_string();
+      let group_name = object["Properties"]["name"].as_str().unwrap_or("").to_uppercase();
+      let matched = if is_sid {
+         object_sid.ends_with(&format!("-{}", group.to_uppercase())) || &object_sid == group
+      } else {
+         group_name.split("@").next().unwrap_or("") == group.to_uppercase()
+      };
+      if matched && !sids.contains(&object_sid) {
+         sids.push(object_sid);
+      }
+   }
+   sids
+}
+
+/// Check if the computer is a domain controller (primaryGroupID 516).
+pub fn is_domain_controller(computer: &serde_json::value::Value) -> bool {
+   computer["PrimaryGroupSID"].as_str().unwrap_or("").ends_with("-516")
+}
+
+/// Push one synthetic edge in the "Aces" of the target object if not already present.
+pub fn add_edge(target: &mut serde_json::value::Value, principal_sid: &String, principal_type: &String, edge: &str) -> bool {
+   let mut relation = prepare_acl_relation_template();
+   relation["RightName"] = edge.into();
+   relation["PrincipalSID"] = principal_sid.to_owned().into();
+   relation["PrincipalType"] = principal_type.to_owned().into();
+   if let Some(aces) = target["Aces"].as_array_mut() {
+      if !aces.contains(&relation) {
+         aces.push(relation);
+         return true
+      }
+   }
+   false
+}
+
+/// Test functions
+#[test]
+pub fn test_parse_edge_rules() {
+   let rules = parse_edge_rules(DEFAULT_EDGE_RULES).unwrap();
+   assert_eq!(rules.len(), 5);
+   assert_eq!(rules[0].group.as_deref(), Some("S-1-5-32-548"));
+   assert_eq!(rules[4].target, EdgeTarget::DomainControllers);
+
+   let custom = "- group: \"VMware Admins\"\n  edge: CanControlVirtualDC\n  target: domain_controllers\n  target_filter: ^ESX\n";
+   let rules = parse_edge_rules(custom).unwrap();
+   assert_eq!(rules[0].group.as_deref(), Some("VMware Admins"));
+   assert!(rules[0].target_filter.as_ref().unwrap().is_match("esx01.domain.local"));
+
+   assert!(parse_edge_rules("- edge: NoTarget\n  group: x\n").is_err());
+}
@@ -0,0 +1,153 @@
+//! Targeted collection from an input file (--targets)
+//!
+//! The file holds one sAMAccountName, distinguishedName or SID per line, empty lines and `#` comments are ignored.
+//! Only these objects are requested, then their direct relationships (member, memberOf, manager, managedBy,
+//! primary group) and the domain object are requested in a second pass.
+//!
+//! ```text
+//! # users.txt
+//! jon.snow
+//! CN=SRV01,OU=SERVERS,DC=ESSOS,DC=LOCAL
+//! S-1-5-21-3470624347-2381939452-3423283143-512
+//! ```
+use ldap3::SearchEntry;
+use log::{debug, error};
+use std::collections::HashSet;
+
+/// Attributes linking one object to another by its distinguishedName.
+pub const RELATED_DN_ATTRIBUTES: [&str; 4] = ["member", "memberOf", "manager", "managedBy"];
+/// Maximum number of values in one OR filter.
+pub const TARGETS_PER_FILTER: usize = 100;
+
+/// Function to read the targets file, return an empty vector if it can't be read.
+pub fn load_targets(path: &String) -> Vec<String> {
+   match std::fs::read_to_string(path) {
+      Ok(content) => content
+         .lines()
+         .map(|line| line.trim())
+         .filter(|line| !line.is_empty() && !line.starts_with('#'))
+         .map(|line| line.to_string())
+         .collect(),
+      Err(err) => {
+         error!("Can't read targets file {}. Reason: {err}", path);
+         Vec::new()
+      }
+   }
+}
+
+/// Function to escape one value in a LDAP filter (RFC 4515).
+pub fn escape_filter_value(value: &str) -> String {
+   let mut escaped = String::new();
+   for c in value.chars() {
+      match c {
+         '*' => escaped.push_str("\\2a"),
+         '(' => escaped.push_str("\\28"),
+         ')' => escaped.push_str("\\29"),
+         '\\' => escaped.push_str("\\5c"),
+         '\0' => escaped.push_str("\\00"),
+         _ => escaped.push(c),
+      }
+   }
+   escaped
+}
+
+/// Function to make the filter matching one target: SID, distinguishedName or sAMAccountName.
+fn target_filter(target: &str) -> String {
+   let value = escape_filter_value(target);
+   if target.to_uppercase().starts_with("S-1-") {
+      format!("(objectSid={})", value)
+   } else if target.contains('=') && target.contains(',') {
+      format!("(distinguishedName={})", value)
+   } else {
+      format!("(sAMAccountName={})", value)
+   }
+}
+
+/// Function to make the OR filters for the targets, split every TARGETS_PER_FILTER values.
+pub fn targets_filters(targets: &Vec<String>) -> Vec<String> {
+   targets
+      .chunks(TARGETS_PER_FILTER)
+      .map(|chunk| {
+         let filters: String = chunk.iter().map(|target| target_filter(target)).collect();
+         format!("(|{})", filters)
+      })
+      .collect()
+}
+
+/// Function to make the filters matching the objects directly related to the collected targets and not collected yet.
+fn related_object_filters(entries: &Vec<SearchEntry>) -> Vec<String> {
+   let collected: HashSet<String> = entries.iter().map(|entry| entry.dn.to_uppercase()).collect();
+   let mut related: Vec<String> = Vec::new();
+   let mut seen: HashSet<String> = HashSet::new();
+   for entry in entries {
+      for attribute in RELATED_DN_ATTRIBUTES {
+         for dn in entry.attrs.get(attribute).into_iter().flatten() {
+            let key = dn.to_uppercase();
+            if !collected.contains(&key) && seen.insert(key) {
+               related.push(format!("(distinguishedName={})", escape_filter_value(dn)));
+            }
+         }
+      }
+      // Primary group is not in memberOf: domain SID of the object with the primaryGroupID as RID
+      let rid = entry.attrs.get("primaryGroupID").and_then(|v| v.first()).and_then(|rid| rid.parse::<u32>().ok());
+      let sid = entry.bin_attrs.get("objectSid").and_then(|v| v.first());
+      if let (Some(rid), Some(sid)) = (rid, sid) {
+         if sid.len() > 12 {
+            let mut group_sid = sid[..sid.len() - 4].to_vec();
+            group_sid.extend_from_slice(&rid.to_le_bytes());
+            let filter = format!("(objectSid={})", escape_filter_bytes(&group_sid));
+            if seen.insert(filter.to_owned()) {
+               related.push(filter);
+            }
+         }
+      }
+   }
+   debug!("{} objects related to the targets", related.len());
+   related
+}
+
+/// Function to escape a binary value in a LDAP filter, like \01\05.
+fn escape_filter_bytes(value: &[u8]) -> String {
+   value.iter().map(|b| format!("\\{:02x}", b)).collect()
+}
+
+/// Function to make the OR filters for the objects related to the targets, with the domain object.
+pub fn related_filters(entries: &Vec<SearchEntry>) -> Vec<String> {
+   let mut filters: Vec<String> = related_object_filters(entries)
+      .chunks(TARGETS_PER_FILTER)
+      .map(|chunk| format!("(|{})", chunk.concat()))
+      .collect();
+   filters.push("(objectClass=domain)".to_string());
+   filters
+}
+
+/// Test functions
+#[test]
+pub fn test_targets_filters() {
+   let targets = vec![
+      "jon.snow".to_string(),
+      "CN=SRV01,OU=SERVERS,DC=ESSOS,DC=LOCAL".to_string(),
+      "S-1-5-21-3470624347-2381939452-3423283143-512".to_string(),
+      "weird*(name)".to_string(),
+   ];
+   let filters = targets_filters(&targets);
+   assert_eq!(filters.len(), 1);
+   assert_eq!(
+      filters[0],
+      "(|(sAMAccountName=jon.snow)(distinguishedName=CN=SRV01,OU=SERVERS,DC=ESSOS,DC=LOCAL)(objectSid=S-1-5-21-3470624347-2381939452-3423283143-512)(sAMAccountName=weird\\2a\\28name\\29))"
+   );
+
+   let mut entry = SearchEntry {
+      dn: "CN=jon.snow,CN=Users,DC=ESSOS,DC=LOCAL".to_string(),
+      attrs: std::collections::HashMap::new(),
+      bin_attrs: std::collections::HashMap::new(),
+   };
+   entry.attrs.insert("memberOf".to_string(), vec!["CN=Night Watch,CN=Users,DC=ESSOS,DC=LOCAL".to_string()]);
+   entry.attrs.insert("primaryGroupID".to_string(), vec!["513".to_string()]);
+   entry.bin_attrs.insert("objectSid".to_string(), vec![vec![1,2,0,0,0,0,0,5,21,0,0,0,0x50,0x04,0,0]]);
+   let filters = related_filters(&vec![entry]);
+   assert_eq!(filters, vec![
+      "(|(distinguishedName=CN=Night Watch,CN=Users,DC=ESSOS,DC=LOCAL)(objectSid=\\01\\02\\00\\00\\00\\00\\00\\05\\15\\00\\00\\00\\01\\02\\00\\00))".to_string(),
+      "(objectClass=domain)".to_string(),
+   ]);
+}
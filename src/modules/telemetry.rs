@@ -0,0 +1,209 @@
+//! Run telemetry and machine-readable logs (--log-json)
+//!
+//! Every phase (LDAP collection, parsing, checking...) and every collector with a progress bar
+//! is timed and counted, the summary gives the duration, the objects and the rate of each phase.
+//! With `--log-json` the log records, the progress and the summary are written on stderr as JSON lines
+//! and the progress bars are hidden, to follow the run from an automation pipeline:
+//!
+//! ```text
+//! {"timestamp":"2022-10-04T13:37:00+02:00","event":"log","level":"INFO","target":"rusthound::ldap","message":"Connected to ESSOS.LOCAL Active Directory!"}
+//! {"timestamp":"2022-10-04T13:37:03+02:00","event":"progress","phase":"LDAP collection","collector":"LDAP objects retreived","count":4000,"unit":"#"}
+//! {"timestamp":"2022-10-04T13:37:09+02:00","event":"summary","elapsed_ms":9000,"errors":0,"phases":[...]}
+//! ```
+use lazy_static::lazy_static;
+use log::Record;
+use std::collections::HashMap;
+use std::io::Write;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Instant;
+
+/// Number of objects between two progress events of a counter.
+const PROGRESS_STEP_COUNT: u64 = 1000;
+/// Percentage between two progress events of a percentage.
+const PROGRESS_STEP_PERCENT: u64 = 10;
+
+static LOG_JSON: AtomicBool = AtomicBool::new(false);
+
+/// Telemetry of one phase.
+#[derive(Debug, Clone)]
+pub struct PhaseTelemetry {
+   pub name: String,
+   pub started: Instant,
+   pub elapsed_ms: Option<u128>,
+   pub objects: u64,
+   /// Collector => last count
+   pub collectors: HashMap<String, u64>,
+}
+
+lazy_static! {
+   static ref STARTED: Mutex<Instant> = Mutex::new(Instant::now());
+   static ref PHASES: Mutex<Vec<PhaseTelemetry>> = Mutex::new(Vec::new());
+}
+
+/// Function to enable the JSON lines mode, the colors are disabled (always with the minimal feature).
+pub fn set_log_json(enabled: bool) {
+   LOG_JSON.store(enabled, Ordering::Relaxed);
+   #[cfg(feature = "console")]
+   if enabled || cfg!(feature = "minimal") {
+      colored::control::set_override(false);
+   } else {
+      colored::control::unset_override();
+   }
+}
+
+/// Function to know if the JSON lines mode is enabled.
+pub fn log_json() -> bool {
+   LOG_JSON.load(Ordering::Relaxed)
+}
+
+/// Function to write one JSON event on stderr.
+pub fn emit_event(mut event: serde_json::value::Value) {
+   let timestamp = chrono::Local::now().to_rfc3339_opts(chrono::SecondsFormat::Secs, false);
+   if let Some(object) = event.as_object_mut() {
+      object.insert("timestamp".to_string(), timestamp.into());
+   }
+   let _ = writeln!(std::io::stderr(), "{}", event);
+}
+
+/// Function to get the JSON event of one log record.
+pub fn log_event(record: &Record, message: &str) -> serde_json::value::Value {
+   serde_json::json!({
+      "event": "log",
+      "level": record.level().to_string(),
+      "target": record.target(),
+      "message": strip_ansi(message),
+   })
+}
+
+/// Function to remove the terminal colors of a message.
+pub fn strip_ansi(message: &str) -> String {
+   let mut stripped = String::with_capacity(message.len());
+   let mut chars = message.chars();
+   while let Some(c) = chars.next() {
+      if c == '\u{1b}' {
+         // ESC [ ... letter
+         for c in chars.by_ref() {
+            if c.is_ascii_alphabetic() {
+               break
+            }
+         }
+         continue
+      }
+      stripped.push(c);
+   }
+   stripped
+}
+
+/// Function to start the telemetry of a run: the run clock starts now and the phases of a previous run
+/// in the same process are dropped.
+pub fn start_telemetry() {
+   if let Ok(mut started) = STARTED.lock() {
+      *started = Instant::now();
+   }
+   if let Ok(mut phases) = PHASES.lock() {
+      phases.clear();
+   }
+}
+
+/// Function to start a new phase, the current one is closed.
+pub fn start_phase(name: &str) {
+   let mut phases = match PHASES.lock() {
+      Ok(phases) => phases,
+      Err(_) => return,
+   };
+   if let Some(current) = phases.last_mut() {
+      if current.elapsed_ms.is_none() {
+         current.elapsed_ms = Some(current.started.elapsed().as_millis());
+         if log_json() {
+            emit_event(phase_json(current));
+         }
+      }
+   }
+   phases.push(PhaseTelemetry {
+      name: name.to_string(),
+      started: Instant::now(),
+      elapsed_ms: None,
+      objects: 0,
+      collectors: HashMap::new(),
+   });
+}
+
+/// Function to add objects processed by the current phase.
+pub fn add_phase_objects(count: u64) {
+   if let Ok(mut phases) = PHASES.lock() {
+      if let Some(current) = phases.last_mut() {
+         current.objects += count;
+      }
+   }
+}
+
+/// Function to record the progress of one collector, emit a JSON event at every step.
+/// unit is "%" for a percentage, else count is a number of objects.
+pub fn record_progress(collector: &str, count: u64, unit: &str) {
+   let mut phases = match PHASES.lock() {
+      Ok(phases) => phases,
+      Err(_) => return,
+   };
+   let current = match phases.last_mut() {
+      Some(current) => current,
+      None => return,
+   };
+   let step = if unit == "%" { PROGRESS_STEP_PERCENT } else { PROGRESS_STEP_COUNT };
+   let previous = current.collectors.insert(collector.to_string(), count).unwrap_or(0);
+   if log_json() && (count / step != previous / step || previous == 0) {
+      emit_event(serde_json::json!({
+         "event": "progress",
+         "phase": current.name,
+         "collector": collector,
+         "count": count,
+         "unit": unit,
+      }));
+   }
+}
+
+fn phase_json(phase: &PhaseTelemetry) -> serde_json::value::Value {
+   let elapsed_ms = phase.elapsed_ms.unwrap_or_else(|| phase.started.elapsed().as_millis());
+   let rate = if elapsed_ms > 0 { phase.objects as f64 * 1000.0 / elapsed_ms as f64 } else { 0.0 };
+   let mut collectors: Vec<(&String, &u64)> = phase.collectors.iter().collect();
+   collectors.sort();
+   serde_json::json!({
+      "event": "phase",
+      "phase": phase.name,
+      "elapsed_ms": elapsed_ms as u64,
+      "objects": phase.objects,
+      "rate_per_second": (rate * 10.0).round() / 10.0,
+      "collectors": collectors.into_iter().map(|(name, count)| serde_json::json!({"name": name, "count": count})).collect::<Vec<_>>(),
+   })
+}
+
+/// Function to get the run summary: every phase with its duration, objects and rate.
+pub fn run_summary(errors: u64) -> serde_json::value::Value {
+   let phases: Vec<serde_json::value::Value> = PHASES.lock()
+      .map(|phases| phases.iter().map(phase_json).collect())
+      .unwrap_or_default();
+   serde_json::json!({
+      "event": "summary",
+      "elapsed_ms": STARTED.lock().map(|started| started.elapsed().as_millis() as u64).unwrap_or(0),
+      "errors": errors,
+      "phases": phases,
+   })
+}
+
+/// Test functions
+#[test]
+pub fn test_telemetry() {
+   assert_eq!(strip_ansi("\u{1b}[1m\u{1b}[32mESSOS.LOCAL\u{1b}[0m connected"), "ESSOS.LOCAL connected");
+   start_telemetry();
+   std::thread::sleep(std::time::Duration::from_millis(20));
+   start_phase("Parsing");
+   add_phase_objects(42);
+   record_progress("Parsing LDAP objects", 50, "%");
+   start_phase("Checking");
+   let summary = run_summary(0);
+   let parsing = summary["phases"].as_array().unwrap().iter().find(|phase| phase["phase"] == "Parsing").unwrap();
+   assert_eq!(parsing["objects"], 42);
+   assert_eq!(parsing["collectors"][0]["count"], 50);
+   // Counted from start_telemetry, not from the first summary
+   assert!(summary["elapsed_ms"].as_u64().unwrap() >= 20);
+}
@@ -0,0 +1,210 @@
+//! HTTPS sink of the JSON files (--upload)
+//!
+//! With `--upload <url>` the JSON files are zipped in memory and sent to an operator-controlled endpoint instead of
+//! being written in `--dirpath`, a collection run from a disposable host never writes the objects on its disk.
+//! The upload follows the tus 1.0 resumable upload protocol (core and creation), served by tusd or any tus server:
+//! - `POST <url>` with the archive length creates the upload, the server answers its `Location`,
+//! - the archive is sent in chunks of 1 MiB, one `PATCH` with its `Upload-Offset` per chunk,
+//! - after a failed chunk the offset received by the server is asked with `HEAD` and the upload resumes there,
+//!   up to 5 retries in a row with an exponential backoff, an answer whose offset does not advance is a failure.
+//!
+//! With `--upload-auth <value>` (or RUSTHOUND_UPLOAD_AUTH) every request carries the `Authorization: <value>`
+//! header, for an endpoint behind a bearer token or basic authentication. The upload runs on a blocking thread.
+//!
+//! With `--encrypt-output` the archive is encrypted before the upload. The reports of the modules (`--reports`,
+//! `--coercion`...) and the CSV or graph files of `--output-format` are still written in `--dirpath`.
+//!
+//! A failed upload fails the run. With `--upload-fallback-dir <dir>` the archive (encrypted with `--encrypt-output`)
+//! is written in this directory instead, the run goes on with a warning.
+use crate::banner::Colorize;
+use log::{debug, info, warn};
+use std::fs;
+use std::io::{Error, ErrorKind, Result};
+use std::thread;
+use std::time::Duration;
+
+use crate::modules::encrypt_output::OutputKey;
+use crate::modules::offline::base64_encode;
+use crate::secret::SecretString;
+
+/// Version of the tus protocol.
+pub const TUS_VERSION: &str = "1.0.0";
+/// Bytes sent in one PATCH.
+pub const UPLOAD_CHUNK_SIZE: usize = 1024 * 1024;
+/// Failed requests in a row before the upload is abandoned.
+pub const UPLOAD_RETRIES: u32 = 5;
+
+/// Function to get the URL of the upload from the Location header, relative to the endpoint or absolute.
+pub fn upload_location(endpoint: &str, location: &str) -> String {
+   if location.starts_with("http://") || location.starts_with("https://") {
+      return location.to_string()
+   }
+   // scheme://host[:port] of the endpoint
+   let authority_end = endpoint.find("://").map(|start| start + 3).and_then(|start| endpoint[start..].find('/').map(|end| start + end)).unwrap_or(endpoint.len());
+   if location.starts_with('/') {
+      format!("{}{}", &endpoint[..authority_end], location)
+   } else {
+      format!("{}/{}", endpoint.trim_end_matches('/'), location)
+   }
+}
+
+/// Function to get the delay before the retry number `retry` (1, 2, 4, 8... seconds).
+pub fn upload_backoff(retry: u32) -> Duration {
+   Duration::from_secs(1 << retry.saturating_sub(1).min(6))
+}
+
+/// Function to get the Upload-Offset of a tus answer, in the archive.
+fn response_offset(response: &ureq::Response, length: usize) -> Result<usize> {
+   response.header("Upload-Offset")
+      .and_then(|offset| offset.trim().parse::<usize>().ok())
+      .filter(|offset| *offset <= length)
+      .ok_or_else(|| Error::new(ErrorKind::InvalidData, "no valid Upload-Offset in the answer"))
+}
+
+/// Function to check that the Upload-Offset of a PATCH answer advances past the offset of the chunk.
+pub fn advanced_offset(offset: usize, next: usize) -> Result<usize> {
+   if next <= offset {
+      return Err(Error::new(ErrorKind::InvalidData, format!("Upload-Offset {} does not advance past {}", next, offset)))
+   }
+   Ok(next)
+}
+
+/// Function to start a tus request, with the Authorization header of --upload-auth.
+fn tus_request(agent: &ureq::Agent, method: &str, url: &str, auth: &Option<SecretString>) -> ureq::Request {
+   let request = agent.request(method, url).set("Tus-Resumable", TUS_VERSION);
+   match auth {
+      Some(auth) => request.set("Authorization", auth.expose()),
+      None => request,
+   }
+}
+
+/// Function to convert a ureq error, the status code is kept.
+fn http_error(err: ureq::Error) -> Error {
+   match err {
+      ureq::Error::Status(code, response) => Error::other(format!("HTTP {} {}", code, response.status_text())),
+      ureq::Error::Transport(transport) => Error::other(transport.to_string()),
+   }
+}
+
+/// Function to create the upload on the endpoint, return its URL.
+fn create_upload(agent: &ureq::Agent, endpoint: &str, auth: &Option<SecretString>, filename: &str, length: usize) -> Result<String> {
+   let mut retry = 0;
+   loop {
+      let result = tus_request(agent, "POST", endpoint, auth)
+         .set("Upload-Length", &length.to_string())
+         .set("Upload-Metadata", &format!("filename {}", base64_encode(filename.as_bytes())))
+         .call()
+         .map_err(http_error)
+         .and_then(|response| {
+            response.header("Location").map(|location| upload_location(endpoint, location))
+               .ok_or_else(|| Error::new(ErrorKind::InvalidData, "no Location in the answer"))
+         });
+      match result {
+         Ok(location) => return Ok(location),
+         Err(err) if retry < UPLOAD_RETRIES => {
+            retry += 1;
+            warn!("Upload creation failed, retry {}/{}. Reason: {err}", retry, UPLOAD_RETRIES);
+            thread::sleep(upload_backoff(retry));
+         }
+         Err(err) => return Err(err),
+      }
+   }
+}
+
+/// Function to send the archive in chunks, resumed at the offset of the server after a failure.
+fn send_chunks(agent: &ureq::Agent, location: &str, auth: &Option<SecretString>, archive: &[u8]) -> Result<()> {
+   let mut offset = 0;
+   let mut retry = 0;
+   while offset < archive.len() {
+      let end = (offset + UPLOAD_CHUNK_SIZE).min(archive.len());
+      let result = tus_request(agent, "PATCH", location, auth)
+         .set("Upload-Offset", &offset.to_string())
+         .set("Content-Type", "application/offset+octet-stream")
+         .send_bytes(&archive[offset..end])
+         .map_err(http_error)
+         .and_then(|response| response_offset(&response, archive.len()))
+         .and_then(|next| advanced_offset(offset, next));
+      match result {
+         Ok(next) => {
+            debug!("Upload {}/{} bytes", next, archive.len());
+            offset = next;
+            retry = 0;
+         }
+         Err(err) if retry < UPLOAD_RETRIES => {
+            retry += 1;
+            warn!("Upload of the chunk at {} failed, retry {}/{}. Reason: {err}", offset, retry, UPLOAD_RETRIES);
+            thread::sleep(upload_backoff(retry));
+            // Resume at the offset received by the server
+            match tus_request(agent, "HEAD", location, auth).call().map_err(http_error).and_then(|response| response_offset(&response, archive.len())) {
+               Ok(received) => offset = received,
+               Err(err) => debug!("Upload offset unknown, the chunk is sent again. Reason: {err}"),
+            }
+         }
+         Err(err) => return Err(err),
+      }
+   }
+   Ok(())
+}
+
+/// Function to write the archive of a failed upload in the --upload-fallback-dir directory, return its path.
+pub fn write_fallback(fallback_dir: &String, filename: &str, archive: &[u8]) -> Result<String> {
+   fs::create_dir_all(fallback_dir)?;
+   let final_path = format!("{}/{}", fallback_dir.trim_end_matches('/'), filename);
+   fs::write(&final_path, archive)?;
+   Ok(final_path)
+}
+
+/// Function to upload the zip archive of the JSON files, encrypted with --encrypt-output, written in
+/// --upload-fallback-dir when the upload fails. Err when the archive is neither uploaded nor written.
+/// Blocking, called from a blocking thread of the runtime.
+pub fn upload_archive(endpoint: &String, domain: &String, archive: Vec<u8>, key: &Option<OutputKey>, auth: &Option<SecretString>, fallback_dir: &String) -> Result<()> {
+   if endpoint.starts_with("http://") && key.is_none() {
+      warn!("{} is not HTTPS, the objects are sent in clear text, see --encrypt-output", endpoint);
+   }
+   let filename = format!("{}_rusthound_result.zip", domain.replace(".", "-").to_lowercase());
+   let (archive, filename) = match key {
+      Some(key) => (key.encrypt(&archive)?, format!("{}.{}", filename, key.extension())),
+      None => (archive, filename),
+   };
+   let agent = ureq::AgentBuilder::new().timeout(Duration::from_secs(60)).build();
+   let upload = create_upload(&agent, endpoint, auth, &filename, archive.len()).and_then(|location| {
+      info!("Upload of {} ({} bytes) to {}", filename.bold(), archive.len(), location);
+      send_chunks(&agent, &location, auth, &archive)
+   });
+   match upload {
+      Ok(()) => info!("{} uploaded!", filename.bold()),
+      Err(err) if !fallback_dir.contains("not set") => {
+         let final_path = write_fallback(fallback_dir, &filename, &archive)
+            .map_err(|write_err| Error::new(write_err.kind(), format!("{err}, and not written in {}: {write_err}", fallback_dir)))?;
+         warn!("Upload failed, {} created instead. Reason: {err}", final_path.bold());
+      }
+      Err(err) => return Err(err),
+   }
+   Ok(())
+}
+
+/// Test functions
+#[test]
+pub fn test_upload_location() {
+   assert_eq!(upload_location("https://files.essos.local:1080/files/", "/files/24e533e"), "https://files.essos.local:1080/files/24e533e");
+   assert_eq!(upload_location("https://files.essos.local/files", "24e533e"), "https://files.essos.local/files/24e533e");
+   assert_eq!(upload_location("https://files.essos.local", "/files/24e533e"), "https://files.essos.local/files/24e533e");
+   assert_eq!(upload_location("https://files.essos.local/files/", "https://cdn.essos.local/24e533e"), "https://cdn.essos.local/24e533e");
+   assert_eq!(upload_backoff(1), Duration::from_secs(1));
+   assert_eq!(upload_backoff(4), Duration::from_secs(8));
+   assert_eq!(advanced_offset(0, 1048576).unwrap(), 1048576);
+   assert!(advanced_offset(1048576, 1048576).is_err());
+   assert!(advanced_offset(1048576, 0).is_err());
+   assert_eq!(base64_encode(b"essos-local_rusthound_result.zip"), "ZXNzb3MtbG9jYWxfcnVzdGhvdW5kX3Jlc3VsdC56aXA=");
+   assert_eq!(base64_encode(b"ab"), "YWI=");
+}
+
+#[test]
+pub fn test_write_fallback() {
+   let dir = std::env::temp_dir().join(format!("rusthound_upload_{}", std::process::id()));
+   let fallback_dir = format!("{}/", dir.display());
+   let final_path = write_fallback(&fallback_dir, "essos-local_rusthound_result.zip.age", b"age-encryption.org/v1").unwrap();
+   assert_eq!(final_path, format!("{}/essos-local_rusthound_result.zip.age", dir.display()));
+   assert_eq!(fs::read(&final_path).unwrap(), b"age-encryption.org/v1");
+   let _ = fs::remove_dir_all(&dir);
+}
@@ -0,0 +1,99 @@
+//! WebClient service detection (--webclient)
+//!
+//! A computer running the WebClient service authenticates over HTTP when it is coerced to a WebDAV path
+//! (`\\attacker@80\share`), and HTTP authentication can be relayed to LDAP whatever the SMB signing settings.
+//! The service creates the `DAV RPC SERVICE` named pipe: it is opened in IPC$ with the collection credentials,
+//! STATUS_OBJECT_NAME_NOT_FOUND means the service is stopped.
+//!
+//! Every enabled computer is probed with `--webclient-threads` probes at the same time (16 by default), the
+//! result is set on the computers (`webclientrunning`, null when the computer is unreachable or the session
+//! is refused) and the hosts running the service are written in `<dirpath>/<domain>_webclient.json`.
+use crate::banner::Colorize;
+use log::{debug, info};
+use serde_json::value::Value;
+use std::collections::HashMap;
+use std::fs;
+
+use crate::modules::remote::{probe_pipes, remote_targets, run_probes, split_username};
+use crate::secret::SecretString;
+
+/// Named pipe of the WebClient service.
+pub const WEBCLIENT_PIPE: &str = "DAV RPC SERVICE";
+/// Default number of WebClient probes at the same time.
+pub const WEBCLIENT_THREADS: usize = 16;
+
+/// Function to probe the WebClient service of one host, None when it can not be known.
+pub fn webclient_running(host: &str, user: &str, domain: &str, password: &SecretString) -> Option<bool> {
+   match probe_pipes(host, user, domain, password, &[WEBCLIENT_PIPE]) {
+      Ok(exists) => exists[0],
+      Err(err) => {
+         debug!("WebClient probe of {} failed. Reason: {err}", host);
+         None
+      }
+   }
+}
+
+/// Function to set the `webclientrunning` property of one computer, null when the probe failed,
+/// return true when the service is running.
+pub fn set_webclient_property(computer: &mut Value, running: Option<bool>) -> bool {
+   computer["Properties"]["webclientrunning"] = running.into();
+   running == Some(true)
+}
+
+/// Function to probe the WebClient service of the computers and write the hosts running it.
+pub async fn make_webclient_report(
+   path: &String,
+   domain: &String,
+   username: &String,
+   password: &SecretString,
+   threads: usize,
+   exclude_stale: bool,
+   vec_computers: &mut [Value],
+   fqdn_ip: &HashMap<String, String>,
+) -> std::io::Result<()> {
+   if username.contains("not set") {
+      return Err(std::io::Error::new(std::io::ErrorKind::InvalidInput, "the WebClient probe needs -u and -p for the SMB session"));
+   }
+   let (user, user_domain) = split_username(username, domain);
+   let targets = remote_targets(vec_computers, fqdn_ip, exclude_stale);
+   info!("WebClient probe of {} computers started ({} threads)...", targets.len().to_string().bold(), threads);
+   let hosts: Vec<String> = targets.iter().map(|(_, host)| host.to_owned()).collect();
+   let password = password.to_owned();
+   // Blocking sockets, out of the tokio workers
+   let results = tokio::task::spawn_blocking(move || run_probes(&hosts, threads, |host| webclient_running(host, &user, &user_domain, &password))).await
+      .map_err(std::io::Error::other)?;
+
+   let mut running: Vec<Value> = Vec::new();
+   for ((index, host), result) in targets.iter().zip(results) {
+      let computer = &mut vec_computers[*index];
+      if set_webclient_property(computer, result) {
+         info!("WebClient running on {}", computer["Properties"]["name"].as_str().unwrap_or("").yellow().bold());
+         running.push(serde_json::json!({
+            "name": computer["Properties"]["name"],
+            "objectid": computer["ObjectIdentifier"],
+            "host": host,
+         }));
+      }
+   }
+   info!("{} computers with the WebClient service running", running.len().to_string().bold());
+
+   fs::create_dir_all(path)?;
+   let final_path = format!("{}/{}_webclient.json", path, domain.replace(".", "-").to_lowercase());
+   fs::write(&final_path, serde_json::to_string_pretty(&running).unwrap_or_default())?;
+   info!("{} created!", final_path.bold());
+   Ok(())
+}
+
+/// Test functions
+#[test]
+pub fn test_webclient_property() {
+   assert_eq!(WEBCLIENT_PIPE, "DAV RPC SERVICE");
+   let mut computer = serde_json::json!({"ObjectIdentifier": "S-1-5-21-1-2-3-1000", "Properties": {"name": "MEEREEN.ESSOS.LOCAL"}});
+   assert!(set_webclient_property(&mut computer, Some(true)));
+   assert_eq!(computer["Properties"]["webclientrunning"], true);
+   assert!(!set_webclient_property(&mut computer, Some(false)));
+   assert_eq!(computer["Properties"]["webclientrunning"], false);
+   // Unreachable or session refused
+   assert!(!set_webclient_property(&mut computer, None));
+   assert!(computer["Properties"]["webclientrunning"].is_null());
+}
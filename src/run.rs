@@ -0,0 +1,504 @@
+//! Collection run of the rusthound binary and of the library entry points
+//!
+//! `run_collection` runs everything after the arguments parsing: LDAP (ADWS, cache or offline input) collection,
+//! parsing, checks, modules and output files, once for the domain and once for every child domain followed with
+//! --follow-referrals. The binary, the C entry point of [`crate::ffi`] and the library users
+//! call it with their `Options`. With `Options::in_memory` the zip archive of the JSON files is returned instead of
+//! written, the report modules and the sidecar files (skipped objects, metrics, crash log) are still written.
+use crate::banner::Colorize;
+use log::{info,trace,warn,error};
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+
+use crate::errors::{Error, Kind, Result};
+use crate::enums;
+use crate::args::*;
+use crate::ldap::*;
+use crate::adws::adws_search;
+use crate::modules::*;
+use crate::json;
+use crate::json::checker::*;
+use crate::json::checker::lookup::LookupMaps;
+use crate::json::checker::conflicts::{resolve_duplicate_sids, make_conflicts_report, take_spilled_duplicates};
+use crate::json::maker::make_result;
+use crate::json::maker::ndjson::make_ndjson_result;
+use crate::json::parser::*;
+use crate::json::spill::{SpillStore, SPILLED_TYPES, for_each_page};
+
+/// Function to run one collection with the options of the command line or of the caller,
+/// return the zip archive with `in_memory` (encrypted with --encrypt-output).
+pub async fn run_collection(mut common_args: Options) -> Result<Option<Vec<u8>>> {
+    reset_control();
+    start_telemetry();
+    // Password of the output files from RUSTHOUND_ENCRYPT_PASSWORD, --encrypt-password-file or a prompt
+    resolve_encrypt_password(&mut common_args);
+    // Decrypt a file of --encrypt-output and exit
+    if !common_args.decrypt.contains("not set") {
+        if let Err(err) = decrypt_file(&common_args.decrypt, &common_args.encrypt_password) {
+            error!("Can't decrypt {}. Reason: {err}", &common_args.decrypt);
+        }
+        return Ok(None)
+    }
+    // Never a plaintext output when the key is missing or invalid
+    let output_key = output_key(&common_args.encrypt_output, &common_args.encrypt_password).map_err(|err| Error::new(Kind::Other).desc("No key for --encrypt-output").with(err))?;
+    let run_start = std::time::SystemTime::now();
+
+    // Password from RUSTHOUND_PASSWORD, --cred-file or a prompt, out of the shell history
+    resolve_credentials(&mut common_args);
+
+    // Get verbose level
+    info!("Verbosity level: {:?}", common_args.verbose);
+    if !common_args.profile.contains("not set") {
+        info!("Collection profile: {}", common_args.profile);
+    }
+    enums::acl::set_edge_justification(common_args.edge_justification);
+    enums::acl::set_edge_provenance(common_args.edge_provenance);
+    enums::acl::set_acl_baseline(!common_args.acl_baseline.contains("not set"));
+    enums::replmeta::set_repl_metadata(common_args.repl_metadata);
+    json::checker::compat::set_bloodhound_schema(&common_args.bh_version);
+    enums::attributes::set_object_props(&common_args.object_props);
+
+    // Control socket and metrics endpoint stopped when the run returns, whatever the exit path
+    let mut listeners = RunListeners::default();
+    // Control socket to follow the progress from another terminal
+    if !common_args.control_socket.contains("not set") {
+        match start_control_socket(&common_args.control_socket) {
+            Ok(thread) => listeners.control_socket = Some((common_args.control_socket.to_owned(), thread)),
+            Err(err) => error!("Can't open control socket {}. Reason: {err}", &common_args.control_socket),
+        }
+    }
+    // Run metrics served during the collection
+    if !common_args.metrics_port.contains("not set") {
+        match start_metrics_server(&common_args.metrics_port) {
+            Ok(thread) => listeners.metrics = Some(thread),
+            Err(err) => error!("Can't serve metrics on port {}. Reason: {err}", &common_args.metrics_port),
+        }
+    }
+
+    // Targeted collection, an unreadable file must not start a full collection
+    let mut targets: Vec<String> = Vec::new();
+    if !common_args.targets.contains("not set") {
+        targets = load_targets(&common_args.targets);
+        if targets.is_empty() {
+            error!("No target in {}, nothing to collect", &common_args.targets);
+            return Ok(None)
+        }
+        info!("Targeted collection of {} objects", targets.len());
+    }
+
+    // The domain, then the child domains of its referrals, each collected as its own domain (--follow-referrals)
+    let (mut output, mut referrals) = collect_domain(&mut common_args, &targets, &output_key).await?;
+    let mut visited: HashSet<String> = HashSet::from([common_args.domain.to_lowercase()]);
+    let mut followed = 0;
+    // The other child domains are still collected, the run fails at the end
+    let mut failed_upload: Option<Error> = None;
+    while let Some(referral) = referrals.pop() {
+        if !visited.insert(referral.domain.to_owned()) {
+            continue
+        }
+        if common_args.in_memory {
+            warn!("{} not collected, the referrals are not followed with the in-memory output", referral.base.bold());
+            continue
+        }
+        if followed >= MAX_REFERRALS || stop_requested() {
+            warn!("{} not collected, {} referrals already followed", referral.base.bold(), followed);
+            continue
+        }
+        followed += 1;
+        info!("Following the referral to {} on {}", referral.base.bold(), referral.host.bold());
+        let mut referred_args = referral_options(&common_args, &referral);
+        match collect_domain(&mut referred_args, &Vec::new(), &output_key).await {
+            Ok((_, referred)) => referrals.extend(referred),
+            Err(err) if matches!(err.kind(), Kind::Upload) => failed_upload = failed_upload.or(Some(err)),
+            Err(err) => warn!("Collection of {} interrupted. Reason: {err}", referral.domain),
+        }
+    }
+
+    if let Err(err) = make_skipped_report(&common_args.path, &common_args.domain) {
+        error!("Error while writing skipped objects report. Reason: {err}");
+    }
+    set_phase("Done");
+    if log_json() {
+        emit_event(run_summary(error_count()));
+    }
+    if common_args.metrics {
+        if let Err(err) = make_metrics_file(&common_args.path, &common_args.domain) {
+            error!("Error while writing metrics file. Reason: {err}");
+        }
+    }
+    // Output files encrypted at rest, the plaintext files are removed
+    if let Some(key) = &output_key {
+        if let Some(archive) = output.take() {
+            // Never the plaintext archive when the encryption fails
+            output = Some(key.encrypt(&archive).map_err(|err| Error::new(Kind::Other).with(err))?);
+        }
+        match encrypt_output_files(&common_args.path, run_start, key) {
+            Ok(count) => info!("{} output files encrypted", count),
+            Err(err) => error!("Error while encrypting output files. Reason: {err}"),
+        }
+    }
+    if let Some(err) = failed_upload {
+        return Err(err)
+    }
+
+    Ok(output)
+}
+
+/// Control socket and metrics endpoint of one run, stopped when dropped.
+#[derive(Default)]
+struct RunListeners {
+    control_socket: Option<(String, ListenerThread)>,
+    metrics: Option<ListenerThread>,
+}
+
+impl Drop for RunListeners {
+    fn drop(&mut self) {
+        if let Some((path, thread)) = self.control_socket.take() {
+            drop(thread);
+            stop_control_socket(&path);
+        }
+    }
+}
+
+/// Function to get the options of a child domain referred by the collected domain: same credentials and same
+/// transport (LDAPS and port of the original session, never the scheme of the referral URL), on the referred host.
+pub fn referral_options(common_args: &Options, referral: &Referral) -> Options {
+    let mut options = common_args.clone();
+    // The account stays in its own domain, not in the referred one
+    if !options.username.contains("not set") && !options.username.contains('@') {
+        options.username = format!("{}@{}", options.username, common_args.domain);
+    }
+    options.domain = referral.domain.to_owned();
+    options.ldapfqdn = referral.host.to_owned();
+    options.ip = "not set".to_string();
+    options.dc = "not set".to_string();
+    options.search_base = "not set".to_string();
+    options
+}
+
+/// Function to collect, parse, check and write one domain, return the zip archive with `in_memory` and the
+/// naming contexts of the referrals to follow.
+async fn collect_domain(common_args: &mut Options, targets: &Vec<String>, output_key: &Option<OutputKey>) -> Result<(Option<Vec<u8>>, Vec<Referral>)> {
+    set_phase("LDAP collection");
+
+    // Pinned DC or DC discovery from the DNS SRV records
+    if !common_args.from_cache && common_args.input_format.contains("not set") {
+        if let Some((ip, ldapfqdn)) = locate_dc(
+            &common_args.dc,
+            &common_args.ip,
+            &common_args.ldapfqdn,
+            &common_args.domain,
+            &common_args.site,
+            common_args.ldaps,
+            &common_args.port,
+            common_args.dns_tcp,
+            &common_args.name_server,
+        ).await {
+            common_args.ip = ip;
+            common_args.ldapfqdn = ldapfqdn;
+        }
+    }
+
+    // Users and computers spilled on disk as they are parsed with --low-memory, unless a module reads them all
+    let spill: Option<Arc<SpillStore>> = if common_args.low_memory && !reads_collection(common_args) {
+        match SpillStore::create(&common_args.path, &common_args.domain) {
+            Ok(spill) => Some(Arc::new(spill)),
+            Err(err) => {
+                error!("Can't create the spill directory in {}, objects kept in memory. Reason: {err}", &common_args.path);
+                None
+            }
+        }
+    } else {
+        if common_args.low_memory {
+            warn!("A module or an output of this run reads all the objects, they are kept in memory during the collection");
+        }
+        None
+    };
+    let spill_error = |err: std::io::Error| Error::new(Kind::Other).desc("spilled objects can't be read").with(err);
+
+    // Parse the LDAP pages during the collection, the raw entries are kept for the cache and the targets
+    let live_ldap = !common_args.from_cache && common_args.input_format.contains("not set");
+    let pipeline = if live_ldap && targets.is_empty() && common_args.cache_dir.contains("not set") {
+        Some(ParsePipeline::start(&common_args.domain, common_args.parse_threads, spill.clone()))
+    } else {
+        None
+    };
+    let pipelined = |pipeline: &Option<ParsePipeline>| pipeline.as_ref().map(|pipeline| pipeline.entries()).unwrap_or(0);
+
+    // Ldap request to get all informations in result, or the raw entries of a previous run or an export
+    let mut referrals: Vec<Referral> = Vec::new();
+    let cache_dir = cache_dir(&common_args.cache_dir);
+    let result = if common_args.from_cache {
+        match load_ldap_cache(&cache_dir, &common_args.domain) {
+            Ok(result) => result,
+            Err(err) => {
+                error!("Can't read LDAP cache in {}. Reason: {err}", &cache_dir);
+                return Ok((None, Vec::new()))
+            }
+        }
+    } else if !common_args.input_format.contains("not set") {
+        match load_input(&common_args.input_format, &common_args.input_path) {
+            Ok(result) => result,
+            Err(err) => {
+                error!("Can't read {} input {}. Reason: {err}", &common_args.input_format, &common_args.input_path);
+                return Ok((None, Vec::new()))
+            }
+        }
+    } else {
+        let result = if common_args.protocol == "adws" {
+            adws_search(common_args, targets, pipeline.as_ref()).await?
+        } else {
+            ldap_search(common_args, targets, pipeline.as_ref()).await?
+        };
+        referrals = result.1;
+        let result = result.0;
+        if !common_args.cache_dir.contains("not set") {
+            let partial = !targets.is_empty() || common_args.sample < 100 || stop_requested()
+                || !common_args.ldap_filter.contains("not set") || !common_args.search_base.contains("not set");
+            if let Err(err) = save_ldap_cache(&cache_dir, &common_args.domain, &result, partial) {
+                error!("Error while writing LDAP cache. Reason: {err}");
+            }
+        }
+        result
+    };
+    add_phase_objects(result.len() as u64 + pipelined(&pipeline));
+
+    // Vector for content all
+    let mut vec_users: Vec<serde_json::value::Value> = Vec::new();
+    let mut vec_groups: Vec<serde_json::value::Value> = Vec::new();
+    let mut vec_computers: Vec<serde_json::value::Value> = Vec::new();
+    let mut vec_ous: Vec<serde_json::value::Value> = Vec::new();
+    let mut vec_domains: Vec<serde_json::value::Value> = Vec::new();
+    let mut vec_gpos: Vec<serde_json::value::Value> = Vec::new();
+    let mut vec_fsps: Vec<serde_json::value::Value> = Vec::new();
+    let mut vec_containers: Vec<serde_json::value::Value> = Vec::new();
+    let mut vec_trusts: Vec<serde_json::value::Value> = Vec::new();
+    let mut vec_psos: Vec<serde_json::value::Value> = Vec::new();
+    // Hashmap to link DN to SID
+    let mut dn_sid = HashMap::new();
+    // Hashmap to link DN to Type
+    let mut sid_type = HashMap::new();
+    // Hashmap to link FQDN to SID
+    let mut fqdn_sid = HashMap::new();
+    // Hashmap to link fqdn to an ip address
+    let mut fqdn_ip = HashMap::new();
+
+    // Analyze object by object //Get type and parse it to get values
+    set_phase("Parsing");
+    add_phase_objects(result.len() as u64 + pipelined(&pipeline));
+    par_parse_result_type(
+        &common_args.domain,
+        result,
+        common_args.parse_threads,
+        pipeline,
+        spill.as_deref(),
+        &mut vec_users,
+        &mut vec_groups,
+        &mut vec_computers,
+        &mut vec_ous,
+        &mut vec_domains,
+        &mut vec_gpos,
+        &mut vec_fsps,
+        &mut vec_containers,
+        &mut vec_trusts,
+        &mut vec_psos,
+        &mut dn_sid,
+        &mut sid_type,
+        &mut fqdn_sid,
+        &mut fqdn_ip,
+    );
+
+    // Objects with the same SID (CNF objects), keep one node and its edges
+    if let Some(spill) = &spill {
+        take_spilled_duplicates(&mut vec_users, &vec_groups, &mut vec_computers, spill).map_err(spill_error)?;
+    }
+    let conflicts = resolve_duplicate_sids(&mut [
+        ("User", &mut vec_users),
+        ("Group", &mut vec_groups),
+        ("Computer", &mut vec_computers),
+    ], &mut sid_type);
+    if let Err(err) = make_conflicts_report(&common_args.path, &common_args.domain, &conflicts) {
+        error!("Error while writing conflicts report. Reason: {err}");
+    }
+
+    // DN/SID/type maps are complete, freeze them for the edge pass
+    let lookups = LookupMaps::freeze(dn_sid, sid_type, fqdn_sid);
+
+    // Functions to replace and add missing values
+    set_phase("Checking");
+    let total_objects = [&vec_users, &vec_groups, &vec_computers, &vec_ous, &vec_domains, &vec_gpos, &vec_containers]
+        .iter().map(|objects| objects.len() as u64).sum::<u64>()
+        + spill.as_ref().map(|spill| SPILLED_TYPES.iter().map(|data_type| spill.count(data_type) as u64).sum::<u64>()).unwrap_or(0);
+    add_phase_objects(total_objects);
+    check_all_result(
+        &common_args.domain,
+        &mut vec_users,
+        &mut vec_groups,
+        &mut vec_computers,
+        &mut vec_ous,
+        &mut vec_domains,
+        &mut vec_gpos,
+        &mut vec_fsps,
+        &mut vec_containers,
+        &mut vec_trusts,
+        &vec_psos,
+        &lookups,
+        &mut fqdn_ip,
+        spill.as_deref(),
+     ).map_err(spill_error)?;
+
+    // Inactive computers, can be skipped by the remote modules
+    let now = chrono::Local::now().timestamp();
+    for_each_page(&mut vec_computers, spill.as_deref(), "computers", |computers| json::checker::bh_41::add_stale_computers(computers, common_args.stale_days, now))
+        .map_err(spill_error)?;
+
+    // Readable justification for ACL edges
+    if common_args.edge_justification {
+        json::checker::bh_41::add_edge_justification_details(&mut [
+            &mut vec_users,
+            &mut vec_groups,
+            &mut vec_computers,
+            &mut vec_ous,
+            &mut vec_domains,
+            &mut vec_gpos,
+            &mut vec_containers,
+        ]);
+    }
+
+    // Running modules
+    set_phase("Modules");
+    add_phase_objects(total_objects);
+    run_modules(
+        &common_args,
+        &mut fqdn_ip,
+        &mut vec_users,
+        &mut vec_groups,
+        &mut vec_computers,
+        &mut vec_ous,
+        &mut vec_domains,
+        &mut vec_gpos,
+        &mut vec_containers,
+        &conflicts,
+        spill.as_deref(),
+    ).await;
+
+    // Edges and properties of the selected BloodHound release
+    if let Some(schema) = json::checker::compat::selected_schema() {
+        let mut removed = json::checker::compat::apply_schema(schema, &mut [
+            &mut vec_users,
+            &mut vec_groups,
+            &mut vec_computers,
+            &mut vec_ous,
+            &mut vec_domains,
+            &mut vec_gpos,
+            &mut vec_containers,
+        ]);
+        if let Some(spill) = &spill {
+            for data_type in SPILLED_TYPES {
+                spill.rewrite(data_type, |objects| removed += json::checker::compat::apply_schema(schema, &mut [objects])).map_err(spill_error)?;
+            }
+        }
+        if removed > 0 {
+            info!("{} edges unknown to BloodHound {} removed", removed, schema.name);
+        }
+    }
+
+    // ACE or attribute of every emitted edge in a sidecar file
+    if common_args.edge_provenance {
+        if let Err(err) = make_edge_provenance_report(&common_args.path, &common_args.domain, &mut collection_mut(&mut vec_users, &mut vec_groups, &mut vec_computers, &mut vec_ous, &mut vec_domains, &mut vec_gpos, &mut vec_containers)) {
+            error!("Error while writing edge provenance. Reason: {err}");
+        }
+    }
+
+    // The steps below only read the collection
+    let objects = collection(&vec_users, &vec_groups, &vec_computers, &vec_ous, &vec_domains, &vec_gpos, &vec_containers);
+
+    // Changes since a previous run
+    if !common_args.diff.contains("not set") {
+        if let Err(err) = make_diff_report(&common_args.path, &common_args.domain, &common_args.diff, &objects) {
+            error!("Error while writing diff report. Reason: {err}");
+        }
+    }
+
+    // Explicit ACEs added since a baseline descriptor set
+    if !common_args.acl_baseline.contains("not set") {
+        if let Err(err) = make_acl_baseline_report(&common_args.path, &common_args.domain, &common_args.acl_baseline, &objects) {
+            error!("Error while writing ACL baseline report. Reason: {err}");
+        }
+    }
+
+    // Objects and edges per type for the run metrics
+    if common_args.metrics || !common_args.metrics_port.contains("not set") {
+        record_objects(&common_args.domain, &objects);
+    }
+
+    // Add all in json files
+    set_phase("Writing");
+    add_phase_objects(total_objects);
+    let res = if common_args.stdout_ndjson {
+        make_ndjson_result(std::io::stdout().lock(), &mut collection_mut(&mut vec_users, &mut vec_groups, &mut vec_computers, &mut vec_ous, &mut vec_domains, &mut vec_gpos, &mut vec_containers)).map(|_| None)
+    } else {
+        make_result(
+            common_args.zip,
+            common_args.in_memory || !common_args.upload.contains("not set"),
+            common_args.pretty,
+            common_args.low_memory,
+            common_args.chunk_size,
+            &common_args.output_format,
+            &common_args.path,
+            &common_args.domain,
+            vec_users,
+            vec_groups,
+            vec_computers,
+            vec_ous,
+            vec_domains,
+            vec_gpos,
+            vec_containers,
+            &vec_psos,
+            spill.as_deref(),
+        )
+    };
+    let mut output: Option<Vec<u8>> = None;
+    // Only the upload encrypts the archive of one domain
+    #[cfg(not(feature = "upload"))]
+    let _ = output_key;
+    match res {
+        Ok(Some(archive)) if common_args.in_memory => {
+            info!("Zip archive of {} bytes returned in memory", archive.len());
+            output = Some(archive);
+        }
+        #[cfg(feature = "upload")]
+        Ok(Some(archive)) => {
+            // ureq and the backoff sleeps block, out of the runtime threads
+            let (endpoint, domain, key, auth) = (common_args.upload.to_owned(), common_args.domain.to_owned(), output_key.to_owned(), common_args.upload_auth.to_owned());
+            let fallback_dir = common_args.upload_fallback_dir.to_owned();
+            let upload = tokio::task::spawn_blocking(move || upload_archive(&endpoint, &domain, archive, &key, &auth, &fallback_dir)).await
+                .map_err(std::io::Error::other)
+                .and_then(|result| result);
+            // The archive is only in memory, a failed upload fails the run
+            if let Err(err) = upload {
+                error!("Error while uploading the zip archive. Reason: {err}");
+                return Err(Error::new(Kind::Upload).desc(format!("zip archive of {} not uploaded", &common_args.domain)).with(err));
+            }
+        }
+        Ok(_) => trace!("Making json/zip files finished!"),
+        Err(err) => error!("Error. Reason: {err}")
+    }
+
+    Ok((output, referrals))
+}
+
+/// Test functions
+#[test]
+pub fn test_referral_options() {
+    let options = extract_args_from(["rusthound", "-d", "essos.local", "-u", "khal.drogo", "-p", "horse", "-i", "192.168.56.12", "--ldaps"]).unwrap();
+    let referral = parse_referral("ldap://child.essos.local/DC=child,DC=essos,DC=local").unwrap();
+    let referred = referral_options(&options, &referral);
+    assert_eq!(referred.domain, "child.essos.local");
+    assert_eq!(referred.username, "khal.drogo@essos.local");
+    assert_eq!(referred.ldapfqdn, "child.essos.local");
+    assert_eq!(referred.ip, "not set");
+    // LDAPS of the original session, not the ldap:// of the referral
+    assert!(referred.ldaps);
+    assert_eq!(referred.password.unwrap().expose(), "horse");
+}
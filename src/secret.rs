@@ -0,0 +1,282 @@
+//! Secrets scrubbing
+//!
+//! The bind password is kept in a `SecretString` which prints `********` with Debug and Display,
+//! the real value is only read with `expose()` when binding to the LDAP server.
+//! Every secret is also registered to be scrubbed from the log records and panic messages,
+//! as long as one copy of it is alive: the last copy dropped removes it from the registry.
+//! The memory of the secrets, and of the keys and hashes derived from them, is zeroed when they are dropped,
+//! and two secrets are compared in constant time.
+//!
+//! To keep the password out of the shell history and the process list, it can be read from
+//! `RUSTHOUND_PASSWORD`, from a netrc-like `--cred-file` or from a prompt without echo:
+//!
+//! ```text
+//! # ~/.rusthound_creds, chmod 600
+//! machine essos.local login jon.snow password Winter2022!
+//! default login daenerys password Dracarys!
+//! ```
+use lazy_static::lazy_static;
+use log::warn;
+use std::fmt;
+use std::sync::RwLock;
+use zeroize::{Zeroize, Zeroizing};
+
+/// Value printed instead of a secret.
+pub const REDACTED: &str = "********";
+/// Environment variable read when the password is not on the command line.
+pub const PASSWORD_ENV: &str = "RUSTHOUND_PASSWORD";
+/// Environment variable of the password of --encrypt-output and --decrypt.
+pub const ENCRYPT_PASSWORD_ENV: &str = "RUSTHOUND_ENCRYPT_PASSWORD";
+
+lazy_static! {
+    // Secret and number of SecretString holding it
+    static ref SECRETS: RwLock<Vec<(Zeroizing<String>, usize)>> = RwLock::new(Vec::new());
+}
+
+/// String holding a password which is never printed, zeroed when dropped.
+/// A missing password is a `None`, every SecretString is a real secret.
+#[derive(Default)]
+pub struct SecretString(String);
+
+impl SecretString {
+    /// Function to wrap a secret and register it to be scrubbed from the logs.
+    pub fn new(secret: &str) -> SecretString {
+        register_secret(secret);
+        SecretString(secret.to_string())
+    }
+
+    /// Function to read the real value, only to send it to the server.
+    pub fn expose(&self) -> &str {
+        &self.0
+    }
+}
+
+impl Clone for SecretString {
+    fn clone(&self) -> SecretString {
+        register_secret(&self.0);
+        SecretString(self.0.to_owned())
+    }
+}
+
+impl From<String> for SecretString {
+    /// Function to wrap a secret without copying it, the String is moved in the SecretString.
+    fn from(secret: String) -> SecretString {
+        register_secret(&secret);
+        SecretString(secret)
+    }
+}
+
+impl Drop for SecretString {
+    fn drop(&mut self) {
+        deregister_secret(&self.0);
+        self.0.zeroize();
+    }
+}
+
+impl PartialEq for SecretString {
+    fn eq(&self, other: &SecretString) -> bool {
+        constant_time_eq(self.0.as_bytes(), other.0.as_bytes())
+    }
+}
+
+impl Eq for SecretString {}
+
+impl fmt::Debug for SecretString {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "SecretString({})", REDACTED)
+    }
+}
+
+impl fmt::Display for SecretString {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(REDACTED)
+    }
+}
+
+/// Function to compare two secrets in a time depending only on their length.
+pub fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    if a.len() != b.len() {
+        return false
+    }
+    a.iter().zip(b.iter()).fold(0u8, |diff, (a, b)| diff | (a ^ b)) == 0
+}
+
+/// Function to register one more copy of a secret to be scrubbed, empty values are ignored.
+pub fn register_secret(secret: &str) {
+    if secret.is_empty() {
+        return
+    }
+    if let Ok(mut secrets) = SECRETS.write() {
+        match secrets.iter_mut().find(|(s, _)| s.as_str() == secret) {
+            Some((_, count)) => *count += 1,
+            None => secrets.push((Zeroizing::new(secret.to_string()), 1)),
+        }
+    }
+}
+
+/// Function to forget one copy of a secret, the registered value is zeroed with the last copy.
+fn deregister_secret(secret: &str) {
+    if secret.is_empty() {
+        return
+    }
+    if let Ok(mut secrets) = SECRETS.write() {
+        if let Some(index) = secrets.iter().position(|(s, _)| s.as_str() == secret) {
+            secrets[index].1 -= 1;
+            if secrets[index].1 == 0 {
+                secrets.swap_remove(index);
+            }
+        }
+    }
+}
+
+/// Function to replace every registered secret in a message.
+pub fn scrub(message: &str) -> String {
+    let mut message = message.to_string();
+    if let Ok(secrets) = SECRETS.read() {
+        for (secret, _) in secrets.iter() {
+            if message.contains(secret.as_str()) {
+                message = message.replace(secret.as_str(), REDACTED);
+            }
+        }
+    }
+    message
+}
+
+/// Function to scrub the secrets from the panic messages, the caught panics are kept for the crash log.
+pub fn install_panic_hook() {
+    let default_hook = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        // Object skipped or collector failed, the location and backtrace go in the crash log
+        if crate::modules::skipped::isolated_panic() {
+            let location = info.location().map(|l| format!("{}:{}:{}", l.file(), l.line(), l.column())).unwrap_or_default();
+            crate::modules::skipped::record_panic_context(location, std::backtrace::Backtrace::force_capture().to_string());
+            return
+        }
+        let payload = info.payload().downcast_ref::<&str>().map(|s| s.to_string())
+            .or_else(|| info.payload().downcast_ref::<String>().map(|s| s.to_owned()));
+        match payload {
+            Some(payload) if scrub(&payload) != payload => {
+                let location = info.location().map(|l| format!(" at {}:{}", l.file(), l.line())).unwrap_or_default();
+                eprintln!("RustHound panicked{}: {}", location, scrub(&payload));
+            },
+            _ => default_hook(info),
+        }
+    }));
+}
+
+/// Credentials of one machine of a credential file.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct FileCredential {
+    pub login: Option<String>,
+    pub password: Option<SecretString>,
+}
+
+/// Function to parse a netrc-like credential file: `machine <domain|dc> login <user> password <password>`
+/// entries and an optional `default` entry, the lines starting with # are comments.
+/// Return the first entry of one of the machines (case insensitive), else the default entry.
+pub fn parse_cred_file(content: &str, machines: &[&String]) -> Option<FileCredential> {
+    let mut tokens = content.lines()
+        .filter(|line| !line.trim_start().starts_with('#'))
+        .flat_map(|line| line.split_whitespace());
+    // (machine, credential), None for the default entry
+    let mut entries: Vec<(Option<String>, FileCredential)> = Vec::new();
+    while let Some(token) = tokens.next() {
+        match token {
+            "machine" => entries.push((Some(tokens.next().unwrap_or_default().to_lowercase()), FileCredential::default())),
+            "default" => entries.push((None, FileCredential::default())),
+            "login" | "password" => {
+                let value = tokens.next().unwrap_or_default();
+                if let Some((_, credential)) = entries.last_mut() {
+                    if token == "login" {
+                        credential.login = Some(value.to_string());
+                    } else {
+                        credential.password = Some(SecretString::new(value));
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+    let found = entries.iter()
+        .find(|(machine, _)| machine.as_ref().map_or(false, |machine| machines.iter().any(|m| m.to_lowercase() == *machine)))
+        .or_else(|| entries.iter().find(|(machine, _)| machine.is_none()));
+    found.map(|(_, credential)| credential.to_owned())
+}
+
+/// Function to read the credential file, with a warning if other users can read it.
+pub fn read_cred_file(path: &String, machines: &[&String]) -> std::io::Result<Option<FileCredential>> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        if std::fs::metadata(path)?.permissions().mode() & 0o077 != 0 {
+            warn!("{} can be read by other users, chmod 600 it", path);
+        }
+    }
+    let content = Zeroizing::new(std::fs::read_to_string(path)?);
+    Ok(parse_cred_file(&content, machines))
+}
+
+/// Function to read a password on the first line of a file, with a warning if other users can read it.
+pub fn read_password_file(path: &String) -> std::io::Result<SecretString> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        if std::fs::metadata(path)?.permissions().mode() & 0o077 != 0 {
+            warn!("{} can be read by other users, chmod 600 it", path);
+        }
+    }
+    let content = Zeroizing::new(std::fs::read_to_string(path)?);
+    let password = content.lines().next().unwrap_or_default().trim_end_matches('\r');
+    if password.is_empty() {
+        return Err(std::io::Error::new(std::io::ErrorKind::InvalidData, "no password on the first line"))
+    }
+    Ok(SecretString::new(password))
+}
+
+/// Function to prompt the password on the terminal without echo.
+pub fn prompt_password(username: &str) -> std::io::Result<SecretString> {
+    prompt_secret(&format!("Password for {}: ", username))
+}
+
+/// Function to prompt a secret on the terminal without echo.
+pub fn prompt_secret(prompt: &str) -> std::io::Result<SecretString> {
+    let password = rpassword::prompt_password(prompt)?;
+    Ok(SecretString::from(password))
+}
+
+/// Test functions
+#[test]
+pub fn test_secret_string() {
+    let password = SecretString::new("Winter2022!");
+    assert_eq!(format!("{}", password), REDACTED);
+    assert!(!format!("{:?}", password).contains("Winter2022!"));
+    assert_eq!(password.expose(), "Winter2022!");
+    assert_eq!(scrub("bind failed for vagrant:Winter2022!"), format!("bind failed for vagrant:{}", REDACTED));
+    assert_eq!(format!("{}", SecretString::new("Iamnot set!")), REDACTED);
+    assert_eq!(SecretString::from("Winter2022!".to_string()), password);
+    assert_ne!(SecretString::new("Winter2023!"), password);
+    assert!(!constant_time_eq(b"Winter2022!", b"Winter2022"));
+    // Scrubbed while one copy is alive
+    let token = SecretString::new("Iamnot set!");
+    let copy = token.clone();
+    drop(token);
+    assert_eq!(scrub("Iamnot set!"), REDACTED);
+    drop(copy);
+    assert_eq!(scrub("Iamnot set!"), "Iamnot set!");
+}
+
+#[test]
+pub fn test_parse_cred_file() {
+    let content = "# lab\nmachine ESSOS.LOCAL login jon.snow\n  password Winter2022!\ndefault login daenerys password Dracarys!\nmachine dc01.sevenkingdoms.local password Summer2023!\n";
+    let domain = "essos.local".to_string();
+    let credential = parse_cred_file(content, &[&domain]).unwrap();
+    assert_eq!(credential.login.as_deref(), Some("jon.snow"));
+    assert_eq!(credential.password.unwrap().expose(), "Winter2022!");
+    let dc = "DC01.sevenkingdoms.local".to_string();
+    let credential = parse_cred_file(content, &[&"sevenkingdoms.local".to_string(), &dc]).unwrap();
+    assert_eq!((credential.login, credential.password.unwrap().expose()), (None, "Summer2023!"));
+    let credential = parse_cred_file(content, &[&"north.local".to_string()]).unwrap();
+    assert_eq!(credential.login.as_deref(), Some("daenerys"));
+    assert_eq!(scrub("Dracarys!"), REDACTED);
+    assert_eq!(parse_cred_file("machine essos.local login jon.snow", &[&"north.local".to_string()]), None);
+}
@@ -0,0 +1,40 @@
+//! --low-memory spills the users and computers during the collection: the files must be the ones of a run in memory.
+use std::path::Path;
+use std::process::Command;
+
+fn collect(path: &Path, low_memory: bool) {
+    let mut command = Command::new(env!("CARGO_BIN_EXE_rusthound"));
+    command
+        .args(["-d", "essos.local", "--input", "ldif", concat!(env!("CARGO_MANIFEST_DIR"), "/resources/fixtures/essos.ldif")])
+        .args(["--chunk-size", "1", "-o"])
+        .arg(path);
+    if low_memory {
+        command.arg("--low-memory");
+    }
+    assert!(command.output().unwrap().status.success());
+}
+
+#[test]
+pub fn test_low_memory_same_files() {
+    let path = std::env::temp_dir().join(format!("rusthound-low-memory-{}", std::process::id()));
+    let (memory, spilled) = (path.join("memory"), path.join("spilled"));
+    collect(&memory, false);
+    collect(&spilled, true);
+
+    let mut files: Vec<String> = std::fs::read_dir(&memory).unwrap()
+        .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+        .collect();
+    files.sort();
+    assert!(files.contains(&"essos-local_users_002.json".to_string()));
+    for file in &files {
+        let read = |dir: &Path| -> serde_json::value::Value { serde_json::from_slice(&std::fs::read(dir.join(file)).unwrap()).unwrap() };
+        assert_eq!(read(&memory), read(&spilled), "{}", file);
+    }
+    // Same files, and the spill directory is removed
+    let mut spilled_files: Vec<String> = std::fs::read_dir(&spilled).unwrap()
+        .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+        .collect();
+    spilled_files.sort();
+    let _ = std::fs::remove_dir_all(&path);
+    assert_eq!(files, spilled_files);
+}
@@ -0,0 +1,25 @@
+//! --stdout-ndjson with the modules printing tables: stdout must only carry the JSON lines.
+use std::process::Command;
+
+#[test]
+pub fn test_stdout_only_json_lines() {
+    let path = std::env::temp_dir().join(format!("rusthound-ndjson-{}", std::process::id()));
+    let output = Command::new(env!("CARGO_BIN_EXE_rusthound"))
+        .args(["-d", "essos.local", "--input", "ldif", concat!(env!("CARGO_MANIFEST_DIR"), "/resources/fixtures/essos.ldif")])
+        .args(["--stdout-ndjson", "--reports", "--query", "kerberoastable_high_value", "-o"])
+        .arg(&path)
+        .output()
+        .unwrap();
+    let _ = std::fs::remove_dir_all(&path);
+    assert!(output.status.success());
+
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.lines().count() > 0);
+    for line in stdout.lines() {
+        let record: serde_json::value::Value = serde_json::from_str(line).unwrap_or_else(|err| panic!("{}: {}", err, line));
+        assert!(record["record"] == "node" || record["record"] == "edge");
+    }
+    // The query and report tables are still printed, on stderr
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("Kerberoastable users with admincount") && stderr.contains("CATEGORY"));
+}